@@ -0,0 +1,74 @@
+// Read-only session viewer embedded by `export_interactive_viewer`.
+// Placeholder for the precompiled bundle: plain DOM, no dependencies.
+(async function () {
+  const node = document.getElementById('workany-viewer-payload');
+  const app = document.getElementById('app');
+  if (!node || !app) return;
+  const bytes = Uint8Array.from(atob(node.textContent.trim()), (c) => c.charCodeAt(0));
+  const stream = new Blob([bytes]).stream().pipeThrough(new DecompressionStream('gzip'));
+  const payload = JSON.parse(await new Response(stream).text());
+
+  const el = (tag, props, ...children) => {
+    const e = document.createElement(tag);
+    Object.assign(e, props || {});
+    for (const c of children) if (c != null) e.append(c);
+    return e;
+  };
+  const attachments = new Map(payload.attachments.map((a) => [a.id, a]));
+  let selected = payload.tasks.length ? payload.tasks[0].id : null;
+  let query = '';
+
+  const matches = (m) =>
+    !query ||
+    [m.content, m.tool_name, m.tool_input, m.tool_output, m.error_message]
+      .some((v) => v && v.toLowerCase().includes(query));
+
+  function renderMessage(m) {
+    if (m.type === 'tool_use' || m.tool_name) {
+      return el('details', { className: 'tool' },
+        el('summary', { textContent: `${m.tool_name || 'tool'} · ${m.created_at}` }),
+        m.tool_input ? el('pre', { textContent: m.tool_input }) : null,
+        m.tool_output ? el('pre', { textContent: m.tool_output }) : null);
+    }
+    const body = el('div', { className: `msg ${m.type}` },
+      el('div', { className: 'meta', textContent: `${m.type} · ${m.created_at}` }),
+      el('div', { className: 'text', textContent: m.content || m.error_message || '' }));
+    for (const id of m.attachment_ids || []) {
+      const a = attachments.get(id);
+      if (a && a.data_uri && (a.mime_type || '').startsWith('image/')) {
+        body.append(el('img', { src: a.data_uri, alt: a.name || '' }));
+      } else if (a) {
+        body.append(el('div', { className: 'meta', textContent: `📎 ${a.name || a.id}${a.omitted ? ` (${a.omitted})` : ''}` }));
+      }
+    }
+    return body;
+  }
+
+  function render() {
+    const list = el('ul', { className: 'tasks' });
+    for (const t of payload.tasks) {
+      if (query && !t.messages.some(matches) && !t.prompt.toLowerCase().includes(query)) continue;
+      const item = el('li', { textContent: t.prompt.split('\n')[0], className: t.id === selected ? 'active' : '' });
+      item.onclick = () => { selected = t.id; render(); };
+      list.append(item);
+    }
+    const task = payload.tasks.find((t) => t.id === selected);
+    const detail = el('main', null, task ? el('h2', { textContent: task.prompt }) : null,
+      ...(task ? task.messages.filter(matches).map(renderMessage) : []));
+    const search = el('input', { type: 'search', placeholder: 'Search', value: query });
+    search.oninput = () => { query = search.value.toLowerCase(); render(); search.focus(); };
+    app.replaceChildren(
+      el('aside', null, el('h1', { textContent: payload.session.prompt.split('\n')[0] }), search, list),
+      detail);
+  }
+
+  document.head.append(el('style', { textContent:
+    'body{margin:0;font:14px/1.5 system-ui,sans-serif;color:#222}#app{display:flex;min-height:100vh}' +
+    'aside{width:280px;border-right:1px solid #ddd;padding:1em;box-sizing:border-box}' +
+    'aside h1{font-size:16px}aside input{width:100%;margin-bottom:1em}' +
+    '.tasks{list-style:none;padding:0}.tasks li{padding:.4em;cursor:pointer;border-radius:4px}' +
+    '.tasks li.active{background:#eef}main{flex:1;padding:1em 2em;max-width:960px}' +
+    '.msg{margin:1em 0}.meta{color:#888;font-size:12px}.text{white-space:pre-wrap}' +
+    'pre{white-space:pre-wrap;background:#f6f6f6;padding:.5em}img{max-width:100%}' }));
+  render();
+})();
@@ -0,0 +1,596 @@
+CREATE INDEX idx_attachments_task_id ON attachments(task_id);
+
+CREATE INDEX idx_backfill_errors_backfill ON backfill_errors(backfill, row_id);
+
+CREATE INDEX idx_captures_status ON captures(status);
+
+CREATE INDEX idx_cost_ledger_recorded_at ON cost_ledger(recorded_at);
+
+CREATE INDEX idx_export_deliveries_session ON export_deliveries(session_id, id);
+
+CREATE UNIQUE INDEX idx_export_presets_name ON export_presets(COALESCE(project_id, ''), name);
+
+CREATE INDEX idx_files_source_file_id ON files(source_file_id);
+
+CREATE INDEX idx_files_task_id ON files(task_id);
+
+CREATE INDEX idx_files_task_sha256 ON files(task_id, sha256);
+
+CREATE INDEX idx_files_timeline ON files(created_epoch, type, source_file_id);
+
+CREATE INDEX idx_focus_session_tasks_session ON focus_session_tasks(focus_session_id);
+
+CREATE INDEX idx_focus_sessions_started ON focus_sessions(started_epoch);
+
+CREATE INDEX idx_integrity_snapshots_taken_at ON integrity_snapshots(taken_at);
+
+CREATE INDEX idx_message_notes_message_id ON message_notes(message_id);
+
+CREATE INDEX idx_messages_include_in_context ON messages(task_id) WHERE include_in_context = 1;
+
+CREATE INDEX idx_messages_task_id ON messages(task_id);
+
+CREATE INDEX idx_messages_tool_use_id ON messages(tool_use_id);
+
+CREATE INDEX idx_redaction_log_task_id ON redaction_log(task_id);
+
+CREATE INDEX idx_routing_rules_priority ON routing_rules(priority, id);
+
+CREATE INDEX idx_session_files_attachment_id ON session_files(attachment_id);
+
+CREATE INDEX idx_sessions_timeline ON sessions(created_epoch);
+
+CREATE INDEX idx_task_attachments_attachment_id ON task_attachments(attachment_id);
+
+CREATE INDEX idx_task_metric_revisions_task ON task_metric_revisions(task_id, id);
+
+CREATE INDEX idx_task_tags_tag_id ON task_tags(tag_id);
+
+CREATE INDEX idx_tasks_forked_from_task ON tasks(forked_from_task);
+
+CREATE INDEX idx_tasks_metrics_pending ON tasks(status) WHERE metrics_final = 0;
+
+CREATE INDEX idx_tasks_session_id ON tasks(session_id);
+
+CREATE INDEX idx_tasks_timeline ON tasks(created_epoch, status, cost);
+
+CREATE INDEX idx_tasks_trashed ON tasks(trashed_at) WHERE trashed_at IS NOT NULL;
+
+CREATE INDEX idx_tool_invocations_called_at ON tool_invocations(called_at);
+
+CREATE TABLE "attachments" (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT,
+                    kind TEXT NOT NULL,
+                    name TEXT,
+                    mime_type TEXT,
+                    encoding TEXT NOT NULL DEFAULT 'utf8',
+                    content TEXT NOT NULL,
+                    size INTEGER NOT NULL,
+                    tokens INTEGER,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+CREATE VIRTUAL TABLE attachments_fts USING fts5(content, content='attachments', content_rowid='id');
+
+CREATE TABLE 'attachments_fts_config'(k PRIMARY KEY, v) WITHOUT ROWID;
+
+CREATE TABLE 'attachments_fts_data'(id INTEGER PRIMARY KEY, block BLOB);
+
+CREATE TABLE 'attachments_fts_docsize'(id INTEGER PRIMARY KEY, sz BLOB);
+
+CREATE TABLE 'attachments_fts_idx'(segid, term, pgno, PRIMARY KEY(segid, term)) WITHOUT ROWID;
+
+CREATE TABLE backfill_errors (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    backfill TEXT NOT NULL,
+                    row_id INTEGER NOT NULL,
+                    error TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE captures (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    url TEXT NOT NULL,
+                    title TEXT,
+                    selection_text TEXT,
+                    screenshot_path TEXT,
+                    source TEXT,
+                    status TEXT NOT NULL DEFAULT 'pending',
+                    task_id TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE cost_ledger (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    delta REAL NOT NULL,
+                    recorded_at INTEGER NOT NULL
+                );
+
+CREATE TABLE deletion_accounting (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    recorded_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    table_name TEXT NOT NULL,
+                    subsystem TEXT NOT NULL,
+                    rows_removed INTEGER NOT NULL
+                );
+
+CREATE TABLE deletion_totals (
+                    table_name TEXT PRIMARY KEY NOT NULL,
+                    deleted INTEGER NOT NULL DEFAULT 0
+                );
+
+CREATE TABLE export_deliveries (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    run_id TEXT NOT NULL,
+                    preset_id INTEGER,
+                    preset_name TEXT NOT NULL,
+                    project_id TEXT,
+                    session_id TEXT NOT NULL,
+                    step INTEGER NOT NULL,
+                    kind TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    bytes INTEGER NOT NULL,
+                    delivered_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    FOREIGN KEY (preset_id) REFERENCES export_presets(id) ON DELETE SET NULL
+                );
+
+CREATE TABLE export_presets (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    project_id TEXT,
+                    name TEXT NOT NULL,
+                    steps TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE file_repair_reports (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    items TEXT NOT NULL,
+                    truncated INTEGER NOT NULL DEFAULT 0,
+                    applied_at TEXT,
+                    results TEXT
+                );
+
+CREATE TABLE files (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    type TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    preview TEXT,
+                    thumbnail TEXT,
+                    is_favorite INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')), sha256 TEXT, source_file_id INTEGER, source_entry TEXT, scan_verdict TEXT, scan_engine TEXT, scan_detail TEXT, scanned_at TEXT, thumbnail_hash TEXT, media_format INTEGER NOT NULL DEFAULT 0, created_epoch INTEGER, row_version INTEGER NOT NULL DEFAULT 0, ignored_at TEXT,
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+CREATE TABLE focus_session_tasks (
+                    focus_session_id INTEGER NOT NULL,
+                    task_id TEXT NOT NULL PRIMARY KEY,
+                    FOREIGN KEY (focus_session_id) REFERENCES focus_sessions(id) ON DELETE CASCADE,
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+CREATE TABLE focus_sessions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    started_epoch INTEGER NOT NULL,
+                    last_activity_epoch INTEGER NOT NULL,
+                    ended_epoch INTEGER,
+                    end_reason TEXT
+                );
+
+CREATE TABLE integrity_snapshots (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    taken_at TEXT NOT NULL,
+                    table_name TEXT NOT NULL,
+                    row_count INTEGER NOT NULL,
+                    max_rowid INTEGER NOT NULL,
+                    content_bytes INTEGER NOT NULL,
+                    deleted_total INTEGER NOT NULL
+                );
+
+CREATE TABLE legacy_sql_usage (
+                    fingerprint TEXT PRIMARY KEY NOT NULL,
+                    statement TEXT NOT NULL,
+                    table_name TEXT,
+                    count INTEGER NOT NULL DEFAULT 0,
+                    denied INTEGER NOT NULL DEFAULT 0,
+                    first_seen TEXT NOT NULL DEFAULT (datetime('now')),
+                    last_seen TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE message_changes (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    message_id INTEGER NOT NULL
+                );
+
+CREATE TABLE message_notes (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    message_id INTEGER NOT NULL,
+                    packet_id TEXT NOT NULL,
+                    reference TEXT NOT NULL,
+                    author TEXT NOT NULL DEFAULT '',
+                    body TEXT NOT NULL,
+                    commented_at TEXT,
+                    imported_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    UNIQUE (packet_id, reference, author, body),
+                    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+                );
+
+CREATE TABLE message_type_repairs (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    changes TEXT NOT NULL,
+                    rows_changed INTEGER NOT NULL,
+                    applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE messages (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    type TEXT NOT NULL,
+                    content TEXT,
+                    tool_name TEXT,
+                    tool_input TEXT,
+                    subtype TEXT,
+                    error_message TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')), tool_output TEXT, tool_use_id TEXT, attachments TEXT, redacted INTEGER NOT NULL DEFAULT 0, include_in_context INTEGER NOT NULL DEFAULT 0,
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+CREATE VIRTUAL TABLE messages_fts USING fts5(content, tool_name, tool_output, content='messages', content_rowid='id');
+
+CREATE TABLE 'messages_fts_config'(k PRIMARY KEY, v) WITHOUT ROWID;
+
+CREATE TABLE 'messages_fts_data'(id INTEGER PRIMARY KEY, block BLOB);
+
+CREATE TABLE 'messages_fts_docsize'(id INTEGER PRIMARY KEY, sz BLOB);
+
+CREATE TABLE 'messages_fts_idx'(segid, term, pgno, PRIMARY KEY(segid, term)) WITHOUT ROWID;
+
+CREATE TABLE redaction_log (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    message_id INTEGER NOT NULL,
+                    task_id TEXT NOT NULL,
+                    category TEXT NOT NULL,
+                    fields TEXT NOT NULL,
+                    span_count INTEGER NOT NULL,
+                    actor TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE review_packets (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    scope TEXT NOT NULL,
+                    scope_id TEXT NOT NULL,
+                    refs TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE routing_rules (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    priority INTEGER NOT NULL DEFAULT 0,
+                    enabled INTEGER NOT NULL DEFAULT 1,
+                    mode TEXT NOT NULL DEFAULT 'first_match',
+                    conditions TEXT NOT NULL,
+                    actions TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE saved_filters (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                    definition TEXT NOT NULL,
+                    pinned INTEGER NOT NULL DEFAULT 0,
+                    position INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE schema_drift (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    kind TEXT NOT NULL,
+                    value TEXT NOT NULL,
+                    occurrences INTEGER NOT NULL DEFAULT 0,
+                    sample_message_ids TEXT NOT NULL DEFAULT '[]',
+                    first_seen_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    last_seen_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    UNIQUE (kind, value)
+                );
+
+CREATE TABLE session_files (
+                    session_id TEXT NOT NULL,
+                    attachment_id INTEGER NOT NULL,
+                    position INTEGER NOT NULL,
+                    always_include INTEGER NOT NULL DEFAULT 1,
+                    added_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    PRIMARY KEY (session_id, attachment_id),
+                    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE,
+                    FOREIGN KEY (attachment_id) REFERENCES attachments(id) ON DELETE CASCADE
+                );
+
+CREATE TABLE sessions (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    prompt TEXT NOT NULL,
+                    task_count INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                , created_epoch INTEGER);
+
+CREATE TABLE settings (
+                    key TEXT PRIMARY KEY NOT NULL,
+                    value TEXT NOT NULL,
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE tags (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                    color TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+CREATE TABLE task_attachments (
+                    task_id TEXT NOT NULL,
+                    attachment_id INTEGER NOT NULL,
+                    position INTEGER NOT NULL,
+                    source TEXT NOT NULL,
+                    PRIMARY KEY (task_id, attachment_id),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+                    FOREIGN KEY (attachment_id) REFERENCES attachments(id) ON DELETE CASCADE
+                );
+
+CREATE TABLE task_metric_revisions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    cost REAL,
+                    duration INTEGER,
+                    reason TEXT,
+                    is_final INTEGER NOT NULL DEFAULT 0,
+                    reported_at INTEGER NOT NULL,
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+CREATE TABLE task_notes (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL UNIQUE,
+                    content TEXT NOT NULL,
+                    row_version INTEGER NOT NULL DEFAULT 1,
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+CREATE VIRTUAL TABLE task_notes_fts USING fts5(content, content='task_notes', content_rowid='id');
+
+CREATE TABLE 'task_notes_fts_config'(k PRIMARY KEY, v) WITHOUT ROWID;
+
+CREATE TABLE 'task_notes_fts_data'(id INTEGER PRIMARY KEY, block BLOB);
+
+CREATE TABLE 'task_notes_fts_docsize'(id INTEGER PRIMARY KEY, sz BLOB);
+
+CREATE TABLE 'task_notes_fts_idx'(segid, term, pgno, PRIMARY KEY(segid, term)) WITHOUT ROWID;
+
+CREATE TABLE task_tags (
+                    task_id TEXT NOT NULL,
+                    tag_id INTEGER NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    PRIMARY KEY (task_id, tag_id),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+                    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+                );
+
+CREATE TABLE tasks (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    prompt TEXT NOT NULL,
+                    status TEXT NOT NULL DEFAULT 'running',
+                    cost REAL,
+                    duration INTEGER,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                , session_id TEXT, task_index INTEGER DEFAULT 1, favorite INTEGER DEFAULT 0, forked_from_task TEXT, forked_from_message INTEGER, routing TEXT, retry_context TEXT, prompt_overflow_attachment_id INTEGER, created_epoch INTEGER, row_version INTEGER NOT NULL DEFAULT 0, trashed_at TEXT, restore_metadata TEXT, trash_bytes INTEGER, workspace_analysis TEXT, context_overrides TEXT, sent_context TEXT, metrics_final INTEGER NOT NULL DEFAULT 0);
+
+CREATE TABLE tool_invocations (
+                    tool_use_id TEXT PRIMARY KEY NOT NULL,
+                    task_id TEXT NOT NULL,
+                    tool_name TEXT,
+                    call_message_id INTEGER,
+                    result_message_id INTEGER,
+                    called_at TEXT,
+                    completed_at TEXT,
+                    latency_ms INTEGER,
+                    is_error INTEGER NOT NULL DEFAULT 0
+                );
+
+CREATE TRIGGER attachments_fts_delete AFTER DELETE ON attachments
+                WHEN OLD.encoding = 'utf8'
+                BEGIN
+                    INSERT INTO attachments_fts(attachments_fts, rowid, content) VALUES ('delete', OLD.id, OLD.content);
+                END;
+
+CREATE TRIGGER attachments_fts_insert AFTER INSERT ON attachments
+                WHEN NEW.encoding = 'utf8'
+                BEGIN
+                    INSERT INTO attachments_fts(rowid, content) VALUES (NEW.id, NEW.content);
+                END;
+
+CREATE TRIGGER cost_ledger_task_insert AFTER INSERT ON tasks
+                WHEN COALESCE(NEW.cost, 0) != 0
+                BEGIN
+                    INSERT INTO cost_ledger (task_id, delta, recorded_at)
+                    VALUES (NEW.id, NEW.cost, CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER));
+                END;
+
+CREATE TRIGGER cost_ledger_task_update AFTER UPDATE OF cost ON tasks
+                WHEN COALESCE(NEW.cost, 0) != COALESCE(OLD.cost, 0)
+                BEGIN
+                    INSERT INTO cost_ledger (task_id, delta, recorded_at)
+                    VALUES (NEW.id, COALESCE(NEW.cost, 0) - COALESCE(OLD.cost, 0), CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER));
+                END;
+
+CREATE TRIGGER db_size_guard_messages BEFORE INSERT ON messages
+                WHEN (SELECT value FROM settings WHERE key = 'db_size_blocked') = 'true'
+                BEGIN
+                    SELECT RAISE(ABORT, 'Database size limit reached: delete old tasks or raise max_db_size_mb to continue');
+                END;
+
+CREATE TRIGGER db_size_guard_tasks BEFORE INSERT ON tasks
+                WHEN (SELECT value FROM settings WHERE key = 'db_size_blocked') = 'true'
+                BEGIN
+                    SELECT RAISE(ABORT, 'Database size limit reached: delete old tasks or raise max_db_size_mb to continue');
+                END;
+
+CREATE TRIGGER deletion_totals_files AFTER DELETE ON files
+                BEGIN
+                    UPDATE deletion_totals SET deleted = deleted + 1 WHERE table_name = 'files';
+                END;
+
+CREATE TRIGGER deletion_totals_messages AFTER DELETE ON messages
+                BEGIN
+                    UPDATE deletion_totals SET deleted = deleted + 1 WHERE table_name = 'messages';
+                END;
+
+CREATE TRIGGER deletion_totals_sessions AFTER DELETE ON sessions
+                BEGIN
+                    UPDATE deletion_totals SET deleted = deleted + 1 WHERE table_name = 'sessions';
+                END;
+
+CREATE TRIGGER deletion_totals_tasks AFTER DELETE ON tasks
+                BEGIN
+                    UPDATE deletion_totals SET deleted = deleted + 1 WHERE table_name = 'tasks';
+                END;
+
+CREATE TRIGGER files_created_epoch AFTER INSERT ON files
+                WHEN NEW.created_epoch IS NULL
+                BEGIN
+                    UPDATE files SET created_epoch = CAST(strftime('%s', NEW.created_at) AS INTEGER) WHERE id = NEW.id;
+                END;
+
+CREATE TRIGGER files_row_version AFTER UPDATE ON files
+                WHEN NEW.row_version = OLD.row_version
+                BEGIN
+                    UPDATE files SET row_version = OLD.row_version + 1 WHERE id = NEW.id;
+                END;
+
+CREATE TRIGGER message_changes_on_delete AFTER DELETE ON messages
+                BEGIN
+                    INSERT INTO message_changes (task_id, message_id) VALUES (OLD.task_id, OLD.id);
+                END;
+
+CREATE TRIGGER message_changes_on_insert AFTER INSERT ON messages
+                BEGIN
+                    INSERT INTO message_changes (task_id, message_id) VALUES (NEW.task_id, NEW.id);
+                END;
+
+CREATE TRIGGER message_changes_on_update AFTER UPDATE ON messages
+                BEGIN
+                    INSERT INTO message_changes (task_id, message_id) VALUES (NEW.task_id, NEW.id);
+                END;
+
+CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages
+                BEGIN
+                    INSERT INTO messages_fts(messages_fts, rowid, content, tool_name, tool_output) VALUES ('delete', OLD.id, OLD.content, OLD.tool_name, OLD.tool_output);
+                END;
+
+CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages
+                BEGIN
+                    INSERT INTO messages_fts(rowid, content, tool_name, tool_output) VALUES (NEW.id, NEW.content, NEW.tool_name, NEW.tool_output);
+                END;
+
+CREATE TRIGGER messages_fts_update AFTER UPDATE OF content, tool_name, tool_output ON messages
+                BEGIN
+                    INSERT INTO messages_fts(messages_fts, rowid, content, tool_name, tool_output) VALUES ('delete', OLD.id, OLD.content, OLD.tool_name, OLD.tool_output);
+                    INSERT INTO messages_fts(rowid, content, tool_name, tool_output) VALUES (NEW.id, NEW.content, NEW.tool_name, NEW.tool_output);
+                END;
+
+CREATE TRIGGER messages_type_guard_insert
+                BEFORE INSERT ON messages
+                WHEN NEW.type IN ('Text', 'TEXT', 'tool-use', 'toolUse', 'ToolUse', 'TOOL_USE', 'tool use', 'tooluse', 'tool-result', 'toolResult', 'ToolResult', 'TOOL_RESULT', 'tool result', 'toolresult', 'Result', 'RESULT', 'Error', 'ERROR', 'User', 'USER', 'Plan', 'PLAN', 'permission-request', 'permissionRequest', 'PermissionRequest', 'PERMISSION_REQUEST', 'permission request', 'permissionrequest')
+                 AND EXISTS (SELECT 1 FROM settings WHERE key = 'message_types_normalized' AND value = 'true')
+                BEGIN
+                    SELECT RAISE(ABORT, 'Non-canonical message type; normalize_message_types maps it to its canonical value');
+                END;
+
+CREATE TRIGGER messages_type_guard_update
+                BEFORE UPDATE OF type ON messages
+                WHEN NEW.type IN ('Text', 'TEXT', 'tool-use', 'toolUse', 'ToolUse', 'TOOL_USE', 'tool use', 'tooluse', 'tool-result', 'toolResult', 'ToolResult', 'TOOL_RESULT', 'tool result', 'toolresult', 'Result', 'RESULT', 'Error', 'ERROR', 'User', 'USER', 'Plan', 'PLAN', 'permission-request', 'permissionRequest', 'PermissionRequest', 'PERMISSION_REQUEST', 'permission request', 'permissionrequest')
+                 AND EXISTS (SELECT 1 FROM settings WHERE key = 'message_types_normalized' AND value = 'true')
+                BEGIN
+                    SELECT RAISE(ABORT, 'Non-canonical message type; normalize_message_types maps it to its canonical value');
+                END;
+
+CREATE TRIGGER quota_guard_tasks BEFORE INSERT ON tasks
+                WHEN (SELECT value FROM settings WHERE key = 'quota_blocked') = 'true'
+                BEGIN
+                    SELECT RAISE(ABORT, 'Spending quota reached: raise the quota or wait for the next period');
+                END;
+
+CREATE TRIGGER sessions_created_epoch AFTER INSERT ON sessions
+                WHEN NEW.created_epoch IS NULL
+                BEGIN
+                    UPDATE sessions SET created_epoch = CAST(strftime('%s', NEW.created_at) AS INTEGER) WHERE id = NEW.id;
+                END;
+
+CREATE TRIGGER task_notes_fts_delete AFTER DELETE ON task_notes
+                BEGIN
+                    INSERT INTO task_notes_fts(task_notes_fts, rowid, content) VALUES ('delete', OLD.id, OLD.content);
+                END;
+
+CREATE TRIGGER task_notes_fts_insert AFTER INSERT ON task_notes
+                BEGIN
+                    INSERT INTO task_notes_fts(rowid, content) VALUES (NEW.id, NEW.content);
+                END;
+
+CREATE TRIGGER task_notes_fts_update AFTER UPDATE OF content ON task_notes
+                BEGIN
+                    INSERT INTO task_notes_fts(task_notes_fts, rowid, content) VALUES ('delete', OLD.id, OLD.content);
+                    INSERT INTO task_notes_fts(rowid, content) VALUES (NEW.id, NEW.content);
+                END;
+
+CREATE TRIGGER tasks_created_epoch AFTER INSERT ON tasks
+                WHEN NEW.created_epoch IS NULL
+                BEGIN
+                    UPDATE tasks SET created_epoch = CAST(strftime('%s', NEW.created_at) AS INTEGER) WHERE id = NEW.id;
+                END;
+
+CREATE TRIGGER tasks_row_version AFTER UPDATE ON tasks
+                WHEN NEW.row_version = OLD.row_version
+                BEGIN
+                    UPDATE tasks SET row_version = OLD.row_version + 1 WHERE id = NEW.id;
+                END;
+
+CREATE TRIGGER tool_invocations_on_call
+                AFTER INSERT ON messages
+                WHEN NEW.type = 'tool_use' AND NEW.tool_use_id IS NOT NULL
+                BEGIN
+                    INSERT OR IGNORE INTO tool_invocations (tool_use_id, task_id) VALUES (NEW.tool_use_id, NEW.task_id);
+                    UPDATE tool_invocations
+                    SET tool_name = NEW.tool_name,
+                        call_message_id = NEW.id,
+                        called_at = NEW.created_at,
+                        latency_ms = CASE WHEN completed_at IS NOT NULL
+                            THEN CAST((julianday(completed_at) - julianday(NEW.created_at)) * 86400000 AS INTEGER) END
+                    WHERE tool_use_id = NEW.tool_use_id;
+                END;
+
+CREATE TRIGGER tool_invocations_on_result
+                AFTER INSERT ON messages
+                WHEN NEW.type = 'tool_result' AND NEW.tool_use_id IS NOT NULL
+                BEGIN
+                    INSERT OR IGNORE INTO tool_invocations (tool_use_id, task_id) VALUES (NEW.tool_use_id, NEW.task_id);
+                    UPDATE tool_invocations
+                    SET result_message_id = NEW.id,
+                        completed_at = NEW.created_at,
+                        is_error = (NEW.error_message IS NOT NULL
+                            OR NEW.subtype = 'error'
+                            OR NEW.tool_output LIKE 'Error:%'
+                            OR NEW.tool_output LIKE '%<tool_use_error>%'),
+                        latency_ms = CASE WHEN called_at IS NOT NULL
+                            THEN CAST((julianday(NEW.created_at) - julianday(called_at)) * 86400000 AS INTEGER) END
+                    WHERE tool_use_id = NEW.tool_use_id;
+                END;
@@ -0,0 +1,343 @@
+// Where the frontend finds the API, and trust-on-first-use pinning for remote APIs.
+//
+// By default the app talks to its own sidecar on loopback. Users can point it at an
+// external API instead; a non-loopback address additionally needs `allow_remote_api`
+// and must use HTTPS. The first successful connection to a remote API records its
+// certificate fingerprint, and every later verification must present the same one.
+// The pin is on top of normal certificate validation, not instead of it: requests go
+// out through the system trust store, so only remotes with a CA-valid certificate
+// are supported and a self-signed one fails verification before anything is pinned.
+
+use crate::cassette::{ApiTape, CassetteStatus};
+use crate::error::CommandError;
+use crate::{db, events, settings};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Url};
+
+pub(crate) const EXTERNAL_API_URL_KEY: &str = "external_api_url";
+pub(crate) const ALLOW_REMOTE_API_KEY: &str = "allow_remote_api";
+pub(crate) const API_PIN_KEY: &str = "api_pin";
+const PIN_EVENTS_KEY: &str = "api_pin_events";
+const MAX_PIN_EVENTS: usize = 50;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_IDENTITY_LEN: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiPin {
+    pub url: String,
+    /// SHA-256 of the leaf certificate, colon separated hex
+    pub fingerprint: String,
+    /// Body of the API's /version endpoint when the pin was taken
+    pub identity: Option<String>,
+    pub established_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinEvent {
+    pub kind: String,
+    pub url: String,
+    pub fingerprint: String,
+    pub at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiConfig {
+    /// `sidecar` or `external`, or `replay` while responses come from a cassette
+    pub mode: &'static str,
+    pub base_url: String,
+    pub remote: bool,
+    pub pin: Option<ApiPin>,
+    /// Set while API traffic is being recorded or replayed
+    pub cassette: Option<CassetteStatus>,
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host_str() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false),
+        None => false,
+    }
+}
+
+/// Parse and vet an external API URL against the remote-access rules
+fn validate_url(raw: &str, allow_remote: bool) -> Result<Url, String> {
+    let url = Url::parse(raw.trim()).map_err(|e| format!("Invalid API URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("API URL must use http or https".to_string());
+    }
+    if !is_loopback(&url) {
+        if !allow_remote {
+            return Err("Remote API addresses require allow_remote_api".to_string());
+        }
+        if url.scheme() != "https" {
+            return Err("Plain-HTTP remote API URLs are refused; use https".to_string());
+        }
+    }
+    Ok(url)
+}
+
+pub(crate) fn load_config(conn: &Connection) -> Result<ApiConfig, String> {
+    let external: Option<String> = settings::get(conn, EXTERNAL_API_URL_KEY)?;
+    let pin: Option<ApiPin> = settings::get(conn, API_PIN_KEY)?;
+    Ok(match external.filter(|u| !u.trim().is_empty()) {
+        Some(url) => {
+            let remote = Url::parse(&url).map(|u| !is_loopback(&u)).unwrap_or(true);
+            ApiConfig {
+                mode: "external",
+                base_url: url,
+                remote,
+                pin: if remote { pin } else { None },
+                cassette: None,
+            }
+        }
+        None => ApiConfig {
+            mode: "sidecar",
+            base_url: format!("http://127.0.0.1:{}", crate::sidecar::api_port()),
+            remote: false,
+            pin: None,
+            cassette: None,
+        },
+    })
+}
+
+/// Host and port to connect to. `host_str` keeps the brackets around an IPv6 address,
+/// which neither resolution nor the TLS server name accepts.
+fn endpoint(url: &Url) -> Result<(&str, u16), String> {
+    let host = url.host_str().ok_or("API URL has no host")?;
+    let host = host.trim_matches(|c| c == '[' || c == ']');
+    Ok((host, url.port_or_known_default().unwrap_or(443)))
+}
+
+fn fingerprint(url: &Url) -> Result<String, String> {
+    let (host, port) = endpoint(url)?;
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Cannot resolve {}: {}", host, e))?
+        .next()
+        .ok_or_else(|| format!("No address for {}", host))?;
+    let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT)).map_err(|e| e.to_string())?;
+    let connector = native_tls::TlsConnector::new().map_err(|e| e.to_string())?;
+    let tls = connector
+        .connect(host, stream)
+        .map_err(|e| format!("TLS handshake failed: {}", e))?;
+    let cert = tls
+        .peer_certificate()
+        .map_err(|e| e.to_string())?
+        .ok_or("Server presented no certificate")?;
+    let der = cert.to_der().map_err(|e| e.to_string())?;
+    let digest = Sha256::digest(der);
+    Ok(digest
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":"))
+}
+
+fn fetch_identity(url: &Url) -> Option<String> {
+    let version_url = url.join("version").ok()?;
+    let body = ureq::AgentBuilder::new()
+        .timeout(CONNECT_TIMEOUT)
+        .build()
+        .get(version_url.as_str())
+        .call()
+        .ok()?
+        .into_string()
+        .ok()?;
+    Some(body.trim().chars().take(MAX_IDENTITY_LEN).collect())
+}
+
+fn record_event(conn: &Connection, kind: &str, url: &str, fingerprint: &str) -> Result<(), String> {
+    let mut events: Vec<PinEvent> = settings::get_or(conn, PIN_EVENTS_KEY, Vec::new())?;
+    events.push(PinEvent {
+        kind: kind.to_string(),
+        url: url.to_string(),
+        fingerprint: fingerprint.to_string(),
+        at: chrono::Utc::now().to_rfc3339(),
+    });
+    let overflow = events.len().saturating_sub(MAX_PIN_EVENTS);
+    events.drain(..overflow);
+    settings::set(conn, PIN_EVENTS_KEY, &events)
+}
+
+fn pin_now(conn: &Connection, url: &Url, fingerprint: String, kind: &str) -> Result<ApiPin, String> {
+    let pin = ApiPin {
+        url: url.to_string(),
+        fingerprint,
+        identity: fetch_identity(url),
+        established_at: chrono::Utc::now().to_rfc3339(),
+    };
+    settings::set(conn, API_PIN_KEY, &pin)?;
+    record_event(conn, kind, &pin.url, &pin.fingerprint)?;
+    Ok(pin)
+}
+
+/// Check the configured remote API against its pin, pinning on first use
+pub(crate) fn verify(app: &AppHandle, conn: &Connection) -> Result<ApiConfig, CommandError> {
+    verify_with(conn, fingerprint, |mismatch| events::emit_typed(app, mismatch))
+}
+
+/// `verify` with the certificate probe and the mismatch notification passed in
+pub(crate) fn verify_with(
+    conn: &Connection,
+    probe: impl FnOnce(&Url) -> Result<String, String>,
+    on_mismatch: impl FnOnce(events::ApiPinMismatch),
+) -> Result<ApiConfig, CommandError> {
+    let config = load_config(conn)?;
+    if !config.remote {
+        return Ok(config);
+    }
+    let allow_remote = settings::get_or(conn, ALLOW_REMOTE_API_KEY, false)?;
+    let url = validate_url(&config.base_url, allow_remote)?;
+    let actual = probe(&url)?;
+
+    match &config.pin {
+        Some(pin) if pin.url == url.as_str() && pin.fingerprint != actual => {
+            record_event(conn, "mismatch", url.as_str(), &actual)?;
+            on_mismatch(events::ApiPinMismatch {
+                url: url.to_string(),
+                expected: pin.fingerprint.clone(),
+                actual: actual.clone(),
+            });
+            Err(CommandError::PinMismatch {
+                message: "The remote API presented a different certificate than the one trusted earlier"
+                    .to_string(),
+                expected: pin.fingerprint.clone(),
+                actual,
+            })
+        }
+        Some(pin) if pin.url == url.as_str() => Ok(config),
+        _ => {
+            let pin = pin_now(conn, &url, actual, "pinned")?;
+            Ok(ApiConfig {
+                pin: Some(pin),
+                ..config
+            })
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_api_config(app: AppHandle) -> Result<ApiConfig, String> {
+    let config = db::with_conn(&app, |conn| load_config(conn)).await?;
+    let cassette = app.state::<ApiTape>().status();
+    let mode = match &cassette {
+        Some(status) if status.mode == "replay" => "replay",
+        _ => config.mode,
+    };
+    Ok(ApiConfig { mode, cassette, ..config })
+}
+
+/// Point the app at an external API, or back at the sidecar with `None`
+#[tauri::command]
+pub async fn set_external_api_url(
+    app: AppHandle,
+    url: Option<String>,
+    allow_remote: bool,
+) -> Result<ApiConfig, String> {
+    let url = match url.filter(|u| !u.trim().is_empty()) {
+        Some(raw) => Some(validate_url(&raw, allow_remote)?.to_string()),
+        None => None,
+    };
+    db::with_conn(&app, move |conn| {
+        let previous: Option<String> = settings::get(conn, EXTERNAL_API_URL_KEY)?;
+        settings::set(conn, EXTERNAL_API_URL_KEY, &url)?;
+        settings::set(conn, ALLOW_REMOTE_API_KEY, &allow_remote)?;
+        // A pin belongs to one URL; switching endpoints starts a new trust decision
+        if previous != url {
+            settings::remove(conn, API_PIN_KEY)?;
+        }
+        load_config(conn)
+    })
+    .await
+}
+
+/// Verify the remote API identity before connecting. Pins on first use and fails
+/// with `pin_mismatch` if the certificate changed since.
+#[tauri::command]
+pub async fn verify_api_identity(app: AppHandle) -> Result<ApiConfig, CommandError> {
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = db::open(&handle)?;
+        verify(&handle, &conn)
+    })
+    .await
+    .map_err(|e| CommandError::from(e.to_string()))?
+}
+
+/// Re-pin a remote API after a mismatch. The caller must pass the fingerprint the
+/// user confirmed, and it must match what the server presents right now.
+#[tauri::command]
+pub async fn trust_new_api_identity(app: AppHandle, confirm_fingerprint: String) -> Result<ApiPin, String> {
+    db::with_conn(&app, move |conn| {
+        let config = load_config(conn)?;
+        if !config.remote {
+            return Err("No remote API is configured".to_string());
+        }
+        let allow_remote = settings::get_or(conn, ALLOW_REMOTE_API_KEY, false)?;
+        let url = validate_url(&config.base_url, allow_remote)?;
+        let actual = fingerprint(&url)?;
+        if !actual.eq_ignore_ascii_case(confirm_fingerprint.trim()) {
+            return Err(format!(
+                "Confirmed fingerprint does not match the one the server presents ({})",
+                actual
+            ));
+        }
+        pin_now(conn, &url, actual, "repinned")
+    })
+    .await
+}
+
+/// Pin history for support diagnostics
+#[tauri::command]
+pub async fn get_api_pin_events(app: AppHandle) -> Result<Vec<PinEvent>, String> {
+    db::with_conn(&app, |conn| settings::get_or(conn, PIN_EVENTS_KEY, Vec::new())).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use native_tls::{Identity, TlsAcceptor};
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn ipv6_hosts_lose_their_brackets() {
+        let url = Url::parse("https://[::1]:8443/api").unwrap();
+        assert_eq!(endpoint(&url).unwrap(), ("::1", 8443));
+        let url = Url::parse("https://api.example.com/").unwrap();
+        assert_eq!(endpoint(&url).unwrap(), ("api.example.com", 443));
+    }
+
+    #[test]
+    fn self_signed_certificates_are_refused() {
+        let identity = Identity::from_pkcs8(
+            include_bytes!("../tests/fixtures/self_signed.pem"),
+            include_bytes!("../tests/fixtures/self_signed.key"),
+        )
+        .unwrap();
+        let acceptor = TlsAcceptor::new(identity).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            // The client aborts the handshake, so this is expected to fail
+            if let Ok(mut tls) = acceptor.accept(stream) {
+                let _ = tls.read(&mut [0; 1]);
+            }
+        });
+
+        let url = Url::parse(&format!("https://127.0.0.1:{}/", port)).unwrap();
+        let err = fingerprint(&url).unwrap_err();
+        assert!(err.starts_with("TLS handshake failed"), "{}", err);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+// Whether the API answers: the readiness watch after each sidecar start, one-off
+// health probes, and the status the webview reads when it missed the events.
+//
+// A probe asks `/health` for a 200; an API without the endpoint counts as up when
+// its port accepts a connection. This is the same whether `sidecar` spawned the API
+// or it runs separately, so it reads the port through `sidecar::api_port`.
+
+use crate::{db, events, settings, sidecar, splash, window};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+// Setting with the seconds to wait for the API at startup
+const API_READY_TIMEOUT_KEY: &str = "api_ready_timeout_secs";
+const DEFAULT_API_READY_TIMEOUT_SECS: u64 = 30;
+const MAX_API_READY_TIMEOUT_SECS: u64 = 600;
+// Health polls back off from the first interval to the second
+const HEALTH_POLL_START: Duration = Duration::from_millis(100);
+const HEALTH_POLL_MAX: Duration = Duration::from_millis(500);
+
+/// How a probe reached the API
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeMethod {
+    /// `/health` answered 200
+    Health,
+    /// `/health` is missing (404) but the port accepts connections
+    Tcp,
+}
+
+/// One health probe: `/health` must answer 200; a server without the endpoint
+/// counts as up if the port accepts a TCP connection
+fn probe_api(agent: &ureq::Agent, port: u16) -> Result<ProbeMethod, String> {
+    match agent.get(&format!("http://127.0.0.1:{}/health", port)).call() {
+        Ok(response) if response.status() == 200 => Ok(ProbeMethod::Health),
+        Ok(response) => Err(format!("/health answered {}", response.status())),
+        Err(ureq::Error::Status(404, _)) => {
+            let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+            std::net::TcpStream::connect_timeout(&addr, Duration::from_secs(2))
+                .map(|_| ProbeMethod::Tcp)
+                .map_err(|e| format!("/health is missing and port {} refused a connection: {}", port, e))
+        }
+        Err(ureq::Error::Status(code, _)) => Err(format!("/health answered {}", code)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn probe_agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(2))
+        .build()
+}
+
+/// Probe the API until it is up, or give up after `timeout` with the last error.
+/// Polls start 100ms apart and back off to 500ms.
+pub(crate) fn wait_for_api_health(port: u16, timeout: Duration) -> Result<(), String> {
+    let agent = probe_agent();
+    let start = Instant::now();
+    let mut interval = HEALTH_POLL_START;
+    loop {
+        let error = match probe_api(&agent, port) {
+            Ok(_) => return Ok(()),
+            Err(error) => error,
+        };
+        if start.elapsed() >= timeout {
+            return Err(format!("API did not become healthy within {}s: {}", timeout.as_secs(), error));
+        }
+        std::thread::sleep(interval.min(timeout.saturating_sub(start.elapsed())));
+        interval = (interval * 2).min(HEALTH_POLL_MAX);
+    }
+}
+
+/// How long to wait for the API to come up, from `api_ready_timeout_secs`
+pub(crate) fn api_ready_timeout(app: &AppHandle) -> Duration {
+    let secs = db::open(app)
+        .and_then(|conn| settings::get_or(&conn, API_READY_TIMEOUT_KEY, DEFAULT_API_READY_TIMEOUT_SECS))
+        .unwrap_or(DEFAULT_API_READY_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Where the API stands since the last sidecar start
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ApiStatus {
+    Pending,
+    Ready(events::ApiReady),
+    Unreachable(events::ApiUnreachable),
+}
+
+/// Latest `ApiStatus`, for a webview that starts listening after the event was sent
+pub struct ApiReadiness(Mutex<ApiStatus>);
+
+impl Default for ApiReadiness {
+    fn default() -> Self {
+        Self(Mutex::new(ApiStatus::Pending))
+    }
+}
+
+pub(crate) fn set_api_status(app: &AppHandle, status: ApiStatus) {
+    if let Ok(mut current) = app.state::<ApiReadiness>().0.lock() {
+        *current = status;
+    }
+}
+
+/// Wait for the API on `port` and tell the main window with `api://ready` or
+/// `api://unreachable`, so the UI doesn't send requests before the server listens.
+/// The splash gives way to the main window once it is unreachable, so a dead API
+/// gets a diagnostic screen.
+async fn wait_for_api_ready(app: AppHandle, port: u16, timeout: Duration) {
+    set_api_status(&app, ApiStatus::Pending);
+    let start = Instant::now();
+    let result = tauri::async_runtime::spawn_blocking(move || wait_for_api_health(port, timeout))
+        .await
+        .unwrap_or_else(|e| Err(e.to_string()));
+    match result {
+        Ok(()) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+            println!("[API] Ready on port {} after {}ms", port, elapsed_ms);
+            let ready = events::ApiReady { port, elapsed_ms };
+            set_api_status(&app, ApiStatus::Ready(ready.clone()));
+            splash::phase(&app, splash::Phase::LoadingInterface);
+            events::emit_typed_to(&app, window::MAIN_WINDOW, ready);
+        }
+        Err(error) => {
+            eprintln!("[API] Not ready on port {}: {}", port, error);
+            let unreachable = events::ApiUnreachable {
+                port,
+                timeout_ms: timeout.as_millis() as u64,
+                error,
+            };
+            set_api_status(&app, ApiStatus::Unreachable(unreachable.clone()));
+            // The main window's error screen replaces the splash
+            splash::finish(&app);
+            events::emit_typed_to(&app, window::MAIN_WINDOW, unreachable);
+        }
+    }
+}
+
+/// Start `wait_for_api_ready` in the background with the configured timeout
+pub(crate) fn watch_api_ready(app: &AppHandle, port: u16) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let timeout = api_ready_timeout(&app);
+        wait_for_api_ready(app, port, timeout).await;
+    });
+}
+
+/// Whether the API has answered since the last sidecar start; pairs with the
+/// `api://ready` and `api://unreachable` events
+#[tauri::command]
+pub fn get_api_status(readiness: tauri::State<'_, ApiReadiness>) -> ApiStatus {
+    readiness.0.lock().map(|s| s.clone()).unwrap_or(ApiStatus::Pending)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ApiHealth {
+    port: u16,
+    healthy: bool,
+    /// How the API was reached when healthy
+    method: Option<ProbeMethod>,
+    error: Option<String>,
+    elapsed_ms: u64,
+}
+
+/// Probe the API once now, e.g. after the machine resumes from sleep. A result
+/// that differs from the stored status replaces it and is announced with
+/// `api://ready` or `api://unreachable`.
+#[tauri::command]
+pub async fn check_api_health(app: AppHandle) -> Result<ApiHealth, String> {
+    let port = sidecar::api_port();
+    let start = Instant::now();
+    let result = tauri::async_runtime::spawn_blocking(move || probe_api(&probe_agent(), port))
+        .await
+        .map_err(|e| e.to_string())?;
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+    let was_ready = app
+        .state::<ApiReadiness>()
+        .0
+        .lock()
+        .is_ok_and(|status| matches!(*status, ApiStatus::Ready(_)));
+    match &result {
+        Ok(_) if !was_ready => {
+            let ready = events::ApiReady { port, elapsed_ms };
+            set_api_status(&app, ApiStatus::Ready(ready.clone()));
+            events::emit_typed_to(&app, window::MAIN_WINDOW, ready);
+        }
+        Err(error) if was_ready => {
+            let unreachable = events::ApiUnreachable {
+                port,
+                timeout_ms: 0,
+                error: error.clone(),
+            };
+            set_api_status(&app, ApiStatus::Unreachable(unreachable.clone()));
+            events::emit_typed_to(&app, window::MAIN_WINDOW, unreachable);
+        }
+        _ => {}
+    }
+    Ok(ApiHealth {
+        port,
+        healthy: result.is_ok(),
+        method: result.as_ref().ok().copied(),
+        error: result.err(),
+        elapsed_ms,
+    })
+}
+
+/// Set how long startup waits for the API before reporting `api://failed`
+#[tauri::command]
+pub async fn set_api_ready_timeout(app: AppHandle, secs: u64) -> Result<(), String> {
+    if !(1..=MAX_API_READY_TIMEOUT_SECS).contains(&secs) {
+        return Err(format!(
+            "The API timeout must be between 1 and {} seconds",
+            MAX_API_READY_TIMEOUT_SECS
+        ));
+    }
+    db::with_conn(&app, move |conn| settings::set(conn, API_READY_TIMEOUT_KEY, &secs)).await
+}
@@ -0,0 +1,623 @@
+// Inspecting and extracting zip and tar(.gz) archives from the files table.
+//
+// Listing reads only the zip central directory (tar has none, so its headers are
+// streamed) and classifies every entry: absolute paths, `..` traversal, symlinks
+// and suspicious expansion ratios are hazards. Extraction runs as a job, writes
+// only the selected entries, and always places them under the destination after
+// normalizing their paths, even when unsafe entries are allowed. Symlinks are never
+// created. An archive whose zip central directory is damaged falls back to reading
+// local headers in order, so whatever precedes the damage can still be listed and
+// extracted.
+
+use crate::error::CommandError;
+use crate::{db, files, jobs};
+use flate2::read::GzDecoder;
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use tauri::AppHandle;
+
+const MAX_ENTRIES: usize = 100_000;
+/// Expansion ratio above which an entry looks like a zip bomb
+const BOMB_RATIO: u64 = 100;
+/// Entries smaller than this are never flagged for their ratio
+const BOMB_MIN_SIZE: u64 = 1024 * 1024;
+/// Cap for entries whose header doesn't state a size (streamed zip entries)
+const MAX_UNDECLARED_ENTRY: u64 = 1024 * 1024 * 1024;
+const COPY_CHUNK: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Zip,
+    TarGz,
+    Tar,
+}
+
+impl Format {
+    fn detect(path: &Path) -> Result<Self, String> {
+        let name = path.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            return Ok(Format::Zip);
+        }
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            return Ok(Format::TarGz);
+        }
+        if name.ends_with(".tar") {
+            return Ok(Format::Tar);
+        }
+        let mut magic = [0u8; 4];
+        let read = File::open(path).and_then(|mut f| f.read(&mut magic)).map_err(|e| e.to_string())?;
+        match &magic[..read] {
+            [b'P', b'K', 3, 4] => Ok(Format::Zip),
+            [0x1f, 0x8b, ..] => Ok(Format::TarGz),
+            _ => Err(format!("'{}' is not a zip or tar archive", path.display())),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Format::Zip => "zip",
+            Format::TarGz => "tar.gz",
+            Format::Tar => "tar",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hazard {
+    AbsolutePath,
+    ParentTraversal,
+    Symlink,
+    /// Devices, fifos and other entries that aren't files or directories
+    SpecialFile,
+    ExpansionRatio,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveEntry {
+    pub index: usize,
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    /// Not known for tar entries, which are compressed as one stream
+    pub compressed_size: Option<u64>,
+    pub hazards: Vec<Hazard>,
+    pub safe: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveListing {
+    pub file_id: i64,
+    pub format: &'static str,
+    pub entries: Vec<ArchiveEntry>,
+    pub total_size: u64,
+    /// False when reading stopped early because of damage or `MAX_ENTRIES`
+    pub complete: bool,
+    /// What stopped the listing early
+    pub error: Option<String>,
+    /// The zip central directory was unreadable; entries come from local headers
+    pub recovered: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedEntry {
+    pub index: usize,
+    pub path: String,
+    pub file_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedEntry {
+    pub index: usize,
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractionReport {
+    pub dest_dir: String,
+    pub extracted: Vec<ExtractedEntry>,
+    pub skipped: Vec<SkippedEntry>,
+    /// Reading the archive stopped early; selected entries after the damage are missing
+    pub error: Option<String>,
+}
+
+/// Entry metadata common to every format, before classification
+struct RawEntry {
+    path: String,
+    kind: EntryKind,
+    size: u64,
+    compressed_size: Option<u64>,
+}
+
+fn classify(index: usize, raw: RawEntry) -> ArchiveEntry {
+    let mut hazards = Vec::new();
+    let path = Path::new(&raw.path);
+    if path.has_root() || raw.path.starts_with('/') || raw.path.starts_with('\\') || raw.path.get(1..2) == Some(":") {
+        hazards.push(Hazard::AbsolutePath);
+    }
+    if path.components().any(|c| c == Component::ParentDir) || raw.path.split(['/', '\\']).any(|s| s == "..") {
+        hazards.push(Hazard::ParentTraversal);
+    }
+    match raw.kind {
+        EntryKind::Symlink => hazards.push(Hazard::Symlink),
+        EntryKind::Other => hazards.push(Hazard::SpecialFile),
+        _ => {}
+    }
+    if let Some(compressed) = raw.compressed_size {
+        if raw.size >= BOMB_MIN_SIZE && raw.size / compressed.max(1) > BOMB_RATIO {
+            hazards.push(Hazard::ExpansionRatio);
+        }
+    }
+    ArchiveEntry {
+        index,
+        safe: hazards.is_empty(),
+        path: raw.path,
+        kind: raw.kind,
+        size: raw.size,
+        compressed_size: raw.compressed_size,
+        hazards,
+    }
+}
+
+/// Where an entry lands under `dest`: roots and drive prefixes are dropped and `..`
+/// can't climb above `dest`. `None` if nothing is left of the path, or if any other
+/// segment has a `:`, which Windows reads as a drive prefix or an NTFS stream.
+fn sandboxed_path(dest: &Path, entry_path: &str) -> Option<PathBuf> {
+    let mut parts: Vec<&str> = Vec::new();
+    for (i, part) in entry_path.split(['/', '\\']).enumerate() {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            // A drive letter like `C:` as the first segment
+            p if i == 0 && p.len() == 2 && p.ends_with(':') => {}
+            p if p.contains(':') => return None,
+            p => parts.push(p),
+        }
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    let mut path = dest.to_path_buf();
+    path.extend(parts);
+    Some(path)
+}
+
+fn zip_kind(file: &zip::read::ZipFile) -> EntryKind {
+    const S_IFMT: u32 = 0o170000;
+    match file.unix_mode().map(|mode| mode & S_IFMT) {
+        Some(0o120000) => EntryKind::Symlink,
+        Some(0o040000) => EntryKind::Directory,
+        Some(0o100000) | Some(0) | None if file.is_dir() => EntryKind::Directory,
+        Some(0o100000) | Some(0) | None => EntryKind::File,
+        Some(_) => EntryKind::Other,
+    }
+}
+
+fn zip_raw(file: &zip::read::ZipFile) -> RawEntry {
+    RawEntry {
+        path: file.name().to_string(),
+        kind: zip_kind(file),
+        size: file.size(),
+        compressed_size: Some(file.compressed_size()),
+    }
+}
+
+fn tar_kind(entry_type: tar::EntryType) -> EntryKind {
+    if entry_type.is_file() || entry_type.is_gnu_sparse() {
+        EntryKind::File
+    } else if entry_type.is_dir() {
+        EntryKind::Directory
+    } else if entry_type.is_symlink() || entry_type.is_hard_link() {
+        EntryKind::Symlink
+    } else {
+        EntryKind::Other
+    }
+}
+
+/// What to do with each entry as the archive is walked
+enum Visit {
+    Continue,
+    Stop,
+}
+
+/// Walk the archive's entries in index order, handing each to `visit` with a reader
+/// for its contents. Returns the error that ended the walk early, if any, and whether
+/// zip local headers had to be used.
+fn walk(
+    path: &Path,
+    format: Format,
+    mut visit: impl FnMut(usize, RawEntry, &mut dyn Read) -> Result<Visit, String>,
+) -> Result<(Option<String>, bool), String> {
+    let file = File::open(path).map_err(|e| format!("Cannot open '{}': {}", path.display(), e))?;
+    match format {
+        Format::Zip => match zip::ZipArchive::new(BufReader::new(file)) {
+            Ok(mut archive) => {
+                for index in 0..archive.len() {
+                    let mut entry = match archive.by_index(index) {
+                        Ok(entry) => entry,
+                        Err(e) => return Ok((Some(format!("Entry {}: {}", index, e)), false)),
+                    };
+                    let raw = zip_raw(&entry);
+                    if let Visit::Stop = visit(index, raw, &mut entry)? {
+                        break;
+                    }
+                }
+                Ok((None, false))
+            }
+            Err(central_error) => {
+                eprintln!(
+                    "[Archive] Central directory of {} unreadable ({}); reading local headers",
+                    path.display(),
+                    central_error
+                );
+                let mut reader = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+                let mut index = 0;
+                loop {
+                    match zip::read::read_zipfile_from_stream(&mut reader) {
+                        Ok(Some(mut entry)) => {
+                            let raw = zip_raw(&entry);
+                            if let Visit::Stop = visit(index, raw, &mut entry)? {
+                                break;
+                            }
+                            index += 1;
+                        }
+                        Ok(None) => break,
+                        Err(e) => return Ok((Some(format!("Entry {}: {}", index, e)), true)),
+                    }
+                }
+                Ok((Some(format!("Central directory unreadable: {}", central_error)), true))
+            }
+        },
+        Format::TarGz | Format::Tar => {
+            let reader: Box<dyn Read> = match format {
+                Format::TarGz => Box::new(GzDecoder::new(BufReader::new(file))),
+                _ => Box::new(BufReader::new(file)),
+            };
+            let mut archive = tar::Archive::new(reader);
+            let entries = archive.entries().map_err(|e| e.to_string())?;
+            for (index, entry) in entries.enumerate() {
+                let mut entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => return Ok((Some(format!("Entry {}: {}", index, e)), false)),
+                };
+                let raw = RawEntry {
+                    path: String::from_utf8_lossy(&entry.path_bytes()).into_owned(),
+                    kind: tar_kind(entry.header().entry_type()),
+                    size: entry.header().size().unwrap_or_default(),
+                    compressed_size: None,
+                };
+                if let Visit::Stop = visit(index, raw, &mut entry)? {
+                    break;
+                }
+            }
+            Ok((None, false))
+        }
+    }
+}
+
+fn list(file_id: i64, path: &Path) -> Result<ArchiveListing, String> {
+    let format = Format::detect(path)?;
+    let archive_bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or_default();
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    let (error, recovered) = walk(path, format, |index, raw, _| {
+        if entries.len() >= MAX_ENTRIES {
+            truncated = true;
+            return Ok(Visit::Stop);
+        }
+        entries.push(classify(index, raw));
+        Ok(Visit::Continue)
+    })?;
+
+    let total_size: u64 = entries.iter().map(|e| e.size).sum();
+    // Tar entries have no compressed size of their own; judge the archive as a whole
+    if format != Format::Zip && total_size / archive_bytes.max(1) > BOMB_RATIO {
+        for entry in entries.iter_mut().filter(|e| e.size >= BOMB_MIN_SIZE) {
+            entry.hazards.push(Hazard::ExpansionRatio);
+            entry.safe = false;
+        }
+    }
+    Ok(ArchiveListing {
+        file_id,
+        format: format.as_str(),
+        entries,
+        total_size,
+        complete: error.is_none() && !truncated,
+        error: error.or_else(|| truncated.then(|| format!("Listing stopped after {} entries", MAX_ENTRIES))),
+        recovered,
+    })
+}
+
+/// Copy at most `limit` bytes to `dest` via a temporary file, failing if the entry
+/// turns out larger than its header said
+fn write_entry(reader: &mut dyn Read, dest: &Path, limit: u64, ctx: &jobs::JobContext) -> Result<(), String> {
+    let tmp = dest.with_file_name(format!(
+        "{}.partial",
+        dest.file_name().map(|n| n.to_string_lossy()).unwrap_or_default()
+    ));
+    let result = (|| -> Result<(), String> {
+        let mut out = File::create(&tmp).map_err(|e| e.to_string())?;
+        let mut limited = reader.take(limit + 1);
+        let mut buf = vec![0u8; COPY_CHUNK];
+        let mut written = 0u64;
+        loop {
+            if ctx.is_cancelled() {
+                return Err("Cancelled".to_string());
+            }
+            let n = match limited.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+            written += n as u64;
+            if written > limit {
+                return Err("Entry is larger than its header claims".to_string());
+            }
+            out.write_all(&buf[..n]).map_err(|e| e.to_string())?;
+        }
+        out.sync_all().map_err(|e| e.to_string())
+    })();
+    match result {
+        Ok(()) => std::fs::rename(&tmp, dest).map_err(|e| e.to_string()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Library file type for a name, matching the frontend's extension table
+fn file_type(name: &str) -> &'static str {
+    let ext = name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+    match ext.as_str() {
+        "js" | "jsx" | "ts" | "tsx" | "py" | "go" | "rs" | "java" | "c" | "cpp" | "h" | "hpp" | "cs" | "rb"
+        | "php" | "swift" | "kt" | "scala" | "sh" | "bash" | "zsh" | "ps1" | "sql" => "code",
+        "jpg" | "jpeg" | "png" | "gif" | "bmp" | "webp" | "svg" | "ico" => "image",
+        "ppt" | "pptx" | "key" | "odp" => "presentation",
+        "xls" | "xlsx" | "numbers" | "ods" => "spreadsheet",
+        "md" | "pdf" | "doc" | "docx" | "txt" | "rtf" | "odt" => "document",
+        "html" | "htm" => "website",
+        _ => "text",
+    }
+}
+
+/// List the entries of a zip or tar(.gz) file from the library, with a safety
+/// classification for each
+#[tauri::command]
+pub async fn list_archive_contents(app: AppHandle, file_id: i64) -> Result<ArchiveListing, String> {
+    db::with_conn(&app, move |conn| {
+        let file = files::load_file(conn, file_id)?;
+        list(file_id, Path::new(&file.path))
+    })
+    .await
+}
+
+/// Extract the entries at `entry_indices` (as numbered by `list_archive_contents`)
+/// into `dest_dir` as a background job, registering each file in the library with
+/// the archive as its source. Entries with hazards are skipped unless `allow_unsafe`
+/// is set; symlinks and special files are never extracted.
+#[tauri::command]
+pub async fn extract_archive_entries(
+    app: AppHandle,
+    file_id: i64,
+    entry_indices: Vec<usize>,
+    dest_dir: String,
+    allow_unsafe: Option<bool>,
+) -> Result<u64, CommandError> {
+    let allow_unsafe = allow_unsafe.unwrap_or(false);
+    if entry_indices.is_empty() {
+        return Err("Select at least one entry to extract".into());
+    }
+    let archive = db::with_conn(&app, move |conn| files::load_file(conn, file_id)).await?;
+    let dest = files::ensure_allowed(&app, Path::new(&dest_dir))?;
+    std::fs::create_dir_all(&dest).map_err(|e| format!("Cannot create '{}': {}", dest.display(), e))?;
+    // Resolve again now that it exists, in case a component was a symlink
+    let dest = files::ensure_allowed(&app, &dest)?;
+
+    let selected: HashSet<usize> = entry_indices.into_iter().collect();
+    Ok(jobs::spawn(&app, "extract_archive", move |ctx| {
+        let conn = db::open(ctx.app())?;
+        let archive_path = PathBuf::from(&archive.path);
+        let format = Format::detect(&archive_path)?;
+        let total = selected.len() as u64;
+        let mut report = ExtractionReport {
+            dest_dir: dest.to_string_lossy().into_owned(),
+            extracted: Vec::new(),
+            skipped: Vec::new(),
+            error: None,
+        };
+        let mut done = 0u64;
+
+        let (error, _) = walk(&archive_path, format, |index, raw, reader| {
+            if !selected.contains(&index) {
+                return Ok(Visit::Continue);
+            }
+            if ctx.is_cancelled() {
+                return Err("Cancelled".to_string());
+            }
+            done += 1;
+            ctx.progress(done, total, Some(raw.path.clone()));
+            let entry = classify(index, raw);
+            let mut skip = |reason: String| {
+                report.skipped.push(SkippedEntry {
+                    index,
+                    path: entry.path.clone(),
+                    reason,
+                });
+            };
+
+            match entry.kind {
+                EntryKind::Symlink | EntryKind::Other => {
+                    skip("Links and special files are never extracted".to_string());
+                    return Ok(Visit::Continue);
+                }
+                _ if !entry.safe && !allow_unsafe => {
+                    skip(format!("Unsafe entry ({:?}); pass allow_unsafe to extract it", entry.hazards));
+                    return Ok(Visit::Continue);
+                }
+                _ => {}
+            }
+            let Some(target) = sandboxed_path(&dest, &entry.path) else {
+                skip("Entry path is empty after normalization or names a drive or stream".to_string());
+                return Ok(Visit::Continue);
+            };
+            if entry.kind == EntryKind::Directory {
+                if let Err(e) = std::fs::create_dir_all(&target) {
+                    skip(e.to_string());
+                }
+                return Ok(Visit::Continue);
+            }
+            if target.exists() {
+                skip(format!("'{}' already exists", target.display()));
+                return Ok(Visit::Continue);
+            }
+            if let Some(parent) = target.parent() {
+                if let Err(e) = std::fs::create_dir_all(parent) {
+                    skip(e.to_string());
+                    return Ok(Visit::Continue);
+                }
+            }
+            let limit = if entry.size > 0 { entry.size } else { MAX_UNDECLARED_ENTRY };
+            match write_entry(reader, &target, limit, ctx) {
+                Ok(()) => {}
+                Err(e) if ctx.is_cancelled() => return Err(e),
+                Err(e) => {
+                    skip(e);
+                    return Ok(Visit::Continue);
+                }
+            }
+
+            let name = target.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let sha256 = files::sha256_file(&target).ok();
+            let inserted = conn.execute(
+                "INSERT INTO files (task_id, name, type, path, sha256, source_file_id, source_entry)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    archive.task_id,
+                    name,
+                    file_type(&name),
+                    target.to_string_lossy(),
+                    sha256,
+                    file_id,
+                    entry.path,
+                ],
+            );
+            match inserted {
+                Ok(_) => report.extracted.push(ExtractedEntry {
+                    index,
+                    path: target.to_string_lossy().into_owned(),
+                    file_id: conn.last_insert_rowid(),
+                }),
+                Err(e) => {
+                    // Keep disk and table in step
+                    let _ = std::fs::remove_file(&target);
+                    skip(e.to_string());
+                }
+            }
+            Ok(if done == total { Visit::Stop } else { Visit::Continue })
+        })?;
+        report.error = error;
+
+        if !report.extracted.is_empty() {
+            db::emit_change(
+                ctx.app(),
+                "files",
+                "insert",
+                report.extracted.iter().map(|e| e.file_id.to_string()).collect(),
+            );
+        }
+        println!(
+            "[Archive] Extracted {} of {} entries from {}",
+            report.extracted.len(),
+            total,
+            archive_path.display()
+        );
+        serde_json::to_value(&report).map_err(|e| e.to_string())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(path: &str, kind: EntryKind, size: u64, compressed: Option<u64>) -> RawEntry {
+        RawEntry {
+            path: path.to_string(),
+            kind,
+            size,
+            compressed_size: compressed,
+        }
+    }
+
+    #[test]
+    fn classifies_hazards() {
+        let hazards = |r| classify(0, r).hazards;
+        assert!(hazards(raw("docs/readme.md", EntryKind::File, 10, Some(8))).is_empty());
+        assert_eq!(hazards(raw("/etc/passwd", EntryKind::File, 10, Some(8))), vec![Hazard::AbsolutePath]);
+        assert_eq!(hazards(raw("C:\\evil.dll", EntryKind::File, 10, Some(8))), vec![Hazard::AbsolutePath]);
+        assert_eq!(hazards(raw("a/../../b", EntryKind::File, 10, Some(8))), vec![Hazard::ParentTraversal]);
+        assert_eq!(hazards(raw("link", EntryKind::Symlink, 0, Some(0))), vec![Hazard::Symlink]);
+        assert_eq!(
+            hazards(raw("zeros.bin", EntryKind::File, 1 << 30, Some(1 << 20))),
+            vec![Hazard::ExpansionRatio]
+        );
+    }
+
+    #[test]
+    fn sandboxed_paths_stay_under_dest() {
+        let dest = Path::new("/dest");
+        assert_eq!(sandboxed_path(dest, "a/b.txt"), Some(PathBuf::from("/dest/a/b.txt")));
+        assert_eq!(sandboxed_path(dest, "/etc/passwd"), Some(PathBuf::from("/dest/etc/passwd")));
+        assert_eq!(sandboxed_path(dest, "../../x"), Some(PathBuf::from("/dest/x")));
+        assert_eq!(sandboxed_path(dest, "a/../../b"), Some(PathBuf::from("/dest/b")));
+        assert_eq!(sandboxed_path(dest, "C:\\Windows\\x.dll"), Some(PathBuf::from("/dest/Windows/x.dll")));
+        assert_eq!(sandboxed_path(dest, "./.."), None);
+        assert_eq!(sandboxed_path(dest, "C:evil.dll"), None);
+        assert_eq!(sandboxed_path(dest, "a/C:x"), None);
+        assert_eq!(sandboxed_path(dest, "D:..\\x"), None);
+        assert_eq!(sandboxed_path(dest, "a/b.txt:stream"), None);
+    }
+
+    #[test]
+    fn damaged_zip_lists_entries_before_the_damage() {
+        let dir = std::env::temp_dir().join(format!("workany-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("damaged.zip");
+        {
+            let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+            let options = zip::write::SimpleFileOptions::default();
+            for name in ["one.txt", "two.txt", "three.txt"] {
+                writer.start_file(name, options).unwrap();
+                writer.write_all(name.repeat(100).as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        // Chop off the central directory and part of the last entry
+        let bytes = std::fs::read(&path).unwrap();
+        let cut = bytes.windows(4).rposition(|w| w == [b'P', b'K', 3, 4]).unwrap() + 40;
+        std::fs::write(&path, &bytes[..cut]).unwrap();
+
+        let listing = list(1, &path).unwrap();
+        assert!(listing.recovered);
+        assert!(!listing.complete);
+        let names: Vec<&str> = listing.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(&names[..2], ["one.txt", "two.txt"]);
+    }
+}
@@ -0,0 +1,538 @@
+// Attachment suggestions while a follow-up task is being composed in a session.
+//
+// Candidates are the files earlier tasks in the session produced and the stored
+// attachments linked to those tasks or parked in the working set. They are loaded
+// once per session into `SuggestionIndex` with their name tokens precomputed, and
+// reloaded only when a cheap fingerprint of the session's rows changes, so the
+// composer can call `suggest_attachments` on every debounced keystroke.
+//
+// Ranking needs the draft to mention a candidate (a name token, the full file name
+// or a type word like "spreadsheet"); being a deliverable, recent or used by
+// earlier tasks only orders the matches. Accepting a suggestion adds the file to
+// the session's working set, which links it to the next task by reference.
+
+use crate::{db, session_files};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+
+// Scoring weights, all in one place
+/// Per distinct name token the draft mentions
+const W_TOKEN: f64 = 3.0;
+/// Name tokens counted at most
+const MAX_TOKEN_MATCHES: usize = 3;
+/// The draft contains the whole file name
+const W_FULL_NAME: f64 = 5.0;
+/// The draft names the file's type ("the spreadsheet", "slides")
+const W_TYPE_WORD: f64 = 2.0;
+/// Output of a task rather than an intermediate file
+const W_DELIVERABLE: f64 = 1.5;
+/// Produced or attached by the latest task; halves per task further back
+const W_RECENCY: f64 = 2.0;
+/// Per later task that used or named the file
+const W_REFERENCE: f64 = 1.0;
+/// References counted at most
+const MAX_REFERENCES: usize = 3;
+
+const DEFAULT_LIMIT: u32 = 5;
+const MAX_LIMIT: u32 = 20;
+/// Sessions kept in the index
+const MAX_CACHED_SESSIONS: usize = 16;
+/// Shortest name token worth matching
+const MIN_TOKEN_CHARS: usize = 3;
+
+/// File types the timeline also counts as deliverables
+const DELIVERABLE_TYPES: &[&str] = &["document", "presentation", "spreadsheet", "website"];
+/// Words in a draft that name a kind of file, and the extensions they stand for
+const TYPE_WORDS: &[(&str, &[&str])] = &[
+    ("spreadsheet", &["xlsx", "xls", "csv", "ods"]),
+    ("excel", &["xlsx", "xls"]),
+    ("csv", &["csv"]),
+    ("slides", &["pptx", "ppt", "key", "odp"]),
+    ("deck", &["pptx", "ppt", "key", "odp"]),
+    ("presentation", &["pptx", "ppt", "key", "odp"]),
+    ("document", &["docx", "doc", "odt", "pdf", "md"]),
+    ("pdf", &["pdf"]),
+    ("image", &["png", "jpg", "jpeg", "gif", "webp", "svg"]),
+    ("screenshot", &["png", "jpg", "jpeg"]),
+    ("chart", &["png", "svg"]),
+    ("website", &["html", "htm"]),
+    ("page", &["html", "htm"]),
+];
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "with", "from", "that", "this", "into", "use", "using", "file", "files", "new", "final",
+    "copy", "please", "make", "can", "you", "your", "our", "about", "also",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "snake_case")]
+pub enum SuggestionSource {
+    /// A row in `files`, produced by a task
+    File(i64),
+    /// A stored attachment
+    Attachment(i64),
+}
+
+#[derive(Debug, Clone)]
+struct Candidate {
+    source: SuggestionSource,
+    name: String,
+    /// `files.type`, or the MIME type of an attachment
+    file_type: Option<String>,
+    path: Option<String>,
+    deliverable: bool,
+    /// Task that produced or first used it
+    task_index: Option<i64>,
+    /// Later tasks that used or named it
+    referenced_by: Vec<i64>,
+    tokens: HashSet<String>,
+    extension: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentSuggestion {
+    pub source: SuggestionSource,
+    pub name: String,
+    pub file_type: Option<String>,
+    pub path: Option<String>,
+    pub task_index: Option<i64>,
+    pub score: f64,
+    /// Why it was suggested, strongest first
+    pub reasons: Vec<String>,
+}
+
+struct CachedSession {
+    fingerprint: String,
+    latest_task: i64,
+    candidates: Vec<Candidate>,
+    used: Instant,
+}
+
+/// Suggestion candidates per session, rebuilt when the session's rows change
+#[derive(Default)]
+pub struct SuggestionIndex(Mutex<HashMap<String, CachedSession>>);
+
+fn stem(token: &str) -> String {
+    match token.strip_suffix('s') {
+        Some(stripped) if stripped.len() >= 4 && !stripped.ends_with('s') => stripped.to_string(),
+        _ => token.to_string(),
+    }
+}
+
+/// Lowercased word tokens worth matching, plurals folded
+fn tokenize(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.chars().count() >= MIN_TOKEN_CHARS)
+        .map(|t| t.to_lowercase())
+        .filter(|t| !STOPWORDS.contains(&t.as_str()) && !t.chars().all(|c| c.is_ascii_digit()))
+        .map(|t| stem(&t))
+        .collect()
+}
+
+fn extension(name: &str) -> Option<String> {
+    let (stem, ext) = name.rsplit_once('.')?;
+    (!stem.is_empty() && !ext.is_empty()).then(|| ext.to_lowercase())
+}
+
+fn candidate(
+    source: SuggestionSource,
+    name: String,
+    file_type: Option<String>,
+    path: Option<String>,
+    task_index: Option<i64>,
+) -> Candidate {
+    let deliverable = matches!(source, SuggestionSource::File(_))
+        && file_type.as_deref().is_some_and(|t| DELIVERABLE_TYPES.contains(&t));
+    Candidate {
+        source,
+        tokens: tokenize(&name),
+        extension: extension(&name),
+        name,
+        file_type,
+        path,
+        deliverable,
+        task_index,
+        referenced_by: Vec::new(),
+    }
+}
+
+fn fingerprint(conn: &Connection, session_id: &str) -> Result<String, String> {
+    conn.query_row(
+        "SELECT (SELECT COUNT(*) || ':' || COALESCE(MAX(task_index), 0) FROM tasks
+                 WHERE session_id = ?1 AND trashed_at IS NULL)
+             || '/' || (SELECT COUNT(*) || ':' || COALESCE(MAX(f.id), 0) FROM files f
+                        JOIN tasks t ON t.id = f.task_id
+                        WHERE t.session_id = ?1 AND f.ignored_at IS NULL)
+             || '/' || (SELECT COUNT(*) || ':' || COALESCE(MAX(ta.rowid), 0) FROM task_attachments ta
+                        JOIN tasks t ON t.id = ta.task_id WHERE t.session_id = ?1)
+             || '/' || (SELECT COUNT(*) || ':' || COALESCE(SUM(always_include), 0) FROM session_files
+                        WHERE session_id = ?1)",
+        [session_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Load the session's candidates; returns them with the latest task index
+fn load_candidates(conn: &Connection, session_id: &str) -> Result<(i64, Vec<Candidate>), String> {
+    let tasks: Vec<(i64, String)> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT COALESCE(task_index, 1), prompt FROM tasks
+                 WHERE session_id = ?1 AND trashed_at IS NULL ORDER BY task_index",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([session_id], |row| Ok((row.get(0)?, row.get::<_, String>(1)?.to_lowercase())))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    let latest_task = tasks.iter().map(|(index, _)| *index).max().unwrap_or(0);
+
+    let mut candidates: Vec<Candidate> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT f.id, f.name, f.type, f.path, COALESCE(t.task_index, 1) FROM files f
+                 JOIN tasks t ON t.id = f.task_id
+                 WHERE t.session_id = ?1 AND t.trashed_at IS NULL
+                   AND f.ignored_at IS NULL AND f.source_file_id IS NULL
+                 ORDER BY f.id",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([session_id], |row| {
+                Ok(candidate(
+                    SuggestionSource::File(row.get(0)?),
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    Some(row.get(4)?),
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+
+    // Attachments always sent with new tasks are already attached; the rest are fair game
+    let mut attachment_uses: HashMap<i64, Vec<i64>> = HashMap::new();
+    {
+        let mut stmt = conn
+            .prepare(
+                "SELECT a.id, a.name, a.mime_type, t.task_index FROM attachments a
+                 JOIN task_attachments ta ON ta.attachment_id = a.id
+                 JOIN tasks t ON t.id = ta.task_id
+                 WHERE t.session_id = ?1 AND t.trashed_at IS NULL AND a.kind = 'file'
+                 UNION ALL
+                 SELECT a.id, a.name, a.mime_type, NULL FROM attachments a
+                 JOIN session_files s ON s.attachment_id = a.id
+                 WHERE s.session_id = ?1 AND s.always_include = 0",
+            )
+            .map_err(|e| e.to_string())?;
+        let mut rows = stmt.query([session_id]).map_err(|e| e.to_string())?;
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let id: i64 = row.get(0).map_err(|e| e.to_string())?;
+            let task_index: Option<i64> = row.get(3).map_err(|e| e.to_string())?;
+            let seen = attachment_uses.contains_key(&id);
+            let uses = attachment_uses.entry(id).or_default();
+            if !seen {
+                let name: Option<String> = row.get(1).map_err(|e| e.to_string())?;
+                candidates.push(candidate(
+                    SuggestionSource::Attachment(id),
+                    name.unwrap_or_else(|| format!("attachment-{}", id)),
+                    row.get(2).map_err(|e| e.to_string())?,
+                    None,
+                    None,
+                ));
+            }
+            uses.extend(task_index);
+        }
+    }
+    let pinned: HashSet<i64> = {
+        let mut stmt = conn
+            .prepare("SELECT attachment_id FROM session_files WHERE session_id = ?1 AND always_include = 1")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([session_id], |row| row.get(0)).map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    candidates.retain(|c| !matches!(c.source, SuggestionSource::Attachment(id) if pinned.contains(&id)));
+
+    for c in &mut candidates {
+        if let SuggestionSource::Attachment(id) = c.source {
+            c.task_index = attachment_uses.get(&id).and_then(|uses| uses.iter().min().copied());
+        }
+        let name = c.name.to_lowercase();
+        let mut referenced: HashSet<i64> = match c.source {
+            SuggestionSource::Attachment(id) => attachment_uses.get(&id).into_iter().flatten().copied().collect(),
+            SuggestionSource::File(_) => HashSet::new(),
+        };
+        for (index, prompt) in &tasks {
+            if prompt.contains(&name) {
+                referenced.insert(*index);
+            }
+        }
+        if let Some(first) = c.task_index {
+            referenced.retain(|index| *index > first);
+        }
+        c.referenced_by = referenced.into_iter().collect();
+        c.referenced_by.sort_unstable();
+    }
+    Ok((latest_task, candidates))
+}
+
+fn type_word_matches(draft_tokens: &HashSet<String>, extension: Option<&str>) -> Option<&'static str> {
+    let extension = extension?;
+    TYPE_WORDS
+        .iter()
+        .find(|(word, extensions)| draft_tokens.contains(*word) && extensions.contains(&extension))
+        .map(|(word, _)| *word)
+}
+
+/// Score one candidate against the draft; `None` when the draft doesn't mention it
+fn score(c: &Candidate, draft: &str, draft_tokens: &HashSet<String>, latest_task: i64) -> Option<(f64, Vec<String>)> {
+    let mut score = 0.0;
+    let mut reasons = Vec::new();
+
+    if c.name.chars().count() >= MIN_TOKEN_CHARS && draft.contains(&c.name.to_lowercase()) {
+        score += W_FULL_NAME;
+        reasons.push(format!("mentioned '{}'", c.name));
+    } else {
+        let mut matched: Vec<&String> = c.tokens.iter().filter(|t| draft_tokens.contains(*t)).collect();
+        matched.sort();
+        matched.truncate(MAX_TOKEN_MATCHES);
+        if !matched.is_empty() {
+            score += W_TOKEN * matched.len() as f64;
+            let words: Vec<String> = matched.iter().map(|t| format!("'{}'", t)).collect();
+            reasons.push(format!("mentioned {}", words.join(", ")));
+        }
+    }
+    if let Some(word) = type_word_matches(draft_tokens, c.extension.as_deref()) {
+        score += W_TYPE_WORD;
+        reasons.push(format!("is a {}", word));
+    }
+    if score == 0.0 {
+        return None;
+    }
+
+    if c.deliverable {
+        score += W_DELIVERABLE;
+        match c.task_index {
+            Some(index) => reasons.push(format!("deliverable of task {}", index)),
+            None => reasons.push("deliverable".to_string()),
+        }
+    }
+    let last_seen = c.referenced_by.iter().copied().chain(c.task_index).max();
+    if let Some(last_seen) = last_seen {
+        let back = (latest_task - last_seen).max(0);
+        score += W_RECENCY * 0.5f64.powi(back.min(30) as i32);
+        if back == 0 {
+            reasons.push("from the latest task".to_string());
+        }
+    }
+    if !c.referenced_by.is_empty() {
+        let counted = c.referenced_by.len().min(MAX_REFERENCES);
+        score += W_REFERENCE * counted as f64;
+        let tasks: Vec<String> = c.referenced_by.iter().map(|i| i.to_string()).collect();
+        reasons.push(format!("used again in task {}", tasks.join(", ")));
+    }
+    Some((score, reasons))
+}
+
+fn rank(candidates: &[Candidate], latest_task: i64, draft: &str, limit: usize) -> Vec<AttachmentSuggestion> {
+    let draft = draft.to_lowercase();
+    let mut draft_tokens = tokenize(&draft);
+    // Type words are matched whole, so keep them even when they are stopwords
+    for (word, _) in TYPE_WORDS {
+        if draft.split(|c: char| !c.is_alphanumeric()).any(|t| t == *word) {
+            draft_tokens.insert(word.to_string());
+        }
+    }
+    let mut ranked: Vec<AttachmentSuggestion> = candidates
+        .iter()
+        .filter_map(|c| {
+            let (score, reasons) = score(c, &draft, &draft_tokens, latest_task)?;
+            Some(AttachmentSuggestion {
+                source: c.source,
+                name: c.name.clone(),
+                file_type: c.file_type.clone(),
+                path: c.path.clone(),
+                task_index: c.task_index,
+                score: (score * 100.0).round() / 100.0,
+                reasons,
+            })
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b.task_index.cmp(&a.task_index))
+            .then(a.name.cmp(&b.name))
+    });
+    ranked.truncate(limit);
+    ranked
+}
+
+impl SuggestionIndex {
+    fn suggest(
+        &self,
+        conn: &Connection,
+        session_id: &str,
+        draft: &str,
+        limit: usize,
+    ) -> Result<Vec<AttachmentSuggestion>, String> {
+        let fingerprint = fingerprint(conn, session_id)?;
+        let mut sessions = self.0.lock().map_err(|e| e.to_string())?;
+        let stale = sessions.get(session_id).map_or(true, |s| s.fingerprint != fingerprint);
+        if stale {
+            let (latest_task, candidates) = load_candidates(conn, session_id)?;
+            if sessions.len() >= MAX_CACHED_SESSIONS && !sessions.contains_key(session_id) {
+                let oldest = sessions.iter().min_by_key(|(_, s)| s.used).map(|(id, _)| id.clone());
+                if let Some(oldest) = oldest {
+                    sessions.remove(&oldest);
+                }
+            }
+            sessions.insert(
+                session_id.to_string(),
+                CachedSession { fingerprint, latest_task, candidates, used: Instant::now() },
+            );
+        }
+        let Some(session) = sessions.get_mut(session_id) else {
+            return Ok(Vec::new());
+        };
+        session.used = Instant::now();
+        Ok(rank(&session.candidates, session.latest_task, draft, limit))
+    }
+}
+
+/// Files from earlier tasks in the session that the draft seems to be about, best first
+#[tauri::command]
+pub async fn suggest_attachments(
+    app: AppHandle,
+    session_id: String,
+    draft_text: String,
+    limit: Option<u32>,
+) -> Result<Vec<AttachmentSuggestion>, String> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize;
+    if draft_text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        handle.state::<SuggestionIndex>().suggest(conn, &session_id, &draft_text, limit)
+    })
+    .await
+}
+
+/// Add a suggested file to the session's working set so the next task gets it by
+/// reference; task outputs are copied into the attachment store first
+#[tauri::command]
+pub async fn accept_attachment_suggestion(
+    app: AppHandle,
+    session_id: String,
+    source: SuggestionSource,
+) -> Result<session_files::WorkingSet, String> {
+    let attachment_id = match source {
+        SuggestionSource::Attachment(id) => id,
+        SuggestionSource::File(file_id) => {
+            let path: String = db::with_conn(&app, {
+                let session_id = session_id.clone();
+                move |conn| {
+                    conn.query_row(
+                        "SELECT f.path FROM files f JOIN tasks t ON t.id = f.task_id
+                         WHERE f.id = ?1 AND t.session_id = ?2",
+                        params![file_id, session_id],
+                        |row| row.get(0),
+                    )
+                    .map_err(|_| format!("File {} is not part of session '{}'", file_id, session_id))
+                }
+            })
+            .await?;
+            session_files::store_attachment_file(app.clone(), path).await?.id
+        }
+    };
+    session_files::add_session_file(app, session_id, attachment_id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, prompt) VALUES ('s1', 'p');
+             INSERT INTO tasks (id, session_id, task_index, prompt) VALUES
+                ('t1', 's1', 1, 'Build the quarterly sales report'),
+                ('t2', 's1', 2, 'Draw a chart of revenue'),
+                ('t3', 's1', 3, 'Summarise sales-report.xlsx in a few notes');
+             INSERT INTO files (id, task_id, name, type, path) VALUES
+                (1, 't1', 'sales-report.xlsx', 'spreadsheet', '/w/sales-report.xlsx'),
+                (2, 't1', 'scratch-report.py', 'code', '/w/scratch-report.py'),
+                (3, 't2', 'revenue-chart.png', 'image', '/w/revenue-chart.png'),
+                (4, 't3', 'notes.md', 'document', '/w/notes.md');
+             INSERT INTO attachments (id, kind, name, encoding, content, size) VALUES
+                (10, 'file', 'brand-guide.pdf', 'base64', '', 0),
+                (11, 'file', 'logo.png', 'base64', '', 0);
+             INSERT INTO task_attachments (task_id, attachment_id, position, source) VALUES
+                ('t1', 10, 0, 'user'), ('t3', 10, 0, 'user');
+             INSERT INTO session_files (session_id, attachment_id, position, always_include) VALUES
+                ('s1', 11, 0, 1);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn suggest(conn: &Connection, draft: &str) -> Vec<AttachmentSuggestion> {
+        SuggestionIndex::default().suggest(conn, "s1", draft, 5).unwrap()
+    }
+
+    #[test]
+    fn deliverables_outrank_intermediate_files_with_the_same_match() {
+        let conn = db();
+        let ranked = suggest(&conn, "Email the report to finance");
+        let names: Vec<&str> = ranked.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["sales-report.xlsx", "scratch-report.py"]);
+        assert_eq!(ranked[0].source, SuggestionSource::File(1));
+        assert_eq!(ranked[0].reasons[0], "mentioned 'report'");
+        assert!(ranked[0].reasons.contains(&"deliverable of task 1".to_string()));
+        assert!(ranked[0].reasons.contains(&"used again in task 3".to_string()));
+    }
+
+    #[test]
+    fn type_words_and_full_names_match() {
+        let conn = db();
+        assert_eq!(suggest(&conn, "turn the spreadsheet into slides")[0].name, "sales-report.xlsx");
+        let ranked = suggest(&conn, "compare notes.md with the chart");
+        assert_eq!(ranked[0].name, "notes.md");
+        assert_eq!(ranked[0].reasons[0], "mentioned 'notes.md'");
+        assert_eq!(ranked[1].name, "revenue-chart.png");
+    }
+
+    #[test]
+    fn attachments_used_earlier_are_suggested_but_pinned_ones_are_not() {
+        let conn = db();
+        let ranked = suggest(&conn, "follow the brand guide and add the logo");
+        assert_eq!(ranked.len(), 1);
+        assert_eq!(ranked[0].source, SuggestionSource::Attachment(10));
+        assert_eq!(ranked[0].task_index, Some(1));
+        assert!(ranked[0].reasons.contains(&"from the latest task".to_string()));
+    }
+
+    #[test]
+    fn unrelated_drafts_get_nothing_and_the_index_follows_new_files() {
+        let conn = db();
+        let index = SuggestionIndex::default();
+        assert!(index.suggest(&conn, "s1", "translate this to French", 5).unwrap().is_empty());
+        assert!(index.suggest(&conn, "s1", "update the budget", 5).unwrap().is_empty());
+        conn.execute(
+            "INSERT INTO files (task_id, name, type, path) VALUES ('t3', 'budget.xlsx', 'spreadsheet', '/w/budget.xlsx')",
+            [],
+        )
+        .unwrap();
+        let ranked = index.suggest(&conn, "s1", "update the budget", 5).unwrap();
+        assert_eq!(ranked[0].name, "budget.xlsx");
+        assert!(ranked[0].score > W_TOKEN + W_DELIVERABLE);
+    }
+}
@@ -0,0 +1,447 @@
+// Background loops and the process's power state.
+//
+// Every periodic loop sleeps through `background::sleep` with a static `Activity`
+// describing it, which also registers it for `get_power_efficiency_report`. While no
+// task is running and nothing holds an `Exemption`, the process is throttled:
+// coalescable activities stretch their interval by `THROTTLED_FACTOR` and wake on
+// shared `COALESCE_QUANTUM_MS` boundaries of the wall clock, so idle wakeups come
+// together instead of spread out. A loop sleeping a stretched interval is woken
+// early when throttling ends.
+//
+// The OS is told as well. On Windows the process opts into execution-speed
+// throttling (efficiency mode) while idle and explicitly out of it while working.
+// On macOS an NSProcessInfo activity is held only while work is running, which
+// leaves App Nap free to act the rest of the time.
+//
+// The frontend writes the database over its own connection, so there is no update
+// hook for these loops to wait on instead of polling; they poll, throttled.
+
+use crate::db;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+const THROTTLED_FACTOR: u32 = 4;
+const MAX_THROTTLED_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const COALESCE_QUANTUM_MS: u64 = 10_000;
+const MAX_TRANSITIONS: usize = 50;
+const MONITOR: Activity = Activity {
+    name: "throttle_monitor",
+    interval: Duration::from_secs(5),
+    coalescable: false,
+};
+
+/// A periodic background loop
+pub struct Activity {
+    pub name: &'static str,
+    pub interval: Duration,
+    /// May be stretched and aligned with other activities while throttled
+    pub coalescable: bool,
+}
+
+/// How long `activity` should sleep from `now_ms` (Unix ms)
+fn next_delay(activity: &Activity, throttled: bool, now_ms: u64) -> Duration {
+    if !throttled || !activity.coalescable {
+        return activity.interval;
+    }
+    let stretched = (activity.interval * THROTTLED_FACTOR).min(MAX_THROTTLED_INTERVAL.max(activity.interval));
+    let target = now_ms + stretched.as_millis() as u64;
+    let aligned = target.div_ceil(COALESCE_QUANTUM_MS) * COALESCE_QUANTUM_MS;
+    Duration::from_millis(aligned - now_ms)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Transition {
+    pub at: i64,
+    pub throttled: bool,
+    pub running_tasks: usize,
+    pub exemptions: usize,
+}
+
+/// Whether the process is throttled, updated from running task and exemption counts
+#[derive(Default)]
+struct Throttle {
+    throttled: bool,
+    running_tasks: usize,
+    exemptions: usize,
+    transitions: VecDeque<Transition>,
+}
+
+impl Throttle {
+    /// Record new counts, returning the transition if the throttled state flips.
+    /// The process starts unthrottled, so the first idle observation throttles it.
+    fn observe(&mut self, running_tasks: usize, exemptions: usize, at: i64) -> Option<Transition> {
+        self.running_tasks = running_tasks;
+        self.exemptions = exemptions;
+        let throttled = running_tasks == 0 && exemptions == 0;
+        if throttled == self.throttled {
+            return None;
+        }
+        self.throttled = throttled;
+        let transition = Transition {
+            at,
+            throttled,
+            running_tasks,
+            exemptions,
+        };
+        self.transitions.push_back(transition);
+        while self.transitions.len() > MAX_TRANSITIONS {
+            self.transitions.pop_front();
+        }
+        Some(transition)
+    }
+}
+
+struct Registered {
+    interval: Duration,
+    current: Duration,
+    coalescable: bool,
+    last_run_at: Option<i64>,
+}
+
+#[derive(Default)]
+pub struct BackgroundState {
+    activities: Mutex<BTreeMap<&'static str, Registered>>,
+    throttle: Mutex<Throttle>,
+    exemptions: AtomicUsize,
+    /// Bumped on every transition to wake sleeping loops
+    generation: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl BackgroundState {
+    fn throttled(&self) -> bool {
+        self.throttle.lock().map(|t| t.throttled).unwrap_or(false)
+    }
+
+    fn planned(&self, activity: &Activity, delay: Duration, ran: bool) {
+        if let Ok(mut activities) = self.activities.lock() {
+            let entry = activities.entry(activity.name).or_insert(Registered {
+                interval: activity.interval,
+                current: delay,
+                coalescable: activity.coalescable,
+                last_run_at: None,
+            });
+            entry.current = delay;
+            if ran {
+                entry.last_run_at = Some(chrono::Utc::now().timestamp_millis());
+            }
+        }
+    }
+}
+
+/// Sleep until `activity` is due again and return how long that was planned to take.
+/// Without the managed state (early startup) this is a plain sleep of the interval.
+pub fn sleep(app: &AppHandle, activity: &Activity) -> Duration {
+    let Some(state) = app.try_state::<BackgroundState>() else {
+        std::thread::sleep(activity.interval);
+        return activity.interval;
+    };
+    let started = Instant::now();
+    let now_ms = chrono::Utc::now().timestamp_millis().max(0) as u64;
+    let mut planned = next_delay(activity, state.throttled(), now_ms);
+    state.planned(activity, planned, false);
+
+    let mut generation = state.generation.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        let elapsed = started.elapsed();
+        if elapsed >= planned {
+            break;
+        }
+        let seen = *generation;
+        generation = state
+            .changed
+            .wait_timeout(generation, planned - elapsed)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+        if *generation != seen && !state.throttled() && planned > activity.interval {
+            // Work started: don't sit out a stretched delay
+            planned = activity.interval;
+            state.planned(activity, planned, false);
+        }
+    }
+    drop(generation);
+    state.planned(activity, planned, true);
+    planned
+}
+
+fn running_tasks(conn: &Connection) -> Result<usize, String> {
+    conn.query_row("SELECT COUNT(*) FROM tasks WHERE status = 'running'", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map(|n| n as usize)
+    .map_err(|e| e.to_string())
+}
+
+/// Re-evaluate the throttled state; `running_tasks` of `None` keeps the last count
+fn update(app: &AppHandle, running_tasks: Option<usize>) {
+    let Some(state) = app.try_state::<BackgroundState>() else { return };
+    let exemptions = state.exemptions.load(Ordering::SeqCst);
+    let transition = state.throttle.lock().ok().and_then(|mut throttle| {
+        let running = running_tasks.unwrap_or(throttle.running_tasks);
+        throttle.observe(running, exemptions, chrono::Utc::now().timestamp_millis())
+    });
+    let Some(transition) = transition else { return };
+
+    println!(
+        "[Background] {} (running tasks: {}, exemptions: {})",
+        if transition.throttled {
+            "Idle, throttling background work"
+        } else {
+            "Work running, throttling lifted"
+        },
+        transition.running_tasks,
+        transition.exemptions
+    );
+    os::apply(app, transition.throttled);
+    if let Ok(mut generation) = state.generation.lock() {
+        *generation += 1;
+    }
+    state.changed.notify_all();
+}
+
+/// Keeps the process out of throttling while alive, for work the tasks table doesn't
+/// show (background jobs)
+pub struct Exemption(AppHandle);
+
+pub fn exempt(app: &AppHandle) -> Exemption {
+    if let Some(state) = app.try_state::<BackgroundState>() {
+        state.exemptions.fetch_add(1, Ordering::SeqCst);
+    }
+    update(app, None);
+    Exemption(app.clone())
+}
+
+impl Drop for Exemption {
+    fn drop(&mut self) {
+        if let Some(state) = self.0.try_state::<BackgroundState>() {
+            state.exemptions.fetch_sub(1, Ordering::SeqCst);
+        }
+        update(&self.0, None);
+    }
+}
+
+/// Start watching for running tasks to decide whether to throttle
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        match db::open(&app).and_then(|conn| running_tasks(&conn)) {
+            Ok(running) => update(&app, Some(running)),
+            Err(e) => eprintln!("[Background] Running task check failed: {}", e),
+        }
+        sleep(&app, &MONITOR);
+    });
+}
+
+#[cfg(windows)]
+mod os {
+    use tauri::AppHandle;
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentProcess, ProcessPowerThrottling, SetProcessInformation, PROCESS_POWER_THROTTLING_CURRENT_VERSION,
+        PROCESS_POWER_THROTTLING_EXECUTION_SPEED, PROCESS_POWER_THROTTLING_STATE,
+    };
+
+    pub const MECHANISM: &str = "process_power_throttling";
+
+    /// Opt into execution-speed throttling while idle, and explicitly out of it
+    /// while working so Windows doesn't apply it on its own heuristics
+    pub fn apply(_app: &AppHandle, throttled: bool) {
+        let state = PROCESS_POWER_THROTTLING_STATE {
+            Version: PROCESS_POWER_THROTTLING_CURRENT_VERSION,
+            ControlMask: PROCESS_POWER_THROTTLING_EXECUTION_SPEED,
+            StateMask: if throttled { PROCESS_POWER_THROTTLING_EXECUTION_SPEED } else { 0 },
+        };
+        // SAFETY: `state` outlives the call and the size passed is its own
+        let ok = unsafe {
+            SetProcessInformation(
+                GetCurrentProcess(),
+                ProcessPowerThrottling,
+                &state as *const PROCESS_POWER_THROTTLING_STATE as *const core::ffi::c_void,
+                std::mem::size_of::<PROCESS_POWER_THROTTLING_STATE>() as u32,
+            )
+        };
+        if ok == 0 {
+            eprintln!(
+                "[Background] SetProcessInformation failed: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod os {
+    use objc2::rc::Retained;
+    use objc2::runtime::{NSObjectProtocol, ProtocolObject};
+    use objc2_foundation::{NSActivityOptions, NSProcessInfo, NSString};
+    use std::cell::RefCell;
+    use tauri::AppHandle;
+
+    pub const MECHANISM: &str = "app_nap_activity";
+
+    thread_local! {
+        // Only touched on the main thread
+        static ACTIVITY: RefCell<Option<Retained<ProtocolObject<dyn NSObjectProtocol>>>> = const { RefCell::new(None) };
+    }
+
+    /// Hold a user-initiated activity while working, which keeps App Nap away, and
+    /// end it when idle
+    #[allow(unused_unsafe)]
+    pub fn apply(app: &AppHandle, throttled: bool) {
+        let result = app.run_on_main_thread(move || {
+            ACTIVITY.with(|slot| {
+                let mut slot = slot.borrow_mut();
+                let info = NSProcessInfo::processInfo();
+                match (throttled, slot.take()) {
+                    (true, Some(token)) => unsafe { info.endActivity(&token) },
+                    (false, None) => {
+                        let reason = NSString::from_str("Running agent tasks");
+                        *slot = Some(unsafe {
+                            info.beginActivityWithOptions_reason(
+                                NSActivityOptions::NSActivityUserInitiatedAllowingIdleSystemSleep,
+                                &reason,
+                            )
+                        });
+                    }
+                    (_, current) => *slot = current,
+                }
+            })
+        });
+        if let Err(e) = result {
+            eprintln!("[Background] Could not update the App Nap activity: {}", e);
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod os {
+    use tauri::AppHandle;
+
+    pub const MECHANISM: &str = "none";
+
+    pub fn apply(_app: &AppHandle, _throttled: bool) {}
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityReport {
+    pub name: &'static str,
+    pub interval_ms: u64,
+    /// The delay planned for the current or last sleep
+    pub current_interval_ms: u64,
+    pub coalescable: bool,
+    /// "coalesced" for activities that join the shared idle schedule, else "fixed"
+    pub scheduler: &'static str,
+    pub last_run_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerEfficiencyReport {
+    pub throttled: bool,
+    pub running_tasks: usize,
+    pub exemptions: usize,
+    /// How the OS is told: "process_power_throttling", "app_nap_activity" or "none"
+    pub os_mechanism: &'static str,
+    pub activities: Vec<ActivityReport>,
+    /// Recent transitions, oldest first
+    pub transitions: Vec<Transition>,
+}
+
+/// Every registered background activity with its current interval, and the
+/// throttling state, so a loop that keeps the app awake shows up here
+#[tauri::command]
+pub fn get_power_efficiency_report(state: State<'_, BackgroundState>) -> Result<PowerEfficiencyReport, String> {
+    let activities = state
+        .activities
+        .lock()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|(name, registered)| ActivityReport {
+            name: *name,
+            interval_ms: registered.interval.as_millis() as u64,
+            current_interval_ms: registered.current.as_millis() as u64,
+            coalescable: registered.coalescable,
+            scheduler: if registered.coalescable { "coalesced" } else { "fixed" },
+            last_run_at: registered.last_run_at,
+        })
+        .collect();
+    let throttle = state.throttle.lock().map_err(|e| e.to_string())?;
+    Ok(PowerEfficiencyReport {
+        throttled: throttle.throttled,
+        running_tasks: throttle.running_tasks,
+        exemptions: state.exemptions.load(Ordering::SeqCst),
+        os_mechanism: os::MECHANISM,
+        activities,
+        transitions: throttle.transitions.iter().copied().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLL: Activity = Activity {
+        name: "poll",
+        interval: Duration::from_secs(5),
+        coalescable: true,
+    };
+    const FIXED: Activity = Activity {
+        name: "fixed",
+        interval: Duration::from_secs(2),
+        coalescable: false,
+    };
+
+    #[test]
+    fn unthrottled_activities_keep_their_interval() {
+        assert_eq!(next_delay(&POLL, false, 1_234), POLL.interval);
+        assert_eq!(next_delay(&FIXED, true, 1_234), FIXED.interval);
+    }
+
+    #[test]
+    fn throttled_activities_stretch_and_align() {
+        let now = 1_700_000_003_000;
+        let delay = next_delay(&POLL, true, now);
+        assert!(delay >= POLL.interval * THROTTLED_FACTOR);
+        assert_eq!((now + delay.as_millis() as u64) % COALESCE_QUANTUM_MS, 0);
+        // Two activities with different intervals land on shared boundaries
+        let other = Activity {
+            name: "other",
+            interval: Duration::from_secs(7),
+            coalescable: true,
+        };
+        let other_delay = next_delay(&other, true, now + 1_500);
+        assert_eq!((now + 1_500 + other_delay.as_millis() as u64) % COALESCE_QUANTUM_MS, 0);
+    }
+
+    #[test]
+    fn long_intervals_are_capped_when_stretched() {
+        let hourly = Activity {
+            name: "hourly",
+            interval: Duration::from_secs(3600),
+            coalescable: true,
+        };
+        let delay = next_delay(&hourly, true, 0);
+        assert_eq!(delay, hourly.interval);
+    }
+
+    #[test]
+    fn throttle_follows_running_tasks_and_exemptions() {
+        let mut throttle = Throttle::default();
+        let into = throttle.observe(0, 0, 1).unwrap();
+        assert!(into.throttled);
+        assert_eq!(throttle.observe(0, 0, 2), None);
+
+        let out = throttle.observe(2, 0, 3).unwrap();
+        assert!(!out.throttled);
+        assert_eq!(out.running_tasks, 2);
+        assert_eq!(throttle.observe(1, 0, 4), None);
+
+        // Tasks finished, but a job still holds an exemption
+        assert_eq!(throttle.observe(0, 1, 5), None);
+        assert!(throttle.observe(0, 0, 6).unwrap().throttled);
+        assert_eq!(throttle.transitions.len(), 3);
+    }
+}
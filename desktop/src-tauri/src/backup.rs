@@ -0,0 +1,1024 @@
+// Database backups: full snapshots plus chains of differential deltas.
+//
+// A full backup is a `VACUUM INTO` copy of the database. Every backup also leaves a
+// row index (`<id>.rows.gz`): a 64-bit hash of each row, by table and rowid, of the
+// state it captured. A differential backup scans the live tables against its
+// parent's index and stores only rows that changed or disappeared (`<id>.delta.gz`).
+// Each delta lists its whole ancestry and its parent's file hash, so a missing or
+// swapped link is reported before anything is restored.
+//
+// Restores are staged next to the database and swapped in by
+// `apply_pending_restore` at the next launch, before migrations run. The loop in
+// `start` follows the policy in settings: full weekly, differential daily and a
+// restore-and-check verification monthly, unless changed.
+
+use crate::{background, db, fts, jobs, settings};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use rusqlite::types::{ToSqlOutput, Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, OpenFlags, Row, ToSql};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const POLICY_KEY: &str = "backup_policy";
+pub(crate) const BACKUP_DIR: &str = "backups";
+const STARTUP_DELAY: Duration = Duration::from_secs(10 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "backup_policy",
+    interval: POLL_INTERVAL,
+    coalescable: true,
+};
+
+/// Held while a backup, verification or restore runs so they never overlap
+static RUNNING: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupMode {
+    Full,
+    Differential,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verification {
+    pub at: String,
+    pub ok: bool,
+    pub detail: Option<String>,
+}
+
+/// `<id>.json`, written last so an interrupted backup never shows up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub id: String,
+    pub mode: BackupMode,
+    pub created_at: String,
+    /// Backups this one builds on, baseline first; empty for a full backup
+    pub chain: Vec<String>,
+    /// SHA-256 of the parent's data file
+    pub parent_hash: Option<String>,
+    /// Data file: the database copy or the delta
+    pub file: String,
+    pub sha256: String,
+    pub bytes: u64,
+    pub schema_version: i64,
+    /// Rows per table in the state this backup restores to
+    pub row_counts: BTreeMap<String, i64>,
+    pub changed_rows: u64,
+    pub deleted_rows: u64,
+    pub verification: Option<Verification>,
+}
+
+impl BackupInfo {
+    fn baseline(&self) -> &str {
+        self.chain.first().unwrap_or(&self.id)
+    }
+}
+
+/// A baseline and the deltas built on it, for `list_backups`
+#[derive(Debug, Serialize)]
+pub struct BackupChain {
+    pub baseline_id: String,
+    /// `None` when the baseline itself is gone
+    pub baseline: Option<BackupInfo>,
+    pub deltas: Vec<BackupInfo>,
+    /// Links referenced by the chain that are no longer on disk
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupPolicy {
+    pub enabled: bool,
+    /// 0 turns the respective step off
+    pub full_every_days: u32,
+    pub differential_every_days: u32,
+    pub verify_every_days: u32,
+    /// Chains kept after a new full backup, including the new one
+    pub keep_chains: u32,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            full_every_days: 7,
+            differential_every_days: 1,
+            verify_every_days: 30,
+            keep_chains: 2,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RowCountMismatch {
+    pub table: String,
+    pub expected: i64,
+    pub actual: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyReport {
+    pub backup_id: String,
+    /// Backups restored, baseline first
+    pub chain: Vec<String>,
+    pub quick_check: String,
+    pub mismatches: Vec<RowCountMismatch>,
+    pub ok: bool,
+}
+
+/// A column value in a delta. Blobs are base64 so the delta stays JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Cell {
+    Null(()),
+    Int(i64),
+    Real(f64),
+    Text(String),
+    Blob { b64: String },
+}
+
+impl ToSql for Cell {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            Cell::Null(()) => ToSqlOutput::Owned(SqlValue::Null),
+            Cell::Int(n) => ToSqlOutput::from(*n),
+            Cell::Real(f) => ToSqlOutput::from(*f),
+            Cell::Text(s) => ToSqlOutput::from(s.as_str()),
+            Cell::Blob { b64 } => {
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(b64)
+                    .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+                ToSqlOutput::Owned(SqlValue::Blob(bytes))
+            }
+        })
+    }
+}
+
+/// Row hash by rowid, by table
+type RowIndex = BTreeMap<String, BTreeMap<i64, u64>>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Delta {
+    parent: String,
+    tables: BTreeMap<String, TableDelta>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TableDelta {
+    columns: Vec<String>,
+    /// (rowid, values in `columns` order)
+    upserts: Vec<(i64, Vec<Cell>)>,
+    deletes: Vec<i64>,
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339()
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn new_id(mode: BackupMode) -> String {
+    let suffix = match mode {
+        BackupMode::Full => "full",
+        BackupMode::Differential => "diff",
+    };
+    format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%S%3fZ"), suffix)
+}
+
+fn backups_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let db_path = db::db_path(app)?;
+    let dir = db_path.parent().ok_or("Database path has no parent")?.join(BACKUP_DIR);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+fn write_gz<T: Serialize>(path: &Path, value: &T) -> Result<u64, String> {
+    let file = File::create(path).map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+    let mut encoder = GzEncoder::new(BufWriter::new(file), flate2::Compression::default());
+    serde_json::to_writer(&mut encoder, value).map_err(|e| e.to_string())?;
+    encoder
+        .finish()
+        .and_then(|mut writer| writer.flush())
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    std::fs::metadata(path).map(|m| m.len()).map_err(|e| e.to_string())
+}
+
+fn read_gz<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    serde_json::from_reader(GzDecoder::new(BufReader::new(file)))
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+}
+
+fn file_hash(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn info_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.json", id))
+}
+
+fn index_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.rows.gz", id))
+}
+
+fn load_info(dir: &Path, id: &str) -> Result<BackupInfo, String> {
+    let path = info_path(dir, id);
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Cannot parse {}: {}", path.display(), e))
+}
+
+fn save_info(dir: &Path, info: &BackupInfo) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(info).map_err(|e| e.to_string())?;
+    std::fs::write(info_path(dir, &info.id), text).map_err(|e| e.to_string())
+}
+
+/// Every backup in `dir`, oldest first
+fn load_all(dir: &Path) -> Result<Vec<BackupInfo>, String> {
+    let mut all = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(|e| e.to_string())?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(id) = name.strip_suffix(".json") else {
+            continue;
+        };
+        match load_info(dir, id) {
+            Ok(info) => all.push(info),
+            Err(e) => eprintln!("[Backup] Skipping {}: {}", name, e),
+        }
+    }
+    all.sort_by(|a, b| (&a.created_at, &a.id).cmp(&(&b.created_at, &b.id)));
+    Ok(all)
+}
+
+/// Remove every file belonging to `id`
+fn discard(dir: &Path, id: &str) {
+    let prefix = format!("{}.", id);
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+fn schema_version(conn: &Connection) -> Result<i64, String> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM _sqlx_migrations WHERE success = 1",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Ordinary rowid tables. Virtual tables and their shadow tables are left out and
+/// rebuilt from their content tables after a restore.
+fn backup_tables(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT name, COALESCE(sql, '') FROM sqlite_master
+             WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+             ORDER BY name",
+        )
+        .map_err(|e| e.to_string())?;
+    let tables: Vec<(String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get::<_, String>(1)?.to_uppercase())))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    let virtual_tables: Vec<&str> = tables
+        .iter()
+        .filter(|(_, sql)| sql.starts_with("CREATE VIRTUAL TABLE"))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    Ok(tables
+        .iter()
+        .filter(|(name, sql)| {
+            !sql.starts_with("CREATE VIRTUAL TABLE")
+                && !sql.contains("WITHOUT ROWID")
+                && !virtual_tables.iter().any(|v| name.starts_with(&format!("{}_", v)))
+        })
+        .map(|(name, _)| name.clone())
+        .collect())
+}
+
+fn columns(conn: &Connection, table: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM pragma_table_info(?1) ORDER BY cid")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([table], |row| row.get(0)).map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn row_hash(row: &Row, width: usize) -> rusqlite::Result<u64> {
+    let mut hasher = Sha256::new();
+    for i in 1..=width {
+        match row.get_ref(i)? {
+            ValueRef::Null => hasher.update([0]),
+            ValueRef::Integer(n) => {
+                hasher.update([1]);
+                hasher.update(n.to_le_bytes());
+            }
+            ValueRef::Real(f) => {
+                hasher.update([2]);
+                hasher.update(f.to_le_bytes());
+            }
+            ValueRef::Text(bytes) => {
+                hasher.update([3]);
+                hasher.update((bytes.len() as u64).to_le_bytes());
+                hasher.update(bytes);
+            }
+            ValueRef::Blob(bytes) => {
+                hasher.update([4]);
+                hasher.update((bytes.len() as u64).to_le_bytes());
+                hasher.update(bytes);
+            }
+        }
+    }
+    let digest = hasher.finalize();
+    Ok(u64::from_le_bytes(digest[..8].try_into().expect("8 bytes")))
+}
+
+fn row_cells(row: &Row, width: usize) -> rusqlite::Result<Vec<Cell>> {
+    (1..=width)
+        .map(|i| {
+            Ok(match row.get_ref(i)? {
+                ValueRef::Null => Cell::Null(()),
+                ValueRef::Integer(n) => Cell::Int(n),
+                ValueRef::Real(f) => Cell::Real(f),
+                ValueRef::Text(bytes) => Cell::Text(String::from_utf8_lossy(bytes).into_owned()),
+                ValueRef::Blob(bytes) => Cell::Blob {
+                    b64: base64::engine::general_purpose::STANDARD.encode(bytes),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Call `visit(rowid, hash, row)` for every row of `table`
+fn scan(
+    conn: &Connection,
+    table: &str,
+    columns: &[String],
+    mut visit: impl FnMut(i64, u64, &Row) -> rusqlite::Result<()>,
+) -> Result<(), String> {
+    let list = columns.iter().map(|c| quote(c)).collect::<Vec<_>>().join(", ");
+    let mut stmt = conn
+        .prepare(&format!("SELECT rowid, {} FROM {}", list, quote(table)))
+        .map_err(|e| e.to_string())?;
+    let mut rows = stmt.query([]).map_err(|e| e.to_string())?;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let rowid: i64 = row.get(0).map_err(|e| e.to_string())?;
+        let hash = row_hash(row, columns.len()).map_err(|e| e.to_string())?;
+        visit(rowid, hash, row).map_err(|e| format!("Reading {}: {}", table, e))?;
+    }
+    Ok(())
+}
+
+fn index(conn: &Connection) -> Result<(RowIndex, BTreeMap<String, i64>), String> {
+    let mut index = RowIndex::new();
+    let mut counts = BTreeMap::new();
+    for table in backup_tables(conn)? {
+        let columns = columns(conn, &table)?;
+        let mut rows = BTreeMap::new();
+        scan(conn, &table, &columns, |rowid, hash, _| {
+            rows.insert(rowid, hash);
+            Ok(())
+        })?;
+        counts.insert(table.clone(), rows.len() as i64);
+        index.insert(table, rows);
+    }
+    Ok((index, counts))
+}
+
+fn full_backup(conn: &Connection, dir: &Path) -> Result<BackupInfo, String> {
+    let id = new_id(BackupMode::Full);
+    let result = (|| {
+        let file = format!("{}.db", id);
+        let path = dir.join(&file);
+        conn.execute("VACUUM INTO ?1", [path.to_string_lossy()])
+            .map_err(|e| format!("Cannot copy the database: {}", e))?;
+        // Index the copy rather than the live database: VACUUM may renumber rowids in
+        // tables without an INTEGER PRIMARY KEY, and deltas are applied to the copy
+        let copy = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| e.to_string())?;
+        let (rows, row_counts) = index(&copy)?;
+        let schema_version = schema_version(&copy)?;
+        drop(copy);
+        write_gz(&index_path(dir, &id), &rows)?;
+        let info = BackupInfo {
+            id: id.clone(),
+            mode: BackupMode::Full,
+            created_at: now(),
+            chain: Vec::new(),
+            parent_hash: None,
+            sha256: file_hash(&path)?,
+            bytes: std::fs::metadata(&path).map(|m| m.len()).map_err(|e| e.to_string())?,
+            file,
+            schema_version,
+            row_counts,
+            changed_rows: 0,
+            deleted_rows: 0,
+            verification: None,
+        };
+        save_info(dir, &info)?;
+        Ok(info)
+    })();
+    if result.is_err() {
+        discard(dir, &id);
+    }
+    result
+}
+
+/// The backup a new differential builds on: the newest one in the newest chain
+fn latest(dir: &Path) -> Result<Option<BackupInfo>, String> {
+    Ok(load_all(dir)?.pop())
+}
+
+fn differential_backup(conn: &mut Connection, dir: &Path) -> Result<BackupInfo, String> {
+    let parent = latest(dir)?.ok_or("There is no backup to build on; take a full backup first")?;
+    resolve_chain(dir, &parent)?;
+    let id = new_id(BackupMode::Differential);
+    let result = (|| {
+        // One read transaction, so every table is read from the same snapshot
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let schema_version = schema_version(&tx)?;
+        if schema_version != parent.schema_version {
+            return Err(format!(
+                "The schema changed since backup {} (version {} to {}); take a full backup",
+                parent.id, parent.schema_version, schema_version
+            ));
+        }
+        let before: RowIndex = read_gz(&index_path(dir, &parent.id))?;
+        let mut after = RowIndex::new();
+        let mut row_counts = BTreeMap::new();
+        let mut delta = Delta {
+            parent: parent.id.clone(),
+            ..Default::default()
+        };
+        let (mut changed_rows, mut deleted_rows) = (0u64, 0u64);
+        for table in backup_tables(&tx)? {
+            let columns = columns(&tx, &table)?;
+            let known = before.get(&table);
+            let mut rows = BTreeMap::new();
+            let mut upserts = Vec::new();
+            scan(&tx, &table, &columns, |rowid, hash, row| {
+                if known.and_then(|k| k.get(&rowid)) != Some(&hash) {
+                    upserts.push((rowid, row_cells(row, columns.len())?));
+                }
+                rows.insert(rowid, hash);
+                Ok(())
+            })?;
+            let deletes: Vec<i64> = known
+                .map(|k| k.keys().filter(|rowid| !rows.contains_key(rowid)).copied().collect())
+                .unwrap_or_default();
+            changed_rows += upserts.len() as u64;
+            deleted_rows += deletes.len() as u64;
+            row_counts.insert(table.clone(), rows.len() as i64);
+            if !upserts.is_empty() || !deletes.is_empty() {
+                delta.tables.insert(table.clone(), TableDelta { columns, upserts, deletes });
+            }
+            after.insert(table, rows);
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+
+        let file = format!("{}.delta.gz", id);
+        let path = dir.join(&file);
+        let bytes = write_gz(&path, &delta)?;
+        write_gz(&index_path(dir, &id), &after)?;
+        let mut chain = parent.chain.clone();
+        chain.push(parent.id.clone());
+        let info = BackupInfo {
+            id: id.clone(),
+            mode: BackupMode::Differential,
+            created_at: now(),
+            chain,
+            parent_hash: Some(parent.sha256.clone()),
+            sha256: file_hash(&path)?,
+            bytes,
+            file,
+            schema_version,
+            row_counts,
+            changed_rows,
+            deleted_rows,
+            verification: None,
+        };
+        save_info(dir, &info)?;
+        Ok(info)
+    })();
+    if result.is_err() {
+        discard(dir, &id);
+    }
+    result
+}
+
+/// The backups to apply for `target`, baseline first, after checking that every
+/// link is present, unaltered and built on the one before it
+fn resolve_chain(dir: &Path, target: &BackupInfo) -> Result<Vec<BackupInfo>, String> {
+    let mut chain = Vec::new();
+    let mut missing = Vec::new();
+    for id in target.chain.iter().chain(std::iter::once(&target.id)) {
+        match load_info(dir, id) {
+            Ok(info) if dir.join(&info.file).is_file() => chain.push(info),
+            _ => missing.push(id.clone()),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(format!(
+            "Backup {} can't be restored; missing from its chain: {}",
+            target.id,
+            missing.join(", ")
+        ));
+    }
+    let altered: Vec<&str> = chain
+        .iter()
+        .filter(|info| file_hash(&dir.join(&info.file)).map_or(true, |hash| hash != info.sha256))
+        .map(|info| info.id.as_str())
+        .collect();
+    if !altered.is_empty() {
+        return Err(format!(
+            "Backup {} can't be restored; changed since they were written: {}",
+            target.id,
+            altered.join(", ")
+        ));
+    }
+    for pair in chain.windows(2) {
+        if pair[1].parent_hash.as_deref() != Some(pair[0].sha256.as_str()) {
+            return Err(format!("Backup {} was not taken on top of {}", pair[1].id, pair[0].id));
+        }
+    }
+    Ok(chain)
+}
+
+fn apply_delta(conn: &Connection, delta: &Delta) -> Result<(), String> {
+    for (table, changes) in &delta.tables {
+        let mut delete = conn
+            .prepare(&format!("DELETE FROM {} WHERE rowid = ?1", quote(table)))
+            .map_err(|e| e.to_string())?;
+        for rowid in &changes.deletes {
+            delete.execute([rowid]).map_err(|e| format!("Deleting from {}: {}", table, e))?;
+        }
+        let list = changes.columns.iter().map(|c| quote(c)).collect::<Vec<_>>().join(", ");
+        let slots = (1..=changes.columns.len() + 1).map(|i| format!("?{}", i)).collect::<Vec<_>>().join(", ");
+        let mut upsert = conn
+            .prepare(&format!("INSERT OR REPLACE INTO {} (rowid, {}) VALUES ({})", quote(table), list, slots))
+            .map_err(|e| format!("Table {} no longer matches the backup: {}", table, e))?;
+        for (rowid, cells) in &changes.upserts {
+            let values = std::iter::once(rowid as &dyn ToSql).chain(cells.iter().map(|c| c as &dyn ToSql));
+            upsert
+                .execute(params_from_iter(values))
+                .map_err(|e| format!("Writing {}: {}", table, e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Rebuild the state `chain` describes as a new database at `dest`
+fn restore_chain(dir: &Path, chain: &[BackupInfo], dest: &Path) -> Result<(), String> {
+    let baseline = chain.first().ok_or("Empty backup chain")?;
+    std::fs::copy(dir.join(&baseline.file), dest).map_err(|e| format!("Cannot copy {}: {}", baseline.file, e))?;
+    if chain.len() == 1 {
+        return Ok(());
+    }
+    let mut conn = Connection::open(dest).map_err(|e| e.to_string())?;
+    // Deltas already hold whatever the triggers wrote, so they must not fire again
+    let triggers: Vec<(String, String)> = {
+        let mut stmt = conn
+            .prepare("SELECT name, sql FROM sqlite_master WHERE type = 'trigger' AND sql IS NOT NULL")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    for (name, _) in &triggers {
+        tx.execute(&format!("DROP TRIGGER {}", quote(name)), [])
+            .map_err(|e| e.to_string())?;
+    }
+    for info in &chain[1..] {
+        let delta: Delta = read_gz(&dir.join(&info.file))?;
+        apply_delta(&tx, &delta).map_err(|e| format!("Applying {}: {}", info.id, e))?;
+    }
+    for (name, sql) in &triggers {
+        tx.execute(sql, []).map_err(|e| format!("Recreating trigger {}: {}", name, e))?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    fts::rebuild(&mut conn)?;
+    Ok(())
+}
+
+fn quick_check(conn: &Connection) -> Result<String, String> {
+    let mut stmt = conn.prepare("PRAGMA quick_check").map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| row.get::<_, String>(0)).map_err(|e| e.to_string())?;
+    let lines: Vec<String> = rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+    Ok(lines.join("\n"))
+}
+
+/// Restore `target` into a scratch database and check it
+fn verify(dir: &Path, target: &BackupInfo) -> Result<VerifyReport, String> {
+    let chain = resolve_chain(dir, target)?;
+    let scratch = std::env::temp_dir().join(format!("workany-verify-{}-{}.db", std::process::id(), target.id));
+    let result = (|| {
+        restore_chain(dir, &chain, &scratch)?;
+        let conn = Connection::open_with_flags(&scratch, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| e.to_string())?;
+        let quick_check = quick_check(&conn)?;
+        let mut mismatches = Vec::new();
+        for (table, &expected) in &target.row_counts {
+            let actual: i64 = conn
+                .query_row(&format!("SELECT COUNT(*) FROM {}", quote(table)), [], |row| row.get(0))
+                .unwrap_or(-1);
+            if actual != expected {
+                mismatches.push(RowCountMismatch {
+                    table: table.clone(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(VerifyReport {
+            backup_id: target.id.clone(),
+            chain: chain.iter().map(|info| info.id.clone()).collect(),
+            ok: quick_check == "ok" && mismatches.is_empty(),
+            quick_check,
+            mismatches,
+        })
+    })();
+    let _ = std::fs::remove_file(&scratch);
+    result
+}
+
+/// Verify and record the outcome on the backup
+fn verify_and_record(dir: &Path, target: &BackupInfo) -> Result<VerifyReport, String> {
+    let outcome = verify(dir, target);
+    let mut info = target.clone();
+    info.verification = Some(Verification {
+        at: now(),
+        ok: outcome.as_ref().is_ok_and(|report| report.ok),
+        detail: match &outcome {
+            Ok(report) if report.ok => None,
+            Ok(report) => Some(format!(
+                "quick_check: {}; {} table(s) with wrong row counts",
+                report.quick_check,
+                report.mismatches.len()
+            )),
+            Err(e) => Some(e.clone()),
+        },
+    });
+    save_info(dir, &info)?;
+    outcome
+}
+
+/// Group backups under their baseline, oldest chain first
+fn chains(dir: &Path, all: Vec<BackupInfo>) -> Vec<BackupChain> {
+    let mut grouped: Vec<BackupChain> = Vec::new();
+    for info in all {
+        let baseline_id = info.baseline().to_string();
+        let position = match grouped.iter().position(|chain| chain.baseline_id == baseline_id) {
+            Some(position) => position,
+            None => {
+                grouped.push(BackupChain {
+                    baseline_id,
+                    baseline: None,
+                    deltas: Vec::new(),
+                    missing: Vec::new(),
+                });
+                grouped.len() - 1
+            }
+        };
+        let chain = &mut grouped[position];
+        match info.mode {
+            BackupMode::Full => chain.baseline = Some(info),
+            BackupMode::Differential => chain.deltas.push(info),
+        }
+    }
+    for chain in &mut grouped {
+        let present: BTreeSet<&str> = chain
+            .baseline
+            .iter()
+            .chain(&chain.deltas)
+            .filter(|info| dir.join(&info.file).is_file())
+            .map(|info| info.id.as_str())
+            .collect();
+        let referenced: BTreeSet<&str> = chain
+            .deltas
+            .iter()
+            .flat_map(|info| info.chain.iter().map(String::as_str).chain(std::iter::once(info.id.as_str())))
+            .chain(std::iter::once(chain.baseline_id.as_str()))
+            .collect();
+        chain.missing = referenced.difference(&present).map(|id| id.to_string()).collect();
+    }
+    grouped
+}
+
+/// Drop all but the newest `keep` chains
+fn prune(dir: &Path, keep: u32) -> Result<(), String> {
+    let grouped = chains(dir, load_all(dir)?);
+    let excess = grouped.len().saturating_sub(keep.max(1) as usize);
+    for chain in &grouped[..excess] {
+        for info in chain.baseline.iter().chain(&chain.deltas) {
+            discard(dir, &info.id);
+        }
+        println!("[Backup] Pruned chain {}", chain.baseline_id);
+    }
+    Ok(())
+}
+
+fn older_than(timestamp: &str, days: u32) -> bool {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map_or(true, |at| Utc::now() - at.with_timezone(&Utc) >= chrono::Duration::days(days as i64))
+}
+
+/// Take whichever backup or verification the policy says is due
+fn run_due(conn: &mut Connection, dir: &Path, policy: &BackupPolicy) -> Result<(), String> {
+    let all = load_all(dir)?;
+    let last_full = all.iter().rev().find(|info| info.mode == BackupMode::Full);
+    let schema_changed = all
+        .last()
+        .is_some_and(|info| schema_version(conn).is_ok_and(|version| version != info.schema_version));
+    let full_due = policy.full_every_days > 0
+        && (schema_changed || last_full.map_or(true, |info| older_than(&info.created_at, policy.full_every_days)));
+    if full_due {
+        let info = full_backup(conn, dir)?;
+        println!("[Backup] Full backup {} ({} bytes)", info.id, info.bytes);
+        return prune(dir, policy.keep_chains);
+    }
+    if let Some(last) = all.last() {
+        if policy.differential_every_days > 0 && older_than(&last.created_at, policy.differential_every_days) {
+            let info = differential_backup(conn, dir)?;
+            println!(
+                "[Backup] Differential backup {} ({} changed, {} deleted rows)",
+                info.id, info.changed_rows, info.deleted_rows
+            );
+            return Ok(());
+        }
+        let last_verified = all.iter().filter_map(|info| info.verification.as_ref()).map(|v| v.at.as_str()).max();
+        if policy.verify_every_days > 0 && last_verified.map_or(true, |at| older_than(at, policy.verify_every_days)) {
+            let report = verify_and_record(dir, last)?;
+            println!("[Backup] Verified {}: {}", report.backup_id, if report.ok { "ok" } else { "FAILED" });
+        }
+    }
+    Ok(())
+}
+
+/// Follow the backup policy in the background
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(STARTUP_DELAY);
+        loop {
+            let result = db::open(&app).and_then(|mut conn| {
+                let policy: BackupPolicy = settings::get_or(&conn, POLICY_KEY, BackupPolicy::default())?;
+                if !policy.enabled {
+                    return Ok(());
+                }
+                let dir = backups_dir(&app)?;
+                let Ok(_running) = RUNNING.try_lock() else {
+                    return Ok(());
+                };
+                run_due(&mut conn, &dir, &policy)
+            });
+            if let Err(e) = result {
+                eprintln!("[Backup] Scheduled backup failed: {}", e);
+            }
+            background::sleep(&app, &ACTIVITY);
+        }
+    });
+}
+
+fn pending_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("db.restore-pending")
+}
+
+/// Swap in a restore staged by `restore_backup`. Runs before migrations, while
+/// nothing else has the database open; the replaced file is kept as
+/// `workany.db.pre-restore`.
+pub fn apply_pending_restore(identifier: &str) {
+    let Some(db_path) = db::db_path_for_identifier(identifier) else {
+        return;
+    };
+    let pending = pending_path(&db_path);
+    if !pending.is_file() {
+        return;
+    }
+    let previous = db_path.with_extension("db.pre-restore");
+    let result = (|| -> std::io::Result<()> {
+        if db_path.exists() {
+            std::fs::rename(&db_path, &previous)?;
+        }
+        // The old WAL belongs with the old file
+        for suffix in ["-wal", "-shm"] {
+            let side = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+            if side.exists() {
+                std::fs::rename(&side, format!("{}{}", previous.display(), suffix))?;
+            }
+        }
+        std::fs::rename(&pending, &db_path)
+    })();
+    match result {
+        Ok(()) => println!("[Backup] Restored the database; the previous one is at {}", previous.display()),
+        Err(e) => eprintln!("[Backup] Cannot apply the staged restore: {}", e),
+    }
+}
+
+/// Id of the backup a path names: its data, index or info file, or a bare id
+fn backup_id_from(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_string_lossy();
+    let id = [".delta.gz", ".rows.gz", ".json", ".db"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(&name);
+    Some(id.to_string())
+}
+
+/// Take a backup as a job. `differential` needs an intact chain with the current schema.
+#[tauri::command]
+pub async fn backup_database(app: AppHandle, mode: BackupMode) -> Result<u64, String> {
+    let dir = backups_dir(&app)?;
+    Ok(jobs::spawn(&app, "backup_database", move |ctx| {
+        let _running = RUNNING.lock().map_err(|e| e.to_string())?;
+        let mut conn = db::open(ctx.app())?;
+        let info = match mode {
+            BackupMode::Full => full_backup(&conn, &dir)?,
+            BackupMode::Differential => differential_backup(&mut conn, &dir)?,
+        };
+        if mode == BackupMode::Full {
+            let policy: BackupPolicy = settings::get_or(&conn, POLICY_KEY, BackupPolicy::default())?;
+            prune(&dir, policy.keep_chains)?;
+        }
+        serde_json::to_value(info).map_err(|e| e.to_string())
+    }))
+}
+
+/// Backups grouped by baseline, oldest chain first
+#[tauri::command]
+pub async fn list_backups(app: AppHandle) -> Result<Vec<BackupChain>, String> {
+    let dir = backups_dir(&app)?;
+    tauri::async_runtime::spawn_blocking(move || Ok(chains(&dir, load_all(&dir)?)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Restore a backup and its chain into a scratch database, then run quick_check and
+/// compare row counts. `path` is any of the backup's files.
+#[tauri::command]
+pub async fn verify_backup(app: AppHandle, path: String) -> Result<u64, String> {
+    let path = PathBuf::from(path);
+    let id = backup_id_from(&path).ok_or("Not a backup file")?;
+    let dir = match path.parent() {
+        Some(parent) if parent.join(format!("{}.json", id)).is_file() => parent.to_path_buf(),
+        _ => backups_dir(&app)?,
+    };
+    Ok(jobs::spawn(&app, "verify_backup", move |_| {
+        let _running = RUNNING.lock().map_err(|e| e.to_string())?;
+        let target = load_info(&dir, &id)?;
+        let report = verify_and_record(&dir, &target)?;
+        serde_json::to_value(report).map_err(|e| e.to_string())
+    }))
+}
+
+/// Rebuild the database as of `backup_id` and stage it to replace the current one on
+/// the next launch. With `restart`, the app relaunches as soon as it's staged.
+#[tauri::command]
+pub async fn restore_backup(app: AppHandle, backup_id: String, restart: Option<bool>) -> Result<u64, String> {
+    let dir = backups_dir(&app)?;
+    let pending = pending_path(&db::db_path(&app)?);
+    Ok(jobs::spawn(&app, "restore_backup", move |ctx| {
+        let _running = RUNNING.lock().map_err(|e| e.to_string())?;
+        let target = load_info(&dir, &backup_id)?;
+        let chain = resolve_chain(&dir, &target)?;
+        let staging = pending.with_extension("restore-tmp");
+        restore_chain(&dir, &chain, &staging)?;
+        let check = Connection::open(&staging)
+            .map_err(|e| e.to_string())
+            .and_then(|conn| quick_check(&conn));
+        if check.as_deref() != Ok("ok") {
+            let _ = std::fs::remove_file(&staging);
+            return Err(format!("The restored database failed quick_check: {}", check.unwrap_or_else(|e| e)));
+        }
+        std::fs::rename(&staging, &pending).map_err(|e| e.to_string())?;
+        println!("[Backup] Staged restore of {} at {}", backup_id, pending.display());
+        if restart.unwrap_or(false) {
+            ctx.app().request_restart();
+        }
+        Ok(json!({
+            "backup_id": backup_id,
+            "chain": chain.iter().map(|info| &info.id).collect::<Vec<_>>(),
+            "staged": pending,
+            "restart_required": true,
+        }))
+    }))
+}
+
+#[tauri::command]
+pub async fn get_backup_policy(app: AppHandle) -> Result<BackupPolicy, String> {
+    db::with_conn(&app, |conn| settings::get_or(conn, POLICY_KEY, BackupPolicy::default())).await
+}
+
+#[tauri::command]
+pub async fn set_backup_policy(app: AppHandle, policy: BackupPolicy) -> Result<BackupPolicy, String> {
+    db::with_conn(&app, move |conn| {
+        settings::set(conn, POLICY_KEY, &policy)?;
+        Ok(policy)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn seeded_db() -> Connection {
+        let conn = crate::migrations::test_db();
+        for (name, color) in [("alpha", "red"), ("beta", "blue"), ("gamma", "green")] {
+            conn.execute("INSERT INTO tags (name, color) VALUES (?1, ?2)", params![name, color])
+                .unwrap();
+        }
+        conn
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workany-backup-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn tags(conn: &Connection) -> Vec<(i64, String, Option<String>)> {
+        let mut stmt = conn.prepare("SELECT id, name, color FROM tags ORDER BY id").unwrap();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn baseline_plus_deltas_restores_the_latest_state() {
+        let dir = scratch("chain");
+        let mut conn = seeded_db();
+        full_backup(&conn, &dir).unwrap();
+
+        conn.execute("UPDATE tags SET color = 'black' WHERE name = 'alpha'", []).unwrap();
+        conn.execute("DELETE FROM tags WHERE name = 'beta'", []).unwrap();
+        let first = differential_backup(&mut conn, &dir).unwrap();
+        assert_eq!(first.row_counts["tags"], 2);
+        assert_eq!(first.deleted_rows, 1);
+
+        conn.execute("INSERT INTO tags (name, color) VALUES ('delta', ?1)", [Option::<String>::None])
+            .unwrap();
+        let second = differential_backup(&mut conn, &dir).unwrap();
+        assert_eq!(second.chain.len(), 2);
+        assert_eq!(second.changed_rows, 1);
+
+        let chain = resolve_chain(&dir, &second).unwrap();
+        let dest = dir.join("restored.db");
+        restore_chain(&dir, &chain, &dest).unwrap();
+        let restored = Connection::open(&dest).unwrap();
+        assert_eq!(tags(&restored), tags(&conn));
+        assert!(verify(&dir, &second).unwrap().ok);
+
+        let listed = chains(&dir, load_all(&dir).unwrap());
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].deltas.len(), 2);
+        assert!(listed[0].missing.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_link_is_reported_before_restoring() {
+        let dir = scratch("broken");
+        let mut conn = seeded_db();
+        full_backup(&conn, &dir).unwrap();
+        conn.execute("UPDATE tags SET color = 'black'", []).unwrap();
+        let first = differential_backup(&mut conn, &dir).unwrap();
+        conn.execute("DELETE FROM tags", []).unwrap();
+        let second = differential_backup(&mut conn, &dir).unwrap();
+
+        std::fs::remove_file(dir.join(&first.file)).unwrap();
+        let error = resolve_chain(&dir, &second).unwrap_err();
+        assert!(error.contains(&first.id), "{}", error);
+        assert_eq!(chains(&dir, load_all(&dir).unwrap())[0].missing, vec![first.id.clone()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,596 @@
+// Page captures sent from the companion browser extension.
+//
+// The extension talks to a native-messaging host: the browser starts this same
+// executable with `--native-messaging-host` (through a small wrapper script, since
+// browsers pass their own arguments) and exchanges length-prefixed JSON over stdio.
+// The host validates a `page_capture`, stores it in the `captures` table and exits
+// when the browser closes the pipe. The running app polls the table and emits
+// `capture://received` for each new capture so the UI can offer to turn it into a task.
+
+use crate::{background, db, events, window};
+use base64::Engine;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// Argument that switches the executable into native-messaging host mode
+pub const NATIVE_HOST_FLAG: &str = "--native-messaging-host";
+/// Must match `identifier` in tauri.conf.json; the host runs without a Tauri context
+const APP_IDENTIFIER: &str = "com.cloudwork.desktop";
+const HOST_NAME: &str = "com.cloudwork.workany";
+const MAX_MESSAGE_BYTES: usize = 12 * 1024 * 1024;
+const MAX_SCREENSHOT_BYTES: usize = 8 * 1024 * 1024;
+const MAX_URL_LEN: usize = 8 * 1024;
+const MAX_TITLE_LEN: usize = 1024;
+const MAX_SELECTION_LEN: usize = 64 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Captures come from the user, so they are picked up promptly even when idle
+const ACTIVITY: background::Activity = background::Activity {
+    name: "captures",
+    interval: POLL_INTERVAL,
+    coalescable: false,
+};
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Capture {
+    pub id: i64,
+    pub url: String,
+    pub title: Option<String>,
+    pub selection_text: Option<String>,
+    pub screenshot_path: Option<String>,
+    pub source: Option<String>,
+    pub status: String,
+    pub task_id: Option<String>,
+    pub created_at: String,
+}
+
+impl Capture {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Capture {
+            id: row.get(0)?,
+            url: row.get(1)?,
+            title: row.get(2)?,
+            selection_text: row.get(3)?,
+            screenshot_path: row.get(4)?,
+            source: row.get(5)?,
+            status: row.get(6)?,
+            task_id: row.get(7)?,
+            created_at: row.get(8)?,
+        })
+    }
+}
+
+const CAPTURE_COLUMNS: &str =
+    "id, url, title, selection_text, screenshot_path, source, status, task_id, created_at";
+
+/// Payload the extension sends for "Send to WorkAny"
+#[derive(Debug, Deserialize)]
+struct PageCapture {
+    url: String,
+    title: Option<String>,
+    selection_text: Option<String>,
+    screenshot_base64: Option<String>,
+}
+
+/// Attachment in the shape the chat input uses for images
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureAttachment {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub attachment_type: &'static str,
+    pub name: String,
+    pub data: String,
+    pub mime_type: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptureConversion {
+    pub capture_id: i64,
+    /// Id to create the task under, in the same format the frontend generates
+    pub task_id: String,
+    pub prompt: String,
+    pub attachments: Vec<CaptureAttachment>,
+}
+
+fn truncate(value: Option<String>, max: usize) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            if v.len() <= max {
+                return v;
+            }
+            let mut end = max;
+            while !v.is_char_boundary(end) {
+                end -= 1;
+            }
+            v[..end].to_string()
+        })
+}
+
+pub(crate) fn screenshot_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".workany").join("captures"))
+}
+
+/// Decode, size-check and save a screenshot, returning its path
+fn save_screenshot(encoded: &str) -> Result<PathBuf, String> {
+    let encoded = encoded.split_once(',').map_or(encoded, |(_, data)| data);
+    if encoded.len() > MAX_SCREENSHOT_BYTES * 4 / 3 + 4 {
+        return Err("Screenshot is too large".to_string());
+    }
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|e| format!("Screenshot is not valid base64: {}", e))?;
+    let ext = if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "png"
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "jpg"
+    } else {
+        return Err("Screenshot must be a PNG or JPEG image".to_string());
+    };
+    let dir = screenshot_dir().ok_or("Cannot resolve the home directory")?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join(format!(
+        "capture-{}.{}",
+        chrono::Utc::now().format("%Y%m%d-%H%M%S%.3f"),
+        ext
+    ));
+    std::fs::write(&path, bytes).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+fn store_capture(conn: &Connection, payload: PageCapture, source: Option<&str>) -> Result<i64, String> {
+    let url = payload.url.trim();
+    if url.len() > MAX_URL_LEN {
+        return Err("URL is too long".to_string());
+    }
+    let parsed = tauri::Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("Only http and https pages can be captured".to_string());
+    }
+    let screenshot = payload
+        .screenshot_base64
+        .filter(|s| !s.is_empty())
+        .map(|s| save_screenshot(&s))
+        .transpose()?;
+
+    let inserted = conn.execute(
+        "INSERT INTO captures (url, title, selection_text, screenshot_path, source) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            parsed.as_str(),
+            truncate(payload.title, MAX_TITLE_LEN),
+            truncate(payload.selection_text, MAX_SELECTION_LEN),
+            screenshot.as_ref().map(|p| p.to_string_lossy().to_string()),
+            source,
+        ],
+    );
+    if let Err(e) = inserted {
+        if let Some(path) = &screenshot {
+            let _ = std::fs::remove_file(path);
+        }
+        return Err(e.to_string());
+    }
+    Ok(conn.last_insert_rowid())
+}
+
+// ============ Native messaging host ============
+
+fn read_message(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_ne_bytes(len) as usize;
+    if len > MAX_MESSAGE_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Message is too large"));
+    }
+    let mut buf = vec![0u8; len];
+    input.read_exact(&mut buf)?;
+    Ok(Some(buf))
+}
+
+fn write_message(output: &mut impl Write, value: &Value) -> io::Result<()> {
+    let bytes = serde_json::to_vec(value)?;
+    output.write_all(&(bytes.len() as u32).to_ne_bytes())?;
+    output.write_all(&bytes)?;
+    output.flush()
+}
+
+fn open_for_host() -> Result<Connection, String> {
+    let path = db::db_path_for_identifier(APP_IDENTIFIER).ok_or("Cannot resolve the app config directory")?;
+    // Never create the database here: it only exists once the app has run
+    let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .map_err(|_| "Open WorkAny once before sending pages to it".to_string())?;
+    conn.busy_timeout(Duration::from_secs(5)).map_err(|e| e.to_string())?;
+    Ok(conn)
+}
+
+fn handle_message(raw: &[u8], source: Option<&str>) -> Value {
+    let message: Value = match serde_json::from_slice(raw) {
+        Ok(message) => message,
+        Err(e) => return json!({ "ok": false, "error": format!("Invalid JSON: {}", e) }),
+    };
+    match message.get("type").and_then(Value::as_str) {
+        Some("ping") => json!({ "ok": true, "version": env!("CARGO_PKG_VERSION") }),
+        Some("page_capture") => {
+            let result = serde_json::from_value::<PageCapture>(message)
+                .map_err(|e| format!("Invalid page_capture: {}", e))
+                .and_then(|payload| store_capture(&open_for_host()?, payload, source));
+            match result {
+                Ok(id) => json!({ "ok": true, "capture_id": id }),
+                Err(e) => json!({ "ok": false, "error": e }),
+            }
+        }
+        other => json!({ "ok": false, "error": format!("Unknown message type {:?}", other) }),
+    }
+}
+
+/// Entry point for native-messaging host mode; returns the process exit code
+pub fn run_native_messaging_host() -> i32 {
+    // Chromium passes the calling origin, Firefox the extension id after the manifest path
+    let source = std::env::args()
+        .skip_while(|arg| arg != NATIVE_HOST_FLAG)
+        .skip(1)
+        .find(|arg| !arg.starts_with("--parent-window") && !arg.ends_with(".json"));
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    loop {
+        match read_message(&mut stdin) {
+            Ok(Some(raw)) => {
+                let reply = handle_message(&raw, source.as_deref());
+                if write_message(&mut stdout, &reply).is_err() {
+                    return 1;
+                }
+            }
+            Ok(None) => return 0,
+            Err(e) => {
+                let _ = write_message(&mut stdout, &json!({ "ok": false, "error": e.to_string() }));
+                return 1;
+            }
+        }
+    }
+}
+
+// ============ Host registration ============
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Browser {
+    Chrome,
+    Chromium,
+    Edge,
+    Brave,
+    Firefox,
+}
+
+impl Browser {
+    fn slug(self) -> &'static str {
+        match self {
+            Browser::Chrome => "chrome",
+            Browser::Chromium => "chromium",
+            Browser::Edge => "edge",
+            Browser::Brave => "brave",
+            Browser::Firefox => "firefox",
+        }
+    }
+
+    /// Directory the browser reads host manifests from (macOS and Linux)
+    #[cfg(not(windows))]
+    fn manifest_dir(self) -> Option<PathBuf> {
+        let home = dirs::home_dir()?;
+        #[cfg(target_os = "macos")]
+        let dir = {
+            let support = home.join("Library/Application Support");
+            match self {
+                Browser::Chrome => support.join("Google/Chrome/NativeMessagingHosts"),
+                Browser::Chromium => support.join("Chromium/NativeMessagingHosts"),
+                Browser::Edge => support.join("Microsoft Edge/NativeMessagingHosts"),
+                Browser::Brave => support.join("BraveSoftware/Brave-Browser/NativeMessagingHosts"),
+                Browser::Firefox => support.join("Mozilla/NativeMessagingHosts"),
+            }
+        };
+        #[cfg(not(target_os = "macos"))]
+        let dir = match self {
+            Browser::Chrome => home.join(".config/google-chrome/NativeMessagingHosts"),
+            Browser::Chromium => home.join(".config/chromium/NativeMessagingHosts"),
+            Browser::Edge => home.join(".config/microsoft-edge/NativeMessagingHosts"),
+            Browser::Brave => home.join(".config/BraveSoftware/Brave-Browser/NativeMessagingHosts"),
+            Browser::Firefox => home.join(".mozilla/native-messaging-hosts"),
+        };
+        Some(dir)
+    }
+
+    /// Per-user registry key pointing at the manifest (Windows)
+    #[cfg(windows)]
+    fn registry_key(self) -> String {
+        let base = match self {
+            Browser::Chrome => r"HKCU\Software\Google\Chrome\NativeMessagingHosts",
+            Browser::Chromium => r"HKCU\Software\Chromium\NativeMessagingHosts",
+            Browser::Edge => r"HKCU\Software\Microsoft\Edge\NativeMessagingHosts",
+            Browser::Brave => r"HKCU\Software\BraveSoftware\Brave-Browser\NativeMessagingHosts",
+            Browser::Firefox => r"HKCU\Software\Mozilla\NativeMessagingHosts",
+        };
+        format!(r"{}\{}", base, HOST_NAME)
+    }
+}
+
+fn host_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join("native-messaging"))
+        .map_err(|e| e.to_string())
+}
+
+/// Write the wrapper the browser launches, which adds our host flag
+fn write_host_wrapper(dir: &Path) -> Result<PathBuf, String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    #[cfg(windows)]
+    let (path, script) = (
+        dir.join("workany-native-host.bat"),
+        format!("@echo off\r\n\"{}\" {} %*\r\n", exe.display(), NATIVE_HOST_FLAG),
+    );
+    #[cfg(not(windows))]
+    let (path, script) = (
+        dir.join("workany-native-host.sh"),
+        format!(
+            "#!/bin/sh\nexec \"{}\" {} \"$@\"\n",
+            exe.display().to_string().replace('"', "\\\""),
+            NATIVE_HOST_FLAG
+        ),
+    );
+
+    std::fs::write(&path, script).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).map_err(|e| e.to_string())?;
+    }
+    Ok(path)
+}
+
+fn manifest_json(browser: Browser, wrapper: &Path, extension_id: &str) -> Value {
+    let mut manifest = json!({
+        "name": HOST_NAME,
+        "description": "WorkAny page capture",
+        "path": wrapper.to_string_lossy(),
+        "type": "stdio",
+    });
+    match browser {
+        Browser::Firefox => manifest["allowed_extensions"] = json!([extension_id]),
+        _ => manifest["allowed_origins"] = json!([format!("chrome-extension://{}/", extension_id)]),
+    }
+    manifest
+}
+
+fn validate_extension_id(extension_id: &str) -> Result<(), String> {
+    let valid = !extension_id.is_empty()
+        && extension_id.len() <= 128
+        && extension_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@._-{}".contains(c));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid extension id '{}'", extension_id))
+    }
+}
+
+/// Register the native-messaging host for one browser, returning the manifest path
+#[tauri::command]
+pub fn install_native_messaging_host(app: AppHandle, browser: Browser, extension_id: String) -> Result<String, String> {
+    validate_extension_id(&extension_id)?;
+    let dir = host_dir(&app)?;
+    let wrapper = write_host_wrapper(&dir)?;
+    let manifest = serde_json::to_string_pretty(&manifest_json(browser, &wrapper, &extension_id))
+        .map_err(|e| e.to_string())?;
+
+    #[cfg(windows)]
+    let manifest_path = {
+        let path = dir.join(format!("{}.{}.json", HOST_NAME, browser.slug()));
+        std::fs::write(&path, manifest).map_err(|e| e.to_string())?;
+        let output = std::process::Command::new("reg")
+            .args(["add", &browser.registry_key(), "/ve", "/t", "REG_SZ", "/d"])
+            .arg(&path)
+            .arg("/f")
+            .output()
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(format!(
+                "Registering the host failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        path
+    };
+    #[cfg(not(windows))]
+    let manifest_path = {
+        let dir = browser.manifest_dir().ok_or("Cannot resolve the home directory")?;
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(format!("{}.json", HOST_NAME));
+        std::fs::write(&path, manifest).map_err(|e| e.to_string())?;
+        path
+    };
+
+    println!("[Captures] Registered native messaging host for {}", browser.slug());
+    Ok(manifest_path.to_string_lossy().to_string())
+}
+
+/// Remove the native-messaging host registration for one browser
+#[tauri::command]
+pub fn uninstall_native_messaging_host(app: AppHandle, browser: Browser) -> Result<(), String> {
+    #[cfg(windows)]
+    {
+        let _ = std::process::Command::new("reg")
+            .args(["delete", &browser.registry_key(), "/f"])
+            .output();
+        let path = host_dir(&app)?.join(format!("{}.{}.json", HOST_NAME, browser.slug()));
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = &app;
+        if let Some(dir) = browser.manifest_dir() {
+            let path = dir.join(format!("{}.json", HOST_NAME));
+            if path.exists() {
+                std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    println!("[Captures] Removed native messaging host for {}", browser.slug());
+    Ok(())
+}
+
+// ============ App side ============
+
+fn new_captures(conn: &Connection, after: i64) -> Result<Vec<Capture>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {CAPTURE_COLUMNS} FROM captures WHERE id > ?1 AND status = 'pending' ORDER BY id"
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([after], Capture::from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Watch for captures written by the host process and announce them
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let mut last_seen: Option<i64> = None;
+        loop {
+            background::sleep(&app, &ACTIVITY);
+            let Ok(conn) = db::open(&app) else { continue };
+            let Some(after) = last_seen else {
+                // Captures from before this launch are listed, not announced
+                last_seen = conn
+                    .query_row("SELECT COALESCE(MAX(id), 0) FROM captures", [], |row| row.get(0))
+                    .ok();
+                continue;
+            };
+            match new_captures(&conn, after) {
+                Ok(captures) => {
+                    for capture in captures {
+                        last_seen = Some(capture.id);
+                        events::emit_typed(&app, events::CaptureReceived(capture));
+                        window::show_main_window(&app);
+                    }
+                }
+                Err(e) => eprintln!("[Captures] Poll failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Captures waiting to be triaged, oldest first
+#[tauri::command]
+pub async fn list_pending_captures(app: AppHandle) -> Result<Vec<Capture>, String> {
+    db::with_conn(&app, |conn| new_captures(conn, 0)).await
+}
+
+fn load_capture(conn: &Connection, capture_id: i64) -> Result<Capture, String> {
+    conn.query_row(
+        &format!("SELECT {CAPTURE_COLUMNS} FROM captures WHERE id = ?1"),
+        [capture_id],
+        Capture::from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Capture {} not found", capture_id))
+}
+
+fn screenshot_attachment(path: &str) -> Option<CaptureAttachment> {
+    let bytes = std::fs::read(path).ok()?;
+    let mime_type = if path.ends_with(".png") { "image/png" } else { "image/jpeg" };
+    let name = Path::new(path).file_name()?.to_string_lossy().to_string();
+    Some(CaptureAttachment {
+        id: name.clone(),
+        attachment_type: "image",
+        name,
+        data: format!(
+            "data:{};base64,{}",
+            mime_type,
+            base64::engine::general_purpose::STANDARD.encode(bytes)
+        ),
+        mime_type: mime_type.to_string(),
+        path: path.to_string(),
+    })
+}
+
+fn compose_prompt(capture: &Capture, prefix: Option<&str>) -> String {
+    let mut parts = Vec::new();
+    if let Some(prefix) = prefix.map(str::trim).filter(|p| !p.is_empty()) {
+        parts.push(prefix.to_string());
+    }
+    match &capture.title {
+        Some(title) => parts.push(format!("{}\n{}", title, capture.url)),
+        None => parts.push(capture.url.clone()),
+    }
+    if let Some(selection) = &capture.selection_text {
+        let quoted: Vec<String> = selection.lines().map(|l| format!("> {}", l)).collect();
+        parts.push(quoted.join("\n"));
+    }
+    parts.join("\n\n")
+}
+
+/// Mark a capture as converted and return the prompt, attachments and task id the
+/// frontend should start the task with
+#[tauri::command]
+pub async fn convert_capture_to_task(
+    app: AppHandle,
+    capture_id: i64,
+    prompt_prefix: Option<String>,
+) -> Result<CaptureConversion, String> {
+    db::with_conn(&app, move |conn| {
+        let capture = load_capture(conn, capture_id)?;
+        if capture.status != "pending" {
+            return Err(format!("Capture {} was already {}", capture_id, capture.status));
+        }
+        let task_id = chrono::Utc::now().timestamp_millis().to_string();
+        conn.execute(
+            "UPDATE captures SET status = 'converted', task_id = ?1 WHERE id = ?2",
+            params![task_id, capture_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(CaptureConversion {
+            capture_id,
+            prompt: compose_prompt(&capture, prompt_prefix.as_deref()),
+            attachments: capture
+                .screenshot_path
+                .as_deref()
+                .and_then(screenshot_attachment)
+                .into_iter()
+                .collect(),
+            task_id,
+        })
+    })
+    .await
+}
+
+/// Drop a capture without creating a task
+#[tauri::command]
+pub async fn dismiss_capture(app: AppHandle, capture_id: i64) -> Result<(), String> {
+    db::with_conn(&app, move |conn| {
+        conn.execute(
+            "UPDATE captures SET status = 'dismissed' WHERE id = ?1 AND status = 'pending'",
+            [capture_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    })
+    .await
+}
@@ -0,0 +1,717 @@
+// Recording and replaying API traffic for frontend tests.
+//
+// While recording, every `sidecar_request` exchange is appended to a cassette file:
+// method, path, the redacted request body and its hash, status, the response headers
+// minus volatile ones, the redacted response, and when it happened. Streams read by the
+// webview are handed over as frames with `record_api_stream`. While replaying,
+// `sidecar_request` answers from the cassette by method + path + body hash instead of
+// calling the API; `Strict` fails unmatched requests, `Passthrough` sends them on.
+// `replay_api_stream` plays recorded frames back as events with their original
+// spacing, or back to back when the replay is accelerated.
+//
+// Cassettes are meant to be committed as fixtures, so the file is written the same way
+// every time: interactions in call order, object keys sorted, volatile headers
+// dropped. Both modes need developer mode, and `get_api_config` reports them.
+//
+// Every cassette recorded is listed under `RECORDED_KEY`, so a message redaction can
+// `scrub` the removed text from them, the tape in memory included, after recording
+// has stopped.
+
+use crate::circuit::CallFailure;
+use crate::error::CommandError;
+use crate::{db, events, redaction, settings};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use rusqlite::Connection;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+pub const CASSETTE_VERSION: u32 = 1;
+/// Paths of the cassettes recorded, for scrubbing redacted text from them
+const RECORDED_KEY: &str = "api_cassettes";
+
+/// Response headers that differ between otherwise identical responses
+const VOLATILE_HEADERS: &[&str] = &[
+    "age",
+    "connection",
+    "content-length",
+    "date",
+    "etag",
+    "expires",
+    "keep-alive",
+    "last-modified",
+    "request-id",
+    "server",
+    "set-cookie",
+    "transfer-encoding",
+    "x-request-id",
+    "x-response-time",
+];
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "cookie", "proxy-authorization", "x-api-key"];
+
+static NEXT_STREAM: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    /// Milliseconds since the stream started
+    pub at_ms: u64,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    pub body_hash: String,
+    pub request: Option<Value>,
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub response: Value,
+    /// Milliseconds since recording started
+    pub at_ms: u64,
+    pub elapsed_ms: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frames: Vec<Frame>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub version: u32,
+    pub interactions: Vec<Interaction>,
+}
+
+/// What a replay does with a request the cassette has no answer for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strictness {
+    Strict,
+    Passthrough,
+}
+
+/// A response as the proxy saw it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exchange {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CassetteStatus {
+    /// `recording` or `replay`
+    pub mode: &'static str,
+    pub path: String,
+    pub interactions: usize,
+    pub strictness: Option<Strictness>,
+    pub accelerated: bool,
+    /// Requests the replay had no recording for
+    pub unmatched: usize,
+}
+
+enum Tape {
+    Off,
+    Recording {
+        path: PathBuf,
+        started: Instant,
+        cassette: Cassette,
+    },
+    Replay {
+        path: PathBuf,
+        cassette: Cassette,
+        strictness: Strictness,
+        accelerated: bool,
+        played: Vec<bool>,
+        unmatched: usize,
+    },
+}
+
+impl Tape {
+    fn status(&self) -> Option<CassetteStatus> {
+        match self {
+            Tape::Off => None,
+            Tape::Recording { path, cassette, .. } => Some(CassetteStatus {
+                mode: "recording",
+                path: path.display().to_string(),
+                interactions: cassette.interactions.len(),
+                strictness: None,
+                accelerated: false,
+                unmatched: 0,
+            }),
+            Tape::Replay {
+                path,
+                cassette,
+                strictness,
+                accelerated,
+                unmatched,
+                ..
+            } => Some(CassetteStatus {
+                mode: "replay",
+                path: path.display().to_string(),
+                interactions: cassette.interactions.len(),
+                strictness: Some(*strictness),
+                accelerated: *accelerated,
+                unmatched: *unmatched,
+            }),
+        }
+    }
+
+    /// The recording for a request: the first one not yet played, or the last one
+    /// when every match has been played already
+    fn lookup(&mut self, method: &str, path: &str, body_hash: &str) -> Option<&Interaction> {
+        let Tape::Replay {
+            cassette,
+            played,
+            unmatched,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let matches: Vec<usize> = cassette
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| i.method == method && i.path == path && i.body_hash == body_hash)
+            .map(|(index, _)| index)
+            .collect();
+        let Some(index) = matches.iter().copied().find(|&i| !played[i]).or(matches.last().copied()) else {
+            *unmatched += 1;
+            return None;
+        };
+        played[index] = true;
+        cassette.interactions.get(index)
+    }
+}
+
+pub struct ApiTape(Mutex<Tape>);
+
+impl Default for ApiTape {
+    fn default() -> Self {
+        ApiTape(Mutex::new(Tape::Off))
+    }
+}
+
+impl ApiTape {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Tape> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn status(&self) -> Option<CassetteStatus> {
+        self.lock().status()
+    }
+}
+
+/// Strip anything credential-like from every string in `value`
+fn redact(value: &Value) -> Value {
+    match value {
+        Value::String(s) => Value::String(redaction::redact_secrets(s)),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        Value::Object(map) => Value::Object(map.iter().map(|(k, v)| (k.clone(), redact(v))).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Hash of the redacted body. serde_json keeps object keys sorted, so equal bodies
+/// hash the same whatever order the caller built them in.
+pub(crate) fn body_hash(body: Option<&Value>) -> String {
+    let canonical = body.map(|b| redact(b).to_string()).unwrap_or_default();
+    Sha256::digest(canonical.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Response headers worth keeping in a fixture
+pub(crate) fn stable_headers<'a>(headers: impl IntoIterator<Item = (&'a str, &'a str)>) -> BTreeMap<String, String> {
+    headers
+        .into_iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value))
+        .filter(|(name, _)| !VOLATILE_HEADERS.contains(&name.as_str()))
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                "[REDACTED:header]".to_string()
+            } else {
+                redaction::redact_secrets(value)
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+fn save(path: &Path, cassette: &Cassette) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let mut text = serde_json::to_string_pretty(cassette).map_err(|e| e.to_string())?;
+    text.push('\n');
+    let tmp = path.with_extension("partial");
+    std::fs::write(&tmp, text).map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+fn load(path: &Path) -> Result<Cassette, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let cassette: Cassette =
+        serde_json::from_str(&text).map_err(|e| format!("{} is not a cassette: {}", path.display(), e))?;
+    if cassette.version != CASSETTE_VERSION {
+        return Err(format!(
+            "Cassette version {} is not supported (expected {})",
+            cassette.version, CASSETTE_VERSION
+        ));
+    }
+    Ok(cassette)
+}
+
+/// Replace redacted text in every string of `value`; true if anything changed
+fn scrub_value(value: &mut Value, scrubber: &redaction::Scrubber) -> bool {
+    match value {
+        Value::String(s) => match scrubber.scrub(s) {
+            Cow::Owned(scrubbed) => {
+                *s = scrubbed;
+                true
+            }
+            Cow::Borrowed(_) => false,
+        },
+        Value::Array(items) => items.iter_mut().fold(false, |changed, v| scrub_value(v, scrubber) | changed),
+        Value::Object(map) => map.values_mut().fold(false, |changed, v| scrub_value(v, scrubber) | changed),
+        _ => false,
+    }
+}
+
+fn scrub_string(text: &mut String, scrubber: &redaction::Scrubber) -> bool {
+    match scrubber.scrub(text) {
+        Cow::Owned(scrubbed) => {
+            *text = scrubbed;
+            true
+        }
+        Cow::Borrowed(_) => false,
+    }
+}
+
+/// Replace redacted text everywhere in a cassette. A rewritten request gets its
+/// hash recomputed, so the cassette still replays.
+fn scrub_cassette(cassette: &mut Cassette, scrubber: &redaction::Scrubber) -> bool {
+    let mut changed = false;
+    for interaction in &mut cassette.interactions {
+        if let Some(request) = interaction.request.as_mut() {
+            if scrub_value(request, scrubber) {
+                interaction.body_hash = body_hash(Some(request));
+                changed = true;
+            }
+        }
+        changed |= scrub_value(&mut interaction.response, scrubber);
+        for value in interaction.headers.values_mut() {
+            changed |= scrub_string(value, scrubber);
+        }
+        for frame in &mut interaction.frames {
+            changed |= scrub_string(&mut frame.data, scrubber);
+        }
+    }
+    changed
+}
+
+/// Scrub redacted text from the tape in memory and every recorded cassette still
+/// on disk. Returns how many cassette files were rewritten.
+pub(crate) fn scrub(app: &AppHandle, conn: &Connection, scrubber: &redaction::Scrubber) -> Result<usize, String> {
+    let recorded: Vec<String> = settings::get_or(conn, RECORDED_KEY, Vec::new())?;
+    let tape = app.state::<ApiTape>();
+    let mut tape = tape.lock();
+    let active = match &mut *tape {
+        Tape::Off => None,
+        Tape::Recording { path, cassette, .. } | Tape::Replay { path, cassette, .. } => Some((path, cassette)),
+    };
+    let mut rewritten = 0;
+    let mut active_path = None;
+    if let Some((path, cassette)) = active {
+        if scrub_cassette(cassette, scrubber) {
+            save(path, cassette)?;
+            rewritten += 1;
+        }
+        active_path = Some(path.clone());
+    }
+
+    let mut kept = Vec::new();
+    for file in recorded {
+        let path = PathBuf::from(&file);
+        if active_path.as_ref() == Some(&path) {
+            kept.push(file);
+            continue;
+        }
+        if !path.exists() {
+            continue;
+        }
+        let mut cassette = load(&path)?;
+        if scrub_cassette(&mut cassette, scrubber) {
+            save(&path, &cassette)?;
+            rewritten += 1;
+        }
+        kept.push(file);
+    }
+    settings::set(conn, RECORDED_KEY, &kept)?;
+    Ok(rewritten)
+}
+
+/// The recorded answer to a request, while replaying. `None` means the request
+/// should go to the API.
+pub(crate) fn replay(app: &AppHandle, method: &str, path: &str, body: Option<&Value>) -> Option<Result<Exchange, CallFailure>> {
+    let tape = app.state::<ApiTape>();
+    let mut tape = tape.lock();
+    let strictness = match &*tape {
+        Tape::Replay { strictness, .. } => *strictness,
+        _ => return None,
+    };
+    match tape.lookup(method, path, &body_hash(body)) {
+        Some(found) => Some(Ok(Exchange {
+            status: found.status,
+            headers: found.headers.clone(),
+            body: found.response.clone(),
+        })),
+        None if strictness == Strictness::Strict => Some(Err(CallFailure::Caller(format!(
+            "No recorded response for {} {} in the replayed cassette",
+            method, path
+        )))),
+        None => None,
+    }
+}
+
+/// Append an exchange to the cassette, while recording
+pub(crate) fn record(
+    app: &AppHandle,
+    method: &str,
+    path: &str,
+    body: Option<&Value>,
+    exchange: &Exchange,
+    sent_at: Instant,
+) {
+    let elapsed_ms = sent_at.elapsed().as_millis() as u64;
+    append(app, method, path, |at_ms| Interaction {
+        method: method.to_string(),
+        path: path.to_string(),
+        body_hash: body_hash(body),
+        request: body.map(redact),
+        status: exchange.status,
+        headers: exchange.headers.clone(),
+        response: redact(&exchange.body),
+        at_ms,
+        elapsed_ms,
+        frames: Vec::new(),
+    });
+}
+
+fn append(
+    app: &AppHandle,
+    method: &str,
+    path: &str,
+    interaction: impl FnOnce(u64) -> Interaction,
+) {
+    let tape = app.state::<ApiTape>();
+    let mut tape = tape.lock();
+    if let Tape::Recording {
+        path: file,
+        started,
+        cassette,
+    } = &mut *tape
+    {
+        cassette
+            .interactions
+            .push(interaction(started.elapsed().as_millis() as u64));
+        if let Err(e) = save(file, cassette) {
+            eprintln!("[Cassette] Failed to save {} {}: {}", method, path, e);
+        }
+    }
+}
+
+async fn require_developer_mode(app: &AppHandle) -> Result<(), CommandError> {
+    let enabled = db::with_conn(app, |conn| Ok(settings::developer_mode(conn))).await?;
+    if enabled {
+        Ok(())
+    } else {
+        Err(CommandError::FeatureDisabled {
+            message: "Recording and replaying API traffic requires developer mode".to_string(),
+            feature: "developer_mode".to_string(),
+        })
+    }
+}
+
+/// Start writing every proxied API exchange to the cassette at `path`
+#[tauri::command]
+pub async fn start_api_recording(app: AppHandle, path: String) -> Result<CassetteStatus, CommandError> {
+    require_developer_mode(&app).await?;
+    let path = PathBuf::from(path);
+    let cassette = Cassette {
+        version: CASSETTE_VERSION,
+        interactions: Vec::new(),
+    };
+    save(&path, &cassette)?;
+    let file = path.display().to_string();
+    db::with_conn(&app, move |conn| {
+        let mut recorded: Vec<String> = settings::get_or(conn, RECORDED_KEY, Vec::new())?;
+        if !recorded.contains(&file) {
+            recorded.push(file);
+            settings::set(conn, RECORDED_KEY, &recorded)
+        } else {
+            Ok(())
+        }
+    })
+    .await?;
+    let tape = app.state::<ApiTape>();
+    let mut tape = tape.lock();
+    *tape = Tape::Recording {
+        path,
+        started: Instant::now(),
+        cassette,
+    };
+    println!("[Cassette] Recording API traffic");
+    Ok(tape.status().expect("recording"))
+}
+
+/// Answer API requests from the cassette at `path` instead of the API
+#[tauri::command]
+pub async fn start_api_replay(
+    app: AppHandle,
+    path: String,
+    strictness: Strictness,
+    accelerated: Option<bool>,
+) -> Result<CassetteStatus, CommandError> {
+    require_developer_mode(&app).await?;
+    let path = PathBuf::from(path);
+    let cassette = load(&path)?;
+    let tape = app.state::<ApiTape>();
+    let mut tape = tape.lock();
+    *tape = Tape::Replay {
+        path,
+        played: vec![false; cassette.interactions.len()],
+        cassette,
+        strictness,
+        accelerated: accelerated.unwrap_or(false),
+        unmatched: 0,
+    };
+    println!("[Cassette] Replaying recorded API traffic; responses are not live");
+    Ok(tape.status().expect("replay"))
+}
+
+/// Stop recording or replaying. Returns what was active.
+#[tauri::command]
+pub async fn stop_api_cassette(app: AppHandle) -> Result<Option<CassetteStatus>, String> {
+    let tape = app.state::<ApiTape>();
+    let mut tape = tape.lock();
+    let status = tape.status();
+    if let Tape::Recording { path, cassette, .. } = &*tape {
+        save(path, cassette)?;
+    }
+    *tape = Tape::Off;
+    Ok(status)
+}
+
+/// Add a stream the webview read to the recording. Frame times are relative to the
+/// start of the stream.
+#[tauri::command]
+pub async fn record_api_stream(
+    app: AppHandle,
+    method: String,
+    path: String,
+    body: Option<Value>,
+    frames: Vec<Frame>,
+) -> Result<(), String> {
+    let mut frames: Vec<Frame> = frames
+        .into_iter()
+        .map(|frame| Frame {
+            at_ms: frame.at_ms,
+            data: redaction::redact_secrets(&frame.data),
+        })
+        .collect();
+    frames.sort_by_key(|frame| frame.at_ms);
+    let method = method.to_ascii_uppercase();
+    let elapsed_ms = frames.last().map_or(0, |frame| frame.at_ms);
+    append(&app, &method, &path, |at_ms| Interaction {
+        method: method.clone(),
+        path: path.clone(),
+        body_hash: body_hash(body.as_ref()),
+        request: body.as_ref().map(redact),
+        status: 200,
+        headers: BTreeMap::new(),
+        response: Value::Null,
+        at_ms: at_ms.saturating_sub(elapsed_ms),
+        elapsed_ms,
+        frames,
+    });
+    Ok(())
+}
+
+/// Play a recorded stream back as `api://replay-frame` events and return the id the
+/// events carry
+#[tauri::command]
+pub async fn replay_api_stream(
+    app: AppHandle,
+    method: String,
+    path: String,
+    body: Option<Value>,
+) -> Result<String, String> {
+    let method = method.to_ascii_uppercase();
+    let (frames, accelerated) = {
+        let tape = app.state::<ApiTape>();
+        let mut tape = tape.lock();
+        let accelerated = matches!(&*tape, Tape::Replay { accelerated: true, .. });
+        let found = tape
+            .lookup(&method, &path, &body_hash(body.as_ref()))
+            .ok_or_else(|| format!("No recorded stream for {} {}", method, path))?;
+        (found.frames.clone(), accelerated)
+    };
+    let stream_id = format!("replay-{}", NEXT_STREAM.fetch_add(1, Ordering::Relaxed));
+    let id = stream_id.clone();
+    std::thread::spawn(move || {
+        let mut previous = 0;
+        let count = frames.len();
+        for (index, frame) in frames.into_iter().enumerate() {
+            if !accelerated {
+                std::thread::sleep(Duration::from_millis(frame.at_ms.saturating_sub(previous)));
+            }
+            previous = frame.at_ms;
+            events::emit_typed(
+                &app,
+                events::ApiReplayFrame {
+                    stream_id: id.clone(),
+                    index,
+                    data: Some(frame.data),
+                    done: index + 1 == count,
+                },
+            );
+        }
+        if count == 0 {
+            events::emit_typed(
+                &app,
+                events::ApiReplayFrame {
+                    stream_id: id,
+                    index: 0,
+                    data: None,
+                    done: true,
+                },
+            );
+        }
+    });
+    Ok(stream_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn interaction(path: &str, body: Option<Value>, response: Value) -> Interaction {
+        Interaction {
+            method: "POST".into(),
+            path: path.into(),
+            body_hash: body_hash(body.as_ref()),
+            request: body,
+            status: 200,
+            headers: BTreeMap::new(),
+            response,
+            at_ms: 0,
+            elapsed_ms: 0,
+            frames: Vec::new(),
+        }
+    }
+
+    fn replaying(interactions: Vec<Interaction>) -> Tape {
+        Tape::Replay {
+            path: PathBuf::from("fixture.json"),
+            played: vec![false; interactions.len()],
+            cassette: Cassette {
+                version: CASSETTE_VERSION,
+                interactions,
+            },
+            strictness: Strictness::Strict,
+            accelerated: true,
+            unmatched: 0,
+        }
+    }
+
+    #[test]
+    fn body_hash_ignores_key_order_and_secrets() {
+        let a: Value = serde_json::from_str(r#"{"prompt":"hi","key":"sk-abcdefghijklmnopqrstuv"}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"key":"sk-zyxwvutsrqponmlkjihgfe","prompt":"hi"}"#).unwrap();
+        assert_eq!(body_hash(Some(&a)), body_hash(Some(&b)));
+        assert_ne!(body_hash(Some(&a)), body_hash(None));
+    }
+
+    #[test]
+    fn repeated_requests_replay_in_recorded_order() {
+        let body = json!({"q": 1});
+        let mut tape = replaying(vec![
+            interaction("/a", Some(body.clone()), json!("first")),
+            interaction("/a", Some(body.clone()), json!("second")),
+        ]);
+        let hash = body_hash(Some(&body));
+        let answers: Vec<Value> = (0..3)
+            .map(|_| tape.lookup("POST", "/a", &hash).unwrap().response.clone())
+            .collect();
+        assert_eq!(answers, vec![json!("first"), json!("second"), json!("second")]);
+        assert!(tape.lookup("POST", "/b", &hash).is_none());
+        assert_eq!(tape.status().unwrap().unmatched, 1);
+    }
+
+    #[test]
+    fn cassettes_are_written_identically() {
+        let headers = stable_headers([
+            ("Content-Type", "application/json"),
+            ("Date", "Tue, 01 Oct 2026 10:00:00 GMT"),
+            ("X-Request-Id", "abc"),
+            ("Authorization", "Bearer whatever"),
+        ]);
+        assert_eq!(headers.keys().collect::<Vec<_>>(), vec!["authorization", "content-type"]);
+        assert_eq!(headers["authorization"], "[REDACTED:header]");
+
+        let dir = std::env::temp_dir().join(format!("workany-cassette-{}", std::process::id()));
+        let path = dir.join("session.json");
+        let mut recorded = interaction("/agent", Some(json!({"b": 2, "a": 1})), json!({"z": true, "y": [1, 2]}));
+        recorded.headers = headers;
+        let cassette = Cassette {
+            version: CASSETTE_VERSION,
+            interactions: vec![recorded],
+        };
+        save(&path, &cassette).unwrap();
+        let first = std::fs::read_to_string(&path).unwrap();
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded, cassette);
+        save(&path, &loaded).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), first);
+        assert!(first.find("\"a\"").unwrap() < first.find("\"b\"").unwrap());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn scrubbing_rewrites_every_copy_and_rehashes_the_request() {
+        let mut recorded = interaction(
+            "/agent",
+            Some(json!({"prompt": "use hunter22 please"})),
+            json!({"echo": ["hunter22"], "n": 1}),
+        );
+        recorded.frames = vec![Frame {
+            at_ms: 5,
+            data: "data: hunter22".into(),
+        }];
+        let untouched = interaction("/other", Some(json!({"prompt": "hi"})), json!("ok"));
+        let mut cassette = Cassette {
+            version: CASSETTE_VERSION,
+            interactions: vec![recorded, untouched.clone()],
+        };
+        let mut scrubber = redaction::Scrubber::default();
+        scrubber.add("hunter22", "[REDACTED:password]");
+
+        assert!(scrub_cassette(&mut cassette, &scrubber));
+        let scrubbed = &cassette.interactions[0];
+        assert_eq!(scrubbed.request, Some(json!({"prompt": "use [REDACTED:password] please"})));
+        assert_eq!(scrubbed.body_hash, body_hash(scrubbed.request.as_ref()));
+        assert_eq!(scrubbed.response, json!({"echo": ["[REDACTED:password]"], "n": 1}));
+        assert_eq!(scrubbed.frames[0].data, "data: [REDACTED:password]");
+        assert_eq!(cassette.interactions[1], untouched);
+        assert!(!scrub_cassette(&mut cassette, &scrubber));
+    }
+}
@@ -0,0 +1,431 @@
+// Resumable single-file exports.
+//
+// An exporter writes through a `CheckpointWriter` into `<output>.partial` and calls
+// `checkpoint` after each unit it can restart from (a task, a file, a batch of JSONL
+// lines). Every checkpoint flushes the output and rewrites `<output>.journal.json`
+// with the unit count, the bytes written and a SHA-256 of those bytes. Resuming
+// re-hashes the partial output, truncates anything written after the last checkpoint
+// and continues from there; if the hash doesn't match, the export starts over.
+// The journal is removed when the export finishes or is discarded. `export_all` is
+// the only exporter that writes through it so far.
+
+use crate::jobs::JobRegistry;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, State};
+
+const JOURNAL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportJournal {
+    pub version: u32,
+    /// Exporter that owns the journal; `resume_job` dispatches on it
+    pub kind: String,
+    /// Exporter arguments, kept so a resumed run reproduces the same output
+    pub params: Value,
+    pub output: PathBuf,
+    pub units_done: u64,
+    pub bytes_written: u64,
+    /// SHA-256 of the first `bytes_written` bytes of the partial output
+    pub sha256: String,
+    pub updated_at: i64,
+}
+
+impl ExportJournal {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read journal '{}': {}", path.display(), e))?;
+        let journal: ExportJournal =
+            serde_json::from_str(&text).map_err(|e| format!("Invalid journal '{}': {}", path.display(), e))?;
+        if journal.version != JOURNAL_VERSION {
+            return Err(format!("Unsupported journal version {}", journal.version));
+        }
+        Ok(journal)
+    }
+
+    /// Written to a temporary name and renamed, so the journal is never half-written
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        let mut file = File::create(&tmp)?;
+        serde_json::to_writer(&mut file, self)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+fn with_suffix(output: &Path, suffix: &str) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+pub fn journal_path(output: &Path) -> PathBuf {
+    with_suffix(output, ".journal.json")
+}
+
+pub fn partial_path(output: &Path) -> PathBuf {
+    with_suffix(output, ".partial")
+}
+
+/// Output writer that hashes what it writes and journals progress at checkpoints
+pub struct CheckpointWriter {
+    out: BufWriter<File>,
+    hasher: Sha256,
+    bytes: u64,
+    journal: ExportJournal,
+    journal_path: PathBuf,
+}
+
+impl Write for CheckpointWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.out.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl CheckpointWriter {
+    /// Start a fresh export, replacing any earlier partial output
+    pub fn create(kind: &str, params: Value, output: &Path) -> Result<Self, String> {
+        let file = File::create(partial_path(output)).map_err(|e| e.to_string())?;
+        let mut writer = CheckpointWriter {
+            out: BufWriter::new(file),
+            hasher: Sha256::new(),
+            bytes: 0,
+            journal: ExportJournal {
+                version: JOURNAL_VERSION,
+                kind: kind.to_string(),
+                params,
+                output: output.to_path_buf(),
+                units_done: 0,
+                bytes_written: 0,
+                sha256: String::new(),
+                updated_at: 0,
+            },
+            journal_path: journal_path(output),
+        };
+        writer.checkpoint(0)?;
+        Ok(writer)
+    }
+
+    /// Reopen the partial output at the journal's last checkpoint, after checking the
+    /// bytes on disk still hash to what was journaled
+    pub fn resume(journal: ExportJournal) -> Result<Self, String> {
+        let partial = partial_path(&journal.output);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&partial)
+            .map_err(|e| format!("Cannot open '{}': {}", partial.display(), e))?;
+        let len = file.metadata().map_err(|e| e.to_string())?.len();
+        if len < journal.bytes_written {
+            return Err(format!(
+                "Partial output is {} bytes, journal expects at least {}",
+                len, journal.bytes_written
+            ));
+        }
+
+        let mut hasher = Sha256::new();
+        let mut remaining = journal.bytes_written;
+        let mut buf = vec![0u8; 64 * 1024];
+        while remaining > 0 {
+            let want = remaining.min(buf.len() as u64) as usize;
+            file.read_exact(&mut buf[..want]).map_err(|e| e.to_string())?;
+            hasher.update(&buf[..want]);
+            remaining -= want as u64;
+        }
+        if format!("{:x}", hasher.clone().finalize()) != journal.sha256 {
+            return Err("Partial output does not match its journal".to_string());
+        }
+
+        // Anything after the checkpoint was written by the interrupted run
+        file.set_len(journal.bytes_written).map_err(|e| e.to_string())?;
+        file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+        Ok(CheckpointWriter {
+            out: BufWriter::new(file),
+            hasher,
+            bytes: journal.bytes_written,
+            journal_path: journal_path(&journal.output),
+            journal,
+        })
+    }
+
+    /// Resume from `journal` when given and valid, otherwise start over. A restart
+    /// keeps the journal's params so the output matches what was being written.
+    pub fn open(kind: &str, mut params: Value, output: &Path, journal: Option<ExportJournal>) -> Result<Self, String> {
+        if let Some(journal) = journal {
+            params = journal.params.clone();
+            match Self::resume(journal) {
+                Ok(writer) => {
+                    println!(
+                        "[Checkpoint] Resuming {} at unit {} ({} bytes)",
+                        output.display(),
+                        writer.units_done(),
+                        writer.bytes
+                    );
+                    return Ok(writer);
+                }
+                Err(e) => eprintln!("[Checkpoint] Restarting {}: {}", output.display(), e),
+            }
+        }
+        Self::create(kind, params, output)
+    }
+
+    pub fn params(&self) -> &Value {
+        &self.journal.params
+    }
+
+    /// Units completed before this run started, or at the last checkpoint
+    pub fn units_done(&self) -> u64 {
+        self.journal.units_done
+    }
+
+    pub fn journal_path(&self) -> &Path {
+        &self.journal_path
+    }
+
+    /// Make everything written so far durable and record it as `units_done` units
+    pub fn checkpoint(&mut self, units_done: u64) -> Result<(), String> {
+        self.out.flush().map_err(|e| e.to_string())?;
+        self.out.get_ref().sync_data().map_err(|e| e.to_string())?;
+        self.journal.units_done = units_done;
+        self.journal.bytes_written = self.bytes;
+        self.journal.sha256 = format!("{:x}", self.hasher.clone().finalize());
+        self.journal.updated_at = chrono::Utc::now().timestamp_millis();
+        self.journal.save(&self.journal_path).map_err(|e| e.to_string())
+    }
+
+    /// Move the output into place and drop the journal; returns the bytes written
+    pub fn finish(self) -> Result<u64, String> {
+        let file = self.out.into_inner().map_err(|e| e.to_string())?;
+        file.sync_all().map_err(|e| e.to_string())?;
+        drop(file);
+        std::fs::rename(partial_path(&self.journal.output), &self.journal.output).map_err(|e| e.to_string())?;
+        let _ = std::fs::remove_file(&self.journal_path);
+        Ok(self.bytes)
+    }
+}
+
+/// Journal path for a job id, or the argument itself when it isn't one
+fn resolve_journal(registry: &JobRegistry, job_id_or_journal_path: &str) -> Result<PathBuf, String> {
+    match job_id_or_journal_path.parse::<u64>() {
+        Ok(job_id) => registry
+            .journal(job_id)
+            .ok_or_else(|| format!("Job {} has no export journal", job_id)),
+        Err(_) => Ok(PathBuf::from(job_id_or_journal_path)),
+    }
+}
+
+/// Continue an interrupted export from its last checkpoint as a new job, given the
+/// id of the job that was interrupted or the path of its journal
+#[tauri::command]
+pub fn resume_job(
+    app: AppHandle,
+    registry: State<'_, JobRegistry>,
+    job_id_or_journal_path: String,
+) -> Result<u64, String> {
+    let path = resolve_journal(&registry, &job_id_or_journal_path)?;
+    let journal = ExportJournal::load(&path)?;
+    if let Some(job_id) = registry.running_with_journal(&path) {
+        return Err(format!("Job {} is still writing this export", job_id));
+    }
+    match journal.kind.as_str() {
+        crate::export::EXPORT_ALL_KIND => Ok(crate::export::spawn_export_all(&app, journal.output.clone(), Some(journal))),
+        other => Err(format!("No exporter can resume '{}' journals", other)),
+    }
+}
+
+/// Delete an interrupted export's partial output and journal
+#[tauri::command]
+pub fn discard_export_journal(registry: State<'_, JobRegistry>, job_id_or_journal_path: String) -> Result<(), String> {
+    let path = resolve_journal(&registry, &job_id_or_journal_path)?;
+    if let Some(job_id) = registry.running_with_journal(&path) {
+        return Err(format!("Job {} is still writing this export", job_id));
+    }
+    discard(&path)
+}
+
+/// Delete the journal at `path` and the partial output it describes. Either may
+/// already be gone.
+fn discard(path: &Path) -> Result<(), String> {
+    let journal = ExportJournal::load(path)?;
+    for file in [partial_path(&journal.output), path.to_path_buf()] {
+        match std::fs::remove_file(&file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Cannot delete '{}': {}", file.display(), e)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workany-checkpoint-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write two checkpointed units and some torn output after them
+    fn interrupted(output: &Path) -> ExportJournal {
+        let mut writer = CheckpointWriter::create("test", json!({ "n": 1 }), output).unwrap();
+        writer.write_all(b"first\n").unwrap();
+        writer.checkpoint(1).unwrap();
+        writer.write_all(b"second\n").unwrap();
+        writer.checkpoint(2).unwrap();
+        writer.write_all(b"torn").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        ExportJournal::load(&journal_path(output)).unwrap()
+    }
+
+    #[test]
+    fn a_journal_round_trips_and_resumes_at_its_checkpoint() {
+        let dir = temp_dir("resume");
+        let output = dir.join("out.jsonl");
+        let journal = interrupted(&output);
+        assert_eq!((journal.kind.as_str(), journal.units_done, journal.bytes_written), ("test", 2, 13));
+        assert_eq!(journal.params, json!({ "n": 1 }));
+        assert_eq!(journal.output, output);
+
+        let mut writer = CheckpointWriter::open("test", json!({}), &output, Some(journal)).unwrap();
+        assert_eq!(writer.units_done(), 2);
+        assert_eq!(writer.params(), &json!({ "n": 1 }));
+        writer.write_all(b"third\n").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "first\nsecond\nthird\n");
+        assert!(!journal_path(&output).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn export_all_killed_at_any_unit_resumes_to_identical_bytes() {
+        use crate::export::{write_export_all, EXPORT_ALL_KIND};
+        use rusqlite::params;
+
+        let conn = crate::migrations::test_db();
+        for t in 0..3 {
+            let task_id = format!("task-{}", t);
+            conn.execute(
+                "INSERT INTO tasks (id, prompt, status, created_at) VALUES (?1, ?2, 'completed', ?3)",
+                params![task_id, format!("Prompt {}", t), format!("2024-01-0{} 00:00:00", t + 1)],
+            )
+            .unwrap();
+            for m in 0..1000 {
+                conn.execute(
+                    "INSERT INTO messages (task_id, type, content, created_at) VALUES (?1, 'text', ?2, '2024-01-01 00:00:00')",
+                    params![task_id, format!("message {} of {}", m, task_id)],
+                )
+                .unwrap();
+            }
+        }
+        let params = json!({ "exported_at": "2024-02-01T00:00:00+00:00" });
+        let dir = temp_dir("export-all");
+
+        let full = dir.join("full.jsonl");
+        let mut writer = CheckpointWriter::create(EXPORT_ALL_KIND, params.clone(), &full).unwrap();
+        write_export_all(&conn, &mut writer, |_| false).unwrap();
+        writer.finish().unwrap();
+        let expected = std::fs::read(&full).unwrap();
+
+        // 3004 lines make three full units; stop after each of them in turn
+        for units in 1..=3 {
+            let output = dir.join(format!("killed-{}.jsonl", units));
+            let mut writer = CheckpointWriter::create(EXPORT_ALL_KIND, params.clone(), &output).unwrap();
+            let mut seen = 0;
+            let result = write_export_all(&conn, &mut writer, |_| {
+                seen += 1;
+                seen == units
+            });
+            assert_eq!(result, Err("Cancelled".to_string()));
+            drop(writer);
+
+            let journal = ExportJournal::load(&journal_path(&output)).unwrap();
+            assert_eq!(journal.units_done, units);
+            let mut writer = CheckpointWriter::open(EXPORT_ALL_KIND, json!({}), &output, Some(journal)).unwrap();
+            assert_eq!(writer.units_done(), units);
+            write_export_all(&conn, &mut writer, |_| false).unwrap();
+            writer.finish().unwrap();
+            assert_eq!(std::fs::read(&output).unwrap(), expected, "killed after {} units", units);
+            assert!(!journal_path(&output).exists());
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unreadable_or_foreign_journals_are_refused() {
+        let dir = temp_dir("invalid");
+        let output = dir.join("out.jsonl");
+        let path = journal_path(&output);
+        assert!(ExportJournal::load(&path).unwrap_err().contains("Cannot read journal"));
+
+        std::fs::write(&path, "{\"version\": 1, \"kind\": ").unwrap();
+        assert!(ExportJournal::load(&path).unwrap_err().contains("Invalid journal"));
+
+        let mut journal = interrupted(&output);
+        journal.version = JOURNAL_VERSION + 1;
+        journal.save(&path).unwrap();
+        assert!(ExportJournal::load(&path).unwrap_err().contains("Unsupported journal version"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stale_or_corrupt_partial_output_is_rejected_and_restarted() {
+        let dir = temp_dir("stale");
+        let output = dir.join("out.jsonl");
+
+        // Truncated below the last checkpoint, as if an older run's output was left
+        let journal = interrupted(&output);
+        std::fs::write(partial_path(&output), b"first\n").unwrap();
+        assert!(CheckpointWriter::resume(journal.clone()).unwrap_err().contains("journal expects"));
+
+        // Same length, different bytes
+        std::fs::write(partial_path(&output), b"first\nsecund\n").unwrap();
+        assert!(CheckpointWriter::resume(journal.clone()).unwrap_err().contains("does not match"));
+
+        // Partial output gone altogether
+        std::fs::remove_file(partial_path(&output)).unwrap();
+        assert!(CheckpointWriter::resume(journal.clone()).is_err());
+
+        // Opening with a bad journal starts over, keeping the journal's params
+        let writer = CheckpointWriter::open("test", json!({}), &output, Some(journal)).unwrap();
+        assert_eq!(writer.units_done(), 0);
+        assert_eq!(writer.params(), &json!({ "n": 1 }));
+        drop(writer);
+        assert_eq!(std::fs::read(partial_path(&output)).unwrap(), b"");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn discarding_removes_the_journal_and_partial_output() {
+        let dir = temp_dir("discard");
+        let output = dir.join("out.jsonl");
+        interrupted(&output);
+        discard(&journal_path(&output)).unwrap();
+        assert!(!journal_path(&output).exists());
+        assert!(!partial_path(&output).exists());
+        assert!(!output.exists());
+
+        // A partial output that is already gone doesn't stop the journal going
+        interrupted(&output);
+        std::fs::remove_file(partial_path(&output)).unwrap();
+        discard(&journal_path(&output)).unwrap();
+        assert!(!journal_path(&output).exists());
+
+        assert!(discard(&journal_path(&output)).is_err(), "no journal to discard");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
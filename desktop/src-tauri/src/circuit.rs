@@ -0,0 +1,394 @@
+// Per-endpoint circuit breakers for calls to the API sidecar.
+//
+// Each endpoint counts failures over a rolling window. Reaching the threshold opens
+// its circuit: calls fail fast with `CommandError::CircuitOpen` instead of piling more
+// retries onto a broken route. After `open_ms` one probe call is let through
+// (half-open); its outcome closes the circuit or opens it again. Endpoints that must
+// always be attempted, like task execution and cancellation, are exempt. Every state
+// change is sent as `circuit://state-changed`, and all breakers reset when the
+// sidecar restarts.
+
+use crate::error::CommandError;
+use crate::{events, settings};
+use rusqlite::Connection;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+const THRESHOLD_KEY: &str = "circuit_failure_threshold";
+const WINDOW_KEY: &str = "circuit_window_ms";
+const OPEN_KEY: &str = "circuit_open_ms";
+const EXEMPT_KEY: &str = "circuit_exempt_endpoints";
+const DEFAULT_THRESHOLD: u32 = 5;
+const DEFAULT_WINDOW_MS: u64 = 30_000;
+const DEFAULT_OPEN_MS: u64 = 15_000;
+const DEFAULT_EXEMPT: &[&str] = &["/agent/execute", "/agent/stop", "/agent/permission"];
+/// How long callers are told to wait while a half-open probe is in flight
+const PROBE_RETRY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakerConfig {
+    pub failure_threshold: u32,
+    pub window: Duration,
+    pub open_for: Duration,
+    pub exempt: Vec<String>,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            failure_threshold: DEFAULT_THRESHOLD,
+            window: Duration::from_millis(DEFAULT_WINDOW_MS),
+            open_for: Duration::from_millis(DEFAULT_OPEN_MS),
+            exempt: DEFAULT_EXEMPT.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+}
+
+impl BreakerConfig {
+    pub fn load(conn: &Connection) -> Result<Self, String> {
+        Ok(BreakerConfig {
+            failure_threshold: settings::get_or(conn, THRESHOLD_KEY, DEFAULT_THRESHOLD)?.max(1),
+            window: Duration::from_millis(settings::get_or(conn, WINDOW_KEY, DEFAULT_WINDOW_MS)?),
+            open_for: Duration::from_millis(settings::get_or(conn, OPEN_KEY, DEFAULT_OPEN_MS)?),
+            exempt: settings::get_or(conn, EXEMPT_KEY, BreakerConfig::default().exempt)?,
+        })
+    }
+
+    fn is_exempt(&self, endpoint: &str) -> bool {
+        self.exempt.iter().any(|e| e == endpoint)
+    }
+}
+
+/// Breaker for one endpoint. Time is passed in so the state machine can be tested
+/// with scripted sequences.
+#[derive(Debug)]
+struct Breaker {
+    state: CircuitState,
+    failures: VecDeque<Instant>,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+    last_error: Option<String>,
+}
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Breaker {
+            state: CircuitState::Closed,
+            failures: VecDeque::new(),
+            opened_at: None,
+            probe_in_flight: false,
+            last_error: None,
+        }
+    }
+}
+
+impl Breaker {
+    fn open(&mut self, now: Instant) {
+        self.state = CircuitState::Open;
+        self.opened_at = Some(now);
+        self.probe_in_flight = false;
+        self.failures.clear();
+    }
+
+    fn prune(&mut self, now: Instant, window: Duration) {
+        while self.failures.front().is_some_and(|at| now.duration_since(*at) >= window) {
+            self.failures.pop_front();
+        }
+    }
+
+    /// Whether a call may go out now; `Err` carries how long until it might
+    fn admit(&mut self, now: Instant, config: &BreakerConfig) -> Result<(), Duration> {
+        match self.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open => {
+                let elapsed = self.opened_at.map(|at| now.duration_since(at)).unwrap_or_default();
+                if elapsed >= config.open_for {
+                    self.state = CircuitState::HalfOpen;
+                    self.probe_in_flight = true;
+                    Ok(())
+                } else {
+                    Err(config.open_for - elapsed)
+                }
+            }
+            CircuitState::HalfOpen if self.probe_in_flight => Err(PROBE_RETRY),
+            CircuitState::HalfOpen => {
+                self.probe_in_flight = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn record(&mut self, now: Instant, config: &BreakerConfig, outcome: Result<(), &str>) {
+        match outcome {
+            Ok(()) => {
+                if self.state == CircuitState::HalfOpen {
+                    self.state = CircuitState::Closed;
+                    self.probe_in_flight = false;
+                    self.opened_at = None;
+                    self.failures.clear();
+                }
+            }
+            Err(error) => {
+                self.last_error = Some(error.to_string());
+                match self.state {
+                    CircuitState::HalfOpen => self.open(now),
+                    CircuitState::Closed => {
+                        self.prune(now, config.window);
+                        self.failures.push_back(now);
+                        if self.failures.len() as u32 >= config.failure_threshold {
+                            self.open(now);
+                        }
+                    }
+                    // A call admitted before the circuit opened; it's already open
+                    CircuitState::Open => {}
+                }
+            }
+        }
+    }
+
+    fn retry_after(&self, now: Instant, config: &BreakerConfig) -> Option<Duration> {
+        match self.state {
+            CircuitState::Closed => None,
+            CircuitState::HalfOpen => Some(PROBE_RETRY),
+            CircuitState::Open => {
+                let elapsed = self.opened_at.map(|at| now.duration_since(at)).unwrap_or_default();
+                Some(config.open_for.saturating_sub(elapsed))
+            }
+        }
+    }
+}
+
+/// How a guarded call failed
+pub enum CallFailure {
+    /// The endpoint is unhealthy (transport error, 5xx); counts against the circuit
+    Endpoint(String),
+    /// The request itself was rejected (4xx, bad input); doesn't count
+    Caller(String),
+}
+
+impl CallFailure {
+    pub(crate) fn into_error(self) -> CommandError {
+        match self {
+            CallFailure::Endpoint(message) | CallFailure::Caller(message) => CommandError::from(message),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CircuitBreakers(Mutex<HashMap<String, Breaker>>);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitStatus {
+    pub endpoint: String,
+    pub state: CircuitState,
+    pub recent_failures: u32,
+    pub retry_after_ms: Option<u64>,
+    pub last_error: Option<String>,
+    pub exempt: bool,
+}
+
+fn emit_change(app: &AppHandle, endpoint: &str, from: CircuitState, to: CircuitState, error: Option<String>) {
+    if from == to {
+        return;
+    }
+    eprintln!("[Circuit] {}: {:?} -> {:?}", endpoint, from, to);
+    events::emit_typed(
+        app,
+        events::CircuitStateChanged {
+            endpoint: endpoint.to_string(),
+            from,
+            to,
+            last_error: error,
+        },
+    );
+}
+
+/// Run `call` behind the endpoint's breaker
+pub fn guard<T>(
+    app: &AppHandle,
+    config: &BreakerConfig,
+    endpoint: &str,
+    call: impl FnOnce() -> Result<T, CallFailure>,
+) -> Result<T, CommandError> {
+    if config.is_exempt(endpoint) {
+        return call().map_err(CallFailure::into_error);
+    }
+    let breakers = app.state::<CircuitBreakers>();
+
+    {
+        let mut map = breakers.0.lock().map_err(|e| e.to_string())?;
+        let breaker = map.entry(endpoint.to_string()).or_default();
+        let before = breaker.state;
+        let admitted = breaker.admit(Instant::now(), config);
+        emit_change(app, endpoint, before, breaker.state, None);
+        if let Err(retry_after) = admitted {
+            return Err(CommandError::CircuitOpen {
+                message: format!("{} is failing; not calling it for now", endpoint),
+                endpoint: endpoint.to_string(),
+                retry_after_ms: retry_after.as_millis() as u64,
+            });
+        }
+    }
+
+    let result = call();
+
+    let mut map = breakers.0.lock().map_err(|e| e.to_string())?;
+    let breaker = map.entry(endpoint.to_string()).or_default();
+    let before = breaker.state;
+    match &result {
+        Ok(_) | Err(CallFailure::Caller(_)) => breaker.record(Instant::now(), config, Ok(())),
+        Err(CallFailure::Endpoint(error)) => breaker.record(Instant::now(), config, Err(error.as_str())),
+    }
+    emit_change(app, endpoint, before, breaker.state, breaker.last_error.clone());
+    result.map_err(CallFailure::into_error)
+}
+
+/// Close every circuit, e.g. after the sidecar restarted
+pub fn reset_all(app: &AppHandle) {
+    let Some(breakers) = app.try_state::<CircuitBreakers>() else {
+        return;
+    };
+    let Ok(mut map) = breakers.0.lock() else {
+        return;
+    };
+    for (endpoint, breaker) in map.drain() {
+        emit_change(app, &endpoint, breaker.state, CircuitState::Closed, None);
+    }
+}
+
+#[tauri::command]
+pub async fn get_circuit_status(
+    app: AppHandle,
+    breakers: State<'_, CircuitBreakers>,
+) -> Result<Vec<CircuitStatus>, String> {
+    let config = crate::db::with_conn(&app, |conn| BreakerConfig::load(conn)).await?;
+    let now = Instant::now();
+    let mut map = breakers.0.lock().map_err(|e| e.to_string())?;
+    let mut status: Vec<CircuitStatus> = map
+        .iter_mut()
+        .map(|(endpoint, breaker)| {
+            breaker.prune(now, config.window);
+            CircuitStatus {
+                endpoint: endpoint.clone(),
+                state: breaker.state,
+                recent_failures: breaker.failures.len() as u32,
+                retry_after_ms: breaker.retry_after(now, &config).map(|d| d.as_millis() as u64),
+                last_error: breaker.last_error.clone(),
+                exempt: config.is_exempt(endpoint),
+            }
+        })
+        .collect();
+    status.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+    Ok(status)
+}
+
+#[tauri::command]
+pub fn reset_circuits(app: AppHandle) {
+    reset_all(&app);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> BreakerConfig {
+        BreakerConfig {
+            failure_threshold: 3,
+            window: Duration::from_secs(10),
+            open_for: Duration::from_secs(5),
+            exempt: Vec::new(),
+        }
+    }
+
+    /// Apply a script of calls, one per second: `true` succeeds, `false` fails.
+    /// Returns the state after each step, with `None` where the call was rejected.
+    fn run(breaker: &mut Breaker, start: Instant, script: &[(u64, bool)]) -> Vec<Option<CircuitState>> {
+        let config = config();
+        script
+            .iter()
+            .map(|&(at, ok)| {
+                let now = start + Duration::from_secs(at);
+                breaker.admit(now, &config).ok()?;
+                breaker.record(now, &config, if ok { Ok(()) } else { Err("boom") });
+                Some(breaker.state)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn opens_after_threshold_within_window() {
+        let mut breaker = Breaker::default();
+        let states = run(&mut breaker, Instant::now(), &[(0, false), (1, false), (2, true), (3, false), (4, true)]);
+        use CircuitState::*;
+        assert_eq!(states, vec![Some(Closed), Some(Closed), Some(Closed), Some(Open), None]);
+    }
+
+    #[test]
+    fn failures_outside_window_do_not_count() {
+        let mut breaker = Breaker::default();
+        let states = run(&mut breaker, Instant::now(), &[(0, false), (1, false), (11, false), (12, true)]);
+        assert!(states.iter().all(|s| *s == Some(CircuitState::Closed)));
+    }
+
+    #[test]
+    fn half_open_probe_closes_on_success() {
+        let mut breaker = Breaker::default();
+        let states = run(
+            &mut breaker,
+            Instant::now(),
+            &[(0, false), (0, false), (0, false), (4, true), (5, true), (6, true)],
+        );
+        use CircuitState::*;
+        assert_eq!(states, vec![Some(Closed), Some(Closed), Some(Open), None, Some(Closed), Some(Closed)]);
+    }
+
+    #[test]
+    fn half_open_probe_reopens_on_failure() {
+        let mut breaker = Breaker::default();
+        let states = run(
+            &mut breaker,
+            Instant::now(),
+            &[(0, false), (0, false), (0, false), (5, false), (9, true), (10, true)],
+        );
+        use CircuitState::*;
+        assert_eq!(states, vec![Some(Closed), Some(Closed), Some(Open), Some(Open), None, Some(Closed)]);
+    }
+
+    #[test]
+    fn only_one_probe_at_a_time() {
+        let config = config();
+        let start = Instant::now();
+        let mut breaker = Breaker::default();
+        for _ in 0..3 {
+            breaker.admit(start, &config).unwrap();
+            breaker.record(start, &config, Err("boom"));
+        }
+        let later = start + Duration::from_secs(6);
+        assert!(breaker.admit(later, &config).is_ok());
+        assert_eq!(breaker.state, CircuitState::HalfOpen);
+        assert_eq!(breaker.admit(later, &config), Err(PROBE_RETRY));
+    }
+
+    #[test]
+    fn rejection_reports_remaining_open_time() {
+        let config = config();
+        let start = Instant::now();
+        let mut breaker = Breaker::default();
+        for _ in 0..3 {
+            breaker.record(start, &config, Err("boom"));
+        }
+        assert_eq!(breaker.admit(start + Duration::from_secs(2), &config), Err(Duration::from_secs(3)));
+    }
+}
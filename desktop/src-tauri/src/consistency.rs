@@ -0,0 +1,59 @@
+// Repair tools for databases created before foreign keys were enforced.
+//
+// Rows in `messages` and `files` can point at tasks that no longer exist. These
+// commands list such orphans and delete them in a single transaction.
+
+use crate::{db, integrity};
+use rusqlite::Connection;
+
+pub(crate) const ORPHANED_MESSAGES: &str =
+    "SELECT m.id FROM messages m LEFT JOIN tasks t ON t.id = m.task_id WHERE t.id IS NULL ORDER BY m.id";
+pub(crate) const ORPHANED_FILES: &str =
+    "SELECT f.id FROM files f LEFT JOIN tasks t ON t.id = f.task_id WHERE t.id IS NULL ORDER BY f.id";
+
+fn orphan_ids(conn: &Connection, sql: &str) -> Result<Vec<i64>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn delete_orphans(conn: &mut Connection, table: &str) -> Result<u32, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let removed = tx
+        .execute(
+            &format!(
+                "DELETE FROM {table} WHERE NOT EXISTS (SELECT 1 FROM tasks t WHERE t.id = {table}.task_id)"
+            ),
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+    integrity::record_deletion(&tx, table, "orphan_cleanup", removed as u32)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(removed as u32)
+}
+
+/// Message ids whose task row no longer exists
+#[tauri::command]
+pub async fn find_orphaned_messages(app: tauri::AppHandle) -> Result<Vec<i64>, String> {
+    db::with_conn(&app, |conn| orphan_ids(conn, ORPHANED_MESSAGES)).await
+}
+
+/// Delete orphaned messages, returning how many were removed
+#[tauri::command]
+pub async fn cleanup_orphaned_messages(app: tauri::AppHandle) -> Result<u32, String> {
+    db::with_conn(&app, |conn| delete_orphans(conn, "messages")).await
+}
+
+/// File ids whose task row no longer exists
+#[tauri::command]
+pub async fn find_orphaned_files(app: tauri::AppHandle) -> Result<Vec<i64>, String> {
+    db::with_conn(&app, |conn| orphan_ids(conn, ORPHANED_FILES)).await
+}
+
+/// Delete orphaned file rows, returning how many were removed
+#[tauri::command]
+pub async fn cleanup_orphaned_files(app: tauri::AppHandle) -> Result<u32, String> {
+    db::with_conn(&app, |conn| delete_orphans(conn, "files")).await
+}
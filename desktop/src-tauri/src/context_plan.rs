@@ -0,0 +1,712 @@
+// Context assembly: everything we add to a task's context besides the prompt
+// itself, sized against the model's context window.
+//
+// The contributions are the working-set files linked to the task, the workspace
+// analysis, a retry context, and messages from earlier tasks in the session marked
+// `include_in_context`. Each gets a token estimate (text through
+// `ContextTokenCache`, keyed by content hash, so replanning on every keystroke only
+// counts what changed), and the plan keeps `RESPONSE_RESERVE_TOKENS` free for the
+// answer.
+//
+// While the total is over budget, `TRIM_RULES` are applied in order, each cutting
+// only as much as needed; ties are broken by position so the same inputs always
+// give the same plan. Users can force an item in (never trimmed) or drop it
+// (before any rule runs) with `set_context_overrides`. Task creation applies the
+// plan and stores it as `tasks.sent_context`, which `get_sent_context` returns.
+
+use crate::tokens::{self, TokenizerKind};
+use crate::{db, workspace_analysis};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+/// Used when the model is unknown
+const DEFAULT_CONTEXT_WINDOW: u64 = 200_000;
+/// Kept free for the model's answer
+const RESPONSE_RESERVE_TOKENS: u64 = 16_000;
+/// A retry context is never cut below this
+const RETRY_CONTEXT_MIN_TOKENS: u64 = 2_000;
+/// Cached token counts; the cache starts over when full
+const TOKEN_CACHE_ENTRIES: usize = 4_096;
+
+/// Applied in this order while the plan is over budget
+const TRIM_RULES: [TrimRule; 4] = [
+    TrimRule::TruncateRetryContext,
+    TrimRule::DropWorkspaceAnalysis,
+    TrimRule::DropPinnedMessages,
+    TrimRule::DropSessionFiles,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextKind {
+    Prompt,
+    SessionFile,
+    WorkspaceAnalysis,
+    RetryContext,
+    PinnedMessage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrimRule {
+    /// Listed in the overrides' `drop`; applied even when within budget
+    UserDrop,
+    /// Cut the retry context from the end, down to `RETRY_CONTEXT_MIN_TOKENS`
+    TruncateRetryContext,
+    DropWorkspaceAnalysis,
+    /// Oldest first
+    DropPinnedMessages,
+    /// Largest first
+    DropSessionFiles,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ContextAction {
+    Include,
+    Truncate { to_tokens: u64 },
+    Drop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextItem {
+    /// `prompt`, `session_file:<attachment id>`, `workspace_analysis`,
+    /// `retry_context` or `message:<message id>`; what overrides refer to
+    pub key: String,
+    pub kind: ContextKind,
+    pub label: String,
+    pub tokens: u64,
+    /// No estimate is possible (binary file), so it counts as 0
+    pub uncounted: bool,
+    /// Force-included by an override; no rule touches it
+    pub forced: bool,
+    pub action: ContextAction,
+    /// Tokens left after `action`
+    pub planned_tokens: u64,
+    /// Position within its kind, for deterministic ordering
+    #[serde(skip)]
+    rank: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrimDecision {
+    pub rule: TrimRule,
+    pub key: String,
+    pub saved_tokens: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContextOverrides {
+    /// Item keys kept whole whatever the budget
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Item keys left out; `include` wins when a key is in both
+    #[serde(default)]
+    pub drop: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextPlan {
+    pub task_id: Option<String>,
+    pub model: Option<String>,
+    pub tokenizer: TokenizerKind,
+    pub context_window: u64,
+    pub reserve_tokens: u64,
+    pub budget_tokens: u64,
+    /// Everything before trimming
+    pub requested_tokens: u64,
+    pub planned_tokens: u64,
+    /// Still over after every rule, e.g. a prompt or forced items alone exceed it
+    pub over_budget: bool,
+    pub items: Vec<ContextItem>,
+    /// In the order they were applied
+    pub decisions: Vec<TrimDecision>,
+    pub overrides: ContextOverrides,
+}
+
+/// Unsaved task being composed in a session
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContextDraft {
+    pub session_id: String,
+    pub prompt: String,
+    pub working_dir: Option<String>,
+    #[serde(default)]
+    pub overrides: ContextOverrides,
+}
+
+/// Token counts of context text, keyed by tokenizer and content hash
+#[derive(Default)]
+pub struct ContextTokenCache(Mutex<HashMap<(TokenizerKind, String), u64>>);
+
+impl ContextTokenCache {
+    fn count(&self, kind: TokenizerKind, text: &str) -> u64 {
+        let key = (kind, format!("{:x}", Sha256::digest(text.as_bytes())));
+        if let Some(tokens) = self.0.lock().ok().and_then(|cache| cache.get(&key).copied()) {
+            return tokens;
+        }
+        let tokens = tokens::count_text(kind, text);
+        if let Ok(mut cache) = self.0.lock() {
+            if cache.len() >= TOKEN_CACHE_ENTRIES {
+                cache.clear();
+            }
+            cache.insert(key, tokens);
+        }
+        tokens
+    }
+}
+
+/// What a task's context is assembled from
+#[derive(Debug, Default)]
+struct Sources {
+    prompt: String,
+    /// Attachment id, name and stored token estimate, in position order
+    files: Vec<(i64, Option<String>, Option<u64>)>,
+    workspace_analysis: Option<String>,
+    retry_context: Option<String>,
+    /// Message id, task index and content, oldest first
+    pinned: Vec<(i64, i64, String)>,
+}
+
+fn item(key: String, kind: ContextKind, label: String, tokens: Option<u64>, rank: i64) -> ContextItem {
+    ContextItem {
+        key,
+        kind,
+        label,
+        tokens: tokens.unwrap_or(0),
+        uncounted: tokens.is_none(),
+        forced: false,
+        action: ContextAction::Include,
+        planned_tokens: tokens.unwrap_or(0),
+        rank,
+    }
+}
+
+fn items(sources: &Sources, kind: TokenizerKind, cache: &ContextTokenCache) -> Vec<ContextItem> {
+    let mut items = vec![item(
+        "prompt".to_string(),
+        ContextKind::Prompt,
+        "Prompt".to_string(),
+        Some(cache.count(kind, &sources.prompt)),
+        0,
+    )];
+    for (position, (id, name, tokens)) in sources.files.iter().enumerate() {
+        items.push(item(
+            format!("session_file:{}", id),
+            ContextKind::SessionFile,
+            name.clone().unwrap_or_else(|| format!("Attachment {}", id)),
+            *tokens,
+            position as i64,
+        ));
+    }
+    if let Some(analysis) = &sources.workspace_analysis {
+        items.push(item(
+            "workspace_analysis".to_string(),
+            ContextKind::WorkspaceAnalysis,
+            "Workspace analysis".to_string(),
+            Some(cache.count(kind, analysis)),
+            0,
+        ));
+    }
+    if let Some(retry) = &sources.retry_context {
+        items.push(item(
+            "retry_context".to_string(),
+            ContextKind::RetryContext,
+            "Retry context".to_string(),
+            Some(cache.count(kind, retry)),
+            0,
+        ));
+    }
+    for (position, (id, task_index, content)) in sources.pinned.iter().enumerate() {
+        items.push(item(
+            format!("message:{}", id),
+            ContextKind::PinnedMessage,
+            format!("Message from task {}", task_index),
+            Some(cache.count(kind, content)),
+            position as i64,
+        ));
+    }
+    items
+}
+
+fn total(items: &[ContextItem]) -> u64 {
+    items.iter().map(|i| i.planned_tokens).sum()
+}
+
+fn drop_item(item: &mut ContextItem, rule: TrimRule, decisions: &mut Vec<TrimDecision>) {
+    decisions.push(TrimDecision {
+        rule,
+        key: item.key.clone(),
+        saved_tokens: item.planned_tokens,
+    });
+    item.action = ContextAction::Drop;
+    item.planned_tokens = 0;
+}
+
+/// Drop the trimmable items of `kind` in `order` until the plan fits
+fn drop_until_fits(
+    items: &mut [ContextItem],
+    kind: ContextKind,
+    rule: TrimRule,
+    budget: u64,
+    order: impl Fn(&ContextItem, &ContextItem) -> std::cmp::Ordering,
+    decisions: &mut Vec<TrimDecision>,
+) {
+    let mut candidates: Vec<usize> = (0..items.len())
+        .filter(|&i| {
+            let item = &items[i];
+            item.kind == kind && !item.forced && item.action != ContextAction::Drop && item.planned_tokens > 0
+        })
+        .collect();
+    candidates.sort_by(|&a, &b| order(&items[a], &items[b]));
+    for index in candidates {
+        if total(items) <= budget {
+            return;
+        }
+        drop_item(&mut items[index], rule, decisions);
+    }
+}
+
+/// Apply the overrides, then `TRIM_RULES` while over `budget`. The prompt is never
+/// trimmed.
+fn apply_rules(items: &mut [ContextItem], overrides: &ContextOverrides, budget: u64) -> Vec<TrimDecision> {
+    let mut decisions = Vec::new();
+    for item in items.iter_mut() {
+        item.forced = item.kind == ContextKind::Prompt || overrides.include.contains(&item.key);
+        item.action = ContextAction::Include;
+        item.planned_tokens = item.tokens;
+    }
+    for item in items.iter_mut() {
+        if !item.forced && overrides.drop.contains(&item.key) {
+            drop_item(item, TrimRule::UserDrop, &mut decisions);
+        }
+    }
+
+    for rule in TRIM_RULES {
+        let over = total(items).saturating_sub(budget);
+        if over == 0 {
+            break;
+        }
+        match rule {
+            TrimRule::TruncateRetryContext => {
+                for item in items.iter_mut() {
+                    if item.kind != ContextKind::RetryContext || item.forced || item.action == ContextAction::Drop {
+                        continue;
+                    }
+                    let to_tokens = item.tokens.saturating_sub(over).max(RETRY_CONTEXT_MIN_TOKENS);
+                    if to_tokens < item.tokens {
+                        decisions.push(TrimDecision {
+                            rule,
+                            key: item.key.clone(),
+                            saved_tokens: item.tokens - to_tokens,
+                        });
+                        item.action = ContextAction::Truncate { to_tokens };
+                        item.planned_tokens = to_tokens;
+                    }
+                }
+            }
+            TrimRule::DropWorkspaceAnalysis => {
+                drop_until_fits(items, ContextKind::WorkspaceAnalysis, rule, budget, |a, b| a.rank.cmp(&b.rank), &mut decisions)
+            }
+            TrimRule::DropPinnedMessages => {
+                drop_until_fits(items, ContextKind::PinnedMessage, rule, budget, |a, b| a.rank.cmp(&b.rank), &mut decisions)
+            }
+            TrimRule::DropSessionFiles => drop_until_fits(
+                items,
+                ContextKind::SessionFile,
+                rule,
+                budget,
+                |a, b| b.tokens.cmp(&a.tokens).then(b.rank.cmp(&a.rank)),
+                &mut decisions,
+            ),
+            TrimRule::UserDrop => {}
+        }
+    }
+    decisions
+}
+
+fn build_plan(
+    conn: &Connection,
+    cache: &ContextTokenCache,
+    task_id: Option<String>,
+    sources: &Sources,
+    overrides: ContextOverrides,
+    model: Option<String>,
+) -> ContextPlan {
+    let table = tokens::pricing_table(conn);
+    let pricing = model.as_deref().and_then(|m| tokens::lookup_model(&table, m)).map(|(_, p)| p);
+    let tokenizer = pricing.map_or(TokenizerKind::Claude, |p| p.tokenizer);
+    let context_window = pricing.map_or(DEFAULT_CONTEXT_WINDOW, |p| p.context_window);
+    let budget_tokens = context_window.saturating_sub(RESPONSE_RESERVE_TOKENS);
+
+    let mut items = items(sources, tokenizer, cache);
+    let requested_tokens = items.iter().map(|i| i.tokens).sum();
+    let decisions = apply_rules(&mut items, &overrides, budget_tokens);
+    let planned_tokens = total(&items);
+    ContextPlan {
+        task_id,
+        model,
+        tokenizer,
+        context_window,
+        reserve_tokens: RESPONSE_RESERVE_TOKENS,
+        budget_tokens,
+        requested_tokens,
+        planned_tokens,
+        over_budget: planned_tokens > budget_tokens,
+        items,
+        decisions,
+        overrides,
+    }
+}
+
+/// Messages marked for context in the session's tasks before `before_index`
+fn pinned_messages(conn: &Connection, session_id: &str, before_index: Option<i64>) -> Result<Vec<(i64, i64, String)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT m.id, COALESCE(t.task_index, 1), m.content FROM messages m
+             JOIN tasks t ON t.id = m.task_id
+             WHERE t.session_id = ?1 AND m.include_in_context = 1 AND m.content IS NOT NULL
+               AND t.trashed_at IS NULL AND (?2 IS NULL OR t.task_index < ?2)
+             ORDER BY t.task_index, m.id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![session_id, before_index], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn task_files(conn: &Connection, task_id: &str) -> Result<Vec<(i64, Option<String>, Option<u64>)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT a.id, a.name, a.tokens FROM task_attachments ta JOIN attachments a ON a.id = ta.attachment_id
+             WHERE ta.task_id = ?1 AND a.kind = 'file' ORDER BY ta.position",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([task_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get::<_, Option<i64>>(2)?.map(|t| t as u64)))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn task_sources(conn: &Connection, task_id: &str) -> Result<(Sources, ContextOverrides), String> {
+    let (session_id, task_index, workspace_analysis, retry_context, overrides): (
+        Option<String>,
+        i64,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+    ) = conn
+        .query_row(
+            "SELECT session_id, COALESCE(task_index, 1), workspace_analysis, retry_context, context_overrides
+             FROM tasks WHERE id = ?1",
+            [task_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Task '{}' not found", task_id))?;
+    let pinned = match &session_id {
+        Some(session_id) => pinned_messages(conn, session_id, Some(task_index))?,
+        None => Vec::new(),
+    };
+    let sources = Sources {
+        prompt: crate::prompts::full_prompt(conn, task_id)?,
+        files: task_files(conn, task_id)?,
+        workspace_analysis,
+        retry_context,
+        pinned,
+    };
+    let overrides = overrides.and_then(|o| serde_json::from_str(&o).ok()).unwrap_or_default();
+    Ok((sources, overrides))
+}
+
+fn draft_sources(conn: &Connection, draft: &ContextDraft, workspace_analysis: Option<String>) -> Result<Sources, String> {
+    let working_set = crate::session_files::load_working_set(conn, &draft.session_id)?;
+    Ok(Sources {
+        prompt: draft.prompt.clone(),
+        files: working_set
+            .files
+            .into_iter()
+            .filter(|f| f.always_include)
+            .map(|f| (f.attachment.id, f.attachment.name, f.attachment.tokens))
+            .collect(),
+        workspace_analysis,
+        retry_context: None,
+        pinned: pinned_messages(conn, &draft.session_id, None)?,
+    })
+}
+
+/// Plan the context of a task that was just inserted with its working-set files
+/// linked, apply it, and record it as the task's `sent_context`. Dropped files are
+/// unlinked from the task.
+pub(crate) fn apply_to_new_task(
+    conn: &Connection,
+    cache: &ContextTokenCache,
+    task_id: &str,
+    overrides: ContextOverrides,
+    model: Option<String>,
+) -> Result<ContextPlan, String> {
+    let (sources, _) = task_sources(conn, task_id)?;
+    let plan = build_plan(conn, cache, Some(task_id.to_string()), &sources, overrides, model);
+    for item in &plan.items {
+        if let (ContextKind::SessionFile, ContextAction::Drop) = (item.kind, &item.action) {
+            if let Some(id) = item.key.strip_prefix("session_file:").and_then(|id| id.parse::<i64>().ok()) {
+                conn.execute(
+                    "DELETE FROM task_attachments WHERE task_id = ?1 AND attachment_id = ?2",
+                    params![task_id, id],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    let overrides = serde_json::to_string(&plan.overrides).map_err(|e| e.to_string())?;
+    let sent = serde_json::to_string(&plan).map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE tasks SET context_overrides = ?1, sent_context = ?2 WHERE id = ?3",
+        params![overrides, sent, task_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(plan)
+}
+
+/// Plan the context of a saved task (`task_id`) or of a task being composed
+/// (`draft`): every contribution with its token estimate and what trimming would do
+#[tauri::command]
+pub async fn get_context_plan(
+    app: AppHandle,
+    task_id: Option<String>,
+    draft: Option<ContextDraft>,
+    model: Option<String>,
+) -> Result<ContextPlan, String> {
+    match (task_id, draft) {
+        (Some(task_id), None) => {
+            let handle = app.clone();
+            db::with_conn(&app, move |conn| {
+                let (sources, overrides) = task_sources(conn, &task_id)?;
+                let cache = handle.state::<ContextTokenCache>();
+                Ok(build_plan(conn, &cache, Some(task_id), &sources, overrides, model))
+            })
+            .await
+        }
+        (None, Some(draft)) => {
+            // A folder that can't be analyzed just contributes nothing
+            let analysis = match draft.working_dir.as_deref() {
+                Some(dir) => workspace_analysis::analyze_dir(&app, PathBuf::from(dir))
+                    .await
+                    .ok()
+                    .and_then(|analysis| serde_json::to_string(&analysis).ok()),
+                None => None,
+            };
+            let handle = app.clone();
+            db::with_conn(&app, move |conn| {
+                let sources = draft_sources(conn, &draft, analysis)?;
+                let cache = handle.state::<ContextTokenCache>();
+                Ok(build_plan(conn, &cache, None, &sources, draft.overrides, model))
+            })
+            .await
+        }
+        _ => Err("Pass either task_id or draft".to_string()),
+    }
+}
+
+/// Save which items of a task's context are forced in or dropped, returning the
+/// plan with them applied
+#[tauri::command]
+pub async fn set_context_overrides(
+    app: AppHandle,
+    task_id: String,
+    overrides: ContextOverrides,
+    model: Option<String>,
+) -> Result<ContextPlan, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let encoded = serde_json::to_string(&overrides).map_err(|e| e.to_string())?;
+        let updated = conn
+            .execute("UPDATE tasks SET context_overrides = ?1 WHERE id = ?2", params![encoded, task_id])
+            .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            return Err(format!("Task '{}' not found", task_id));
+        }
+        let (sources, overrides) = task_sources(conn, &task_id)?;
+        let cache = handle.state::<ContextTokenCache>();
+        Ok(build_plan(conn, &cache, Some(task_id), &sources, overrides, model))
+    })
+    .await
+}
+
+/// The plan applied when the task was created; `None` for tasks created before
+/// context plans were recorded
+#[tauri::command]
+pub async fn get_sent_context(app: AppHandle, task_id: String) -> Result<Option<ContextPlan>, String> {
+    db::with_conn(&app, move |conn| {
+        let sent: Option<Option<String>> = conn
+            .query_row("SELECT sent_context FROM tasks WHERE id = ?1", [&task_id], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let sent = sent.ok_or_else(|| format!("Task '{}' not found", task_id))?;
+        sent.map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .transpose()
+    })
+    .await
+}
+
+/// Mark a message to be carried into the context of later tasks in its session
+#[tauri::command]
+pub async fn set_message_include_in_context(app: AppHandle, message_id: i64, include: bool) -> Result<(), String> {
+    db::with_conn(&app, move |conn| {
+        let updated = conn
+            .execute(
+                "UPDATE messages SET include_in_context = ?1 WHERE id = ?2",
+                params![include, message_id],
+            )
+            .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            return Err(format!("Message {} not found", message_id));
+        }
+        Ok(())
+    })
+    .await?;
+    db::emit_change(&app, "messages", "update", vec![message_id.to_string()]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(kind: ContextKind, key: &str, tokens: u64, rank: i64) -> ContextItem {
+        item(key.to_string(), kind, key.to_string(), Some(tokens), rank)
+    }
+
+    fn sample() -> Vec<ContextItem> {
+        vec![
+            fixture(ContextKind::Prompt, "prompt", 1_000, 0),
+            fixture(ContextKind::SessionFile, "session_file:1", 30_000, 0),
+            fixture(ContextKind::SessionFile, "session_file:2", 50_000, 1),
+            fixture(ContextKind::SessionFile, "session_file:3", 30_000, 2),
+            fixture(ContextKind::WorkspaceAnalysis, "workspace_analysis", 500, 0),
+            fixture(ContextKind::RetryContext, "retry_context", 20_000, 0),
+            fixture(ContextKind::PinnedMessage, "message:10", 4_000, 0),
+            fixture(ContextKind::PinnedMessage, "message:11", 4_000, 1),
+        ]
+    }
+
+    fn actions(items: &[ContextItem]) -> Vec<(&str, ContextAction)> {
+        items.iter().map(|i| (i.key.as_str(), i.action.clone())).collect()
+    }
+
+    #[test]
+    fn within_budget_nothing_is_trimmed() {
+        let mut items = sample();
+        let decisions = apply_rules(&mut items, &ContextOverrides::default(), 200_000);
+        assert!(decisions.is_empty());
+        assert_eq!(total(&items), 139_500);
+    }
+
+    #[test]
+    fn rules_apply_in_order_and_stop_once_it_fits() {
+        let mut items = sample();
+        // 139_500 requested: the retry context goes down to its floor (-18_000),
+        // then the analysis, both pinned messages and the largest file go
+        let decisions = apply_rules(&mut items, &ContextOverrides::default(), 70_000);
+        let rules: Vec<(TrimRule, &str)> = decisions.iter().map(|d| (d.rule, d.key.as_str())).collect();
+        assert_eq!(
+            rules,
+            vec![
+                (TrimRule::TruncateRetryContext, "retry_context"),
+                (TrimRule::DropWorkspaceAnalysis, "workspace_analysis"),
+                (TrimRule::DropPinnedMessages, "message:10"),
+                (TrimRule::DropPinnedMessages, "message:11"),
+                (TrimRule::DropSessionFiles, "session_file:2"),
+            ]
+        );
+        assert_eq!(items[5].action, ContextAction::Truncate { to_tokens: RETRY_CONTEXT_MIN_TOKENS });
+        assert_eq!(total(&items), 63_000);
+        // Same input, same plan
+        let mut again = sample();
+        apply_rules(&mut again, &ContextOverrides::default(), 70_000);
+        assert_eq!(actions(&again), actions(&items));
+    }
+
+    #[test]
+    fn equal_files_drop_the_later_one_first() {
+        let mut items = sample();
+        let overrides = ContextOverrides {
+            include: vec!["session_file:2".to_string()],
+            drop: Vec::new(),
+        };
+        let decisions = apply_rules(&mut items, &overrides, 85_000);
+        let dropped: Vec<&str> = decisions
+            .iter()
+            .filter(|d| d.rule == TrimRule::DropSessionFiles)
+            .map(|d| d.key.as_str())
+            .collect();
+        assert_eq!(dropped, vec!["session_file:3"]);
+        assert_eq!(items[2].action, ContextAction::Include);
+        assert!(items[2].forced);
+    }
+
+    #[test]
+    fn overrides_drop_first_and_force_beats_the_budget() {
+        let mut items = sample();
+        let overrides = ContextOverrides {
+            include: vec!["retry_context".to_string(), "message:10".to_string()],
+            drop: vec!["session_file:1".to_string(), "message:10".to_string(), "prompt".to_string()],
+        };
+        let decisions = apply_rules(&mut items, &overrides, 10_000);
+        assert_eq!(decisions[0], TrimDecision {
+            rule: TrimRule::UserDrop,
+            key: "session_file:1".to_string(),
+            saved_tokens: 30_000,
+        });
+        assert_eq!(items[0].action, ContextAction::Include);
+        assert_eq!(items[5].action, ContextAction::Include);
+        assert_eq!(items[6].action, ContextAction::Include);
+        // Prompt, retry context and the forced message are all that is left
+        assert_eq!(total(&items), 25_000);
+    }
+
+    #[test]
+    fn new_tasks_record_the_applied_plan() {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, prompt) VALUES ('s1', 'p');
+             INSERT INTO tasks (id, session_id, task_index, prompt) VALUES ('t1', 's1', 1, 'first'), ('t2', 's1', 2, 'second');
+             INSERT INTO messages (id, task_id, type, content, include_in_context) VALUES
+                (1, 't1', 'text', 'keep this in mind', 1), (2, 't1', 'text', 'noise', 0);
+             INSERT INTO attachments (id, kind, name, encoding, content, size, tokens) VALUES
+                (5, 'file', 'big.md', 'utf8', '', 0, 500000), (6, 'file', 'small.md', 'utf8', '', 0, 100);
+             INSERT INTO task_attachments (task_id, attachment_id, position, source) VALUES
+                ('t2', 5, 0, 'session'), ('t2', 6, 1, 'session');",
+        )
+        .unwrap();
+        let cache = ContextTokenCache::default();
+        let plan = apply_to_new_task(&conn, &cache, "t2", ContextOverrides::default(), None).unwrap();
+        assert_eq!(plan.context_window, DEFAULT_CONTEXT_WINDOW);
+        let keys: Vec<&str> = plan.items.iter().map(|i| i.key.as_str()).collect();
+        assert_eq!(keys, vec!["prompt", "session_file:5", "session_file:6", "message:1"]);
+        assert_eq!(plan.items[1].action, ContextAction::Drop);
+        assert!(!plan.over_budget);
+
+        let linked: Vec<i64> = conn
+            .prepare("SELECT attachment_id FROM task_attachments WHERE task_id = 't2'")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(linked, vec![6]);
+        let sent: String = conn
+            .query_row("SELECT sent_context FROM tasks WHERE id = 't2'", [], |row| row.get(0))
+            .unwrap();
+        let sent: ContextPlan = serde_json::from_str(&sent).unwrap();
+        assert_eq!(sent.planned_tokens, plan.planned_tokens);
+    }
+}
@@ -0,0 +1,71 @@
+// Direct SQLite access for Rust-side commands.
+//
+// The webview talks to `workany.db` through tauri-plugin-sql, which resolves
+// `sqlite:workany.db` against the app config directory. Rust commands open their
+// own short-lived rusqlite connection to the same file and run on a blocking thread.
+
+use crate::events;
+use rusqlite::Connection;
+use std::path::PathBuf;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+pub const DB_URL: &str = "sqlite:workany.db";
+const DB_FILE: &str = "workany.db";
+
+pub fn emit_change(app: &AppHandle, table: &'static str, op: &'static str, ids: Vec<String>) {
+    events::emit_typed(
+        app,
+        events::DbChanged {
+            table,
+            op,
+            ids,
+            versions: Vec::new(),
+        },
+    );
+}
+
+/// `emit_change` for rows whose new `row_version` is known
+pub fn emit_versioned_change(app: &AppHandle, table: &'static str, op: &'static str, rows: Vec<(String, i64)>) {
+    let (ids, versions) = rows.into_iter().unzip();
+    events::emit_typed(app, events::DbChanged { table, op, ids, versions });
+}
+
+/// Absolute path of the database file shared with the sql plugin
+pub fn db_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join(DB_FILE))
+        .map_err(|e| e.to_string())
+}
+
+/// Database path computed without an AppHandle, for reads that must happen
+/// before the app is built. Mirrors how Tauri resolves `app_config_dir()`.
+pub fn db_path_for_identifier(identifier: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(identifier).join(DB_FILE))
+}
+
+/// Open a connection with the same pragmas the app relies on
+pub fn open(app: &AppHandle) -> Result<Connection, String> {
+    let conn = Connection::open(db_path(app)?).map_err(|e| e.to_string())?;
+    conn.busy_timeout(Duration::from_secs(5))
+        .map_err(|e| e.to_string())?;
+    conn.pragma_update(None, "foreign_keys", "ON")
+        .map_err(|e| e.to_string())?;
+    Ok(conn)
+}
+
+/// Run `f` against a fresh connection on the blocking thread pool
+pub async fn with_conn<T, F>(app: &AppHandle, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Connection) -> Result<T, String> + Send + 'static,
+{
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut conn = open(&app)?;
+        f(&mut conn)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,128 @@
+// Optional cap on database size.
+//
+// A background check compares the space the database actually uses (pages in use,
+// not the file length, so deletes count immediately) against `max_db_size_mb`. When
+// the cap is exceeded and enforcement is on, it sets `db_size_blocked`, which the
+// guard triggers on `tasks` and `messages` read to reject inserts from any writer.
+// Deletes and VACUUM are never blocked, so users can always prune their way back.
+
+use crate::{background, db, events, settings};
+use rusqlite::Connection;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const MAX_SIZE_KEY: &str = "max_db_size_mb";
+const ENFORCE_KEY: &str = "max_db_size_enforce";
+/// Read by the guard triggers from migration 12
+const BLOCKED_KEY: &str = "db_size_blocked";
+const EXCEEDED_KEY: &str = "db_size_exceeded";
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "db_size",
+    interval: CHECK_INTERVAL,
+    coalescable: true,
+};
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DbSizeStatus {
+    pub used_bytes: u64,
+    pub file_bytes: u64,
+    /// `None` when no cap is configured
+    pub limit_bytes: Option<u64>,
+    pub enforce: bool,
+    pub exceeded: bool,
+    pub blocked: bool,
+}
+
+fn used_bytes(conn: &Connection) -> Result<u64, String> {
+    let pragma = |name: &str| -> Result<u64, String> {
+        conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get::<_, i64>(0))
+            .map(|v| v.max(0) as u64)
+            .map_err(|e| e.to_string())
+    };
+    Ok(pragma("page_count")?.saturating_sub(pragma("freelist_count")?) * pragma("page_size")?)
+}
+
+fn file_bytes(app: &AppHandle) -> u64 {
+    let Ok(path) = db::db_path(app) else { return 0 };
+    let wal = path.with_extension("db-wal");
+    [path, wal]
+        .iter()
+        .filter_map(|p| std::fs::metadata(p).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Measure the database, update the block flag and report whether the cap is hit
+fn check(app: &AppHandle, conn: &Connection) -> Result<DbSizeStatus, String> {
+    let limit_mb: Option<u64> = settings::get(conn, MAX_SIZE_KEY)?;
+    let limit_bytes = limit_mb.filter(|mb| *mb > 0).map(|mb| mb * 1024 * 1024);
+    let enforce = settings::get_or(conn, ENFORCE_KEY, false)?;
+    let used = used_bytes(conn)?;
+    let exceeded = limit_bytes.is_some_and(|limit| used > limit);
+    let blocked = exceeded && enforce;
+
+    let was_exceeded = settings::get_or(conn, EXCEEDED_KEY, false)?;
+    if exceeded != was_exceeded {
+        settings::set(conn, EXCEEDED_KEY, &exceeded)?;
+    }
+    if blocked != settings::get_or(conn, BLOCKED_KEY, false)? {
+        settings::set(conn, BLOCKED_KEY, &blocked)?;
+    }
+
+    let status = DbSizeStatus {
+        used_bytes: used,
+        file_bytes: file_bytes(app),
+        limit_bytes,
+        enforce,
+        exceeded,
+        blocked,
+    };
+    if exceeded && !was_exceeded {
+        eprintln!(
+            "[DbSize] Database uses {} bytes, over the {} byte cap",
+            used,
+            limit_bytes.unwrap_or_default()
+        );
+        events::emit_typed(app, events::DbSizeExceeded(status.clone()));
+    }
+    Ok(status)
+}
+
+/// Start the periodic size check. The first check waits one interval so the
+/// frontend has loaded the database and run migrations.
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &ACTIVITY);
+        let result = db::open(&app).and_then(|conn| check(&app, &conn));
+        if let Err(e) = result {
+            eprintln!("[DbSize] Size check failed: {}", e);
+        }
+    });
+}
+
+/// Current size against the cap, re-measured now (e.g. right after pruning)
+#[tauri::command]
+pub async fn check_db_size(app: AppHandle) -> Result<DbSizeStatus, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| check(&handle, conn)).await
+}
+
+/// Set the cap in megabytes (`None` or 0 disables it) and whether exceeding it
+/// blocks new tasks and messages rather than only warning
+#[tauri::command]
+pub async fn set_max_db_size(app: AppHandle, max_mb: Option<u64>, enforce: bool) -> Result<DbSizeStatus, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        match max_mb.filter(|mb| *mb > 0) {
+            Some(mb) => settings::set(conn, MAX_SIZE_KEY, &mb)?,
+            None => settings::remove(conn, MAX_SIZE_KEY)?,
+        }
+        settings::set(conn, ENFORCE_KEY, &enforce)?;
+        check(&handle, conn)
+    })
+    .await
+}
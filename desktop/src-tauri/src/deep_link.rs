@@ -0,0 +1,88 @@
+// `workany://` links. Opening one focuses the main window and tells the frontend
+// what to show; `copy_session_link` gives users a link to paste into notes.
+
+use crate::{db, events, window};
+use rusqlite::params;
+use tauri::AppHandle;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_deep_link::DeepLinkExt;
+
+pub const SCHEME: &str = "workany";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Session(String),
+}
+
+/// Ids go into the URL path unescaped, so only accept characters that need none
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn parse(url: &str) -> Option<Target> {
+    let rest = url.strip_prefix(SCHEME)?.strip_prefix("://")?;
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    match rest.trim_end_matches('/').split_once('/')? {
+        ("session", id) if valid_id(id) => Some(Target::Session(id.to_string())),
+        _ => None,
+    }
+}
+
+fn handle_url(app: &AppHandle, url: &str) {
+    match parse(url) {
+        Some(Target::Session(session_id)) => {
+            window::show_main_window(app);
+            events::emit_typed(app, events::OpenSession { session_id });
+        }
+        None => eprintln!("[DeepLink] Ignoring unrecognized link: {}", url),
+    }
+}
+
+/// Listen for links opened while running and handle the one the app was launched with
+pub fn init(app: &AppHandle) {
+    // Installed bundles register the scheme; dev builds register it at runtime
+    #[cfg(all(debug_assertions, any(target_os = "linux", windows)))]
+    if let Err(e) = app.deep_link().register_all() {
+        eprintln!("[DeepLink] Failed to register scheme: {}", e);
+    }
+
+    let handle = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        for url in event.urls() {
+            handle_url(&handle, url.as_str());
+        }
+    });
+    if let Ok(Some(urls)) = app.deep_link().get_current() {
+        for url in urls {
+            handle_url(app, url.as_str());
+        }
+    }
+}
+
+/// Link to a session, after checking that the session exists
+#[tauri::command]
+pub async fn session_deep_link(app: AppHandle, session_id: String) -> Result<String, String> {
+    if !valid_id(&session_id) {
+        return Err(format!("Invalid session id '{}'", session_id));
+    }
+    db::with_conn(&app, move |conn| {
+        let exists: bool = conn
+            .query_row("SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)", params![session_id], |row| {
+                row.get(0)
+            })
+            .map_err(|e| e.to_string())?;
+        if !exists {
+            return Err(format!("Session '{}' not found", session_id));
+        }
+        Ok(format!("{}://session/{}", SCHEME, session_id))
+    })
+    .await
+}
+
+/// Put a session's link on the clipboard and return it
+#[tauri::command]
+pub async fn copy_session_link(app: AppHandle, session_id: String) -> Result<String, String> {
+    let link = session_deep_link(app.clone(), session_id).await?;
+    app.clipboard().write_text(link.clone()).map_err(|e| e.to_string())?;
+    Ok(link)
+}
@@ -0,0 +1,554 @@
+// "Explain this error": a follow-up task that carries what the failed one left behind.
+//
+// `create_diagnostic_task` collects the failed task's last errors, the tool call most
+// likely responsible, a bounded listing of its session workspace, the sidecar output
+// from while it ran and its permission mode. Everything goes through secret
+// redaction, then is cut to `MAX_CONTEXT_BYTES` in priority order - errors are kept
+// longest, logs are cut first - and stored as the new task's `retry_context`. The
+// frontend runs the new task; this only creates it.
+
+use crate::error::CommandError;
+use crate::message_types::MessageType;
+use crate::routing::RoutingDecision;
+use crate::workspace_ignore::WorkspaceRules;
+use crate::{db, file_repair, forks, prompts, quota, redaction, sidecar_log};
+use chrono::{Duration, NaiveDateTime, TimeZone, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::path::Path;
+use tauri::AppHandle;
+
+/// Cap on the text in a package; JSON overhead comes on top
+const MAX_CONTEXT_BYTES: usize = 24 * 1024;
+const MAX_PROMPT_BYTES: usize = 2000;
+const MAX_ERRORS: usize = 10;
+const MAX_LISTING: usize = 200;
+const MAX_LISTING_DEPTH: usize = 4;
+const MAX_LOG_LINES: usize = 300;
+/// Sidecar lines this long before the task started or after it last changed still count
+const LOG_PADDING_SECS: i64 = 10;
+/// Error text quoted in the new task's prompt; the full text is in the package
+const PROMPT_ERROR_BYTES: usize = 500;
+/// A clipped fragment shorter than this isn't worth including
+const MIN_CLIP_BYTES: usize = 80;
+const ELLIPSIS: &str = "…";
+
+/// Same test `tool_stats` uses for a failed tool result, over the alias `m`
+const RESULT_IS_ERROR: &str = "(m.error_message IS NOT NULL
+     OR m.subtype = 'error'
+     OR m.tool_output LIKE 'Error:%'
+     OR m.tool_output LIKE '%<tool_use_error>%')";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    Errors,
+    ToolCall,
+    Workspace,
+    Logs,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ToolCall {
+    pub tool_name: Option<String>,
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub is_error: bool,
+}
+
+/// Everything gathered for a package, before redaction and the size cap
+#[derive(Debug, Clone, Default)]
+struct RawContext {
+    failed_task_id: String,
+    prompt: String,
+    permission_mode: Option<String>,
+    /// Most recent first
+    errors: Vec<String>,
+    more_errors: bool,
+    tool_call: Option<ToolCall>,
+    workspace: Vec<String>,
+    more_workspace: bool,
+    /// Oldest first
+    logs: Vec<String>,
+}
+
+/// What the diagnostic task receives as `retry_context`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContextPackage {
+    pub failed_task_id: String,
+    pub prompt: String,
+    pub permission_mode: Option<String>,
+    /// Most recent first
+    pub errors: Vec<String>,
+    pub tool_call: Option<ToolCall>,
+    /// Paths relative to the session workspace; directories end in `/`
+    pub workspace: Vec<String>,
+    /// Sidecar output while the task ran, oldest first
+    pub logs: Vec<String>,
+    /// Sections that lost entries or text to the size cap or their own limits
+    pub truncated: Vec<Section>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticTask {
+    pub task_id: String,
+    pub session_id: String,
+    pub task_index: i64,
+    pub prompt: String,
+    pub truncated: Vec<Section>,
+}
+
+/// `text` cut at a character boundary to at most `max` bytes, marked with an ellipsis
+fn clip(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut end = max.saturating_sub(ELLIPSIS.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &text[..end], ELLIPSIS)
+}
+
+struct Budget(usize);
+
+impl Budget {
+    /// `text` whole if it fits, clipped to the remaining room if that is still
+    /// useful, otherwise nothing. The flag says whether anything was lost.
+    fn fit(&mut self, text: &str) -> (Option<String>, bool) {
+        if text.len() <= self.0 {
+            self.0 -= text.len();
+            return (Some(text.to_string()), false);
+        }
+        if self.0 < MIN_CLIP_BYTES {
+            return (None, true);
+        }
+        let clipped = clip(text, self.0);
+        self.0 = 0;
+        (Some(clipped), true)
+    }
+
+    /// Items in order until one doesn't fit. With `partial`, the first one that
+    /// doesn't fit may be clipped; otherwise only whole items are taken.
+    fn take(&mut self, items: &[String], partial: bool) -> (Vec<String>, bool) {
+        let mut taken = Vec::new();
+        for item in items {
+            if item.len() <= self.0 {
+                self.0 -= item.len();
+                taken.push(item.clone());
+                continue;
+            }
+            if partial {
+                if let (Some(clipped), _) = self.fit(item) {
+                    taken.push(clipped);
+                }
+            }
+            return (taken, true);
+        }
+        (taken, false)
+    }
+}
+
+/// Redact `raw` and cut it to `max_bytes`, filling errors, then the tool call, then
+/// the workspace listing and finally the logs
+fn assemble(raw: &RawContext, max_bytes: usize) -> ContextPackage {
+    let redact_all = |items: &[String]| -> Vec<String> { items.iter().map(|s| redaction::redact_secrets(s)).collect() };
+    let prompt = clip(&redaction::redact_secrets(&raw.prompt), MAX_PROMPT_BYTES);
+    let permission_mode = raw.permission_mode.clone();
+    let mut budget = Budget(
+        max_bytes
+            .saturating_sub(prompt.len())
+            .saturating_sub(permission_mode.as_deref().map_or(0, str::len)),
+    );
+    let mut truncated = Vec::new();
+
+    let (errors, cut) = budget.take(&redact_all(&raw.errors), true);
+    if cut || raw.more_errors {
+        truncated.push(Section::Errors);
+    }
+
+    let tool_call = raw.tool_call.as_ref().map(|call| {
+        let mut cut = false;
+        let mut fit = |text: &Option<String>| {
+            let (text, lost) = match text {
+                Some(text) => budget.fit(&redaction::redact_secrets(text)),
+                None => (None, false),
+            };
+            cut |= lost;
+            text
+        };
+        let input = fit(&call.input);
+        let output = fit(&call.output);
+        if cut {
+            truncated.push(Section::ToolCall);
+        }
+        ToolCall {
+            tool_name: call.tool_name.clone(),
+            input,
+            output,
+            is_error: call.is_error,
+        }
+    });
+
+    let (workspace, cut) = budget.take(&redact_all(&raw.workspace), false);
+    if cut || raw.more_workspace {
+        truncated.push(Section::Workspace);
+    }
+
+    // The newest lines are the likeliest to matter, so those are kept
+    let mut newest_first = redact_all(&raw.logs);
+    newest_first.reverse();
+    let (mut logs, cut) = budget.take(&newest_first, false);
+    logs.reverse();
+    if cut {
+        truncated.push(Section::Logs);
+    }
+
+    ContextPackage {
+        failed_task_id: raw.failed_task_id.clone(),
+        prompt,
+        permission_mode,
+        errors,
+        tool_call,
+        workspace,
+        logs,
+        truncated,
+    }
+}
+
+fn diagnostic_prompt(package: &ContextPackage) -> String {
+    let error = package
+        .errors
+        .first()
+        .map(|e| clip(e, PROMPT_ERROR_BYTES))
+        .unwrap_or_else(|| "an error that left no message".to_string());
+    format!(
+        "The previous attempt failed with:\n\n{}\n\nThe original request was:\n\n{}\n\n\
+         Use the diagnostic context attached to this task to work out what went wrong, \
+         fix it, and then finish the original request.",
+        error, package.prompt
+    )
+}
+
+/// Paths under `dir` in name order, depth first, skipping symlinks and what the
+/// workspace rules ignore. Ignored folders are listed but not entered. Returns false
+/// if `MAX_LISTING` was hit.
+fn list_workspace(rules: &WorkspaceRules, dir: &Path, depth: usize, out: &mut Vec<String>) -> bool {
+    let root = rules.root();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return true;
+    };
+    let mut entries: Vec<_> = entries.flatten().collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let Ok(file_type) = entry.file_type() else { continue };
+        if file_type.is_symlink() {
+            continue;
+        }
+        if out.len() >= MAX_LISTING {
+            return false;
+        }
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().into_owned();
+        if file_type.is_dir() {
+            out.push(format!("{}/", relative));
+            let skipped = rules.is_ignored(&path, true);
+            if !skipped && depth + 1 < MAX_LISTING_DEPTH && !list_workspace(rules, &path, depth + 1, out) {
+                return false;
+            }
+        } else if !rules.is_ignored(&path, false) {
+            out.push(relative);
+        }
+    }
+    true
+}
+
+fn parse_sqlite_time(value: &str) -> Option<chrono::DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|t| Utc.from_utc_datetime(&t))
+}
+
+fn gather(app: &AppHandle, conn: &Connection, task_id: &str) -> Result<(String, RawContext), String> {
+    let (session_id, prompt, status, routing, created_at, updated_at): (
+        Option<String>,
+        String,
+        String,
+        Option<String>,
+        String,
+        String,
+    ) = conn
+        .query_row(
+            &format!(
+                "SELECT session_id, {}, status, routing, created_at, updated_at FROM tasks WHERE id = ?1",
+                prompts::FULL_PROMPT_SQL
+            ),
+            [task_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Task '{}' not found", task_id))?;
+    let session_id = session_id.ok_or_else(|| format!("Task '{}' has no session", task_id))?;
+    if status != "error" {
+        return Err(format!("Task '{}' has not failed", task_id));
+    }
+
+    let mut errors: Vec<String> = {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT COALESCE(m.error_message, m.content, m.tool_output, m.subtype)
+                 FROM messages m
+                 WHERE m.task_id = ?1
+                   AND (m.type = '{error}'
+                        OR (m.type = '{result}' AND COALESCE(m.subtype, '') != 'success')
+                        OR (m.type = '{tool_result}' AND {is_error}))
+                 ORDER BY m.id DESC LIMIT ?2",
+                error = MessageType::Error.as_str(),
+                result = MessageType::Result.as_str(),
+                tool_result = MessageType::ToolResult.as_str(),
+                is_error = RESULT_IS_ERROR
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![task_id, MAX_ERRORS as i64 + 1], |row| row.get::<_, Option<String>>(0))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .flatten()
+            .filter(|e| !e.trim().is_empty())
+            .collect()
+    };
+    let more_errors = errors.len() > MAX_ERRORS;
+    errors.truncate(MAX_ERRORS);
+
+    // The last failed call, or the last call at all if none failed
+    let tool_call = conn
+        .query_row(
+            &format!(
+                "SELECT u.tool_name, u.tool_input, COALESCE(m.tool_output, m.error_message),
+                        COALESCE({is_error}, 0) AS failed
+                 FROM messages u
+                 LEFT JOIN messages m
+                   ON m.task_id = u.task_id AND m.type = '{tool_result}' AND m.tool_use_id = u.tool_use_id
+                 WHERE u.task_id = ?1 AND u.type = '{tool_use}'
+                 ORDER BY failed DESC, u.id DESC LIMIT 1",
+                is_error = RESULT_IS_ERROR,
+                tool_result = MessageType::ToolResult.as_str(),
+                tool_use = MessageType::ToolUse.as_str()
+            ),
+            [task_id],
+            |row| {
+                Ok(ToolCall {
+                    tool_name: row.get(0)?,
+                    input: row.get(1)?,
+                    output: row.get(2)?,
+                    is_error: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    let mut workspace = Vec::new();
+    let mut more_workspace = false;
+    if let Some(dir) = file_repair::sessions_dir().map(|d| d.join(&session_id)) {
+        let rules = WorkspaceRules::load(conn, &dir)?;
+        more_workspace = !list_workspace(&rules, &dir, 0, &mut workspace);
+    }
+
+    let logs = match (parse_sqlite_time(&created_at), parse_sqlite_time(&updated_at)) {
+        (Some(from), Some(to)) => {
+            let padding = Duration::seconds(LOG_PADDING_SECS);
+            let lines = sidecar_log::between(app, from - padding, to + padding);
+            let skip = lines.len().saturating_sub(MAX_LOG_LINES);
+            lines
+                .into_iter()
+                .skip(skip)
+                .map(|l| {
+                    let stream = match l.stream {
+                        sidecar_log::LogStream::Stdout => "out",
+                        sidecar_log::LogStream::Stderr => "err",
+                    };
+                    format!("{} [{}] {}", l.at.format("%H:%M:%S%.3f"), stream, l.line)
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+
+    let permission_mode = routing
+        .and_then(|r| serde_json::from_str::<RoutingDecision>(&r).ok())
+        .and_then(|d| d.permission_mode);
+
+    Ok((
+        session_id,
+        RawContext {
+            failed_task_id: task_id.to_string(),
+            prompt,
+            permission_mode,
+            errors,
+            more_errors,
+            tool_call,
+            workspace,
+            more_workspace,
+            logs,
+        },
+    ))
+}
+
+fn create(
+    conn: &mut Connection,
+    session_id: &str,
+    package: &ContextPackage,
+) -> Result<DiagnosticTask, String> {
+    let context = serde_json::to_string(package).map_err(|e| e.to_string())?;
+    let prompt = diagnostic_prompt(package);
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let (task_id, task_index) = forks::next_task_slot(&tx, session_id)?;
+    tx.execute(
+        "INSERT INTO tasks (id, session_id, task_index, prompt, status, retry_context)
+         VALUES (?1, ?2, ?3, ?4, 'stopped', ?5)",
+        params![task_id, session_id, task_index, prompt, context],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE sessions SET task_count = MAX(task_count, ?1), updated_at = datetime('now') WHERE id = ?2",
+        params![task_index, session_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(DiagnosticTask {
+        task_id,
+        session_id: session_id.to_string(),
+        task_index,
+        prompt,
+        truncated: package.truncated.clone(),
+    })
+}
+
+/// Create a task in the failed task's session that asks the agent to explain and
+/// fix the failure, with the redacted context package as its `retry_context`. The
+/// caller runs it. Fails with `quota_exceeded` while a quota blocks new tasks.
+#[tauri::command]
+pub async fn create_diagnostic_task(app: AppHandle, failed_task_id: String) -> Result<DiagnosticTask, CommandError> {
+    if let Some(error) = db::with_conn(&app, |conn| quota::blocking_error(conn)).await? {
+        return Err(error);
+    }
+    let handle = app.clone();
+    let created = db::with_conn(&app, move |conn| {
+        let (session_id, raw) = gather(&handle, conn, &failed_task_id)?;
+        let package = assemble(&raw, MAX_CONTEXT_BYTES);
+        create(conn, &session_id, &package)
+    })
+    .await?;
+    db::emit_change(&app, "tasks", "insert", vec![created.task_id.clone()]);
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fixture() -> RawContext {
+        RawContext {
+            failed_task_id: "s1-task-01".to_string(),
+            prompt: "Build the report".to_string(),
+            permission_mode: Some("acceptEdits".to_string()),
+            errors: vec![
+                "Command failed: npm run build".to_string(),
+                "ANTHROPIC_API_KEY=abcdef123456 is invalid".to_string(),
+            ],
+            more_errors: false,
+            tool_call: Some(ToolCall {
+                tool_name: Some("Bash".to_string()),
+                input: Some(r#"{"command":"npm run build"}"#.to_string()),
+                output: Some("Error: exit code 1".to_string()),
+                is_error: true,
+            }),
+            workspace: vec!["package.json".to_string(), "src/".to_string(), "src/index.ts".to_string()],
+            more_workspace: false,
+            logs: vec![
+                "10:00:00.000 [out] starting".to_string(),
+                "10:00:01.000 [err] build failed".to_string(),
+            ],
+        }
+    }
+
+    #[test]
+    fn package_shape_is_stable() {
+        let package = assemble(&fixture(), MAX_CONTEXT_BYTES);
+        assert_eq!(
+            serde_json::to_value(&package).unwrap(),
+            json!({
+                "failed_task_id": "s1-task-01",
+                "prompt": "Build the report",
+                "permission_mode": "acceptEdits",
+                "errors": [
+                    "Command failed: npm run build",
+                    "ANTHROPIC_API_KEY=[REDACTED:secret] is invalid",
+                ],
+                "tool_call": {
+                    "tool_name": "Bash",
+                    "input": "{\"command\":\"npm run build\"}",
+                    "output": "Error: exit code 1",
+                    "is_error": true,
+                },
+                "workspace": ["package.json", "src/", "src/index.ts"],
+                "logs": ["10:00:00.000 [out] starting", "10:00:01.000 [err] build failed"],
+                "truncated": [],
+            })
+        );
+    }
+
+    #[test]
+    fn logs_are_cut_before_anything_else() {
+        let mut raw = fixture();
+        raw.logs = (0..100).map(|i| format!("10:00:{:02}.000 [out] line {}", i % 60, i)).collect();
+        let fixed = raw.prompt.len() + "acceptEdits".len();
+        let without_logs = assemble(&RawContext { logs: Vec::new(), ..raw.clone() }, usize::MAX);
+        let call = without_logs.tool_call.clone().unwrap();
+        let used: usize = without_logs.errors.iter().map(String::len).sum::<usize>()
+            + without_logs.workspace.iter().map(String::len).sum::<usize>()
+            + call.input.map_or(0, |s| s.len())
+            + call.output.map_or(0, |s| s.len());
+        let package = assemble(&raw, fixed + used + 200);
+        assert_eq!(package.errors, without_logs.errors);
+        assert_eq!(package.workspace, without_logs.workspace);
+        assert!(!package.logs.is_empty() && package.logs.len() < 100);
+        assert_eq!(package.logs.last().unwrap(), raw.logs.last().unwrap());
+        assert_eq!(package.truncated, vec![Section::Logs]);
+    }
+
+    #[test]
+    fn errors_survive_a_tiny_budget() {
+        let mut raw = fixture();
+        raw.errors = vec!["x".repeat(1000)];
+        let package = assemble(&raw, raw.prompt.len() + "acceptEdits".len() + 300);
+        assert_eq!(package.errors.len(), 1);
+        assert!(package.errors[0].ends_with(ELLIPSIS) && package.errors[0].len() <= 300);
+        assert!(package.workspace.is_empty() && package.logs.is_empty());
+        let call = package.tool_call.unwrap();
+        assert_eq!((call.input, call.output), (None, None));
+        assert_eq!(
+            package.truncated,
+            vec![Section::Errors, Section::ToolCall, Section::Workspace, Section::Logs]
+        );
+    }
+
+    #[test]
+    fn prompt_quotes_the_latest_error() {
+        let package = assemble(&fixture(), MAX_CONTEXT_BYTES);
+        let prompt = diagnostic_prompt(&package);
+        assert!(prompt.starts_with("The previous attempt failed with:\n\nCommand failed: npm run build\n\n"));
+        assert!(prompt.contains("The original request was:\n\nBuild the report"));
+    }
+
+    #[test]
+    fn clip_respects_char_boundaries() {
+        assert_eq!(clip("héllo", 5), "h…");
+        assert_eq!(clip("short", 10), "short");
+    }
+}
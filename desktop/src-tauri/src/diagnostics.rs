@@ -0,0 +1,193 @@
+// Developer diagnostics for support sessions: devtools per window, window facts,
+// and an FPS/memory overlay fed by `diagnostics://sample` events.
+//
+// Everything here requires developer mode (always on in debug builds). Sampling runs
+// only while at least one window has the overlay open.
+
+use crate::error::CommandError;
+use crate::{background, db, events, ipc_access, settings};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::ipc::Invoke;
+use tauri::{AppHandle, Manager, Runtime};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Runs only while an overlay is showing, which the user is watching
+const ACTIVITY: background::Activity = background::Activity {
+    name: "diagnostics_sampler",
+    interval: SAMPLE_INTERVAL,
+    coalescable: false,
+};
+
+static IPC_CALLS: AtomicU64 = AtomicU64::new(0);
+static EVENTS_EMITTED: AtomicU64 = AtomicU64::new(0);
+
+/// Windows showing the overlay, and whether the sampling thread is alive
+#[derive(Default)]
+pub struct DiagnosticsState {
+    overlays: Mutex<HashSet<String>>,
+    sampling: AtomicBool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowDiagnostics {
+    pub label: String,
+    pub inner_size: (u32, u32),
+    pub outer_size: (u32, u32),
+    pub scale_factor: f64,
+    pub visible: bool,
+    pub focused: bool,
+    pub webview_version: Option<String>,
+    pub url: Option<String>,
+    /// Path plus fragment, which is where the frontend router keeps its state
+    pub route: Option<String>,
+    pub overlay_active: bool,
+    /// Calls this window made to commands it isn't allowed to use, since launch
+    pub ipc_denied: u64,
+}
+
+/// Wrap the command handler so every IPC call is counted for the overlay
+pub fn count_ipc<R: Runtime>(
+    handler: impl Fn(Invoke<R>) -> bool + Send + Sync + 'static,
+) -> impl Fn(Invoke<R>) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        IPC_CALLS.fetch_add(1, Ordering::Relaxed);
+        handler(invoke)
+    }
+}
+
+/// Count an event sent to the webview
+pub fn record_event() {
+    EVENTS_EMITTED.fetch_add(1, Ordering::Relaxed);
+}
+
+async fn require_developer_mode(app: &AppHandle) -> Result<(), CommandError> {
+    let enabled = db::with_conn(app, |conn| Ok(settings::developer_mode(conn))).await?;
+    if enabled {
+        Ok(())
+    } else {
+        Err(CommandError::FeatureDisabled {
+            message: "Diagnostics require developer mode".to_string(),
+            feature: "developer_mode".to_string(),
+        })
+    }
+}
+
+fn webview_window(app: &AppHandle, label: &str) -> Result<tauri::WebviewWindow, CommandError> {
+    app.get_webview_window(label)
+        .ok_or_else(|| format!("No window labelled '{}'", label).into())
+}
+
+fn sampling_loop(app: AppHandle) {
+    let mut last_at = Instant::now();
+    let mut last_ipc = IPC_CALLS.load(Ordering::Relaxed);
+    let mut last_events = EVENTS_EMITTED.load(Ordering::Relaxed);
+    loop {
+        background::sleep(&app, &ACTIVITY);
+        let state = app.state::<DiagnosticsState>();
+        let targets: Vec<String> = {
+            let Ok(mut overlays) = state.overlays.lock() else { break };
+            overlays.retain(|label| app.get_webview_window(label).is_some());
+            if overlays.is_empty() {
+                // Cleared under the lock so a concurrent toggle either sees the
+                // thread alive or starts a new one
+                state.sampling.store(false, Ordering::SeqCst);
+                break;
+            }
+            overlays.iter().cloned().collect()
+        };
+
+        let elapsed = last_at.elapsed().as_secs_f64().max(f64::EPSILON);
+        let ipc = IPC_CALLS.load(Ordering::Relaxed);
+        // Count before emitting so the samples themselves don't show up as load
+        let emitted = EVENTS_EMITTED.load(Ordering::Relaxed);
+        let sample = events::DiagnosticsSample {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            rss_bytes: memory_stats::memory_stats().map(|m| m.physical_mem as u64),
+            ipc_calls_per_sec: (ipc - last_ipc) as f64 / elapsed,
+            events_per_sec: (emitted - last_events) as f64 / elapsed,
+        };
+        last_at = Instant::now();
+        last_ipc = ipc;
+        last_events = emitted;
+
+        for label in targets {
+            events::emit_typed_to(&app, &label, sample.clone());
+        }
+    }
+}
+
+/// Open or close devtools on a window
+#[tauri::command]
+pub async fn set_devtools(app: AppHandle, window_label: String, open: bool) -> Result<(), CommandError> {
+    require_developer_mode(&app).await?;
+    let window = webview_window(&app, &window_label)?;
+    if open {
+        window.open_devtools();
+    } else {
+        window.close_devtools();
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_window_diagnostics(app: AppHandle, window_label: String) -> Result<WindowDiagnostics, CommandError> {
+    require_developer_mode(&app).await?;
+    let window = webview_window(&app, &window_label)?;
+    let inner = window.inner_size().map_err(|e| e.to_string())?;
+    let outer = window.outer_size().map_err(|e| e.to_string())?;
+    let url = window.url().ok();
+    let route = url.as_ref().map(|url| match url.fragment() {
+        Some(fragment) => format!("{}#{}", url.path(), fragment),
+        None => url.path().to_string(),
+    });
+    let overlay_active = app
+        .state::<DiagnosticsState>()
+        .overlays
+        .lock()
+        .map(|overlays| overlays.contains(&window_label))
+        .unwrap_or(false);
+    let ipc_denied = ipc_access::denials_for(&window_label);
+    Ok(WindowDiagnostics {
+        label: window_label,
+        inner_size: (inner.width, inner.height),
+        outer_size: (outer.width, outer.height),
+        scale_factor: window.scale_factor().map_err(|e| e.to_string())?,
+        visible: window.is_visible().unwrap_or(false),
+        focused: window.is_focused().unwrap_or(false),
+        webview_version: tauri::webview_version().ok(),
+        url: url.map(|url| url.to_string()),
+        route,
+        overlay_active,
+        ipc_denied,
+    })
+}
+
+/// Show or hide the overlay on a window, returning whether it is now visible
+#[tauri::command]
+pub async fn toggle_diagnostic_overlay(app: AppHandle, window_label: String) -> Result<bool, CommandError> {
+    require_developer_mode(&app).await?;
+    webview_window(&app, &window_label)?;
+    let state = app.state::<DiagnosticsState>();
+    let visible = {
+        let mut overlays = state.overlays.lock().map_err(|e| e.to_string())?;
+        if overlays.remove(&window_label) {
+            false
+        } else {
+            overlays.insert(window_label.clone());
+            true
+        }
+    };
+    if visible && !state.sampling.swap(true, Ordering::SeqCst) {
+        let handle = app.clone();
+        std::thread::spawn(move || sampling_loop(handle));
+    }
+    events::emit_typed_to(&app, &window_label, events::DiagnosticsOverlay { visible });
+    Ok(visible)
+}
@@ -0,0 +1,85 @@
+// Structured command errors for cases the UI needs to react to, rather than
+// just display. Serialized as `{ "kind": "...", "message": "...", ... }`.
+
+use crate::file_scan::ScanVerdict;
+use crate::platform::Feature;
+use crate::quota::QuotaPeriod;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CommandError {
+    /// Plain failure with a human readable message
+    Failed { message: String },
+    /// The target already exists; `existing` describes what is in the way
+    Conflict { message: String, existing: Value },
+    /// A remote API presented a different certificate than the one pinned
+    PinMismatch {
+        message: String,
+        expected: String,
+        actual: String,
+    },
+    /// The feature is switched off, e.g. a developer tool outside developer mode
+    FeatureDisabled { message: String, feature: String },
+    /// The endpoint's circuit breaker is open; callers should skip and retry later
+    CircuitOpen {
+        message: String,
+        endpoint: String,
+        retry_after_ms: u64,
+    },
+    /// A spending quota blocks new tasks until the period ends or the limit is raised
+    QuotaExceeded {
+        message: String,
+        period: QuotaPeriod,
+        used_usd: f64,
+        limit_usd: f64,
+    },
+    /// Opening needs a clean scan first; `verdict` is `None` if it was never scanned
+    ScanRequired {
+        message: String,
+        file_id: i64,
+        verdict: Option<ScanVerdict>,
+    },
+    /// The calling window isn't allowed to use this command
+    Forbidden {
+        message: String,
+        command: String,
+        window: String,
+    },
+    /// The desktop session can't provide the feature, e.g. no portal under Wayland
+    Unsupported { message: String, feature: Feature },
+    /// Raw SQL from the webview on a path that has moved to `replacement`
+    Migrated {
+        message: String,
+        statement: String,
+        table: String,
+        replacement: String,
+    },
+    /// A path on a network volume didn't answer within the volume timeout
+    VolumeUnavailable {
+        message: String,
+        path: String,
+        timeout_ms: u64,
+    },
+    /// A cron expression failed to parse; `position` and `length` are in characters
+    InvalidSchedule {
+        message: String,
+        position: usize,
+        length: usize,
+    },
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::Failed { message }
+    }
+}
+
+impl From<&str> for CommandError {
+    fn from(message: &str) -> Self {
+        CommandError::Failed {
+            message: message.to_string(),
+        }
+    }
+}
@@ -0,0 +1,482 @@
+// Every event sent to the webview, as a typed payload with a fixed name.
+//
+// Events are emitted only through `emit_typed` / `emit_typed_to`, so a payload can't
+// drift from its declared type. The JSON schema of every payload is snapshotted in
+// `events_schema.json` by the tests below: renaming a field shows up as a schema diff
+// in review, and `VERSION` should be bumped when the change isn't backwards compatible.
+// `get_event_catalog` lets the frontend check names and versions at startup.
+
+use crate::captures::Capture;
+use crate::circuit::CircuitState;
+use crate::db_size::DbSizeStatus;
+use crate::focus::FocusReason;
+use crate::integrity::Anomaly;
+use crate::jobs::JobInfo;
+use crate::migrations::MigrationStatus;
+use crate::power::SleepInterval;
+use crate::quota::{QuotaMode, QuotaPeriod, WarningLevel};
+use schemars::schema::RootSchema;
+use schemars::{schema_for, JsonSchema};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+pub trait TypedEvent: Serialize + Clone + JsonSchema {
+    const NAME: &'static str;
+    /// Bumped on incompatible payload changes
+    const VERSION: u32;
+}
+
+/// Emit to every window
+pub fn emit_typed<E: TypedEvent>(app: &AppHandle, event: E) {
+    crate::diagnostics::record_event();
+    let _ = app.emit(E::NAME, event);
+}
+
+/// Emit to the window (or webview) labelled `label` only
+pub fn emit_typed_to<E: TypedEvent>(app: &AppHandle, label: &str, event: E) {
+    crate::diagnostics::record_event();
+    let _ = app.emit_to(label, E::NAME, event);
+}
+
+// ---------------------------------------------------------------------------
+// Payloads
+// ---------------------------------------------------------------------------
+
+/// Rows changed by a Rust command; open views reload the listed ids
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DbChanged {
+    pub table: &'static str,
+    pub op: &'static str,
+    pub ids: Vec<String>,
+    /// `row_version` of each id after the change, in the same order; empty when the
+    /// writer didn't read them back. Views ignore a change older than what they show.
+    pub versions: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct JobProgress(pub JobInfo);
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct JobFinished(pub JobInfo);
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ThumbnailProgress {
+    pub task_id: Option<String>,
+    pub done: u32,
+    pub total: u32,
+    pub generated: u32,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct MigrationChecksumMismatch(pub MigrationStatus);
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct DbSizeExceeded(pub DbSizeStatus);
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct IntegrityAnomaly(pub Vec<Anomaly>);
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ApiPinMismatch {
+    pub url: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct CaptureReceived(pub Capture);
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SidecarIdleStopped {
+    pub idle_minutes: u32,
+}
+
+/// The API sidecar exited with a failure; `restart_in_ms` is `None` once it has
+/// crashed `max_attempts` times in a row and is left down
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SidecarCrashed {
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub restart_in_ms: Option<u64>,
+}
+
+/// `restart_api_sidecar` is stopping the sidecar; `sidecar://started` follows once
+/// the new one is spawned
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SidecarRestarting {
+    pub pid: Option<u32>,
+}
+
+/// A sidecar process was spawned, at launch or by a crash or manual restart
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SidecarStarted {
+    pub pid: u32,
+    pub port: u16,
+}
+
+/// A sidecar process exited, for any reason. `expected` when it was stopped on
+/// purpose; otherwise `sidecar://crashed` follows for a failure exit.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SidecarTerminated {
+    pub pid: u32,
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    pub expected: bool,
+    /// The process's last stderr lines, oldest first
+    pub stderr_tail: Vec<String>,
+}
+
+/// A sidecar process exists; `sidecar://started` follows with its port
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SidecarSpawned {
+    pub pid: u32,
+}
+
+/// One line the sidecar wrote to stderr
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SidecarStderr {
+    pub line: String,
+}
+
+/// One line the sidecar wrote to stdout; only sent with the `sidecar_stdout_events`
+/// debug setting on
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SidecarStdout {
+    pub line: String,
+}
+
+/// A network working directory of running tasks stopped answering
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct VolumeLost {
+    pub path: String,
+    pub task_ids: Vec<String>,
+    pub retry_in_ms: u64,
+}
+
+/// A lost network working directory answers again
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct VolumeRestored {
+    pub path: String,
+    pub task_ids: Vec<String>,
+    pub down_ms: u64,
+}
+
+/// Stored messages have shapes this build can't render; suggest an update. Sent
+/// once per install.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SchemaDriftDetected {
+    pub shapes: u32,
+    pub occurrences: i64,
+}
+
+/// A task's cost or duration changed, or its metrics became final; views showing
+/// totals should reload them
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TaskMetricsUpdated {
+    pub task_id: String,
+    pub cost: Option<f64>,
+    pub duration: Option<i64>,
+    pub metrics_final: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiLogLevel {
+    /// stdout
+    Info,
+    /// stderr
+    Error,
+}
+
+/// One line of sidecar output
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ApiLogLine {
+    pub level: ApiLogLevel,
+    pub line: String,
+    /// RFC 3339, when the line arrived
+    pub ts: String,
+}
+
+/// Sidecar output that arrived faster than it is streamed line by line, in order
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ApiLogBatch {
+    pub lines: Vec<ApiLogLine>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OpenSession {
+    pub session_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DiagnosticsSample {
+    pub timestamp_ms: u64,
+    pub rss_bytes: Option<u64>,
+    pub ipc_calls_per_sec: f64,
+    pub events_per_sec: f64,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DiagnosticsOverlay {
+    pub visible: bool,
+}
+
+/// A running task has produced no messages for longer than the stall timeout
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TaskStalled {
+    pub task_id: String,
+    pub idle_ms: u64,
+    pub timeout_ms: u64,
+}
+
+/// Show this task because it needs the user; see `focus`
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct FocusSwitchTask {
+    pub task_id: String,
+    pub reason: FocusReason,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CircuitStateChanged {
+    pub endpoint: String,
+    pub from: CircuitState,
+    pub to: CircuitState,
+    pub last_error: Option<String>,
+}
+
+/// Sent together with `PowerResume`, once the sleep has been detected
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PowerSuspend {
+    pub started_at: i64,
+}
+
+/// Reconnect streams and resync; the sidecar gets `grace_ms` before liveness checks
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PowerResume {
+    pub interval: SleepInterval,
+    pub slept_ms: u64,
+    pub grace_ms: u64,
+}
+
+/// Spend this period has reached a warning level of its limit
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct QuotaWarning {
+    pub period: QuotaPeriod,
+    pub level: WarningLevel,
+    pub used_usd: f64,
+    pub limit_usd: f64,
+    pub mode: QuotaMode,
+}
+
+/// Stop these running tasks: the quota is exceeded in hard-stop mode
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct QuotaStopTasks {
+    pub task_ids: Vec<String>,
+}
+
+/// No tray host is running, so closing the main window quits instead of hiding it.
+/// Sent once per install.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TrayUnavailable {
+    pub desktop: Option<String>,
+    pub message: String,
+}
+
+/// The webview ran a raw SQL statement through the sql plugin. Sent the first time
+/// each fingerprint is seen in a run.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct LegacySqlQuery {
+    /// The statement with literals and placeholders replaced by `?`
+    pub fingerprint: String,
+    pub statement: String,
+    pub table: Option<String>,
+    /// Set when the denylist rejected it
+    pub replacement: Option<String>,
+}
+
+/// A notification for the webview to show as a toast or on the badge
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct NotificationShown {
+    pub kind: crate::notification_prefs::NotificationKind,
+    pub task_id: Option<String>,
+    pub title: String,
+    pub body: String,
+    pub channel: crate::notification_prefs::Channel,
+    /// Quiet hours kept it off the system notification center
+    pub quiet: bool,
+}
+
+/// One frame of a stream replayed from an API cassette
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ApiReplayFrame {
+    pub stream_id: String,
+    pub index: usize,
+    /// `None` only for the end marker of a stream with no frames
+    pub data: Option<String>,
+    pub done: bool,
+}
+
+/// The API answered its health check after a sidecar start; `port` is where the
+/// webview should connect
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ApiReady {
+    pub port: u16,
+    pub elapsed_ms: u64,
+}
+
+/// The API didn't answer within `api_ready_timeout_secs`, or stopped answering;
+/// `error` is the last probe's
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ApiUnreachable {
+    pub port: u16,
+    pub timeout_ms: u64,
+    pub error: String,
+}
+
+macro_rules! typed_events {
+    ($($ty:ty => $name:literal, $version:literal;)*) => {
+        $(
+            impl TypedEvent for $ty {
+                const NAME: &'static str = $name;
+                const VERSION: u32 = $version;
+            }
+        )*
+
+        /// Every event with its payload schema, in declaration order
+        fn catalog() -> Vec<(&'static str, u32, RootSchema)> {
+            vec![$((<$ty>::NAME, <$ty>::VERSION, schema_for!($ty)),)*]
+        }
+    };
+}
+
+typed_events! {
+    DbChanged => "db://changed", 1;
+    JobProgress => "job://progress", 1;
+    JobFinished => "job://finished", 1;
+    ThumbnailProgress => "thumbnail-progress", 1;
+    MigrationChecksumMismatch => "migration://checksum-mismatch", 1;
+    DbSizeExceeded => "db-size-exceeded", 1;
+    IntegrityAnomaly => "integrity://anomaly", 1;
+    ApiPinMismatch => "api://pin-mismatch", 1;
+    CaptureReceived => "capture://received", 1;
+    SidecarIdleStopped => "sidecar-idle-stopped", 1;
+    SidecarCrashed => "sidecar://crashed", 1;
+    SidecarRestarting => "sidecar://restarting", 1;
+    SidecarStarted => "sidecar://started", 1;
+    SidecarTerminated => "sidecar://terminated", 1;
+    SidecarSpawned => "sidecar://spawned", 1;
+    SidecarStderr => "sidecar://stderr", 1;
+    SidecarStdout => "sidecar://stdout", 1;
+    VolumeLost => "workspace://volume-lost", 1;
+    VolumeRestored => "workspace://volume-restored", 1;
+    SchemaDriftDetected => "schema://drift-detected", 1;
+    TaskMetricsUpdated => "task://metrics-updated", 1;
+    ApiLogLine => "api-log", 1;
+    ApiLogBatch => "api-log-batch", 1;
+    OpenSession => "open-session", 1;
+    DiagnosticsSample => "diagnostics://sample", 1;
+    DiagnosticsOverlay => "diagnostics://overlay", 1;
+    TaskStalled => "task-stalled", 1;
+    FocusSwitchTask => "focus://switch-task", 1;
+    CircuitStateChanged => "circuit://state-changed", 1;
+    PowerSuspend => "power://suspend", 1;
+    PowerResume => "power://resume", 1;
+    QuotaWarning => "quota://warning", 1;
+    QuotaStopTasks => "quota://stop-tasks", 1;
+    TrayUnavailable => "system://tray-unavailable", 1;
+    LegacySqlQuery => "sql://legacy-query", 1;
+    ApiReplayFrame => "api://replay-frame", 1;
+    NotificationShown => "notify://show", 1;
+    ApiReady => "api://ready", 1;
+    ApiUnreachable => "api://unreachable", 1;
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventCatalogEntry {
+    pub name: &'static str,
+    pub version: u32,
+}
+
+/// Names and payload versions of every event the backend can emit
+#[tauri::command]
+pub fn get_event_catalog() -> Vec<EventCatalogEntry> {
+    catalog()
+        .into_iter()
+        .map(|(name, version, _)| EventCatalogEntry { name, version })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, HashSet};
+    use std::path::Path;
+
+    const SCHEMA_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/events_schema.json");
+
+    fn schema_document() -> String {
+        let events: BTreeMap<&str, serde_json::Value> = catalog()
+            .into_iter()
+            .map(|(name, version, schema)| {
+                (name, serde_json::json!({ "version": version, "payload": schema }))
+            })
+            .collect();
+        serde_json::to_string_pretty(&events).unwrap() + "\n"
+    }
+
+    #[test]
+    fn event_names_are_unique() {
+        let mut seen = HashSet::new();
+        for (name, _, _) in catalog() {
+            assert!(seen.insert(name), "duplicate event name {}", name);
+        }
+    }
+
+    /// Regenerate with `UPDATE_EVENT_SCHEMA=1 cargo test` and commit the result
+    #[test]
+    fn payload_schemas_match_snapshot() {
+        let generated = schema_document();
+        let path = Path::new(SCHEMA_FILE);
+        if std::env::var_os("UPDATE_EVENT_SCHEMA").is_some() {
+            std::fs::write(path, &generated).unwrap();
+            return;
+        }
+        let snapshot = std::fs::read_to_string(path).unwrap_or_else(|e| {
+            panic!("{} is missing ({}); generate it with UPDATE_EVENT_SCHEMA=1 cargo test", SCHEMA_FILE, e)
+        });
+        assert!(
+            snapshot == generated,
+            "Event payloads changed. Review the diff, bump VERSION for incompatible changes, \
+             and regenerate events_schema.json with UPDATE_EVENT_SCHEMA=1 cargo test"
+        );
+    }
+
+    #[test]
+    fn no_raw_emits_outside_events_module() {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut offenders = Vec::new();
+        for entry in std::fs::read_dir(&src).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("rs") || path.ends_with("events.rs") {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).unwrap();
+            for (i, line) in text.lines().enumerate() {
+                let code = line.split("//").next().unwrap_or_default();
+                if code.contains(".emit(") || code.contains(".emit_to(") || code.contains(".emit_filter(") {
+                    offenders.push(format!("{}:{}", path.display(), i + 1));
+                }
+            }
+        }
+        assert!(offenders.is_empty(), "emit through events::emit_typed instead: {:?}", offenders);
+    }
+}
@@ -0,0 +1,130 @@
+// EXPLAIN QUERY PLAN for the app's own queries, to catch missing-index regressions
+// after schema changes. Only queries registered here can be explained, selected by
+// id, so the command can never be used to run arbitrary SQL. Developer mode only.
+
+use crate::{consistency, db, settings};
+use rusqlite::types::Value as SqlValue;
+use rusqlite::params_from_iter;
+use serde::Serialize;
+use serde_json::Value;
+
+const KNOWN_QUERIES: &[(&str, &str)] = &[
+    ("list_sessions", "SELECT * FROM sessions ORDER BY created_at DESC"),
+    ("list_tasks", "SELECT * FROM tasks ORDER BY created_at DESC"),
+    (
+        "list_session_tasks",
+        "SELECT * FROM tasks WHERE session_id = ?1 ORDER BY task_index ASC",
+    ),
+    (
+        "list_task_messages",
+        "SELECT * FROM messages WHERE task_id = ?1 ORDER BY created_at ASC",
+    ),
+    (
+        "list_task_files",
+        "SELECT * FROM files WHERE task_id = ?1 ORDER BY created_at ASC",
+    ),
+    ("list_files", "SELECT * FROM files ORDER BY created_at DESC"),
+    ("orphaned_messages", consistency::ORPHANED_MESSAGES),
+    ("orphaned_files", consistency::ORPHANED_FILES),
+    (
+        "tool_invocations_range",
+        "SELECT tool_name, task_id, latency_ms, is_error, completed_at IS NULL
+         FROM tool_invocations
+         WHERE tool_name IS NOT NULL AND called_at >= ?1 AND called_at < ?2",
+    ),
+    (
+        "messages_by_tool_use_id",
+        "SELECT id FROM messages WHERE tool_use_id = ?1",
+    ),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanRow {
+    pub id: i64,
+    pub parent: i64,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KnownQuery {
+    pub id: &'static str,
+    pub sql: &'static str,
+}
+
+fn to_sql_value(value: Value) -> Result<SqlValue, String> {
+    Ok(match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(b as i64),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlValue::Text(s),
+        other => return Err(format!("Unsupported parameter value: {}", other)),
+    })
+}
+
+fn require_developer_mode(conn: &rusqlite::Connection) -> Result<(), String> {
+    if settings::developer_mode(conn) {
+        Ok(())
+    } else {
+        Err("Query plans are only available in developer mode".to_string())
+    }
+}
+
+/// Ids and SQL of the queries `explain_query` accepts
+#[tauri::command]
+pub async fn list_explainable_queries(app: tauri::AppHandle) -> Result<Vec<KnownQuery>, String> {
+    db::with_conn(&app, |conn| {
+        require_developer_mode(conn)?;
+        Ok(KNOWN_QUERIES
+            .iter()
+            .map(|&(id, sql)| KnownQuery { id, sql })
+            .collect())
+    })
+    .await
+}
+
+/// Run EXPLAIN QUERY PLAN for one of the registered queries
+#[tauri::command]
+pub async fn explain_query(
+    app: tauri::AppHandle,
+    query_id: String,
+    params: Vec<Value>,
+) -> Result<Vec<PlanRow>, String> {
+    let sql = KNOWN_QUERIES
+        .iter()
+        .find(|(id, _)| *id == query_id)
+        .map(|(_, sql)| *sql)
+        .ok_or_else(|| format!("Unknown query id '{}'", query_id))?;
+    let values = params
+        .into_iter()
+        .map(to_sql_value)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    db::with_conn(&app, move |conn| {
+        require_developer_mode(conn)?;
+        let mut stmt = conn
+            .prepare(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .map_err(|e| e.to_string())?;
+        if stmt.parameter_count() != values.len() {
+            return Err(format!(
+                "Query '{}' takes {} parameters, got {}",
+                query_id,
+                stmt.parameter_count(),
+                values.len()
+            ));
+        }
+        let rows = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok(PlanRow {
+                    id: row.get(0)?,
+                    parent: row.get(1)?,
+                    detail: row.get(3)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    })
+    .await
+}
@@ -0,0 +1,704 @@
+// Exports of whole histories: one Markdown or JSON file per task, or the whole
+// database as a single JSONL file. Per-task files list the session files linked to
+// the task; the JSONL export carries the working sets with their content.
+//
+// Tasks and their messages are read row by row and written straight to disk, so
+// memory use doesn't grow with the size of the history. Each per-task file is written
+// to a temporary name and renamed into place, so a failed task never leaves a partial
+// file. The JSONL export runs as a job that checkpoints every `LINES_PER_UNIT` lines
+// and can be resumed after an interruption (see `checkpoint`). Both kinds of export
+// are listed in a signed manifest (see `export_manifest`).
+
+use crate::checkpoint::{CheckpointWriter, ExportJournal};
+use crate::message_types::MessageType;
+use crate::task_notes::{self, TaskNote};
+use crate::tasks::TaskQuery;
+use crate::{db, export_manifest, jobs};
+use rusqlite::{params_from_iter, Connection, Row};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const MAX_FILE_STEM: usize = 120;
+
+pub(crate) const EXPORT_ALL_KIND: &str = "export_all";
+/// JSONL lines between checkpoints in `export_all`
+const LINES_PER_UNIT: u64 = 1000;
+
+// Column 3 is the full prompt, reassembled from `prompts::FULL_PROMPT_SQL`
+const TASK_SELECT: &str = "SELECT id, session_id, task_index,
+                                  COALESCE((SELECT content FROM attachments
+                                            WHERE id = tasks.prompt_overflow_attachment_id), prompt),
+                                  status, cost, duration, created_at, updated_at
+                           FROM tasks";
+const MESSAGE_SELECT: &str = "SELECT id, type, content, tool_name, tool_input, tool_output, tool_use_id,
+                                     subtype, error_message, attachments, created_at
+                              FROM messages WHERE task_id = ?1 ORDER BY id";
+const SESSION_FILE_SELECT: &str = "SELECT s.session_id, s.position, s.always_include, a.id, a.name, a.mime_type,
+                                          a.encoding, a.size, a.content
+                                   FROM session_files s JOIN attachments a ON a.id = s.attachment_id
+                                   ORDER BY s.session_id, s.position";
+// Working-set files linked to one task; the content stays in `export_all`
+const TASK_FILE_SELECT: &str = "SELECT a.id, a.name, a.mime_type, a.size
+                                FROM task_attachments t JOIN attachments a ON a.id = t.attachment_id
+                                WHERE t.task_id = ?1 ORDER BY t.position";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    fn parse(format: &str) -> Result<Self, String> {
+        match format {
+            "md" => Ok(ExportFormat::Markdown),
+            "json" => Ok(ExportFormat::Json),
+            other => Err(format!("Invalid export format '{}': expected 'md' or 'json'", other)),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportFailure {
+    pub task_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BulkExportReport {
+    pub dest_dir: String,
+    pub format: String,
+    pub exported: u64,
+    pub failed: u64,
+    pub bytes_written: u64,
+    pub failures: Vec<ExportFailure>,
+    /// `None` when the manifest couldn't be written
+    pub manifest_path: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TaskRow {
+    id: String,
+    session_id: Option<String>,
+    task_index: Option<i64>,
+    prompt: String,
+    status: String,
+    cost: Option<f64>,
+    duration: Option<i64>,
+    created_at: String,
+    updated_at: String,
+}
+
+#[derive(Serialize)]
+struct MessageRow {
+    id: i64,
+    #[serde(rename = "type")]
+    kind: MessageType,
+    content: Option<String>,
+    tool_name: Option<String>,
+    tool_input: Option<String>,
+    tool_output: Option<String>,
+    tool_use_id: Option<String>,
+    subtype: Option<String>,
+    error_message: Option<String>,
+    attachments: Option<String>,
+    created_at: String,
+}
+
+#[derive(Serialize)]
+struct SessionFileRow {
+    session_id: String,
+    position: i64,
+    always_include: bool,
+    attachment_id: i64,
+    name: Option<String>,
+    mime_type: Option<String>,
+    encoding: String,
+    size: i64,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct TaskFileRow {
+    attachment_id: i64,
+    name: Option<String>,
+    mime_type: Option<String>,
+    size: i64,
+}
+
+impl TaskRow {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(TaskRow {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            task_index: row.get(2)?,
+            prompt: row.get(3)?,
+            status: row.get(4)?,
+            cost: row.get(5)?,
+            duration: row.get(6)?,
+            created_at: row.get(7)?,
+            updated_at: row.get(8)?,
+        })
+    }
+}
+
+impl MessageRow {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(MessageRow {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            content: row.get(2)?,
+            tool_name: row.get(3)?,
+            tool_input: row.get(4)?,
+            tool_output: row.get(5)?,
+            tool_use_id: row.get(6)?,
+            subtype: row.get(7)?,
+            error_message: row.get(8)?,
+            attachments: row.get(9)?,
+            created_at: row.get(10)?,
+        })
+    }
+}
+
+impl SessionFileRow {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(SessionFileRow {
+            session_id: row.get(0)?,
+            position: row.get(1)?,
+            always_include: row.get(2)?,
+            attachment_id: row.get(3)?,
+            name: row.get(4)?,
+            mime_type: row.get(5)?,
+            encoding: row.get(6)?,
+            size: row.get(7)?,
+            content: row.get(8)?,
+        })
+    }
+}
+
+fn task_files(conn: &Connection, task_id: &str) -> Result<Vec<TaskFileRow>, String> {
+    let mut stmt = conn.prepare_cached(TASK_FILE_SELECT).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([task_id], |row| {
+            Ok(TaskFileRow {
+                attachment_id: row.get(0)?,
+                name: row.get(1)?,
+                mime_type: row.get(2)?,
+                size: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Filesystem-safe stem for a task id, unique within this export (case-insensitively,
+/// for macOS and Windows)
+fn file_stem(task_id: &str, used: &mut HashSet<String>) -> String {
+    let mut base: String = task_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .take(MAX_FILE_STEM)
+        .collect();
+    base = base.trim_matches('.').to_string();
+    if base.is_empty() {
+        base = "task".to_string();
+    }
+    let mut stem = base.clone();
+    let mut n = 2;
+    while !used.insert(stem.to_lowercase()) {
+        stem = format!("{}-{}", base, n);
+        n += 1;
+    }
+    stem
+}
+
+fn fence(text: &str) -> &'static str {
+    if text.contains("```") {
+        "~~~~"
+    } else {
+        "```"
+    }
+}
+
+fn write_markdown_header(out: &mut impl Write, task: &TaskRow) -> std::io::Result<()> {
+    writeln!(out, "# {}\n", task.prompt.lines().next().unwrap_or_default())?;
+    writeln!(out, "- Task: `{}`", task.id)?;
+    if let Some(session_id) = &task.session_id {
+        writeln!(out, "- Session: `{}`", session_id)?;
+    }
+    writeln!(out, "- Status: {}", task.status)?;
+    writeln!(out, "- Created: {}", task.created_at)?;
+    if let Some(cost) = task.cost {
+        writeln!(out, "- Cost: ${:.4}", cost)?;
+    }
+    writeln!(out, "\n## Prompt\n\n{}\n", task.prompt)
+}
+
+fn write_markdown_files(out: &mut impl Write, files: &[TaskFileRow]) -> std::io::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "## Session files\n")?;
+    for file in files {
+        let name = file.name.as_deref().unwrap_or("(unnamed)");
+        writeln!(out, "- {} ({} bytes, attachment {})", name, file.size, file.attachment_id)?;
+    }
+    writeln!(out)
+}
+
+/// The task's note, set apart from the conversation it isn't part of
+fn write_markdown_notes(out: &mut impl Write, note: Option<&TaskNote>) -> std::io::Result<()> {
+    let Some(note) = note.filter(|n| !n.content.trim().is_empty()) else {
+        return Ok(());
+    };
+    writeln!(
+        out,
+        "## Reviewer notes\n\n_Kept by people on this task; not shown to the agent. Updated {}._\n",
+        note.updated_at
+    )?;
+    writeln!(out, "{}\n", note.content.trim_end())
+}
+
+fn write_markdown_message(out: &mut impl Write, m: &MessageRow) -> std::io::Result<()> {
+    let heading = match &m.kind {
+        MessageType::User => "User".to_string(),
+        MessageType::Text => "Assistant".to_string(),
+        MessageType::ToolUse => format!("Tool call: {}", m.tool_name.as_deref().unwrap_or("unknown")),
+        MessageType::ToolResult => "Tool result".to_string(),
+        MessageType::Result => "Result".to_string(),
+        MessageType::Error => "Error".to_string(),
+        MessageType::Plan => "Plan".to_string(),
+        MessageType::PermissionRequest => "Permission request".to_string(),
+        MessageType::Other(other) => other.clone(),
+    };
+    writeln!(out, "## {}\n\n_{}_\n", heading, m.created_at)?;
+    if let Some(content) = m.content.as_deref().filter(|c| !c.is_empty()) {
+        writeln!(out, "{}\n", content)?;
+    }
+    for (label, body) in [("json", &m.tool_input), ("", &m.tool_output)] {
+        if let Some(body) = body.as_deref().filter(|b| !b.is_empty()) {
+            let fence = fence(body);
+            writeln!(out, "{}{}\n{}\n{}\n", fence, label, body, fence)?;
+        }
+    }
+    if let Some(error) = &m.error_message {
+        writeln!(out, "> {}\n", error)?;
+    }
+    Ok(())
+}
+
+/// Write one task's file; returns the bytes written
+fn export_task(
+    conn: &Connection,
+    task: &TaskRow,
+    path: &Path,
+    format: ExportFormat,
+    include_notes: bool,
+) -> Result<u64, String> {
+    let tmp = path.with_extension(format!("{}.partial", format.extension()));
+    let result = (|| -> Result<u64, String> {
+        let file = File::create(&tmp).map_err(|e| e.to_string())?;
+        let mut out = BufWriter::new(file);
+        let mut stmt = conn.prepare(MESSAGE_SELECT).map_err(|e| e.to_string())?;
+        let mut rows = stmt.query([&task.id]).map_err(|e| e.to_string())?;
+        let files = task_files(conn, &task.id)?;
+        let note = if include_notes {
+            task_notes::load(conn, &task.id).map_err(|e| e.to_string())?
+        } else {
+            None
+        };
+
+        match format {
+            ExportFormat::Markdown => {
+                write_markdown_header(&mut out, task).map_err(|e| e.to_string())?;
+                write_markdown_files(&mut out, &files).map_err(|e| e.to_string())?;
+                write_markdown_notes(&mut out, note.as_ref()).map_err(|e| e.to_string())?;
+            }
+            ExportFormat::Json => {
+                let exported_at = serde_json::to_string(&chrono::Utc::now().to_rfc3339()).map_err(|e| e.to_string())?;
+                write!(out, "{{\"exported_at\":{},\"task\":", exported_at).map_err(|e| e.to_string())?;
+                serde_json::to_writer(&mut out, task).map_err(|e| e.to_string())?;
+                write!(out, ",\"session_files\":").map_err(|e| e.to_string())?;
+                serde_json::to_writer(&mut out, &files).map_err(|e| e.to_string())?;
+                if include_notes {
+                    write!(out, ",\"reviewer_notes\":").map_err(|e| e.to_string())?;
+                    serde_json::to_writer(&mut out, &note).map_err(|e| e.to_string())?;
+                }
+                write!(out, ",\"messages\":[").map_err(|e| e.to_string())?;
+            }
+        }
+        let mut first = true;
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let message = MessageRow::from_row(row).map_err(|e| e.to_string())?;
+            match format {
+                ExportFormat::Markdown => write_markdown_message(&mut out, &message).map_err(|e| e.to_string())?,
+                ExportFormat::Json => {
+                    if !first {
+                        write!(out, ",").map_err(|e| e.to_string())?;
+                    }
+                    serde_json::to_writer(&mut out, &message).map_err(|e| e.to_string())?;
+                }
+            }
+            first = false;
+        }
+        if format == ExportFormat::Json {
+            write!(out, "]}}").map_err(|e| e.to_string())?;
+        }
+        let file = out.into_inner().map_err(|e| e.to_string())?;
+        file.sync_all().map_err(|e| e.to_string())?;
+        Ok(file.metadata().map(|m| m.len()).unwrap_or_default())
+    })();
+    match result {
+        Ok(bytes) => {
+            std::fs::rename(&tmp, path).map_err(|e| e.to_string())?;
+            Ok(bytes)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Write `<dest_dir>/<task_id>.<md|json>` for every task matching `query`. Failures
+/// are collected per task instead of stopping the run. Re-exporting into the same
+/// directory replaces the earlier files. `include_notes` adds each task's reviewer
+/// notes.
+#[tauri::command]
+pub async fn bulk_export_tasks(
+    app: tauri::AppHandle,
+    dest_dir: String,
+    query: TaskQuery,
+    format: String,
+    include_notes: Option<bool>,
+) -> Result<BulkExportReport, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        export_tasks(&handle, conn, Path::new(&dest_dir), &query, &format, include_notes.unwrap_or(false))
+    })
+    .await
+}
+
+/// The body of `bulk_export_tasks`, writing into `dir`
+pub(crate) fn export_tasks(
+    app: &tauri::AppHandle,
+    conn: &Connection,
+    dir: &Path,
+    query: &TaskQuery,
+    format: &str,
+    include_notes: bool,
+) -> Result<BulkExportReport, String> {
+    let export_format = ExportFormat::parse(format)?;
+    std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create '{}': {}", dir.display(), e))?;
+    let (filter, params) = query.filter();
+    let sql = format!("{} WHERE {} ORDER BY {}", TASK_SELECT, filter, query.sort.order_by());
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let mut rows = stmt.query(params_from_iter(params)).map_err(|e| e.to_string())?;
+
+    let mut report = BulkExportReport {
+        dest_dir: dir.to_string_lossy().into_owned(),
+        format: format.to_string(),
+        exported: 0,
+        failed: 0,
+        bytes_written: 0,
+        failures: Vec::new(),
+        manifest_path: None,
+    };
+    let mut used = HashSet::new();
+    let mut written = Vec::new();
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let task = match TaskRow::from_row(row) {
+            Ok(task) => task,
+            Err(e) => {
+                let task_id = row.get::<_, String>(0).unwrap_or_default();
+                report.failed += 1;
+                report.failures.push(ExportFailure { task_id, error: e.to_string() });
+                continue;
+            }
+        };
+        let stem = file_stem(&task.id, &mut used);
+        let path = dir.join(format!("{}.{}", stem, export_format.extension()));
+        match export_task(conn, &task, &path, export_format, include_notes) {
+            Ok(bytes) => {
+                report.exported += 1;
+                report.bytes_written += bytes;
+                written.push(path);
+            }
+            Err(error) => {
+                report.failed += 1;
+                report.failures.push(ExportFailure { task_id: task.id, error });
+            }
+        }
+    }
+    match export_manifest::write_signed(app, conn, dir, "bulk_export_tasks", &written) {
+        Ok(path) => report.manifest_path = Some(path.to_string_lossy().into_owned()),
+        Err(e) => eprintln!("[Export] No manifest for {}: {}", dir.display(), e),
+    }
+    println!(
+        "[Export] Exported {} tasks to {} ({} failed)",
+        report.exported, report.dest_dir, report.failed
+    );
+    Ok(report)
+}
+
+/// One line of the `export_all` JSONL file
+#[derive(Serialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum ExportLine<'a> {
+    Header {
+        format: &'static str,
+        version: u32,
+        exported_at: &'a str,
+    },
+    Task(&'a TaskRow),
+    Message {
+        task_id: &'a str,
+        #[serde(flatten)]
+        message: &'a MessageRow,
+    },
+    /// Working-set files, after all tasks, with their content
+    SessionFile(&'a SessionFileRow),
+}
+
+/// Writes JSONL lines, skipping the ones a resumed export already has and
+/// checkpointing every `LINES_PER_UNIT` lines
+struct LineSink<'a, F: FnMut(u64) -> bool> {
+    writer: &'a mut CheckpointWriter,
+    skip: u64,
+    line: u64,
+    /// Called after each checkpoint with the lines written; returns true to stop
+    on_checkpoint: F,
+}
+
+impl<F: FnMut(u64) -> bool> LineSink<'_, F> {
+    fn push(&mut self, line: &ExportLine) -> Result<(), String> {
+        if self.line >= self.skip {
+            serde_json::to_writer(&mut *self.writer, line).map_err(|e| e.to_string())?;
+            self.writer.write_all(b"\n").map_err(|e| e.to_string())?;
+        }
+        self.line += 1;
+        if self.line > self.skip && self.line % LINES_PER_UNIT == 0 {
+            self.writer.checkpoint(self.line / LINES_PER_UNIT)?;
+            if (self.on_checkpoint)(self.line) {
+                return Err("Cancelled".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write every task and message as JSONL, continuing after the writer's last
+/// checkpoint. Returns the number of lines in the output.
+pub(crate) fn write_export_all(
+    conn: &Connection,
+    writer: &mut CheckpointWriter,
+    on_checkpoint: impl FnMut(u64) -> bool,
+) -> Result<u64, String> {
+    let exported_at = writer.params()["exported_at"].as_str().unwrap_or_default().to_string();
+    let skip = writer.units_done() * LINES_PER_UNIT;
+    let mut sink = LineSink {
+        writer,
+        skip,
+        line: 0,
+        on_checkpoint,
+    };
+    sink.push(&ExportLine::Header {
+        format: "workany-export",
+        version: 2,
+        exported_at: &exported_at,
+    })?;
+
+    let mut tasks = conn
+        .prepare(&format!("{} ORDER BY created_at, id", TASK_SELECT))
+        .map_err(|e| e.to_string())?;
+    let mut messages = conn.prepare(MESSAGE_SELECT).map_err(|e| e.to_string())?;
+    let mut task_rows = tasks.query([]).map_err(|e| e.to_string())?;
+    while let Some(row) = task_rows.next().map_err(|e| e.to_string())? {
+        let task = TaskRow::from_row(row).map_err(|e| e.to_string())?;
+        sink.push(&ExportLine::Task(&task))?;
+        let mut message_rows = messages.query([&task.id]).map_err(|e| e.to_string())?;
+        while let Some(row) = message_rows.next().map_err(|e| e.to_string())? {
+            let message = MessageRow::from_row(row).map_err(|e| e.to_string())?;
+            sink.push(&ExportLine::Message {
+                task_id: &task.id,
+                message: &message,
+            })?;
+        }
+    }
+
+    let mut files = conn.prepare(SESSION_FILE_SELECT).map_err(|e| e.to_string())?;
+    let mut file_rows = files.query([]).map_err(|e| e.to_string())?;
+    while let Some(row) = file_rows.next().map_err(|e| e.to_string())? {
+        let file = SessionFileRow::from_row(row).map_err(|e| e.to_string())?;
+        sink.push(&ExportLine::SessionFile(&file))?;
+    }
+    Ok(sink.line)
+}
+
+/// Run `export_all` as a job, resuming from `journal` when given
+pub(crate) fn spawn_export_all(app: &tauri::AppHandle, output: PathBuf, journal: Option<ExportJournal>) -> u64 {
+    jobs::spawn(app, EXPORT_ALL_KIND, move |ctx| {
+        let conn = db::open(ctx.app())?;
+        let params = json!({ "exported_at": chrono::Utc::now().to_rfc3339() });
+        let mut writer = CheckpointWriter::open(EXPORT_ALL_KIND, params, &output, journal)?;
+        ctx.set_journal(writer.journal_path().to_path_buf());
+
+        let total: i64 = conn
+            .query_row(
+                "SELECT 1 + (SELECT COUNT(*) FROM tasks)
+                          + (SELECT COUNT(*) FROM messages WHERE task_id IN (SELECT id FROM tasks))
+                          + (SELECT COUNT(*) FROM session_files)",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        let resumed_from = writer.units_done() * LINES_PER_UNIT;
+        ctx.progress(resumed_from, total as u64, None);
+        let lines = write_export_all(&conn, &mut writer, |lines| {
+            ctx.progress(lines, total as u64, None);
+            ctx.is_cancelled()
+        })?;
+        let bytes = writer.finish()?;
+        println!("[Export] Wrote {} lines to {}", lines, output.display());
+        let files = [output.clone()];
+        let manifest = export_manifest::write_signed(ctx.app(), &conn, &output, EXPORT_ALL_KIND, &files)
+            .map_err(|e| eprintln!("[Export] No manifest for {}: {}", output.display(), e))
+            .ok();
+        Ok(json!({
+            "path": output,
+            "lines": lines,
+            "bytes": bytes,
+            "resumed_from_line": resumed_from,
+            "manifest": manifest,
+        }))
+    })
+}
+
+/// Export every task and message to one JSONL file as a background job. An
+/// interrupted or cancelled export keeps its journal and can be continued with
+/// `resume_job`.
+#[tauri::command]
+pub fn export_all(app: tauri::AppHandle, dest_path: String) -> Result<u64, String> {
+    let output = PathBuf::from(&dest_path);
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Cannot create '{}': {}", parent.display(), e))?;
+    }
+    Ok(spawn_export_all(&app, output, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint::{journal_path, partial_path};
+    use rusqlite::params;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workany-export-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn seeded_db() -> Connection {
+        let conn = crate::migrations::test_db();
+        for t in 0..5 {
+            let task_id = format!("task-{}", t);
+            conn.execute(
+                "INSERT INTO tasks (id, prompt, status, created_at) VALUES (?1, ?2, 'completed', ?3)",
+                params![task_id, format!("Prompt {}", t), format!("2024-01-0{} 00:00:00", t + 1)],
+            )
+            .unwrap();
+            for m in 0..700 {
+                conn.execute(
+                    "INSERT INTO messages (task_id, type, content, created_at) VALUES (?1, 'text', ?2, '2024-01-01 00:00:00')",
+                    params![task_id, format!("message {} of {}", m, task_id)],
+                )
+                .unwrap();
+            }
+        }
+        conn
+    }
+
+    fn params() -> serde_json::Value {
+        json!({ "exported_at": "2024-02-01T00:00:00+00:00" })
+    }
+
+    fn uninterrupted(conn: &Connection, dir: &Path) -> Vec<u8> {
+        let output = dir.join("full.jsonl");
+        let mut writer = CheckpointWriter::create(EXPORT_ALL_KIND, params(), &output).unwrap();
+        write_export_all(conn, &mut writer, |_| false).unwrap();
+        writer.finish().unwrap();
+        assert!(!journal_path(&output).exists());
+        std::fs::read(output).unwrap()
+    }
+
+    /// Stop after `checkpoints` checkpoints and leave some torn output behind, as if
+    /// the process died between checkpoints
+    fn interrupt(conn: &Connection, output: &Path, checkpoints: u64) {
+        let mut writer = CheckpointWriter::create(EXPORT_ALL_KIND, params(), output).unwrap();
+        let mut seen = 0;
+        let result = write_export_all(conn, &mut writer, |_| {
+            seen += 1;
+            seen == checkpoints
+        });
+        assert_eq!(result, Err("Cancelled".to_string()));
+        writer.write_all(b"{\"record\":\"message\",\"task_id\":\"tas").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+    }
+
+    #[test]
+    fn resumed_export_matches_uninterrupted_run() {
+        let conn = seeded_db();
+        let dir = temp_dir("resume");
+        let expected = uninterrupted(&conn, &dir);
+
+        let output = dir.join("resumed.jsonl");
+        interrupt(&conn, &output, 2);
+        assert!(!output.exists());
+
+        let journal = ExportJournal::load(&journal_path(&output)).unwrap();
+        assert_eq!(journal.units_done, 2);
+        let mut writer = CheckpointWriter::open(EXPORT_ALL_KIND, json!({}), &output, Some(journal)).unwrap();
+        assert_eq!(writer.units_done(), 2);
+        write_export_all(&conn, &mut writer, |_| false).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(std::fs::read(&output).unwrap(), expected);
+        assert!(!journal_path(&output).exists());
+        assert!(!partial_path(&output).exists());
+    }
+
+    #[test]
+    fn corrupted_partial_output_restarts() {
+        let conn = seeded_db();
+        let dir = temp_dir("corrupt");
+        let expected = uninterrupted(&conn, &dir);
+
+        let output = dir.join("corrupt.jsonl");
+        interrupt(&conn, &output, 3);
+        let mut partial = std::fs::read(partial_path(&output)).unwrap();
+        partial[10] ^= 0xff;
+        std::fs::write(partial_path(&output), partial).unwrap();
+
+        let journal = ExportJournal::load(&journal_path(&output)).unwrap();
+        assert!(CheckpointWriter::resume(journal.clone()).is_err());
+        let mut writer = CheckpointWriter::open(EXPORT_ALL_KIND, json!({}), &output, Some(journal)).unwrap();
+        assert_eq!(writer.units_done(), 0);
+        write_export_all(&conn, &mut writer, |_| false).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(std::fs::read(&output).unwrap(), expected);
+    }
+}
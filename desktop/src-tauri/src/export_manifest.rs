@@ -0,0 +1,558 @@
+// Signed manifests for exports, so a recipient can tell whether an export was changed
+// after it left the app.
+//
+// Every exporter lists the files it wrote in `manifest.json` (path, sha256, size) and
+// signs the manifest's exact bytes with this installation's Ed25519 key; the signature
+// goes in `manifest.sig` beside it. Directory exports carry both files inside the
+// directory; single-file exports get `<file>.manifest.json` and `<file>.manifest.sig`
+// next to the file.
+//
+// The private key lives in the secrets backend (keychain or encrypted file). Public
+// keys are kept in settings with their ids, including rotated ones, so exports signed
+// before a rotation still verify. `verify_export` checks contents and signature;
+// imports run the same check and only refuse on failure when
+// `strict_export_verification` is on.
+
+use crate::{db, secrets, settings};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const SIGNATURE_FILE: &str = "manifest.sig";
+const MANIFEST_FORMAT: &str = "workany-manifest";
+const MANIFEST_VERSION: u32 = 1;
+const SECRET_KEY: &str = "export_signing_key";
+const KEYS_KEY: &str = "export_signing_keys";
+pub(crate) const STRICT_KEY: &str = "strict_export_verification";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Relative to the export, `/`-separated
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    /// Which exporter wrote it, e.g. `export_all`
+    pub kind: String,
+    pub created_at: String,
+    pub key_id: Option<String>,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DetachedSignature {
+    key_id: String,
+    public_key: String,
+    signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SigningKeyInfo {
+    pub key_id: String,
+    /// Base64 of the 32-byte Ed25519 public key
+    pub public_key: String,
+    pub created_at: String,
+    /// Set once the key has been rotated out; it still verifies old exports
+    pub retired_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportSigningKeys {
+    pub current: SigningKeyInfo,
+    pub previous: Vec<SigningKeyInfo>,
+}
+
+fn key_id(public: &VerifyingKey) -> String {
+    Sha256::digest(public.as_bytes())[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn key_info(public: &VerifyingKey) -> SigningKeyInfo {
+    SigningKeyInfo {
+        key_id: key_id(public),
+        public_key: BASE64.encode(public.as_bytes()),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        retired_at: None,
+    }
+}
+
+fn parse_public_key(encoded: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = BASE64
+        .decode(encoded.trim())
+        .map_err(|e| format!("Invalid public key: {}", e))?
+        .try_into()
+        .map_err(|_| "Invalid public key: expected 32 bytes".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("Invalid public key: {}", e))
+}
+
+fn generate() -> SigningKey {
+    let mut seed = [0u8; 32];
+    OsRng.fill_bytes(&mut seed);
+    SigningKey::from_bytes(&seed)
+}
+
+/// Save a new signing key, retiring the current one
+fn install(app: &AppHandle, conn: &Connection, key: &SigningKey) -> Result<SigningKeyInfo, String> {
+    let encoded = BASE64.encode(key.to_bytes());
+    secrets::with_backend_blocking(app, |backend| backend.set(SECRET_KEY, &encoded))?;
+    let mut keys: Vec<SigningKeyInfo> = settings::get_or(conn, KEYS_KEY, Vec::new())?;
+    let now = chrono::Utc::now().to_rfc3339();
+    for old in keys.iter_mut().filter(|k| k.retired_at.is_none()) {
+        old.retired_at = Some(now.clone());
+    }
+    let info = key_info(&key.verifying_key());
+    keys.push(info.clone());
+    settings::set(conn, KEYS_KEY, &keys)?;
+    Ok(info)
+}
+
+/// This installation's signing key, created on first use
+fn signing_key(app: &AppHandle, conn: &Connection) -> Result<SigningKey, String> {
+    let stored = secrets::with_backend_blocking(app, |backend| backend.get(SECRET_KEY))?;
+    if let Some(encoded) = stored {
+        let bytes: [u8; 32] = BASE64
+            .decode(encoded)
+            .map_err(|e| e.to_string())?
+            .try_into()
+            .map_err(|_| "Stored export signing key is malformed".to_string())?;
+        return Ok(SigningKey::from_bytes(&bytes));
+    }
+    let key = generate();
+    let info = install(app, conn, &key)?;
+    println!("[ExportManifest] Created export signing key {}", info.key_id);
+    Ok(key)
+}
+
+fn hash_file(path: &Path) -> Result<(String, u64), String> {
+    let mut file = File::open(path).map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    let digest = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    Ok((digest, size))
+}
+
+fn relative_name(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Where the manifest and signature of an export live, and the folder its paths are
+/// relative to
+fn locate(export: &Path) -> (PathBuf, PathBuf, PathBuf) {
+    if export.is_dir() {
+        return (export.join(MANIFEST_FILE), export.join(SIGNATURE_FILE), export.to_path_buf());
+    }
+    let name = export.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let base = export.parent().map(Path::to_path_buf).unwrap_or_default();
+    if let Some(stem) = name.strip_suffix(&format!(".{}", MANIFEST_FILE)) {
+        return (export.to_path_buf(), base.join(format!("{}.{}", stem, SIGNATURE_FILE)), base);
+    }
+    if name == MANIFEST_FILE {
+        return (export.to_path_buf(), base.join(SIGNATURE_FILE), base);
+    }
+    (
+        base.join(format!("{}.{}", name, MANIFEST_FILE)),
+        base.join(format!("{}.{}", name, SIGNATURE_FILE)),
+        base,
+    )
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension("partial");
+    std::fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, path).map_err(|e| e.to_string())
+}
+
+/// Write the manifest for `files` of the export at `export` (a directory, or the
+/// single exported file) and sign it. The manifest is written even when signing
+/// fails, so contents can still be checked. Returns the manifest path.
+pub fn write_signed(
+    app: &AppHandle,
+    conn: &Connection,
+    export: &Path,
+    kind: &str,
+    files: &[PathBuf],
+) -> Result<PathBuf, String> {
+    let (manifest_path, signature_path, base) = locate(export);
+    let mut entries = files
+        .iter()
+        .map(|path| {
+            let (sha256, size) = hash_file(path)?;
+            Ok(ManifestEntry {
+                path: relative_name(&base, path),
+                sha256,
+                size,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let key = signing_key(app, conn);
+    let mut manifest = Manifest {
+        format: MANIFEST_FORMAT.to_string(),
+        version: MANIFEST_VERSION,
+        kind: kind.to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        key_id: key.as_ref().ok().map(|k| key_id(&k.verifying_key())),
+        files: entries,
+    };
+    manifest.files.dedup_by(|a, b| a.path == b.path);
+    let mut bytes = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    bytes.push(b'\n');
+    write_atomic(&manifest_path, &bytes)?;
+
+    match key {
+        Ok(key) => {
+            let public = key.verifying_key();
+            let signature = DetachedSignature {
+                key_id: key_id(&public),
+                public_key: BASE64.encode(public.as_bytes()),
+                signature: BASE64.encode(key.sign(&bytes).to_bytes()),
+            };
+            let encoded = serde_json::to_vec_pretty(&signature).map_err(|e| e.to_string())?;
+            write_atomic(&signature_path, &encoded)?;
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&signature_path);
+            eprintln!("[ExportManifest] {} left unsigned: {}", manifest_path.display(), e);
+        }
+    }
+    Ok(manifest_path)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    Valid,
+    Invalid,
+    /// Signed by a key this installation doesn't know and none was supplied
+    UnknownKey,
+    Missing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    Missing,
+    SizeMismatch,
+    HashMismatch,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub path: String,
+    pub problem: Problem,
+    pub expected: String,
+    pub actual: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationReport {
+    pub manifest_path: String,
+    pub kind: String,
+    pub signature: SignatureStatus,
+    pub key_id: Option<String>,
+    /// Whether the key that verified is a rotated-out one
+    pub key_retired: bool,
+    pub files_checked: usize,
+    pub mismatches: Vec<Mismatch>,
+    /// Files in a directory export that the manifest doesn't list
+    pub unlisted: Vec<String>,
+    pub ok: bool,
+}
+
+/// Check an export against its manifest and the manifest against its signature.
+/// `known` are the public keys to trust when no key is supplied.
+pub fn verify(
+    export: &Path,
+    supplied: Option<&VerifyingKey>,
+    known: &[SigningKeyInfo],
+) -> Result<Option<VerificationReport>, String> {
+    let (manifest_path, signature_path, base) = locate(export);
+    if !manifest_path.is_file() {
+        return Ok(None);
+    }
+    let bytes = std::fs::read(&manifest_path).map_err(|e| e.to_string())?;
+    let manifest: Manifest = serde_json::from_slice(&bytes).map_err(|e| format!("Invalid manifest: {}", e))?;
+    if manifest.format != MANIFEST_FORMAT || manifest.version != MANIFEST_VERSION {
+        return Err(format!("Unsupported manifest {} v{}", manifest.format, manifest.version));
+    }
+
+    let detached: Option<DetachedSignature> = std::fs::read(&signature_path)
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok());
+    let mut key_retired = false;
+    let signature = match &detached {
+        None => SignatureStatus::Missing,
+        Some(detached) => {
+            let trusted = match supplied {
+                Some(key) => Some(*key),
+                None => known.iter().find(|k| k.key_id == detached.key_id).and_then(|k| {
+                    key_retired = k.retired_at.is_some();
+                    parse_public_key(&k.public_key).ok()
+                }),
+            };
+            let signature = BASE64
+                .decode(&detached.signature)
+                .ok()
+                .and_then(|raw| Signature::from_slice(&raw).ok());
+            match (trusted, signature) {
+                (None, _) => SignatureStatus::UnknownKey,
+                (Some(key), Some(signature)) if key.verify(&bytes, &signature).is_ok() => SignatureStatus::Valid,
+                _ => SignatureStatus::Invalid,
+            }
+        }
+    };
+
+    let mut mismatches = Vec::new();
+    for entry in &manifest.files {
+        let path = base.join(&entry.path);
+        match hash_file(&path) {
+            Err(_) => mismatches.push(Mismatch {
+                path: entry.path.clone(),
+                problem: Problem::Missing,
+                expected: entry.sha256.clone(),
+                actual: None,
+            }),
+            Ok((_, size)) if size != entry.size => mismatches.push(Mismatch {
+                path: entry.path.clone(),
+                problem: Problem::SizeMismatch,
+                expected: entry.size.to_string(),
+                actual: Some(size.to_string()),
+            }),
+            Ok((sha256, _)) if sha256 != entry.sha256 => mismatches.push(Mismatch {
+                path: entry.path.clone(),
+                problem: Problem::HashMismatch,
+                expected: entry.sha256.clone(),
+                actual: Some(sha256),
+            }),
+            Ok(_) => {}
+        }
+    }
+
+    let mut unlisted = Vec::new();
+    if export.is_dir() {
+        let listed: BTreeSet<&str> = manifest.files.iter().map(|e| e.path.as_str()).collect();
+        let mut stack = vec![base.clone()];
+        while let Some(dir) = stack.pop() {
+            for entry in std::fs::read_dir(&dir).map_err(|e| e.to_string())?.flatten() {
+                let path = entry.path();
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    stack.push(path);
+                    continue;
+                }
+                let name = relative_name(&base, &path);
+                if name != MANIFEST_FILE && name != SIGNATURE_FILE && !listed.contains(name.as_str()) {
+                    unlisted.push(name);
+                }
+            }
+        }
+        unlisted.sort();
+    }
+
+    Ok(Some(VerificationReport {
+        manifest_path: manifest_path.to_string_lossy().into_owned(),
+        kind: manifest.kind,
+        ok: signature == SignatureStatus::Valid && mismatches.is_empty(),
+        signature,
+        key_id: detached.map(|d| d.key_id),
+        key_retired,
+        files_checked: manifest.files.len(),
+        mismatches,
+        unlisted,
+    }))
+}
+
+fn known_keys(conn: &Connection) -> Result<Vec<SigningKeyInfo>, String> {
+    settings::get_or(conn, KEYS_KEY, Vec::new())
+}
+
+/// Verify an export before importing from it. Failures are logged and returned;
+/// they only stop the import when `strict_export_verification` is on. Exports
+/// without a manifest pass.
+pub fn check_import(conn: &Connection, export: &Path) -> Result<Option<VerificationReport>, String> {
+    let report = verify(export, None, &known_keys(conn)?)?;
+    if let Some(report) = report.as_ref().filter(|r| !r.ok) {
+        eprintln!(
+            "[ExportManifest] {} failed verification: signature {:?}, {} mismatched file(s)",
+            report.manifest_path,
+            report.signature,
+            report.mismatches.len()
+        );
+        if settings::get_or(conn, STRICT_KEY, false)? {
+            return Err(format!(
+                "{} failed verification and strict export verification is on",
+                export.display()
+            ));
+        }
+    }
+    Ok(report)
+}
+
+/// The public key exports are signed with, and the rotated-out ones that still
+/// verify older exports
+#[tauri::command]
+pub async fn get_export_signing_key(app: AppHandle) -> Result<ExportSigningKeys, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let current = key_info(&signing_key(&handle, conn)?.verifying_key());
+        let keys = known_keys(conn)?;
+        Ok(ExportSigningKeys {
+            current: keys
+                .iter()
+                .find(|k| k.key_id == current.key_id)
+                .cloned()
+                .unwrap_or(current),
+            previous: keys.into_iter().filter(|k| k.retired_at.is_some()).collect(),
+        })
+    })
+    .await
+}
+
+/// Replace the signing key. Earlier public keys stay known for verification.
+#[tauri::command]
+pub async fn rotate_export_signing_key(app: AppHandle) -> Result<SigningKeyInfo, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let info = install(&handle, conn, &generate())?;
+        println!("[ExportManifest] Rotated export signing key to {}", info.key_id);
+        Ok(info)
+    })
+    .await
+}
+
+/// Check an export (a directory, an exported file or a manifest) against its
+/// manifest and signature. With `public_key` (base64) the signature must be from
+/// that key; otherwise from one of this installation's keys.
+#[tauri::command]
+pub async fn verify_export(
+    app: AppHandle,
+    path: String,
+    public_key: Option<String>,
+) -> Result<VerificationReport, String> {
+    let supplied = public_key.as_deref().map(parse_public_key).transpose()?;
+    db::with_conn(&app, move |conn| {
+        verify(Path::new(&path), supplied.as_ref(), &known_keys(conn)?)?
+            .ok_or_else(|| format!("No manifest found for '{}'", path))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(dir: &Path, key: &SigningKey, files: &[&str]) {
+        let paths: Vec<PathBuf> = files.iter().map(|f| dir.join(f)).collect();
+        let (manifest_path, signature_path, base) = locate(dir);
+        let entries = paths
+            .iter()
+            .map(|p| {
+                let (sha256, size) = hash_file(p).unwrap();
+                ManifestEntry {
+                    path: relative_name(&base, p),
+                    sha256,
+                    size,
+                }
+            })
+            .collect();
+        let manifest = Manifest {
+            format: MANIFEST_FORMAT.into(),
+            version: MANIFEST_VERSION,
+            kind: "test".into(),
+            created_at: String::new(),
+            key_id: Some(key_id(&key.verifying_key())),
+            files: entries,
+        };
+        let bytes = serde_json::to_vec_pretty(&manifest).unwrap();
+        std::fs::write(&manifest_path, &bytes).unwrap();
+        let signature = DetachedSignature {
+            key_id: key_id(&key.verifying_key()),
+            public_key: BASE64.encode(key.verifying_key().as_bytes()),
+            signature: BASE64.encode(key.sign(&bytes).to_bytes()),
+        };
+        std::fs::write(signature_path, serde_json::to_vec(&signature).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn detects_tampering_and_verifies_with_retired_keys() {
+        let dir = std::env::temp_dir().join(format!("workany-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.md"), "alpha").unwrap();
+        std::fs::write(dir.join("sub/b.json"), "{}").unwrap();
+        let old = generate();
+        sign(&dir, &old, &["a.md", "sub/b.json"]);
+
+        let mut retired = key_info(&old.verifying_key());
+        retired.retired_at = Some("2026-01-01T00:00:00Z".into());
+        let current = key_info(&generate().verifying_key());
+        let known = vec![retired, current];
+
+        let report = verify(&dir, None, &known).unwrap().unwrap();
+        assert!(report.ok && report.key_retired);
+        assert_eq!(report.files_checked, 2);
+
+        std::fs::write(dir.join("a.md"), "alphx").unwrap();
+        std::fs::write(dir.join("extra.txt"), "x").unwrap();
+        std::fs::remove_file(dir.join("sub/b.json")).unwrap();
+        let report = verify(&dir, None, &known).unwrap().unwrap();
+        assert!(!report.ok);
+        let problems: Vec<&Problem> = report.mismatches.iter().map(|m| &m.problem).collect();
+        assert_eq!(problems, vec![&Problem::HashMismatch, &Problem::Missing]);
+        assert_eq!(report.unlisted, vec!["extra.txt".to_string()]);
+
+        let stranger = generate().verifying_key();
+        let report = verify(&dir, Some(&stranger), &known).unwrap().unwrap();
+        assert_eq!(report.signature, SignatureStatus::Invalid);
+        assert_eq!(verify(&dir, None, &[]).unwrap().unwrap().signature, SignatureStatus::UnknownKey);
+
+        let mut manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        manifest = manifest.replace("\"test\"", "\"edited\"");
+        std::fs::write(dir.join(MANIFEST_FILE), manifest).unwrap();
+        assert_eq!(verify(&dir, None, &known).unwrap().unwrap().signature, SignatureStatus::Invalid);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn single_file_exports_keep_the_manifest_beside_them() {
+        let dir = std::env::temp_dir().join(format!("workany-manifest-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let export = dir.join("all.jsonl");
+        let (manifest, signature, base) = locate(&export);
+        assert_eq!(manifest, dir.join("all.jsonl.manifest.json"));
+        assert_eq!(signature, dir.join("all.jsonl.manifest.sig"));
+        assert_eq!(base, dir);
+        assert_eq!(locate(&manifest).1, signature);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
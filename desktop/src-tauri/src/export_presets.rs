@@ -0,0 +1,829 @@
+// Export presets: the exports a client gets every time, run for a session in one go.
+//
+// A preset is an ordered list of steps, each one of the session exports (the HTML
+// viewer, a review packet, per-task transcripts, or a zip of the files the session's
+// tasks produced) with its options and an output path template. Templates are
+// absolute paths that may use `{date}` (local, YYYY-MM-DD), `{project}` and
+// `{session}` (the first line of the session prompt). Variable values are made safe
+// for a path component before they are substituted, so a prompt can't add
+// directories or `..`. Templates are checked on save and again with the real values
+// before a run, against the same allowed folders as every other file write.
+//
+// Presets belong to a project (a free-form key chosen by the frontend) or, with no
+// project, are global; a global preset can't use `{project}`. `run_export_preset`
+// runs the steps as one job, reporting progress per step. The first failing step
+// stops the run: what earlier steps wrote stays in place and is listed in the
+// report next to the failure. Every artifact written is recorded in
+// `export_deliveries`, the delivered history.
+
+use crate::review_packet::{self, Scope};
+use crate::tasks::TaskQuery;
+use crate::{db, export, files, jobs, viewer_export};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use tauri::AppHandle;
+
+const RUN_KIND: &str = "export_preset";
+const MAX_NAME_CHARS: usize = 100;
+const MAX_STEPS: usize = 20;
+/// Characters kept of a variable value
+const MAX_VALUE_CHARS: usize = 60;
+const DEFAULT_HISTORY: u32 = 100;
+const MAX_HISTORY: u32 = 1000;
+/// Device names Windows reserves in every directory
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ExportStep {
+    /// The session as a self-contained HTML viewer; `path` is the file
+    Viewer { path: String },
+    /// Printable review packet with its references; `path` is a directory
+    ReviewPacket {
+        path: String,
+        #[serde(default)]
+        include_notes: bool,
+    },
+    /// One Markdown (`md`) or JSON (`json`) file per task; `path` is a directory
+    Transcripts {
+        path: String,
+        format: String,
+        #[serde(default)]
+        include_notes: bool,
+    },
+    /// The files the session's tasks produced, zipped; `path` is the zip file
+    Deliverables { path: String },
+}
+
+impl ExportStep {
+    fn kind(&self) -> &'static str {
+        match self {
+            ExportStep::Viewer { .. } => "viewer",
+            ExportStep::ReviewPacket { .. } => "review_packet",
+            ExportStep::Transcripts { .. } => "transcripts",
+            ExportStep::Deliverables { .. } => "deliverables",
+        }
+    }
+
+    fn path(&self) -> &str {
+        match self {
+            ExportStep::Viewer { path }
+            | ExportStep::ReviewPacket { path, .. }
+            | ExportStep::Transcripts { path, .. }
+            | ExportStep::Deliverables { path } => path,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportPreset {
+    pub id: i64,
+    /// `None` for a global preset
+    pub project_id: Option<String>,
+    pub name: String,
+    pub steps: Vec<ExportStep>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportPresetInput {
+    #[serde(default)]
+    pub project_id: Option<String>,
+    pub name: String,
+    pub steps: Vec<ExportStep>,
+}
+
+/// A file or directory a run wrote
+#[derive(Debug, Clone, Serialize)]
+pub struct Artifact {
+    /// Index of the step that wrote it
+    pub step: usize,
+    pub kind: &'static str,
+    pub path: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StepFailure {
+    pub step: usize,
+    pub kind: &'static str,
+    pub error: String,
+}
+
+/// The result of `run_export_preset`'s job
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryReport {
+    pub run_id: String,
+    pub preset_id: i64,
+    pub preset_name: String,
+    pub session_id: String,
+    /// Every step ran
+    pub completed: bool,
+    pub artifacts: Vec<Artifact>,
+    /// The step that stopped the run; its earlier artifacts are still listed
+    pub failure: Option<StepFailure>,
+}
+
+/// One row of the delivered history
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivery {
+    pub id: i64,
+    pub run_id: String,
+    /// `None` once the preset was deleted
+    pub preset_id: Option<i64>,
+    pub preset_name: String,
+    pub project_id: Option<String>,
+    pub session_id: String,
+    pub step: i64,
+    pub kind: String,
+    pub path: String,
+    pub bytes: i64,
+    pub delivered_at: String,
+}
+
+/// Values for the template variables
+struct TemplateValues {
+    date: String,
+    project: Option<String>,
+    session: String,
+}
+
+impl TemplateValues {
+    /// Stand-ins used to check templates when a preset is saved
+    fn sample(project: Option<&str>) -> Self {
+        Self {
+            date: "2000-01-01".to_string(),
+            project: project.map(str::to_string),
+            session: "session".to_string(),
+        }
+    }
+}
+
+/// `value` as a single path component: separators, characters Windows forbids and
+/// control characters become `-`, surrounding dots and spaces go, and a reserved
+/// device name gets a trailing `_`
+fn sanitize(value: &str) -> String {
+    let replaced: String = value
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let cut: String = replaced
+        .trim_matches(|c: char| c == '.' || c.is_whitespace())
+        .chars()
+        .take(MAX_VALUE_CHARS)
+        .collect();
+    let mut cleaned = cut.trim_end_matches(|c: char| c == '.' || c.is_whitespace()).to_string();
+    if cleaned.is_empty() {
+        cleaned = "untitled".to_string();
+    }
+    let stem = cleaned.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    if RESERVED_NAMES.contains(&stem.as_str()) {
+        cleaned.push('_');
+    }
+    cleaned
+}
+
+/// `template` with its variables replaced by sanitized values
+fn expand(template: &str, values: &TemplateValues) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..open]);
+        if rest[open..].starts_with('}') {
+            return Err(format!("Unmatched '}}' in \"{}\"", template));
+        }
+        let close = open
+            + rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed '{{' in \"{}\"", template))?;
+        let value = match &rest[open + 1..close] {
+            "date" => values.date.as_str(),
+            "session" => values.session.as_str(),
+            "project" => values
+                .project
+                .as_deref()
+                .ok_or_else(|| format!("{{project}} needs a project preset, in \"{}\"", template))?,
+            other => {
+                return Err(format!(
+                    "Unknown variable {{{}}} in \"{}\"; use {{date}}, {{project}} or {{session}}",
+                    other, template
+                ))
+            }
+        };
+        expanded.push_str(&sanitize(value));
+        rest = &rest[close + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// The allowed roots, resolved, to compare resolved targets against
+fn resolved_roots(app: &AppHandle) -> Vec<PathBuf> {
+    files::allowed_roots(app)
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .collect()
+}
+
+/// Resolve `path`, whose parents may not exist yet, and check it sits inside one of
+/// `roots`
+fn check_target(roots: &[PathBuf], path: &Path) -> Result<PathBuf, String> {
+    if !path.is_absolute() {
+        return Err(format!("'{}' is not an absolute path", path.display()));
+    }
+    if path.components().any(|c| matches!(c, Component::ParentDir)) {
+        return Err(format!("Path '{}' must not contain '..'", path.display()));
+    }
+    // Resolve the deepest part that exists, so links there are followed
+    let mut existing = path;
+    let mut missing = Vec::new();
+    while !existing.exists() {
+        missing.push(existing.file_name().ok_or("Path has no file name")?);
+        existing = existing.parent().ok_or("Path has no parent directory")?;
+    }
+    let mut resolved = existing
+        .canonicalize()
+        .map_err(|e| format!("Cannot resolve '{}': {}", existing.display(), e))?;
+    resolved.extend(missing.iter().rev());
+    if roots.iter().any(|root| resolved.starts_with(root)) {
+        Ok(resolved)
+    } else {
+        Err(format!("'{}' is outside the app's allowed folders", path.display()))
+    }
+}
+
+/// Every step's output path for a run with `values`
+fn targets(steps: &[ExportStep], values: &TemplateValues, roots: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    steps
+        .iter()
+        .enumerate()
+        .map(|(index, step)| {
+            let expanded = expand(step.path(), values).map_err(|e| format!("Step {}: {}", index + 1, e))?;
+            check_target(roots, Path::new(&expanded)).map_err(|e| format!("Step {}: {}", index + 1, e))
+        })
+        .collect()
+}
+
+fn validate_input(input: &ExportPresetInput, roots: &[PathBuf]) -> Result<(Option<String>, String), String> {
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err("An export preset needs a name".to_string());
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!("Export preset names are limited to {} characters", MAX_NAME_CHARS));
+    }
+    let project = input.project_id.as_deref().map(str::trim).filter(|p| !p.is_empty());
+    if input.steps.is_empty() {
+        return Err("An export preset needs at least one step".to_string());
+    }
+    if input.steps.len() > MAX_STEPS {
+        return Err(format!("Export presets are limited to {} steps", MAX_STEPS));
+    }
+    for (index, step) in input.steps.iter().enumerate() {
+        if let ExportStep::Transcripts { format, .. } = step {
+            if format != "md" && format != "json" {
+                return Err(format!("Step {}: transcript format must be 'md' or 'json'", index + 1));
+            }
+        }
+    }
+    targets(&input.steps, &TemplateValues::sample(project), roots)?;
+    Ok((project.map(str::to_string), name.to_string()))
+}
+
+fn name_taken(name: &str) -> impl Fn(rusqlite::Error) -> String + '_ {
+    move |e| match e.sqlite_error_code() {
+        Some(ErrorCode::ConstraintViolation) => format!("An export preset named \"{}\" already exists", name),
+        _ => e.to_string(),
+    }
+}
+
+fn load_presets(conn: &Connection, project_id: Option<&str>, id: Option<i64>) -> Result<Vec<ExportPreset>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, project_id, name, steps, created_at, updated_at FROM export_presets
+             WHERE (?1 IS NULL OR id = ?1) AND (?2 IS NULL OR project_id IS NULL OR project_id = ?2)
+             ORDER BY project_id IS NOT NULL DESC, name COLLATE NOCASE, id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![id, project_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    let mut presets = Vec::new();
+    for row in rows {
+        let (id, project_id, name, steps, created_at, updated_at) = row.map_err(|e| e.to_string())?;
+        presets.push(ExportPreset {
+            id,
+            project_id,
+            name,
+            steps: serde_json::from_str(&steps).map_err(|e| format!("Export preset {} is malformed: {}", id, e))?,
+            created_at,
+            updated_at,
+        });
+    }
+    Ok(presets)
+}
+
+fn load_preset(conn: &Connection, id: i64) -> Result<ExportPreset, String> {
+    load_presets(conn, None, Some(id))?
+        .pop()
+        .ok_or_else(|| format!("Export preset {} not found", id))
+}
+
+fn create(conn: &Connection, input: &ExportPresetInput, roots: &[PathBuf]) -> Result<ExportPreset, String> {
+    let (project_id, name) = validate_input(input, roots)?;
+    conn.execute(
+        "INSERT INTO export_presets (project_id, name, steps) VALUES (?1, ?2, ?3)",
+        params![project_id, name, serde_json::to_string(&input.steps).map_err(|e| e.to_string())?],
+    )
+    .map_err(name_taken(&name))?;
+    load_preset(conn, conn.last_insert_rowid())
+}
+
+fn update(conn: &Connection, id: i64, input: &ExportPresetInput, roots: &[PathBuf]) -> Result<ExportPreset, String> {
+    let (project_id, name) = validate_input(input, roots)?;
+    let changed = conn
+        .execute(
+            "UPDATE export_presets SET project_id = ?1, name = ?2, steps = ?3, updated_at = datetime('now')
+             WHERE id = ?4",
+            params![project_id, name, serde_json::to_string(&input.steps).map_err(|e| e.to_string())?, id],
+        )
+        .map_err(name_taken(&name))?;
+    if changed == 0 {
+        return Err(format!("Export preset {} not found", id));
+    }
+    load_preset(conn, id)
+}
+
+/// Zip the files the tasks of `session_id` produced into `target`. A file missing
+/// on disk fails the step rather than delivering an incomplete zip.
+fn zip_deliverables(conn: &Connection, session_id: &str, target: &Path) -> Result<u64, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT f.name, f.path FROM files f JOIN tasks t ON t.id = f.task_id
+             WHERE t.session_id = ?1 AND t.trashed_at IS NULL AND f.ignored_at IS NULL
+             ORDER BY f.id",
+        )
+        .map_err(|e| e.to_string())?;
+    let files: Vec<(String, String)> = stmt
+        .query_map([session_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    if files.is_empty() {
+        return Err("The session has no files to deliver".to_string());
+    }
+    let missing: Vec<&str> = files
+        .iter()
+        .filter(|(_, path)| !Path::new(path).is_file())
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("{} file(s) are missing on disk: {}", missing.len(), missing.join(", ")));
+    }
+
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Cannot create '{}': {}", parent.display(), e))?;
+    }
+    let tmp = target.with_extension("zip.partial");
+    let written = (|| -> io::Result<()> {
+        let mut writer = zip::ZipWriter::new(File::create(&tmp)?);
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let mut used = HashSet::new();
+        for (name, path) in &files {
+            let base = sanitize(name);
+            let mut entry = base.clone();
+            let mut n = 2;
+            while !used.insert(entry.to_lowercase()) {
+                entry = match base.rsplit_once('.') {
+                    Some((stem, ext)) => format!("{} ({}).{}", stem, n, ext),
+                    None => format!("{} ({})", base, n),
+                };
+                n += 1;
+            }
+            writer.start_file(entry, options)?;
+            io::copy(&mut File::open(path)?, &mut writer)?;
+        }
+        writer.finish()?.flush()
+    })();
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(format!("Cannot write '{}': {}", target.display(), e));
+    }
+    std::fs::rename(&tmp, target).map_err(|e| e.to_string())?;
+    std::fs::metadata(target).map(|m| m.len()).map_err(|e| e.to_string())
+}
+
+fn file_artifact(step: usize, kind: &'static str, path: &Path) -> Artifact {
+    Artifact {
+        step,
+        kind,
+        path: path.to_string_lossy().into_owned(),
+        bytes: std::fs::metadata(path).map_or(0, |m| m.len()),
+    }
+}
+
+/// Run one step, returning what it wrote
+fn run_step(
+    app: &AppHandle,
+    conn: &Connection,
+    session_id: &str,
+    index: usize,
+    step: &ExportStep,
+    target: &Path,
+) -> Result<Vec<Artifact>, String> {
+    let kind = step.kind();
+    match step {
+        ExportStep::Viewer { .. } => {
+            let report = viewer_export::export_session(app, conn, session_id, target)?;
+            Ok(vec![Artifact {
+                step: index,
+                kind,
+                path: report.path,
+                bytes: report.bytes,
+            }])
+        }
+        ExportStep::ReviewPacket { include_notes, .. } => {
+            let report = review_packet::export_packet(app, conn, Scope::Session, session_id, target, *include_notes)?;
+            Ok(vec![
+                file_artifact(index, kind, Path::new(&report.html_path)),
+                file_artifact(index, kind, Path::new(&report.references_path)),
+            ])
+        }
+        ExportStep::Transcripts { format, include_notes, .. } => {
+            let query = TaskQuery {
+                session_id: Some(session_id.to_string()),
+                ..TaskQuery::default()
+            };
+            let report = export::export_tasks(app, conn, target, &query, format, *include_notes)?;
+            if report.failed > 0 {
+                let reasons: Vec<String> = report
+                    .failures
+                    .iter()
+                    .map(|f| format!("{}: {}", f.task_id, f.error))
+                    .collect();
+                return Err(format!("{} task(s) could not be exported: {}", report.failed, reasons.join("; ")));
+            }
+            Ok(vec![Artifact {
+                step: index,
+                kind,
+                path: report.dest_dir,
+                bytes: report.bytes_written,
+            }])
+        }
+        ExportStep::Deliverables { .. } => {
+            let bytes = zip_deliverables(conn, session_id, target)?;
+            Ok(vec![Artifact {
+                step: index,
+                kind,
+                path: target.to_string_lossy().into_owned(),
+                bytes,
+            }])
+        }
+    }
+}
+
+fn record_delivery(
+    conn: &Connection,
+    report: &DeliveryReport,
+    project_id: Option<&str>,
+    artifact: &Artifact,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO export_deliveries
+             (run_id, preset_id, preset_name, project_id, session_id, step, kind, path, bytes)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            report.run_id,
+            report.preset_id,
+            report.preset_name,
+            project_id,
+            report.session_id,
+            artifact.step as i64,
+            artifact.kind,
+            artifact.path,
+            artifact.bytes as i64,
+        ],
+    )?;
+    Ok(())
+}
+
+fn run(ctx: &jobs::JobContext, preset: ExportPreset, session_id: String, targets: Vec<PathBuf>) -> DeliveryReport {
+    let mut report = DeliveryReport {
+        run_id: format!("dl-{}", chrono::Utc::now().format("%Y%m%d-%H%M%S%3f")),
+        preset_id: preset.id,
+        preset_name: preset.name.clone(),
+        session_id,
+        completed: false,
+        artifacts: Vec::new(),
+        failure: None,
+    };
+    let total = preset.steps.len() as u64;
+    let conn = match db::open(ctx.app()) {
+        Ok(conn) => conn,
+        Err(error) => {
+            report.failure = preset.steps.first().map(|step| StepFailure {
+                step: 0,
+                kind: step.kind(),
+                error,
+            });
+            return report;
+        }
+    };
+    for (index, (step, target)) in preset.steps.iter().zip(&targets).enumerate() {
+        if ctx.is_cancelled() {
+            report.failure = Some(StepFailure {
+                step: index,
+                kind: step.kind(),
+                error: "Cancelled".to_string(),
+            });
+            return report;
+        }
+        ctx.progress(index as u64, total, Some(format!("{}: {}", step.kind(), target.display())));
+        match run_step(ctx.app(), &conn, &report.session_id, index, step, target) {
+            Ok(artifacts) => {
+                for artifact in artifacts {
+                    if let Err(e) = record_delivery(&conn, &report, preset.project_id.as_deref(), &artifact) {
+                        eprintln!("[ExportPresets] Cannot record delivery of {}: {}", artifact.path, e);
+                    }
+                    report.artifacts.push(artifact);
+                }
+            }
+            Err(error) => {
+                eprintln!(
+                    "[ExportPresets] Preset \"{}\" stopped at step {} ({}): {}",
+                    preset.name,
+                    index + 1,
+                    step.kind(),
+                    error
+                );
+                report.failure = Some(StepFailure {
+                    step: index,
+                    kind: step.kind(),
+                    error,
+                });
+                return report;
+            }
+        }
+    }
+    ctx.progress(total, total, None);
+    report.completed = true;
+    println!(
+        "[ExportPresets] Delivered session {} with \"{}\": {} artifact(s)",
+        report.session_id,
+        preset.name,
+        report.artifacts.len()
+    );
+    report
+}
+
+/// Presets for `project_id` followed by the global ones; every preset without one
+#[tauri::command]
+pub async fn list_export_presets(app: AppHandle, project_id: Option<String>) -> Result<Vec<ExportPreset>, String> {
+    db::with_conn(&app, move |conn| load_presets(conn, project_id.as_deref(), None)).await
+}
+
+#[tauri::command]
+pub async fn create_export_preset(app: AppHandle, preset: ExportPresetInput) -> Result<ExportPreset, String> {
+    let roots = resolved_roots(&app);
+    db::with_conn(&app, move |conn| create(conn, &preset, &roots)).await
+}
+
+#[tauri::command]
+pub async fn update_export_preset(app: AppHandle, id: i64, preset: ExportPresetInput) -> Result<ExportPreset, String> {
+    let roots = resolved_roots(&app);
+    db::with_conn(&app, move |conn| update(conn, id, &preset, &roots)).await
+}
+
+/// Delete a preset; its deliveries stay in the history
+#[tauri::command]
+pub async fn delete_export_preset(app: AppHandle, id: i64) -> Result<bool, String> {
+    db::with_conn(&app, move |conn| {
+        conn.execute("DELETE FROM export_presets WHERE id = ?1", [id])
+            .map(|n| n > 0)
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Run a preset's steps for `session_id` as a job and return its id. The job's
+/// result is a `DeliveryReport`; a failed step ends it with the report, not an error.
+#[tauri::command]
+pub async fn run_export_preset(app: AppHandle, preset_id: i64, session_id: String) -> Result<u64, String> {
+    let roots = resolved_roots(&app);
+    // Resolve the paths now, so a bad preset or session fails the call, not the job
+    let (preset, session_id, targets) = db::with_conn(&app, move |conn| {
+        let preset = load_preset(conn, preset_id)?;
+        let prompt: String = conn
+            .query_row("SELECT prompt FROM sessions WHERE id = ?1", [&session_id], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Session '{}' not found", session_id))?;
+        let values = TemplateValues {
+            date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+            project: preset.project_id.clone(),
+            session: prompt.lines().next().unwrap_or_default().to_string(),
+        };
+        let targets = targets(&preset.steps, &values, &roots)?;
+        Ok((preset, session_id, targets))
+    })
+    .await?;
+    Ok(jobs::spawn(&app, RUN_KIND, move |ctx| {
+        serde_json::to_value(run(ctx, preset, session_id, targets)).map_err(|e| e.to_string())
+    }))
+}
+
+/// Delivered artifacts, newest first, for one session or all of them
+#[tauri::command]
+pub async fn list_deliveries(
+    app: AppHandle,
+    session_id: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<Delivery>, String> {
+    let limit = limit.unwrap_or(DEFAULT_HISTORY).clamp(1, MAX_HISTORY);
+    db::with_conn(&app, move |conn| {
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, run_id, preset_id, preset_name, project_id, session_id, step, kind, path, bytes,
+                        delivered_at
+                 FROM export_deliveries WHERE ?1 IS NULL OR session_id = ?1
+                 ORDER BY id DESC LIMIT ?2",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![session_id, limit], |row| {
+                Ok(Delivery {
+                    id: row.get(0)?,
+                    run_id: row.get(1)?,
+                    preset_id: row.get(2)?,
+                    preset_name: row.get(3)?,
+                    project_id: row.get(4)?,
+                    session_id: row.get(5)?,
+                    step: row.get(6)?,
+                    kind: row.get(7)?,
+                    path: row.get(8)?,
+                    bytes: row.get(9)?,
+                    delivered_at: row.get(10)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workany-presets-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn values() -> TemplateValues {
+        TemplateValues {
+            date: "2026-10-16".to_string(),
+            project: Some("Acme / Corp".to_string()),
+            session: "Quarterly report: draft?".to_string(),
+        }
+    }
+
+    fn input(root: &Path, project: Option<&str>, name: &str) -> ExportPresetInput {
+        ExportPresetInput {
+            project_id: project.map(str::to_string),
+            name: name.to_string(),
+            steps: vec![
+                ExportStep::Viewer {
+                    path: format!("{}/{{project}}/{{date}}/session.html", root.display()),
+                },
+                ExportStep::Deliverables {
+                    path: format!("{}/{{project}}/{{date}}/deliverables.zip", root.display()),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn variables_are_expanded_as_single_safe_components() {
+        let expanded = expand("/out/{project}/{date} - {session}.html", &values()).unwrap();
+        assert_eq!(expanded, "/out/Acme - Corp/2026-10-16 - Quarterly report- draft-.html");
+
+        let hostile = TemplateValues {
+            session: "../../etc/passwd".to_string(),
+            ..values()
+        };
+        assert_eq!(expand("/out/{session}", &hostile).unwrap(), "/out/-..-etc-passwd");
+        assert_eq!(sanitize(" .. "), "untitled");
+        assert_eq!(sanitize("con.txt"), "con.txt_");
+        assert_eq!(sanitize(&"x".repeat(200)).len(), MAX_VALUE_CHARS);
+    }
+
+    #[test]
+    fn malformed_templates_are_rejected() {
+        assert!(expand("/out/{client}", &values()).unwrap_err().contains("Unknown variable {client}"));
+        assert!(expand("/out/{date", &values()).unwrap_err().contains("Unclosed"));
+        assert!(expand("/out/date}", &values()).unwrap_err().contains("Unmatched"));
+        let global = TemplateValues { project: None, ..values() };
+        assert!(expand("/out/{project}", &global).unwrap_err().contains("needs a project preset"));
+    }
+
+    #[test]
+    fn targets_must_stay_inside_the_allowed_folders() {
+        let root = temp_root("targets");
+        let roots = vec![root.clone()];
+        let inside = check_target(&roots, &root.join("new/deeper/file.html")).unwrap();
+        assert_eq!(inside, root.join("new/deeper/file.html"));
+        assert!(check_target(&roots, &root.join("../escape.html")).is_err());
+        assert!(check_target(&roots, Path::new("relative/file.html")).is_err());
+        assert!(check_target(&roots, &std::env::temp_dir().join("elsewhere.html")).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn presets_are_validated_and_named_uniquely_per_project() {
+        let root = temp_root("crud");
+        let roots = vec![root.clone()];
+        let conn = crate::migrations::test_db();
+
+        let acme = create(&conn, &input(&root, Some("acme"), "Deliver"), &roots).unwrap();
+        assert_eq!(acme.steps.len(), 2);
+        // The same name in another project is fine, not twice in one
+        create(&conn, &input(&root, Some("globex"), "Deliver"), &roots).unwrap();
+        let duplicate = create(&conn, &input(&root, Some("acme"), "Deliver"), &roots).unwrap_err();
+        assert!(duplicate.contains("already exists"), "{}", duplicate);
+        // A global preset can't name a project in its paths
+        assert!(create(&conn, &input(&root, None, "Global"), &roots).is_err());
+        let outside = input(Path::new("/definitely/not/allowed"), Some("acme"), "Elsewhere");
+        assert!(create(&conn, &outside, &roots).unwrap_err().contains("allowed folders"));
+
+        let listed: Vec<String> = load_presets(&conn, Some("acme"), None)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.project_id.unwrap_or_default())
+            .collect();
+        assert_eq!(listed, vec!["acme"]);
+        let renamed = update(&conn, acme.id, &input(&root, Some("acme"), "Weekly"), &roots).unwrap();
+        assert_eq!(renamed.name, "Weekly");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn deliverables_are_zipped_with_unique_names_or_fail_when_missing() {
+        let root = temp_root("zip");
+        let conn = crate::migrations::test_db();
+        for (dir, body) in [("a", "first"), ("b", "second")] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+            std::fs::write(root.join(dir).join("report.txt"), body).unwrap();
+        }
+        conn.execute_batch(&format!(
+            "INSERT INTO sessions (id, prompt) VALUES ('s1', 'Session');
+             INSERT INTO tasks (id, prompt, session_id) VALUES ('t1', 'one', 's1');
+             INSERT INTO files (task_id, name, type, path) VALUES
+                 ('t1', 'report.txt', 'document', '{a}'),
+                 ('t1', 'report.txt', 'document', '{b}');",
+            a = root.join("a/report.txt").display(),
+            b = root.join("b/report.txt").display(),
+        ))
+        .unwrap();
+
+        let target = root.join("out/deliverables.zip");
+        assert!(zip_deliverables(&conn, "s1", &target).unwrap() > 0);
+        let mut archive = zip::ZipArchive::new(File::open(&target).unwrap()).unwrap();
+        let mut names: Vec<String> = (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().name().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["report (2).txt", "report.txt"]);
+
+        std::fs::remove_file(root.join("b/report.txt")).unwrap();
+        let error = zip_deliverables(&conn, "s1", &root.join("out/again.zip")).unwrap_err();
+        assert!(error.contains("missing on disk"), "{}", error);
+        assert!(!root.join("out/again.zip").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
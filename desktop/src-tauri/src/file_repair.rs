@@ -0,0 +1,810 @@
+// Finding and repairing mismatches between the files table, message attachments
+// and the disk.
+//
+// `scan_file_integrity` runs as a job and stores a report: every problem found, its
+// category, the actions that apply to it and the one we recommend. Nothing changes
+// until `apply_file_repairs` is called with the user's decisions, given per category
+// or per item. Row changes from one run commit in a single transaction; files and
+// folders go to the system trash afterwards, and only from inside the allowed roots.
+// A row is relinked to a file found elsewhere only if the file's sha256 matches the
+// one recorded for the row.
+//
+// Session workspaces are the `~/.workany/sessions/<session id>` folders the frontend
+// creates for each session.
+
+use crate::workspace_ignore::WorkspaceRules;
+use crate::{db, files, integrity, jobs, media_backfill, thumbnails};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+/// Stop walking the workspaces after this many files; the report is marked truncated
+const MAX_DISK_FILES: usize = 50_000;
+const THUMBNAIL_DIM: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairCategory {
+    /// A files row whose path no longer exists
+    MissingFile,
+    /// Like `MissingFile`, for a row that still carries a thumbnail of the lost file
+    ThumbnailWithoutSource,
+    /// A message attachment whose path no longer exists
+    MissingAttachment,
+    /// The file exists but no longer matches the recorded sha256
+    HashMismatch,
+    /// A file in a session workspace that no row or attachment refers to
+    UntrackedFile,
+    /// A session workspace whose session and tasks have been deleted
+    OrphanWorkspace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairAction {
+    Ignore,
+    /// Point the row at a matching file found elsewhere in the workspaces
+    Relink,
+    /// Recompute the hash and thumbnail, or drop a thumbnail whose source is gone
+    Regenerate,
+    /// Delete the files row, or remove the entry from the message's attachments
+    DeleteRow,
+    /// Move the file or workspace folder to the system trash
+    TrashFile,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairItem {
+    pub id: usize,
+    pub category: RepairCategory,
+    pub path: String,
+    pub file_id: Option<i64>,
+    pub message_id: Option<i64>,
+    /// Position in the message's attachment array
+    pub attachment_index: Option<usize>,
+    /// Files elsewhere in the workspaces with this row's name and content
+    #[serde(default)]
+    pub relink_candidates: Vec<String>,
+    pub recommended: RepairAction,
+    pub actions: Vec<RepairAction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairResult {
+    pub item_id: usize,
+    pub action: RepairAction,
+    pub ok: bool,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RepairReport {
+    pub id: i64,
+    pub created_at: String,
+    pub items: Vec<RepairItem>,
+    /// The workspace walk stopped at `MAX_DISK_FILES`
+    pub truncated: bool,
+    pub applied_at: Option<String>,
+    pub results: Option<Vec<RepairResult>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RepairReportSummary {
+    pub id: i64,
+    pub created_at: String,
+    pub item_count: usize,
+    pub applied_at: Option<String>,
+}
+
+/// The action for every item in `category`, or for the item `item_id` alone. Item
+/// decisions win over category ones; items with neither are left alone.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RepairDecision {
+    pub category: Option<RepairCategory>,
+    pub item_id: Option<usize>,
+    pub action: RepairAction,
+}
+
+pub(crate) fn sessions_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".workany").join("sessions"))
+}
+
+/// Collect regular files under `dir`, skipping symlinks and what the workspace rules
+/// ignore, so ignored files are never reported as untracked. Returns false if `limit`
+/// was hit.
+fn walk_files(rules: &WorkspaceRules, dir: &Path, out: &mut Vec<PathBuf>, limit: usize) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return true;
+    };
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else { continue };
+        let path = entry.path();
+        if file_type.is_dir() {
+            if rules.is_ignored(&path, true) {
+                continue;
+            }
+            if !walk_files(rules, &path, out, limit) {
+                return false;
+            }
+        } else if file_type.is_file() && !rules.is_ignored(&path, false) {
+            if out.len() >= limit {
+                return false;
+            }
+            out.push(path);
+        }
+    }
+    true
+}
+
+struct FileRow {
+    id: i64,
+    path: String,
+    sha256: Option<String>,
+    has_thumbnail: bool,
+}
+
+fn load_rows(conn: &Connection) -> Result<Vec<FileRow>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT id, path, sha256, {} FROM files ORDER BY id",
+            media_backfill::has_thumbnail_sql(conn)?
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(FileRow {
+                id: row.get(0)?,
+                path: row.get(1)?,
+                sha256: row.get(2)?,
+                has_thumbnail: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// `(message id, attachment array)` for every message with attachments
+fn load_attachments(conn: &Connection) -> Result<Vec<(i64, Vec<Value>)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, attachments FROM messages WHERE attachments IS NOT NULL AND attachments != ''")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?;
+    let mut out = Vec::new();
+    for row in rows {
+        let (id, raw) = row.map_err(|e| e.to_string())?;
+        if let Ok(Value::Array(entries)) = serde_json::from_str(&raw) {
+            out.push((id, entries));
+        }
+    }
+    Ok(out)
+}
+
+fn attachment_path(entry: &Value) -> Option<&str> {
+    entry.get("path").and_then(Value::as_str).filter(|p| !p.is_empty())
+}
+
+fn workspace_in_use(conn: &Connection, session_id: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)
+             OR EXISTS(SELECT 1 FROM tasks WHERE session_id = ?1)",
+        [session_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn new_item(category: RepairCategory, path: &Path, recommended: RepairAction, actions: Vec<RepairAction>) -> RepairItem {
+    RepairItem {
+        id: 0,
+        category,
+        path: path.to_string_lossy().into_owned(),
+        file_id: None,
+        message_id: None,
+        attachment_index: None,
+        relink_candidates: Vec::new(),
+        recommended,
+        actions,
+    }
+}
+
+fn scan(ctx: &jobs::JobContext) -> Result<(Vec<RepairItem>, bool), String> {
+    let conn = db::open(ctx.app())?;
+    let mut items: Vec<RepairItem> = Vec::new();
+
+    // Workspaces first: files inside an orphaned workspace are reported with it
+    ctx.progress(0, 4, Some("Scanning workspaces".to_string()));
+    let mut orphan_workspaces = Vec::new();
+    let mut disk = Vec::new();
+    let mut complete = true;
+    if let Some(root) = sessions_dir().filter(|root| root.is_dir()) {
+        for entry in std::fs::read_dir(&root).map_err(|e| e.to_string())?.flatten() {
+            if !entry.file_type().is_ok_and(|t| t.is_dir()) {
+                continue;
+            }
+            let session_id = entry.file_name().to_string_lossy().into_owned();
+            if workspace_in_use(&conn, &session_id)? {
+                let rules = WorkspaceRules::load(&conn, &entry.path())?;
+                complete = complete && walk_files(&rules, &entry.path(), &mut disk, MAX_DISK_FILES);
+            } else {
+                orphan_workspaces.push(entry.path());
+            }
+        }
+    }
+    let mut by_name: HashMap<OsString, Vec<&PathBuf>> = HashMap::new();
+    for path in &disk {
+        if let Some(name) = path.file_name() {
+            by_name.entry(name.to_os_string()).or_default().push(path);
+        }
+    }
+
+    let rows = load_rows(&conn)?;
+    let total = rows.len() as u64;
+    let mut referenced: HashSet<PathBuf> = HashSet::new();
+    for (done, row) in rows.iter().enumerate() {
+        if ctx.is_cancelled() {
+            return Err("Cancelled".to_string());
+        }
+        if done % 100 == 0 {
+            ctx.progress(done as u64, total, Some("Checking files".to_string()));
+        }
+        let path = Path::new(&row.path);
+        referenced.insert(path.to_path_buf());
+        if !path.exists() {
+            let candidates: Vec<String> = match (&row.sha256, path.file_name()) {
+                (Some(hash), Some(name)) => by_name
+                    .get(name)
+                    .into_iter()
+                    .flatten()
+                    .filter(|found| files::sha256_file(found).is_ok_and(|h| &h == hash))
+                    .map(|found| found.to_string_lossy().into_owned())
+                    .collect(),
+                _ => Vec::new(),
+            };
+            let category = if row.has_thumbnail {
+                RepairCategory::ThumbnailWithoutSource
+            } else {
+                RepairCategory::MissingFile
+            };
+            let mut actions = vec![RepairAction::Ignore, RepairAction::DeleteRow];
+            if row.has_thumbnail {
+                actions.push(RepairAction::Regenerate);
+            }
+            let recommended = if candidates.is_empty() {
+                RepairAction::DeleteRow
+            } else {
+                actions.push(RepairAction::Relink);
+                RepairAction::Relink
+            };
+            items.push(RepairItem {
+                file_id: Some(row.id),
+                relink_candidates: candidates,
+                ..new_item(category, path, recommended, actions)
+            });
+        } else if let Some(hash) = &row.sha256 {
+            if files::sha256_file(path).is_ok_and(|h| &h != hash) {
+                items.push(RepairItem {
+                    file_id: Some(row.id),
+                    ..new_item(
+                        RepairCategory::HashMismatch,
+                        path,
+                        RepairAction::Regenerate,
+                        vec![RepairAction::Ignore, RepairAction::Regenerate],
+                    )
+                });
+            }
+        }
+    }
+
+    ctx.progress(2, 4, Some("Checking attachments".to_string()));
+    for (message_id, entries) in load_attachments(&conn)? {
+        for (index, entry) in entries.iter().enumerate() {
+            let Some(path) = attachment_path(entry).map(Path::new) else { continue };
+            referenced.insert(path.to_path_buf());
+            if path.exists() {
+                continue;
+            }
+            // Images keep their data inline and still render without the file
+            let has_data = entry.get("data").and_then(Value::as_str).is_some_and(|d| !d.is_empty());
+            items.push(RepairItem {
+                message_id: Some(message_id),
+                attachment_index: Some(index),
+                ..new_item(
+                    RepairCategory::MissingAttachment,
+                    path,
+                    if has_data { RepairAction::Ignore } else { RepairAction::DeleteRow },
+                    vec![RepairAction::Ignore, RepairAction::DeleteRow],
+                )
+            });
+        }
+    }
+
+    ctx.progress(3, 4, Some("Checking untracked files".to_string()));
+    for path in &disk {
+        if !referenced.contains(path) {
+            // Agents leave working files in their workspace on purpose
+            items.push(new_item(
+                RepairCategory::UntrackedFile,
+                path,
+                RepairAction::Ignore,
+                vec![RepairAction::Ignore, RepairAction::TrashFile],
+            ));
+        }
+    }
+    for path in &orphan_workspaces {
+        items.push(new_item(
+            RepairCategory::OrphanWorkspace,
+            path,
+            RepairAction::TrashFile,
+            vec![RepairAction::Ignore, RepairAction::TrashFile],
+        ));
+    }
+    for (id, item) in items.iter_mut().enumerate() {
+        item.id = id;
+    }
+    ctx.progress(4, 4, None);
+    Ok((items, !complete))
+}
+
+fn load_report(conn: &Connection, report_id: i64) -> Result<RepairReport, String> {
+    let row = conn
+        .query_row(
+            "SELECT id, created_at, items, truncated, applied_at, results FROM file_repair_reports WHERE id = ?1",
+            [report_id],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, bool>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Repair report {} not found", report_id))?;
+    let (id, created_at, items, truncated, applied_at, results) = row;
+    Ok(RepairReport {
+        id,
+        created_at,
+        items: serde_json::from_str(&items).map_err(|e| e.to_string())?,
+        truncated,
+        applied_at,
+        results: results
+            .map(|r| serde_json::from_str(&r))
+            .transpose()
+            .map_err(|e| e.to_string())?,
+    })
+}
+
+/// Pick the action for each item from the decisions, checking it applies to the item
+fn resolve(items: &[RepairItem], decisions: &[RepairDecision]) -> Result<Vec<(usize, RepairAction)>, String> {
+    let mut by_item = HashMap::new();
+    let mut by_category = HashMap::new();
+    for decision in decisions {
+        match (decision.item_id, decision.category) {
+            (Some(item_id), _) => {
+                if item_id >= items.len() {
+                    return Err(format!("No item {} in this report", item_id));
+                }
+                by_item.insert(item_id, decision.action);
+            }
+            (None, Some(category)) => {
+                by_category.insert(category, decision.action);
+            }
+            (None, None) => return Err("A decision needs a category or an item_id".to_string()),
+        }
+    }
+    let mut chosen = Vec::new();
+    for item in items {
+        let action = by_item.get(&item.id).or_else(|| by_category.get(&item.category));
+        let Some(&action) = action else { continue };
+        if !item.actions.contains(&action) {
+            // A category-wide choice that doesn't fit this item leaves it alone
+            if by_item.contains_key(&item.id) {
+                return Err(format!("{:?} does not apply to item {}", action, item.id));
+            }
+            continue;
+        }
+        chosen.push((item.id, action));
+    }
+    Ok(chosen)
+}
+
+/// Row changes worked out before the transaction starts
+enum RowChange {
+    Relink { file_id: i64, path: String, name: String },
+    SetHash { file_id: i64, sha256: String, thumbnail_hash: Option<String> },
+    ClearThumbnail { file_id: i64 },
+    DeleteFile { file_id: i64 },
+    DeleteAttachment { message_id: i64, index: usize, path: String },
+}
+
+#[derive(Default)]
+struct Applied {
+    results: Vec<RepairResult>,
+    deleted_files: Vec<String>,
+    updated_files: Vec<String>,
+    updated_messages: Vec<String>,
+}
+
+/// Where the item's row now points, failing if it was deleted or moved since the scan
+fn current_row(conn: &Connection, item: &RepairItem) -> Result<(Option<String>, String), String> {
+    let file_id = item.file_id.ok_or("Item has no files row")?;
+    let (path, sha256, file_type): (String, Option<String>, String) = conn
+        .query_row(
+            "SELECT path, sha256, type FROM files WHERE id = ?1",
+            [file_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or("The row no longer exists")?;
+    if path != item.path {
+        return Err("The row changed since the scan".to_string());
+    }
+    Ok((sha256, file_type))
+}
+
+/// Work out the row change for one item. Reads the disk but writes nothing; an
+/// error means the item no longer applies and is reported as failed.
+fn plan(app: &AppHandle, conn: &Connection, item: &RepairItem, action: RepairAction) -> Result<Option<RowChange>, String> {
+    let path = Path::new(&item.path);
+    let still_missing = || {
+        if path.exists() {
+            Err("The file is back on disk".to_string())
+        } else {
+            Ok(())
+        }
+    };
+    match (item.category, action) {
+        (_, RepairAction::Ignore | RepairAction::TrashFile) => Ok(None),
+        (RepairCategory::MissingAttachment, RepairAction::DeleteRow) => {
+            still_missing()?;
+            Ok(Some(RowChange::DeleteAttachment {
+                message_id: item.message_id.ok_or("Item has no message")?,
+                index: item.attachment_index.ok_or("Item has no attachment index")?,
+                path: item.path.clone(),
+            }))
+        }
+        (_, RepairAction::DeleteRow) => {
+            current_row(conn, item)?;
+            still_missing()?;
+            Ok(Some(RowChange::DeleteFile {
+                file_id: item.file_id.ok_or("Item has no files row")?,
+            }))
+        }
+        (_, RepairAction::Relink) => {
+            let (sha256, _) = current_row(conn, item)?;
+            still_missing()?;
+            let sha256 = sha256.ok_or("The row has no recorded hash to match against")?;
+            let found = item
+                .relink_candidates
+                .iter()
+                .filter_map(|candidate| files::ensure_allowed(app, Path::new(candidate)).ok())
+                .find(|candidate| files::sha256_file(candidate).is_ok_and(|h| h == sha256))
+                .ok_or("No candidate matches the recorded hash")?;
+            Ok(Some(RowChange::Relink {
+                file_id: item.file_id.ok_or("Item has no files row")?,
+                name: found
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                path: found.to_string_lossy().into_owned(),
+            }))
+        }
+        (RepairCategory::ThumbnailWithoutSource, RepairAction::Regenerate) => {
+            current_row(conn, item)?;
+            still_missing()?;
+            Ok(Some(RowChange::ClearThumbnail {
+                file_id: item.file_id.ok_or("Item has no files row")?,
+            }))
+        }
+        (_, RepairAction::Regenerate) => {
+            let (_, file_type) = current_row(conn, item)?;
+            let sha256 = files::sha256_file(path).map_err(|e| e.to_string())?;
+            let thumbnail_hash = if file_type == "image" {
+                thumbnails::render_thumbnail(path, THUMBNAIL_DIM).ok()
+            } else {
+                None
+            };
+            Ok(Some(RowChange::SetHash {
+                file_id: item.file_id.ok_or("Item has no files row")?,
+                sha256,
+                thumbnail_hash,
+            }))
+        }
+    }
+}
+
+/// Move an untracked file or orphaned workspace to the trash, if that still holds
+fn trash(app: &AppHandle, conn: &Connection, item: &RepairItem) -> Result<(), String> {
+    let path = files::ensure_allowed(app, Path::new(&item.path))?;
+    if !path.exists() {
+        return Err("Already gone".to_string());
+    }
+    match item.category {
+        RepairCategory::UntrackedFile => {
+            let tracked: bool = conn
+                .query_row("SELECT EXISTS(SELECT 1 FROM files WHERE path = ?1)", [&item.path], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            if tracked {
+                return Err("The file is tracked again".to_string());
+            }
+        }
+        RepairCategory::OrphanWorkspace => {
+            let session_id = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            if workspace_in_use(conn, &session_id)? {
+                return Err("The workspace belongs to a session again".to_string());
+            }
+        }
+        _ => return Err("Only untracked files and orphaned workspaces can be trashed".to_string()),
+    }
+    trash::delete(&path).map_err(|e| e.to_string())
+}
+
+/// Remove attachment `index` from a message, provided it still has `path`
+fn delete_attachment(tx: &Connection, message_id: i64, index: usize, path: &str) -> Result<(), String> {
+    let raw: Option<String> = tx
+        .query_row("SELECT attachments FROM messages WHERE id = ?1", [message_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?
+        .flatten();
+    let mut entries: Vec<Value> = raw
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .ok_or("The message no longer has attachments")?;
+    if entries.get(index).and_then(attachment_path) != Some(path) {
+        return Err("The attachment changed since the scan".to_string());
+    }
+    entries.remove(index);
+    let updated = if entries.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(&entries).map_err(|e| e.to_string())?)
+    };
+    tx.execute(
+        "UPDATE messages SET attachments = ?1 WHERE id = ?2",
+        params![updated, message_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn apply(app: &AppHandle, conn: &mut Connection, report_id: i64, decisions: &[RepairDecision]) -> Result<Applied, String> {
+    let report = load_report(conn, report_id)?;
+    if report.applied_at.is_some() {
+        return Err(format!("Repair report {} was already applied; run a new scan", report_id));
+    }
+    let chosen = resolve(&report.items, decisions)?;
+    let mut applied = Applied::default();
+    let result = |item_id, action, outcome: Result<(), String>| RepairResult {
+        item_id,
+        action,
+        ok: outcome.is_ok(),
+        detail: outcome.err(),
+    };
+
+    let mut changes = Vec::new();
+    for &(item_id, action) in &chosen {
+        match plan(app, conn, &report.items[item_id], action) {
+            Ok(Some(change)) => changes.push((item_id, action, change)),
+            Ok(None) => {}
+            Err(e) => applied.results.push(result(item_id, action, Err(e))),
+        }
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut row_results = Vec::new();
+    // Highest index first so earlier removals don't shift later ones in one message
+    changes.sort_by_key(|(_, _, change)| match change {
+        RowChange::DeleteAttachment { message_id, index, .. } => (1, *message_id, usize::MAX - index),
+        _ => (0, 0, 0),
+    });
+    for (item_id, action, change) in &changes {
+        let sql_result = |r: rusqlite::Result<usize>| r.map(|_| ()).map_err(|e| e.to_string());
+        match change {
+            RowChange::Relink { file_id, path, name } => {
+                sql_result(tx.execute(
+                    "UPDATE files SET path = ?1, name = ?2 WHERE id = ?3",
+                    params![path, name, file_id],
+                ))?;
+                applied.updated_files.push(file_id.to_string());
+            }
+            RowChange::SetHash { file_id, sha256, thumbnail_hash } => {
+                sql_result(tx.execute(
+                    "UPDATE files SET sha256 = ?1,
+                                      thumbnail = CASE WHEN ?2 IS NULL THEN thumbnail END,
+                                      thumbnail_hash = COALESCE(?2, thumbnail_hash),
+                                      media_format = CASE WHEN ?2 IS NULL THEN media_format ELSE 1 END
+                     WHERE id = ?3",
+                    params![sha256, thumbnail_hash, file_id],
+                ))?;
+                applied.updated_files.push(file_id.to_string());
+            }
+            RowChange::ClearThumbnail { file_id } => {
+                sql_result(tx.execute(
+                    "UPDATE files SET thumbnail = NULL, thumbnail_hash = NULL WHERE id = ?1",
+                    [file_id],
+                ))?;
+                applied.updated_files.push(file_id.to_string());
+            }
+            RowChange::DeleteFile { file_id } => {
+                sql_result(tx.execute("DELETE FROM files WHERE id = ?1", [file_id]))?;
+                applied.deleted_files.push(file_id.to_string());
+            }
+            RowChange::DeleteAttachment { message_id, index, path } => {
+                let outcome = delete_attachment(&tx, *message_id, *index, path);
+                if outcome.is_ok() {
+                    applied.updated_messages.push(message_id.to_string());
+                }
+                row_results.push(result(*item_id, *action, outcome));
+                continue;
+            }
+        }
+        row_results.push(result(*item_id, *action, Ok(())));
+    }
+    integrity::record_deletion(&tx, "files", "file_repair", applied.deleted_files.len() as u32)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    applied.results.extend(row_results);
+
+    for &(item_id, action) in &chosen {
+        match action {
+            RepairAction::Ignore => applied.results.push(result(item_id, action, Ok(()))),
+            RepairAction::TrashFile => {
+                let outcome = trash(app, conn, &report.items[item_id]);
+                applied.results.push(result(item_id, action, outcome));
+            }
+            _ => {}
+        }
+    }
+    applied.results.sort_by_key(|r| r.item_id);
+    applied.updated_messages.dedup();
+
+    conn.execute(
+        "UPDATE file_repair_reports SET applied_at = datetime('now'), results = ?1 WHERE id = ?2",
+        params![serde_json::to_string(&applied.results).map_err(|e| e.to_string())?, report_id],
+    )
+    .map_err(|e| e.to_string())?;
+    let failed = applied.results.iter().filter(|r| !r.ok).count();
+    println!(
+        "[FileRepair] Applied report {}: {} action(s), {} failed",
+        report_id,
+        applied.results.len(),
+        failed
+    );
+    Ok(applied)
+}
+
+/// Cross-check files rows, attachments and session workspaces against the disk as a
+/// background job. The job result carries the id of the stored report.
+#[tauri::command]
+pub fn scan_file_integrity(app: AppHandle) -> u64 {
+    jobs::spawn(&app, "scan_file_integrity", |ctx| {
+        let (items, truncated) = scan(ctx)?;
+        let conn = db::open(ctx.app())?;
+        conn.execute(
+            "INSERT INTO file_repair_reports (items, truncated) VALUES (?1, ?2)",
+            params![serde_json::to_string(&items).map_err(|e| e.to_string())?, truncated],
+        )
+        .map_err(|e| e.to_string())?;
+        let report_id = conn.last_insert_rowid();
+        println!("[FileRepair] Scan found {} item(s), report {}", items.len(), report_id);
+        Ok(json!({ "report_id": report_id, "items": items.len(), "truncated": truncated }))
+    })
+}
+
+#[tauri::command]
+pub async fn get_file_repair_report(app: AppHandle, report_id: i64) -> Result<RepairReport, String> {
+    db::with_conn(&app, move |conn| load_report(conn, report_id)).await
+}
+
+/// Stored reports, newest first
+#[tauri::command]
+pub async fn list_file_repair_reports(app: AppHandle) -> Result<Vec<RepairReportSummary>, String> {
+    db::with_conn(&app, |conn| {
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, created_at, json_array_length(items), applied_at
+                 FROM file_repair_reports ORDER BY id DESC",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(RepairReportSummary {
+                    id: row.get(0)?,
+                    created_at: row.get(1)?,
+                    item_count: row.get::<_, i64>(2)? as usize,
+                    applied_at: row.get(3)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Carry out the chosen actions for a stored report. Each report can be applied
+/// once; items that changed since the scan are skipped and reported as failed.
+#[tauri::command]
+pub async fn apply_file_repairs(
+    app: AppHandle,
+    report_id: i64,
+    decisions: Vec<RepairDecision>,
+) -> Result<RepairReport, String> {
+    let handle = app.clone();
+    let (applied, report) = db::with_conn(&app, move |conn| {
+        let applied = apply(&handle, conn, report_id, &decisions)?;
+        Ok((applied, load_report(conn, report_id)?))
+    })
+    .await?;
+    if !applied.deleted_files.is_empty() {
+        db::emit_change(&app, "files", "delete", applied.deleted_files);
+    }
+    if !applied.updated_files.is_empty() {
+        db::emit_change(&app, "files", "update", applied.updated_files);
+    }
+    if !applied.updated_messages.is_empty() {
+        db::emit_change(&app, "messages", "update", applied.updated_messages);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: usize, category: RepairCategory, actions: Vec<RepairAction>) -> RepairItem {
+        RepairItem {
+            id,
+            ..new_item(category, Path::new("/x"), actions[0], actions)
+        }
+    }
+
+    fn decision(category: Option<RepairCategory>, item_id: Option<usize>, action: RepairAction) -> RepairDecision {
+        RepairDecision {
+            category,
+            item_id,
+            action,
+        }
+    }
+
+    #[test]
+    fn item_decisions_override_category_decisions() {
+        use RepairAction::*;
+        let items = vec![
+            item(0, RepairCategory::MissingFile, vec![Ignore, DeleteRow, Relink]),
+            item(1, RepairCategory::MissingFile, vec![Ignore, DeleteRow]),
+            item(2, RepairCategory::OrphanWorkspace, vec![Ignore, TrashFile]),
+        ];
+        let chosen = resolve(
+            &items,
+            &[
+                decision(Some(RepairCategory::MissingFile), None, DeleteRow),
+                decision(None, Some(0), Relink),
+            ],
+        )
+        .unwrap();
+        // The workspace had no decision and is left alone
+        assert_eq!(chosen, vec![(0, Relink), (1, DeleteRow)]);
+    }
+
+    #[test]
+    fn category_decision_skips_items_it_does_not_fit() {
+        use RepairAction::*;
+        let items = vec![
+            item(0, RepairCategory::MissingFile, vec![Ignore, DeleteRow, Relink]),
+            item(1, RepairCategory::MissingFile, vec![Ignore, DeleteRow]),
+        ];
+        let chosen = resolve(&items, &[decision(Some(RepairCategory::MissingFile), None, Relink)]).unwrap();
+        assert_eq!(chosen, vec![(0, Relink)]);
+        assert!(resolve(&items, &[decision(None, Some(1), Relink)]).is_err());
+        assert!(resolve(&items, &[decision(None, Some(7), Ignore)]).is_err());
+    }
+}
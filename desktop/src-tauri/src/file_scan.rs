@@ -0,0 +1,496 @@
+// Handing files to the platform's malware scanning before they are opened.
+//
+// `scan_file` first makes sure the file carries the OS's download marks (the
+// Zone.Identifier stream on Windows, so SmartScreen applies; the quarantine xattr on
+// macOS, so Gatekeeper and XProtect check it on open), then asks whatever engine the
+// platform has: AMSI on Windows, clamd over its socket on Linux and macOS. Without
+// clamd, macOS records `quarantined`, meaning the OS checks the file when it's
+// opened. Where there is no engine at all the verdict is `unscanned`, never `clean`.
+//
+// With `require_scan_before_open` on, opening or previewing a file needs a `clean`
+// or `quarantined` verdict.
+
+use crate::error::CommandError;
+use crate::{db, files, jobs, settings};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+const REQUIRE_KEY: &str = "require_scan_before_open";
+/// Overrides the clamd socket locations probed by default
+#[cfg_attr(not(unix), allow(dead_code))]
+const CLAMD_SOCKET_KEY: &str = "clamd_socket";
+/// Files larger than this are scanned as a job
+const INLINE_LIMIT: u64 = 16 * 1024 * 1024;
+const CHUNK: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanVerdict {
+    Clean,
+    Flagged,
+    /// Marked for the OS to check when opened (macOS Gatekeeper/XProtect)
+    Quarantined,
+    /// No engine was available, or it couldn't scan this file
+    Unscanned,
+}
+
+impl ScanVerdict {
+    fn as_str(self) -> &'static str {
+        match self {
+            ScanVerdict::Clean => "clean",
+            ScanVerdict::Flagged => "flagged",
+            ScanVerdict::Quarantined => "quarantined",
+            ScanVerdict::Unscanned => "unscanned",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "clean" => Some(ScanVerdict::Clean),
+            "flagged" => Some(ScanVerdict::Flagged),
+            "quarantined" => Some(ScanVerdict::Quarantined),
+            "unscanned" => Some(ScanVerdict::Unscanned),
+            _ => None,
+        }
+    }
+}
+
+/// What an engine reported, with the signature or reason as detail
+struct Outcome {
+    verdict: ScanVerdict,
+    engine: &'static str,
+    detail: Option<String>,
+}
+
+impl Outcome {
+    fn unscanned(engine: &'static str, detail: impl Into<String>) -> Self {
+        Outcome {
+            verdict: ScanVerdict::Unscanned,
+            engine,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanResult {
+    pub file_id: i64,
+    pub verdict: ScanVerdict,
+    pub engine: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ScanResponse {
+    Completed { result: ScanResult },
+    /// A large file is being scanned by this job; its result is the `ScanResult`
+    Running { job_id: u64 },
+}
+
+/// Read `path` in chunks, stopping early when `cancelled` says so
+fn for_each_chunk(
+    path: &Path,
+    cancelled: &dyn Fn() -> bool,
+    mut chunk: impl FnMut(&[u8]) -> Result<bool, String>,
+) -> Result<(), String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; CHUNK];
+    loop {
+        if cancelled() {
+            return Err("Cancelled".to_string());
+        }
+        let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 || !chunk(&buf[..n])? {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{for_each_chunk, Outcome, ScanVerdict};
+    use std::path::Path;
+    use windows_sys::Win32::System::Antimalware::{
+        AmsiCloseSession, AmsiInitialize, AmsiOpenSession, AmsiScanBuffer, AmsiUninitialize, AMSI_RESULT_DETECTED,
+        HAMSICONTEXT, HAMSISESSION,
+    };
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    /// Add a Zone.Identifier stream (Internet zone) unless one is there already
+    pub fn mark_downloaded(path: &Path, source_url: Option<&str>) -> Result<(), String> {
+        let stream = format!("{}:Zone.Identifier", path.display());
+        if Path::new(&stream).exists() {
+            return Ok(());
+        }
+        let host = source_url.map(|url| format!("HostUrl={}\r\n", url)).unwrap_or_default();
+        std::fs::write(&stream, format!("[ZoneTransfer]\r\nZoneId=3\r\n{}", host)).map_err(|e| e.to_string())
+    }
+
+    /// Scan through AMSI, which hands the buffers to the registered antimalware
+    /// provider (Defender unless another product replaced it)
+    pub fn scan(_socket: Option<&Path>, path: &Path, cancelled: &dyn Fn() -> bool) -> Result<Outcome, String> {
+        let app_name = wide("WorkAny");
+        let content_name = wide(&path.to_string_lossy());
+        // SAFETY: plain handle types, initialised by the calls below before use
+        let mut context: HAMSICONTEXT = unsafe { std::mem::zeroed() };
+        let mut session: HAMSISESSION = unsafe { std::mem::zeroed() };
+        // SAFETY: `app_name` is NUL-terminated and outlives the call
+        let hr = unsafe { AmsiInitialize(app_name.as_ptr(), &mut context) };
+        if hr < 0 {
+            return Ok(Outcome::unscanned("amsi", format!("AMSI is unavailable (0x{:08x})", hr)));
+        }
+        // SAFETY: `context` was initialised above
+        let hr = unsafe { AmsiOpenSession(context, &mut session) };
+        if hr < 0 {
+            // SAFETY: initialised above and not used afterwards
+            unsafe { AmsiUninitialize(context) };
+            return Ok(Outcome::unscanned("amsi", format!("AMSI session failed (0x{:08x})", hr)));
+        }
+
+        let mut flagged = false;
+        let scanned = for_each_chunk(path, cancelled, |chunk| {
+            let mut result = 0;
+            // SAFETY: the buffer and name outlive the call; lengths match
+            let hr = unsafe {
+                AmsiScanBuffer(
+                    context,
+                    chunk.as_ptr() as *const core::ffi::c_void,
+                    chunk.len() as u32,
+                    content_name.as_ptr(),
+                    session,
+                    &mut result,
+                )
+            };
+            if hr < 0 {
+                return Err(format!("AMSI scan failed (0x{:08x})", hr));
+            }
+            flagged = result >= AMSI_RESULT_DETECTED;
+            Ok(!flagged)
+        });
+        // SAFETY: both handles were initialised above and are not used afterwards
+        unsafe {
+            AmsiCloseSession(context, session);
+            AmsiUninitialize(context);
+        }
+        scanned?;
+        Ok(Outcome {
+            verdict: if flagged { ScanVerdict::Flagged } else { ScanVerdict::Clean },
+            engine: "amsi",
+            detail: flagged.then(|| "Detected by the antimalware provider".to_string()),
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{clamd, Outcome, ScanVerdict};
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    const QUARANTINE_XATTR: &str = "com.apple.quarantine";
+
+    /// Set the quarantine xattr the way browsers do, unless the file has one
+    pub fn mark_downloaded(path: &Path, _source_url: Option<&str>) -> Result<(), String> {
+        let present = Command::new("xattr")
+            .args(["-p", QUARANTINE_XATTR])
+            .arg(path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success());
+        if present {
+            return Ok(());
+        }
+        let value = format!("0081;{:08x};WorkAny;", chrono::Utc::now().timestamp());
+        let status = Command::new("xattr")
+            .args(["-w", QUARANTINE_XATTR, &value])
+            .arg(path)
+            .status()
+            .map_err(|e| e.to_string())?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("xattr exited with {}", status))
+        }
+    }
+
+    /// clamd when it's running; otherwise Gatekeeper and XProtect take over on open
+    pub fn scan(socket: Option<&Path>, path: &Path, cancelled: &dyn Fn() -> bool) -> Result<Outcome, String> {
+        match socket {
+            Some(socket) => clamd::scan(socket, path, cancelled),
+            None => Ok(Outcome {
+                verdict: ScanVerdict::Quarantined,
+                engine: "gatekeeper",
+                detail: Some("Quarantined; macOS checks the file when it is opened".to_string()),
+            }),
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use super::{clamd, Outcome};
+    use std::path::Path;
+
+    /// Linux desktops have no download mark that openers honour
+    pub fn mark_downloaded(_path: &Path, _source_url: Option<&str>) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn scan(socket: Option<&Path>, path: &Path, cancelled: &dyn Fn() -> bool) -> Result<Outcome, String> {
+        match socket {
+            Some(socket) => clamd::scan(socket, path, cancelled),
+            None => Ok(Outcome::unscanned("none", "No scanning engine found: clamd is not running")),
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use super::Outcome;
+    use std::path::Path;
+
+    pub fn mark_downloaded(_path: &Path, _source_url: Option<&str>) -> Result<(), String> {
+        Ok(())
+    }
+
+    pub fn scan(_socket: Option<&Path>, _path: &Path, _cancelled: &dyn Fn() -> bool) -> Result<Outcome, String> {
+        Ok(Outcome::unscanned("none", "No scanning engine on this platform"))
+    }
+}
+
+#[cfg(unix)]
+mod clamd {
+    use super::{for_each_chunk, Outcome, ScanVerdict};
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    const DEFAULT_SOCKETS: &[&str] = &[
+        "/var/run/clamav/clamd.ctl",
+        "/run/clamav/clamd.ctl",
+        "/var/run/clamd.scan/clamd.sock",
+        "/run/clamd.scan/clamd.sock",
+        "/opt/homebrew/var/run/clamav/clamd.sock",
+        "/usr/local/var/run/clamav/clamd.sock",
+        "/tmp/clamd.socket",
+    ];
+    const TIMEOUT: Duration = Duration::from_secs(120);
+
+    pub fn find_socket(configured: Option<String>) -> Option<PathBuf> {
+        match configured {
+            Some(path) => Some(PathBuf::from(path)),
+            None => DEFAULT_SOCKETS.iter().map(PathBuf::from).find(|p| p.exists()),
+        }
+    }
+
+    /// Turn clamd's reply to INSTREAM into an outcome
+    pub fn parse_reply(reply: &str) -> Result<Outcome, String> {
+        let reply = reply.trim_end_matches(['\0', '\n', ' ']);
+        let body = reply.strip_prefix("stream: ").unwrap_or(reply);
+        if body == "OK" {
+            return Ok(Outcome {
+                verdict: ScanVerdict::Clean,
+                engine: "clamd",
+                detail: None,
+            });
+        }
+        if let Some(signature) = body.strip_suffix(" FOUND") {
+            return Ok(Outcome {
+                verdict: ScanVerdict::Flagged,
+                engine: "clamd",
+                detail: Some(signature.to_string()),
+            });
+        }
+        if let Some(error) = body.strip_suffix(" ERROR") {
+            // e.g. the file is over clamd's StreamMaxLength
+            return Ok(Outcome::unscanned("clamd", error));
+        }
+        Err(format!("Unexpected reply from clamd: {}", reply))
+    }
+
+    /// Stream the file to clamd with INSTREAM
+    pub fn scan(socket: &Path, path: &Path, cancelled: &dyn Fn() -> bool) -> Result<Outcome, String> {
+        let mut stream = match UnixStream::connect(socket) {
+            Ok(stream) => stream,
+            Err(e) => {
+                return Ok(Outcome::unscanned(
+                    "clamd",
+                    format!("clamd at {} is not reachable: {}", socket.display(), e),
+                ))
+            }
+        };
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.write_all(b"zINSTREAM\0").map_err(|e| e.to_string())?;
+        let sent = for_each_chunk(path, cancelled, |chunk| {
+            stream
+                .write_all(&(chunk.len() as u32).to_be_bytes())
+                .and_then(|_| stream.write_all(chunk))
+                .map(|_| true)
+                .map_err(|e| e.to_string())
+        });
+        // clamd closes the stream early once a size limit is hit, then replies
+        if let Err(e) = sent {
+            if e == "Cancelled" {
+                return Err(e);
+            }
+        } else {
+            stream.write_all(&0u32.to_be_bytes()).map_err(|e| e.to_string())?;
+        }
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).map_err(|e| e.to_string())?;
+        parse_reply(&reply)
+    }
+}
+
+fn clamd_socket(conn: &Connection) -> Result<Option<PathBuf>, String> {
+    #[cfg(unix)]
+    {
+        Ok(clamd::find_socket(settings::get(conn, CLAMD_SOCKET_KEY)?))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = conn;
+        Ok(None)
+    }
+}
+
+fn record(conn: &Connection, file_id: i64, outcome: &Outcome) -> Result<(), String> {
+    conn.execute(
+        "UPDATE files SET scan_verdict = ?1, scan_engine = ?2, scan_detail = ?3, scanned_at = datetime('now')
+         WHERE id = ?4",
+        params![outcome.verdict.as_str(), outcome.engine, outcome.detail, file_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn run_scan(app: &AppHandle, file_id: i64, path: &Path, cancelled: &dyn Fn() -> bool) -> Result<ScanResult, String> {
+    if let Err(e) = platform::mark_downloaded(path, None) {
+        eprintln!("[Scan] Could not mark {} as downloaded: {}", path.display(), e);
+    }
+    let conn = db::open(app)?;
+    let socket = clamd_socket(&conn)?;
+    let outcome = platform::scan(socket.as_deref(), path, cancelled)?;
+    record(&conn, file_id, &outcome)?;
+    db::emit_change(app, "files", "update", vec![file_id.to_string()]);
+    if outcome.verdict == ScanVerdict::Flagged {
+        eprintln!(
+            "[Scan] {} flagged by {}: {}",
+            path.display(),
+            outcome.engine,
+            outcome.detail.as_deref().unwrap_or("no detail")
+        );
+    }
+    Ok(ScanResult {
+        file_id,
+        verdict: outcome.verdict,
+        engine: outcome.engine.to_string(),
+        detail: outcome.detail,
+    })
+}
+
+/// Refuse to open a file that isn't known to be safe while scanning is required
+pub(crate) fn ensure_openable(conn: &Connection, file_id: i64) -> Result<(), CommandError> {
+    if !settings::get_or(conn, REQUIRE_KEY, false)? {
+        return Ok(());
+    }
+    let verdict: Option<String> = conn
+        .query_row("SELECT scan_verdict FROM files WHERE id = ?1", [file_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?
+        .flatten();
+    let verdict = verdict.as_deref().and_then(ScanVerdict::parse);
+    match verdict {
+        Some(ScanVerdict::Clean | ScanVerdict::Quarantined) => Ok(()),
+        _ => Err(CommandError::ScanRequired {
+            message: match verdict {
+                Some(ScanVerdict::Flagged) => "The file was flagged by the malware scanner".to_string(),
+                Some(_) => "The file could not be scanned, and scanning is required before opening".to_string(),
+                None => "Scan the file before opening it".to_string(),
+            },
+            file_id,
+            verdict,
+        }),
+    }
+}
+
+/// Scan a file with the platform's malware scanning. Small files are scanned before
+/// returning; larger ones as a job whose result is the `ScanResult`.
+#[tauri::command]
+pub async fn scan_file(app: AppHandle, file_id: i64) -> Result<ScanResponse, String> {
+    let file = db::with_conn(&app, move |conn| files::load_file(conn, file_id)).await?;
+    let path = files::ensure_allowed(&app, Path::new(&file.path))?;
+    let size = std::fs::metadata(&path)
+        .map_err(|e| format!("Cannot read '{}': {}", file.path, e))?
+        .len();
+
+    if size > INLINE_LIMIT {
+        let job_id = jobs::spawn(&app, "scan_file", move |ctx| {
+            ctx.progress(0, 1, Some(file.name.clone()));
+            let result = run_scan(ctx.app(), file_id, &path, &|| ctx.is_cancelled())?;
+            ctx.progress(1, 1, None);
+            serde_json::to_value(&result).map_err(|e| e.to_string())
+        });
+        return Ok(ScanResponse::Running { job_id });
+    }
+    let handle = app.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || run_scan(&handle, file_id, &path, &|| false))
+        .await
+        .map_err(|e| e.to_string())??;
+    Ok(ScanResponse::Completed { result })
+}
+
+/// Whether files must have a clean (or quarantined) verdict before they are opened
+#[tauri::command]
+pub async fn set_require_scan_before_open(app: AppHandle, required: bool) -> Result<(), String> {
+    db::with_conn(&app, move |conn| settings::set(conn, REQUIRE_KEY, &required)).await
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanSettings {
+    pub require_scan_before_open: bool,
+    /// The clamd socket scans would use, if one was found
+    pub clamd_socket: Option<String>,
+}
+
+#[tauri::command]
+pub async fn get_scan_settings(app: AppHandle) -> Result<ScanSettings, String> {
+    db::with_conn(&app, |conn| {
+        Ok(ScanSettings {
+            require_scan_before_open: settings::get_or(conn, REQUIRE_KEY, false)?,
+            clamd_socket: clamd_socket(conn)?.map(|p| p.to_string_lossy().into_owned()),
+        })
+    })
+    .await
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_clamd_replies() {
+        let clean = clamd::parse_reply("stream: OK\0").unwrap();
+        assert_eq!(clean.verdict, ScanVerdict::Clean);
+
+        let found = clamd::parse_reply("stream: Win.Test.EICAR_HDB-1 FOUND\0").unwrap();
+        assert_eq!(found.verdict, ScanVerdict::Flagged);
+        assert_eq!(found.detail.as_deref(), Some("Win.Test.EICAR_HDB-1"));
+
+        let limit = clamd::parse_reply("INSTREAM size limit exceeded. ERROR\0").unwrap();
+        assert_eq!(limit.verdict, ScanVerdict::Unscanned);
+
+        assert!(clamd::parse_reply("garbage").is_err());
+    }
+}
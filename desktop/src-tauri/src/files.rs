@@ -0,0 +1,410 @@
+// Operations on files registered in the `files` table.
+//
+// Every filesystem change is confined to the same roots the fs plugin scope allows,
+// and is paired with the matching row update. When the database update fails after
+// the file already moved, the move is undone so disk and table never disagree.
+
+use crate::{db, integrity};
+use crate::error::CommandError;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ManagedFile {
+    pub id: i64,
+    pub task_id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub file_type: String,
+    pub path: String,
+    pub is_favorite: bool,
+    pub created_at: String,
+}
+
+/// Longest `files.preview` kept, in characters; the frontend cuts tool output to the same
+pub(crate) const PREVIEW_CHARS: usize = 500;
+
+/// Preview text for a file: the first `PREVIEW_CHARS` characters of its content
+pub(crate) fn preview(text: &str) -> &str {
+    match text.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+pub(crate) fn load_file(conn: &Connection, file_id: i64) -> Result<ManagedFile, String> {
+    conn.query_row(
+        "SELECT id, task_id, name, type, path, is_favorite, created_at FROM files WHERE id = ?1",
+        [file_id],
+        |row| {
+            Ok(ManagedFile {
+                id: row.get(0)?,
+                task_id: row.get(1)?,
+                name: row.get(2)?,
+                file_type: row.get(3)?,
+                path: row.get(4)?,
+                is_favorite: row.get::<_, i64>(5)? != 0,
+                created_at: row.get(6)?,
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("File {} not found", file_id))
+}
+
+/// Directories the app may modify, matching the fs scope in capabilities/default.json
+pub(crate) fn allowed_roots(app: &AppHandle) -> Vec<PathBuf> {
+    let path = app.path();
+    let mut roots = Vec::new();
+    if let Ok(home) = path.home_dir() {
+        roots.push(home.join(".workany"));
+        roots.push(home.join(".claude"));
+    }
+    roots.extend(path.download_dir().ok());
+    roots.extend(path.desktop_dir().ok());
+    roots.extend(path.document_dir().ok());
+    roots.extend(path.app_data_dir().ok());
+    roots
+}
+
+/// Resolve `path` (which may not exist yet) and check it sits inside an allowed root
+pub(crate) fn ensure_allowed(app: &AppHandle, path: &Path) -> Result<PathBuf, String> {
+    if path.components().any(|c| matches!(c, Component::ParentDir)) {
+        return Err(format!("Path '{}' must not contain '..'", path.display()));
+    }
+    let resolved = match path.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(_) => {
+            let parent = path.parent().ok_or("Path has no parent directory")?;
+            let name = path.file_name().ok_or("Path has no file name")?;
+            parent
+                .canonicalize()
+                .map_err(|e| format!("Cannot resolve '{}': {}", parent.display(), e))?
+                .join(name)
+        }
+    };
+    let allowed = allowed_roots(app)
+        .iter()
+        .filter_map(|root| root.canonicalize().ok())
+        .any(|root| resolved.starts_with(root));
+    if allowed {
+        Ok(resolved)
+    } else {
+        Err(format!("'{}' is outside the app's allowed folders", path.display()))
+    }
+}
+
+pub(crate) fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    let trimmed = name.trim();
+    if trimmed.is_empty() || trimmed == "." || trimmed == ".." {
+        return Err("File name must not be empty".to_string());
+    }
+    if trimmed.contains(['/', '\\', '\0']) {
+        return Err("File name must not contain path separators".to_string());
+    }
+    Ok(())
+}
+
+fn is_cross_device(err: &io::Error) -> bool {
+    // EXDEV on Unix, ERROR_NOT_SAME_DEVICE on Windows
+    if cfg!(windows) {
+        err.raw_os_error() == Some(17)
+    } else {
+        err.raw_os_error() == Some(18)
+    }
+}
+
+enum Moved {
+    Renamed,
+    // Source is only removed once the database agrees
+    Copied,
+}
+
+fn move_file(src: &Path, dest: &Path) -> Result<Moved, String> {
+    match fs::rename(src, dest) {
+        Ok(()) => Ok(Moved::Renamed),
+        Err(e) if is_cross_device(&e) => {
+            fs::copy(src, dest).map_err(|e| format!("Copy failed: {}", e))?;
+            let same = sha256_file(src).ok().zip(sha256_file(dest).ok()).map(|(a, b)| a == b);
+            if same != Some(true) {
+                let _ = fs::remove_file(dest);
+                return Err("Copied file failed hash verification".to_string());
+            }
+            Ok(Moved::Copied)
+        }
+        Err(e) => Err(format!("Move failed: {}", e)),
+    }
+}
+
+fn existing_metadata(conn: &Connection, dest: &Path) -> serde_json::Value {
+    let meta = fs::metadata(dest).ok();
+    let modified = meta
+        .as_ref()
+        .and_then(|m| m.modified().ok())
+        .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339());
+    let file_id: Option<i64> = conn
+        .query_row(
+            "SELECT id FROM files WHERE path = ?1 LIMIT 1",
+            [dest.to_string_lossy()],
+            |row| row.get(0),
+        )
+        .optional()
+        .unwrap_or(None);
+    json!({
+        "path": dest.to_string_lossy(),
+        "size": meta.as_ref().map(|m| m.len()),
+        "modified": modified,
+        "file_id": file_id,
+    })
+}
+
+fn relocate(app: &AppHandle, file_id: i64, dest: PathBuf, overwrite: bool) -> Result<ManagedFile, CommandError> {
+    let mut conn = db::open(app)?;
+    let file = load_file(&conn, file_id)?;
+    let src = ensure_allowed(app, Path::new(&file.path))?;
+    if !src.is_file() {
+        return Err(format!("'{}' no longer exists on disk", file.path).into());
+    }
+    let dest = ensure_allowed(app, &dest)?;
+    if dest == src {
+        return Ok(file);
+    }
+
+    // Park whatever is at the destination so it can be put back on failure
+    let mut displaced = None;
+    if dest.exists() {
+        if !overwrite {
+            return Err(CommandError::Conflict {
+                message: format!("'{}' already exists", dest.display()),
+                existing: existing_metadata(&conn, &dest),
+            });
+        }
+        let parked = dest.with_file_name(format!(
+            ".{}.displaced-{}",
+            dest.file_name().unwrap_or_default().to_string_lossy(),
+            chrono::Utc::now().timestamp_millis()
+        ));
+        fs::rename(&dest, &parked).map_err(|e| format!("Cannot move existing file aside: {}", e))?;
+        displaced = Some(parked);
+    }
+    let restore_displaced = |displaced: &Option<PathBuf>| {
+        if let Some(parked) = displaced {
+            let _ = fs::rename(parked, &dest);
+        }
+    };
+
+    let moved = match move_file(&src, &dest) {
+        Ok(moved) => moved,
+        Err(e) => {
+            restore_displaced(&displaced);
+            return Err(e.into());
+        }
+    };
+
+    let dest_str = dest.to_string_lossy().to_string();
+    let name = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| file.name.clone());
+    let updated = (|| -> rusqlite::Result<()> {
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE files SET path = ?1, name = ?2 WHERE id = ?3",
+            params![dest_str, name, file_id],
+        )?;
+        if displaced.is_some() {
+            // Rows for the overwritten file would otherwise point at our file
+            tx.execute(
+                "DELETE FROM files WHERE path = ?1 AND id != ?2",
+                params![dest_str, file_id],
+            )?;
+        }
+        tx.commit()
+    })();
+
+    if let Err(e) = updated {
+        // Compensate: put the file back where the table still says it is
+        match moved {
+            Moved::Renamed => {
+                let _ = fs::rename(&dest, &src);
+            }
+            Moved::Copied => {
+                let _ = fs::remove_file(&dest);
+            }
+        }
+        restore_displaced(&displaced);
+        return Err(format!("Database update failed, move rolled back: {}", e).into());
+    }
+
+    if let Moved::Copied = moved {
+        if let Err(e) = fs::remove_file(&src) {
+            eprintln!("[Files] Copied {} but could not remove the original: {}", src.display(), e);
+        }
+    }
+    if let Some(parked) = displaced {
+        if let Err(e) = trash::delete(&parked) {
+            eprintln!("[Files] Could not move {} to the trash: {}", parked.display(), e);
+        }
+    }
+
+    db::emit_change(app, "files", "update", vec![file_id.to_string()]);
+    Ok(load_file(&conn, file_id)?)
+}
+
+async fn run_relocate(app: AppHandle, file_id: i64, dest: PathBuf, overwrite: bool) -> Result<ManagedFile, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || relocate(&app, file_id, dest, overwrite))
+        .await
+        .map_err(|e| CommandError::from(e.to_string()))?
+}
+
+/// Rename a managed file in place. A name collision returns a `conflict` error
+/// unless `overwrite` is set, in which case the existing file goes to the trash.
+#[tauri::command]
+pub async fn rename_managed_file(
+    app: AppHandle,
+    file_id: i64,
+    new_name: String,
+    overwrite: Option<bool>,
+) -> Result<ManagedFile, CommandError> {
+    validate_name(&new_name)?;
+    let path = db::with_conn(&app, move |conn| load_file(conn, file_id).map(|f| f.path)).await?;
+    let dest = Path::new(&path).with_file_name(new_name.trim());
+    run_relocate(app, file_id, dest, overwrite.unwrap_or(false)).await
+}
+
+/// Move a managed file into another allowed directory, keeping its name
+#[tauri::command]
+pub async fn move_managed_file(
+    app: AppHandle,
+    file_id: i64,
+    dest_dir: String,
+    overwrite: Option<bool>,
+) -> Result<ManagedFile, CommandError> {
+    let file = db::with_conn(&app, move |conn| load_file(conn, file_id)).await?;
+    let dir = PathBuf::from(dest_dir);
+    if !dir.is_dir() {
+        return Err(format!("'{}' is not a directory", dir.display()).into());
+    }
+    let name = Path::new(&file.path)
+        .file_name()
+        .map(|n| n.to_os_string())
+        .ok_or("File has no name")?;
+    run_relocate(app, file_id, dir.join(name), overwrite.unwrap_or(false)).await
+}
+
+struct HashedRow {
+    id: i64,
+    path: String,
+    sha256: Option<String>,
+    is_favorite: bool,
+}
+
+/// Collapse rows in one task that point at identical content (same `sha256`) into
+/// the earliest row, carrying over `is_favorite`. Rows whose path is also registered
+/// by another task are left alone. Returns how many rows were removed.
+#[tauri::command]
+pub async fn coalesce_task_files(app: AppHandle, task_id: String) -> Result<u32, String> {
+    let handle = app.clone();
+    let (removed, kept) = db::with_conn(&app, move |conn| {
+        let mut rows = {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id, path, sha256, is_favorite FROM files
+                     WHERE task_id = ?1 ORDER BY created_at ASC, id ASC",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map([&task_id], |row| {
+                    Ok(HashedRow {
+                        id: row.get(0)?,
+                        path: row.get(1)?,
+                        sha256: row.get(2)?,
+                        is_favorite: row.get::<_, i64>(3)? != 0,
+                    })
+                })
+                .map_err(|e| e.to_string())?;
+            rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?
+        };
+
+        // Hash rows registered before the column existed; missing files stay unhashed
+        let mut hashed = Vec::new();
+        for row in rows.iter_mut().filter(|r| r.sha256.is_none()) {
+            if let Ok(digest) = sha256_file(Path::new(&row.path)) {
+                row.sha256 = Some(digest.clone());
+                hashed.push((row.id, digest));
+            }
+        }
+
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        for (id, digest) in &hashed {
+            tx.execute("UPDATE files SET sha256 = ?1 WHERE id = ?2", params![digest, id])
+                .map_err(|e| e.to_string())?;
+        }
+
+        let mut removed = Vec::new();
+        let mut kept = Vec::new();
+        let mut seen: Vec<(&str, usize)> = Vec::new();
+        for (index, row) in rows.iter().enumerate() {
+            let Some(digest) = row.sha256.as_deref() else {
+                continue;
+            };
+            let Some(&(_, keeper)) = seen.iter().find(|(d, _)| *d == digest) else {
+                seen.push((digest, index));
+                continue;
+            };
+            let shared: bool = tx
+                .query_row(
+                    "SELECT EXISTS(SELECT 1 FROM files WHERE path = ?1 AND task_id != ?2)",
+                    params![row.path, task_id],
+                    |r| r.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            if shared {
+                continue;
+            }
+            let keeper_id = rows[keeper].id.to_string();
+            if row.is_favorite && !rows[keeper].is_favorite && !kept.contains(&keeper_id) {
+                tx.execute("UPDATE files SET is_favorite = 1 WHERE id = ?1", [rows[keeper].id])
+                    .map_err(|e| e.to_string())?;
+                kept.push(keeper_id);
+            }
+            tx.execute("DELETE FROM files WHERE id = ?1", [row.id])
+                .map_err(|e| e.to_string())?;
+            removed.push(row.id.to_string());
+        }
+        integrity::record_deletion(&tx, "files", "dedupe", removed.len() as u32)?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok((removed, kept))
+    })
+    .await?;
+
+    let count = removed.len() as u32;
+    if !removed.is_empty() {
+        db::emit_change(&handle, "files", "delete", removed);
+    }
+    if !kept.is_empty() {
+        db::emit_change(&handle, "files", "update", kept);
+    }
+    Ok(count)
+}
@@ -0,0 +1,265 @@
+// Fast path for single-row flag updates such as favorites.
+//
+// A flag write is one UPDATE on a connection the lane keeps open, with its statement
+// prepared once. The UPDATE bumps the row's `row_version` itself and reads it back,
+// so the acknowledgement and the `db://changed` event carry the version the write
+// produced. Other writers get their bump from the `*_row_version` triggers.
+//
+// The lane lock is held across the write and the event, so events for a row leave
+// in version order. The frontend keeps the highest version it has seen per row and
+// drops anything older, which makes rapid toggling settle on the last write.
+// `task_notes` writes share the lane through `FlagLane::write`.
+
+use crate::db;
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    TaskFavorite,
+    FileFavorite,
+}
+
+impl Flag {
+    fn table(self) -> &'static str {
+        match self {
+            Flag::TaskFavorite => "tasks",
+            Flag::FileFavorite => "files",
+        }
+    }
+
+    fn sql(self) -> &'static str {
+        match self {
+            Flag::TaskFavorite => {
+                "UPDATE tasks SET favorite = ?2, row_version = row_version + 1 WHERE id = ?1 RETURNING row_version"
+            }
+            Flag::FileFavorite => {
+                "UPDATE files SET is_favorite = ?2, row_version = row_version + 1 WHERE id = ?1 RETURNING row_version"
+            }
+        }
+    }
+
+    /// The row key as bound in `sql`
+    fn key(self, id: &str) -> Result<Value, String> {
+        match self {
+            Flag::TaskFavorite => Ok(Value::Text(id.to_string())),
+            Flag::FileFavorite => id
+                .parse()
+                .map(Value::Integer)
+                .map_err(|_| format!("Invalid file id: {}", id)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagAck {
+    pub flag: Flag,
+    pub id: String,
+    pub value: bool,
+    /// `row_version` after this write
+    pub row_version: i64,
+}
+
+/// The connection flag writes, and other small single-row writes such as task notes,
+/// go through
+#[derive(Default)]
+pub struct FlagLane(Mutex<Option<Connection>>);
+
+impl FlagLane {
+    /// Run `write` on the lane's connection. Nothing else runs on the lane until it
+    /// returns, so events sent from inside it leave in write order.
+    pub(crate) fn write<T>(
+        &self,
+        open: impl FnOnce() -> Result<Connection, String>,
+        write: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, String> {
+        let mut lane = self.0.lock().map_err(|e| e.to_string())?;
+        let conn = match &mut *lane {
+            Some(conn) => conn,
+            slot => slot.insert(open()?),
+        };
+        write(conn).map_err(|e| {
+            // Reopen on the next write rather than reuse a connection in a bad state
+            *lane = None;
+            e.to_string()
+        })
+    }
+
+    /// Write `value` and call `acknowledged` before the next flag write can start
+    fn apply(
+        &self,
+        open: impl FnOnce() -> Result<Connection, String>,
+        flag: Flag,
+        id: &str,
+        value: bool,
+        acknowledged: impl FnOnce(&FlagAck),
+    ) -> Result<FlagAck, String> {
+        let key = flag.key(id)?;
+        let ack = self.write(open, |conn| {
+            let written: Option<i64> = conn
+                .prepare_cached(flag.sql())?
+                .query_row(params![key, value], |row| row.get(0))
+                .optional()?;
+            let ack = written.map(|row_version| FlagAck {
+                flag,
+                id: id.to_string(),
+                value,
+                row_version,
+            });
+            if let Some(ack) = &ack {
+                acknowledged(ack);
+            }
+            Ok(ack)
+        })?;
+        ack.ok_or_else(|| format!("No row {} in {}", id, flag.table()))
+    }
+}
+
+/// Set a flag on one row right away, returning the row's new version
+#[tauri::command]
+pub async fn set_flag(app: AppHandle, flag: Flag, id: String, value: bool) -> Result<FlagAck, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let lane = app.state::<FlagLane>();
+        lane.apply(
+            || db::open(&app),
+            flag,
+            &id,
+            value,
+            |ack| db::emit_versioned_change(&app, flag.table(), "update", vec![(ack.id.clone(), ack.row_version)]),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn open(path: &PathBuf) -> Result<Connection, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.busy_timeout(Duration::from_secs(5)).map_err(|e| e.to_string())?;
+        Ok(conn)
+    }
+
+    /// A migrated database file with task `t1`, and its starting row version
+    fn task_db(name: &str) -> (PathBuf, Connection, i64) {
+        let path = std::env::temp_dir().join(format!("workany-flags-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut conn = open(&path).unwrap();
+        assert!(crate::migrations::migrate(&mut conn).error.is_none());
+        conn.execute("INSERT INTO tasks (id, prompt) VALUES ('t1', 'p')", []).unwrap();
+        let start = conn
+            .query_row("SELECT row_version FROM tasks WHERE id = 't1'", [], |row| row.get(0))
+            .unwrap();
+        (path, conn, start)
+    }
+
+    fn stored(conn: &Connection) -> (bool, i64) {
+        conn.query_row("SELECT favorite, row_version FROM tasks WHERE id = 't1'", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap()
+    }
+
+    /// Toggle `t1` from `threads` threads at once, `per_thread` times each. Returns
+    /// the acknowledgements callers got and the events, in the order they were sent.
+    fn toggle(lane: &Arc<FlagLane>, path: &PathBuf, threads: usize, per_thread: usize) -> (Vec<FlagAck>, Vec<FlagAck>) {
+        let events = Arc::new(Mutex::new(Vec::<FlagAck>::new()));
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| {
+                let (lane, events, path) = (lane.clone(), events.clone(), path.clone());
+                std::thread::spawn(move || {
+                    (0..per_thread)
+                        .map(|i| {
+                            lane.apply(|| open(&path), Flag::TaskFavorite, "t1", (thread + i) % 2 == 0, |ack| {
+                                events.lock().unwrap().push(ack.clone())
+                            })
+                            .unwrap()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let acks = handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect();
+        let events = Arc::try_unwrap(events).unwrap().into_inner().unwrap();
+        (acks, events)
+    }
+
+    #[test]
+    fn two_hundred_interleaved_toggles_end_on_the_last_acknowledged_version() {
+        let (path, conn, start) = task_db("stress");
+        let lane = Arc::new(FlagLane::default());
+        let (mut acks, events) = toggle(&lane, &path, 8, 25);
+
+        assert_eq!(events.len(), 200);
+        // No event goes back to, or repeats, an older version
+        assert!(events.windows(2).all(|pair| pair[0].row_version < pair[1].row_version));
+        assert_eq!(events.first().unwrap().row_version, start + 1);
+
+        // Every caller was told exactly the version its event carried
+        acks.sort_by_key(|ack| ack.row_version);
+        let pairs: Vec<(i64, bool)> = acks.iter().map(|ack| (ack.row_version, ack.value)).collect();
+        let sent: Vec<(i64, bool)> = events.iter().map(|ack| (ack.row_version, ack.value)).collect();
+        assert_eq!(pairs, sent);
+
+        let last = events.last().unwrap();
+        assert_eq!(stored(&conn), (last.value, last.row_version));
+        assert_eq!(last.row_version, start + 200);
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn writes_outside_the_lane_still_move_the_version_forward() {
+        let (path, conn, start) = task_db("mixed");
+        let lane = Arc::new(FlagLane::default());
+        // Writes outside the lane, as the webview makes them
+        let raw_path = path.clone();
+        let raw = std::thread::spawn(move || {
+            let conn = open(&raw_path).unwrap();
+            for _ in 0..20 {
+                conn.execute("UPDATE tasks SET duration = COALESCE(duration, 0) + 1 WHERE id = 't1'", [])
+                    .unwrap();
+            }
+        });
+        let (_, events) = toggle(&lane, &path, 4, 50);
+        raw.join().unwrap();
+
+        assert_eq!(events.len(), 200);
+        assert!(events.windows(2).all(|pair| pair[0].row_version < pair[1].row_version));
+        let (favorite, version) = stored(&conn);
+        assert_eq!(favorite, events.last().unwrap().value);
+        assert_eq!(version, start + 220);
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_missing_row_is_an_error_and_sends_nothing() {
+        let (path, conn, start) = task_db("missing");
+        let lane = FlagLane::default();
+        let sent = Mutex::new(0);
+        let err = lane
+            .apply(|| open(&path), Flag::TaskFavorite, "nope", true, |_| *sent.lock().unwrap() += 1)
+            .unwrap_err();
+        assert_eq!(err, "No row nope in tasks");
+        assert!(lane
+            .apply(|| open(&path), Flag::FileFavorite, "abc", true, |_| *sent.lock().unwrap() += 1)
+            .is_err());
+        assert_eq!(*sent.lock().unwrap(), 0);
+        assert_eq!(stored(&conn).1, start);
+
+        drop(conn);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,286 @@
+// Focus-follows-task: when `auto_focus_on_attention` is on, a task that needs the
+// user (a permission request, a question, a failure) is brought to the foreground
+// with a `focus://switch-task` event.
+//
+// Requests come from a watcher on new messages and task failures, and from the
+// frontend for approval prompts that never reach the database. The arbiter allows
+// at most one switch per `SWITCH_INTERVAL` and none within `TYPING_GRACE` of the
+// user typing; a request that arrives then is held as pending and retried, unless
+// the user navigates somewhere themselves first. Every switch is kept in a short
+// history so users can see why the view jumped.
+
+use crate::message_types::MessageType;
+use crate::{background, db, events, settings, window};
+use rusqlite::Connection;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+const ENABLED_KEY: &str = "auto_focus_on_attention";
+const UNHIDE_KEY: &str = "auto_focus_unhide";
+const SWITCH_INTERVAL: Duration = Duration::from_secs(10);
+const TYPING_GRACE: Duration = Duration::from_secs(5);
+const TICK: Duration = Duration::from_secs(1);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "focus",
+    interval: TICK,
+    coalescable: true,
+};
+const MAX_HISTORY: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusReason {
+    TaskFailed,
+    Question,
+    ApprovalRequest,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FocusRecord {
+    pub task_id: String,
+    pub reason: FocusReason,
+    /// Unix ms
+    pub switched_at: i64,
+    /// Whether the window was brought back from the tray as well
+    pub unhid: bool,
+}
+
+#[derive(Debug, Clone)]
+struct FocusRequest {
+    task_id: String,
+    reason: FocusReason,
+}
+
+#[derive(Default)]
+struct Arbiter {
+    last_switch: Option<Instant>,
+    last_typed: Option<Instant>,
+    pending: Option<FocusRequest>,
+    history: VecDeque<FocusRecord>,
+    // Watcher position
+    last_message_id: Option<i64>,
+    running: HashSet<String>,
+}
+
+#[derive(Default)]
+pub struct FocusArbiter(Mutex<Arbiter>);
+
+impl Arbiter {
+    fn suppressed(&self, now: Instant) -> bool {
+        let within = |at: Option<Instant>, window: Duration| at.is_some_and(|at| now.duration_since(at) < window);
+        within(self.last_switch, SWITCH_INTERVAL) || within(self.last_typed, TYPING_GRACE)
+    }
+
+    /// Keep the more urgent of the pending request and `request`
+    fn hold(&mut self, request: FocusRequest) {
+        match &self.pending {
+            Some(pending) if pending.reason > request.reason => {}
+            _ => self.pending = Some(request),
+        }
+    }
+}
+
+fn enabled(conn: &Connection) -> Result<bool, String> {
+    settings::get_or(conn, ENABLED_KEY, false)
+}
+
+fn switch(app: &AppHandle, arbiter: &mut Arbiter, request: FocusRequest, unhide: bool) {
+    if unhide {
+        window::show_main_window(app);
+    }
+    println!("[Focus] Switching to task {} ({:?})", request.task_id, request.reason);
+    arbiter.last_switch = Some(Instant::now());
+    arbiter.history.push_back(FocusRecord {
+        task_id: request.task_id.clone(),
+        reason: request.reason,
+        switched_at: chrono::Utc::now().timestamp_millis(),
+        unhid: unhide,
+    });
+    while arbiter.history.len() > MAX_HISTORY {
+        arbiter.history.pop_front();
+    }
+    events::emit_typed(
+        app,
+        events::FocusSwitchTask {
+            task_id: request.task_id,
+            reason: request.reason,
+        },
+    );
+}
+
+/// Switch now if allowed, otherwise hold the request for a later tick
+fn submit(app: &AppHandle, conn: &Connection, arbiter: &mut Arbiter, request: FocusRequest) -> Result<(), String> {
+    if !enabled(conn)? {
+        return Ok(());
+    }
+    if arbiter.suppressed(Instant::now()) {
+        arbiter.hold(request);
+        return Ok(());
+    }
+    let unhide = settings::get_or(conn, UNHIDE_KEY, false)?;
+    switch(app, arbiter, request, unhide);
+    Ok(())
+}
+
+/// Whether a message asks something of the user
+pub(crate) fn attention_reason(
+    kind: &MessageType,
+    tool_name: Option<&str>,
+    subtype: Option<&str>,
+) -> Option<FocusReason> {
+    match (kind, tool_name, subtype) {
+        (MessageType::PermissionRequest, _, _) | (_, _, Some("permission_request")) => {
+            Some(FocusReason::ApprovalRequest)
+        }
+        (MessageType::ToolUse, Some("AskUserQuestion"), _) | (_, _, Some("question")) => Some(FocusReason::Question),
+        _ => None,
+    }
+}
+
+/// Attention requests from messages inserted since the last tick and tasks that
+/// went from running to error
+fn collect(conn: &Connection, arbiter: &mut Arbiter) -> Result<Vec<FocusRequest>, String> {
+    let mut requests = Vec::new();
+    match arbiter.last_message_id {
+        None => {
+            arbiter.last_message_id = Some(
+                conn.query_row("SELECT COALESCE(MAX(id), 0) FROM messages", [], |row| row.get(0))
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+        Some(after) => {
+            let mut stmt = conn
+                .prepare_cached(
+                    "SELECT id, task_id, type, tool_name, subtype FROM messages WHERE id > ?1 ORDER BY id",
+                )
+                .map_err(|e| e.to_string())?;
+            let mut rows = stmt.query([after]).map_err(|e| e.to_string())?;
+            while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+                let id: i64 = row.get(0).map_err(|e| e.to_string())?;
+                let task_id: String = row.get(1).map_err(|e| e.to_string())?;
+                let kind: MessageType = row.get(2).map_err(|e| e.to_string())?;
+                let tool_name: Option<String> = row.get(3).map_err(|e| e.to_string())?;
+                let subtype: Option<String> = row.get(4).map_err(|e| e.to_string())?;
+                arbiter.last_message_id = Some(id);
+                let Some(reason) = attention_reason(&kind, tool_name.as_deref(), subtype.as_deref()) else {
+                    continue;
+                };
+                requests.push(FocusRequest { task_id, reason });
+            }
+        }
+    }
+
+    let mut stmt = conn
+        .prepare_cached("SELECT id FROM tasks WHERE status = 'running'")
+        .map_err(|e| e.to_string())?;
+    let running: HashSet<String> = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    for task_id in arbiter.running.difference(&running) {
+        let status: Option<String> = conn
+            .query_row("SELECT status FROM tasks WHERE id = ?1", [task_id], |row| row.get(0))
+            .ok();
+        if status.as_deref() == Some("error") {
+            requests.push(FocusRequest {
+                task_id: task_id.clone(),
+                reason: FocusReason::TaskFailed,
+            });
+        }
+    }
+    arbiter.running = running;
+    Ok(requests)
+}
+
+fn tick(app: &AppHandle) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let state = app.state::<FocusArbiter>();
+    let mut arbiter = state.0.lock().map_err(|e| e.to_string())?;
+    // The watcher keeps its position while disabled, so turning the setting on
+    // doesn't replay old messages
+    let requests = collect(&conn, &mut arbiter)?;
+    if !enabled(&conn)? {
+        arbiter.pending = None;
+        return Ok(());
+    }
+    // Only the most urgent request of a tick can win; the rest would be debounced
+    if let Some(request) = requests.into_iter().max_by_key(|r| r.reason) {
+        arbiter.hold(request);
+    }
+    if !arbiter.suppressed(Instant::now()) {
+        if let Some(request) = arbiter.pending.take() {
+            let unhide = settings::get_or(&conn, UNHIDE_KEY, false)?;
+            switch(app, &mut arbiter, request, unhide);
+        }
+    }
+    Ok(())
+}
+
+/// Start watching for tasks that need attention
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &ACTIVITY);
+        if let Err(e) = tick(&app) {
+            eprintln!("[Focus] Tick failed: {}", e);
+        }
+    });
+}
+
+/// Ask for a task to be brought forward, for attention the database doesn't see
+/// (approval prompts held in the frontend)
+#[tauri::command]
+pub async fn request_task_focus(app: AppHandle, task_id: String, reason: FocusReason) -> Result<(), String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let state = handle.state::<FocusArbiter>();
+        let mut arbiter = state.0.lock().map_err(|e| e.to_string())?;
+        submit(&handle, conn, &mut arbiter, FocusRequest { task_id, reason })
+    })
+    .await
+}
+
+/// Activity heartbeat: the user typed, so hold off auto-switching for a moment
+#[tauri::command]
+pub fn report_user_typing(state: State<'_, FocusArbiter>) {
+    if let Ok(mut arbiter) = state.0.lock() {
+        arbiter.last_typed = Some(Instant::now());
+    }
+}
+
+/// The user navigated themselves; drop any auto-switch still waiting
+#[tauri::command]
+pub fn report_manual_navigation(state: State<'_, FocusArbiter>) {
+    if let Ok(mut arbiter) = state.0.lock() {
+        if let Some(pending) = arbiter.pending.take() {
+            println!("[Focus] Dropped pending switch to {} after manual navigation", pending.task_id);
+        }
+    }
+}
+
+/// Recent auto-switches, newest first
+#[tauri::command]
+pub fn get_focus_history(state: State<'_, FocusArbiter>) -> Vec<FocusRecord> {
+    state
+        .0
+        .lock()
+        .map(|arbiter| arbiter.history.iter().rev().cloned().collect())
+        .unwrap_or_default()
+}
+
+#[tauri::command]
+pub async fn set_auto_focus(app: AppHandle, enabled: bool, unhide: Option<bool>) -> Result<(), String> {
+    db::with_conn(&app, move |conn| {
+        settings::set(conn, ENABLED_KEY, &enabled)?;
+        if let Some(unhide) = unhide {
+            settings::set(conn, UNHIDE_KEY, &unhide)?;
+        }
+        Ok(())
+    })
+    .await
+}
@@ -0,0 +1,420 @@
+// Focus sessions: the work done in one sitting, across however many sessions and
+// tasks it spanned.
+//
+// A focus session opens with the first task created after a gap of at least
+// `focus_gap_minutes` and collects later tasks until the next such gap, or until
+// `end_focus_session`. Gaps are measured from the session's last activity: task
+// creation, updates to its tasks, and running tasks (the idle module's notion of
+// busy). `track` runs on a background tick and before every read. It assigns tasks
+// by creation time rather than by when they were seen, so a task created shortly
+// before a session's start (within `LEAD_IN_SECS`) or seen late still lands in the
+// right session.
+//
+// Aggregates (cost, duration, deliverables) are computed when read, so they follow
+// later changes to the tasks.
+
+use crate::timeline::{self, TimelineRange};
+use crate::{background, db, idle, settings};
+use chrono::Local;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::Serialize;
+use std::time::Duration;
+use tauri::AppHandle;
+
+const GAP_KEY: &str = "focus_gap_minutes";
+const DEFAULT_GAP_MINUTES: u32 = 30;
+const MIN_GAP_MINUTES: u32 = 5;
+const MAX_GAP_MINUTES: u32 = 8 * 60;
+/// A task created this long before a focus session's start still belongs to it
+const LEAD_IN_SECS: i64 = 60;
+const ACTIVITY: background::Activity = background::Activity {
+    name: "focus_sessions",
+    interval: Duration::from_secs(60),
+    coalescable: true,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FocusDeliverable {
+    pub file_id: i64,
+    pub name: String,
+    pub file_type: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FocusSession {
+    pub id: i64,
+    /// Unix seconds
+    pub started_epoch: i64,
+    pub last_activity_epoch: i64,
+    /// `None` while the session is open
+    pub ended_epoch: Option<i64>,
+    /// `gap` or `manual`
+    pub end_reason: Option<String>,
+    /// Oldest first
+    pub task_ids: Vec<String>,
+    pub cost: f64,
+    /// Sum of the tasks' `duration`
+    pub duration: i64,
+    pub deliverables: Vec<FocusDeliverable>,
+}
+
+fn gap_minutes(conn: &Connection) -> Result<u32, String> {
+    Ok(settings::get_or(conn, GAP_KEY, DEFAULT_GAP_MINUTES)?.clamp(MIN_GAP_MINUTES, MAX_GAP_MINUTES))
+}
+
+/// The latest session whose window contains `created`: from `LEAD_IN_SECS` before
+/// its start until `gap` after its last activity, or until it was ended by hand
+fn containing_session(tx: &Transaction, created: i64, gap: i64) -> Result<Option<i64>, String> {
+    tx.query_row(
+        "SELECT id FROM focus_sessions
+         WHERE started_epoch - ?2 <= ?1
+           AND ?1 <= CASE WHEN end_reason = 'manual' THEN ended_epoch ELSE last_activity_epoch + ?3 END
+         ORDER BY started_epoch DESC LIMIT 1",
+        params![created, LEAD_IN_SECS, gap],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// Start a session at `created`, ending the open one if it started earlier. A task
+/// older than the open session gets a session of its own that is already ended.
+fn start_session(tx: &Transaction, created: i64) -> Result<i64, String> {
+    tx.execute(
+        "UPDATE focus_sessions SET ended_epoch = last_activity_epoch, end_reason = 'gap'
+         WHERE ended_epoch IS NULL AND started_epoch <= ?1",
+        [created],
+    )
+    .map_err(|e| e.to_string())?;
+    let still_open: bool = tx
+        .query_row("SELECT EXISTS(SELECT 1 FROM focus_sessions WHERE ended_epoch IS NULL)", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO focus_sessions (started_epoch, last_activity_epoch, ended_epoch, end_reason)
+         VALUES (?1, ?1, CASE WHEN ?2 THEN ?1 END, CASE WHEN ?2 THEN 'gap' END)",
+        params![created, still_open],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(tx.last_insert_rowid())
+}
+
+/// Assign new tasks to focus sessions and end the open session once it has been
+/// idle for `gap` seconds. `busy` (tasks running) keeps it open.
+fn track(conn: &mut Connection, now: i64, gap: i64, busy: bool) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let latest_start: Option<i64> = tx
+        .query_row("SELECT MAX(started_epoch) FROM focus_sessions", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    // History from before tracking started isn't backfilled
+    let since = latest_start.unwrap_or(now - gap) - LEAD_IN_SECS;
+    let tasks: Vec<(String, i64)> = {
+        let mut stmt = tx
+            .prepare(
+                "SELECT id, created_epoch FROM tasks t
+                 WHERE created_epoch >= ?1
+                   AND NOT EXISTS (SELECT 1 FROM focus_session_tasks f WHERE f.task_id = t.id)
+                 ORDER BY created_epoch, id",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([since], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    for (task_id, created) in tasks {
+        let id = match containing_session(&tx, created, gap)? {
+            Some(id) => id,
+            None => start_session(&tx, created)?,
+        };
+        tx.execute(
+            "INSERT INTO focus_session_tasks (focus_session_id, task_id) VALUES (?1, ?2)",
+            params![id, task_id],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE focus_sessions SET started_epoch = MIN(started_epoch, ?2),
+                    last_activity_epoch = MAX(last_activity_epoch, ?2),
+                    ended_epoch = CASE WHEN end_reason = 'gap' THEN MAX(ended_epoch, ?2) ELSE ended_epoch END
+             WHERE id = ?1",
+            params![id, created],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    let open: Option<(i64, i64)> = tx
+        .query_row(
+            "SELECT id, last_activity_epoch FROM focus_sessions WHERE ended_epoch IS NULL
+             ORDER BY started_epoch DESC LIMIT 1",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some((id, last_activity)) = open {
+        let updated: Option<i64> = tx
+            .query_row(
+                "SELECT MAX(CAST(strftime('%s', t.updated_at) AS INTEGER))
+                 FROM focus_session_tasks f JOIN tasks t ON t.id = f.task_id WHERE f.focus_session_id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        let last_activity = if busy {
+            now
+        } else {
+            last_activity.max(updated.unwrap_or(last_activity).min(now))
+        };
+        let sql = if now - last_activity > gap {
+            "UPDATE focus_sessions SET last_activity_epoch = ?2, ended_epoch = ?2, end_reason = 'gap' WHERE id = ?1"
+        } else {
+            "UPDATE focus_sessions SET last_activity_epoch = ?2 WHERE id = ?1"
+        };
+        tx.execute(sql, params![id, last_activity]).map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// `track` with the configured gap and the idle module's view of running tasks
+fn track_now(conn: &mut Connection) -> Result<(), String> {
+    let minutes = gap_minutes(conn)?;
+    let (_, busy) = idle::activity(conn, minutes)?;
+    track(conn, chrono::Utc::now().timestamp(), minutes as i64 * 60, busy)
+}
+
+fn load(
+    conn: &Connection,
+    id: i64,
+    started_epoch: i64,
+    last_activity_epoch: i64,
+    ended_epoch: Option<i64>,
+    end_reason: Option<String>,
+) -> Result<FocusSession, String> {
+    let mut tasks = conn
+        .prepare_cached(
+            "SELECT t.id, t.cost, t.duration FROM focus_session_tasks f JOIN tasks t ON t.id = f.task_id
+             WHERE f.focus_session_id = ?1 ORDER BY t.created_epoch, t.id",
+        )
+        .map_err(|e| e.to_string())?;
+    let tasks: Vec<(String, Option<f64>, Option<i64>)> = tasks
+        .query_map([id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    let mut deliverables = conn
+        .prepare_cached(
+            "SELECT fl.id, fl.name, fl.type FROM files fl JOIN focus_session_tasks f ON f.task_id = fl.task_id
+             WHERE f.focus_session_id = ?1
+               AND fl.type IN ('document', 'presentation', 'spreadsheet', 'website')
+               AND fl.source_file_id IS NULL
+             ORDER BY fl.created_epoch, fl.id",
+        )
+        .map_err(|e| e.to_string())?;
+    let deliverables = deliverables
+        .query_map([id], |row| {
+            Ok(FocusDeliverable {
+                file_id: row.get(0)?,
+                name: row.get(1)?,
+                file_type: row.get(2)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(FocusSession {
+        id,
+        started_epoch,
+        last_activity_epoch,
+        ended_epoch,
+        end_reason,
+        cost: tasks.iter().filter_map(|t| t.1).sum(),
+        duration: tasks.iter().filter_map(|t| t.2).sum(),
+        task_ids: tasks.into_iter().map(|t| t.0).collect(),
+        deliverables,
+    })
+}
+
+/// Focus sessions that started in `[start, end)` Unix seconds, oldest first
+pub(crate) fn in_range(conn: &Connection, start: i64, end: i64) -> Result<Vec<FocusSession>, String> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT id, started_epoch, last_activity_epoch, ended_epoch, end_reason FROM focus_sessions
+             WHERE started_epoch >= ?1 AND started_epoch < ?2 ORDER BY started_epoch, id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows: Vec<(i64, i64, i64, Option<i64>, Option<String>)> = stmt
+        .query_map(params![start, end], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    rows.into_iter()
+        .map(|(id, started, last, ended, reason)| load(conn, id, started, last, ended, reason))
+        .collect()
+}
+
+/// Bring focus sessions up to date; `get_timeline_buckets` calls this before
+/// grouping by them
+pub(crate) fn refresh(conn: &mut Connection) -> Result<(), String> {
+    track_now(conn)
+}
+
+/// Start assigning tasks to focus sessions in the background
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &ACTIVITY);
+        if let Err(e) = db::open(&app).and_then(|mut conn| track_now(&mut conn)) {
+            eprintln!("[FocusSessions] Tracking failed: {}", e);
+        }
+    });
+}
+
+/// Focus sessions that started in a range of local dates (the last 90 days by
+/// default), oldest first
+#[tauri::command]
+pub async fn list_focus_sessions(app: AppHandle, range: Option<TimelineRange>) -> Result<Vec<FocusSession>, String> {
+    let range = range.unwrap_or_default();
+    db::with_conn(&app, move |conn| {
+        track_now(conn)?;
+        let (from, to) = timeline::resolve_range(&range, Local::now().date_naive())?;
+        let (start, _) = timeline::day_bounds(&Local, from);
+        let (_, end) = timeline::day_bounds(&Local, to);
+        in_range(conn, start, end)
+    })
+    .await
+}
+
+/// End the open focus session now; the next task starts a new one. Returns the
+/// ended session, or `None` if none was open.
+#[tauri::command]
+pub async fn end_focus_session(app: AppHandle) -> Result<Option<FocusSession>, String> {
+    db::with_conn(&app, move |conn| {
+        track_now(conn)?;
+        let now = chrono::Utc::now().timestamp();
+        let open: Option<(i64, i64, i64)> = conn
+            .query_row(
+                "SELECT id, started_epoch, last_activity_epoch FROM focus_sessions WHERE ended_epoch IS NULL
+                 ORDER BY started_epoch DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some((id, started, last_activity)) = open else {
+            return Ok(None);
+        };
+        conn.execute(
+            "UPDATE focus_sessions SET ended_epoch = ?2, end_reason = 'manual' WHERE id = ?1",
+            params![id, now],
+        )
+        .map_err(|e| e.to_string())?;
+        load(conn, id, started, last_activity, Some(now), Some("manual".to_string())).map(Some)
+    })
+    .await
+}
+
+/// Set the idle gap that ends a focus session, in minutes; `None` restores the
+/// default
+#[tauri::command]
+pub async fn set_focus_gap(app: AppHandle, minutes: Option<u32>) -> Result<(), String> {
+    if minutes.is_some_and(|m| !(MIN_GAP_MINUTES..=MAX_GAP_MINUTES).contains(&m)) {
+        return Err(format!(
+            "The focus gap must be between {} and {} minutes",
+            MIN_GAP_MINUTES, MAX_GAP_MINUTES
+        ));
+    }
+    db::with_conn(&app, move |conn| match minutes {
+        Some(m) => settings::set(conn, GAP_KEY, &m),
+        None => settings::remove(conn, GAP_KEY),
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAP: i64 = 30 * 60;
+    const T0: i64 = 1_700_000_000;
+
+    fn db() -> Connection {
+        crate::migrations::test_db()
+    }
+
+    fn task(conn: &Connection, id: &str, at: i64) {
+        conn.execute(
+            "INSERT INTO tasks (id, prompt, status, cost, created_at, updated_at)
+             VALUES (?1, 'p', 'completed', 0.5, datetime(?2, 'unixepoch'), datetime(?2, 'unixepoch'))",
+            params![id, at],
+        )
+        .unwrap();
+    }
+
+    fn sessions(conn: &Connection) -> Vec<FocusSession> {
+        in_range(conn, 0, i64::MAX).unwrap()
+    }
+
+    #[test]
+    fn gaps_split_sessions_and_late_tasks_land_by_creation_time() {
+        let mut conn = db();
+        track(&mut conn, T0, GAP, false).unwrap();
+        task(&conn, "a", T0 + 10);
+        task(&conn, "b", T0 + 600);
+        track(&mut conn, T0 + 700, GAP, false).unwrap();
+        // Seen after the session started, created 30s before it
+        task(&conn, "early", T0 - 20);
+        task(&conn, "c", T0 + 600 + GAP + 60);
+        track(&mut conn, T0 + 600 + GAP + 120, GAP, false).unwrap();
+
+        let all = sessions(&conn);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].task_ids, vec!["early", "a", "b"]);
+        assert_eq!(all[0].started_epoch, T0 - 20);
+        assert_eq!(all[0].ended_epoch, Some(T0 + 600));
+        assert_eq!(all[0].end_reason.as_deref(), Some("gap"));
+        assert_eq!(all[0].cost, 1.5);
+        assert_eq!(all[1].task_ids, vec!["c"]);
+        assert_eq!(all[1].ended_epoch, None);
+    }
+
+    #[test]
+    fn idle_sessions_close_unless_tasks_are_running() {
+        let mut conn = db();
+        track(&mut conn, T0, GAP, false).unwrap();
+        task(&conn, "a", T0 + 10);
+        track(&mut conn, T0 + 60, GAP, false).unwrap();
+        track(&mut conn, T0 + GAP + 600, GAP, true).unwrap();
+        assert_eq!(sessions(&conn)[0].ended_epoch, None);
+        assert_eq!(sessions(&conn)[0].last_activity_epoch, T0 + GAP + 600);
+
+        track(&mut conn, T0 + 2 * GAP + 700, GAP, false).unwrap();
+        let all = sessions(&conn);
+        assert_eq!(all[0].ended_epoch, Some(T0 + GAP + 600));
+        assert_eq!(all[0].end_reason.as_deref(), Some("gap"));
+    }
+
+    #[test]
+    fn manual_ends_hold_and_deliverables_are_collected() {
+        let mut conn = db();
+        track(&mut conn, T0, GAP, false).unwrap();
+        task(&conn, "a", T0 + 10);
+        conn.execute_batch(
+            "INSERT INTO files (task_id, name, type, path) VALUES ('a', 'plan.md', 'document', '/tmp/plan.md');
+             INSERT INTO files (task_id, name, type, path) VALUES ('a', 'log.txt', 'text', '/tmp/log.txt');",
+        )
+        .unwrap();
+        track(&mut conn, T0 + 60, GAP, false).unwrap();
+        conn.execute("UPDATE focus_sessions SET ended_epoch = ?1, end_reason = 'manual'", [T0 + 70])
+            .unwrap();
+        task(&conn, "b", T0 + 80);
+        track(&mut conn, T0 + 90, GAP, false).unwrap();
+
+        let all = sessions(&conn);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].task_ids, vec!["a"]);
+        assert_eq!(all[0].deliverables.len(), 1);
+        assert_eq!(all[0].deliverables[0].name, "plan.md");
+        assert_eq!(all[1].task_ids, vec!["b"]);
+    }
+}
@@ -0,0 +1,256 @@
+// Conversation branching: fork a task at a message into a new task in the same
+// session, linked back through `forked_from_task` / `forked_from_message`.
+//
+// The fork copies the message history up to the fork point as new rows. Tool calls
+// are never split: if a call in the copied range has its result after the fork
+// point (or no result yet), the fork stops just before that call.
+
+use crate::message_types::MessageType;
+use crate::{db, prompts};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ForkedTask {
+    pub task_id: String,
+    pub session_id: String,
+    pub task_index: i64,
+    pub prompt: String,
+    pub forked_from_task: String,
+    /// The message the caller asked to fork from
+    pub forked_from_message: i64,
+    /// Last message actually copied; earlier than requested when a tool call would
+    /// otherwise have been split from its result
+    pub copied_through_message: Option<i64>,
+    pub copied_messages: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ForkNode {
+    pub task_id: String,
+    pub prompt: String,
+    pub status: String,
+    pub task_index: Option<i64>,
+    pub forked_from_message: Option<i64>,
+    pub created_at: String,
+    pub children: Vec<ForkNode>,
+}
+
+struct SourceMessage {
+    id: i64,
+    kind: MessageType,
+    tool_use_id: Option<String>,
+}
+
+/// Id of the last message to copy so no tool call is separated from its result
+fn pairing_safe_end(messages: &[SourceMessage]) -> Option<i64> {
+    let results: HashSet<&str> = messages
+        .iter()
+        .filter(|m| m.kind == MessageType::ToolResult)
+        .filter_map(|m| m.tool_use_id.as_deref())
+        .collect();
+    let first_unpaired = messages.iter().position(|m| {
+        m.kind == MessageType::ToolUse && m.tool_use_id.as_deref().is_some_and(|id| !results.contains(id))
+    });
+    let end = first_unpaired.unwrap_or(messages.len());
+    end.checked_sub(1).map(|i| messages[i].id)
+}
+
+/// Id and index for the next task in `session_id`
+pub(crate) fn next_task_slot(conn: &Connection, session_id: &str) -> Result<(String, i64), String> {
+    let task_index: i64 = conn
+        .query_row(
+            "SELECT COALESCE(MAX(task_index), 0) + 1 FROM tasks WHERE session_id = ?1",
+            [session_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    // Same id shape the task input uses for follow-up tasks in a session, falling back
+    // to the timestamp ids the home page uses if that one is taken
+    let id = format!("{}-task-{:02}", session_id, task_index);
+    let taken: bool = conn
+        .query_row("SELECT EXISTS(SELECT 1 FROM tasks WHERE id = ?1)", [&id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if taken {
+        return Ok((chrono::Utc::now().timestamp_millis().to_string(), task_index));
+    }
+    Ok((id, task_index))
+}
+
+fn fork(conn: &mut Connection, task_id: &str, from_message_id: i64) -> Result<ForkedTask, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let (session_id, prompt): (Option<String>, String) = tx
+        .query_row(
+            &format!("SELECT session_id, {} FROM tasks WHERE id = ?1", prompts::FULL_PROMPT_SQL),
+            [task_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Task '{}' not found", task_id))?;
+    let session_id = session_id.ok_or_else(|| format!("Task '{}' has no session", task_id))?;
+
+    let in_task: bool = tx
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM messages WHERE id = ?1 AND task_id = ?2)",
+            params![from_message_id, task_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if !in_task {
+        return Err(format!("Message {} does not belong to task '{}'", from_message_id, task_id));
+    }
+
+    let messages: Vec<SourceMessage> = {
+        let mut stmt = tx
+            .prepare("SELECT id, type, tool_use_id FROM messages WHERE task_id = ?1 AND id <= ?2 ORDER BY id")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![task_id, from_message_id], |row| {
+                Ok(SourceMessage {
+                    id: row.get(0)?,
+                    kind: row.get(1)?,
+                    tool_use_id: row.get(2)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    let copied_through = pairing_safe_end(&messages);
+
+    let (new_task_id, task_index) = next_task_slot(&tx, &session_id)?;
+    let (stored_prompt, overflow) = prompts::split(&tx, &prompt)?;
+    tx.execute(
+        "INSERT INTO tasks (id, session_id, task_index, prompt, status, forked_from_task, forked_from_message)
+         VALUES (?1, ?2, ?3, ?4, 'stopped', ?5, ?6)",
+        params![new_task_id, session_id, task_index, stored_prompt, task_id, from_message_id],
+    )
+    .map_err(|e| e.to_string())?;
+    if overflow {
+        prompts::attach_overflow(&tx, &new_task_id, &prompt)?;
+    }
+    tx.execute(
+        "UPDATE sessions SET task_count = MAX(task_count, ?1), updated_at = datetime('now') WHERE id = ?2",
+        params![task_index, session_id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut copied = 0;
+    if let Some(end) = copied_through {
+        // Tool use ids are unique across the database (tool_invocations is keyed on
+        // them), so the copies get the same suffix on both halves of each pair
+        let suffix = format!("_fork{:02}", task_index);
+        tx.execute(
+            "INSERT INTO messages (task_id, type, content, tool_name, tool_input, tool_output, tool_use_id,
+                                   subtype, error_message, attachments, redacted, created_at)
+             SELECT ?1, type, content, tool_name, tool_input, tool_output,
+                    CASE WHEN tool_use_id IS NOT NULL THEN tool_use_id || ?2 END,
+                    subtype, error_message, attachments, redacted, created_at
+             FROM messages WHERE task_id = ?3 AND id <= ?4 ORDER BY id",
+            params![new_task_id, suffix, task_id, end],
+        )
+        .map_err(|e| e.to_string())?;
+        copied = messages.iter().filter(|m| m.id <= end).count();
+    }
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(ForkedTask {
+        task_id: new_task_id,
+        session_id,
+        task_index,
+        prompt,
+        forked_from_task: task_id.to_string(),
+        forked_from_message: from_message_id,
+        copied_through_message: copied_through,
+        copied_messages: copied,
+    })
+}
+
+/// Depth-first build of the fork tree below `id`, skipping ids already visited
+fn build_tree(
+    id: &str,
+    stmt: &mut rusqlite::Statement,
+    children_stmt: &mut rusqlite::Statement,
+    visited: &mut HashSet<String>,
+) -> Result<Option<ForkNode>, String> {
+    if !visited.insert(id.to_string()) {
+        return Ok(None);
+    }
+    let node = stmt
+        .query_row([id], |row| {
+            Ok(ForkNode {
+                task_id: row.get(0)?,
+                prompt: row.get(1)?,
+                status: row.get(2)?,
+                task_index: row.get(3)?,
+                forked_from_message: row.get(4)?,
+                created_at: row.get(5)?,
+                children: Vec::new(),
+            })
+        })
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some(mut node) = node else { return Ok(None) };
+    let child_ids: Vec<String> = children_stmt
+        .query_map([id], |row| row.get(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    for child in child_ids {
+        if let Some(child) = build_tree(&child, stmt, children_stmt, visited)? {
+            node.children.push(child);
+        }
+    }
+    Ok(Some(node))
+}
+
+/// Create a new task in the same session holding the conversation up to
+/// `from_message_id`, ready to be continued from there
+#[tauri::command]
+pub async fn fork_task(app: tauri::AppHandle, task_id: String, from_message_id: i64) -> Result<ForkedTask, String> {
+    let handle = app.clone();
+    let forked = db::with_conn(&app, move |conn| fork(conn, &task_id, from_message_id)).await?;
+    db::emit_change(&handle, "tasks", "insert", vec![forked.task_id.clone()]);
+    Ok(forked)
+}
+
+/// All tasks connected to `task_id` through fork links, rooted at the original task
+#[tauri::command]
+pub async fn get_fork_tree(app: tauri::AppHandle, task_id: String) -> Result<ForkNode, String> {
+    db::with_conn(&app, move |conn| {
+        let parent_of = |id: &str| -> Result<Option<String>, String> {
+            conn.query_row("SELECT forked_from_task FROM tasks WHERE id = ?1", [id], |row| row.get(0))
+                .optional()
+                .map_err(|e| e.to_string())
+                .map(Option::flatten)
+        };
+        let mut root = task_id.clone();
+        let mut seen = HashSet::from([root.clone()]);
+        while let Some(parent) = parent_of(&root)? {
+            // A parent that was deleted ends the walk; so does a cycle
+            let exists: bool = conn
+                .query_row("SELECT EXISTS(SELECT 1 FROM tasks WHERE id = ?1)", [&parent], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            if !exists || !seen.insert(parent.clone()) {
+                break;
+            }
+            root = parent;
+        }
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, prompt, status, task_index, forked_from_message, created_at
+                 FROM tasks WHERE id = ?1",
+            )
+            .map_err(|e| e.to_string())?;
+        let mut children_stmt = conn
+            .prepare("SELECT id FROM tasks WHERE forked_from_task = ?1 ORDER BY created_at, id")
+            .map_err(|e| e.to_string())?;
+
+        build_tree(&root, &mut stmt, &mut children_stmt, &mut HashSet::new())?
+            .ok_or_else(|| format!("Task '{}' not found", task_id))
+    })
+    .await
+}
@@ -0,0 +1,58 @@
+// Full-text search index maintenance.
+//
+// `reindex_fts` rebuilds every FTS3/4/5 table from its content table, for when an
+// index has drifted after imports or manual edits. Tables are discovered from the
+// schema rather than listed here, so indexes added by later migrations are covered.
+
+use crate::db;
+use rusqlite::{Connection, TransactionBehavior};
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+/// Held for the duration of a rebuild so two can't run at once
+static REINDEX: Mutex<()> = Mutex::new(());
+
+fn fts_tables(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND sql LIKE 'CREATE VIRTUAL TABLE%USING fts%'
+             ORDER BY name",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| row.get(0)).map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+pub(crate) fn rebuild(conn: &mut Connection) -> Result<u32, String> {
+    // IMMEDIATE takes the write lock up front, so other writers wait on their busy
+    // timeout instead of changing base tables halfway through the rebuild
+    let tx = conn
+        .transaction_with_behavior(TransactionBehavior::Immediate)
+        .map_err(|e| e.to_string())?;
+    let mut rows = 0u32;
+    for table in fts_tables(&tx)? {
+        let quoted = format!("\"{}\"", table.replace('"', "\"\""));
+        tx.execute(&format!("INSERT INTO {0}({0}) VALUES('rebuild')", quoted), [])
+            .map_err(|e| format!("Rebuilding {}: {}", table, e))?;
+        let count: i64 = tx
+            .query_row(&format!("SELECT COUNT(*) FROM {}", quoted), [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        println!("[FTS] Rebuilt {} ({} rows)", table, count);
+        rows += count as u32;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(rows)
+}
+
+/// Rebuild every full-text index from its base table, returning the rows reindexed
+#[tauri::command]
+pub async fn reindex_fts(app: AppHandle) -> Result<u32, String> {
+    db::with_conn(&app, |conn| {
+        let _guard = REINDEX
+            .try_lock()
+            .map_err(|_| "A search index rebuild is already running".to_string())?;
+        rebuild(conn)
+    })
+    .await
+}
@@ -0,0 +1,33 @@
+// Syntax highlighting for code shown in the webview.
+//
+// Output is HTML with syntect's class names (`class="source rust"`, ...) rather
+// than inline colors, so the webview's stylesheet decides the theme and light/dark
+// switches need no re-highlighting. The syntax set loads once, on first use.
+
+use std::sync::OnceLock;
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Larger code is left to the webview as plain text
+const MAX_BYTES: usize = 64 * 1024;
+const MAX_LINES: usize = 2_000;
+
+fn syntaxes() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// `code` as classed HTML, or `None` for unknown languages and oversized input
+pub fn highlight(code: &str, language: &str) -> Option<String> {
+    if code.len() > MAX_BYTES || code.lines().count() > MAX_LINES {
+        return None;
+    }
+    let set = syntaxes();
+    let syntax = set.find_syntax_by_token(language.trim())?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, set, ClassStyle::Spaced);
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line_which_includes_newline(line).ok()?;
+    }
+    Some(generator.finalize())
+}
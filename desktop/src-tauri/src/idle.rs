@@ -0,0 +1,154 @@
+// Stop the API sidecar after a period without task activity.
+//
+// Activity is read from the database rather than from the UI: any write to
+// `messages` advances the `message_changes` feed, and a task still marked running
+// and updated within the idle window counts as busy. Disabled unless
+// `idle_shutdown_minutes` is set. The frontend calls `ensure_api_running` before
+// starting a task, which brings a stopped sidecar back and waits for it to be healthy.
+
+use crate::{background, db, settings};
+use rusqlite::Connection;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const IDLE_MINUTES_KEY: &str = "idle_shutdown_minutes";
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "idle_shutdown",
+    interval: CHECK_INTERVAL,
+    coalescable: true,
+};
+
+struct Tracker {
+    last_seq: Option<i64>,
+    last_activity: Instant,
+    /// The sidecar was stopped by us and should be restarted on demand
+    stopped: bool,
+}
+
+pub struct IdleShutdownState(Mutex<Tracker>);
+
+impl Default for IdleShutdownState {
+    fn default() -> Self {
+        Self(Mutex::new(Tracker {
+            last_seq: None,
+            last_activity: Instant::now(),
+            stopped: false,
+        }))
+    }
+}
+
+fn idle_minutes(conn: &Connection) -> Result<Option<u32>, String> {
+    Ok(settings::get::<u32>(conn, IDLE_MINUTES_KEY)?.filter(|m| *m > 0))
+}
+
+pub(crate) fn activity(conn: &Connection, minutes: u32) -> Result<(i64, bool), String> {
+    let seq: i64 = conn
+        .query_row("SELECT COALESCE(MAX(seq), 0) FROM message_changes", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let running: bool = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM tasks WHERE status = 'running'
+                           AND julianday(updated_at) >= julianday('now', ?1))",
+            [format!("-{} minutes", minutes)],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok((seq, running))
+}
+
+fn check(app: &AppHandle) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let Some(minutes) = idle_minutes(&conn)? else {
+        return Ok(());
+    };
+    let (seq, running) = activity(&conn, minutes)?;
+
+    let state = app.state::<IdleShutdownState>();
+    let mut tracker = state.0.lock().map_err(|e| e.to_string())?;
+    if tracker.last_seq != Some(seq) || running {
+        tracker.last_seq = Some(seq);
+        tracker.last_activity = Instant::now();
+        return Ok(());
+    }
+    if tracker.stopped || tracker.last_activity.elapsed() < Duration::from_secs(minutes as u64 * 60) {
+        return Ok(());
+    }
+
+    // A separately run API server isn't ours to stop
+    if crate::sidecar_mode::managed() && app.state::<crate::sidecar::Manager>().stop() {
+        tracker.stopped = true;
+        println!("[Idle] No task activity for {} minutes; stopped the API sidecar", minutes);
+        crate::events::emit_typed(app, crate::events::SidecarIdleStopped { idle_minutes: minutes });
+    }
+    Ok(())
+}
+
+/// Restart the idle timer after the machine wakes, so the sleep doesn't count as idle
+pub fn note_resume(app: &AppHandle) {
+    if let Ok(mut tracker) = app.state::<IdleShutdownState>().0.lock() {
+        tracker.last_activity = Instant::now();
+    }
+}
+
+/// Start the periodic idle check
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &ACTIVITY);
+        if let Err(e) = check(&app) {
+            eprintln!("[Idle] Check failed: {}", e);
+        }
+    });
+}
+
+/// Set the idle period in minutes; `None` or 0 disables idle shutdown
+#[tauri::command]
+pub async fn set_idle_shutdown(app: AppHandle, minutes: Option<u32>) -> Result<(), String> {
+    db::with_conn(&app, move |conn| match minutes.filter(|m| *m > 0) {
+        Some(minutes) => settings::set(conn, IDLE_MINUTES_KEY, &minutes),
+        None => settings::remove(conn, IDLE_MINUTES_KEY),
+    })
+    .await?;
+    if let Ok(mut tracker) = app.state::<IdleShutdownState>().0.lock() {
+        tracker.last_activity = Instant::now();
+    }
+    Ok(())
+}
+
+/// Make sure the API is up before starting a task, restarting a sidecar that idle
+/// shutdown stopped and waiting until it answers its health check
+#[tauri::command]
+pub async fn ensure_api_running(app: AppHandle) -> Result<(), String> {
+    let state = app.state::<IdleShutdownState>();
+    let stopped = {
+        let mut tracker = state.0.lock().map_err(|e| e.to_string())?;
+        tracker.last_activity = Instant::now();
+        tracker.stopped
+    };
+    if !stopped {
+        return Ok(());
+    }
+
+    if crate::sidecar_mode::managed() {
+        let handle = app.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            let sidecar = handle.state::<crate::sidecar::Manager>();
+            // A restart by hand since the idle stop may have brought it back already
+            if sidecar.pid().is_none() {
+                sidecar.launch()?;
+            }
+            // Just after a resume the machine may still be bringing its network back
+            let timeout = crate::api_health::api_ready_timeout(&handle) + crate::power::grace_remaining(&handle);
+            crate::api_health::wait_for_api_health(crate::sidecar::api_port(), timeout)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
+        println!("[Idle] API sidecar restarted on demand");
+    }
+    if let Ok(mut tracker) = state.0.lock() {
+        tracker.stopped = false;
+    }
+    Ok(())
+}
@@ -0,0 +1,347 @@
+// Integrity journal: periodic row-count snapshots that catch silent data loss.
+//
+// A daily snapshot records, per table, the row count, max rowid, total content bytes
+// and the running deletion counter kept by triggers (migration 13). Rows can only
+// disappear through a DELETE, so between two snapshots a table must hold at least
+// `previous - deleted` rows. Anything below that is an unexplained drop. Subsystems
+// that delete in bulk also write `deletion_accounting` rows via `record_deletion`, so
+// expected decreases can be attributed rather than just counted.
+
+use crate::{background, db, events, settings};
+use rusqlite::{params, Connection};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::AppHandle;
+
+/// Tracked tables and the column summed as a cheap content checksum
+const TRACKED: &[(&str, &str)] = &[
+    ("sessions", "prompt"),
+    ("tasks", "prompt"),
+    ("messages", "content"),
+    ("files", "path"),
+];
+/// Tables whose rowid is AUTOINCREMENT and so may never go backwards
+const MONOTONIC_ROWID: &[&str] = &["messages", "files"];
+const ANOMALIES_KEY: &str = "integrity_anomalies";
+const SNAPSHOT_EVERY_HOURS: i64 = 24;
+const STARTUP_DELAY: Duration = Duration::from_secs(120);
+const POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "integrity_snapshot",
+    interval: POLL_INTERVAL,
+    coalescable: true,
+};
+const RETENTION_DAYS: i64 = 90;
+const DEFAULT_HISTORY: u32 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableSnapshot {
+    pub table: String,
+    pub row_count: i64,
+    pub max_rowid: i64,
+    pub content_bytes: i64,
+    pub deleted_total: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Attribution {
+    pub subsystem: String,
+    pub rows_removed: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Anomaly {
+    pub table: String,
+    pub previous_taken_at: String,
+    pub taken_at: String,
+    pub previous_count: i64,
+    pub count: i64,
+    /// Deletions the triggers saw between the two snapshots
+    pub deleted: i64,
+    /// Rows missing beyond what those deletions explain
+    pub unexplained: i64,
+    pub attributed: Vec<Attribution>,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IntegritySnapshot {
+    pub taken_at: String,
+    pub tables: Vec<TableSnapshot>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+/// Note rows a subsystem removed on purpose, so snapshots can attribute the drop
+pub(crate) fn record_deletion(conn: &Connection, table: &str, subsystem: &str, rows: u32) -> Result<(), String> {
+    if rows == 0 {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO deletion_accounting (table_name, subsystem, rows_removed) VALUES (?1, ?2, ?3)",
+        params![table, subsystem, rows],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn now() -> String {
+    // Same format as datetime('now'), so it compares with deletion_accounting
+    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// One aggregate pass per table
+fn measure(conn: &Connection) -> Result<Vec<TableSnapshot>, String> {
+    TRACKED
+        .iter()
+        .map(|&(table, column)| {
+            let (row_count, max_rowid, content_bytes) = conn
+                .query_row(
+                    &format!(
+                        "SELECT COUNT(*), COALESCE(MAX(rowid), 0), COALESCE(SUM(octet_length({column})), 0) FROM {table}"
+                    ),
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .map_err(|e| format!("Cannot measure {}: {}", table, e))?;
+            let deleted_total = conn
+                .query_row(
+                    "SELECT deleted FROM deletion_totals WHERE table_name = ?1",
+                    [table],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            Ok(TableSnapshot {
+                table: table.to_string(),
+                row_count,
+                max_rowid,
+                content_bytes,
+                deleted_total,
+            })
+        })
+        .collect()
+}
+
+fn load_tables(conn: &Connection, taken_at: &str) -> Result<Vec<TableSnapshot>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT table_name, row_count, max_rowid, content_bytes, deleted_total
+             FROM integrity_snapshots WHERE taken_at = ?1 ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([taken_at], |row| {
+            Ok(TableSnapshot {
+                table: row.get(0)?,
+                row_count: row.get(1)?,
+                max_rowid: row.get(2)?,
+                content_bytes: row.get(3)?,
+                deleted_total: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn attribution(conn: &Connection, table: &str, from: &str, to: &str) -> Result<Vec<Attribution>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT subsystem, SUM(rows_removed) FROM deletion_accounting
+             WHERE table_name = ?1 AND recorded_at > ?2 AND recorded_at <= ?3
+             GROUP BY subsystem ORDER BY subsystem",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![table, from, to], |row| {
+            Ok(Attribution {
+                subsystem: row.get(0)?,
+                rows_removed: row.get(1)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn compare(
+    conn: &Connection,
+    previous_taken_at: &str,
+    previous: &[TableSnapshot],
+    taken_at: &str,
+    current: &[TableSnapshot],
+) -> Result<Vec<Anomaly>, String> {
+    let mut anomalies = Vec::new();
+    for cur in current {
+        let Some(prev) = previous.iter().find(|p| p.table == cur.table) else {
+            continue;
+        };
+        let deleted = cur.deleted_total - prev.deleted_total;
+        let mut problems = Vec::new();
+        let unexplained = if deleted < 0 {
+            problems.push("deletion counter went backwards (database replaced or restored?)".to_string());
+            (prev.row_count - cur.row_count).max(0)
+        } else {
+            (prev.row_count - deleted - cur.row_count).max(0)
+        };
+        if unexplained > 0 {
+            problems.push(format!("{} rows disappeared without a recorded delete", unexplained));
+        }
+        if MONOTONIC_ROWID.contains(&cur.table.as_str()) && cur.max_rowid < prev.max_rowid {
+            problems.push(format!("max rowid fell from {} to {}", prev.max_rowid, cur.max_rowid));
+        }
+        if problems.is_empty() {
+            continue;
+        }
+
+        let mut attributed = attribution(conn, &cur.table, previous_taken_at, taken_at)?;
+        let accounted: i64 = attributed.iter().map(|a| a.rows_removed).sum();
+        if deleted > accounted {
+            attributed.push(Attribution {
+                subsystem: "user".to_string(),
+                rows_removed: deleted - accounted,
+            });
+        }
+        anomalies.push(Anomaly {
+            table: cur.table.clone(),
+            previous_taken_at: previous_taken_at.to_string(),
+            taken_at: taken_at.to_string(),
+            previous_count: prev.row_count,
+            count: cur.row_count,
+            deleted: deleted.max(0),
+            unexplained,
+            attributed,
+            detail: problems.join("; "),
+        });
+    }
+    Ok(anomalies)
+}
+
+fn snapshot_times(conn: &Connection, limit: u32) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT taken_at FROM integrity_snapshots ORDER BY taken_at DESC LIMIT ?1")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([limit], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Record a snapshot and compare it with the previous one
+fn take_snapshot(app: &AppHandle, conn: &mut Connection) -> Result<IntegritySnapshot, String> {
+    let previous_taken_at = snapshot_times(conn, 1)?.into_iter().next();
+    let taken_at = now();
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let tables = measure(&tx)?;
+    for table in &tables {
+        tx.execute(
+            "INSERT INTO integrity_snapshots (taken_at, table_name, row_count, max_rowid, content_bytes, deleted_total)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                taken_at,
+                table.table,
+                table.row_count,
+                table.max_rowid,
+                table.content_bytes,
+                table.deleted_total
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute(
+        "DELETE FROM integrity_snapshots WHERE taken_at < datetime('now', ?1)",
+        [format!("-{} days", RETENTION_DAYS)],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "DELETE FROM deletion_accounting WHERE recorded_at < datetime('now', ?1)",
+        [format!("-{} days", RETENTION_DAYS)],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    let anomalies = match &previous_taken_at {
+        Some(prev_at) => compare(conn, prev_at, &load_tables(conn, prev_at)?, &taken_at, &tables)?,
+        None => Vec::new(),
+    };
+    if !anomalies.is_empty() {
+        eprintln!("[Integrity] {} unexplained change(s) since {}", anomalies.len(), previous_taken_at.unwrap_or_default());
+        settings::set(conn, ANOMALIES_KEY, &anomalies)?;
+        events::emit_typed(app, events::IntegrityAnomaly(anomalies.clone()));
+    }
+
+    Ok(IntegritySnapshot {
+        taken_at,
+        tables,
+        anomalies,
+    })
+}
+
+fn snapshot_due(conn: &Connection) -> Result<bool, String> {
+    let Some(last) = snapshot_times(conn, 1)?.into_iter().next() else {
+        return Ok(true);
+    };
+    let due: bool = conn
+        .query_row(
+            "SELECT ?1 <= datetime('now', ?2)",
+            params![last, format!("-{} hours", SNAPSHOT_EVERY_HOURS)],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(due)
+}
+
+/// Anomalies from the most recent snapshot that found any, for diagnostics
+pub(crate) fn latest_anomalies(conn: &Connection) -> Result<Vec<Anomaly>, String> {
+    settings::get_or(conn, ANOMALIES_KEY, Vec::new())
+}
+
+/// Take a snapshot once a day in the background
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(STARTUP_DELAY);
+        loop {
+            let result = db::open(&app).and_then(|mut conn| {
+                if snapshot_due(&conn)? {
+                    take_snapshot(&app, &mut conn)?;
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                eprintln!("[Integrity] Snapshot failed: {}", e);
+            }
+            background::sleep(&app, &ACTIVITY);
+        }
+    });
+}
+
+/// Take a snapshot now instead of waiting for the daily one
+#[tauri::command]
+pub async fn take_integrity_snapshot(app: AppHandle) -> Result<IntegritySnapshot, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| take_snapshot(&handle, conn)).await
+}
+
+/// Recent snapshots, newest first, each compared with the one before it
+#[tauri::command]
+pub async fn get_integrity_history(app: AppHandle, limit: Option<u32>) -> Result<Vec<IntegritySnapshot>, String> {
+    let limit = limit.unwrap_or(DEFAULT_HISTORY).max(1);
+    db::with_conn(&app, move |conn| {
+        let times = snapshot_times(conn, limit + 1)?;
+        let mut history = Vec::with_capacity(times.len());
+        for (index, taken_at) in times.iter().enumerate().take(limit as usize) {
+            let tables = load_tables(conn, taken_at)?;
+            let anomalies = match times.get(index + 1) {
+                Some(prev_at) => compare(conn, prev_at, &load_tables(conn, prev_at)?, taken_at, &tables)?,
+                None => Vec::new(),
+            };
+            history.push(IntegritySnapshot {
+                taken_at: taken_at.clone(),
+                tables,
+                anomalies,
+            });
+        }
+        Ok(history)
+    })
+    .await
+}
@@ -0,0 +1,429 @@
+// Per-window access to the app's commands.
+//
+// Every command belongs to one group in `COMMANDS`, and `WINDOWS` says which groups
+// each window label may call. Labels that aren't listed get `DEFAULT_GROUPS`, and
+// commands missing from the table are only callable from the main window. `guard`
+// wraps the command handler, so the check runs before any command body; the label
+// always comes from the webview that sent the call, never from its arguments.
+// `ipc_batch` runs the same check for every call inside a batch.
+//
+// Plugin commands (`plugin:sql|...` and friends) don't pass through the handler;
+// those are scoped by the files in `capabilities/`, and raw SQL is additionally
+// counted and denied per table by `legacy_sql`.
+
+use crate::error::CommandError;
+use crate::window::MAIN_WINDOW;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tauri::ipc::Invoke;
+use tauri::Runtime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandGroup {
+    /// Reading tasks, messages, files and status for display
+    Read,
+    /// Focus, window placement and links between views
+    Navigation,
+    /// Creating, running and changing tasks
+    Tasks,
+    /// Opening, moving and unpacking files
+    Files,
+    /// Preferences, routing rules and API configuration
+    Settings,
+    Secrets,
+    /// Repairs, cleanups, reindexing and restarts
+    Maintenance,
+    /// Developer-mode tooling
+    Developer,
+}
+
+use CommandGroup::*;
+
+const ALL_GROUPS: &[CommandGroup] = &[Read, Navigation, Tasks, Files, Settings, Secrets, Maintenance, Developer];
+
+/// What a window not listed in `WINDOWS` may call
+const DEFAULT_GROUPS: &[CommandGroup] = &[Read, Navigation];
+
+const WINDOWS: &[(&str, &[CommandGroup])] = &[
+    (MAIN_WINDOW, ALL_GROUPS),
+    ("quick-capture", &[Read, Navigation, Tasks]),
+    ("monitor", DEFAULT_GROUPS),
+    ("presentation", DEFAULT_GROUPS),
+    ("print", &[Read]),
+];
+
+/// Every registered command, by the name the frontend invokes it with
+const COMMANDS: &[(&str, CommandGroup)] = &[
+    ("greet", Read),
+    ("force_restart_api", Maintenance),
+    ("restart_api_sidecar", Maintenance),
+    ("get_api_status", Read),
+    ("get_api_port", Read),
+    ("api_status", Read),
+    ("get_sidecar_status", Read),
+    ("set_pinned_api_port", Settings),
+    ("set_sidecar_enabled", Settings),
+    ("set_api_port", Settings),
+    ("set_api_ready_timeout", Settings),
+    ("set_sidecar_shutdown_grace", Settings),
+    ("set_sidecar_stdout_events", Settings),
+    ("get_job", Read),
+    ("list_jobs", Read),
+    ("cancel_job", Tasks),
+    ("redact_message_content", Tasks),
+    ("redact_pattern_across_task", Tasks),
+    ("find_orphaned_messages", Maintenance),
+    ("cleanup_orphaned_messages", Maintenance),
+    ("find_orphaned_files", Maintenance),
+    ("cleanup_orphaned_files", Maintenance),
+    ("run_self_test", Maintenance),
+    ("get_last_self_test", Maintenance),
+    ("get_close_behavior", Settings),
+    ("set_close_behavior", Settings),
+    ("tile_windows", Navigation),
+    ("retile", Navigation),
+    ("prewarm_thumbnails", Read),
+    ("estimate_tokens", Read),
+    ("get_model_pricing", Read),
+    ("task_neighbors", Read),
+    ("rename_managed_file", Files),
+    ("move_managed_file", Files),
+    ("get_hardware_acceleration", Settings),
+    ("set_hardware_acceleration", Settings),
+    ("get_tool_stats", Read),
+    ("backfill_tool_invocations", Maintenance),
+    ("list_explainable_queries", Developer),
+    ("explain_query", Developer),
+    ("get_api_config", Settings),
+    ("set_external_api_url", Settings),
+    ("verify_api_identity", Settings),
+    ("trust_new_api_identity", Settings),
+    ("get_api_pin_events", Settings),
+    ("coalesce_task_files", Files),
+    ("list_messages", Read),
+    ("prime_message_cache", Read),
+    ("get_message_cache_stats", Developer),
+    ("check_db_size", Maintenance),
+    ("set_max_db_size", Settings),
+    ("take_integrity_snapshot", Maintenance),
+    ("get_integrity_history", Maintenance),
+    ("install_native_messaging_host", Settings),
+    ("uninstall_native_messaging_host", Settings),
+    ("list_pending_captures", Read),
+    ("convert_capture_to_task", Tasks),
+    ("dismiss_capture", Tasks),
+    ("tag_autocomplete", Read),
+    ("get_migration_status", Maintenance),
+    ("acknowledge_migration_checksums", Maintenance),
+    ("set_idle_shutdown", Settings),
+    ("ensure_api_running", Tasks),
+    ("store_secret", Secrets),
+    ("get_secret", Secrets),
+    ("delete_secret", Secrets),
+    ("get_secrets_backend_info", Secrets),
+    ("setup_file_secrets", Secrets),
+    ("unlock_secrets", Secrets),
+    ("migrate_secrets_backend", Secrets),
+    ("session_deep_link", Navigation),
+    ("copy_session_link", Navigation),
+    ("set_devtools", Developer),
+    ("get_window_diagnostics", Developer),
+    ("toggle_diagnostic_overlay", Developer),
+    ("is_first_run", Read),
+    ("get_first_run_state", Read),
+    ("complete_onboarding", Settings),
+    ("fork_task", Tasks),
+    ("get_fork_tree", Read),
+    ("bulk_export_tasks", Files),
+    ("get_event_catalog", Read),
+    ("detect_stalled_tasks", Read),
+    ("set_stall_timeout", Settings),
+    ("can_quick_look", Read),
+    ("quick_look_file", Files),
+    ("get_notification_settings", Settings),
+    ("set_notification_sound", Settings),
+    ("set_notify_on_complete", Settings),
+    ("play_notification_sound", Navigation),
+    ("export_all", Files),
+    ("resume_job", Tasks),
+    ("discard_export_journal", Tasks),
+    ("reindex_fts", Maintenance),
+    ("request_task_focus", Navigation),
+    ("report_user_typing", Navigation),
+    ("report_manual_navigation", Navigation),
+    ("get_focus_history", Navigation),
+    ("set_auto_focus", Settings),
+    ("get_circuit_status", Read),
+    ("reset_circuits", Maintenance),
+    ("sidecar_request", Tasks),
+    ("get_sleep_intervals", Read),
+    ("get_awake_ms", Read),
+    ("create_task", Tasks),
+    ("list_routing_rules", Settings),
+    ("create_routing_rule", Settings),
+    ("update_routing_rule", Settings),
+    ("delete_routing_rule", Settings),
+    ("reorder_routing_rules", Settings),
+    ("test_routing_rules", Settings),
+    ("get_task_routing_explanation", Read),
+    ("list_archive_contents", Read),
+    ("extract_archive_entries", Files),
+    ("get_quota_status", Read),
+    ("set_quota", Settings),
+    ("scan_file_integrity", Maintenance),
+    ("get_file_repair_report", Maintenance),
+    ("list_file_repair_reports", Maintenance),
+    ("apply_file_repairs", Maintenance),
+    ("get_power_efficiency_report", Developer),
+    ("scan_file", Files),
+    ("set_require_scan_before_open", Settings),
+    ("get_scan_settings", Settings),
+    ("open_file", Files),
+    ("create_diagnostic_task", Tasks),
+    ("get_full_prompt", Read),
+    ("get_prompt_storage", Settings),
+    ("set_prompt_inline_limit", Settings),
+    ("backfill_prompt_overflow", Maintenance),
+    ("store_attachment_file", Files),
+    ("add_session_file", Tasks),
+    ("remove_session_file", Tasks),
+    ("list_session_files", Read),
+    ("reorder_session_files", Tasks),
+    ("set_session_file_always_include", Tasks),
+    ("delete_attachment", Files),
+    ("get_media_backfill_status", Read),
+    ("get_backfill_errors", Maintenance),
+    ("export_review_packet", Files),
+    ("import_review_comments", Tasks),
+    ("list_message_notes", Read),
+    ("get_system_capabilities", Read),
+    ("get_timeline_buckets", Read),
+    ("get_timeline_day", Read),
+    ("get_legacy_sql_report", Developer),
+    ("set_legacy_sql_denylist", Settings),
+    ("preview_schedule", Read),
+    ("set_flag", Tasks),
+    ("trash_task", Tasks),
+    ("restore_task", Tasks),
+    ("get_trash_summary", Read),
+    ("empty_trash", Maintenance),
+    ("set_trash_limits", Settings),
+    ("get_message_blocks", Read),
+    ("start_api_recording", Developer),
+    ("start_api_replay", Developer),
+    ("stop_api_cassette", Developer),
+    ("record_api_stream", Developer),
+    ("replay_api_stream", Developer),
+    ("get_notification_prefs", Settings),
+    ("set_notification_prefs", Settings),
+    ("preview_notification", Settings),
+    ("test_ignore_rules", Files),
+    ("get_workspace_ignore_overrides", Files),
+    ("set_workspace_ignore_overrides", Settings),
+    ("get_export_signing_key", Read),
+    ("rotate_export_signing_key", Secrets),
+    ("verify_export", Files),
+    ("batch_invoke", Read),
+    ("list_batchable_commands", Read),
+    ("backup_database", Maintenance),
+    ("list_backups", Maintenance),
+    ("verify_backup", Maintenance),
+    ("restore_backup", Maintenance),
+    ("get_backup_policy", Settings),
+    ("set_backup_policy", Settings),
+    ("get_task_note", Read),
+    ("set_task_note", Tasks),
+    ("analyze_workspace", Files),
+    ("get_workspace_analysis", Read),
+    ("export_interactive_viewer", Files),
+    ("validate_viewer_export", Files),
+    ("get_safe_mode_report", Read),
+    ("disable_feature_and_restart", Maintenance),
+    ("enable_startup_feature", Settings),
+    ("reset_setting_and_restart", Maintenance),
+    ("retry_full_startup", Maintenance),
+    ("suggest_attachments", Read),
+    ("accept_attachment_suggestion", Files),
+    ("get_webview_storage_usage", Read),
+    ("clear_webview_storage", Maintenance),
+    ("get_storage_breakdown", Read),
+    ("check_api_health", Read),
+    ("get_context_plan", Read),
+    ("set_context_overrides", Tasks),
+    ("get_sent_context", Read),
+    ("set_message_include_in_context", Tasks),
+    ("get_volume_status", Read),
+    ("set_volume_timeout", Settings),
+    ("list_focus_sessions", Read),
+    ("end_focus_session", Tasks),
+    ("set_focus_gap", Settings),
+    ("get_schema_drift_report", Read),
+    ("get_api_log_path", Read),
+    ("open_api_log", Files),
+    ("read_api_log_tail", Read),
+    ("list_saved_filters", Read),
+    ("create_saved_filter", Tasks),
+    ("update_saved_filter", Tasks),
+    ("delete_saved_filter", Tasks),
+    ("reorder_saved_filters", Tasks),
+    ("evaluate_filter", Read),
+    ("report_task_metrics", Tasks),
+    ("get_metric_history", Read),
+    ("search_messages", Read),
+    ("list_export_presets", Read),
+    ("create_export_preset", Settings),
+    ("update_export_preset", Settings),
+    ("delete_export_preset", Settings),
+    ("run_export_preset", Files),
+    ("list_deliveries", Read),
+    ("normalize_message_types", Maintenance),
+    ("add_tag", Tasks),
+    ("remove_tag", Tasks),
+    ("list_tags", Read),
+    ("tasks_by_tag", Read),
+];
+
+/// Denied calls per (window label, command) since launch
+static DENIALS: Mutex<BTreeMap<(String, String), u64>> = Mutex::new(BTreeMap::new());
+
+fn groups_for(label: &str) -> &'static [CommandGroup] {
+    WINDOWS
+        .iter()
+        .find(|(window, _)| *window == label)
+        .map_or(DEFAULT_GROUPS, |(_, groups)| groups)
+}
+
+pub(crate) fn group_of(command: &str) -> Option<CommandGroup> {
+    COMMANDS.iter().find(|(name, _)| *name == command).map(|(_, group)| *group)
+}
+
+/// Whether the window `label` may call `command`
+pub fn check(label: &str, command: &str) -> Result<(), CommandError> {
+    let allowed = match group_of(command) {
+        Some(group) => groups_for(label).contains(&group),
+        None => label == MAIN_WINDOW,
+    };
+    if allowed {
+        return Ok(());
+    }
+    Err(CommandError::Forbidden {
+        message: format!("The '{}' window may not call '{}'", label, command),
+        command: command.to_string(),
+        window: label.to_string(),
+    })
+}
+
+pub(crate) fn record_denial(label: &str, command: &str) {
+    eprintln!("[IPC] Denied '{}' from window '{}'", command, label);
+    if let Ok(mut denials) = DENIALS.lock() {
+        *denials.entry((label.to_string(), command.to_string())).or_default() += 1;
+    }
+}
+
+/// Denied calls from `label` since launch
+pub fn denials_for(label: &str) -> u64 {
+    DENIALS
+        .lock()
+        .map(|denials| denials.iter().filter(|((l, _), _)| l == label).map(|(_, n)| n).sum())
+        .unwrap_or(0)
+}
+
+/// Wrap the command handler so calls a window isn't allowed to make are rejected
+/// with `forbidden` before they reach the command
+pub fn guard<R: Runtime>(
+    handler: impl Fn(Invoke<R>) -> bool + Send + Sync + 'static,
+) -> impl Fn(Invoke<R>) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        let label = invoke.message.webview_ref().label().to_string();
+        let command = invoke.message.command().to_string();
+        if let Err(error) = check(&label, &command) {
+            record_denial(&label, &command);
+            invoke.resolver.reject(error);
+            return true;
+        }
+        handler(invoke)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use tauri::ipc::{CallbackFn, InvokeBody};
+    use tauri::test::{get_ipc_response, mock_builder, mock_context, noop_assets, INVOKE_KEY};
+    use tauri::webview::InvokeRequest;
+    use tauri::WebviewWindowBuilder;
+
+    #[tauri::command]
+    fn store_secret() -> &'static str {
+        "stored"
+    }
+
+    #[tauri::command]
+    fn list_messages() -> &'static str {
+        "messages"
+    }
+
+    fn invoke(window: &tauri::WebviewWindow<tauri::test::MockRuntime>, cmd: &str) -> Result<String, serde_json::Value> {
+        get_ipc_response(
+            window,
+            InvokeRequest {
+                cmd: cmd.into(),
+                callback: CallbackFn(0),
+                error: CallbackFn(1),
+                url: "tauri://localhost".parse().unwrap(),
+                body: InvokeBody::default(),
+                headers: Default::default(),
+                invoke_key: INVOKE_KEY.to_string(),
+            },
+        )
+        .map(|body| body.deserialize::<String>().unwrap())
+    }
+
+    #[test]
+    fn every_registered_command_has_a_group() {
+        let source = include_str!("lib.rs");
+        let start = source.find("generate_handler![").expect("command list");
+        let end = start + source[start..].find(']').expect("end of command list");
+        let registered: HashSet<&str> = source[start + "generate_handler![".len()..end]
+            .split(',')
+            .map(|entry| entry.trim().rsplit("::").next().unwrap_or_default())
+            .filter(|name| !name.is_empty())
+            .collect();
+        let missing: Vec<_> = registered.iter().filter(|name| group_of(name).is_none()).collect();
+        assert!(missing.is_empty(), "commands without a group: {:?}", missing);
+    }
+
+    #[test]
+    fn unknown_windows_and_commands_fall_back_to_the_minimum() {
+        assert!(check("some-popup", "list_messages").is_ok());
+        assert!(check("some-popup", "set_quota").is_err());
+        assert!(check(MAIN_WINDOW, "not_in_the_table").is_ok());
+        assert!(check("quick-capture", "not_in_the_table").is_err());
+        assert!(check("quick-capture", "create_task").is_ok());
+    }
+
+    #[test]
+    fn secondary_window_is_denied_restricted_commands() {
+        let app = mock_builder()
+            .invoke_handler(guard(tauri::generate_handler![store_secret, list_messages]))
+            .build(mock_context(noop_assets()))
+            .unwrap();
+        let main = WebviewWindowBuilder::new(&app, MAIN_WINDOW, Default::default()).build().unwrap();
+        let capture = WebviewWindowBuilder::new(&app, "quick-capture", Default::default())
+            .build()
+            .unwrap();
+
+        assert_eq!(invoke(&main, "store_secret").unwrap(), "stored");
+        assert_eq!(invoke(&capture, "list_messages").unwrap(), "messages");
+
+        let before = denials_for("quick-capture");
+        let denied = invoke(&capture, "store_secret").unwrap_err();
+        assert_eq!(denied["kind"], "forbidden");
+        assert_eq!(denied["window"], "quick-capture");
+        assert_eq!(denied["command"], "store_secret");
+        assert_eq!(denials_for("quick-capture"), before + 1);
+    }
+}
@@ -0,0 +1,257 @@
+// Several cheap commands in one IPC round trip.
+//
+// Heartbeats and status polls arrive many times a second, and each invoke costs a
+// serialization round trip through the webview. `batch_invoke` runs a list of calls
+// in order and answers with one array. Only the commands in `BATCHABLE` can be
+// batched: in-memory reads and idempotent reports that never block on the database.
+// Each inner call goes through the same `ipc_access::check` as a direct invoke, and
+// an inner failure takes its slot in the response without failing the batch.
+
+use crate::error::CommandError;
+use crate::ipc_access;
+use crate::{focus, jobs, power};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, Runtime, Webview};
+
+/// Most calls accepted in one batch
+pub const MAX_BATCH: usize = 128;
+
+/// Commands `batch_invoke` will run. The frontend's invoke wrapper reads this list
+/// (`list_batchable_commands`) and coalesces these calls within an animation frame.
+pub const BATCHABLE: &[&str] = &[
+    "report_user_typing",
+    "report_manual_navigation",
+    "get_focus_history",
+    "get_job",
+    "list_jobs",
+    "get_sleep_intervals",
+    "get_awake_ms",
+];
+
+#[derive(Debug, Deserialize)]
+pub struct BatchCall {
+    pub command: String,
+    /// Named arguments, camelCase as for a direct invoke
+    #[serde(default)]
+    pub args: Value,
+}
+
+/// The result of one call, in the same position as the call
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    Ok { value: Value },
+    Err { error: CommandError },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JobArgs {
+    job_id: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SinceArgs {
+    since_ms: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RangeArgs {
+    start_ms: i64,
+    end_ms: i64,
+}
+
+fn args<T: DeserializeOwned>(command: &str, args: Value) -> Result<T, CommandError> {
+    // A call without arguments may omit `args`; treat that like an empty object
+    let args = if args.is_null() { Value::Object(Default::default()) } else { args };
+    serde_json::from_value(args).map_err(|e| format!("Invalid arguments for '{}': {}", command, e).into())
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, CommandError> {
+    serde_json::to_value(value).map_err(|e| e.to_string().into())
+}
+
+fn dispatch<R: Runtime>(app: &AppHandle<R>, call: BatchCall) -> Result<Value, CommandError> {
+    let BatchCall { command, args: raw } = call;
+    match command.as_str() {
+        "report_user_typing" => {
+            focus::report_user_typing(app.state());
+            Ok(Value::Null)
+        }
+        "report_manual_navigation" => {
+            focus::report_manual_navigation(app.state());
+            Ok(Value::Null)
+        }
+        "get_focus_history" => to_value(focus::get_focus_history(app.state())),
+        "get_job" => {
+            let JobArgs { job_id } = args(&command, raw)?;
+            to_value(jobs::get_job(app.state(), job_id))
+        }
+        "list_jobs" => to_value(jobs::list_jobs(app.state())),
+        "get_sleep_intervals" => {
+            let SinceArgs { since_ms } = args(&command, raw)?;
+            to_value(power::get_sleep_intervals(app.state(), since_ms))
+        }
+        "get_awake_ms" => {
+            let RangeArgs { start_ms, end_ms } = args(&command, raw)?;
+            to_value(power::get_awake_ms(app.state(), start_ms, end_ms))
+        }
+        _ => Err(format!("'{}' can't be batched", command).into()),
+    }
+}
+
+/// Run `calls` in order for the window `label`
+fn run<R: Runtime>(app: &AppHandle<R>, label: &str, calls: Vec<BatchCall>) -> Vec<BatchOutcome> {
+    calls
+        .into_iter()
+        .map(|call| {
+            let result = ipc_access::check(label, &call.command)
+                .inspect_err(|_| ipc_access::record_denial(label, &call.command))
+                .and_then(|()| dispatch(app, call));
+            match result {
+                Ok(value) => BatchOutcome::Ok { value },
+                Err(error) => BatchOutcome::Err { error },
+            }
+        })
+        .collect()
+}
+
+/// Run up to `MAX_BATCH` batchable commands in one round trip. The response has one
+/// outcome per call, in order; only an oversized batch fails as a whole.
+#[tauri::command]
+pub async fn batch_invoke<R: Runtime>(
+    app: AppHandle<R>,
+    webview: Webview<R>,
+    calls: Vec<BatchCall>,
+) -> Result<Vec<BatchOutcome>, CommandError> {
+    if calls.len() > MAX_BATCH {
+        return Err(format!("A batch holds at most {} calls, got {}", MAX_BATCH, calls.len()).into());
+    }
+    Ok(run(&app, webview.label(), calls))
+}
+
+/// Which commands the frontend may route through `batch_invoke`
+#[tauri::command]
+pub fn list_batchable_commands() -> &'static [&'static str] {
+    BATCHABLE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window::MAIN_WINDOW;
+    use serde_json::json;
+    use std::time::{Duration, Instant};
+    use tauri::ipc::{CallbackFn, InvokeBody};
+    use tauri::test::{get_ipc_response, mock_builder, mock_context, noop_assets, MockRuntime, INVOKE_KEY};
+    use tauri::webview::InvokeRequest;
+    use tauri::{WebviewWindow, WebviewWindowBuilder};
+
+    fn app() -> tauri::App<MockRuntime> {
+        mock_builder()
+            .manage(focus::FocusArbiter::default())
+            .manage(jobs::JobRegistry::default())
+            .manage(power::PowerState::default())
+            .invoke_handler(ipc_access::guard(tauri::generate_handler![
+                batch_invoke,
+                focus::report_user_typing
+            ]))
+            .build(mock_context(noop_assets()))
+            .unwrap()
+    }
+
+    fn invoke(window: &WebviewWindow<MockRuntime>, cmd: &str, body: Value) -> Result<Value, Value> {
+        get_ipc_response(
+            window,
+            InvokeRequest {
+                cmd: cmd.into(),
+                callback: CallbackFn(0),
+                error: CallbackFn(1),
+                url: "tauri://localhost".parse().unwrap(),
+                body: InvokeBody::Json(body),
+                headers: Default::default(),
+                invoke_key: INVOKE_KEY.to_string(),
+            },
+        )
+        .map(|body| body.deserialize::<Value>().unwrap())
+    }
+
+    #[test]
+    fn every_batchable_command_is_registered_and_dispatched() {
+        let app = app();
+        for command in BATCHABLE {
+            assert!(ipc_access::group_of(command).is_some(), "{} has no group", command);
+            let call = BatchCall {
+                command: command.to_string(),
+                args: json!({ "jobId": 1, "startMs": 0, "endMs": 10 }),
+            };
+            assert!(dispatch(app.handle(), call).is_ok(), "{} was not dispatched", command);
+        }
+    }
+
+    #[test]
+    fn failures_stay_in_their_slot() {
+        let app = app();
+        let print = WebviewWindowBuilder::new(&app, "print", Default::default()).build().unwrap();
+        let calls = json!({ "calls": [
+            { "command": "list_jobs" },
+            { "command": "report_user_typing" },
+            { "command": "get_job", "args": {} },
+            { "command": "store_secret" },
+            { "command": "get_awake_ms", "args": { "startMs": 0, "endMs": 5 } },
+        ]});
+        let outcomes = invoke(&print, "batch_invoke", calls).unwrap();
+        let statuses: Vec<_> = outcomes.as_array().unwrap().iter().map(|o| o["status"].clone()).collect();
+        assert_eq!(statuses, vec!["ok", "err", "err", "err", "ok"]);
+        assert_eq!(outcomes[1]["error"]["kind"], "forbidden");
+        assert_eq!(outcomes[2]["error"]["kind"], "failed");
+        assert_eq!(outcomes[4]["value"], 5);
+    }
+
+    #[test]
+    fn oversized_batches_are_rejected() {
+        let app = app();
+        let main = WebviewWindowBuilder::new(&app, MAIN_WINDOW, Default::default()).build().unwrap();
+        let calls: Vec<_> = (0..=MAX_BATCH).map(|_| json!({ "command": "list_jobs" })).collect();
+        let error = invoke(&main, "batch_invoke", json!({ "calls": calls })).unwrap_err();
+        assert_eq!(error["kind"], "failed");
+    }
+
+    #[test]
+    fn batching_cuts_per_call_overhead() {
+        const CALLS: usize = 100;
+        let app = app();
+        let main = WebviewWindowBuilder::new(&app, MAIN_WINDOW, Default::default()).build().unwrap();
+        let batch = json!({ "calls": vec![json!({ "command": "report_user_typing" }); CALLS] });
+
+        // Warm both paths so the first-call setup isn't measured
+        invoke(&main, "report_user_typing", json!({})).unwrap();
+        invoke(&main, "batch_invoke", batch.clone()).unwrap();
+
+        let mut individual = Duration::ZERO;
+        let mut batched = Duration::ZERO;
+        for _ in 0..5 {
+            let started = Instant::now();
+            for _ in 0..CALLS {
+                invoke(&main, "report_user_typing", json!({})).unwrap();
+            }
+            individual += started.elapsed();
+
+            let started = Instant::now();
+            let outcomes = invoke(&main, "batch_invoke", batch.clone()).unwrap();
+            batched += started.elapsed();
+            assert_eq!(outcomes.as_array().unwrap().len(), CALLS);
+        }
+        println!(
+            "[IPC] {} calls: {:?} individually, {:?} batched",
+            CALLS,
+            individual / 5,
+            batched / 5
+        );
+        assert!(batched < individual, "batched {:?} vs individual {:?}", batched, individual);
+    }
+}
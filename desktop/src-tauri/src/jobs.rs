@@ -0,0 +1,244 @@
+// Background jobs for long-running maintenance work.
+//
+// A job runs on the blocking thread pool, reports progress through `job://progress`
+// events and ends with a single `job://finished` event carrying its final state.
+
+use crate::{background, events};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tauri::{AppHandle, Manager, State};
+
+// Finished jobs kept around so the UI can still query their result
+const MAX_FINISHED_JOBS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct JobInfo {
+    pub id: u64,
+    pub kind: String,
+    pub status: JobStatus,
+    pub done: u64,
+    pub total: u64,
+    pub message: Option<String>,
+    /// Estimated time left, from the progress rate since the first report
+    pub eta_ms: Option<u64>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+struct JobEntry {
+    info: JobInfo,
+    cancel: Arc<AtomicBool>,
+    /// Export journal, for jobs that can be resumed (see `checkpoint`)
+    journal: Option<PathBuf>,
+}
+
+#[derive(Default)]
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    jobs: Mutex<HashMap<u64, JobEntry>>,
+}
+
+impl JobRegistry {
+    fn update(&self, id: u64, f: impl FnOnce(&mut JobInfo)) -> Option<JobInfo> {
+        let mut jobs = self.jobs.lock().ok()?;
+        let entry = jobs.get_mut(&id)?;
+        f(&mut entry.info);
+        Some(entry.info.clone())
+    }
+
+    /// Journal written by the job, if it checkpoints
+    pub fn journal(&self, id: u64) -> Option<PathBuf> {
+        self.jobs.lock().ok()?.get(&id)?.journal.clone()
+    }
+
+    /// Running job that is writing `journal`
+    pub fn running_with_journal(&self, journal: &Path) -> Option<u64> {
+        let jobs = self.jobs.lock().ok()?;
+        jobs.values()
+            .find(|e| e.info.status == JobStatus::Running && e.journal.as_deref() == Some(journal))
+            .map(|e| e.info.id)
+    }
+
+    fn prune_finished(jobs: &mut HashMap<u64, JobEntry>) {
+        let mut finished: Vec<(i64, u64)> = jobs
+            .values()
+            .filter_map(|e| e.info.finished_at.map(|at| (at, e.info.id)))
+            .collect();
+        if finished.len() <= MAX_FINISHED_JOBS {
+            return;
+        }
+        finished.sort();
+        for (_, id) in &finished[..finished.len() - MAX_FINISHED_JOBS] {
+            jobs.remove(id);
+        }
+    }
+}
+
+/// Handle passed to the job body for progress reporting and cancellation checks
+pub struct JobContext {
+    pub id: u64,
+    app: AppHandle,
+    cancel: Arc<AtomicBool>,
+    /// When and where progress was first reported; resumed jobs start part way
+    first_progress: Mutex<Option<(Instant, u64)>>,
+}
+
+impl JobContext {
+    pub fn app(&self) -> &AppHandle {
+        &self.app
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn set_journal(&self, journal: PathBuf) {
+        let registry = self.app.state::<JobRegistry>();
+        if let Ok(mut jobs) = registry.jobs.lock() {
+            if let Some(entry) = jobs.get_mut(&self.id) {
+                entry.journal = Some(journal);
+            }
+        }
+    }
+
+    pub fn progress(&self, done: u64, total: u64, message: Option<String>) {
+        let eta_ms = self.first_progress.lock().ok().and_then(|mut first| {
+            let (since, from) = *first.get_or_insert((Instant::now(), done));
+            let advanced = done.checked_sub(from).filter(|n| *n > 0)?;
+            let elapsed = since.elapsed().as_millis() as u64;
+            Some(elapsed * total.saturating_sub(done) / advanced)
+        });
+        let registry = self.app.state::<JobRegistry>();
+        if let Some(info) = registry.update(self.id, |info| {
+            info.done = done;
+            info.total = total;
+            info.message = message;
+            info.eta_ms = eta_ms;
+        }) {
+            events::emit_typed(&self.app, events::JobProgress(info));
+        }
+    }
+}
+
+/// Start `body` as a background job and return its id immediately
+pub fn spawn<F>(app: &AppHandle, kind: &str, body: F) -> u64
+where
+    F: FnOnce(&JobContext) -> Result<Value, String> + Send + 'static,
+{
+    let registry = app.state::<JobRegistry>();
+    let id = registry.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let info = JobInfo {
+        id,
+        kind: kind.to_string(),
+        status: JobStatus::Running,
+        done: 0,
+        total: 0,
+        message: None,
+        eta_ms: None,
+        result: None,
+        error: None,
+        started_at: chrono::Utc::now().timestamp_millis(),
+        finished_at: None,
+    };
+    if let Ok(mut jobs) = registry.jobs.lock() {
+        jobs.insert(
+            id,
+            JobEntry {
+                info,
+                cancel: cancel.clone(),
+                journal: None,
+            },
+        );
+    }
+
+    let ctx = JobContext {
+        id,
+        app: app.clone(),
+        cancel,
+        first_progress: Mutex::new(None),
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        let exemption = background::exempt(&ctx.app);
+        let outcome = body(&ctx);
+        drop(exemption);
+        let cancelled = ctx.is_cancelled();
+        let registry = ctx.app.state::<JobRegistry>();
+        let finished = registry.update(id, |info| {
+            info.finished_at = Some(chrono::Utc::now().timestamp_millis());
+            match outcome {
+                Ok(value) => {
+                    info.status = if cancelled {
+                        JobStatus::Cancelled
+                    } else {
+                        JobStatus::Completed
+                    };
+                    info.result = Some(value);
+                }
+                Err(error) => {
+                    info.status = if cancelled {
+                        JobStatus::Cancelled
+                    } else {
+                        JobStatus::Failed
+                    };
+                    info.error = Some(error);
+                }
+            }
+        });
+        if let Ok(mut jobs) = registry.jobs.lock() {
+            JobRegistry::prune_finished(&mut jobs);
+        }
+        if let Some(info) = finished {
+            events::emit_typed(&ctx.app, events::JobFinished(info));
+        }
+    });
+    id
+}
+
+#[tauri::command]
+pub fn get_job(registry: State<'_, JobRegistry>, job_id: u64) -> Option<JobInfo> {
+    let jobs = registry.jobs.lock().ok()?;
+    jobs.get(&job_id).map(|e| e.info.clone())
+}
+
+#[tauri::command]
+pub fn list_jobs(registry: State<'_, JobRegistry>) -> Vec<JobInfo> {
+    let Ok(jobs) = registry.jobs.lock() else {
+        return Vec::new();
+    };
+    let mut list: Vec<JobInfo> = jobs.values().map(|e| e.info.clone()).collect();
+    list.sort_by_key(|info| info.id);
+    list
+}
+
+/// Request cooperative cancellation; returns false if the job is unknown or already done
+#[tauri::command]
+pub fn cancel_job(registry: State<'_, JobRegistry>, job_id: u64) -> bool {
+    let Ok(jobs) = registry.jobs.lock() else {
+        return false;
+    };
+    match jobs.get(&job_id) {
+        Some(entry) if entry.info.status == JobStatus::Running => {
+            entry.cancel.store(true, Ordering::Relaxed);
+            true
+        }
+        _ => false,
+    }
+}
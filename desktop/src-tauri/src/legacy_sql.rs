@@ -0,0 +1,543 @@
+// The webview's raw SQL access through tauri-plugin-sql, on its way out.
+//
+// The sql plugin is registered through `wrap`, which sees every `plugin:sql|execute`
+// and `plugin:sql|select` call before the plugin does. Each statement is reduced to a
+// fingerprint (literals and placeholders become `?`, so no values are kept) and
+// counted in memory; counts are written to `legacy_sql_usage` once a minute, on exit
+// and when `get_legacy_sql_report` is called. The first sighting of a fingerprint in
+// a run is logged and sent as `sql://legacy-query`, which the frontend prints in dev
+// builds.
+//
+// Statements matching a rule in the `legacy_sql_denylist` setting are rejected with
+// a `migrated` error naming the command to use instead. The list starts empty: the
+// writes to `messages` and `tasks` are the first to deny once the Rust commands for
+// them exist, and the report shows which paths still need one.
+
+use crate::error::CommandError;
+use crate::events::{self, LegacySqlQuery};
+use crate::{background, db, settings};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::ipc::{Invoke, InvokeBody};
+use tauri::plugin::Plugin;
+use tauri::webview::PageLoadPayload;
+use tauri::{AppHandle, Manager, RunEvent, State, Url, Webview, Window, Wry};
+
+const DENYLIST_KEY: &str = "legacy_sql_denylist";
+const FLUSH_ACTIVITY: background::Activity = background::Activity {
+    name: "legacy_sql_flush",
+    interval: Duration::from_secs(60),
+    coalescable: true,
+};
+/// Plugin commands that carry a statement
+const QUERY_COMMANDS: &[&str] = &["execute", "select"];
+
+/// Reject `statement` (e.g. `insert`) on `table` and point at `replacement`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DenyRule {
+    pub statement: String,
+    pub table: String,
+    /// The command that replaced this path
+    pub replacement: String,
+}
+
+impl DenyRule {
+    fn matches(&self, statement: &str, table: Option<&str>) -> bool {
+        self.statement == statement && Some(self.table.as_str()) == table
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LegacySqlEntry {
+    pub fingerprint: String,
+    pub statement: String,
+    pub table: Option<String>,
+    pub count: i64,
+    pub denied: i64,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LegacySqlReport {
+    /// Most used first
+    pub entries: Vec<LegacySqlEntry>,
+    pub total: i64,
+    pub denylist: Vec<DenyRule>,
+}
+
+/// Counts since the last flush for one fingerprint
+struct Usage {
+    statement: String,
+    table: Option<String>,
+    count: i64,
+    denied: i64,
+}
+
+#[derive(Default)]
+pub struct LegacySqlState {
+    usage: Mutex<HashMap<String, Usage>>,
+    denylist: Mutex<Vec<DenyRule>>,
+}
+
+/// What `record` made of one statement
+struct Outcome {
+    /// Set on the first sighting of the fingerprint in this run
+    sighting: Option<LegacySqlQuery>,
+    rejection: Option<CommandError>,
+}
+
+impl LegacySqlState {
+    fn record(&self, sql: &str) -> Outcome {
+        let fingerprint = fingerprint(sql);
+        let (statement, table) = classify(&fingerprint);
+        let rule = match self.denylist.lock() {
+            Ok(rules) => rules.iter().find(|r| r.matches(&statement, table.as_deref())).cloned(),
+            Err(_) => None,
+        };
+
+        let mut usage = match self.usage.lock() {
+            Ok(usage) => usage,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let first = !usage.contains_key(&fingerprint);
+        let entry = usage.entry(fingerprint.clone()).or_insert_with(|| Usage {
+            statement: statement.clone(),
+            table: table.clone(),
+            count: 0,
+            denied: 0,
+        });
+        entry.count += 1;
+        if rule.is_some() {
+            entry.denied += 1;
+        }
+        drop(usage);
+
+        let rejection = rule.as_ref().map(|rule| CommandError::Migrated {
+            message: format!(
+                "Raw SQL {} on {} is no longer allowed from the webview; use the `{}` command",
+                rule.statement.to_uppercase(),
+                rule.table,
+                rule.replacement
+            ),
+            statement: rule.statement.clone(),
+            table: rule.table.clone(),
+            replacement: rule.replacement.clone(),
+        });
+        Outcome {
+            sighting: first.then(|| LegacySqlQuery {
+                fingerprint,
+                statement,
+                table,
+                replacement: rule.map(|rule| rule.replacement),
+            }),
+            rejection,
+        }
+    }
+
+    /// Write the counts gathered since the last flush
+    fn flush(&self, conn: &mut Connection) -> Result<(), String> {
+        let mut pending = Vec::new();
+        {
+            let mut usage = self.usage.lock().map_err(|_| "Legacy SQL counters are unavailable".to_string())?;
+            // Entries stay behind at zero so later queries aren't first sightings
+            for (fingerprint, entry) in usage.iter_mut().filter(|(_, u)| u.count > 0) {
+                pending.push((
+                    fingerprint.clone(),
+                    entry.statement.clone(),
+                    entry.table.clone(),
+                    std::mem::take(&mut entry.count),
+                    std::mem::take(&mut entry.denied),
+                ));
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        {
+            let mut upsert = tx
+                .prepare(
+                    "INSERT INTO legacy_sql_usage (fingerprint, statement, table_name, count, denied)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT(fingerprint) DO UPDATE SET
+                        count = count + excluded.count,
+                        denied = denied + excluded.denied,
+                        last_seen = datetime('now')",
+                )
+                .map_err(|e| e.to_string())?;
+            for (fingerprint, statement, table, count, denied) in &pending {
+                upsert
+                    .execute(params![fingerprint, statement, table, count, denied])
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+}
+
+/// `sql` with comments dropped, whitespace collapsed, keywords and names lowercased,
+/// and every literal, placeholder and list of them replaced by a single `?`
+pub(crate) fn fingerprint(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut pending_space = false;
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                pending_space = !out.is_empty();
+                continue;
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                pending_space = !out.is_empty();
+                continue;
+            }
+            '\'' => {
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                '?'
+            }
+            '$' | '?' | ':' | '@' => {
+                while chars.peek().is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+                    chars.next();
+                }
+                '?'
+            }
+            c if c.is_ascii_digit() && !out.ends_with(|p: char| p.is_alphanumeric() || p == '_') => {
+                while chars.peek().is_some_and(|c| c.is_ascii_alphanumeric() || *c == '.') {
+                    chars.next();
+                }
+                '?'
+            }
+            '"' | '`' | '[' | ']' => continue,
+            ';' => continue,
+            c => c.to_ascii_lowercase(),
+        };
+        if pending_space {
+            out.push(' ');
+            pending_space = false;
+        }
+        out.push(token);
+    }
+    // `IN (?, ?, ?)` and multi-row `VALUES` give the same fingerprint at any length
+    for (run, single) in [("?, ?", "?"), ("?,?", "?"), ("(?), (?)", "(?)"), ("(?),(?)", "(?)")] {
+        while out.contains(run) {
+            out = out.replace(run, single);
+        }
+    }
+    out
+}
+
+/// Statement kind and main table of a fingerprint, e.g. `("insert", Some("messages"))`
+pub(crate) fn classify(fingerprint: &str) -> (String, Option<String>) {
+    let tokens: Vec<&str> = fingerprint
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == ',')
+        .filter(|t| !t.is_empty())
+        .collect();
+    let statement = tokens
+        .iter()
+        .find(|t| matches!(**t, "select" | "insert" | "replace" | "update" | "delete" | "create" | "drop" | "alter"))
+        .or(tokens.first())
+        .map(|t| t.to_string())
+        .unwrap_or_default();
+    let after = |keyword: &str| {
+        tokens
+            .iter()
+            .position(|t| *t == keyword)
+            .and_then(|i| tokens.get(i + 1))
+    };
+    let table = match statement.as_str() {
+        "insert" | "replace" => after("into"),
+        "update" => tokens
+            .iter()
+            .position(|t| *t == "update")
+            .map(|i| if tokens.get(i + 1) == Some(&"or") { i + 3 } else { i + 1 })
+            .and_then(|i| tokens.get(i)),
+        "delete" | "select" => after("from"),
+        _ => None,
+    };
+    let table = table.map(|t| t.strip_prefix("main.").unwrap_or(*t).to_string());
+    (statement, table)
+}
+
+fn load_denylist(conn: &Connection) -> Result<Vec<DenyRule>, String> {
+    settings::get_or(conn, DENYLIST_KEY, Vec::new())
+}
+
+/// The sql plugin with every statement the webview sends passed through `record`
+pub struct LegacySqlPlugin<P> {
+    inner: P,
+}
+
+pub fn wrap<P: Plugin<Wry>>(inner: P) -> LegacySqlPlugin<P> {
+    LegacySqlPlugin { inner }
+}
+
+impl<P: Plugin<Wry>> Plugin<Wry> for LegacySqlPlugin<P> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn initialize(&mut self, app: &AppHandle, config: Value) -> Result<(), Box<dyn std::error::Error>> {
+        self.inner.initialize(app, config)
+    }
+
+    fn initialization_script(&self) -> Option<String> {
+        self.inner.initialization_script()
+    }
+
+    fn window_created(&mut self, window: Window) {
+        self.inner.window_created(window)
+    }
+
+    fn webview_created(&mut self, webview: Webview) {
+        self.inner.webview_created(webview)
+    }
+
+    fn on_navigation(&mut self, webview: &Webview, url: &Url) -> bool {
+        self.inner.on_navigation(webview, url)
+    }
+
+    fn on_page_load(&mut self, webview: &Webview, payload: &PageLoadPayload<'_>) {
+        self.inner.on_page_load(webview, payload)
+    }
+
+    fn on_event(&mut self, app: &AppHandle, event: &RunEvent) {
+        if let RunEvent::Exit = event {
+            let state = app.state::<LegacySqlState>();
+            if let Err(e) = db::open(app).and_then(|mut conn| state.flush(&mut conn)) {
+                eprintln!("[LegacySql] Failed to save usage on exit: {}", e);
+            }
+        }
+        self.inner.on_event(app, event)
+    }
+
+    fn extend_api(&mut self, invoke: Invoke<Wry>) -> bool {
+        let command = invoke.message.command();
+        if QUERY_COMMANDS.contains(&command) {
+            let query = match invoke.message.payload() {
+                InvokeBody::Json(Value::Object(args)) => args.get("query").and_then(|q| q.as_str()),
+                _ => None,
+            };
+            if let Some(query) = query {
+                let app = invoke.message.webview_ref().app_handle().clone();
+                let outcome = app.state::<LegacySqlState>().record(query);
+                if let Some(sighting) = outcome.sighting {
+                    println!(
+                        "[LegacySql] {} {}: {}",
+                        command,
+                        if sighting.replacement.is_some() { "denied" } else { "allowed" },
+                        sighting.fingerprint
+                    );
+                    events::emit_typed(&app, sighting);
+                }
+                if let Some(error) = outcome.rejection {
+                    invoke.resolver.reject(error);
+                    return true;
+                }
+            }
+        }
+        self.inner.extend_api(invoke)
+    }
+}
+
+/// Load the denylist and start the periodic flush
+pub fn start(app: &AppHandle) {
+    match db::open(app).and_then(|conn| load_denylist(&conn)) {
+        Ok(rules) => {
+            if let Ok(mut denylist) = app.state::<LegacySqlState>().denylist.lock() {
+                *denylist = rules;
+            }
+        }
+        Err(e) => eprintln!("[LegacySql] Failed to load the denylist: {}", e),
+    }
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &FLUSH_ACTIVITY);
+        let state = app.state::<LegacySqlState>();
+        if let Err(e) = db::open(&app).and_then(|mut conn| state.flush(&mut conn)) {
+            eprintln!("[LegacySql] Failed to save usage: {}", e);
+        }
+    });
+}
+
+fn report(conn: &Connection, denylist: Vec<DenyRule>) -> Result<LegacySqlReport, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT fingerprint, statement, table_name, count, denied, first_seen, last_seen
+             FROM legacy_sql_usage ORDER BY count DESC, fingerprint",
+        )
+        .map_err(|e| e.to_string())?;
+    let entries = stmt
+        .query_map([], |row| {
+            Ok(LegacySqlEntry {
+                fingerprint: row.get(0)?,
+                statement: row.get(1)?,
+                table: row.get(2)?,
+                count: row.get(3)?,
+                denied: row.get(4)?,
+                first_seen: row.get(5)?,
+                last_seen: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(LegacySqlReport {
+        total: entries.iter().map(|e| e.count).sum(),
+        entries,
+        denylist,
+    })
+}
+
+/// Raw statements the webview has issued, by fingerprint, with the current denylist
+#[tauri::command]
+pub async fn get_legacy_sql_report(app: AppHandle) -> Result<LegacySqlReport, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let state = handle.state::<LegacySqlState>();
+        state.flush(conn)?;
+        let denylist = state.denylist.lock().map(|rules| rules.clone()).unwrap_or_default();
+        report(conn, denylist)
+    })
+    .await
+}
+
+/// Replace the denylist; takes effect for the next statement
+#[tauri::command]
+pub async fn set_legacy_sql_denylist(
+    app: AppHandle,
+    state: State<'_, LegacySqlState>,
+    rules: Vec<DenyRule>,
+) -> Result<Vec<DenyRule>, String> {
+    let rules: Vec<DenyRule> = rules
+        .into_iter()
+        .map(|rule| DenyRule {
+            statement: rule.statement.trim().to_lowercase(),
+            table: rule.table.trim().to_lowercase(),
+            replacement: rule.replacement.trim().to_string(),
+        })
+        .collect();
+    for rule in &rules {
+        if !matches!(rule.statement.as_str(), "select" | "insert" | "replace" | "update" | "delete") {
+            return Err(format!("Unsupported statement in denylist: {}", rule.statement));
+        }
+        if rule.table.is_empty() || rule.replacement.is_empty() {
+            return Err("Denylist rules need a table and a replacement command".to_string());
+        }
+    }
+    let saved = rules.clone();
+    db::with_conn(&app, move |conn| settings::set(conn, DENYLIST_KEY, &saved)).await?;
+    let mut denylist = state.denylist.lock().map_err(|e| e.to_string())?;
+    *denylist = rules.clone();
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn fingerprints_drop_values() {
+        assert_eq!(
+            fingerprint("SELECT * FROM messages WHERE task_id = $1 AND id > 42 -- newest\n ORDER BY id"),
+            "select * from messages where task_id = ? and id > ? order by id"
+        );
+        assert_eq!(
+            fingerprint("INSERT INTO tasks (id, prompt) VALUES ('it''s', $2), ('b', $4);"),
+            "insert into tasks (id, prompt) values (?)"
+        );
+        assert_eq!(
+            fingerprint("DELETE FROM files WHERE id IN (1, 2, 3)"),
+            fingerprint("delete from files where id in (7)")
+        );
+        assert_eq!(fingerprint("SELECT col2 FROM t1"), "select col2 from t1");
+    }
+
+    #[test]
+    fn classifies_statement_and_table() {
+        let cases = [
+            ("INSERT OR REPLACE INTO settings (key) VALUES ($1)", "insert", Some("settings")),
+            ("UPDATE OR IGNORE \"tasks\" SET status = $1", "update", Some("tasks")),
+            ("DELETE FROM messages WHERE task_id = $1", "delete", Some("messages")),
+            ("SELECT COUNT(*) FROM main.files", "select", Some("files")),
+            ("PRAGMA user_version", "pragma", None),
+        ];
+        for (sql, statement, table) in cases {
+            let (s, t) = classify(&fingerprint(sql));
+            assert_eq!((s.as_str(), t.as_deref()), (statement, table), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn denylist_rejects_with_the_replacement_and_still_counts() {
+        let state = LegacySqlState::default();
+        *state.denylist.lock().unwrap() = vec![DenyRule {
+            statement: "insert".to_string(),
+            table: "messages".to_string(),
+            replacement: "append_message".to_string(),
+        }];
+        let first = state.record("INSERT INTO messages (task_id, content) VALUES ($1, $2)");
+        assert!(matches!(
+            first.rejection,
+            Some(CommandError::Migrated { ref replacement, .. }) if replacement == "append_message"
+        ));
+        assert!(first.sighting.is_some());
+        let second = state.record("insert into messages (task_id, content) values ($1, $2)");
+        assert!(second.sighting.is_none());
+        assert!(state.record("SELECT * FROM messages WHERE task_id = $1").rejection.is_none());
+
+        let mut conn = crate::migrations::test_db();
+        state.flush(&mut conn).unwrap();
+        state.record("SELECT * FROM messages WHERE task_id = $1");
+        state.flush(&mut conn).unwrap();
+        let report = report(&conn, Vec::new()).unwrap();
+        let counts: Vec<(i64, i64)> = report.entries.iter().map(|e| (e.count, e.denied)).collect();
+        assert_eq!(counts, vec![(2, 2), (2, 0)]);
+        assert_eq!(report.total, 4);
+    }
+
+    #[test]
+    fn recording_stays_well_under_a_millisecond() {
+        let state = LegacySqlState::default();
+        *state.denylist.lock().unwrap() = vec![DenyRule {
+            statement: "delete".to_string(),
+            table: "tasks".to_string(),
+            replacement: "delete_task".to_string(),
+        }];
+        let long_insert = format!(
+            "INSERT INTO messages (task_id, type, content) VALUES ($1, $2, '{}')",
+            "x".repeat(20_000)
+        );
+        let queries = [
+            "SELECT * FROM tasks WHERE session_id = $1 ORDER BY task_index ASC",
+            "UPDATE tasks SET status = $1, cost = $2, duration = $3, updated_at = datetime('now') WHERE id = $4",
+            "SELECT * FROM messages WHERE task_id = $1 ORDER BY id ASC",
+            long_insert.as_str(),
+        ];
+        const ROUNDS: usize = 2_000;
+        let started = Instant::now();
+        for i in 0..ROUNDS {
+            state.record(queries[i % queries.len()]);
+        }
+        let per_query = started.elapsed() / ROUNDS as u32;
+        assert!(per_query < Duration::from_millis(1), "recording took {:?} per query", per_query);
+    }
+}
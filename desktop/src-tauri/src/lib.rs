@@ -1,16 +1,95 @@
-#[cfg(not(debug_assertions))]
 use tauri::Manager;
-#[cfg(not(debug_assertions))]
-use tauri_plugin_shell::ShellExt;
-#[cfg(not(debug_assertions))]
-use tauri_plugin_shell::process::CommandChild;
-#[cfg(not(debug_assertions))]
-use std::sync::Mutex;
-use tauri_plugin_sql::{Migration, MigrationKind};
 
-// Store the sidecar child process for cleanup on exit
-#[cfg(not(debug_assertions))]
-struct ApiSidecar(Mutex<Option<CommandChild>>);
+mod api_config;
+mod api_health;
+mod archive;
+mod attachment_suggestions;
+mod background;
+mod backup;
+mod captures;
+mod cassette;
+mod checkpoint;
+mod circuit;
+mod consistency;
+mod context_plan;
+mod db;
+mod db_size;
+mod deep_link;
+mod diagnostic_task;
+mod diagnostics;
+mod error;
+mod events;
+mod explain;
+mod export;
+mod export_manifest;
+mod export_presets;
+mod file_repair;
+mod file_scan;
+mod files;
+mod flags;
+mod focus;
+mod focus_sessions;
+mod forks;
+mod fts;
+mod highlight;
+mod idle;
+mod integrity;
+mod ipc_access;
+mod ipc_batch;
+mod jobs;
+mod legacy_sql;
+mod media_backfill;
+mod message_blocks;
+mod message_search;
+mod message_types;
+mod messages;
+mod migrations;
+mod notification_prefs;
+mod notify;
+mod onboarding;
+mod pidfile;
+mod platform;
+mod port_owner;
+mod power;
+mod prompts;
+mod proxy;
+mod quick_look;
+mod quota;
+mod redaction;
+mod review_packet;
+mod routing;
+mod safe_mode;
+mod saved_filters;
+mod schedule;
+mod schema_drift;
+mod secrets;
+mod self_test;
+mod session_files;
+mod settings;
+mod sidecar;
+mod sidecar_log;
+mod sidecar_mode;
+mod splash;
+mod stall;
+mod tags;
+mod task_metrics;
+mod task_notes;
+mod tasks;
+mod thumbnails;
+mod tiling;
+mod timeline;
+mod tokens;
+mod tool_stats;
+mod trash;
+mod viewer_export;
+mod volumes;
+mod webview;
+mod webview_storage;
+mod window;
+mod workspace_analysis;
+mod workspace_ignore;
+
+pub use captures::{run_native_messaging_host, NATIVE_HOST_FLAG};
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -18,271 +97,372 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
-/// Kill any existing process on the API port before starting sidecar
-#[cfg(not(debug_assertions))]
-fn kill_existing_api_process(port: u16) {
-    use std::process::Command;
-
-    // On macOS/Linux, use lsof to find and kill process on port
-    #[cfg(unix)]
-    {
-        if let Ok(output) = Command::new("lsof")
-            .args(["-ti", &format!(":{}", port)])
-            .output()
-        {
-            let pids = String::from_utf8_lossy(&output.stdout);
-            for pid in pids.lines() {
-                if let Ok(pid_num) = pid.trim().parse::<i32>() {
-                    println!("[API] Killing existing process on port {}: PID {}", port, pid_num);
-                    let _ = Command::new("kill")
-                        .args(["-9", &pid_num.to_string()])
-                        .output();
-                }
-            }
-        }
+/// The part of setup that needs the migrated database: create the main window,
+/// start the background work and the API sidecar
+fn finish_setup(app: &tauri::AppHandle) {
+    splash::phase(app, splash::Phase::OpeningWindow);
+    if let Err(e) = window::create_main_window(app) {
+        splash::fail(app, &format!("The main window could not be created: {}", e));
+        return;
     }
+    platform::init(app);
+    window::init(app);
+    migrations::report(app);
+    onboarding::init(app);
+    splash::phase(app, splash::Phase::StartingServices);
+    // Background starters, skipped in safe mode
+    safe_mode::init(app);
+    splash::start_fallback(app);
 
-    // On Windows, use netstat and taskkill
-    #[cfg(windows)]
-    {
-        if let Ok(output) = Command::new("netstat")
-            .args(["-ano", "-p", "TCP"])
-            .output()
-        {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-            for line in output_str.lines() {
-                if line.contains(&format!(":{}", port)) && line.contains("LISTENING") {
-                    if let Some(pid) = line.split_whitespace().last() {
-                        println!("[API] Killing existing process on port {}: PID {}", port, pid);
-                        let _ = Command::new("taskkill")
-                            .args(["/F", "/PID", pid])
-                            .output();
-                    }
-                }
-            }
-        }
+    // Spawn the bundled API sidecar on this run's port, unless the API is run
+    // separately (`pnpm dev:api` in development, for hot reload). Safe mode defers
+    // the sidecar until force_restart_api, but the port is chosen now, after any
+    // sidecar a killed previous run left on it is stopped
+    let mode = sidecar_mode::decide(app, sidecar::default_external_port());
+    if !mode.managed {
+        splash::phase(app, splash::Phase::WaitingForApi);
+        api_health::watch_api_ready(app, mode.external_port);
+        return;
+    }
+    splash::phase(app, splash::Phase::StartingApi);
+    match app.state::<sidecar::Manager>().start(app) {
+        Ok(true) => splash::phase(app, splash::Phase::WaitingForApi),
+        // No readiness probe runs without the sidecar
+        Ok(false) => splash::phase(app, splash::Phase::LoadingInterface),
+        Err(e) => splash::fail(app, &format!("The API server could not be started: {}", e)),
     }
-
-    // Give the OS a moment to release the port
-    std::thread::sleep(std::time::Duration::from_millis(500));
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Database migrations
-    let migrations = vec![
-        Migration {
-            version: 1,
-            description: "create_tasks_and_messages_tables",
-            sql: r#"
-                CREATE TABLE IF NOT EXISTS tasks (
-                    id TEXT PRIMARY KEY NOT NULL,
-                    prompt TEXT NOT NULL,
-                    status TEXT NOT NULL DEFAULT 'running',
-                    cost REAL,
-                    duration INTEGER,
-                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
-                );
+    splash::begin();
+    let context = tauri::generate_context!();
+    let identifier = context.config().identifier.clone();
+    // First, so a failure in anything after it counts towards a crash loop
+    let startup = safe_mode::begin(&identifier);
+    // The webview reads these at startup, so they must be set before any window exists
+    let hardware_acceleration = webview::apply_hardware_acceleration(&identifier);
+    // Webview files are only unlocked before the first window, the splash, opens
+    webview_storage::apply_pending_cleanup(&identifier);
+    let sidecar = sidecar::Manager::default();
 
-                CREATE TABLE IF NOT EXISTS messages (
-                    id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    task_id TEXT NOT NULL,
-                    type TEXT NOT NULL,
-                    content TEXT,
-                    tool_name TEXT,
-                    tool_input TEXT,
-                    subtype TEXT,
-                    error_message TEXT,
-                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
-                );
-
-                CREATE INDEX IF NOT EXISTS idx_messages_task_id ON messages(task_id);
-            "#,
-            kind: MigrationKind::Up,
-        },
-        Migration {
-            version: 2,
-            description: "add_tool_result_fields",
-            sql: r#"
-                ALTER TABLE messages ADD COLUMN tool_output TEXT;
-                ALTER TABLE messages ADD COLUMN tool_use_id TEXT;
-            "#,
-            kind: MigrationKind::Up,
-        },
-        Migration {
-            version: 3,
-            description: "create_files_table",
-            sql: r#"
-                CREATE TABLE IF NOT EXISTS files (
-                    id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    task_id TEXT NOT NULL,
-                    name TEXT NOT NULL,
-                    type TEXT NOT NULL,
-                    path TEXT NOT NULL,
-                    preview TEXT,
-                    thumbnail TEXT,
-                    is_favorite INTEGER NOT NULL DEFAULT 0,
-                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
-                );
-
-                CREATE INDEX IF NOT EXISTS idx_files_task_id ON files(task_id);
-            "#,
-            kind: MigrationKind::Up,
-        },
-        Migration {
-            version: 4,
-            description: "create_settings_table",
-            sql: r#"
-                CREATE TABLE IF NOT EXISTS settings (
-                    key TEXT PRIMARY KEY NOT NULL,
-                    value TEXT NOT NULL,
-                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
-                );
-            "#,
-            kind: MigrationKind::Up,
-        },
-        Migration {
-            version: 5,
-            description: "create_sessions_table_and_update_tasks",
-            sql: r#"
-                CREATE TABLE IF NOT EXISTS sessions (
-                    id TEXT PRIMARY KEY NOT NULL,
-                    prompt TEXT NOT NULL,
-                    task_count INTEGER NOT NULL DEFAULT 0,
-                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
-                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
-                );
-
-                ALTER TABLE tasks ADD COLUMN session_id TEXT;
-                ALTER TABLE tasks ADD COLUMN task_index INTEGER DEFAULT 1;
-
-                CREATE INDEX IF NOT EXISTS idx_tasks_session_id ON tasks(session_id);
-            "#,
-            kind: MigrationKind::Up,
-        },
-        Migration {
-            version: 6,
-            description: "add_attachments_to_messages",
-            sql: r#"
-                ALTER TABLE messages ADD COLUMN attachments TEXT;
-            "#,
-            kind: MigrationKind::Up,
-        },
-        Migration {
-            version: 7,
-            description: "add_favorite_to_tasks",
-            sql: r#"
-                ALTER TABLE tasks ADD COLUMN favorite INTEGER DEFAULT 0;
-            "#,
-            kind: MigrationKind::Up,
-        },
-    ];
-
-    #[cfg(not(debug_assertions))]
-    let api_sidecar = ApiSidecar(Mutex::new(None));
-
-    #[allow(unused_mut)]
-    let mut builder = tauri::Builder::default()
+    tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_shell::init())
-        .plugin(
+        .plugin(tauri_plugin_deep_link::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(legacy_sql::wrap(
             tauri_plugin_sql::Builder::default()
-                .add_migrations("sqlite:workany.db", migrations)
+                .add_migrations(db::DB_URL, migrations::plugin_migrations())
                 .build(),
-        );
-
-    // Manage the sidecar state in production
-    #[cfg(not(debug_assertions))]
-    {
-        builder = builder.manage(api_sidecar);
-    }
-
-    builder
-        .setup(|app| {
-            // In development mode (tauri dev), skip sidecar and use external API server
-            // Run `pnpm dev:api` separately for hot-reload support
-            // In production, spawn the bundled API sidecar
-            #[cfg(not(debug_assertions))]
-            {
-                const API_PORT: u16 = 2620;
-
-                // Kill any existing process on the API port
-                kill_existing_api_process(API_PORT);
-
-                let sidecar_command = app.shell().sidecar("workany-api")
-                    .unwrap()
-                    .env("PORT", API_PORT.to_string())
-                    .env("NODE_ENV", "production");
-                let (mut rx, child) = sidecar_command.spawn().expect("Failed to spawn API sidecar");
-
-                // Store the child process for cleanup on exit
-                if let Some(state) = app.try_state::<ApiSidecar>() {
-                    if let Ok(mut guard) = state.0.lock() {
-                        *guard = Some(child);
-                    }
+        ))
+        .manage(jobs::JobRegistry::default())
+        .manage(window::CloseBehaviorState::default())
+        .manage(tokens::TokenCache::default())
+        .manage(messages::MessageCache::default())
+        .manage(hardware_acceleration)
+        .manage(idle::IdleShutdownState::default())
+        .manage(secrets::Secrets::default())
+        .manage(diagnostics::DiagnosticsState::default())
+        .manage(stall::StallWatchdog::default())
+        .manage(quick_look::QuickLookState::default())
+        .manage(sidecar_log::SidecarLog::default())
+        .manage(focus::FocusArbiter::default())
+        .manage(circuit::CircuitBreakers::default())
+        .manage(power::PowerState::default())
+        .manage(quota::QuotaState::default())
+        .manage(background::BackgroundState::default())
+        .manage(platform::PlatformState::default())
+        .manage(legacy_sql::LegacySqlState::default())
+        .manage(flags::FlagLane::default())
+        .manage(message_blocks::BlockCache::default())
+        .manage(cassette::ApiTape::default())
+        .manage(workspace_analysis::AnalysisCache::default())
+        .manage(attachment_suggestions::SuggestionIndex::default())
+        .manage(context_plan::ContextTokenCache::default())
+        .manage(volumes::VolumeMonitor::default())
+        .manage(startup)
+        .manage(api_health::ApiReadiness::default())
+        .register_uri_scheme_protocol(thumbnails::STORE_SCHEME, |_ctx, request| thumbnails::serve(request))
+        .manage(sidecar.clone())
+        .on_window_event(window::on_window_event)
+        .setup(move |app| {
+            // The splash shows while the database is prepared off the main thread;
+            // the rest of setup needs it and the main window, so it runs after
+            splash::open(app.handle());
+            let handle = app.handle().clone();
+            std::thread::spawn(move || {
+                splash::phase(&handle, splash::Phase::RestoringBackup);
+                // Applied before any webview can open the database through the sql plugin
+                backup::apply_pending_restore(&identifier);
+                splash::phase(&handle, splash::Phase::Migrating);
+                tauri::Manager::manage(&handle, migrations::migrate_at_startup(&identifier));
+                let main_thread = handle.clone();
+                if let Err(e) = handle.run_on_main_thread(move || finish_setup(&main_thread)) {
+                    splash::fail(&handle, &format!("Startup could not continue: {}", e));
                 }
-
-                // Log sidecar output
-                tauri::async_runtime::spawn(async move {
-                    use tauri_plugin_shell::process::CommandEvent;
-                    while let Some(event) = rx.recv().await {
-                        match event {
-                            CommandEvent::Stdout(line) => {
-                                println!("[API] {}", String::from_utf8_lossy(&line));
-                            }
-                            CommandEvent::Stderr(line) => {
-                                eprintln!("[API Error] {}", String::from_utf8_lossy(&line));
-                            }
-                            CommandEvent::Error(error) => {
-                                eprintln!("[API Spawn Error] {}", error);
-                            }
-                            CommandEvent::Terminated(status) => {
-                                println!("[API] Process terminated with status: {:?}", status);
-                                break;
-                            }
-                            _ => {}
-                        }
-                    }
-                });
-            }
-
-            #[cfg(debug_assertions)]
-            {
-                // Suppress unused variable warning in debug mode
-                let _ = app;
-                println!("[Tauri Dev] API sidecar disabled. Run `pnpm dev:api` for the API server on port 2026.");
-            }
-
+            });
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![greet])
-        .build(tauri::generate_context!())
+        .invoke_handler(diagnostics::count_ipc(ipc_access::guard(tauri::generate_handler![
+            greet,
+            sidecar::force_restart_api,
+            sidecar::restart_api_sidecar,
+            api_health::get_api_status,
+            sidecar::get_api_port,
+            sidecar::api_status,
+            sidecar::get_sidecar_status,
+            sidecar::set_pinned_api_port,
+            sidecar::set_sidecar_enabled,
+            sidecar::set_api_port,
+            api_health::set_api_ready_timeout,
+            sidecar::set_sidecar_shutdown_grace,
+            sidecar::set_sidecar_stdout_events,
+            jobs::get_job,
+            jobs::list_jobs,
+            jobs::cancel_job,
+            redaction::redact_message_content,
+            redaction::redact_pattern_across_task,
+            consistency::find_orphaned_messages,
+            consistency::cleanup_orphaned_messages,
+            consistency::find_orphaned_files,
+            consistency::cleanup_orphaned_files,
+            self_test::run_self_test,
+            self_test::get_last_self_test,
+            window::get_close_behavior,
+            window::set_close_behavior,
+            tiling::tile_windows,
+            tiling::retile,
+            thumbnails::prewarm_thumbnails,
+            tokens::estimate_tokens,
+            tokens::get_model_pricing,
+            tasks::task_neighbors,
+            files::rename_managed_file,
+            files::move_managed_file,
+            webview::get_hardware_acceleration,
+            webview::set_hardware_acceleration,
+            tool_stats::get_tool_stats,
+            tool_stats::backfill_tool_invocations,
+            explain::list_explainable_queries,
+            explain::explain_query,
+            api_config::get_api_config,
+            api_config::set_external_api_url,
+            api_config::verify_api_identity,
+            api_config::trust_new_api_identity,
+            api_config::get_api_pin_events,
+            files::coalesce_task_files,
+            messages::list_messages,
+            messages::prime_message_cache,
+            messages::get_message_cache_stats,
+            db_size::check_db_size,
+            db_size::set_max_db_size,
+            integrity::take_integrity_snapshot,
+            integrity::get_integrity_history,
+            captures::install_native_messaging_host,
+            captures::uninstall_native_messaging_host,
+            captures::list_pending_captures,
+            captures::convert_capture_to_task,
+            captures::dismiss_capture,
+            tags::tag_autocomplete,
+            migrations::get_migration_status,
+            migrations::acknowledge_migration_checksums,
+            idle::set_idle_shutdown,
+            idle::ensure_api_running,
+            secrets::store_secret,
+            secrets::get_secret,
+            secrets::delete_secret,
+            secrets::get_secrets_backend_info,
+            secrets::setup_file_secrets,
+            secrets::unlock_secrets,
+            secrets::migrate_secrets_backend,
+            deep_link::session_deep_link,
+            deep_link::copy_session_link,
+            diagnostics::set_devtools,
+            diagnostics::get_window_diagnostics,
+            diagnostics::toggle_diagnostic_overlay,
+            onboarding::is_first_run,
+            onboarding::get_first_run_state,
+            onboarding::complete_onboarding,
+            forks::fork_task,
+            forks::get_fork_tree,
+            export::bulk_export_tasks,
+            events::get_event_catalog,
+            stall::detect_stalled_tasks,
+            stall::set_stall_timeout,
+            quick_look::can_quick_look,
+            quick_look::quick_look_file,
+            notify::get_notification_settings,
+            notify::set_notification_sound,
+            notify::set_notify_on_complete,
+            notify::play_notification_sound,
+            export::export_all,
+            checkpoint::resume_job,
+            checkpoint::discard_export_journal,
+            fts::reindex_fts,
+            focus::request_task_focus,
+            focus::report_user_typing,
+            focus::report_manual_navigation,
+            focus::get_focus_history,
+            focus::set_auto_focus,
+            circuit::get_circuit_status,
+            circuit::reset_circuits,
+            proxy::sidecar_request,
+            power::get_sleep_intervals,
+            power::get_awake_ms,
+            routing::create_task,
+            routing::list_routing_rules,
+            routing::create_routing_rule,
+            routing::update_routing_rule,
+            routing::delete_routing_rule,
+            routing::reorder_routing_rules,
+            routing::test_routing_rules,
+            routing::get_task_routing_explanation,
+            archive::list_archive_contents,
+            archive::extract_archive_entries,
+            quota::get_quota_status,
+            quota::set_quota,
+            file_repair::scan_file_integrity,
+            file_repair::get_file_repair_report,
+            file_repair::list_file_repair_reports,
+            file_repair::apply_file_repairs,
+            background::get_power_efficiency_report,
+            file_scan::scan_file,
+            file_scan::set_require_scan_before_open,
+            file_scan::get_scan_settings,
+            quick_look::open_file,
+            diagnostic_task::create_diagnostic_task,
+            prompts::get_full_prompt,
+            prompts::get_prompt_storage,
+            prompts::set_prompt_inline_limit,
+            prompts::backfill_prompt_overflow,
+            session_files::store_attachment_file,
+            session_files::add_session_file,
+            session_files::remove_session_file,
+            session_files::list_session_files,
+            session_files::reorder_session_files,
+            session_files::set_session_file_always_include,
+            session_files::delete_attachment,
+            media_backfill::get_media_backfill_status,
+            media_backfill::get_backfill_errors,
+            review_packet::export_review_packet,
+            review_packet::import_review_comments,
+            review_packet::list_message_notes,
+            platform::get_system_capabilities,
+            timeline::get_timeline_buckets,
+            timeline::get_timeline_day,
+            legacy_sql::get_legacy_sql_report,
+            legacy_sql::set_legacy_sql_denylist,
+            schedule::preview_schedule,
+            flags::set_flag,
+            trash::trash_task,
+            trash::restore_task,
+            trash::get_trash_summary,
+            trash::empty_trash,
+            trash::set_trash_limits,
+            message_blocks::get_message_blocks,
+            cassette::start_api_recording,
+            cassette::start_api_replay,
+            cassette::stop_api_cassette,
+            cassette::record_api_stream,
+            cassette::replay_api_stream,
+            notify::get_notification_prefs,
+            notify::set_notification_prefs,
+            notify::preview_notification,
+            workspace_ignore::test_ignore_rules,
+            workspace_ignore::get_workspace_ignore_overrides,
+            workspace_ignore::set_workspace_ignore_overrides,
+            export_manifest::get_export_signing_key,
+            export_manifest::rotate_export_signing_key,
+            export_manifest::verify_export,
+            ipc_batch::batch_invoke,
+            ipc_batch::list_batchable_commands,
+            backup::backup_database,
+            backup::list_backups,
+            backup::verify_backup,
+            backup::restore_backup,
+            backup::get_backup_policy,
+            backup::set_backup_policy,
+            task_notes::get_task_note,
+            task_notes::set_task_note,
+            workspace_analysis::analyze_workspace,
+            workspace_analysis::get_workspace_analysis,
+            viewer_export::export_interactive_viewer,
+            viewer_export::validate_viewer_export,
+            safe_mode::get_safe_mode_report,
+            safe_mode::disable_feature_and_restart,
+            safe_mode::enable_startup_feature,
+            safe_mode::reset_setting_and_restart,
+            safe_mode::retry_full_startup,
+            attachment_suggestions::suggest_attachments,
+            attachment_suggestions::accept_attachment_suggestion,
+            webview_storage::get_webview_storage_usage,
+            webview_storage::clear_webview_storage,
+            webview_storage::get_storage_breakdown,
+            api_health::check_api_health,
+            context_plan::get_context_plan,
+            context_plan::set_context_overrides,
+            context_plan::get_sent_context,
+            context_plan::set_message_include_in_context,
+            volumes::get_volume_status,
+            volumes::set_volume_timeout,
+            focus_sessions::list_focus_sessions,
+            focus_sessions::end_focus_session,
+            focus_sessions::set_focus_gap,
+            schema_drift::get_schema_drift_report,
+            sidecar_log::get_api_log_path,
+            sidecar_log::open_api_log,
+            sidecar_log::read_api_log_tail,
+            saved_filters::list_saved_filters,
+            saved_filters::create_saved_filter,
+            saved_filters::update_saved_filter,
+            saved_filters::delete_saved_filter,
+            saved_filters::reorder_saved_filters,
+            saved_filters::evaluate_filter,
+            task_metrics::report_task_metrics,
+            task_metrics::get_metric_history,
+            message_search::search_messages,
+            export_presets::list_export_presets,
+            export_presets::create_export_preset,
+            export_presets::update_export_preset,
+            export_presets::delete_export_preset,
+            export_presets::run_export_preset,
+            export_presets::list_deliveries,
+            message_types::normalize_message_types,
+            tags::add_tag,
+            tags::remove_tag,
+            tags::list_tags,
+            tags::tasks_by_tag,
+        ])))
+        .build(context)
         .expect("error while building tauri application")
-        .run(|app_handle, event| {
-            // Handle app exit to cleanup sidecar
-            if let tauri::RunEvent::Exit = event {
-                #[cfg(not(debug_assertions))]
-                {
-                    println!("[App] Cleaning up API sidecar...");
-                    if let Some(state) = app_handle.try_state::<ApiSidecar>() {
-                        if let Ok(mut guard) = state.0.lock() {
-                            if let Some(child) = guard.take() as Option<CommandChild> {
-                                println!("[App] Killing API sidecar process...");
-                                let _ = child.kill();
-                            }
+        .run(move |app_handle, event| match event {
+            // Hold the exit until the sidecar has shut down gracefully (or been killed
+            // after the grace period), then exit again
+            tauri::RunEvent::ExitRequested { api, code, .. } => {
+                if sidecar.begin_shutdown() {
+                    api.prevent_exit();
+                    println!("[App] Stopping API sidecar before exit...");
+                    let sidecar = sidecar.clone();
+                    let handle = app_handle.clone();
+                    std::thread::spawn(move || {
+                        if sidecar.stop() {
+                            println!("[App] API sidecar stopped");
                         }
-                    }
-                    // Also try to kill by port as a fallback
-                    kill_existing_api_process(2620);
+                        handle.exit(code.unwrap_or(0));
+                    });
                 }
-                #[cfg(debug_assertions)]
-                {
-                    let _ = app_handle;
+            }
+            // Handle app exit to clean up the preview process and the sidecar
+            tauri::RunEvent::Exit => {
+                splash::close(app_handle);
+                quick_look::close_preview(app_handle);
+                if sidecar_mode::managed() {
+                    println!("[App] Cleaning up API sidecar...");
+                    sidecar.shutdown();
                 }
             }
+            _ => {}
         });
 }
@@ -2,5 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // Started by a browser as the page-capture native messaging host
+    if std::env::args().any(|arg| arg == workany_lib::NATIVE_HOST_FLAG) {
+        std::process::exit(workany_lib::run_native_messaging_host());
+    }
     workany_lib::run()
 }
@@ -0,0 +1,347 @@
+// Startup backfill that moves legacy media out of `files` rows.
+//
+// Older rows keep thumbnails as base64 data URLs in `files.thumbnail`, and some keep
+// whole file contents in `files.preview`. The backfill walks `files` by id in
+// batches of `BATCH`, moves thumbnails into the content-addressed store (see
+// `thumbnails`), cuts previews down with `files::preview` and sets `media_format`.
+// Each batch commits together with the high-water mark, so quitting mid-way resumes
+// at the next batch on the following launch. A row that can't be converted is
+// recorded in `backfill_errors` and left as it was.
+//
+// Until a pass completes, readers go by each row's `media_format`; once
+// `media_backfill_complete` is set they read the new columns only.
+// `has_thumbnail_sql` is the compatibility shim to delete once every install has
+// completed.
+
+use crate::{db, files, jobs, settings, thumbnails};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use serde_json::json;
+use tauri::AppHandle;
+
+const BACKFILL: &str = "media";
+const JOB_KIND: &str = "media_backfill";
+const BATCH: i64 = 500;
+const HIGH_WATER_KEY: &str = "media_backfill_high_water";
+const COMPLETE_KEY: &str = "media_backfill_complete";
+const RECLAIMED_KEY: &str = "media_backfill_reclaimed_bytes";
+
+/// `files.media_format` of rows written before the thumbnail store
+pub(crate) const FORMAT_LEGACY: i64 = 0;
+/// Thumbnail in the store, preview no longer than `files::PREVIEW_CHARS`
+pub(crate) const FORMAT_STORED: i64 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaBackfillStatus {
+    pub complete: bool,
+    /// Highest `files.id` the backfill has processed
+    pub high_water: i64,
+    /// Rows above the high-water mark still in the legacy format
+    pub remaining_rows: i64,
+    pub reclaimed_bytes: u64,
+    pub errors: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackfillError {
+    pub id: i64,
+    pub backfill: String,
+    pub row_id: i64,
+    pub error: String,
+    pub created_at: String,
+}
+
+struct FileRow {
+    id: i64,
+    format: i64,
+    thumbnail: Option<String>,
+    preview: Option<String>,
+}
+
+struct Converted {
+    thumbnail_hash: Option<String>,
+    preview: Option<String>,
+    reclaimed: u64,
+}
+
+#[derive(Debug, Default)]
+struct BatchResult {
+    rows: u64,
+    converted: u64,
+    failed: u64,
+    reclaimed: u64,
+    high_water: i64,
+}
+
+pub(crate) fn is_complete(conn: &Connection) -> Result<bool, String> {
+    settings::get_or(conn, COMPLETE_KEY, false)
+}
+
+/// SQL for "this `files` row has a thumbnail"
+pub(crate) fn has_thumbnail_sql(conn: &Connection) -> Result<&'static str, String> {
+    Ok(if is_complete(conn)? {
+        "thumbnail_hash IS NOT NULL"
+    } else {
+        "(thumbnail_hash IS NOT NULL OR (media_format = 0 AND COALESCE(thumbnail, '') != ''))"
+    })
+}
+
+fn convert(row: &FileRow) -> Result<Converted, String> {
+    let thumbnail = row.thumbnail.as_deref().filter(|t| !t.is_empty());
+    let thumbnail_hash = thumbnail
+        .map(|url| thumbnails::decode_data_url(url).and_then(|bytes| thumbnails::store_bytes(&bytes)))
+        .transpose()
+        .map_err(|e| format!("thumbnail: {}", e))?;
+    let preview = row.preview.as_deref().map(files::preview);
+    let reclaimed = thumbnail.map_or(0, str::len)
+        + row.preview.as_deref().map_or(0, str::len)
+        - preview.map_or(0, str::len);
+    Ok(Converted {
+        thumbnail_hash,
+        preview: preview.map(str::to_string),
+        reclaimed: reclaimed as u64,
+    })
+}
+
+/// Convert the next batch above `high_water`; `None` once no rows are left
+fn run_batch(conn: &Connection, high_water: i64) -> Result<Option<BatchResult>, String> {
+    let rows: Vec<FileRow> = {
+        let mut stmt = conn
+            .prepare("SELECT id, media_format, thumbnail, preview FROM files WHERE id > ?1 ORDER BY id LIMIT ?2")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![high_water, BATCH], |row| {
+                Ok(FileRow {
+                    id: row.get(0)?,
+                    format: row.get(1)?,
+                    thumbnail: row.get(2)?,
+                    preview: row.get(3)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    let Some(last) = rows.last() else {
+        return Ok(None);
+    };
+    let mut result = BatchResult {
+        rows: rows.len() as u64,
+        high_water: last.id,
+        ..Default::default()
+    };
+    // Store writes happen before the transaction; they are idempotent by hash
+    let outcomes: Vec<(i64, Result<Converted, String>)> = rows
+        .iter()
+        .filter(|row| row.format == FORMAT_LEGACY)
+        .map(|row| (row.id, convert(row)))
+        .collect();
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    for (id, outcome) in outcomes {
+        match outcome {
+            Ok(converted) => {
+                tx.execute(
+                    "UPDATE files SET thumbnail = NULL, thumbnail_hash = COALESCE(?1, thumbnail_hash),
+                                      preview = ?2, media_format = ?3
+                     WHERE id = ?4",
+                    params![converted.thumbnail_hash, converted.preview, FORMAT_STORED, id],
+                )
+                .map_err(|e| e.to_string())?;
+                result.converted += 1;
+                result.reclaimed += converted.reclaimed;
+            }
+            Err(error) => {
+                tx.execute(
+                    "INSERT INTO backfill_errors (backfill, row_id, error) VALUES (?1, ?2, ?3)",
+                    params![BACKFILL, id, error],
+                )
+                .map_err(|e| e.to_string())?;
+                result.failed += 1;
+            }
+        }
+    }
+    let reclaimed: u64 = settings::get_or(&tx, RECLAIMED_KEY, 0)?;
+    settings::set(&tx, RECLAIMED_KEY, &(reclaimed + result.reclaimed))?;
+    settings::set(&tx, HIGH_WATER_KEY, &result.high_water)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(Some(result))
+}
+
+fn freelist_bytes(conn: &Connection) -> Result<u64, String> {
+    conn.query_row(
+        "SELECT (SELECT freelist_count FROM pragma_freelist_count) * (SELECT page_size FROM pragma_page_size)",
+        [],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|bytes| bytes.max(0) as u64)
+    .map_err(|e| e.to_string())
+}
+
+fn run(ctx: &jobs::JobContext) -> Result<serde_json::Value, String> {
+    let conn = db::open(ctx.app())?;
+    let mut high_water: i64 = settings::get_or(&conn, HIGH_WATER_KEY, 0)?;
+    let (mut done, total): (i64, i64) = conn
+        .query_row(
+            "SELECT COUNT(*) FILTER (WHERE id <= ?1), COUNT(*) FROM files",
+            [high_water],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let resumed_from = done;
+    let mut totals = BatchResult::default();
+    ctx.progress(done as u64, total as u64, None);
+    loop {
+        if ctx.is_cancelled() {
+            return Err("Cancelled".to_string());
+        }
+        let Some(batch) = run_batch(&conn, high_water)? else {
+            break;
+        };
+        high_water = batch.high_water;
+        done += batch.rows as i64;
+        totals.converted += batch.converted;
+        totals.failed += batch.failed;
+        totals.reclaimed += batch.reclaimed;
+        ctx.progress(done as u64, total.max(done) as u64, None);
+    }
+    settings::set(&conn, COMPLETE_KEY, &true)?;
+    // Freed pages stay in the file until a VACUUM; report what one would give back
+    let freelist_bytes = freelist_bytes(&conn)?;
+    println!(
+        "[MediaBackfill] Converted {} row(s), {} failed, {} bytes moved out of files ({} bytes free in the database)",
+        totals.converted, totals.failed, totals.reclaimed, freelist_bytes
+    );
+    Ok(json!({
+        "converted": totals.converted,
+        "failed": totals.failed,
+        "reclaimed_bytes": totals.reclaimed,
+        "freelist_bytes": freelist_bytes,
+        "resumed_from_row": resumed_from,
+    }))
+}
+
+/// Start the backfill when rows above the high-water mark are still in the legacy
+/// format, which is always the case until the first pass completes
+pub fn start(app: &AppHandle) {
+    let pending = db::open(app).and_then(|conn| {
+        let high_water: i64 = settings::get_or(&conn, HIGH_WATER_KEY, 0)?;
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM files WHERE id > ?1 AND media_format = ?2)",
+            params![high_water, FORMAT_LEGACY],
+            |row| row.get::<_, bool>(0),
+        )
+        .map_err(|e| e.to_string())
+    });
+    match pending {
+        Ok(true) => {
+            jobs::spawn(app, JOB_KIND, run);
+        }
+        Ok(false) => {}
+        Err(e) => eprintln!("[MediaBackfill] Cannot check for legacy rows: {}", e),
+    }
+}
+
+#[tauri::command]
+pub async fn get_media_backfill_status(app: AppHandle) -> Result<MediaBackfillStatus, String> {
+    db::with_conn(&app, |conn| {
+        let high_water: i64 = settings::get_or(conn, HIGH_WATER_KEY, 0)?;
+        let (remaining_rows, errors) = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM files WHERE id > ?1 AND media_format = ?2),
+                        (SELECT COUNT(*) FROM backfill_errors WHERE backfill = ?3)",
+                params![high_water, FORMAT_LEGACY, BACKFILL],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(MediaBackfillStatus {
+            complete: is_complete(conn)?,
+            high_water,
+            remaining_rows,
+            reclaimed_bytes: settings::get_or(conn, RECLAIMED_KEY, 0)?,
+            errors,
+        })
+    })
+    .await
+}
+
+/// Rows the backfills couldn't convert, newest first
+#[tauri::command]
+pub async fn get_backfill_errors(app: AppHandle) -> Result<Vec<BackfillError>, String> {
+    db::with_conn(&app, |conn| {
+        let mut stmt = conn
+            .prepare("SELECT id, backfill, row_id, error, created_at FROM backfill_errors ORDER BY id DESC")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(BackfillError {
+                    id: row.get(0)?,
+                    backfill: row.get(1)?,
+                    row_id: row.get(2)?,
+                    error: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        crate::migrations::test_db()
+    }
+
+    fn insert_file(conn: &Connection, id: i64, thumbnail: Option<&str>, preview: Option<&str>) {
+        conn.execute(
+            "INSERT INTO files (id, task_id, name, type, path, thumbnail, preview)
+             VALUES (?1, 't1', 'f', 'image', '/tmp/f', ?2, ?3)",
+            params![id, thumbnail, preview],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn batches_advance_the_high_water_mark_and_record_failures() {
+        let conn = db();
+        let long = "x".repeat(files::PREVIEW_CHARS * 3);
+        insert_file(&conn, 1, None, Some(&long));
+        insert_file(&conn, 2, Some("data:image/png;base64,not-base64!"), None);
+        insert_file(&conn, 3, None, Some("short"));
+
+        let batch = run_batch(&conn, 0).unwrap().unwrap();
+        assert_eq!((batch.rows, batch.converted, batch.failed), (3, 2, 1));
+        assert_eq!(batch.high_water, 3);
+        assert_eq!(batch.reclaimed, (files::PREVIEW_CHARS * 2) as u64);
+        assert_eq!(settings::get_or(&conn, HIGH_WATER_KEY, 0i64).unwrap(), 3);
+        assert!(run_batch(&conn, 3).unwrap().is_none());
+
+        let preview: String = conn.query_row("SELECT preview FROM files WHERE id = 1", [], |r| r.get(0)).unwrap();
+        assert_eq!(preview.len(), files::PREVIEW_CHARS);
+        let (format, thumbnail): (i64, Option<String>) = conn
+            .query_row("SELECT media_format, thumbnail FROM files WHERE id = 2", [], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!(format, FORMAT_LEGACY);
+        assert!(thumbnail.is_some());
+        let failed: i64 = conn
+            .query_row("SELECT row_id FROM backfill_errors WHERE backfill = 'media'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(failed, 2);
+    }
+
+    #[test]
+    fn read_path_drops_legacy_thumbnails_once_complete() {
+        let conn = db();
+        insert_file(&conn, 1, Some("data:image/png;base64,AAAA"), None);
+        let count = |conn: &Connection| -> i64 {
+            let sql = format!("SELECT COUNT(*) FROM files WHERE {}", has_thumbnail_sql(conn).unwrap());
+            conn.query_row(&sql, [], |r| r.get(0)).unwrap()
+        };
+        assert_eq!(count(&conn), 1);
+        settings::set(&conn, COMPLETE_KEY, &true).unwrap();
+        assert_eq!(count(&conn), 0);
+    }
+}
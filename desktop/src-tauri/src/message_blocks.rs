@@ -0,0 +1,497 @@
+// Long assistant messages split into top-level markdown blocks.
+//
+// `get_message_blocks` parses a message with pulldown-cmark and returns one entry
+// per top-level block, with the block's markdown source, a plain-text extract for
+// find-in-task, and code pre-highlighted by `highlight`. The webview renders and
+// virtualizes the blocks one by one instead of one 200KB document. Block indices are
+// positions in the message, so they stay the same for the same content.
+//
+// Results are cached by content hash and looked up by message id. The cache follows
+// the `message_changes` feed (see `messages`), so an edited or redacted message is
+// re-parsed on the next request. Parsing is bounded by input size, nesting depth,
+// block count and time. Past any limit the message comes back as one raw block.
+
+use crate::{db, highlight};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const MAX_INPUT_BYTES: usize = 4 * 1024 * 1024;
+const MAX_DEPTH: usize = 32;
+const MAX_BLOCKS: usize = 20_000;
+const MAX_PARSE_TIME: Duration = Duration::from_millis(500);
+const MAX_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlockKind {
+    Paragraph,
+    Heading { level: u8 },
+    Code { language: Option<String>, html: Option<String> },
+    Table,
+    List { ordered: bool },
+    Image { src: String, alt: String },
+    Quote,
+    Rule,
+    Html,
+    /// Anything else, and the whole message when parsing gave up
+    Raw,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Block {
+    pub index: usize,
+    #[serde(flatten)]
+    pub kind: BlockKind,
+    /// The block's markdown, as written in the message
+    pub source: String,
+    /// Text without markup, for search hits
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageBlocks {
+    pub message_id: i64,
+    pub content_hash: String,
+    pub blocks: Arc<Vec<Block>>,
+    /// Why the message came back as one raw block
+    pub degraded: Option<String>,
+}
+
+struct Parsed {
+    blocks: Arc<Vec<Block>>,
+    degraded: Option<String>,
+    bytes: usize,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    by_hash: HashMap<String, Parsed>,
+    /// Which content each message had when it was last parsed
+    by_message: HashMap<i64, String>,
+    feed_seq: Option<i64>,
+    bytes: usize,
+    tick: u64,
+}
+
+impl CacheInner {
+    fn clear(&mut self) {
+        self.by_hash.clear();
+        self.by_message.clear();
+        self.bytes = 0;
+    }
+
+    /// Forget a message's parse, and the parse itself unless another message shares it
+    fn forget(&mut self, message_id: i64) {
+        if let Some(hash) = self.by_message.remove(&message_id) {
+            if !self.by_message.values().any(|h| *h == hash) {
+                if let Some(parsed) = self.by_hash.remove(&hash) {
+                    self.bytes -= parsed.bytes;
+                }
+            }
+        }
+    }
+
+    fn get(&mut self, hash: &str) -> Option<(Arc<Vec<Block>>, Option<String>)> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.by_hash.get_mut(hash).map(|parsed| {
+            parsed.last_used = tick;
+            (parsed.blocks.clone(), parsed.degraded.clone())
+        })
+    }
+
+    fn insert(&mut self, hash: String, blocks: Arc<Vec<Block>>, degraded: Option<String>) {
+        let bytes = 64 + blocks
+            .iter()
+            .map(|b| 64 + b.source.len() + b.text.len() + code_html(b).map_or(0, str::len))
+            .sum::<usize>();
+        self.tick += 1;
+        if let Some(old) = self.by_hash.insert(
+            hash,
+            Parsed {
+                blocks,
+                degraded,
+                bytes,
+                last_used: self.tick,
+            },
+        ) {
+            self.bytes -= old.bytes;
+        }
+        self.bytes += bytes;
+        while self.bytes > MAX_CACHE_BYTES {
+            let Some(victim) = self.by_hash.iter().min_by_key(|(_, p)| p.last_used).map(|(h, _)| h.clone()) else {
+                break;
+            };
+            if let Some(parsed) = self.by_hash.remove(&victim) {
+                self.bytes -= parsed.bytes;
+            }
+        }
+    }
+}
+
+fn code_html(block: &Block) -> Option<&str> {
+    match &block.kind {
+        BlockKind::Code { html, .. } => html.as_deref(),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+pub struct BlockCache(Mutex<CacheInner>);
+
+impl BlockCache {
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheInner> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Drop a message's parsed blocks now, without waiting for the change feed
+    pub(crate) fn forget(&self, message_id: i64) {
+        self.lock().forget(message_id);
+    }
+}
+
+/// Forget the parse of every message the change feed reports as written
+fn sync(conn: &Connection, cache: &BlockCache) -> Result<(), String> {
+    let since = cache.lock().feed_seq;
+    let (oldest, newest): (Option<i64>, Option<i64>) = conn
+        .query_row("SELECT MIN(seq), MAX(seq) FROM message_changes", [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?;
+    let Some(since) = since else {
+        cache.lock().feed_seq = Some(newest.unwrap_or(0));
+        return Ok(());
+    };
+    if newest.unwrap_or(0) <= since {
+        return Ok(());
+    }
+    let mut stmt = conn
+        .prepare_cached("SELECT DISTINCT message_id FROM message_changes WHERE seq > ?1")
+        .map_err(|e| e.to_string())?;
+    let changed = stmt
+        .query_map([since], |row| row.get::<_, i64>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut inner = cache.lock();
+    if oldest.is_some_and(|oldest| oldest > since + 1) {
+        // Feed rows were pruned before we saw them
+        inner.clear();
+    }
+    for message_id in changed {
+        inner.forget(message_id);
+    }
+    inner.feed_seq = newest;
+    Ok(())
+}
+
+fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn raw(content: &str) -> Vec<Block> {
+    vec![Block {
+        index: 0,
+        kind: BlockKind::Raw,
+        source: content.to_string(),
+        text: content.to_string(),
+    }]
+}
+
+/// A top-level block being collected
+struct Open {
+    kind: BlockKind,
+    range: Range<usize>,
+    text: String,
+    code: String,
+    /// Images seen, and whether anything else was
+    images: Vec<(String, String)>,
+    other_content: bool,
+}
+
+/// Split `content` into top-level blocks, or say why it had to stay whole
+pub(crate) fn parse_blocks(content: &str) -> Result<Vec<Block>, String> {
+    if content.len() > MAX_INPUT_BYTES {
+        return Err(format!("message is over {} bytes", MAX_INPUT_BYTES));
+    }
+    let started = Instant::now();
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut blocks = Vec::new();
+    let mut open: Option<Open> = None;
+    let mut depth = 0usize;
+    let mut in_image = false;
+
+    for (n, (event, range)) in Parser::new_ext(content, options).into_offset_iter().enumerate() {
+        if n % 1024 == 0 && started.elapsed() > MAX_PARSE_TIME {
+            return Err("parsing took too long".to_string());
+        }
+        match event {
+            Event::Start(tag) => {
+                depth += 1;
+                if depth > MAX_DEPTH {
+                    return Err(format!("nesting is deeper than {}", MAX_DEPTH));
+                }
+                if depth == 1 {
+                    let kind = match &tag {
+                        Tag::Paragraph => BlockKind::Paragraph,
+                        Tag::Heading { level, .. } => BlockKind::Heading { level: *level as u8 },
+                        Tag::CodeBlock(CodeBlockKind::Fenced(language)) => BlockKind::Code {
+                            language: language.split_whitespace().next().map(str::to_string),
+                            html: None,
+                        },
+                        Tag::CodeBlock(CodeBlockKind::Indented) => BlockKind::Code {
+                            language: None,
+                            html: None,
+                        },
+                        Tag::Table(_) => BlockKind::Table,
+                        Tag::List(start) => BlockKind::List { ordered: start.is_some() },
+                        Tag::BlockQuote(_) => BlockKind::Quote,
+                        Tag::HtmlBlock => BlockKind::Html,
+                        _ => BlockKind::Raw,
+                    };
+                    open = Some(Open {
+                        kind,
+                        range,
+                        text: String::new(),
+                        code: String::new(),
+                        images: Vec::new(),
+                        other_content: false,
+                    });
+                } else if let (Some(block), Tag::Image { dest_url, .. }) = (open.as_mut(), &tag) {
+                    block.images.push((dest_url.to_string(), String::new()));
+                    in_image = true;
+                }
+            }
+            Event::End(tag) => {
+                depth = depth.saturating_sub(1);
+                if matches!(tag, TagEnd::Image) {
+                    in_image = false;
+                }
+                if let Some(block) = open.as_mut() {
+                    if matches!(
+                        tag,
+                        TagEnd::Paragraph | TagEnd::Item | TagEnd::TableRow | TagEnd::TableHead | TagEnd::Heading(_)
+                    ) && !block.text.ends_with('\n')
+                    {
+                        block.text.push('\n');
+                    }
+                }
+                if depth == 0 {
+                    if let Some(block) = open.take() {
+                        blocks.push(finish(content, block, blocks.len()));
+                    }
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(block) = open.as_mut() {
+                    if matches!(block.kind, BlockKind::Code { .. }) {
+                        block.code.push_str(&text);
+                    }
+                    if in_image {
+                        if let Some(image) = block.images.last_mut() {
+                            image.1.push_str(&text);
+                        }
+                    } else if !text.trim().is_empty() {
+                        block.other_content = true;
+                    }
+                    block.text.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some(block) = open.as_mut() {
+                    block.text.push('\n');
+                }
+            }
+            Event::Rule if depth == 0 => blocks.push(Block {
+                index: blocks.len(),
+                kind: BlockKind::Rule,
+                source: content[range].to_string(),
+                text: String::new(),
+            }),
+            Event::Html(html) if depth == 0 => blocks.push(Block {
+                index: blocks.len(),
+                kind: BlockKind::Html,
+                source: content[range].to_string(),
+                text: html.to_string(),
+            }),
+            _ => {
+                if let Some(block) = open.as_mut() {
+                    block.other_content = true;
+                }
+            }
+        }
+        if blocks.len() > MAX_BLOCKS {
+            return Err(format!("message has more than {} blocks", MAX_BLOCKS));
+        }
+    }
+    if let Some(block) = open.take() {
+        blocks.push(finish(content, block, blocks.len()));
+    }
+    Ok(blocks)
+}
+
+fn finish(content: &str, block: Open, index: usize) -> Block {
+    let kind = match block.kind {
+        BlockKind::Code { language, .. } => {
+            let html = language.as_deref().and_then(|lang| highlight::highlight(&block.code, lang));
+            BlockKind::Code { language, html }
+        }
+        // A paragraph that is only an image is an image block
+        BlockKind::Paragraph if block.images.len() == 1 && !block.other_content => {
+            let (src, alt) = block.images.into_iter().next().unwrap_or_default();
+            BlockKind::Image { src, alt }
+        }
+        kind => kind,
+    };
+    Block {
+        index,
+        kind,
+        source: content.get(block.range).unwrap_or_default().to_string(),
+        text: block.text.trim_end().to_string(),
+    }
+}
+
+fn load(conn: &Connection, cache: &BlockCache, message_id: i64) -> Result<MessageBlocks, String> {
+    sync(conn, cache)?;
+    let known = cache.lock().by_message.get(&message_id).cloned();
+    if let Some(hash) = known {
+        if let Some((blocks, degraded)) = cache.lock().get(&hash) {
+            return Ok(MessageBlocks {
+                message_id,
+                content_hash: hash,
+                blocks,
+                degraded,
+            });
+        }
+    }
+
+    let content: Option<Option<String>> = conn
+        .query_row("SELECT content FROM messages WHERE id = ?1", [message_id], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some(content) = content else {
+        return Err(format!("Message {} not found", message_id));
+    };
+    let content = content.unwrap_or_default();
+    let hash = content_hash(&content);
+    let cached = cache.lock().get(&hash);
+    let (blocks, degraded) = match cached {
+        Some(found) => found,
+        None => {
+            let (blocks, degraded) = match parse_blocks(&content) {
+                Ok(blocks) => (blocks, None),
+                Err(reason) => {
+                    eprintln!("[MessageBlocks] Message {} left whole: {}", message_id, reason);
+                    (raw(&content), Some(reason))
+                }
+            };
+            let blocks = Arc::new(blocks);
+            cache.lock().insert(hash.clone(), blocks.clone(), degraded.clone());
+            (blocks, degraded)
+        }
+    };
+    cache.lock().by_message.insert(message_id, hash.clone());
+    Ok(MessageBlocks {
+        message_id,
+        content_hash: hash,
+        blocks,
+        degraded,
+    })
+}
+
+/// A message's markdown as top-level blocks, for virtualized rendering
+#[tauri::command]
+pub async fn get_message_blocks(app: AppHandle, message_id: i64) -> Result<MessageBlocks, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| load(conn, &handle.state::<BlockCache>(), message_id)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(content: &str) -> Vec<BlockKind> {
+        parse_blocks(content).unwrap().into_iter().map(|b| b.kind).collect()
+    }
+
+    #[test]
+    fn splits_top_level_blocks() {
+        let content = "# Title\n\nSome *text* here.\n\n```rust\nfn main() {}\n```\n\n- a\n- b\n\n| x | y |\n|---|---|\n| 1 | 2 |\n\n![chart](chart.png)\n\n---\n";
+        let blocks = parse_blocks(content).unwrap();
+        let kinds: Vec<&str> = blocks
+            .iter()
+            .map(|b| match &b.kind {
+                BlockKind::Heading { .. } => "heading",
+                BlockKind::Paragraph => "paragraph",
+                BlockKind::Code { .. } => "code",
+                BlockKind::List { .. } => "list",
+                BlockKind::Table => "table",
+                BlockKind::Image { .. } => "image",
+                BlockKind::Rule => "rule",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(kinds, vec!["heading", "paragraph", "code", "list", "table", "image", "rule"]);
+        assert_eq!(blocks[1].text, "Some text here.");
+        assert_eq!(blocks[1].source.trim_end(), "Some *text* here.");
+        assert_eq!(blocks[3].text, "a\nb");
+        assert!(matches!(
+            &blocks[2].kind,
+            BlockKind::Code { language: Some(lang), html: Some(html) } if lang == "rust" && html.contains("<span")
+        ));
+        assert!(matches!(&blocks[5].kind, BlockKind::Image { src, alt } if src == "chart.png" && alt == "chart"));
+        assert!(blocks.iter().enumerate().all(|(i, b)| b.index == i));
+    }
+
+    #[test]
+    fn unterminated_fences_run_to_the_end() {
+        assert_eq!(
+            kinds("Intro\n\n```python\nprint(1)\n\nmore\n"),
+            vec![
+                BlockKind::Paragraph,
+                BlockKind::Code {
+                    language: Some("python".into()),
+                    html: highlight::highlight("print(1)\n\nmore\n", "python"),
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn deep_nesting_degrades_to_one_raw_block() {
+        let nested: String = (0..200).map(|depth| format!("{}- item\n", "  ".repeat(depth))).collect();
+        let quoted = format!("{}deep", "> ".repeat(5_000));
+        for content in [nested, quoted] {
+            let started = Instant::now();
+            assert!(parse_blocks(&content).is_err());
+            assert!(started.elapsed() < Duration::from_secs(2));
+        }
+    }
+
+    #[test]
+    fn cache_follows_the_change_feed() {
+        let conn = crate::migrations::test_db();
+        conn.execute("INSERT INTO messages (id, task_id, type, content) VALUES (1, 't1', 'text', 'one\n\ntwo')", [])
+            .unwrap();
+        let cache = BlockCache::default();
+        let first = load(&conn, &cache, 1).unwrap();
+        assert_eq!(first.blocks.len(), 2);
+        assert!(Arc::ptr_eq(&first.blocks, &load(&conn, &cache, 1).unwrap().blocks));
+
+        conn.execute("UPDATE messages SET content = 'redacted' WHERE id = 1", []).unwrap();
+        let edited = load(&conn, &cache, 1).unwrap();
+        assert_ne!(edited.content_hash, first.content_hash);
+        assert_eq!(edited.blocks.len(), 1);
+        assert_eq!(edited.blocks[0].text, "redacted");
+    }
+}
@@ -0,0 +1,255 @@
+// Full-text search over message content, tool names and tool output.
+//
+// `messages_fts` is an external-content FTS5 index over `messages`, kept in sync by
+// triggers (migration 39). Each search term is quoted, so operators typed by the
+// user are literal and all terms must match; hits are ranked by bm25 and carry an
+// FTS5 `snippet()` with the matched terms between `HIT_START` and `HIT_END`.
+// Messages of trashed tasks are left out. Where the SQLite build has no FTS5, the
+// search falls back to a case-insensitive LIKE scan with a snippet built here,
+// and a warning is logged once.
+
+use crate::db;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::AppHandle;
+
+const MAX_LIMIT: u32 = 200;
+/// Around matched terms in `MessageHit::snippet`; control characters, so they can't
+/// occur in the text and the frontend can split on them without escaping
+const HIT_START: &str = "\u{2}";
+const HIT_END: &str = "\u{3}";
+const ELLIPSIS: &str = "…";
+/// Tokens in an FTS5 snippet
+const SNIPPET_TOKENS: u32 = 16;
+/// Characters either side of the match in a fallback snippet
+const FALLBACK_CONTEXT: usize = 40;
+
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MessageHit {
+    pub task_id: String,
+    pub message_id: i64,
+    pub snippet: String,
+}
+
+/// `query` as an FTS5 expression of quoted terms, `None` when it has none
+fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+fn search_fts(conn: &Connection, expression: &str, limit: u32) -> rusqlite::Result<Vec<MessageHit>> {
+    // snippet() column -1 picks whichever column matched best
+    let mut stmt = conn.prepare_cached(
+        "SELECT m.task_id, m.id, snippet(messages_fts, -1, ?2, ?3, ?4, ?5)
+         FROM messages_fts
+         JOIN messages m ON m.id = messages_fts.rowid
+         JOIN tasks t ON t.id = m.task_id
+         WHERE messages_fts MATCH ?1 AND t.trashed_at IS NULL
+         ORDER BY messages_fts.rank
+         LIMIT ?6",
+    )?;
+    let rows = stmt.query_map(
+        params![expression, HIT_START, HIT_END, ELLIPSIS, SNIPPET_TOKENS, limit],
+        |row| {
+            Ok(MessageHit {
+                task_id: row.get(0)?,
+                message_id: row.get(1)?,
+                snippet: row.get(2)?,
+            })
+        },
+    )?;
+    rows.collect()
+}
+
+/// The text around the first case-insensitive occurrence of `term` in `text`,
+/// with the occurrence marked
+fn fallback_snippet(text: &str, term: &str) -> Option<String> {
+    let lower = text.to_lowercase();
+    let term = term.to_lowercase();
+    // Lowercasing can change byte lengths, so work in characters
+    let chars: Vec<char> = text.chars().collect();
+    let lower_chars: Vec<char> = lower.chars().collect();
+    if chars.len() != lower_chars.len() {
+        return Some(text.chars().take(FALLBACK_CONTEXT * 2).collect());
+    }
+    let term_chars: Vec<char> = term.chars().collect();
+    let start = lower_chars.windows(term_chars.len()).position(|w| w == term_chars.as_slice())?;
+    let end = start + term_chars.len();
+    let from = start.saturating_sub(FALLBACK_CONTEXT);
+    let to = (end + FALLBACK_CONTEXT).min(chars.len());
+    let slice = |a: usize, b: usize| chars[a..b].iter().collect::<String>();
+    Some(format!(
+        "{}{}{}{}{}{}{}",
+        if from > 0 { ELLIPSIS } else { "" },
+        slice(from, start),
+        HIT_START,
+        slice(start, end),
+        HIT_END,
+        slice(end, to),
+        if to < chars.len() { ELLIPSIS } else { "" },
+    ))
+}
+
+fn search_like(conn: &Connection, query: &str, limit: u32) -> rusqlite::Result<Vec<MessageHit>> {
+    let terms: Vec<&str> = query.split_whitespace().collect();
+    let Some(first) = terms.first() else {
+        return Ok(Vec::new());
+    };
+    let mut sql = String::from(
+        "SELECT m.task_id, m.id, COALESCE(m.content, ''), COALESCE(m.tool_name, ''), COALESCE(m.tool_output, '')
+         FROM messages m JOIN tasks t ON t.id = m.task_id
+         WHERE t.trashed_at IS NULL",
+    );
+    let mut patterns = Vec::new();
+    for term in &terms {
+        let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        patterns.push(format!("%{}%", escaped));
+        let n = patterns.len();
+        sql.push_str(&format!(
+            " AND (m.content LIKE ?{n} ESCAPE '\\' OR m.tool_name LIKE ?{n} ESCAPE '\\' \
+             OR m.tool_output LIKE ?{n} ESCAPE '\\')"
+        ));
+    }
+    sql.push_str(&format!(" ORDER BY m.id DESC LIMIT {}", limit));
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(&patterns), |row| {
+        let fields: [String; 3] = [row.get(2)?, row.get(3)?, row.get(4)?];
+        let snippet = fields
+            .iter()
+            .find_map(|field| fallback_snippet(field, first))
+            .unwrap_or_default();
+        Ok(MessageHit {
+            task_id: row.get(0)?,
+            message_id: row.get(1)?,
+            snippet,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Whether `error` means FTS5 itself is unavailable, rather than a bad query
+fn fts_unavailable(error: &rusqlite::Error) -> bool {
+    let message = error.to_string();
+    message.contains("no such module: fts5") || message.contains("no such table: messages_fts")
+}
+
+/// Merge the index's segments so postings of deleted or rewritten rows are dropped
+/// from the file, not just marked deleted. Nothing to do without FTS5.
+pub(crate) fn purge_deleted(conn: &Connection) -> Result<(), String> {
+    match conn.execute("INSERT INTO messages_fts(messages_fts) VALUES ('optimize')", []) {
+        Ok(_) => Ok(()),
+        Err(e) if fts_unavailable(&e) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub(crate) fn search(conn: &Connection, query: &str, limit: u32) -> Result<Vec<MessageHit>, String> {
+    let Some(expression) = match_expression(query) else {
+        return Ok(Vec::new());
+    };
+    let limit = limit.clamp(1, MAX_LIMIT);
+    match search_fts(conn, &expression, limit) {
+        Ok(hits) => Ok(hits),
+        Err(e) if fts_unavailable(&e) => {
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                eprintln!("[Search] Full-text search is unavailable ({}); falling back to LIKE", e);
+            }
+            search_like(conn, query, limit).map_err(|e| e.to_string())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Messages matching every term of `query`, best first, at most `limit` (up to
+/// `MAX_LIMIT`). Snippets mark the matched terms with U+0002 and U+0003.
+#[tauri::command]
+pub async fn search_messages(app: AppHandle, query: String, limit: u32) -> Result<Vec<MessageHit>, String> {
+    db::with_conn(&app, move |conn| search(conn, &query, limit)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch(
+            "INSERT INTO tasks (id, prompt) VALUES ('t1', 'one'), ('t2', 'two');
+             INSERT INTO messages (id, task_id, type, content) VALUES
+                 (1, 't1', 'text', 'The deploy failed with a timeout'),
+                 (2, 't1', 'text', 'Nothing to see here'),
+                 (3, 't2', 'text', 'Another deploy, this one worked');
+             INSERT INTO messages (id, task_id, type, tool_name, tool_output) VALUES
+                 (4, 't2', 'tool_use', 'Bash', 'error: connection timeout after 30s');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn ids(hits: &[MessageHit]) -> Vec<i64> {
+        let mut ids: Vec<i64> = hits.iter().map(|hit| hit.message_id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn finds_content_and_tool_output_with_marked_snippets() {
+        let conn = setup();
+        let hits = search(&conn, "timeout", 10).unwrap();
+        assert_eq!(ids(&hits), vec![1, 4]);
+        let hit = hits.iter().find(|hit| hit.message_id == 4).unwrap();
+        assert_eq!(hit.task_id, "t2");
+        assert!(hit.snippet.contains("\u{2}timeout\u{3}"), "{}", hit.snippet);
+
+        // Every term must match
+        assert_eq!(ids(&search(&conn, "deploy timeout", 10).unwrap()), vec![1]);
+        assert_eq!(ids(&search(&conn, "bash", 10).unwrap()), vec![4]);
+    }
+
+    #[test]
+    fn the_index_follows_updates_deletes_and_the_trash() {
+        let conn = setup();
+        conn.execute("UPDATE messages SET content = 'All clear' WHERE id = 1", []).unwrap();
+        conn.execute("DELETE FROM messages WHERE id = 4", []).unwrap();
+        assert!(search(&conn, "timeout", 10).unwrap().is_empty());
+        assert_eq!(ids(&search(&conn, "clear", 10).unwrap()), vec![1]);
+
+        conn.execute("UPDATE tasks SET trashed_at = 1 WHERE id = 't1'", []).unwrap();
+        assert!(search(&conn, "clear", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn operators_are_literal_and_blank_queries_match_nothing() {
+        let conn = setup();
+        assert!(search(&conn, "   ", 10).unwrap().is_empty());
+        assert!(search(&conn, "deploy OR \"nothing", 10).unwrap().is_empty());
+        assert_eq!(search(&conn, "deploy", 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn the_like_fallback_matches_the_same_messages() {
+        let conn = setup();
+        let hits = search_like(&conn, "TIMEOUT", 10).unwrap();
+        assert_eq!(ids(&hits), vec![1, 4]);
+        let hit = hits.iter().find(|hit| hit.message_id == 1).unwrap();
+        assert_eq!(hit.snippet, "The deploy failed with a \u{2}timeout\u{3}");
+        assert!(search_like(&conn, "100%", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn fallback_snippets_are_trimmed_around_the_match() {
+        let text = format!("{}needle{}", "a".repeat(100), "b".repeat(100));
+        let snippet = fallback_snippet(&text, "NEEDLE").unwrap();
+        assert_eq!(
+            snippet,
+            format!("…{}\u{2}needle\u{3}{}…", "a".repeat(FALLBACK_CONTEXT), "b".repeat(FALLBACK_CONTEXT))
+        );
+        assert_eq!(fallback_snippet("haystack", "needle"), None);
+    }
+}
@@ -0,0 +1,340 @@
+// The canonical values of `messages.type`.
+//
+// The column is free text, and older databases hold the same type spelled several
+// ways (`tool-use`, `toolUse`, `ToolUse`), which every query filtering by type
+// misses. `MessageType` is the one list of canonical values: Rust reads the column
+// through it and writes only its strings, and `list_messages` returns it, so the
+// frontend gets canonical types without its own mapping. Reading normalizes a known
+// misspelling to its canonical type. A value that matches no type stays as it is
+// (`Other`), and the schema-drift monitor reports it.
+//
+// `normalize_message_types` repairs stored rows: a dry run reports what each known
+// misspelling would become, and applying rewrites the rows in one transaction and
+// logs the run in `message_type_repairs`. It also sets `message_types_normalized`,
+// which arms the triggers from migration 41 that refuse inserts and updates using a
+// known misspelling. Unknown values are still accepted.
+
+use crate::{db, settings};
+use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use tauri::AppHandle;
+
+/// Set once stored types were normalized; arms the guard triggers
+pub(crate) const NORMALIZED_KEY: &str = "message_types_normalized";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MessageType {
+    Text,
+    ToolUse,
+    ToolResult,
+    Result,
+    Error,
+    User,
+    Plan,
+    PermissionRequest,
+    /// A value that matches no canonical type, kept as stored
+    Other(String),
+}
+
+pub(crate) const CANONICAL: &[MessageType] = &[
+    MessageType::Text,
+    MessageType::ToolUse,
+    MessageType::ToolResult,
+    MessageType::Result,
+    MessageType::Error,
+    MessageType::User,
+    MessageType::Plan,
+    MessageType::PermissionRequest,
+];
+
+/// `value` lowercased without separators, so spellings of one type compare equal
+fn squash(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+impl MessageType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            MessageType::Text => "text",
+            MessageType::ToolUse => "tool_use",
+            MessageType::ToolResult => "tool_result",
+            MessageType::Result => "result",
+            MessageType::Error => "error",
+            MessageType::User => "user",
+            MessageType::Plan => "plan",
+            MessageType::PermissionRequest => "permission_request",
+            MessageType::Other(value) => value,
+        }
+    }
+
+    /// The type `value` names exactly, without normalizing
+    pub fn canonical(value: &str) -> Option<Self> {
+        CANONICAL.iter().find(|t| t.as_str() == value).cloned()
+    }
+
+    /// The type `value` names, allowing for case and separators, or `Other`
+    pub fn parse(value: &str) -> Self {
+        if let Some(known) = Self::canonical(value) {
+            return known;
+        }
+        let squashed = squash(value);
+        CANONICAL
+            .iter()
+            .find(|t| !squashed.is_empty() && squash(t.as_str()) == squashed)
+            .cloned()
+            .unwrap_or_else(|| MessageType::Other(value.to_string()))
+    }
+
+    pub fn is_canonical(&self) -> bool {
+        !matches!(self, MessageType::Other(_))
+    }
+}
+
+/// The misspellings of each canonical type seen in user databases: kebab, camel,
+/// Pascal and upper case, spaced and unseparated. Migration 41's guard triggers
+/// list exactly these.
+pub(crate) fn legacy_variants() -> Vec<String> {
+    let mut variants = Vec::new();
+    for canonical in CANONICAL {
+        let value = canonical.as_str();
+        let words: Vec<&str> = value.split('_').collect();
+        let capitalized: Vec<String> = words
+            .iter()
+            .map(|w| w[..1].to_ascii_uppercase() + &w[1..])
+            .collect();
+        let candidates = [
+            words.join("-"),
+            format!("{}{}", words[0], capitalized[1..].concat()),
+            capitalized.concat(),
+            value.to_ascii_uppercase(),
+            words.join(" "),
+            words.concat(),
+        ];
+        for candidate in candidates {
+            if candidate != value && !variants.contains(&candidate) {
+                variants.push(candidate);
+            }
+        }
+    }
+    variants
+}
+
+impl Serialize for MessageType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|value| MessageType::parse(&value))
+    }
+}
+
+impl FromSql for MessageType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str().map(MessageType::parse)
+    }
+}
+
+impl ToSql for MessageType {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TypeChange {
+    pub from: String,
+    pub to: MessageType,
+    pub rows: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnknownType {
+    pub value: String,
+    pub rows: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageTypeRepair {
+    pub dry_run: bool,
+    /// Misspelled values and what they become, most rows first
+    pub changes: Vec<TypeChange>,
+    /// Values matching no type, left as they are
+    pub unknown: Vec<UnknownType>,
+    pub rows_changed: i64,
+    /// The guard triggers refuse known misspellings
+    pub guarded: bool,
+}
+
+/// What normalizing would change, or, unless `dry_run`, did change
+pub(crate) fn normalize(conn: &mut Connection, dry_run: bool) -> Result<MessageTypeRepair, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let counts: BTreeMap<String, i64> = {
+        let mut stmt = tx
+            .prepare("SELECT type, COUNT(*) FROM messages GROUP BY type")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    let mut changes = Vec::new();
+    let mut unknown = Vec::new();
+    for (value, rows) in counts {
+        match MessageType::parse(&value) {
+            MessageType::Other(value) => unknown.push(UnknownType { value, rows }),
+            to if to.as_str() != value => changes.push(TypeChange { from: value, to, rows }),
+            _ => {}
+        }
+    }
+    changes.sort_by(|a, b| b.rows.cmp(&a.rows).then_with(|| a.from.cmp(&b.from)));
+    unknown.sort_by(|a, b| b.rows.cmp(&a.rows).then_with(|| a.value.cmp(&b.value)));
+    let rows_changed = changes.iter().map(|c| c.rows).sum();
+
+    let mut guarded = settings::get_or(&tx, NORMALIZED_KEY, false)?;
+    if !dry_run {
+        for change in &changes {
+            tx.execute("UPDATE messages SET type = ?1 WHERE type = ?2", params![change.to, change.from])
+                .map_err(|e| e.to_string())?;
+        }
+        tx.execute(
+            "INSERT INTO message_type_repairs (changes, rows_changed) VALUES (?1, ?2)",
+            params![serde_json::to_string(&changes).map_err(|e| e.to_string())?, rows_changed],
+        )
+        .map_err(|e| e.to_string())?;
+        settings::set(&tx, NORMALIZED_KEY, &true)?;
+        guarded = true;
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+    Ok(MessageTypeRepair {
+        dry_run,
+        changes,
+        unknown,
+        rows_changed,
+        guarded,
+    })
+}
+
+/// Rewrite misspelled message types to their canonical values and from then on
+/// refuse them; with `dry_run`, only report what would change
+#[tauri::command]
+pub async fn normalize_message_types(app: AppHandle, dry_run: bool) -> Result<MessageTypeRepair, String> {
+    let repair = db::with_conn(&app, move |conn| normalize(conn, dry_run)).await?;
+    if !dry_run {
+        println!(
+            "[MessageTypes] Normalized {} message(s) across {} misspelled type(s)",
+            repair.rows_changed,
+            repair.changes.len()
+        );
+    }
+    Ok(repair)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute("INSERT INTO tasks (id, prompt) VALUES ('t1', 'p')", []).unwrap();
+        conn
+    }
+
+    fn insert(conn: &Connection, kind: &str) -> rusqlite::Result<usize> {
+        conn.execute("INSERT INTO messages (task_id, type) VALUES ('t1', ?1)", [kind])
+    }
+
+    #[test]
+    fn every_canonical_value_round_trips() {
+        let conn = db();
+        for canonical in CANONICAL {
+            assert_eq!(MessageType::parse(canonical.as_str()), *canonical);
+            assert_eq!(MessageType::canonical(canonical.as_str()).as_ref(), Some(canonical));
+            let json = serde_json::to_string(canonical).unwrap();
+            assert_eq!(json, format!("\"{}\"", canonical.as_str()));
+            assert_eq!(serde_json::from_str::<MessageType>(&json).unwrap(), *canonical);
+            let stored: MessageType = conn.query_row("SELECT ?1", [canonical], |row| row.get(0)).unwrap();
+            assert_eq!(stored, *canonical);
+        }
+    }
+
+    #[test]
+    fn every_known_variant_normalizes_to_its_type() {
+        for (variant, expected) in [
+            ("tool-use", MessageType::ToolUse),
+            ("toolUse", MessageType::ToolUse),
+            ("ToolUse", MessageType::ToolUse),
+            ("TOOL_USE", MessageType::ToolUse),
+            ("tool use", MessageType::ToolUse),
+            ("tooluse", MessageType::ToolUse),
+            ("tool-result", MessageType::ToolResult),
+            ("permissionRequest", MessageType::PermissionRequest),
+            ("Text", MessageType::Text),
+        ] {
+            assert_eq!(MessageType::parse(variant), expected, "{}", variant);
+        }
+        for variant in legacy_variants() {
+            let parsed = MessageType::parse(&variant);
+            assert!(parsed.is_canonical() && parsed.as_str() != variant, "{}", variant);
+        }
+        assert_eq!(MessageType::parse("thinking"), MessageType::Other("thinking".to_string()));
+        assert_eq!(MessageType::parse("--"), MessageType::Other("--".to_string()));
+    }
+
+    #[test]
+    fn a_dry_run_reports_and_applying_rewrites() {
+        let mut conn = db();
+        for kind in ["tool_use", "tool-use", "toolUse", "toolUse", "Text", "thinking"] {
+            insert(&conn, kind).unwrap();
+        }
+
+        let preview = normalize(&mut conn, true).unwrap();
+        assert!(!preview.guarded);
+        assert_eq!(preview.rows_changed, 4);
+        assert_eq!(
+            preview.changes[0],
+            TypeChange { from: "toolUse".to_string(), to: MessageType::ToolUse, rows: 2 }
+        );
+        assert_eq!(preview.unknown, vec![UnknownType { value: "thinking".to_string(), rows: 1 }]);
+        let untouched: i64 =
+            conn.query_row("SELECT COUNT(*) FROM messages WHERE type = 'tool_use'", [], |row| row.get(0)).unwrap();
+        assert_eq!(untouched, 1);
+
+        let applied = normalize(&mut conn, false).unwrap();
+        assert!(applied.guarded);
+        assert_eq!(applied.changes, preview.changes);
+        let types: Vec<String> = conn
+            .prepare("SELECT DISTINCT type FROM messages ORDER BY type")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(types, vec!["text", "thinking", "tool_use"]);
+        assert!(normalize(&mut conn, true).unwrap().changes.is_empty());
+    }
+
+    #[test]
+    fn misspellings_are_refused_only_after_normalizing() {
+        let mut conn = db();
+        insert(&conn, "tool-use").unwrap();
+        normalize(&mut conn, false).unwrap();
+        for variant in legacy_variants() {
+            assert!(insert(&conn, &variant).is_err(), "{} was accepted", variant);
+        }
+        assert!(conn.execute("UPDATE messages SET type = 'toolResult'", []).is_err());
+        for canonical in CANONICAL {
+            insert(&conn, canonical.as_str()).unwrap();
+        }
+        // Unknown types are still accepted, for the drift monitor to report
+        insert(&conn, "thinking").unwrap();
+    }
+}
@@ -0,0 +1,565 @@
+// Paged message reads backed by a bounded prefetch cache.
+//
+// Pages are keyed by task, cursor and page size. Every write to `messages`, whether
+// it comes from Rust or from the webview through the sql plugin, is recorded by
+// triggers in `message_changes`. That table is the change feed: before the cache
+// answers, it applies new feed rows and drops only the pages whose id range covers a
+// changed message, so an edit or redaction is never served stale.
+
+use crate::db;
+use crate::message_types::MessageType;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+const PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+const PAGES_PER_TASK: usize = 10;
+const MAX_CACHE_BYTES: usize = 32 * 1024 * 1024;
+/// Feed rows kept behind the newest one; older rows are pruned
+const FEED_RETENTION: i64 = 10_000;
+
+const MESSAGE_COLUMNS: &str = "id, task_id, type, content, tool_name, tool_input, tool_output, \
+     tool_use_id, subtype, error_message, attachments, redacted, created_at";
+
+/// A `messages` row, shaped like the rows the frontend selects itself
+#[derive(Debug, Clone, Serialize)]
+pub struct Message {
+    pub id: i64,
+    pub task_id: String,
+    #[serde(rename = "type")]
+    pub message_type: MessageType,
+    pub content: Option<String>,
+    pub tool_name: Option<String>,
+    pub tool_input: Option<String>,
+    pub tool_output: Option<String>,
+    pub tool_use_id: Option<String>,
+    pub subtype: Option<String>,
+    pub error_message: Option<String>,
+    pub attachments: Option<String>,
+    pub redacted: i64,
+    pub created_at: String,
+}
+
+impl Message {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Message {
+            id: row.get(0)?,
+            task_id: row.get(1)?,
+            message_type: row.get(2)?,
+            content: row.get(3)?,
+            tool_name: row.get(4)?,
+            tool_input: row.get(5)?,
+            tool_output: row.get(6)?,
+            tool_use_id: row.get(7)?,
+            subtype: row.get(8)?,
+            error_message: row.get(9)?,
+            attachments: row.get(10)?,
+            redacted: row.get(11)?,
+            created_at: row.get(12)?,
+        })
+    }
+
+    fn approx_bytes(&self) -> usize {
+        let text = [
+            &self.content,
+            &self.tool_name,
+            &self.tool_input,
+            &self.tool_output,
+            &self.tool_use_id,
+            &self.subtype,
+            &self.error_message,
+            &self.attachments,
+        ];
+        128 + self.task_id.len()
+            + self.message_type.as_str().len()
+            + self.created_at.len()
+            + text.iter().map(|f| f.as_ref().map_or(0, |s| s.len())).sum::<usize>()
+    }
+}
+
+/// Messages strictly after or before a message id, in ascending id order either way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(tag = "direction", content = "id", rename_all = "snake_case")]
+pub enum PageCursor {
+    After(i64),
+    Before(i64),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessagePage {
+    pub messages: Vec<Message>,
+    /// More messages exist beyond this page in the cursor's direction
+    pub has_more: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub bytes: usize,
+    pub max_bytes: usize,
+    pub pages: usize,
+    pub tasks: usize,
+    pub invalidated: u64,
+    pub evicted: u64,
+    pub feed_seq: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PageKey {
+    task_id: String,
+    cursor: PageCursor,
+    limit: u32,
+}
+
+struct CachedPage {
+    page: MessagePage,
+    // Inclusive id range whose changes can alter this page
+    lo: i64,
+    hi: i64,
+    bytes: usize,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    pages: HashMap<PageKey, CachedPage>,
+    bytes: usize,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+    invalidated: u64,
+    evicted: u64,
+    /// Last change-feed sequence applied; `None` until the first sync
+    feed_seq: Option<i64>,
+}
+
+impl CacheInner {
+    fn remove(&mut self, key: &PageKey) {
+        if let Some(page) = self.pages.remove(key) {
+            self.bytes -= page.bytes;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.invalidated += self.pages.len() as u64;
+        self.pages.clear();
+        self.bytes = 0;
+    }
+
+    fn invalidate(&mut self, task_id: &str, message_id: i64) {
+        let stale: Vec<PageKey> = self
+            .pages
+            .iter()
+            .filter(|(key, page)| key.task_id == task_id && page.lo <= message_id && message_id <= page.hi)
+            .map(|(key, _)| key.clone())
+            .collect();
+        self.invalidated += stale.len() as u64;
+        for key in &stale {
+            self.remove(key);
+        }
+    }
+
+    fn lru_key(&self, task_id: Option<&str>) -> Option<PageKey> {
+        self.pages
+            .iter()
+            .filter(|(key, _)| task_id.is_none() || task_id == Some(key.task_id.as_str()))
+            .min_by_key(|(_, page)| page.last_used)
+            .map(|(key, _)| key.clone())
+    }
+
+    fn get(&mut self, key: &PageKey) -> Option<MessagePage> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.pages.get_mut(key) {
+            Some(page) => {
+                page.last_used = tick;
+                self.hits += 1;
+                Some(page.page.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, key: PageKey, page: MessagePage, lo: i64, hi: i64, read_at_seq: i64) {
+        // Feed rows past `read_at_seq` were applied before this page arrived and may
+        // already have invalidated it; caching it now could resurrect stale data
+        if !matches!(self.feed_seq, Some(seq) if seq <= read_at_seq) {
+            return;
+        }
+        self.tick += 1;
+        let bytes = 256 + page.messages.iter().map(Message::approx_bytes).sum::<usize>();
+        let task_id = key.task_id.clone();
+        self.remove(&key);
+        self.bytes += bytes;
+        self.pages.insert(
+            key,
+            CachedPage {
+                page,
+                lo,
+                hi,
+                bytes,
+                last_used: self.tick,
+            },
+        );
+
+        while self.pages.keys().filter(|k| k.task_id == task_id).count() > PAGES_PER_TASK {
+            let Some(victim) = self.lru_key(Some(&task_id)) else { break };
+            self.remove(&victim);
+            self.evicted += 1;
+        }
+        while self.bytes > MAX_CACHE_BYTES {
+            let Some(victim) = self.lru_key(None) else { break };
+            self.remove(&victim);
+            self.evicted += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MessageCache(Mutex<CacheInner>);
+
+impl MessageCache {
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheInner> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Drop the pages holding a message now, without waiting for the change feed
+    pub(crate) fn forget(&self, task_id: &str, message_id: i64) {
+        self.lock().invalidate(task_id, message_id);
+    }
+}
+
+fn max_feed_seq(conn: &Connection) -> Result<i64, String> {
+    conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM message_changes", [], |row| row.get(0))
+        .map_err(|e| e.to_string())
+}
+
+/// Apply change-feed rows the cache has not seen yet
+fn sync(conn: &Connection, cache: &MessageCache) -> Result<(), String> {
+    let since = cache.lock().feed_seq;
+    let Some(since) = since else {
+        let seq = max_feed_seq(conn)?;
+        let mut inner = cache.lock();
+        inner.clear();
+        inner.feed_seq = Some(seq);
+        return Ok(());
+    };
+
+    let oldest: Option<i64> = conn
+        .query_row("SELECT MIN(seq) FROM message_changes", [], |row| row.get(0))
+        .optional()
+        .map_err(|e| e.to_string())?
+        .flatten();
+    let mut stmt = conn
+        .prepare_cached("SELECT seq, task_id, message_id FROM message_changes WHERE seq > ?1 ORDER BY seq")
+        .map_err(|e| e.to_string())?;
+    let changes = stmt
+        .query_map([since], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?)))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut inner = cache.lock();
+    let applied = inner.feed_seq.unwrap_or(since);
+    if oldest.is_some_and(|oldest| oldest > applied + 1) {
+        // Rows we never saw were pruned; nothing cached can be trusted
+        inner.clear();
+    }
+    let mut seen = HashSet::new();
+    for (seq, task_id, message_id) in &changes {
+        if *seq > applied && seen.insert((task_id.as_str(), *message_id)) {
+            inner.invalidate(task_id, *message_id);
+        }
+    }
+    if let Some((last, _, _)) = changes.last() {
+        inner.feed_seq = Some(applied.max(*last));
+    }
+    drop(inner);
+
+    if let (Some(oldest), Some((last, _, _))) = (oldest, changes.last()) {
+        if last - oldest > 2 * FEED_RETENTION {
+            conn.execute("DELETE FROM message_changes WHERE seq <= ?1", [last - FEED_RETENTION])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Read one page from the database along with the id range it depends on
+fn query_page(conn: &Connection, task_id: &str, cursor: PageCursor, limit: u32) -> Result<(MessagePage, i64, i64), String> {
+    let (sql, anchor) = match cursor {
+        PageCursor::After(id) => (
+            format!("SELECT {MESSAGE_COLUMNS} FROM messages WHERE task_id = ?1 AND id > ?2 ORDER BY id ASC LIMIT ?3"),
+            id,
+        ),
+        PageCursor::Before(id) => (
+            format!("SELECT {MESSAGE_COLUMNS} FROM messages WHERE task_id = ?1 AND id < ?2 ORDER BY id DESC LIMIT ?3"),
+            id,
+        ),
+    };
+    let mut stmt = conn.prepare_cached(&sql).map_err(|e| e.to_string())?;
+    let mut messages = stmt
+        .query_map(params![task_id, anchor, limit + 1], Message::from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    // The extra row only tells us whether more exist; it still bounds the range,
+    // since deleting it would change `has_more`
+    let probe = (messages.len() > limit as usize).then(|| messages.pop()).flatten();
+    let has_more = probe.is_some();
+    let (lo, hi) = match cursor {
+        PageCursor::After(id) => (id.saturating_add(1), probe.map_or(i64::MAX, |m| m.id)),
+        PageCursor::Before(id) => {
+            messages.reverse();
+            (probe.map_or(i64::MIN, |m| m.id), id.saturating_sub(1))
+        }
+    };
+    Ok((MessagePage { messages, has_more }, lo, hi))
+}
+
+/// Serve a page from the cache or the database; the flag is true on a cache miss
+fn read_page(
+    conn: &mut Connection,
+    cache: &MessageCache,
+    task_id: &str,
+    cursor: PageCursor,
+    limit: u32,
+) -> Result<(MessagePage, bool), String> {
+    sync(conn, cache)?;
+    let key = PageKey {
+        task_id: task_id.to_string(),
+        cursor,
+        limit,
+    };
+    if let Some(page) = cache.lock().get(&key) {
+        return Ok((page, false));
+    }
+
+    // Read the page and the feed position from one snapshot
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let seq = max_feed_seq(&tx)?;
+    let (page, lo, hi) = query_page(&tx, task_id, cursor, limit)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    cache.lock().insert(key, page.clone(), lo, hi, seq);
+    Ok((page, true))
+}
+
+/// Page through a task's messages. Without a cursor this starts from the oldest message.
+#[tauri::command]
+pub async fn list_messages(
+    app: AppHandle,
+    task_id: String,
+    cursor: Option<PageCursor>,
+    limit: Option<u32>,
+) -> Result<MessagePage, String> {
+    let limit = limit.unwrap_or(PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let cursor = cursor.unwrap_or(PageCursor::After(0));
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let cache = handle.state::<MessageCache>();
+        read_page(conn, &cache, &task_id, cursor, limit).map(|(page, _)| page)
+    })
+    .await
+}
+
+/// Load the pages around `around_cursor` that scrolling would request next, using
+/// the same cursors `list_messages` is called with. Returns how many pages were read
+/// from the database rather than already cached.
+#[tauri::command]
+pub async fn prime_message_cache(
+    app: AppHandle,
+    task_id: String,
+    around_cursor: Option<i64>,
+    pages_before: u32,
+    pages_after: u32,
+) -> Result<u32, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let cache = handle.state::<MessageCache>();
+        let mut loaded = 0;
+
+        let mut cursor = PageCursor::After(around_cursor.unwrap_or(0));
+        for _ in 0..pages_after.min(PAGES_PER_TASK as u32) {
+            let (page, missed) = read_page(conn, &cache, &task_id, cursor, PAGE_SIZE)?;
+            loaded += missed as u32;
+            match page.messages.last() {
+                Some(last) if page.has_more => cursor = PageCursor::After(last.id),
+                _ => break,
+            }
+        }
+
+        if let Some(around) = around_cursor {
+            let mut cursor = PageCursor::Before(around);
+            for _ in 0..pages_before.min(PAGES_PER_TASK as u32) {
+                let (page, missed) = read_page(conn, &cache, &task_id, cursor, PAGE_SIZE)?;
+                loaded += missed as u32;
+                match page.messages.first() {
+                    Some(first) if page.has_more => cursor = PageCursor::Before(first.id),
+                    _ => break,
+                }
+            }
+        }
+        Ok(loaded)
+    })
+    .await
+}
+
+/// Counters for the debug overlay
+#[tauri::command]
+pub fn get_message_cache_stats(cache: tauri::State<'_, MessageCache>) -> MessageCacheStats {
+    let inner = cache.lock();
+    let lookups = inner.hits + inner.misses;
+    MessageCacheStats {
+        hits: inner.hits,
+        misses: inner.misses,
+        hit_rate: if lookups == 0 {
+            0.0
+        } else {
+            inner.hits as f64 / lookups as f64
+        },
+        bytes: inner.bytes,
+        max_bytes: MAX_CACHE_BYTES,
+        pages: inner.pages.len(),
+        tasks: inner.pages.keys().map(|k| &k.task_id).collect::<HashSet<_>>().len(),
+        invalidated: inner.invalidated,
+        evicted: inner.evicted,
+        feed_seq: inner.feed_seq,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seed(conn: &Connection, task_id: &str, ids: std::ops::RangeInclusive<i64>) {
+        for id in ids {
+            conn.execute(
+                "INSERT INTO messages (id, task_id, type, content) VALUES (?1, ?2, 'text', ?3)",
+                params![id, task_id, format!("message {}", id)],
+            )
+            .unwrap();
+        }
+    }
+
+    fn page(conn: &mut Connection, cache: &MessageCache, task_id: &str, cursor: PageCursor) -> (Vec<String>, bool) {
+        let (page, missed) = read_page(conn, cache, task_id, cursor, 3).unwrap();
+        let contents = page.messages.into_iter().map(|m| m.content.unwrap_or_default()).collect();
+        (contents, missed)
+    }
+
+    fn key(task_id: &str) -> PageKey {
+        PageKey {
+            task_id: task_id.to_string(),
+            cursor: PageCursor::After(0),
+            limit: PAGE_SIZE,
+        }
+    }
+
+    fn large_page(task_id: &str, bytes: usize) -> MessagePage {
+        let message = Message {
+            id: 1,
+            task_id: task_id.to_string(),
+            message_type: MessageType::Text,
+            content: Some("x".repeat(bytes)),
+            tool_name: None,
+            tool_input: None,
+            tool_output: None,
+            tool_use_id: None,
+            subtype: None,
+            error_message: None,
+            attachments: None,
+            redacted: 0,
+            created_at: "2026-01-01 00:00:00".to_string(),
+        };
+        MessagePage {
+            messages: vec![message],
+            has_more: false,
+        }
+    }
+
+    #[test]
+    fn an_edit_drops_only_the_pages_covering_it() {
+        let mut conn = crate::migrations::test_db();
+        seed(&conn, "t1", 1..=6);
+        let cache = MessageCache::default();
+        let first = PageCursor::After(0);
+        let second = PageCursor::After(3);
+
+        assert!(page(&mut conn, &cache, "t1", first).1);
+        assert!(page(&mut conn, &cache, "t1", second).1);
+        let (contents, missed) = page(&mut conn, &cache, "t1", first);
+        assert_eq!(contents, ["message 1", "message 2", "message 3"]);
+        assert!(!missed);
+
+        conn.execute("UPDATE messages SET content = 'edited' WHERE id = 2", []).unwrap();
+        let (contents, missed) = page(&mut conn, &cache, "t1", first);
+        assert_eq!(contents, ["message 1", "edited", "message 3"]);
+        assert!(missed);
+        assert!(!page(&mut conn, &cache, "t1", second).1, "a page not covering the edit stays cached");
+
+        conn.execute("UPDATE messages SET redacted = 1, content = '[REDACTED:secret]' WHERE id = 5", []).unwrap();
+        let (contents, missed) = page(&mut conn, &cache, "t1", second);
+        assert!(missed);
+        assert_eq!(contents[1], "[REDACTED:secret]");
+        assert_eq!(cache.lock().invalidated, 2);
+    }
+
+    #[test]
+    fn a_feed_pruned_past_the_cursor_resets_the_cache() {
+        let mut conn = crate::migrations::test_db();
+        seed(&conn, "t1", 1..=3);
+        seed(&conn, "t2", 4..=6);
+        seed(&conn, "t3", 7..=7);
+        let cache = MessageCache::default();
+        let start = PageCursor::After(0);
+        page(&mut conn, &cache, "t1", start);
+        page(&mut conn, &cache, "t2", start);
+        let seen = cache.lock().feed_seq.unwrap();
+
+        // The change to t2 is pruned before the cache reads the feed again
+        conn.execute("UPDATE messages SET content = 'edited' WHERE id = 5", []).unwrap();
+        conn.execute("UPDATE messages SET content = 'other' WHERE id = 7", []).unwrap();
+        conn.execute("DELETE FROM message_changes WHERE seq <= ?1", [seen + 1]).unwrap();
+
+        let (contents, missed) = page(&mut conn, &cache, "t2", start);
+        assert!(missed);
+        assert_eq!(contents[1], "edited");
+        assert!(page(&mut conn, &cache, "t1", start).1, "every page was dropped, not just t2's");
+        assert_eq!(cache.lock().feed_seq, Some(seen + 2));
+    }
+
+    #[test]
+    fn the_byte_ceiling_evicts_the_least_recently_used_page_of_any_task() {
+        let mut inner = CacheInner {
+            feed_seq: Some(0),
+            ..Default::default()
+        };
+        let size = MAX_CACHE_BYTES / 4;
+        for task in ["t0", "t1", "t2"] {
+            inner.insert(key(task), large_page(task, size), 1, 1, 0);
+        }
+        assert_eq!((inner.pages.len(), inner.evicted), (3, 0));
+
+        // t0 is used again, so t1 and then t2 are the ones to go
+        assert!(inner.get(&key("t0")).is_some());
+        inner.insert(key("t3"), large_page("t3", size), 1, 1, 0);
+        inner.insert(key("t4"), large_page("t4", size), 1, 1, 0);
+
+        let mut cached: Vec<&str> = inner.pages.keys().map(|k| k.task_id.as_str()).collect();
+        cached.sort();
+        assert_eq!(cached, vec!["t0", "t3", "t4"]);
+        assert_eq!(inner.evicted, 2);
+        assert!(inner.bytes <= MAX_CACHE_BYTES);
+        assert_eq!(inner.bytes, inner.pages.values().map(|p| p.bytes).sum::<usize>());
+    }
+}
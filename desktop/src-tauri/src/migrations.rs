@@ -0,0 +1,1290 @@
+// Schema migrations.
+//
+// Every migration is an `AppMigration` with a version, SQL and an optional Rust data
+// step that runs after the SQL in the same transaction. They are applied from Rust
+// before the app is built, and recorded in `_sqlx_migrations` exactly as
+// tauri-plugin-sql (sqlx) records them, so the plugin sees them as applied and only
+// acts as a fallback.
+//
+// Two guards keep shipped migrations from drifting:
+// - `checksum` is an FNV-1a hash of the SQL checked at compile time, so editing a
+//   migration's SQL without deliberately updating its checksum fails the build.
+// - At startup the SHA-384 recorded for each applied version is compared with the
+//   binary's. On a mismatch no further migrations run until the user acknowledges
+//   it, and `migration://checksum-mismatch` is emitted.
+//
+// The SQL text below is byte-for-byte what shipped (sqlx checksums it), so never
+// re-indent or reformat it.
+
+use crate::{db, events};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sha2::{Digest, Sha384};
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_sql::{Migration, MigrationKind};
+
+/// Rust step of a migration, run after its SQL inside the same transaction
+pub(crate) type DataMigration = fn(&Transaction) -> Result<(), String>;
+
+pub(crate) struct AppMigration {
+    pub version: i64,
+    pub description: &'static str,
+    /// FNV-1a 64 of `sql`, verified at compile time
+    pub checksum: u64,
+    pub sql: &'static str,
+    pub data: Option<DataMigration>,
+}
+
+pub(crate) const MIGRATIONS: &[AppMigration] = &[
+    AppMigration {
+        version: 1,
+        description: "create_tasks_and_messages_tables",
+        checksum: 0x4866ca88914fac24,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS tasks (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    prompt TEXT NOT NULL,
+                    status TEXT NOT NULL DEFAULT 'running',
+                    cost REAL,
+                    duration INTEGER,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+                CREATE TABLE IF NOT EXISTS messages (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    type TEXT NOT NULL,
+                    content TEXT,
+                    tool_name TEXT,
+                    tool_input TEXT,
+                    subtype TEXT,
+                    error_message TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_messages_task_id ON messages(task_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 2,
+        description: "add_tool_result_fields",
+        checksum: 0xc2f5771133376221,
+        sql: r#"
+                ALTER TABLE messages ADD COLUMN tool_output TEXT;
+                ALTER TABLE messages ADD COLUMN tool_use_id TEXT;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 3,
+        description: "create_files_table",
+        checksum: 0xe088891b4c2269a3,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS files (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    type TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    preview TEXT,
+                    thumbnail TEXT,
+                    is_favorite INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_files_task_id ON files(task_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 4,
+        description: "create_settings_table",
+        checksum: 0x795e6328f9267f5d,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS settings (
+                    key TEXT PRIMARY KEY NOT NULL,
+                    value TEXT NOT NULL,
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 5,
+        description: "create_sessions_table_and_update_tasks",
+        checksum: 0x5c5e2b8c4baa3eb6,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS sessions (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    prompt TEXT NOT NULL,
+                    task_count INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+                ALTER TABLE tasks ADD COLUMN session_id TEXT;
+                ALTER TABLE tasks ADD COLUMN task_index INTEGER DEFAULT 1;
+
+                CREATE INDEX IF NOT EXISTS idx_tasks_session_id ON tasks(session_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 6,
+        description: "add_attachments_to_messages",
+        checksum: 0x92aa3ef16506b780,
+        sql: r#"
+                ALTER TABLE messages ADD COLUMN attachments TEXT;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 7,
+        description: "add_favorite_to_tasks",
+        checksum: 0x1cf2420f91e09e82,
+        sql: r#"
+                ALTER TABLE tasks ADD COLUMN favorite INTEGER DEFAULT 0;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 8,
+        description: "add_message_redaction",
+        checksum: 0x6498e741fa85834f,
+        sql: r#"
+                ALTER TABLE messages ADD COLUMN redacted INTEGER NOT NULL DEFAULT 0;
+
+                CREATE TABLE IF NOT EXISTS redaction_log (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    message_id INTEGER NOT NULL,
+                    task_id TEXT NOT NULL,
+                    category TEXT NOT NULL,
+                    fields TEXT NOT NULL,
+                    span_count INTEGER NOT NULL,
+                    actor TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_redaction_log_task_id ON redaction_log(task_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 9,
+        description: "create_tool_invocations",
+        checksum: 0xb77ad5c05bab727a,
+        sql: r#"
+                CREATE INDEX IF NOT EXISTS idx_messages_tool_use_id ON messages(tool_use_id);
+
+                CREATE TABLE IF NOT EXISTS tool_invocations (
+                    tool_use_id TEXT PRIMARY KEY NOT NULL,
+                    task_id TEXT NOT NULL,
+                    tool_name TEXT,
+                    call_message_id INTEGER,
+                    result_message_id INTEGER,
+                    called_at TEXT,
+                    completed_at TEXT,
+                    latency_ms INTEGER,
+                    is_error INTEGER NOT NULL DEFAULT 0
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_tool_invocations_called_at ON tool_invocations(called_at);
+
+                CREATE TRIGGER IF NOT EXISTS tool_invocations_on_call
+                AFTER INSERT ON messages
+                WHEN NEW.type = 'tool_use' AND NEW.tool_use_id IS NOT NULL
+                BEGIN
+                    INSERT OR IGNORE INTO tool_invocations (tool_use_id, task_id) VALUES (NEW.tool_use_id, NEW.task_id);
+                    UPDATE tool_invocations
+                    SET tool_name = NEW.tool_name,
+                        call_message_id = NEW.id,
+                        called_at = NEW.created_at,
+                        latency_ms = CASE WHEN completed_at IS NOT NULL
+                            THEN CAST((julianday(completed_at) - julianday(NEW.created_at)) * 86400000 AS INTEGER) END
+                    WHERE tool_use_id = NEW.tool_use_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS tool_invocations_on_result
+                AFTER INSERT ON messages
+                WHEN NEW.type = 'tool_result' AND NEW.tool_use_id IS NOT NULL
+                BEGIN
+                    INSERT OR IGNORE INTO tool_invocations (tool_use_id, task_id) VALUES (NEW.tool_use_id, NEW.task_id);
+                    UPDATE tool_invocations
+                    SET result_message_id = NEW.id,
+                        completed_at = NEW.created_at,
+                        is_error = (NEW.error_message IS NOT NULL
+                            OR NEW.subtype = 'error'
+                            OR NEW.tool_output LIKE 'Error:%'
+                            OR NEW.tool_output LIKE '%<tool_use_error>%'),
+                        latency_ms = CASE WHEN called_at IS NOT NULL
+                            THEN CAST((julianday(NEW.created_at) - julianday(called_at)) * 86400000 AS INTEGER) END
+                    WHERE tool_use_id = NEW.tool_use_id;
+                END;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 10,
+        description: "add_file_sha256",
+        checksum: 0x4eeaa481f2b44973,
+        sql: r#"
+                ALTER TABLE files ADD COLUMN sha256 TEXT;
+                CREATE INDEX IF NOT EXISTS idx_files_task_sha256 ON files(task_id, sha256);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 11,
+        description: "create_message_changes",
+        checksum: 0x29cc121bb0cc7f08,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS message_changes (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    message_id INTEGER NOT NULL
+                );
+
+                CREATE TRIGGER IF NOT EXISTS message_changes_on_insert AFTER INSERT ON messages
+                BEGIN
+                    INSERT INTO message_changes (task_id, message_id) VALUES (NEW.task_id, NEW.id);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS message_changes_on_update AFTER UPDATE ON messages
+                BEGIN
+                    INSERT INTO message_changes (task_id, message_id) VALUES (NEW.task_id, NEW.id);
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS message_changes_on_delete AFTER DELETE ON messages
+                BEGIN
+                    INSERT INTO message_changes (task_id, message_id) VALUES (OLD.task_id, OLD.id);
+                END;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 12,
+        description: "add_db_size_guard",
+        checksum: 0x2afae72d2d3cf1e3,
+        sql: r#"
+                CREATE TRIGGER IF NOT EXISTS db_size_guard_tasks BEFORE INSERT ON tasks
+                WHEN (SELECT value FROM settings WHERE key = 'db_size_blocked') = 'true'
+                BEGIN
+                    SELECT RAISE(ABORT, 'Database size limit reached: delete old tasks or raise max_db_size_mb to continue');
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS db_size_guard_messages BEFORE INSERT ON messages
+                WHEN (SELECT value FROM settings WHERE key = 'db_size_blocked') = 'true'
+                BEGIN
+                    SELECT RAISE(ABORT, 'Database size limit reached: delete old tasks or raise max_db_size_mb to continue');
+                END;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 13,
+        description: "create_integrity_journal",
+        checksum: 0xc7a283642b3475c0,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS integrity_snapshots (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    taken_at TEXT NOT NULL,
+                    table_name TEXT NOT NULL,
+                    row_count INTEGER NOT NULL,
+                    max_rowid INTEGER NOT NULL,
+                    content_bytes INTEGER NOT NULL,
+                    deleted_total INTEGER NOT NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_integrity_snapshots_taken_at ON integrity_snapshots(taken_at);
+
+                CREATE TABLE IF NOT EXISTS deletion_totals (
+                    table_name TEXT PRIMARY KEY NOT NULL,
+                    deleted INTEGER NOT NULL DEFAULT 0
+                );
+
+                INSERT OR IGNORE INTO deletion_totals (table_name) VALUES ('sessions'), ('tasks'), ('messages'), ('files');
+
+                CREATE TRIGGER IF NOT EXISTS deletion_totals_sessions AFTER DELETE ON sessions
+                BEGIN
+                    UPDATE deletion_totals SET deleted = deleted + 1 WHERE table_name = 'sessions';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS deletion_totals_tasks AFTER DELETE ON tasks
+                BEGIN
+                    UPDATE deletion_totals SET deleted = deleted + 1 WHERE table_name = 'tasks';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS deletion_totals_messages AFTER DELETE ON messages
+                BEGIN
+                    UPDATE deletion_totals SET deleted = deleted + 1 WHERE table_name = 'messages';
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS deletion_totals_files AFTER DELETE ON files
+                BEGIN
+                    UPDATE deletion_totals SET deleted = deleted + 1 WHERE table_name = 'files';
+                END;
+
+                CREATE TABLE IF NOT EXISTS deletion_accounting (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    recorded_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    table_name TEXT NOT NULL,
+                    subsystem TEXT NOT NULL,
+                    rows_removed INTEGER NOT NULL
+                );
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 14,
+        description: "create_captures_table",
+        checksum: 0x38039e1b3527a781,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS captures (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    url TEXT NOT NULL,
+                    title TEXT,
+                    selection_text TEXT,
+                    screenshot_path TEXT,
+                    source TEXT,
+                    status TEXT NOT NULL DEFAULT 'pending',
+                    task_id TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_captures_status ON captures(status);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 15,
+        description: "create_tags_tables",
+        checksum: 0x694eb05d9ff5a02e,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS tags (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                    color TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+                CREATE TABLE IF NOT EXISTS task_tags (
+                    task_id TEXT NOT NULL,
+                    tag_id INTEGER NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    PRIMARY KEY (task_id, tag_id),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+                    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_task_tags_tag_id ON task_tags(tag_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 16,
+        description: "add_task_fork_links",
+        checksum: 0x6e4c42e644d9e63c,
+        sql: r#"
+                ALTER TABLE tasks ADD COLUMN forked_from_task TEXT;
+                ALTER TABLE tasks ADD COLUMN forked_from_message INTEGER;
+
+                CREATE INDEX IF NOT EXISTS idx_tasks_forked_from_task ON tasks(forked_from_task);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 17,
+        description: "add_routing_rules",
+        checksum: 0x6a637bd7b09ae9de,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS routing_rules (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL,
+                    priority INTEGER NOT NULL DEFAULT 0,
+                    enabled INTEGER NOT NULL DEFAULT 1,
+                    mode TEXT NOT NULL DEFAULT 'first_match',
+                    conditions TEXT NOT NULL,
+                    actions TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+                CREATE INDEX IF NOT EXISTS idx_routing_rules_priority ON routing_rules(priority, id);
+
+                ALTER TABLE tasks ADD COLUMN routing TEXT;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 18,
+        description: "add_file_provenance",
+        checksum: 0x7ddf6edbbf58d5e0,
+        sql: r#"
+                ALTER TABLE files ADD COLUMN source_file_id INTEGER;
+                ALTER TABLE files ADD COLUMN source_entry TEXT;
+
+                CREATE INDEX IF NOT EXISTS idx_files_source_file_id ON files(source_file_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 19,
+        description: "add_cost_ledger",
+        checksum: 0x6134ea1bd773ac9d,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS cost_ledger (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    delta REAL NOT NULL,
+                    recorded_at INTEGER NOT NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_cost_ledger_recorded_at ON cost_ledger(recorded_at);
+
+                INSERT INTO cost_ledger (task_id, delta, recorded_at)
+                    SELECT id, cost, CAST(strftime('%s', updated_at) AS INTEGER) * 1000
+                    FROM tasks WHERE cost IS NOT NULL AND cost != 0;
+
+                CREATE TRIGGER IF NOT EXISTS cost_ledger_task_insert AFTER INSERT ON tasks
+                WHEN COALESCE(NEW.cost, 0) != 0
+                BEGIN
+                    INSERT INTO cost_ledger (task_id, delta, recorded_at)
+                    VALUES (NEW.id, NEW.cost, CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER));
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS cost_ledger_task_update AFTER UPDATE OF cost ON tasks
+                WHEN COALESCE(NEW.cost, 0) != COALESCE(OLD.cost, 0)
+                BEGIN
+                    INSERT INTO cost_ledger (task_id, delta, recorded_at)
+                    VALUES (NEW.id, COALESCE(NEW.cost, 0) - COALESCE(OLD.cost, 0), CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER));
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS quota_guard_tasks BEFORE INSERT ON tasks
+                WHEN (SELECT value FROM settings WHERE key = 'quota_blocked') = 'true'
+                BEGIN
+                    SELECT RAISE(ABORT, 'Spending quota reached: raise the quota or wait for the next period');
+                END;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 20,
+        description: "create_file_repair_reports",
+        checksum: 0xa5bbc275807cffcf,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS file_repair_reports (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    items TEXT NOT NULL,
+                    truncated INTEGER NOT NULL DEFAULT 0,
+                    applied_at TEXT,
+                    results TEXT
+                );
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 21,
+        description: "add_file_scan_verdicts",
+        checksum: 0x762d09060184c811,
+        sql: r#"
+                ALTER TABLE files ADD COLUMN scan_verdict TEXT;
+                ALTER TABLE files ADD COLUMN scan_engine TEXT;
+                ALTER TABLE files ADD COLUMN scan_detail TEXT;
+                ALTER TABLE files ADD COLUMN scanned_at TEXT;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 22,
+        description: "add_task_retry_context",
+        checksum: 0xbf5696ecb4bdbbfc,
+        sql: r#"
+                ALTER TABLE tasks ADD COLUMN retry_context TEXT;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 23,
+        description: "add_prompt_overflow_attachments",
+        checksum: 0xbaa81fbc6011e382,
+        sql: r#"
+                CREATE TABLE attachments (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    content TEXT NOT NULL,
+                    size INTEGER NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+                CREATE INDEX idx_attachments_task_id ON attachments(task_id);
+                ALTER TABLE tasks ADD COLUMN prompt_overflow_attachment_id INTEGER;
+
+                CREATE VIRTUAL TABLE attachments_fts USING fts5(content, content='attachments', content_rowid='id');
+                CREATE TRIGGER attachments_fts_insert AFTER INSERT ON attachments
+                BEGIN
+                    INSERT INTO attachments_fts(rowid, content) VALUES (NEW.id, NEW.content);
+                END;
+                CREATE TRIGGER attachments_fts_delete AFTER DELETE ON attachments
+                BEGIN
+                    INSERT INTO attachments_fts(attachments_fts, rowid, content) VALUES ('delete', OLD.id, OLD.content);
+                END;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 24,
+        description: "add_session_working_sets",
+        checksum: 0x98662412d73a7965,
+        sql: r#"
+                -- Session files are not owned by a task, so task_id becomes optional
+                CREATE TABLE attachments_new (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT,
+                    kind TEXT NOT NULL,
+                    name TEXT,
+                    mime_type TEXT,
+                    encoding TEXT NOT NULL DEFAULT 'utf8',
+                    content TEXT NOT NULL,
+                    size INTEGER NOT NULL,
+                    tokens INTEGER,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+                INSERT INTO attachments_new (id, task_id, kind, content, size, created_at)
+                    SELECT id, task_id, kind, content, size, created_at FROM attachments;
+                DROP TABLE attachments;
+                ALTER TABLE attachments_new RENAME TO attachments;
+                CREATE INDEX idx_attachments_task_id ON attachments(task_id);
+
+                -- Only text is indexed; base64 content would just fill the index with noise
+                CREATE TRIGGER attachments_fts_insert AFTER INSERT ON attachments
+                WHEN NEW.encoding = 'utf8'
+                BEGIN
+                    INSERT INTO attachments_fts(rowid, content) VALUES (NEW.id, NEW.content);
+                END;
+                CREATE TRIGGER attachments_fts_delete AFTER DELETE ON attachments
+                WHEN OLD.encoding = 'utf8'
+                BEGIN
+                    INSERT INTO attachments_fts(attachments_fts, rowid, content) VALUES ('delete', OLD.id, OLD.content);
+                END;
+
+                CREATE TABLE session_files (
+                    session_id TEXT NOT NULL,
+                    attachment_id INTEGER NOT NULL,
+                    position INTEGER NOT NULL,
+                    always_include INTEGER NOT NULL DEFAULT 1,
+                    added_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    PRIMARY KEY (session_id, attachment_id),
+                    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE,
+                    FOREIGN KEY (attachment_id) REFERENCES attachments(id) ON DELETE CASCADE
+                );
+                CREATE INDEX idx_session_files_attachment_id ON session_files(attachment_id);
+
+                CREATE TABLE task_attachments (
+                    task_id TEXT NOT NULL,
+                    attachment_id INTEGER NOT NULL,
+                    position INTEGER NOT NULL,
+                    source TEXT NOT NULL,
+                    PRIMARY KEY (task_id, attachment_id),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE,
+                    FOREIGN KEY (attachment_id) REFERENCES attachments(id) ON DELETE CASCADE
+                );
+                CREATE INDEX idx_task_attachments_attachment_id ON task_attachments(attachment_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 25,
+        description: "add_media_backfill",
+        checksum: 0xfcced47a0bbf73cb,
+        sql: r#"
+                ALTER TABLE files ADD COLUMN thumbnail_hash TEXT;
+                ALTER TABLE files ADD COLUMN media_format INTEGER NOT NULL DEFAULT 0;
+                CREATE TABLE IF NOT EXISTS backfill_errors (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    backfill TEXT NOT NULL,
+                    row_id INTEGER NOT NULL,
+                    error TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+                CREATE INDEX IF NOT EXISTS idx_backfill_errors_backfill ON backfill_errors(backfill, row_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 26,
+        description: "add_review_packets",
+        checksum: 0x604dabb3f6833a98,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS review_packets (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    scope TEXT NOT NULL,
+                    scope_id TEXT NOT NULL,
+                    refs TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+                CREATE TABLE IF NOT EXISTS message_notes (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    message_id INTEGER NOT NULL,
+                    packet_id TEXT NOT NULL,
+                    reference TEXT NOT NULL,
+                    author TEXT NOT NULL DEFAULT '',
+                    body TEXT NOT NULL,
+                    commented_at TEXT,
+                    imported_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    UNIQUE (packet_id, reference, author, body),
+                    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+                );
+                CREATE INDEX IF NOT EXISTS idx_message_notes_message_id ON message_notes(message_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 27,
+        description: "add_timeline_epochs",
+        checksum: 0x71890ba80a08922d,
+        sql: r#"
+                ALTER TABLE tasks ADD COLUMN created_epoch INTEGER;
+                ALTER TABLE sessions ADD COLUMN created_epoch INTEGER;
+                ALTER TABLE files ADD COLUMN created_epoch INTEGER;
+                UPDATE tasks SET created_epoch = CAST(strftime('%s', created_at) AS INTEGER);
+                UPDATE sessions SET created_epoch = CAST(strftime('%s', created_at) AS INTEGER);
+                UPDATE files SET created_epoch = CAST(strftime('%s', created_at) AS INTEGER);
+
+                -- Rows are inserted by the frontend as well, so the epoch is filled in here
+                CREATE TRIGGER IF NOT EXISTS tasks_created_epoch AFTER INSERT ON tasks
+                WHEN NEW.created_epoch IS NULL
+                BEGIN
+                    UPDATE tasks SET created_epoch = CAST(strftime('%s', NEW.created_at) AS INTEGER) WHERE id = NEW.id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS sessions_created_epoch AFTER INSERT ON sessions
+                WHEN NEW.created_epoch IS NULL
+                BEGIN
+                    UPDATE sessions SET created_epoch = CAST(strftime('%s', NEW.created_at) AS INTEGER) WHERE id = NEW.id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS files_created_epoch AFTER INSERT ON files
+                WHEN NEW.created_epoch IS NULL
+                BEGIN
+                    UPDATE files SET created_epoch = CAST(strftime('%s', NEW.created_at) AS INTEGER) WHERE id = NEW.id;
+                END;
+
+                -- Covering indexes for the timeline's day buckets
+                CREATE INDEX IF NOT EXISTS idx_tasks_timeline ON tasks(created_epoch, status, cost);
+                CREATE INDEX IF NOT EXISTS idx_sessions_timeline ON sessions(created_epoch);
+                CREATE INDEX IF NOT EXISTS idx_files_timeline ON files(created_epoch, type, source_file_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 28,
+        description: "add_legacy_sql_usage",
+        checksum: 0x86ef0cd5dda26ab8,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS legacy_sql_usage (
+                    fingerprint TEXT PRIMARY KEY NOT NULL,
+                    statement TEXT NOT NULL,
+                    table_name TEXT,
+                    count INTEGER NOT NULL DEFAULT 0,
+                    denied INTEGER NOT NULL DEFAULT 0,
+                    first_seen TEXT NOT NULL DEFAULT (datetime('now')),
+                    last_seen TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 29,
+        description: "add_row_versions",
+        checksum: 0x7434edbd420e2058,
+        sql: r#"
+                ALTER TABLE tasks ADD COLUMN row_version INTEGER NOT NULL DEFAULT 0;
+                ALTER TABLE files ADD COLUMN row_version INTEGER NOT NULL DEFAULT 0;
+
+                -- Writers that don't bump the version themselves (the webview's raw SQL) get it
+                -- bumped here; the flag fast path bumps it in its own UPDATE to read it back
+                CREATE TRIGGER IF NOT EXISTS tasks_row_version AFTER UPDATE ON tasks
+                WHEN NEW.row_version = OLD.row_version
+                BEGIN
+                    UPDATE tasks SET row_version = OLD.row_version + 1 WHERE id = NEW.id;
+                END;
+                CREATE TRIGGER IF NOT EXISTS files_row_version AFTER UPDATE ON files
+                WHEN NEW.row_version = OLD.row_version
+                BEGIN
+                    UPDATE files SET row_version = OLD.row_version + 1 WHERE id = NEW.id;
+                END;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 30,
+        description: "add_task_trash",
+        checksum: 0x0106dad37e16dd2f,
+        sql: r#"
+                ALTER TABLE tasks ADD COLUMN trashed_at TEXT;
+                -- Session and tags at trash time, JSON; see trash::RestoreMetadata
+                ALTER TABLE tasks ADD COLUMN restore_metadata TEXT;
+                -- Bytes of messages and attachments the task holds, measured when it was trashed
+                ALTER TABLE tasks ADD COLUMN trash_bytes INTEGER;
+                CREATE INDEX IF NOT EXISTS idx_tasks_trashed ON tasks(trashed_at) WHERE trashed_at IS NOT NULL;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 31,
+        description: "add_files_ignored_at",
+        checksum: 0x51e5c77d7c1ceacc,
+        sql: r#"
+                -- Set on favorited files rows whose path became ignored by the workspace rules
+                ALTER TABLE files ADD COLUMN ignored_at TEXT;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 32,
+        description: "add_task_notes",
+        checksum: 0xa2516d70a3251a43,
+        sql: r#"
+                CREATE TABLE task_notes (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL UNIQUE,
+                    content TEXT NOT NULL,
+                    row_version INTEGER NOT NULL DEFAULT 1,
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+                CREATE VIRTUAL TABLE task_notes_fts USING fts5(content, content='task_notes', content_rowid='id');
+                CREATE TRIGGER task_notes_fts_insert AFTER INSERT ON task_notes
+                BEGIN
+                    INSERT INTO task_notes_fts(rowid, content) VALUES (NEW.id, NEW.content);
+                END;
+                CREATE TRIGGER task_notes_fts_delete AFTER DELETE ON task_notes
+                BEGIN
+                    INSERT INTO task_notes_fts(task_notes_fts, rowid, content) VALUES ('delete', OLD.id, OLD.content);
+                END;
+                CREATE TRIGGER task_notes_fts_update AFTER UPDATE OF content ON task_notes
+                BEGIN
+                    INSERT INTO task_notes_fts(task_notes_fts, rowid, content) VALUES ('delete', OLD.id, OLD.content);
+                    INSERT INTO task_notes_fts(rowid, content) VALUES (NEW.id, NEW.content);
+                END;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 33,
+        description: "add_task_workspace_analysis",
+        checksum: 0x5b297e05f7e169de,
+        sql: r#"
+                ALTER TABLE tasks ADD COLUMN workspace_analysis TEXT;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 34,
+        description: "add_context_plans",
+        checksum: 0x22f443f314d78379,
+        sql: r#"
+                ALTER TABLE messages ADD COLUMN include_in_context INTEGER NOT NULL DEFAULT 0;
+                ALTER TABLE tasks ADD COLUMN context_overrides TEXT;
+                ALTER TABLE tasks ADD COLUMN sent_context TEXT;
+                CREATE INDEX idx_messages_include_in_context ON messages(task_id) WHERE include_in_context = 1;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 35,
+        description: "create_focus_sessions",
+        checksum: 0x6aeedab96e27cf18,
+        sql: r#"
+                CREATE TABLE focus_sessions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    started_epoch INTEGER NOT NULL,
+                    last_activity_epoch INTEGER NOT NULL,
+                    ended_epoch INTEGER,
+                    end_reason TEXT
+                );
+                CREATE INDEX idx_focus_sessions_started ON focus_sessions(started_epoch);
+                CREATE TABLE focus_session_tasks (
+                    focus_session_id INTEGER NOT NULL,
+                    task_id TEXT NOT NULL PRIMARY KEY,
+                    FOREIGN KEY (focus_session_id) REFERENCES focus_sessions(id) ON DELETE CASCADE,
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+                CREATE INDEX idx_focus_session_tasks_session ON focus_session_tasks(focus_session_id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 36,
+        description: "create_schema_drift",
+        checksum: 0xeef3eaec5125df3a,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS schema_drift (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    kind TEXT NOT NULL,
+                    value TEXT NOT NULL,
+                    occurrences INTEGER NOT NULL DEFAULT 0,
+                    sample_message_ids TEXT NOT NULL DEFAULT '[]',
+                    first_seen_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    last_seen_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    UNIQUE (kind, value)
+                );
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 37,
+        description: "create_saved_filters",
+        checksum: 0x70c77f94af912b76,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS saved_filters (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                    definition TEXT NOT NULL,
+                    pinned INTEGER NOT NULL DEFAULT 0,
+                    position INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 38,
+        description: "add_task_metric_revisions",
+        checksum: 0xe0c8a8560f8393b3,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS task_metric_revisions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    task_id TEXT NOT NULL,
+                    cost REAL,
+                    duration INTEGER,
+                    reason TEXT,
+                    is_final INTEGER NOT NULL DEFAULT 0,
+                    reported_at INTEGER NOT NULL,
+                    FOREIGN KEY (task_id) REFERENCES tasks(id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_task_metric_revisions_task ON task_metric_revisions(task_id, id);
+
+                ALTER TABLE tasks ADD COLUMN metrics_final INTEGER NOT NULL DEFAULT 0;
+
+                -- Tasks that already finished keep the metrics they have
+                UPDATE tasks SET metrics_final = 1 WHERE status != 'running';
+
+                CREATE INDEX IF NOT EXISTS idx_tasks_metrics_pending ON tasks(status) WHERE metrics_final = 0;
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 39,
+        description: "add_messages_fts",
+        checksum: 0x32cb6d45fb81e235,
+        sql: r#"
+                CREATE VIRTUAL TABLE messages_fts USING fts5(content, tool_name, tool_output, content='messages', content_rowid='id');
+                CREATE TRIGGER messages_fts_insert AFTER INSERT ON messages
+                BEGIN
+                    INSERT INTO messages_fts(rowid, content, tool_name, tool_output) VALUES (NEW.id, NEW.content, NEW.tool_name, NEW.tool_output);
+                END;
+                CREATE TRIGGER messages_fts_delete AFTER DELETE ON messages
+                BEGIN
+                    INSERT INTO messages_fts(messages_fts, rowid, content, tool_name, tool_output) VALUES ('delete', OLD.id, OLD.content, OLD.tool_name, OLD.tool_output);
+                END;
+                CREATE TRIGGER messages_fts_update AFTER UPDATE OF content, tool_name, tool_output ON messages
+                BEGIN
+                    INSERT INTO messages_fts(messages_fts, rowid, content, tool_name, tool_output) VALUES ('delete', OLD.id, OLD.content, OLD.tool_name, OLD.tool_output);
+                    INSERT INTO messages_fts(rowid, content, tool_name, tool_output) VALUES (NEW.id, NEW.content, NEW.tool_name, NEW.tool_output);
+                END;
+
+                -- Index the messages that already exist
+                INSERT INTO messages_fts(messages_fts) VALUES ('rebuild');
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 40,
+        description: "add_export_presets",
+        checksum: 0x7bcbd93777d838b9,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS export_presets (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    project_id TEXT,
+                    name TEXT NOT NULL,
+                    steps TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+                -- Names are unique per project, and among the global presets
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_export_presets_name ON export_presets(COALESCE(project_id, ''), name);
+
+                CREATE TABLE IF NOT EXISTS export_deliveries (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    run_id TEXT NOT NULL,
+                    preset_id INTEGER,
+                    preset_name TEXT NOT NULL,
+                    project_id TEXT,
+                    session_id TEXT NOT NULL,
+                    step INTEGER NOT NULL,
+                    kind TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    bytes INTEGER NOT NULL,
+                    delivered_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    FOREIGN KEY (preset_id) REFERENCES export_presets(id) ON DELETE SET NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_export_deliveries_session ON export_deliveries(session_id, id);
+            "#,
+        data: None,
+    },
+    AppMigration {
+        version: 41,
+        description: "add_message_type_guard",
+        checksum: 0xe5f92e76e85c97f3,
+        sql: r#"
+                CREATE TABLE IF NOT EXISTS message_type_repairs (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    changes TEXT NOT NULL,
+                    rows_changed INTEGER NOT NULL,
+                    applied_at TEXT NOT NULL DEFAULT (datetime('now'))
+                );
+
+                -- Once normalize_message_types has run, known misspellings of a type are refused;
+                -- keep the list in sync with message_types::legacy_variants
+                CREATE TRIGGER IF NOT EXISTS messages_type_guard_insert
+                BEFORE INSERT ON messages
+                WHEN NEW.type IN ('Text', 'TEXT', 'tool-use', 'toolUse', 'ToolUse', 'TOOL_USE', 'tool use', 'tooluse', 'tool-result', 'toolResult', 'ToolResult', 'TOOL_RESULT', 'tool result', 'toolresult', 'Result', 'RESULT', 'Error', 'ERROR', 'User', 'USER', 'Plan', 'PLAN', 'permission-request', 'permissionRequest', 'PermissionRequest', 'PERMISSION_REQUEST', 'permission request', 'permissionrequest')
+                 AND EXISTS (SELECT 1 FROM settings WHERE key = 'message_types_normalized' AND value = 'true')
+                BEGIN
+                    SELECT RAISE(ABORT, 'Non-canonical message type; normalize_message_types maps it to its canonical value');
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS messages_type_guard_update
+                BEFORE UPDATE OF type ON messages
+                WHEN NEW.type IN ('Text', 'TEXT', 'tool-use', 'toolUse', 'ToolUse', 'TOOL_USE', 'tool use', 'tooluse', 'tool-result', 'toolResult', 'ToolResult', 'TOOL_RESULT', 'tool result', 'toolresult', 'Result', 'RESULT', 'Error', 'ERROR', 'User', 'USER', 'Plan', 'PLAN', 'permission-request', 'permissionRequest', 'PermissionRequest', 'PERMISSION_REQUEST', 'permission request', 'permissionrequest')
+                 AND EXISTS (SELECT 1 FROM settings WHERE key = 'message_types_normalized' AND value = 'true')
+                BEGIN
+                    SELECT RAISE(ABORT, 'Non-canonical message type; normalize_message_types maps it to its canonical value');
+                END;
+            "#,
+        data: None,
+    },
+];
+
+const fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+        i += 1;
+    }
+    hash
+}
+
+const fn verify(migrations: &[AppMigration]) {
+    let mut i = 0;
+    while i < migrations.len() {
+        let m = &migrations[i];
+        if fnv1a(m.sql.as_bytes()) != m.checksum {
+            panic!("migration SQL does not match its checksum; shipped migrations must not change");
+        }
+        if i > 0 && m.version <= migrations[i - 1].version {
+            panic!("migration versions must be strictly increasing");
+        }
+        i += 1;
+    }
+}
+
+const _: () = verify(MIGRATIONS);
+
+/// Latest schema version this binary knows
+pub(crate) fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// The same migrations for tauri-plugin-sql
+pub(crate) fn plugin_migrations() -> Vec<Migration> {
+    MIGRATIONS
+        .iter()
+        .map(|m| Migration {
+            version: m.version,
+            description: m.description,
+            sql: m.sql,
+            kind: MigrationKind::Up,
+        })
+        .collect()
+}
+
+/// Checksum as sqlx stores it in `_sqlx_migrations`
+fn sqlx_checksum(sql: &str) -> Vec<u8> {
+    Sha384::digest(sql.as_bytes()).to_vec()
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ChecksumMismatch {
+    pub version: i64,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct MigrationStatus {
+    pub current_version: Option<i64>,
+    pub latest_version: i64,
+    pub applied_now: Vec<i64>,
+    pub mismatches: Vec<ChecksumMismatch>,
+    /// Applied versions this binary doesn't know (database from a newer build)
+    pub unknown_versions: Vec<i64>,
+    pub error: Option<String>,
+}
+
+impl MigrationStatus {
+    /// The database was created by this launch rather than upgraded
+    pub fn created_now(&self) -> bool {
+        self.applied_now.first() == Some(&1)
+    }
+}
+
+#[derive(Default)]
+pub struct MigrationState(pub Mutex<MigrationStatus>);
+
+fn ensure_table(conn: &Connection) -> rusqlite::Result<()> {
+    // Same definition sqlx uses, so the plugin can keep using the table
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS _sqlx_migrations (
+            version BIGINT PRIMARY KEY,
+            description TEXT NOT NULL,
+            installed_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            success BOOLEAN NOT NULL,
+            checksum BLOB NOT NULL,
+            execution_time BIGINT NOT NULL
+        );",
+    )
+}
+
+fn apply(conn: &mut Connection, migration: &AppMigration) -> Result<(), String> {
+    let start = Instant::now();
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute_batch(migration.sql)
+        .map_err(|e| format!("Migration {} failed: {}", migration.version, e))?;
+    if let Some(data) = migration.data {
+        data(&tx).map_err(|e| format!("Data migration {} failed: {}", migration.version, e))?;
+    }
+    tx.execute(
+        "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+         VALUES (?1, ?2, 1, ?3, ?4)",
+        params![
+            migration.version,
+            migration.description,
+            sqlx_checksum(migration.sql),
+            start.elapsed().as_nanos() as i64
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Verify applied checksums, then apply pending migrations unless something is off
+pub(crate) fn migrate(conn: &mut Connection) -> MigrationStatus {
+    let mut status = MigrationStatus {
+        latest_version: latest_version(),
+        ..Default::default()
+    };
+    let result = (|| -> Result<(), String> {
+        ensure_table(conn).map_err(|e| e.to_string())?;
+        let dirty: Option<i64> = conn
+            .query_row("SELECT version FROM _sqlx_migrations WHERE success = 0 LIMIT 1", [], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        if let Some(version) = dirty {
+            return Err(format!("Migration {} is recorded as partially applied", version));
+        }
+
+        let applied: Vec<(i64, Vec<u8>)> = {
+            let mut stmt = conn
+                .prepare("SELECT version, checksum FROM _sqlx_migrations ORDER BY version")
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|e| e.to_string())?;
+            rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+        };
+        for (version, checksum) in &applied {
+            match MIGRATIONS.iter().find(|m| m.version == *version) {
+                Some(m) if sqlx_checksum(m.sql) != *checksum => status.mismatches.push(ChecksumMismatch {
+                    version: *version,
+                    description: m.description.to_string(),
+                }),
+                Some(_) => {}
+                None => status.unknown_versions.push(*version),
+            }
+        }
+        status.current_version = applied.last().map(|(v, _)| *v);
+        if !status.mismatches.is_empty() || !status.unknown_versions.is_empty() {
+            return Ok(());
+        }
+
+        for migration in MIGRATIONS.iter().filter(|m| !applied.iter().any(|(v, _)| *v == m.version)) {
+            apply(conn, migration)?;
+            status.applied_now.push(migration.version);
+            status.current_version = Some(migration.version);
+        }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        status.error = Some(e);
+    }
+    status
+}
+
+/// In-memory database with every migration applied, for tests; fails loudly if a
+/// migration does not
+#[cfg(test)]
+pub(crate) fn test_db() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    let status = migrate(&mut conn);
+    assert!(status.error.is_none(), "migrations failed: {:?}", status.error);
+    conn
+}
+
+/// Run migrations against the app database before the app (and its webview) starts
+pub fn migrate_at_startup(identifier: &str) -> MigrationState {
+    let status = (|| -> Result<MigrationStatus, String> {
+        let path = db::db_path_for_identifier(identifier).ok_or("Cannot resolve the app config directory")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let mut conn = Connection::open(&path).map_err(|e| e.to_string())?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))
+            .map_err(|e| e.to_string())?;
+        Ok(migrate(&mut conn))
+    })()
+    .unwrap_or_else(|e| MigrationStatus {
+        latest_version: latest_version(),
+        error: Some(e),
+        ..Default::default()
+    });
+
+    if !status.applied_now.is_empty() {
+        println!("[Migrations] Applied {:?}", status.applied_now);
+    }
+    if let Some(e) = &status.error {
+        eprintln!("[Migrations] {}", e);
+    }
+    if !status.mismatches.is_empty() {
+        eprintln!("[Migrations] Checksum mismatch for {:?}; not applying further migrations", status.mismatches);
+    }
+    MigrationState(Mutex::new(status))
+}
+
+/// Tell the frontend about checksum problems found at startup
+pub fn report(app: &AppHandle) {
+    let state = app.state::<MigrationState>();
+    let status = state.0.lock().map(|s| s.clone()).unwrap_or_default();
+    if !status.mismatches.is_empty() || !status.unknown_versions.is_empty() {
+        events::emit_typed(app, events::MigrationChecksumMismatch(status));
+    }
+}
+
+#[tauri::command]
+pub fn get_migration_status(state: tauri::State<'_, MigrationState>) -> MigrationStatus {
+    state.0.lock().map(|s| s.clone()).unwrap_or_default()
+}
+
+/// Accept the binary's SQL for the listed mismatched versions, record its checksum
+/// and apply any migrations that were held back
+#[tauri::command]
+pub async fn acknowledge_migration_checksums(app: AppHandle, versions: Vec<i64>) -> Result<MigrationStatus, String> {
+    let status = db::with_conn(&app, move |conn| {
+        for version in &versions {
+            let migration = MIGRATIONS
+                .iter()
+                .find(|m| m.version == *version)
+                .ok_or_else(|| format!("Unknown migration version {}", version))?;
+            conn.execute(
+                "UPDATE _sqlx_migrations SET checksum = ?1 WHERE version = ?2",
+                params![sqlx_checksum(migration.sql), version],
+            )
+            .map_err(|e| e.to_string())?;
+            println!("[Migrations] Checksum for version {} acknowledged", version);
+        }
+        Ok(migrate(conn))
+    })
+    .await?;
+    if let Ok(mut current) = app.state::<MigrationState>().0.lock() {
+        *current = status.clone();
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA_SNAPSHOT: &str = include_str!("../schema.sql");
+
+    fn schema(conn: &Connection) -> String {
+        let mut stmt = conn
+            .prepare(
+                "SELECT sql FROM sqlite_master
+                 WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' AND name != '_sqlx_migrations'
+                 ORDER BY type, name",
+            )
+            .unwrap();
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0)).unwrap();
+        rows.map(|sql| format!("{};\n", sql.unwrap())).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn versions_strictly_increase() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version, "{} before {}", pair[0].version, pair[1].version);
+        }
+    }
+
+    #[test]
+    fn checksums_match_sql() {
+        for m in MIGRATIONS {
+            assert_eq!(fnv1a(m.sql.as_bytes()), m.checksum, "migration {}", m.version);
+        }
+    }
+
+    #[test]
+    fn all_migrations_produce_the_snapshot_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let status = migrate(&mut conn);
+        assert_eq!(status.error, None);
+        assert_eq!(status.current_version, Some(latest_version()));
+        assert_eq!(schema(&conn), SCHEMA_SNAPSHOT);
+    }
+
+    #[test]
+    fn rerun_is_a_no_op() {
+        let mut conn = test_db();
+        let status = migrate(&mut conn);
+        assert!(status.applied_now.is_empty());
+        assert!(status.mismatches.is_empty());
+    }
+
+    #[test]
+    fn changed_checksum_blocks_pending_migrations() {
+        let mut conn = test_db();
+        conn.execute("UPDATE _sqlx_migrations SET checksum = x'00' WHERE version = 1", [])
+            .unwrap();
+        conn.execute("DELETE FROM _sqlx_migrations WHERE version = ?1", [latest_version()])
+            .unwrap();
+        let status = migrate(&mut conn);
+        assert_eq!(status.mismatches.len(), 1);
+        assert_eq!(status.mismatches[0].version, 1);
+        assert!(status.applied_now.is_empty());
+    }
+}
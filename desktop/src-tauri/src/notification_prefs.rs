@@ -0,0 +1,337 @@
+// Notification preferences per event kind, and quiet hours.
+//
+// Each kind has an enabled flag, a minimum severity and an ordered list of channels;
+// `notify` delivers through the first channel that works. During a quiet-hours window
+// only kinds marked `break_through_quiet_hours` (approval requests by default) keep
+// their OS notification and sound; the rest drop to toast or badge.
+//
+// Windows are local wall-clock times on the days they start, so `22:00-07:00` on
+// Friday covers Saturday morning too. The zone is looked up on every evaluation, so a
+// timezone change applies from the next notification.
+
+use crate::settings;
+use chrono::{DateTime, Datelike, NaiveTime, TimeZone, Timelike, Weekday};
+use rusqlite::Connection;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const PREFS_KEY: &str = "notification_prefs";
+/// The completion toggle from before per-kind preferences
+const LEGACY_COMPLETE_KEY: &str = "notify_on_complete";
+const MAX_QUIET_WINDOWS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    TaskCompleted,
+    TaskFailed,
+    Question,
+    ApprovalRequest,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 4] = [
+        NotificationKind::TaskCompleted,
+        NotificationKind::TaskFailed,
+        NotificationKind::Question,
+        NotificationKind::ApprovalRequest,
+    ];
+
+    pub fn severity(self) -> Severity {
+        match self {
+            NotificationKind::TaskCompleted => Severity::Info,
+            NotificationKind::TaskFailed | NotificationKind::Question => Severity::Warning,
+            NotificationKind::ApprovalRequest => Severity::Critical,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    /// A system notification
+    Os,
+    /// An in-app toast
+    Toast,
+    /// The unread badge only
+    Badge,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KindPrefs {
+    pub enabled: bool,
+    /// Tried in order until one delivers
+    pub channels: Vec<Channel>,
+    pub min_severity: Severity,
+    pub sound: bool,
+    pub break_through_quiet_hours: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Day {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl From<Weekday> for Day {
+    fn from(day: Weekday) -> Self {
+        match day {
+            Weekday::Mon => Day::Mon,
+            Weekday::Tue => Day::Tue,
+            Weekday::Wed => Day::Wed,
+            Weekday::Thu => Day::Thu,
+            Weekday::Fri => Day::Fri,
+            Weekday::Sat => Day::Sat,
+            Weekday::Sun => Day::Sun,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuietWindow {
+    /// Local `HH:MM`
+    pub start: String,
+    /// Local `HH:MM`; earlier than `start` for windows that cross midnight, equal to
+    /// it for the whole day
+    pub end: String,
+    /// Days the window starts on; empty means every day
+    #[serde(default)]
+    pub days: Vec<Day>,
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").map_err(|_| format!("Invalid time '{}': expected HH:MM", value))
+}
+
+impl QuietWindow {
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day.into())
+    }
+
+    /// Whether a local time falls inside the window
+    pub fn contains(&self, day: Weekday, time: NaiveTime) -> bool {
+        let (Ok(start), Ok(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+            return false;
+        };
+        let time = time.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(time);
+        if start < end {
+            self.starts_on(day) && start <= time && time < end
+        } else {
+            // Crosses midnight (or lasts all day): the evening part belongs to today,
+            // the morning part to the window that started yesterday
+            (self.starts_on(day) && time >= start) || (self.starts_on(day.pred()) && time < end)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationPrefs {
+    pub kinds: BTreeMap<NotificationKind, KindPrefs>,
+    #[serde(default)]
+    pub quiet_hours: Vec<QuietWindow>,
+}
+
+impl NotificationPrefs {
+    fn defaults(completion_enabled: bool) -> Self {
+        let kinds = NotificationKind::ALL
+            .iter()
+            .map(|&kind| {
+                let prefs = KindPrefs {
+                    enabled: kind != NotificationKind::TaskCompleted || completion_enabled,
+                    channels: vec![Channel::Os, Channel::Toast],
+                    min_severity: Severity::Info,
+                    sound: matches!(kind, NotificationKind::TaskCompleted | NotificationKind::ApprovalRequest),
+                    break_through_quiet_hours: kind == NotificationKind::ApprovalRequest,
+                };
+                (kind, prefs)
+            })
+            .collect();
+        NotificationPrefs {
+            kinds,
+            quiet_hours: Vec::new(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (kind, prefs) in &self.kinds {
+            if prefs.channels.is_empty() {
+                return Err(format!("{:?} needs at least one channel", kind));
+            }
+            for (i, channel) in prefs.channels.iter().enumerate() {
+                if prefs.channels[..i].contains(channel) {
+                    return Err(format!("{:?} lists {:?} twice", kind, channel));
+                }
+            }
+        }
+        if self.quiet_hours.len() > MAX_QUIET_WINDOWS {
+            return Err(format!("At most {} quiet-hours windows are allowed", MAX_QUIET_WINDOWS));
+        }
+        for window in &self.quiet_hours {
+            parse_time(&window.start)?;
+            parse_time(&window.end)?;
+        }
+        Ok(())
+    }
+
+    pub fn quiet_at<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> bool {
+        let (day, time) = (at.weekday(), at.time());
+        self.quiet_hours.iter().any(|window| window.contains(day, time))
+    }
+
+    /// How a notification of `kind` goes out at a moment that is or isn't in quiet
+    /// hours; `None` when it doesn't go out at all
+    pub fn route(&self, kind: NotificationKind, severity: Severity, quiet: bool) -> Option<Route> {
+        let prefs = self.kinds.get(&kind)?;
+        if !prefs.enabled || severity < prefs.min_severity {
+            return None;
+        }
+        let held_back = quiet && !prefs.break_through_quiet_hours;
+        let mut channels = Vec::new();
+        for &channel in &prefs.channels {
+            let channel = if held_back && channel == Channel::Os {
+                Channel::Toast
+            } else {
+                channel
+            };
+            if !channels.contains(&channel) {
+                channels.push(channel);
+            }
+        }
+        if held_back && !channels.contains(&Channel::Badge) {
+            channels.push(Channel::Badge);
+        }
+        Some(Route {
+            channels,
+            sound: prefs.sound && !held_back,
+            quiet: held_back,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Route {
+    pub channels: Vec<Channel>,
+    pub sound: bool,
+    /// Downgraded by quiet hours
+    pub quiet: bool,
+}
+
+pub fn load(conn: &Connection) -> Result<NotificationPrefs, String> {
+    let mut prefs = match settings::get::<NotificationPrefs>(conn, PREFS_KEY)? {
+        Some(prefs) => prefs,
+        None => NotificationPrefs::defaults(settings::get_or(conn, LEGACY_COMPLETE_KEY, true)?),
+    };
+    // Kinds added after the preferences were saved get their defaults
+    for (kind, defaults) in NotificationPrefs::defaults(true).kinds {
+        prefs.kinds.entry(kind).or_insert(defaults);
+    }
+    Ok(prefs)
+}
+
+pub fn save(conn: &Connection, prefs: &NotificationPrefs) -> Result<(), String> {
+    prefs.validate()?;
+    settings::set(conn, PREFS_KEY, prefs)?;
+    let completion = prefs.kinds.get(&NotificationKind::TaskCompleted).is_none_or(|p| p.enabled);
+    settings::set(conn, LEGACY_COMPLETE_KEY, &completion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Tz;
+
+    fn window(start: &str, end: &str, days: Vec<Day>) -> QuietWindow {
+        QuietWindow {
+            start: start.into(),
+            end: end.into(),
+            days,
+        }
+    }
+
+    fn at(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn same_day_windows() {
+        let w = window("09:00", "17:30", vec![Day::Mon, Day::Tue]);
+        assert!(w.contains(Weekday::Mon, at(9, 0)));
+        assert!(w.contains(Weekday::Tue, at(17, 29)));
+        assert!(!w.contains(Weekday::Tue, at(17, 30)));
+        assert!(!w.contains(Weekday::Wed, at(12, 0)));
+    }
+
+    #[test]
+    fn windows_crossing_midnight_belong_to_the_start_day() {
+        let w = window("22:00", "07:00", vec![Day::Fri]);
+        assert!(w.contains(Weekday::Fri, at(23, 15)));
+        assert!(w.contains(Weekday::Sat, at(6, 59)));
+        assert!(!w.contains(Weekday::Sat, at(7, 0)));
+        assert!(!w.contains(Weekday::Sat, at(23, 0)));
+        assert!(!w.contains(Weekday::Fri, at(6, 0)));
+
+        let every_night = window("22:00", "07:00", vec![]);
+        assert!(every_night.contains(Weekday::Mon, at(0, 30)));
+        let all_day = window("00:00", "00:00", vec![Day::Sun]);
+        assert!(all_day.contains(Weekday::Sun, at(13, 0)));
+        assert!(!all_day.contains(Weekday::Mon, at(0, 1)));
+    }
+
+    #[test]
+    fn evaluation_follows_the_zone_of_the_moment() {
+        let prefs = NotificationPrefs {
+            quiet_hours: vec![window("22:00", "07:00", vec![])],
+            ..NotificationPrefs::defaults(true)
+        };
+        let instant = chrono::Utc.with_ymd_and_hms(2026, 3, 10, 5, 0, 0).unwrap();
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let tokyo: Tz = "Asia/Tokyo".parse().unwrap();
+        assert!(prefs.quiet_at(&instant.with_timezone(&berlin)));
+        assert!(!prefs.quiet_at(&instant.with_timezone(&tokyo)));
+    }
+
+    #[test]
+    fn quiet_hours_downgrade_all_but_break_through_kinds() {
+        let prefs = NotificationPrefs::defaults(true);
+        let completed = prefs
+            .route(NotificationKind::TaskCompleted, Severity::Info, true)
+            .unwrap();
+        assert_eq!(completed.channels, vec![Channel::Toast, Channel::Badge]);
+        assert!(!completed.sound && completed.quiet);
+        let approval = prefs
+            .route(NotificationKind::ApprovalRequest, Severity::Critical, true)
+            .unwrap();
+        assert_eq!(approval.channels, vec![Channel::Os, Channel::Toast]);
+        assert!(approval.sound && !approval.quiet);
+
+        let mut strict = prefs.clone();
+        strict.kinds.get_mut(&NotificationKind::TaskFailed).unwrap().min_severity = Severity::Critical;
+        assert!(strict.route(NotificationKind::TaskFailed, Severity::Warning, false).is_none());
+    }
+
+    #[test]
+    fn validation() {
+        let mut prefs = NotificationPrefs::defaults(true);
+        assert!(prefs.validate().is_ok());
+        prefs.quiet_hours.push(window("25:00", "07:00", vec![]));
+        assert!(prefs.validate().is_err());
+        prefs.quiet_hours.clear();
+        prefs.kinds.get_mut(&NotificationKind::Question).unwrap().channels = vec![Channel::Os, Channel::Os];
+        assert!(prefs.validate().is_err());
+    }
+}
@@ -0,0 +1,431 @@
+// Notifications and completion sounds.
+//
+// The frontend writes tasks and messages to the database directly, so events are
+// noticed by watching it: a task that leaves `running` as `completed` or `error`, and
+// new messages asking for approval or an answer. `notify` routes each through the
+// user's preferences (see `notification_prefs`): the first channel that works gets
+// it, an OS notification or an in-app toast/badge via `notify://show`, and the sound
+// plays if the kind wants one. Sounds are `notification_sound`, a built-in name
+// (mapped to a system sound on each platform) or the path of a custom audio file.
+
+use crate::message_types::MessageType;
+use crate::notification_prefs::{self, Channel, NotificationKind, NotificationPrefs, Route};
+use crate::{background, db, events, focus, schedule, settings};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::AppHandle;
+use tauri_plugin_notification::{NotificationExt, PermissionState};
+
+const SOUND_KEY: &str = "notification_sound";
+const NOTIFY_KEY: &str = "notify_on_complete";
+const NONE: &str = "none";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "notify",
+    interval: POLL_INTERVAL,
+    coalescable: true,
+};
+
+/// Built-in sound names and the system files tried for each, first existing wins
+const BUILT_IN: &[(&str, &[&str])] = &[
+    (
+        "chime",
+        &[
+            "/System/Library/Sounds/Glass.aiff",
+            "C:\\Windows\\Media\\chimes.wav",
+            "/usr/share/sounds/freedesktop/stereo/complete.oga",
+        ],
+    ),
+    (
+        "ping",
+        &[
+            "/System/Library/Sounds/Ping.aiff",
+            "C:\\Windows\\Media\\Windows Notify System Generic.wav",
+            "C:\\Windows\\Media\\notify.wav",
+            "/usr/share/sounds/freedesktop/stereo/message.oga",
+        ],
+    ),
+    (
+        "pop",
+        &[
+            "/System/Library/Sounds/Pop.aiff",
+            "C:\\Windows\\Media\\Windows Pop-up Blocked.wav",
+            "C:\\Windows\\Media\\ding.wav",
+            "/usr/share/sounds/freedesktop/stereo/bell.oga",
+        ],
+    ),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NotificationSettings {
+    pub notify_on_complete: bool,
+    /// `none`, a built-in name or a file path
+    pub sound: String,
+    /// Built-in names with a sound file on this machine
+    pub built_in: Vec<&'static str>,
+}
+
+fn built_in_path(name: &str) -> Option<PathBuf> {
+    BUILT_IN
+        .iter()
+        .find(|(n, _)| *n == name)
+        .and_then(|(_, candidates)| candidates.iter().map(PathBuf::from).find(|p| p.is_file()))
+}
+
+/// Check that the file decodes as audio this platform can play
+fn check_playable(path: &Path) -> Result<(), String> {
+    if !path.is_file() {
+        return Err(format!("Sound file '{}' does not exist", path.display()));
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let status = std::process::Command::new("afinfo")
+            .arg(path)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map_err(|e| e.to_string())?;
+        if !status.success() {
+            return Err(format!("'{}' is not a playable audio file", path.display()));
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+        rodio::Decoder::new(std::io::BufReader::new(file))
+            .map_err(|e| format!("'{}' is not a playable audio file: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+/// Path to play for a setting value; `None` for `none`
+fn resolve_sound(sound: &str) -> Result<Option<PathBuf>, String> {
+    if sound == NONE {
+        return Ok(None);
+    }
+    if BUILT_IN.iter().any(|(name, _)| *name == sound) {
+        return built_in_path(sound)
+            .map(Some)
+            .ok_or_else(|| format!("Built-in sound '{}' is not available on this system", sound));
+    }
+    let path = PathBuf::from(sound);
+    if !path.is_absolute() {
+        return Err(format!("Unknown sound '{}': expected 'none', a built-in name or an absolute path", sound));
+    }
+    check_playable(&path)?;
+    Ok(Some(path))
+}
+
+/// Play on a background thread; failures are logged, never surfaced
+fn play(path: PathBuf) {
+    std::thread::spawn(move || {
+        #[cfg(target_os = "macos")]
+        let result = std::process::Command::new("afplay")
+            .arg(&path)
+            .status()
+            .map_err(|e| e.to_string())
+            .map(|_| ());
+
+        #[cfg(not(target_os = "macos"))]
+        let result = (|| -> Result<(), String> {
+            let (_stream, handle) = rodio::OutputStream::try_default().map_err(|e| e.to_string())?;
+            let sink = rodio::Sink::try_new(&handle).map_err(|e| e.to_string())?;
+            let file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
+            let source = rodio::Decoder::new(std::io::BufReader::new(file)).map_err(|e| e.to_string())?;
+            sink.append(source);
+            sink.sleep_until_end();
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            eprintln!("[Notify] Failed to play {}: {}", path.display(), e);
+        }
+    });
+}
+
+fn sound_setting(conn: &Connection) -> Result<String, String> {
+    settings::get_or(conn, SOUND_KEY, NONE.to_string())
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub task_id: Option<String>,
+    pub title: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivery {
+    pub kind: NotificationKind,
+    /// `None` when preferences turned the notification off
+    pub channel: Option<Channel>,
+    pub sound: bool,
+    /// Quiet hours downgraded it
+    pub quiet: bool,
+}
+
+fn show(app: &AppHandle, notification: &Notification, channel: Channel, quiet: bool) -> Result<(), String> {
+    match channel {
+        Channel::Os => {
+            let notifications = app.notification();
+            if notifications.permission_state().map_err(|e| e.to_string())? != PermissionState::Granted {
+                return Err("System notifications are not permitted".to_string());
+            }
+            notifications
+                .builder()
+                .title(&notification.title)
+                .body(&notification.body)
+                .show()
+                .map_err(|e| e.to_string())
+        }
+        Channel::Toast | Channel::Badge => {
+            events::emit_typed(
+                app,
+                events::NotificationShown {
+                    kind: notification.kind,
+                    task_id: notification.task_id.clone(),
+                    title: notification.title.clone(),
+                    body: notification.body.clone(),
+                    channel,
+                    quiet,
+                },
+            );
+            Ok(())
+        }
+    }
+}
+
+fn deliver(app: &AppHandle, conn: &Connection, notification: &Notification, route: Option<Route>) -> Result<Delivery, String> {
+    let mut delivery = Delivery {
+        kind: notification.kind,
+        channel: None,
+        sound: false,
+        quiet: false,
+    };
+    let Some(route) = route else {
+        return Ok(delivery);
+    };
+    delivery.quiet = route.quiet;
+    for channel in route.channels {
+        match show(app, notification, channel, route.quiet) {
+            Ok(()) => {
+                delivery.channel = Some(channel);
+                break;
+            }
+            Err(e) => eprintln!("[Notify] {:?} unavailable, trying the next channel: {}", channel, e),
+        }
+    }
+    if route.sound {
+        if let Some(path) = resolve_sound(&sound_setting(conn)?)? {
+            play(path);
+            delivery.sound = true;
+        }
+    }
+    Ok(delivery)
+}
+
+/// Send a notification the way the user's preferences say, right now
+pub fn notify(app: &AppHandle, conn: &Connection, notification: &Notification) -> Result<Delivery, String> {
+    let prefs = notification_prefs::load(conn)?;
+    let quiet = prefs.quiet_at(&chrono::Utc::now().with_timezone(&schedule::system_zone()));
+    let route = prefs.route(notification.kind, notification.kind.severity(), quiet);
+    deliver(app, conn, notification, route)
+}
+
+fn running(conn: &Connection) -> Result<HashSet<String>, String> {
+    let mut stmt = conn
+        .prepare_cached("SELECT id FROM tasks WHERE status = 'running'")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| row.get(0)).map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn title(kind: NotificationKind, count: usize) -> String {
+    match (kind, count) {
+        (NotificationKind::TaskCompleted, 1) => "Task completed".to_string(),
+        (NotificationKind::TaskCompleted, n) => format!("{} tasks completed", n),
+        (NotificationKind::TaskFailed, 1) => "Task failed".to_string(),
+        (NotificationKind::TaskFailed, n) => format!("{} tasks failed", n),
+        (NotificationKind::Question, _) => "A task has a question".to_string(),
+        (NotificationKind::ApprovalRequest, _) => "A task needs your approval".to_string(),
+    }
+}
+
+fn prompt(conn: &Connection, task_id: &str) -> String {
+    let prompt: String = conn
+        .query_row("SELECT prompt FROM tasks WHERE id = ?1", [task_id], |row| row.get(0))
+        .unwrap_or_default();
+    prompt.chars().take(120).collect()
+}
+
+#[derive(Default)]
+struct Watcher {
+    running: Option<HashSet<String>>,
+    last_message_id: Option<i64>,
+}
+
+impl Watcher {
+    /// Task ids per kind of event since the last tick
+    fn collect(&mut self, conn: &Connection) -> Result<BTreeMap<NotificationKind, Vec<String>>, String> {
+        let mut found: BTreeMap<NotificationKind, Vec<String>> = BTreeMap::new();
+        let current = running(conn)?;
+        if let Some(previous) = &self.running {
+            for task_id in previous.difference(&current) {
+                let status: Option<String> = conn
+                    .query_row("SELECT status FROM tasks WHERE id = ?1", [task_id], |row| row.get(0))
+                    .ok();
+                let kind = match status.as_deref() {
+                    Some("completed") => NotificationKind::TaskCompleted,
+                    Some("error") => NotificationKind::TaskFailed,
+                    _ => continue,
+                };
+                found.entry(kind).or_default().push(task_id.clone());
+            }
+        }
+        self.running = Some(current);
+
+        let after = match self.last_message_id {
+            Some(after) => after,
+            None => {
+                self.last_message_id = Some(
+                    conn.query_row("SELECT COALESCE(MAX(id), 0) FROM messages", [], |row| row.get(0))
+                        .map_err(|e| e.to_string())?,
+                );
+                return Ok(found);
+            }
+        };
+        let mut stmt = conn
+            .prepare_cached("SELECT id, task_id, type, tool_name, subtype FROM messages WHERE id > ?1 ORDER BY id")
+            .map_err(|e| e.to_string())?;
+        let mut rows = stmt.query([after]).map_err(|e| e.to_string())?;
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let id: i64 = row.get(0).map_err(|e| e.to_string())?;
+            let task_id: String = row.get(1).map_err(|e| e.to_string())?;
+            let kind: MessageType = row.get(2).map_err(|e| e.to_string())?;
+            let tool_name: Option<String> = row.get(3).map_err(|e| e.to_string())?;
+            let subtype: Option<String> = row.get(4).map_err(|e| e.to_string())?;
+            self.last_message_id = Some(id);
+            let kind = match focus::attention_reason(&kind, tool_name.as_deref(), subtype.as_deref()) {
+                Some(focus::FocusReason::ApprovalRequest) => NotificationKind::ApprovalRequest,
+                Some(_) => NotificationKind::Question,
+                None => continue,
+            };
+            found.entry(kind).or_default().push(task_id);
+        }
+        Ok(found)
+    }
+}
+
+/// Start watching for tasks that finish or need the user
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let mut watcher = Watcher::default();
+        loop {
+            background::sleep(&app, &ACTIVITY);
+            let result = db::open(&app).and_then(|conn| {
+                // Several events of a kind in the same tick get one notification
+                for (kind, task_ids) in watcher.collect(&conn)? {
+                    let notification = Notification {
+                        kind,
+                        title: title(kind, task_ids.len()),
+                        body: prompt(&conn, &task_ids[0]),
+                        task_id: task_ids.into_iter().next(),
+                    };
+                    notify(&app, &conn, &notification)?;
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                eprintln!("[Notify] {}", e);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn get_notification_settings(app: AppHandle) -> Result<NotificationSettings, String> {
+    db::with_conn(&app, |conn| {
+        Ok(NotificationSettings {
+            notify_on_complete: settings::get_or(conn, NOTIFY_KEY, true)?,
+            sound: sound_setting(conn)?,
+            built_in: BUILT_IN
+                .iter()
+                .map(|(name, _)| *name)
+                .filter(|name| built_in_path(name).is_some())
+                .collect(),
+        })
+    })
+    .await
+}
+
+/// Choose the completion sound: `none`, a built-in name, or an absolute path to an
+/// audio file, which must exist and decode
+#[tauri::command]
+pub async fn set_notification_sound(app: AppHandle, sound: String) -> Result<(), String> {
+    let sound = sound.trim().to_string();
+    resolve_sound(&sound)?;
+    db::with_conn(&app, move |conn| settings::set(conn, SOUND_KEY, &sound)).await
+}
+
+#[tauri::command]
+pub async fn set_notify_on_complete(app: AppHandle, enabled: bool) -> Result<(), String> {
+    db::with_conn(&app, move |conn| {
+        let mut prefs = notification_prefs::load(conn)?;
+        if let Some(completed) = prefs.kinds.get_mut(&NotificationKind::TaskCompleted) {
+            completed.enabled = enabled;
+        }
+        notification_prefs::save(conn, &prefs)?;
+        settings::set(conn, NOTIFY_KEY, &enabled)
+    })
+    .await
+}
+
+/// Play a sound now so the user can hear their choice; defaults to the saved one
+#[tauri::command]
+pub async fn play_notification_sound(app: AppHandle, sound: Option<String>) -> Result<(), String> {
+    let sound = match sound {
+        Some(sound) => sound,
+        None => db::with_conn(&app, |conn| sound_setting(conn)).await?,
+    };
+    if let Some(path) = resolve_sound(sound.trim())? {
+        play(path);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_notification_prefs(app: AppHandle) -> Result<NotificationPrefs, String> {
+    db::with_conn(&app, |conn| notification_prefs::load(conn)).await
+}
+
+#[tauri::command]
+pub async fn set_notification_prefs(app: AppHandle, prefs: NotificationPrefs) -> Result<NotificationPrefs, String> {
+    prefs.validate()?;
+    db::with_conn(&app, move |conn| {
+        notification_prefs::save(conn, &prefs)?;
+        notification_prefs::load(conn)
+    })
+    .await
+}
+
+/// Send a sample notification of `kind` through the saved preferences, quiet hours
+/// included, and report how it went out
+#[tauri::command]
+pub async fn preview_notification(app: AppHandle, kind: NotificationKind) -> Result<Delivery, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let notification = Notification {
+            kind,
+            task_id: None,
+            title: title(kind, 1),
+            body: "This is a preview of how this notification looks.".to_string(),
+        };
+        notify(&handle, conn, &notification)
+    })
+    .await
+}
@@ -0,0 +1,153 @@
+// First-run detection and onboarding completion.
+//
+// Onboarding is done once `onboarding_completed` exists in `settings`. A database
+// created by this launch also gets default settings seeded, so the first window
+// doesn't have to infer defaults from empty tables. Keys match the frontend's
+// `Settings` fields.
+
+use crate::migrations::MigrationState;
+use crate::{db, settings};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+
+const COMPLETED_KEY: &str = "onboarding_completed";
+
+/// Seeded only into a brand new database, never over existing values
+fn default_settings() -> Vec<(&'static str, Value)> {
+    vec![
+        ("theme", json!("system")),
+        ("accentColor", json!("orange")),
+        ("backgroundStyle", json!("default")),
+        // Empty means "detect from the system" to the frontend
+        ("language", json!("")),
+        ("mcpEnabled", json!(true)),
+        ("skillsEnabled", json!(true)),
+        ("sandboxEnabled", json!(true)),
+        ("defaultSandboxProvider", json!("codex")),
+        ("defaultAgentRuntime", json!("claude")),
+    ]
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FirstRunState {
+    pub first_run: bool,
+    /// The database was created by this launch
+    pub fresh_database: bool,
+    pub completed_at: Option<String>,
+}
+
+fn created_now(app: &AppHandle) -> bool {
+    app.state::<MigrationState>()
+        .0
+        .lock()
+        .map(|status| status.created_now())
+        .unwrap_or(false)
+}
+
+fn is_first_run_in(conn: &Connection) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT NOT EXISTS(SELECT 1 FROM settings WHERE key = ?1)",
+        [COMPLETED_KEY],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Seed defaults when this launch created the database
+pub fn init(app: &AppHandle) {
+    if !created_now(app) {
+        return;
+    }
+    let result = db::open(app).and_then(|conn| {
+        for (key, value) in default_settings() {
+            conn.execute(
+                "INSERT OR IGNORE INTO settings (key, value, updated_at) VALUES (?1, ?2, datetime('now'))",
+                params![key, value.to_string()],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    });
+    match result {
+        Ok(()) => println!("[Onboarding] Fresh database; seeded default settings"),
+        Err(e) => eprintln!("[Onboarding] Failed to seed default settings: {}", e),
+    }
+}
+
+/// Set `apiKey` on one entry of a providers list
+fn apply_provider_token(providers: &mut Value, token: &Value) -> Result<(), String> {
+    let provider_id = token
+        .get("providerId")
+        .and_then(Value::as_str)
+        .ok_or("providerToken.providerId is required")?;
+    let api_key = token
+        .get("apiKey")
+        .and_then(Value::as_str)
+        .ok_or("providerToken.apiKey is required")?;
+    let provider = providers
+        .as_array_mut()
+        .and_then(|list| list.iter_mut().find(|p| p.get("id").and_then(Value::as_str) == Some(provider_id)))
+        .ok_or_else(|| format!("Unknown provider '{}'", provider_id))?;
+    provider["apiKey"] = json!(api_key);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn is_first_run(app: AppHandle) -> Result<bool, String> {
+    db::with_conn(&app, |conn| is_first_run_in(conn)).await
+}
+
+#[tauri::command]
+pub async fn get_first_run_state(app: AppHandle) -> Result<FirstRunState, String> {
+    let fresh_database = created_now(&app);
+    db::with_conn(&app, move |conn| {
+        let completed: Option<Value> = settings::get(conn, COMPLETED_KEY)?;
+        Ok(FirstRunState {
+            first_run: is_first_run_in(conn)?,
+            fresh_database,
+            completed_at: completed
+                .as_ref()
+                .and_then(|v| v.get("completedAt"))
+                .and_then(Value::as_str)
+                .map(String::from),
+        })
+    })
+    .await
+}
+
+/// Mark onboarding done and save what the user chose, all or nothing. `data` may
+/// hold `defaultProvider`, `defaultModel`, `providers` (the full list) and
+/// `providerToken: { providerId, apiKey }`; the token is applied to `providers`
+/// from `data` or, failing that, to the stored list.
+#[tauri::command]
+pub async fn complete_onboarding(app: AppHandle, data: Value) -> Result<(), String> {
+    if !data.is_object() && !data.is_null() {
+        return Err("Onboarding data must be an object".to_string());
+    }
+    db::with_conn(&app, move |conn| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        for key in ["defaultProvider", "defaultModel"] {
+            if let Some(value) = data.get(key).filter(|v| !v.is_null()) {
+                settings::set(&tx, key, value)?;
+            }
+        }
+        let mut providers = data.get("providers").cloned().filter(|v| v.is_array());
+        if let Some(token) = data.get("providerToken").filter(|v| !v.is_null()) {
+            let mut list = match providers.take() {
+                Some(list) => list,
+                None => settings::get::<Value>(&tx, "providers")?.ok_or("No providers configured")?,
+            };
+            apply_provider_token(&mut list, token)?;
+            providers = Some(list);
+        }
+        if let Some(providers) = providers {
+            settings::set(&tx, "providers", &providers)?;
+        }
+        let completed_at = chrono::Utc::now().to_rfc3339();
+        settings::set(&tx, COMPLETED_KEY, &json!({ "completedAt": completed_at }))?;
+        tx.commit().map_err(|e| e.to_string())
+    })
+    .await
+}
@@ -0,0 +1,302 @@
+// The running sidecar's PID, kept on disk so a launch after a crash or force-quit
+// can stop the sidecar the previous run left behind.
+//
+// `RunEvent::Exit` stops the sidecar on a normal quit, but never runs when the app
+// is killed, and the orphan then holds the API port. `sidecar.pid` in the app data
+// directory holds the PID of each spawned sidecar with the OS's start time for that
+// process. At the next startup the PID only counts as our orphan if the process is
+// still named `workany-api` and started at the recorded time, so a PID the OS has
+// since given to another process is never signalled. The file is read once and
+// removed whatever it held.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+const PID_FILE: &str = "sidecar.pid";
+const SIDECAR_NAME: &str = "workany-api";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PidRecord {
+    pub pid: u32,
+    /// Opaque OS start time of the process, compared for equality only; `None`
+    /// where it couldn't be read
+    pub process_start: Option<u64>,
+    /// RFC 3339, when the sidecar was spawned
+    pub started_at: String,
+}
+
+/// What the OS says about a live process
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProcessInfo {
+    name: String,
+    start: Option<u64>,
+}
+
+fn pid_path(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_data_dir().ok().map(|dir| dir.join(PID_FILE))
+}
+
+/// Whether `found` is still the process `record` describes
+fn same_process(record: &PidRecord, found: Option<&ProcessInfo>) -> bool {
+    let Some(found) = found else {
+        return false;
+    };
+    if !found.name.contains(SIDECAR_NAME) {
+        return false;
+    }
+    // Without a start time on either side, the name is all there is to go on
+    match (record.process_start, found.start) {
+        (Some(recorded), Some(current)) => recorded == current,
+        _ => true,
+    }
+}
+
+fn write_record(path: &Path, record: &PidRecord) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_vec(record)?)
+}
+
+/// Remove the file at `path` and return its record if the process is still there
+fn take_orphan_at(path: &Path, inspect: impl Fn(u32) -> Option<ProcessInfo>) -> Option<PidRecord> {
+    let contents = fs::read(path).ok()?;
+    if let Err(e) = fs::remove_file(path) {
+        eprintln!("[API] Cannot remove {}: {}", path.display(), e);
+    }
+    let record: PidRecord = match serde_json::from_slice(&contents) {
+        Ok(record) => record,
+        Err(e) => {
+            eprintln!("[API] Ignoring an unreadable sidecar PID file: {}", e);
+            return None;
+        }
+    };
+    if same_process(&record, inspect(record.pid).as_ref()) {
+        Some(record)
+    } else {
+        println!("[API] The sidecar recorded by the previous run (PID {}) is gone", record.pid);
+        None
+    }
+}
+
+/// Record a freshly spawned sidecar, replacing the previous record
+pub(crate) fn record(app: &AppHandle, pid: u32) {
+    let Some(path) = pid_path(app) else {
+        return;
+    };
+    let record = PidRecord {
+        pid,
+        process_start: inspect(pid).and_then(|info| info.start),
+        started_at: chrono::Utc::now().to_rfc3339(),
+    };
+    if let Err(e) = write_record(&path, &record) {
+        eprintln!("[API] Cannot write {}: {}", path.display(), e);
+    }
+}
+
+/// Forget the sidecar, after it was stopped on a normal exit
+pub(crate) fn clear(app: &AppHandle) {
+    if let Some(path) = pid_path(app) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// The sidecar a previous run left running, if it is still alive. The file is
+/// removed either way.
+pub(crate) fn take_orphan(app: &AppHandle) -> Option<PidRecord> {
+    take_orphan_at(&pid_path(app)?, inspect)
+}
+
+/// Whether the recorded process is still running
+pub(crate) fn is_running(record: &PidRecord) -> bool {
+    same_process(record, inspect(record.pid).as_ref())
+}
+
+fn inspect(pid: u32) -> Option<ProcessInfo> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::inspect(pid)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        macos::inspect(pid)
+    }
+    #[cfg(windows)]
+    {
+        windows::inspect(pid)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    {
+        let _ = pid;
+        None
+    }
+}
+
+/// Start time in clock ticks since boot from `/proc/<pid>/stat`, `None` for a
+/// zombie. The name in field 2 may hold spaces and parentheses, so fields are
+/// counted from the last `)`: state is field 3, start time field 22.
+#[cfg(any(target_os = "linux", test))]
+fn stat_start_ticks(stat: &str) -> Option<u64> {
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace();
+    if fields.next()? == "Z" {
+        return None;
+    }
+    fields.nth(18)?.parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::ProcessInfo;
+    use std::fs;
+
+    pub(super) fn inspect(pid: u32) -> Option<ProcessInfo> {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        let start = super::stat_start_ticks(&stat)?;
+        let name = fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+        Some(ProcessInfo {
+            name: name.trim_end().to_string(),
+            start: Some(start),
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::ProcessInfo;
+    use libproc::bsd_info::BSDInfo;
+    use libproc::proc_pid::{name, pidinfo};
+
+    pub(super) fn inspect(pid: u32) -> Option<ProcessInfo> {
+        let info = pidinfo::<BSDInfo>(pid as i32, 0).ok()?;
+        Some(ProcessInfo {
+            name: name(pid as i32).ok()?,
+            start: Some(info.pbi_start_tvsec),
+        })
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use super::ProcessInfo;
+    use windows_sys::Win32::Foundation::{CloseHandle, FILETIME, STILL_ACTIVE};
+    use windows_sys::Win32::System::Threading::{
+        GetExitCodeProcess, GetProcessTimes, OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    pub(super) fn inspect(pid: u32) -> Option<ProcessInfo> {
+        // SAFETY: the handle is checked before use and closed before returning
+        unsafe {
+            let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if handle.is_null() {
+                return None;
+            }
+            let mut exit_code = 0u32;
+            let alive = GetExitCodeProcess(handle, &mut exit_code) != 0 && exit_code == STILL_ACTIVE as u32;
+            let mut path = [0u16; 1024];
+            let mut len = path.len() as u32;
+            let named = QueryFullProcessImageNameW(handle, PROCESS_NAME_WIN32, path.as_mut_ptr(), &mut len) != 0;
+            let zero = FILETIME {
+                dwLowDateTime: 0,
+                dwHighDateTime: 0,
+            };
+            let (mut created, mut exited, mut kernel, mut user) = (zero, zero, zero, zero);
+            let timed = GetProcessTimes(handle, &mut created, &mut exited, &mut kernel, &mut user) != 0;
+            CloseHandle(handle);
+            if !alive || !named {
+                return None;
+            }
+            Some(ProcessInfo {
+                name: String::from_utf16_lossy(&path[..len as usize]),
+                start: timed.then(|| ((created.dwHighDateTime as u64) << 32) | created.dwLowDateTime as u64),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workany-pidfile-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(PID_FILE)
+    }
+
+    fn record(pid: u32, process_start: Option<u64>) -> PidRecord {
+        PidRecord {
+            pid,
+            process_start,
+            started_at: "2026-10-16T09:00:00Z".to_string(),
+        }
+    }
+
+    fn sidecar(start: u64) -> Option<ProcessInfo> {
+        Some(ProcessInfo {
+            name: "workany-api".to_string(),
+            start: Some(start),
+        })
+    }
+
+    #[test]
+    fn a_live_sidecar_is_an_orphan() {
+        let path = temp_file("live");
+        write_record(&path, &record(4242, Some(900))).unwrap();
+        let orphan = take_orphan_at(&path, |pid| if pid == 4242 { sidecar(900) } else { None });
+        assert_eq!(orphan, Some(record(4242, Some(900))));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn a_missing_file_means_no_orphan() {
+        let path = temp_file("missing");
+        assert_eq!(take_orphan_at(&path, |_| sidecar(900)), None);
+    }
+
+    #[test]
+    fn a_stale_file_for_an_exited_process_is_removed() {
+        let path = temp_file("stale");
+        write_record(&path, &record(4242, Some(900))).unwrap();
+        assert_eq!(take_orphan_at(&path, |_| None), None);
+        assert!(!path.exists());
+
+        fs::write(&path, b"not json").unwrap();
+        assert_eq!(take_orphan_at(&path, |_| sidecar(900)), None);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn a_reused_pid_is_left_alone() {
+        let path = temp_file("reused");
+        // Another program got the PID
+        write_record(&path, &record(4242, Some(900))).unwrap();
+        let editor = |_| {
+            Some(ProcessInfo {
+                name: "vim".to_string(),
+                start: Some(900),
+            })
+        };
+        assert_eq!(take_orphan_at(&path, editor), None);
+        // A later sidecar got the PID: same name, different start
+        write_record(&path, &record(4242, Some(900))).unwrap();
+        assert_eq!(take_orphan_at(&path, |_| sidecar(1200)), None);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn the_name_decides_when_no_start_time_was_recorded() {
+        assert!(same_process(&record(1, None), sidecar(5).as_ref()));
+        assert!(!same_process(&record(1, None), None));
+    }
+
+    #[test]
+    fn proc_stat_start_time_skips_odd_process_names() {
+        let stat = "4242 (workany (api) x) S 1 4242 4242 0 -1 4194560 1 0 0 0 3 1 0 0 20 0 11 0 987654 1234 56";
+        assert_eq!(stat_start_ticks(stat), Some(987654));
+        let zombie = "4242 (workany-api) Z 1 4242 4242 0 -1 4194560 1 0 0 0 3 1 0 0 20 0 11 0 987654 0 0";
+        assert_eq!(stat_start_ticks(zombie), None);
+    }
+}
@@ -0,0 +1,267 @@
+// What the desktop session supports, probed once at startup.
+//
+// On Linux this matters: under Wayland, global shortcuts and screen capture only
+// work through the XDG desktop portal, and GNOME shows no tray icon unless an
+// extension provides a StatusNotifier host. `probe` reads the session type from the
+// environment and asks the session bus which portals and hosts are present. Other
+// platforms report everything as native.
+//
+// Features call `require` before using one of these, and get a typed `unsupported`
+// error instead of failing silently. Without a tray host, closing the main window
+// quits even when the saved preference is minimize-to-tray (see `window`).
+
+use crate::error::CommandError;
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    GlobalShortcuts,
+    ScreenCapture,
+    Tray,
+}
+
+impl Feature {
+    fn label(self) -> &'static str {
+        match self {
+            Feature::GlobalShortcuts => "Global shortcuts",
+            Feature::ScreenCapture => "Screen capture",
+            Feature::Tray => "The tray icon",
+        }
+    }
+}
+
+/// How a feature can be provided in this session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Support {
+    Native,
+    /// Through the XDG desktop portal, which asks the user for consent
+    Portal,
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemCapabilities {
+    pub os: &'static str,
+    /// `wayland`, `x11` or `tty` from `XDG_SESSION_TYPE`, Linux only
+    pub session_type: Option<String>,
+    /// `XDG_CURRENT_DESKTOP`, e.g. `GNOME` or `KDE`
+    pub desktop: Option<String>,
+    /// `org.freedesktop.portal.Desktop` is on the session bus
+    pub portal_available: bool,
+    pub global_shortcuts_portal_version: Option<u32>,
+    pub screen_cast_portal_version: Option<u32>,
+    /// A StatusNotifier host is registered, so tray icons are shown
+    pub tray_host: bool,
+    pub global_shortcuts: Support,
+    pub screen_capture: Support,
+    pub tray: Support,
+    /// Probe steps that failed, for support reports
+    pub probe_errors: Vec<String>,
+}
+
+impl SystemCapabilities {
+    pub fn support(&self, feature: Feature) -> Support {
+        match feature {
+            Feature::GlobalShortcuts => self.global_shortcuts,
+            Feature::ScreenCapture => self.screen_capture,
+            Feature::Tray => self.tray,
+        }
+    }
+
+    /// Why `feature` is unsupported, for error messages
+    fn reason(&self, feature: Feature) -> String {
+        let session = self.session_type.as_deref().unwrap_or("unknown");
+        match feature {
+            Feature::Tray => format!(
+                "no StatusNotifier host is running on {} (on GNOME, install the AppIndicator extension)",
+                self.desktop.as_deref().unwrap_or("this desktop")
+            ),
+            Feature::GlobalShortcuts if !self.portal_available => {
+                format!("the {} session has no XDG desktop portal", session)
+            }
+            Feature::GlobalShortcuts => "the desktop portal has no GlobalShortcuts interface".to_string(),
+            Feature::ScreenCapture if !self.portal_available => {
+                format!("the {} session has no XDG desktop portal", session)
+            }
+            Feature::ScreenCapture => "the desktop portal has no ScreenCast interface".to_string(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PlatformState(Mutex<Option<SystemCapabilities>>);
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use zbus::blocking::Connection;
+    use zbus::zvariant::OwnedValue;
+
+    const PORTAL_NAME: &str = "org.freedesktop.portal.Desktop";
+    const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+    const WATCHER_NAME: &str = "org.kde.StatusNotifierWatcher";
+    const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+
+    fn property(conn: &Connection, dest: &str, path: &str, interface: &str, name: &str) -> zbus::Result<OwnedValue> {
+        let reply = conn.call_method(
+            Some(dest),
+            path,
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &(interface, name),
+        )?;
+        reply.body().deserialize::<OwnedValue>()
+    }
+
+    fn has_owner(conn: &Connection, name: &str) -> zbus::Result<bool> {
+        let reply = conn.call_method(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            Some("org.freedesktop.DBus"),
+            "NameHasOwner",
+            &(name,),
+        )?;
+        reply.body().deserialize::<bool>()
+    }
+
+    fn portal_version(conn: &Connection, interface: &str) -> Option<u32> {
+        let value = property(conn, PORTAL_NAME, PORTAL_PATH, interface, "version").ok()?;
+        u32::try_from(value).ok()
+    }
+
+    pub(super) struct BusProbe {
+        pub portal_available: bool,
+        pub global_shortcuts: Option<u32>,
+        pub screen_cast: Option<u32>,
+        pub tray_host: bool,
+    }
+
+    pub(super) fn probe(errors: &mut Vec<String>) -> BusProbe {
+        let mut found = BusProbe {
+            portal_available: false,
+            global_shortcuts: None,
+            screen_cast: None,
+            tray_host: false,
+        };
+        let conn = match Connection::session() {
+            Ok(conn) => conn,
+            Err(e) => {
+                errors.push(format!("session bus: {}", e));
+                return found;
+            }
+        };
+        match has_owner(&conn, PORTAL_NAME) {
+            Ok(true) => {
+                found.portal_available = true;
+                found.global_shortcuts = portal_version(&conn, "org.freedesktop.portal.GlobalShortcuts");
+                found.screen_cast = portal_version(&conn, "org.freedesktop.portal.ScreenCast");
+            }
+            Ok(false) => {}
+            Err(e) => errors.push(format!("portal lookup: {}", e)),
+        }
+        match property(&conn, WATCHER_NAME, WATCHER_PATH, WATCHER_NAME, "IsStatusNotifierHostRegistered") {
+            Ok(value) => found.tray_host = bool::try_from(value).unwrap_or(false),
+            // No watcher at all means no host either
+            Err(zbus::Error::MethodError(..)) => {}
+            Err(e) => errors.push(format!("StatusNotifier lookup: {}", e)),
+        }
+        found
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn probe() -> SystemCapabilities {
+    let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+    let mut probe_errors = Vec::new();
+    let bus = linux::probe(&mut probe_errors);
+    let session_type = env("XDG_SESSION_TYPE").or_else(|| {
+        // Some login managers leave XDG_SESSION_TYPE unset
+        if env("WAYLAND_DISPLAY").is_some() {
+            Some("wayland".to_string())
+        } else {
+            env("DISPLAY").map(|_| "x11".to_string())
+        }
+    });
+    let wayland = session_type.as_deref() == Some("wayland");
+    let through_portal = |version: Option<u32>| match (wayland, version) {
+        (false, _) => Support::Native,
+        (true, Some(_)) => Support::Portal,
+        (true, None) => Support::Unsupported,
+    };
+    SystemCapabilities {
+        os: std::env::consts::OS,
+        desktop: env("XDG_CURRENT_DESKTOP"),
+        portal_available: bus.portal_available,
+        global_shortcuts_portal_version: bus.global_shortcuts,
+        screen_cast_portal_version: bus.screen_cast,
+        tray_host: bus.tray_host,
+        global_shortcuts: through_portal(bus.global_shortcuts),
+        screen_capture: through_portal(bus.screen_cast),
+        tray: if bus.tray_host { Support::Native } else { Support::Unsupported },
+        session_type,
+        probe_errors,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn probe() -> SystemCapabilities {
+    SystemCapabilities {
+        os: std::env::consts::OS,
+        session_type: None,
+        desktop: None,
+        portal_available: false,
+        global_shortcuts_portal_version: None,
+        screen_cast_portal_version: None,
+        tray_host: true,
+        global_shortcuts: Support::Native,
+        screen_capture: Support::Native,
+        tray: Support::Native,
+        probe_errors: Vec::new(),
+    }
+}
+
+/// Probe the session during setup, before anything that depends on it
+pub fn init(app: &AppHandle) {
+    let capabilities = probe();
+    println!(
+        "[Platform] {} session={} desktop={} portal={} tray_host={}",
+        capabilities.os,
+        capabilities.session_type.as_deref().unwrap_or("-"),
+        capabilities.desktop.as_deref().unwrap_or("-"),
+        capabilities.portal_available,
+        capabilities.tray_host
+    );
+    for error in &capabilities.probe_errors {
+        eprintln!("[Platform] Probe failed: {}", error);
+    }
+    if let Ok(mut current) = app.state::<PlatformState>().0.lock() {
+        *current = Some(capabilities);
+    }
+}
+
+pub fn capabilities(app: &AppHandle) -> Option<SystemCapabilities> {
+    app.state::<PlatformState>().0.lock().ok()?.clone()
+}
+
+/// How `feature` is available, or an `unsupported` error saying why it isn't. Before
+/// the probe has run everything counts as native.
+pub fn require(app: &AppHandle, feature: Feature) -> Result<Support, CommandError> {
+    let Some(capabilities) = capabilities(app) else {
+        return Ok(Support::Native);
+    };
+    match capabilities.support(feature) {
+        Support::Unsupported => Err(CommandError::Unsupported {
+            message: format!("{} can't work here: {}", feature.label(), capabilities.reason(feature)),
+            feature,
+        }),
+        support => Ok(support),
+    }
+}
+
+#[tauri::command]
+pub fn get_system_capabilities(state: State<'_, PlatformState>) -> Option<SystemCapabilities> {
+    state.0.lock().ok()?.clone()
+}
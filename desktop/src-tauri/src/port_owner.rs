@@ -0,0 +1,192 @@
+// Which process listens on a TCP port, asked of the OS directly.
+//
+// Shelling out to lsof or netstat failed on minimal Linux installs without lsof and
+// on Windows locales that translate netstat's output. Linux reads the listening
+// sockets from `/proc/net/tcp{,6}` and finds the process holding the socket inode
+// among `/proc/<pid>/fd`; only processes of the same user are visible, which covers
+// our own sidecar. macOS walks process sockets through libproc, and Windows asks
+// `GetExtendedTcpTable` for the listener table with owning PIDs.
+
+/// The process listening on `port`, or `None` if nothing listens there or the
+/// owner can't be seen
+pub(crate) fn port_owner(port: u16) -> Option<u32> {
+    #[cfg(target_os = "linux")]
+    {
+        linux::port_owner(port)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        macos::port_owner(port)
+    }
+    #[cfg(windows)]
+    {
+        windows::port_owner(port)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    {
+        let _ = port;
+        None
+    }
+}
+
+/// Socket inodes listening on `port` in the contents of `/proc/net/tcp` or
+/// `/proc/net/tcp6`. Rows are `sl local_address rem_address st ... inode`, with
+/// addresses as `HEXADDR:HEXPORT` and state `0A` for LISTEN.
+#[cfg(any(target_os = "linux", test))]
+fn listening_inodes(table: &str, port: u16) -> Vec<u64> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            let (local, state, inode) = (columns.get(1)?, columns.get(3)?, columns.get(9)?);
+            let local_port = u16::from_str_radix(local.rsplit_once(':')?.1, 16).ok()?;
+            if *state != "0A" || local_port != port {
+                return None;
+            }
+            inode.parse().ok().filter(|inode| *inode != 0)
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs;
+
+    pub(super) fn port_owner(port: u16) -> Option<u32> {
+        let inodes: Vec<u64> = ["/proc/net/tcp", "/proc/net/tcp6"]
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .flat_map(|table| super::listening_inodes(&table, port))
+            .collect();
+        if inodes.is_empty() {
+            return None;
+        }
+        let sockets: Vec<String> = inodes.iter().map(|inode| format!("socket:[{}]", inode)).collect();
+        fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            // Other users' fds aren't readable; those processes are skipped
+            let fds = fs::read_dir(entry.path().join("fd")).ok()?;
+            fds.flatten()
+                .filter_map(|fd| fs::read_link(fd.path()).ok())
+                .any(|target| sockets.iter().any(|s| target.as_os_str() == s.as_str()))
+                .then_some(pid)
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use libproc::bsd_info::BSDInfo;
+    use libproc::file_info::{pidfdinfo, ListFDs, ProcFDType};
+    use libproc::net_info::{SocketFDInfo, SocketInfoKind, TcpSIState};
+    use libproc::proc_pid::{listpidinfo, pidinfo};
+    use libproc::processes::{pids_by_type, ProcFilter};
+
+    pub(super) fn port_owner(port: u16) -> Option<u32> {
+        pids_by_type(ProcFilter::All).ok()?.into_iter().find(|&pid| listens_on(pid as i32, port))
+    }
+
+    fn listens_on(pid: i32, port: u16) -> bool {
+        let Ok(info) = pidinfo::<BSDInfo>(pid, 0) else {
+            return false;
+        };
+        let Ok(fds) = listpidinfo::<ListFDs>(pid, info.pbi_nfiles as usize) else {
+            return false;
+        };
+        fds.iter()
+            .filter(|fd| matches!(ProcFDType::from(fd.proc_fdtype), ProcFDType::Socket))
+            .filter_map(|fd| pidfdinfo::<SocketFDInfo>(pid, fd.proc_fd).ok())
+            .any(|socket| {
+                if !matches!(SocketInfoKind::from(socket.psi.soi_kind), SocketInfoKind::Tcp) {
+                    return false;
+                }
+                // soi_kind says which member of the union is set
+                let tcp = unsafe { socket.psi.soi_proto.pri_tcp };
+                let local_port = u16::from_be(tcp.tcpsi_ini.insi_lport as u16);
+                matches!(TcpSIState::from(tcp.tcpsi_state), TcpSIState::Listen) && local_port == port
+            })
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use windows_sys::Win32::Foundation::{ERROR_INSUFFICIENT_BUFFER, NO_ERROR};
+    use windows_sys::Win32::NetworkManagement::IpHelper::{
+        GetExtendedTcpTable, MIB_TCP6ROW_OWNER_PID, MIB_TCP6TABLE_OWNER_PID, MIB_TCPROW_OWNER_PID,
+        MIB_TCPTABLE_OWNER_PID, TCP_TABLE_OWNER_PID_LISTENER,
+    };
+    use windows_sys::Win32::Networking::WinSock::{AF_INET, AF_INET6};
+
+    pub(super) fn port_owner(port: u16) -> Option<u32> {
+        owner_in(AF_INET as u32, port).or_else(|| owner_in(AF_INET6 as u32, port))
+    }
+
+    /// The listener table for one address family. Ports are in network byte order
+    /// in the low 16 bits of `dwLocalPort`.
+    fn owner_in(family: u32, port: u16) -> Option<u32> {
+        let mut size = 0u32;
+        // SAFETY: a null buffer only asks for the size needed
+        let status = unsafe {
+            GetExtendedTcpTable(std::ptr::null_mut(), &mut size, 0, family, TCP_TABLE_OWNER_PID_LISTENER, 0)
+        };
+        if status != ERROR_INSUFFICIENT_BUFFER {
+            return None;
+        }
+        // u64s keep the buffer aligned for the table structs
+        let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+        // SAFETY: the buffer holds `size` bytes
+        let status = unsafe {
+            GetExtendedTcpTable(buffer.as_mut_ptr().cast(), &mut size, 0, family, TCP_TABLE_OWNER_PID_LISTENER, 0)
+        };
+        if status != NO_ERROR {
+            return None;
+        }
+        let matches = |local_port: u32| u16::from_be(local_port as u16) == port;
+        // SAFETY: on success the buffer starts with the table for `family`, whose
+        // `dwNumEntries` rows follow the count
+        unsafe {
+            if family == AF_INET as u32 {
+                let table = &*(buffer.as_ptr() as *const MIB_TCPTABLE_OWNER_PID);
+                let rows: &[MIB_TCPROW_OWNER_PID] =
+                    std::slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize);
+                rows.iter().find(|row| matches(row.dwLocalPort)).map(|row| row.dwOwningPid)
+            } else {
+                let table = &*(buffer.as_ptr() as *const MIB_TCP6TABLE_OWNER_PID);
+                let rows: &[MIB_TCP6ROW_OWNER_PID] =
+                    std::slice::from_raw_parts(table.table.as_ptr(), table.dwNumEntries as usize);
+                rows.iter().find(|row| matches(row.dwLocalPort)).map(|row| row.dwOwningPid)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proc_net_tcp_rows_match_listeners_on_the_exact_port() {
+        // 2620 is 0x0A3C, 26200 is 0x6658, 12620 is 0x314C
+        let table = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0A3C 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41001 1 0 100 0 0 10 0
+   1: 00000000:6658 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41002 1 0 100 0 0 10 0
+   2: 00000000:314C 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41003 1 0 100 0 0 10 0
+   3: 0100007F:C8A2 0100007F:0A3C 01 00000000:00000000 00:00000000 00000000  1000        0 41004 1 0 20 4 30 10 -1
+   4: 0100007F:0A3C 0100007F:C8A3 06 00000000:00000000 03:00000CB4 00000000     0        0 0 3 0
+";
+        assert_eq!(listening_inodes(table, 2620), vec![41001]);
+        assert_eq!(listening_inodes(table, 26200), vec![41002]);
+        assert!(listening_inodes(table, 51362).is_empty());
+    }
+
+    #[test]
+    fn proc_net_tcp6_rows_parse_the_same_way() {
+        let table = "\
+  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000000000000:0A3C 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 52001 1 0 100 0 0 10 0
+";
+        assert_eq!(listening_inodes(table, 2620), vec![52001]);
+    }
+}
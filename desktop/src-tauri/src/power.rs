@@ -0,0 +1,204 @@
+// System suspend and resume.
+//
+// A watcher thread wakes every `TICK` and compares wall-clock time with its previous
+// run. While the machine sleeps the thread can't run, so a gap well beyond the tick
+// is a sleep, whatever the platform's monotonic clock does across suspend. The
+// watcher sees the sleep only after waking, so `power://suspend` and `power://resume`
+// are sent together, both carrying the wall-clock times of the gap.
+//
+// On resume the sidecar gets `RESUME_GRACE` before health checks count against it,
+// the idle and stall trackers restart their timers, and circuit breakers reset.
+// Sleep intervals are kept so time accounting can subtract them (`awake_ms`).
+
+use crate::{background, circuit, events, idle, stall};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+const TICK: Duration = Duration::from_secs(2);
+/// Never stretched: a late tick would be taken for a sleep
+const ACTIVITY: background::Activity = background::Activity {
+    name: "sleep_detector",
+    interval: TICK,
+    coalescable: false,
+};
+/// Extra delay beyond the tick that counts as a sleep rather than scheduling jitter
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(30);
+pub const RESUME_GRACE: Duration = Duration::from_secs(60);
+const MAX_INTERVALS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct SleepInterval {
+    /// Unix ms of the last tick before the sleep
+    pub started_at: i64,
+    /// Unix ms of the first tick after it
+    pub ended_at: i64,
+}
+
+impl SleepInterval {
+    pub fn duration_ms(&self) -> u64 {
+        (self.ended_at - self.started_at).max(0) as u64
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    intervals: VecDeque<SleepInterval>,
+    resumed_at: Option<Instant>,
+}
+
+#[derive(Default)]
+pub struct PowerState(Mutex<Inner>);
+
+/// The sleep between two ticks, if the wall clock moved much further than `tick`.
+/// A clock set backwards is not a sleep.
+fn detect_sleep(previous_ms: i64, now_ms: i64, tick: Duration) -> Option<SleepInterval> {
+    let gap = now_ms - previous_ms;
+    let limit = (tick + SLEEP_THRESHOLD).as_millis() as i64;
+    (gap > limit).then_some(SleepInterval {
+        started_at: previous_ms,
+        ended_at: now_ms,
+    })
+}
+
+/// Wall-clock milliseconds between `start_ms` and `end_ms` with the sleeps removed
+pub fn awake_ms(start_ms: i64, end_ms: i64, sleeps: &[SleepInterval]) -> u64 {
+    if end_ms <= start_ms {
+        return 0;
+    }
+    let slept: i64 = sleeps
+        .iter()
+        .map(|s| (s.ended_at.min(end_ms) - s.started_at.max(start_ms)).max(0))
+        .sum();
+    (end_ms - start_ms - slept).max(0) as u64
+}
+
+/// Time left in the post-resume grace period; zero when not recently resumed
+pub fn grace_remaining(app: &AppHandle) -> Duration {
+    app.try_state::<PowerState>()
+        .and_then(|state| state.0.lock().ok().and_then(|inner| inner.resumed_at))
+        .map(|at| RESUME_GRACE.saturating_sub(at.elapsed()))
+        .unwrap_or_default()
+}
+
+fn on_resume(app: &AppHandle, interval: SleepInterval) {
+    println!("[Power] Resumed after sleeping {}s", interval.duration_ms() / 1000);
+    if let Ok(mut inner) = app.state::<PowerState>().0.lock() {
+        inner.intervals.push_back(interval);
+        while inner.intervals.len() > MAX_INTERVALS {
+            inner.intervals.pop_front();
+        }
+        inner.resumed_at = Some(Instant::now());
+    }
+    idle::note_resume(app);
+    stall::note_resume(app);
+    circuit::reset_all(app);
+
+    events::emit_typed(
+        app,
+        events::PowerSuspend {
+            started_at: interval.started_at,
+        },
+    );
+    events::emit_typed(
+        app,
+        events::PowerResume {
+            interval,
+            slept_ms: interval.duration_ms(),
+            grace_ms: RESUME_GRACE.as_millis() as u64,
+        },
+    );
+}
+
+/// Start watching for sleeps
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let mut previous = chrono::Utc::now().timestamp_millis();
+        loop {
+            let planned = background::sleep(&app, &ACTIVITY);
+            let now = chrono::Utc::now().timestamp_millis();
+            if let Some(interval) = detect_sleep(previous, now, planned) {
+                on_resume(&app, interval);
+            }
+            previous = now;
+        }
+    });
+}
+
+/// Sleeps that ended at or after `since_ms` (Unix ms), oldest first
+#[tauri::command]
+pub fn get_sleep_intervals(state: State<'_, PowerState>, since_ms: Option<i64>) -> Vec<SleepInterval> {
+    let since = since_ms.unwrap_or(i64::MIN);
+    state
+        .0
+        .lock()
+        .map(|inner| inner.intervals.iter().filter(|s| s.ended_at >= since).copied().collect())
+        .unwrap_or_default()
+}
+
+/// Milliseconds the machine was awake between two Unix ms timestamps
+#[tauri::command]
+pub fn get_awake_ms(state: State<'_, PowerState>, start_ms: i64, end_ms: i64) -> u64 {
+    let sleeps: Vec<SleepInterval> = state
+        .0
+        .lock()
+        .map(|inner| inner.intervals.iter().copied().collect())
+        .unwrap_or_default();
+    awake_ms(start_ms, end_ms, &sleeps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T0: i64 = 1_700_000_000_000;
+
+    #[test]
+    fn normal_ticks_are_not_sleeps() {
+        assert_eq!(detect_sleep(T0, T0 + 2_000, TICK), None);
+        // A busy machine running the thread late is still not a sleep
+        assert_eq!(detect_sleep(T0, T0 + 20_000, TICK), None);
+    }
+
+    #[test]
+    fn clock_jump_is_a_sleep() {
+        let jumped = T0 + 45 * 60 * 1000;
+        assert_eq!(
+            detect_sleep(T0, jumped, TICK),
+            Some(SleepInterval {
+                started_at: T0,
+                ended_at: jumped
+            })
+        );
+    }
+
+    #[test]
+    fn clock_set_backwards_is_not_a_sleep() {
+        assert_eq!(detect_sleep(T0, T0 - 3_600_000, TICK), None);
+    }
+
+    #[test]
+    fn awake_time_excludes_sleeps_across_the_gap() {
+        let sleeps = [
+            SleepInterval {
+                started_at: T0 + 10_000,
+                ended_at: T0 + 70_000,
+            },
+            SleepInterval {
+                started_at: T0 + 100_000,
+                ended_at: T0 + 400_000,
+            },
+        ];
+        // Fully containing the first sleep and half of the second
+        assert_eq!(awake_ms(T0, T0 + 250_000, &sleeps), 250_000 - 60_000 - 150_000);
+        // Entirely inside a sleep
+        assert_eq!(awake_ms(T0 + 20_000, T0 + 30_000, &sleeps), 0);
+        // No overlap
+        assert_eq!(awake_ms(T0 + 70_000, T0 + 100_000, &sleeps), 30_000);
+        assert_eq!(awake_ms(T0 + 5, T0, &sleeps), 0);
+    }
+}
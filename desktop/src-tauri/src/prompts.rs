@@ -0,0 +1,221 @@
+// Oversized task prompts.
+//
+// A prompt longer than `prompt_inline_limit` bytes is kept whole in the
+// `attachments` table (kind `prompt`), and `tasks.prompt` holds only a preview, so
+// list queries that select the column stay cheap. `prompt_overflow_attachment_id`
+// links the two; `get_full_prompt` and `FULL_PROMPT_SQL` reassemble the text.
+// `attachments_fts` indexes the overflow text, which is how search still finds it.
+//
+// Raising the limit only affects prompts stored afterwards; prompts already moved
+// out stay where they are.
+
+use crate::{db, jobs, settings};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::json;
+use tauri::AppHandle;
+
+const INLINE_LIMIT_KEY: &str = "prompt_inline_limit";
+const DEFAULT_INLINE_LIMIT: usize = 32 * 1024;
+/// Below this a preview would save next to nothing
+const MIN_INLINE_LIMIT: usize = 1024;
+const PREVIEW_CHARS: usize = 500;
+const BACKFILL_BATCH: i64 = 50;
+const PROMPT_ATTACHMENT: &str = "prompt";
+
+/// The full prompt of the `tasks` row in scope, for use in a SELECT list
+pub(crate) const FULL_PROMPT_SQL: &str = "COALESCE((SELECT content FROM attachments
+                                                    WHERE id = tasks.prompt_overflow_attachment_id), tasks.prompt)";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptStorage {
+    pub inline_limit: usize,
+    pub overflowed_tasks: i64,
+    pub oversized_inline_tasks: i64,
+}
+
+pub(crate) fn inline_limit(conn: &Connection) -> Result<usize, String> {
+    settings::get_or(conn, INLINE_LIMIT_KEY, DEFAULT_INLINE_LIMIT)
+}
+
+/// The first `PREVIEW_CHARS` characters, marked when something was cut
+pub(crate) fn preview(prompt: &str) -> String {
+    match prompt.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", &prompt[..end]),
+        None => prompt.to_string(),
+    }
+}
+
+/// What goes into `tasks.prompt` for `prompt`, and whether the full text has to be
+/// stored with `attach_overflow` once the row is inserted
+pub(crate) fn split(conn: &Connection, prompt: &str) -> Result<(String, bool), String> {
+    if prompt.len() > inline_limit(conn)? {
+        Ok((preview(prompt), true))
+    } else {
+        Ok((prompt.to_string(), false))
+    }
+}
+
+/// Store `prompt` as the overflow of `task_id` and point the task at it
+pub(crate) fn attach_overflow(conn: &Connection, task_id: &str, prompt: &str) -> Result<i64, String> {
+    conn.execute(
+        "INSERT INTO attachments (task_id, kind, content, size) VALUES (?1, ?2, ?3, ?4)",
+        params![task_id, PROMPT_ATTACHMENT, prompt, prompt.len() as i64],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+    conn.execute(
+        "UPDATE tasks SET prompt = ?1, prompt_overflow_attachment_id = ?2 WHERE id = ?3",
+        params![preview(prompt), id, task_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(id)
+}
+
+pub(crate) fn full_prompt(conn: &Connection, task_id: &str) -> Result<String, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM tasks WHERE id = ?1", FULL_PROMPT_SQL),
+        [task_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Task '{}' not found", task_id))
+}
+
+/// Move the prompts above the limit that are still stored inline
+fn backfill(ctx: &jobs::JobContext) -> Result<u64, String> {
+    let conn = db::open(ctx.app())?;
+    let limit = inline_limit(&conn)? as i64;
+    let total: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM tasks WHERE prompt_overflow_attachment_id IS NULL AND length(CAST(prompt AS BLOB)) > ?1",
+            [limit],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    let mut moved = 0u64;
+    loop {
+        if ctx.is_cancelled() {
+            return Err("Cancelled".to_string());
+        }
+        let batch: Vec<(String, String)> = {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id, prompt FROM tasks
+                     WHERE prompt_overflow_attachment_id IS NULL AND length(CAST(prompt AS BLOB)) > ?1
+                     LIMIT ?2",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map(params![limit, BACKFILL_BATCH], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|e| e.to_string())?;
+            rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+        };
+        if batch.is_empty() {
+            break;
+        }
+        let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+        for (task_id, prompt) in &batch {
+            attach_overflow(&tx, task_id, prompt)?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        moved += batch.len() as u64;
+        ctx.progress(moved, total as u64, None);
+    }
+    if moved > 0 {
+        println!("[Prompts] Moved {} oversized prompt(s) out of the tasks table", moved);
+    }
+    Ok(moved)
+}
+
+/// The whole prompt of a task, including any part stored outside the row
+#[tauri::command]
+pub async fn get_full_prompt(app: AppHandle, task_id: String) -> Result<String, String> {
+    db::with_conn(&app, move |conn| full_prompt(conn, &task_id)).await
+}
+
+#[tauri::command]
+pub async fn get_prompt_storage(app: AppHandle) -> Result<PromptStorage, String> {
+    db::with_conn(&app, |conn| {
+        let limit = inline_limit(conn)?;
+        let (overflowed, oversized) = conn
+            .query_row(
+                "SELECT COUNT(prompt_overflow_attachment_id),
+                        COUNT(*) FILTER (WHERE prompt_overflow_attachment_id IS NULL
+                                         AND length(CAST(prompt AS BLOB)) > ?1)
+                 FROM tasks",
+                [limit as i64],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(PromptStorage {
+            inline_limit: limit,
+            overflowed_tasks: overflowed,
+            oversized_inline_tasks: oversized,
+        })
+    })
+    .await
+}
+
+/// Set the size in bytes above which new prompts are stored outside the tasks table
+#[tauri::command]
+pub async fn set_prompt_inline_limit(app: AppHandle, bytes: usize) -> Result<(), String> {
+    if bytes < MIN_INLINE_LIMIT {
+        return Err(format!("The inline prompt limit must be at least {} bytes", MIN_INLINE_LIMIT));
+    }
+    db::with_conn(&app, move |conn| settings::set(conn, INLINE_LIMIT_KEY, &bytes)).await
+}
+
+/// Move existing prompts above the limit out of the tasks table as a background job
+#[tauri::command]
+pub fn backfill_prompt_overflow(app: AppHandle) -> u64 {
+    jobs::spawn(&app, "backfill_prompt_overflow", |ctx| {
+        let moved = backfill(ctx)?;
+        Ok(json!({ "moved": moved }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch("PRAGMA foreign_keys = ON").unwrap();
+        conn
+    }
+
+    #[test]
+    fn preview_cuts_on_characters() {
+        let long = "é".repeat(PREVIEW_CHARS + 10);
+        let cut = preview(&long);
+        assert_eq!(cut.chars().count(), PREVIEW_CHARS + 1);
+        assert!(cut.ends_with('…'));
+        assert_eq!(preview("short"), "short");
+    }
+
+    #[test]
+    fn overflow_round_trips_and_is_searchable() {
+        let conn = db();
+        let prompt = format!("{} needle", "word ".repeat(20_000));
+        let (inline, overflow) = split(&conn, &prompt).unwrap();
+        assert!(overflow);
+        conn.execute("INSERT INTO tasks (id, prompt) VALUES ('t1', ?1)", [&inline]).unwrap();
+        attach_overflow(&conn, "t1", &prompt).unwrap();
+
+        let stored: String = conn.query_row("SELECT prompt FROM tasks WHERE id = 't1'", [], |r| r.get(0)).unwrap();
+        assert_eq!(stored, preview(&prompt));
+        assert_eq!(full_prompt(&conn, "t1").unwrap(), prompt);
+        let hits: i64 = conn
+            .query_row("SELECT COUNT(*) FROM attachments_fts WHERE attachments_fts MATCH 'needle'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(hits, 1);
+
+        conn.execute("DELETE FROM tasks WHERE id = 't1'", []).unwrap();
+        let hits: i64 = conn
+            .query_row("SELECT COUNT(*) FROM attachments_fts WHERE attachments_fts MATCH 'needle'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(hits, 0);
+    }
+}
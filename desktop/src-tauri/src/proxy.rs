@@ -0,0 +1,170 @@
+// Requests to the API, routed through Rust so they share one circuit breaker per
+// endpoint (see `circuit`). Background callers such as title generation or digests
+// use this so a failing route fails fast instead of being retried by each of them.
+// It is also where API traffic is recorded to and replayed from cassettes. A remote
+// API must present its pinned certificate (see `api_config`) before anything is sent.
+
+use crate::cassette::{self, Exchange};
+use crate::circuit::{self, BreakerConfig, CallFailure};
+use crate::error::CommandError;
+use crate::api_config::{self, ApiConfig};
+use crate::db;
+use rusqlite::Connection;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Breaker key for a request path: the first two segments, so per-session paths
+/// like `/agent/stop/<id>` share a breaker
+fn endpoint(path: &str) -> String {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).take(2).collect();
+    format!("/{}", segments.join("/"))
+}
+
+fn send(url: &str, method: &str, body: Option<&Value>) -> Result<Exchange, CallFailure> {
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    let request = agent.request(method, url);
+    let response = match body {
+        Some(body) => request.send_json(body),
+        None => request.call(),
+    };
+    let response = match response {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(e) => return Err(CallFailure::Endpoint(e.to_string())),
+    };
+    let status = response.status();
+    let names = response.headers_names();
+    let headers = cassette::stable_headers(
+        names
+            .iter()
+            .filter_map(|name| response.header(name).map(|value| (name.as_str(), value))),
+    );
+    let text = response
+        .into_string()
+        .map_err(|e| CallFailure::Endpoint(e.to_string()))?;
+    Ok(Exchange {
+        status,
+        headers,
+        body: serde_json::from_str(&text).unwrap_or(Value::String(text)),
+    })
+}
+
+/// The response body, or the failure an error status stands for
+fn outcome(url: &str, exchange: Exchange) -> Result<Value, CallFailure> {
+    if exchange.status < 400 {
+        return Ok(exchange.body);
+    }
+    let detail = match exchange.body {
+        Value::String(text) => text,
+        other => other.to_string(),
+    };
+    let message = format!("{} returned {}: {}", url, exchange.status, detail.trim());
+    Err(if exchange.status >= 500 {
+        CallFailure::Endpoint(message)
+    } else {
+        CallFailure::Caller(message)
+    })
+}
+
+/// The URL for `path` and the breaker settings to send it under. `verify` checks a
+/// remote API against its certificate pin and fails with `pin_mismatch` if it changed.
+fn prepare(
+    conn: &Connection,
+    path: &str,
+    verify: impl FnOnce(&Connection) -> Result<ApiConfig, CommandError>,
+) -> Result<(String, BreakerConfig), CommandError> {
+    let base_url = verify(conn)?.base_url;
+    let config = BreakerConfig::load(conn)?;
+    Ok((format!("{}{}", base_url.trim_end_matches('/'), path), config))
+}
+
+/// Call the API at `path` (e.g. `/files/readdir`) and return its JSON response, or
+/// the body as a string when it isn't JSON. Fails with `circuit_open` while the
+/// endpoint's circuit is open, and with `pin_mismatch` if a remote API's certificate
+/// no longer matches its pin.
+#[tauri::command]
+pub async fn sidecar_request(
+    app: AppHandle,
+    method: String,
+    path: String,
+    body: Option<Value>,
+) -> Result<Value, CommandError> {
+    let method = method.to_ascii_uppercase();
+    if !matches!(method.as_str(), "GET" | "POST" | "PUT" | "PATCH" | "DELETE") {
+        return Err(format!("Unsupported method '{}'", method).into());
+    }
+    if !path.starts_with('/') {
+        return Err("Path must start with '/'".into());
+    }
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = db::open(&handle)?;
+        let (url, config) = prepare(&conn, &path, |conn| api_config::verify(&handle, conn))?;
+        drop(conn);
+        if let Some(replayed) = cassette::replay(&handle, &method, &path, body.as_ref()) {
+            return replayed.and_then(|exchange| outcome(&url, exchange)).map_err(CallFailure::into_error);
+        }
+        circuit::guard(&handle, &config, &endpoint(&path), || {
+            let sent_at = Instant::now();
+            let exchange = send(&url, &method, body.as_ref())?;
+            cassette::record(&handle, &method, &path, body.as_ref(), &exchange, sent_at);
+            outcome(&url, exchange)
+        })
+    })
+    .await
+    .map_err(|e| CommandError::from(e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_config::{ApiPin, ALLOW_REMOTE_API_KEY, API_PIN_KEY, EXTERNAL_API_URL_KEY};
+    use crate::settings;
+
+    const REMOTE: &str = "https://api.example.com/";
+
+    fn remote_db(pinned: &str) -> Connection {
+        let conn = crate::migrations::test_db();
+        settings::set(&conn, EXTERNAL_API_URL_KEY, &REMOTE).unwrap();
+        settings::set(&conn, ALLOW_REMOTE_API_KEY, &true).unwrap();
+        let pin = ApiPin {
+            url: REMOTE.to_string(),
+            fingerprint: pinned.to_string(),
+            identity: None,
+            established_at: "2024-01-01T00:00:00+00:00".to_string(),
+        };
+        settings::set(&conn, API_PIN_KEY, &pin).unwrap();
+        conn
+    }
+
+    #[test]
+    fn a_mismatched_pin_blocks_the_request() {
+        let conn = remote_db("AA:AA");
+        let mut mismatches = Vec::new();
+        let result = prepare(&conn, "/agent/run", |conn| {
+            api_config::verify_with(conn, |_| Ok("BB:BB".to_string()), |m| mismatches.push(m))
+        });
+        match result {
+            Err(CommandError::PinMismatch { expected, actual, .. }) => {
+                assert_eq!(expected, "AA:AA");
+                assert_eq!(actual, "BB:BB");
+            }
+            other => panic!("expected pin_mismatch, got {:?}", other),
+        }
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].url, REMOTE);
+    }
+
+    #[test]
+    fn a_matching_pin_lets_the_request_through() {
+        let conn = remote_db("AA:AA");
+        let (url, _) = prepare(&conn, "/agent/run", |conn| {
+            api_config::verify_with(conn, |_| Ok("AA:AA".to_string()), |_| panic!("no mismatch expected"))
+        })
+        .unwrap();
+        assert_eq!(url, "https://api.example.com/agent/run");
+    }
+}
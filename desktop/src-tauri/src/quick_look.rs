@@ -0,0 +1,162 @@
+// Native file preview. On macOS this is Quick Look through `qlmanage -p`, which
+// handles formats the in-app viewer never will (Keynote, HEIC, 3D models). Other
+// platforms open the file with its default app instead.
+//
+// The preview is a separate process, so it never shows or focuses our window and
+// leaves the close-to-tray state alone. It is closed when another preview starts,
+// when the file disappears, and when the app exits.
+
+use crate::error::CommandError;
+use crate::{db, file_scan, files};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewMethod {
+    QuickLook,
+    OpenFile,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QuickLookSupport {
+    pub supported: bool,
+    pub method: Option<PreviewMethod>,
+    /// Why there's no preview, when there isn't one
+    pub reason: Option<String>,
+}
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+struct Preview {
+    file_id: i64,
+    child: std::process::Child,
+}
+
+/// The open Quick Look process, if any
+#[derive(Default)]
+pub struct QuickLookState(Mutex<Option<Preview>>);
+
+fn platform_method() -> PreviewMethod {
+    if cfg!(target_os = "macos") {
+        PreviewMethod::QuickLook
+    } else {
+        PreviewMethod::OpenFile
+    }
+}
+
+/// Resolve a file row to an existing path inside the allowed folders
+async fn resolve(app: &AppHandle, file_id: i64) -> Result<PathBuf, String> {
+    let file = db::with_conn(app, move |conn| files::load_file(conn, file_id)).await?;
+    let path = files::ensure_allowed(app, std::path::Path::new(&file.path))?;
+    if !path.is_file() {
+        return Err(format!("'{}' no longer exists", file.path));
+    }
+    Ok(path)
+}
+
+/// Close the open preview, if any
+pub fn close_preview(app: &AppHandle) {
+    let Some(state) = app.try_state::<QuickLookState>() else { return };
+    let preview = state.0.lock().ok().and_then(|mut guard| guard.take());
+    if let Some(mut preview) = preview {
+        let _ = preview.child.kill();
+        let _ = preview.child.wait();
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn open_quick_look(app: &AppHandle, file_id: i64, path: PathBuf) -> Result<(), String> {
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    close_preview(app);
+    let child = Command::new("qlmanage")
+        .arg("-p")
+        .arg(&path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start Quick Look: {}", e))?;
+    let state = app.state::<QuickLookState>();
+    *state.0.lock().map_err(|e| e.to_string())? = Some(Preview { file_id, child });
+
+    // Close the panel if the file goes away underneath it; stop once it's closed
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_millis(500));
+        let state = app.state::<QuickLookState>();
+        let Ok(mut guard) = state.0.lock() else { return };
+        let Some(preview) = guard.as_mut().filter(|p| p.file_id == file_id) else { return };
+        let exited = !matches!(preview.child.try_wait(), Ok(None));
+        if exited {
+            *guard = None;
+            return;
+        }
+        if !path.exists() {
+            let _ = preview.child.kill();
+            let _ = preview.child.wait();
+            *guard = None;
+            return;
+        }
+    });
+    Ok(())
+}
+
+/// Whether a native preview is available for the file on this platform
+#[tauri::command]
+pub async fn can_quick_look(app: AppHandle, file_id: i64) -> Result<QuickLookSupport, String> {
+    Ok(match resolve(&app, file_id).await {
+        Ok(_) => QuickLookSupport {
+            supported: true,
+            method: Some(platform_method()),
+            reason: None,
+        },
+        Err(reason) => QuickLookSupport {
+            supported: false,
+            method: None,
+            reason: Some(reason),
+        },
+    })
+}
+
+async fn ensure_openable(app: &AppHandle, file_id: i64) -> Result<(), CommandError> {
+    db::with_conn(app, move |conn| Ok(file_scan::ensure_openable(conn, file_id))).await?
+}
+
+fn open_with_default_app(app: &AppHandle, path: PathBuf) -> Result<(), String> {
+    use tauri_plugin_opener::OpenerExt;
+    app.opener()
+        .open_path(path.to_string_lossy(), None::<&str>)
+        .map_err(|e| e.to_string())
+}
+
+/// Open a file with its default app. Fails with `scan_required` while
+/// `require_scan_before_open` is on and the file has no clean scan.
+#[tauri::command]
+pub async fn open_file(app: AppHandle, file_id: i64) -> Result<(), CommandError> {
+    let path = resolve(&app, file_id).await?;
+    ensure_openable(&app, file_id).await?;
+    Ok(open_with_default_app(&app, path)?)
+}
+
+/// Preview a file natively: Quick Look on macOS, the default app elsewhere. Subject
+/// to the same scan requirement as `open_file`.
+#[tauri::command]
+pub async fn quick_look_file(app: AppHandle, file_id: i64) -> Result<PreviewMethod, CommandError> {
+    let path = resolve(&app, file_id).await?;
+    ensure_openable(&app, file_id).await?;
+
+    #[cfg(target_os = "macos")]
+    {
+        open_quick_look(&app, file_id, path)?;
+        Ok(PreviewMethod::QuickLook)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        open_with_default_app(&app, path)?;
+        Ok(PreviewMethod::OpenFile)
+    }
+}
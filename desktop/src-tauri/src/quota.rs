@@ -0,0 +1,413 @@
+// Global spending quotas across all tasks.
+//
+// Task costs are written by the frontend straight into `tasks.cost`, a running total
+// the sidecar may revise. Triggers from migration 19 record every change to it as a
+// delta in `cost_ledger`, stamped with the time of the write, so the ledger is the
+// spend history whoever wrote the cost, and it survives restarts. A late or revised
+// cost arrives as one more delta (negative when corrected downwards), and every check
+// sums the period from the ledger again instead of patching a running total, so a
+// correction is reflected on the next check.
+//
+// Periods follow local time: days start at local midnight and weeks on Monday.
+// Reaching `WARN_FRACTION` of a limit, and then the limit itself, emits
+// `quota://warning`. Over the limit, soft_stop sets `quota_blocked`, which the guard
+// trigger on `tasks` reads to reject new tasks from any writer; hard_stop also asks
+// the frontend to stop running tasks through `quota://stop-tasks`.
+
+use crate::error::CommandError;
+use crate::{background, db, events, settings};
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDate, NaiveTime, TimeZone};
+use rusqlite::Connection;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+const DAILY_KEY: &str = "quota_daily_usd";
+const WEEKLY_KEY: &str = "quota_weekly_usd";
+const MODE_KEY: &str = "quota_mode";
+/// Read by the guard trigger from migration 19
+const BLOCKED_KEY: &str = "quota_blocked";
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "quota",
+    interval: CHECK_INTERVAL,
+    coalescable: true,
+};
+const WARN_FRACTION: f64 = 0.8;
+/// Window the burn rate is measured over
+const BURN_WINDOW_MS: i64 = 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaMode {
+    /// Only emit warnings
+    #[default]
+    Warn,
+    /// Reject new tasks; running ones finish
+    SoftStop,
+    /// Reject new tasks and stop running ones
+    HardStop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QuotaPeriod {
+    Day,
+    Week,
+}
+
+impl QuotaPeriod {
+    const ALL: [QuotaPeriod; 2] = [QuotaPeriod::Day, QuotaPeriod::Week];
+
+    fn name(self) -> &'static str {
+        match self {
+            QuotaPeriod::Day => "day",
+            QuotaPeriod::Week => "week",
+        }
+    }
+
+    fn limit_key(self) -> &'static str {
+        match self {
+            QuotaPeriod::Day => DAILY_KEY,
+            QuotaPeriod::Week => WEEKLY_KEY,
+        }
+    }
+
+    /// Start and end (Unix ms) of the period containing `now`, in `now`'s time zone
+    fn bounds<Tz: TimeZone>(self, now: &DateTime<Tz>) -> (i64, i64) {
+        let today = now.date_naive();
+        let (first, days) = match self {
+            QuotaPeriod::Day => (today, 1),
+            QuotaPeriod::Week => (
+                today - ChronoDuration::days(now.weekday().num_days_from_monday() as i64),
+                7,
+            ),
+        };
+        let tz = now.timezone();
+        (
+            midnight(&tz, first),
+            midnight(&tz, first + ChronoDuration::days(days)),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningLevel {
+    /// Past `WARN_FRACTION` of the limit
+    Approaching,
+    Exceeded,
+}
+
+/// Unix ms of the start of `date`. Where a DST change skips midnight, the first
+/// local time of the day that exists.
+pub(crate) fn midnight<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> i64 {
+    let mut time = date.and_time(NaiveTime::MIN);
+    for _ in 0..96 {
+        if let Some(at) = tz.from_local_datetime(&time).earliest() {
+            return at.timestamp_millis();
+        }
+        time += ChronoDuration::minutes(15);
+    }
+    date.and_time(NaiveTime::MIN).and_utc().timestamp_millis()
+}
+
+/// When spending at `rate_per_ms` reaches `limit`: `now_ms` if it already has, `None`
+/// if not before the period ends
+fn project_exhaustion(used: f64, limit: f64, rate_per_ms: f64, now_ms: i64, end_ms: i64) -> Option<i64> {
+    if used >= limit {
+        return Some(now_ms);
+    }
+    if rate_per_ms <= 0.0 {
+        return None;
+    }
+    let at = now_ms.saturating_add(((limit - used) / rate_per_ms).ceil() as i64);
+    (at < end_ms).then_some(at)
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PeriodUsage {
+    pub period: QuotaPeriod,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub used_usd: f64,
+    /// `None` when no limit is set for this period
+    pub limit_usd: Option<f64>,
+    /// When the limit is reached at the current burn rate, if within this period
+    pub exhausts_at: Option<i64>,
+}
+
+impl PeriodUsage {
+    fn level(&self) -> Option<WarningLevel> {
+        let limit = self.limit_usd?;
+        if self.used_usd >= limit {
+            Some(WarningLevel::Exceeded)
+        } else if self.used_usd >= limit * WARN_FRACTION {
+            Some(WarningLevel::Approaching)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct QuotaStatus {
+    pub mode: QuotaMode,
+    pub periods: Vec<PeriodUsage>,
+    /// Spend over the last hour
+    pub burn_rate_usd_per_hour: f64,
+    pub exceeded: bool,
+    /// New tasks are rejected
+    pub blocked: bool,
+    pub checked_at: i64,
+}
+
+#[derive(Default)]
+struct Inner {
+    last: Option<QuotaStatus>,
+    /// Warnings already sent, by period, period start and level
+    warned: HashSet<(QuotaPeriod, i64, WarningLevel)>,
+    /// Running tasks already asked to stop
+    stopped: HashSet<String>,
+}
+
+#[derive(Default)]
+pub struct QuotaState(Mutex<Inner>);
+
+fn spent_since(conn: &Connection, since_ms: i64) -> Result<f64, String> {
+    conn.query_row(
+        "SELECT COALESCE(SUM(delta), 0) FROM cost_ledger WHERE recorded_at >= ?1",
+        [since_ms],
+        |row| row.get::<_, f64>(0),
+    )
+    .map(|spent| spent.max(0.0))
+    .map_err(|e| e.to_string())
+}
+
+fn measure<Tz: TimeZone>(conn: &Connection, now: DateTime<Tz>) -> Result<QuotaStatus, String> {
+    let mode = settings::get_or(conn, MODE_KEY, QuotaMode::Warn)?;
+    let now_ms = now.timestamp_millis();
+    let rate_per_ms = spent_since(conn, now_ms - BURN_WINDOW_MS)? / BURN_WINDOW_MS as f64;
+
+    let mut periods = Vec::new();
+    for period in QuotaPeriod::ALL {
+        let (start_ms, end_ms) = period.bounds(&now);
+        let used_usd = spent_since(conn, start_ms)?;
+        let limit_usd = settings::get::<f64>(conn, period.limit_key())?.filter(|l| *l > 0.0);
+        periods.push(PeriodUsage {
+            period,
+            start_ms,
+            end_ms,
+            used_usd,
+            limit_usd,
+            exhausts_at: limit_usd.and_then(|limit| project_exhaustion(used_usd, limit, rate_per_ms, now_ms, end_ms)),
+        });
+    }
+    let exceeded = periods.iter().any(|p| p.level() == Some(WarningLevel::Exceeded));
+    Ok(QuotaStatus {
+        mode,
+        periods,
+        burn_rate_usd_per_hour: rate_per_ms * BURN_WINDOW_MS as f64,
+        exceeded,
+        blocked: exceeded && mode != QuotaMode::Warn,
+        checked_at: now_ms,
+    })
+}
+
+/// Measure usage, update the block flag, and send warnings and stop requests that
+/// haven't been sent yet
+pub(crate) fn check(app: &AppHandle, conn: &Connection) -> Result<QuotaStatus, String> {
+    let status = measure(conn, Local::now())?;
+    if status.blocked != settings::get_or(conn, BLOCKED_KEY, false)? {
+        settings::set(conn, BLOCKED_KEY, &status.blocked)?;
+        println!(
+            "[Quota] New tasks {}",
+            if status.blocked { "blocked: spending quota reached" } else { "allowed again" }
+        );
+    }
+    let running: Vec<String> = if status.exceeded && status.mode == QuotaMode::HardStop {
+        let mut stmt = conn
+            .prepare("SELECT id FROM tasks WHERE status = 'running'")
+            .map_err(|e| e.to_string())?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+        ids
+    } else {
+        Vec::new()
+    };
+
+    let state = app.state::<QuotaState>();
+    let mut inner = state.0.lock().map_err(|e| e.to_string())?;
+    let current: HashSet<(QuotaPeriod, i64)> = status.periods.iter().map(|p| (p.period, p.start_ms)).collect();
+    inner.warned.retain(|(period, start, _)| current.contains(&(*period, *start)));
+    for usage in &status.periods {
+        let (Some(level), Some(limit_usd)) = (usage.level(), usage.limit_usd) else {
+            continue;
+        };
+        if inner.warned.insert((usage.period, usage.start_ms, level)) {
+            eprintln!(
+                "[Quota] Spend this {} is ${:.2} of ${:.2} ({:?})",
+                usage.period.name(),
+                usage.used_usd, limit_usd, level
+            );
+            events::emit_typed(
+                app,
+                events::QuotaWarning {
+                    period: usage.period,
+                    level,
+                    used_usd: usage.used_usd,
+                    limit_usd,
+                    mode: status.mode,
+                },
+            );
+        }
+    }
+
+    inner.stopped.retain(|id| running.contains(id));
+    let to_stop: Vec<String> = running
+        .into_iter()
+        .filter(|id| inner.stopped.insert(id.clone()))
+        .collect();
+    if !to_stop.is_empty() {
+        println!("[Quota] Asking {} running task(s) to stop", to_stop.len());
+        events::emit_typed(app, events::QuotaStopTasks { task_ids: to_stop });
+    }
+    inner.last = Some(status.clone());
+    Ok(status)
+}
+
+/// The error for a new task while the quota blocks it, measured now so a limit that
+/// was just raised applies immediately
+pub(crate) fn blocking_error(conn: &Connection) -> Result<Option<CommandError>, String> {
+    let status = measure(conn, Local::now())?;
+    if !status.blocked {
+        return Ok(None);
+    }
+    Ok(status
+        .periods
+        .into_iter()
+        .find(|p| p.level() == Some(WarningLevel::Exceeded))
+        .map(|p| {
+            let limit_usd = p.limit_usd.unwrap_or_default();
+            CommandError::QuotaExceeded {
+                message: format!(
+                    "Spending quota reached: ${:.2} of ${:.2} this {}",
+                    p.used_usd,
+                    limit_usd,
+                    p.period.name()
+                ),
+                period: p.period,
+                used_usd: p.used_usd,
+                limit_usd,
+            }
+        }))
+}
+
+/// Start the periodic quota check
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        let result = db::open(&app).and_then(|conn| check(&app, &conn));
+        if let Err(e) = result {
+            eprintln!("[Quota] Check failed: {}", e);
+        }
+        background::sleep(&app, &ACTIVITY);
+    });
+}
+
+/// Usage against the limits as of the last check (at most a few seconds old)
+#[tauri::command]
+pub async fn get_quota_status(app: AppHandle, state: State<'_, QuotaState>) -> Result<QuotaStatus, String> {
+    let cached = state.0.lock().map_err(|e| e.to_string())?.last.clone();
+    if let Some(status) = cached {
+        return Ok(status);
+    }
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| check(&handle, conn)).await
+}
+
+/// Set the daily and weekly limits in USD (`None` or 0 removes one) and what
+/// happens when one is reached. Takes effect immediately.
+#[tauri::command]
+pub async fn set_quota(
+    app: AppHandle,
+    daily_usd: Option<f64>,
+    weekly_usd: Option<f64>,
+    mode: QuotaMode,
+) -> Result<QuotaStatus, String> {
+    for limit in [daily_usd, weekly_usd].into_iter().flatten() {
+        if !limit.is_finite() || limit < 0.0 {
+            return Err(format!("Invalid quota {}", limit));
+        }
+    }
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        for (key, limit) in [(DAILY_KEY, daily_usd), (WEEKLY_KEY, weekly_usd)] {
+            match limit.filter(|l| *l > 0.0) {
+                Some(limit) => settings::set(conn, key, &limit)?,
+                None => settings::remove(conn, key)?,
+            }
+        }
+        settings::set(conn, MODE_KEY, &mode)?;
+        check(&handle, conn)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn at(offset_hours: i32, rfc3339: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&FixedOffset::east_opt(offset_hours * 3600).unwrap())
+    }
+
+    fn ms(rfc3339: &str) -> i64 {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().timestamp_millis()
+    }
+
+    #[test]
+    fn periods_follow_local_midnight() {
+        // 23:30 on Wednesday 2026-10-14 in UTC+2 is still that Wednesday locally
+        let now = at(2, "2026-10-14T21:30:00Z");
+        assert_eq!(
+            QuotaPeriod::Day.bounds(&now),
+            (ms("2026-10-14T00:00:00+02:00"), ms("2026-10-15T00:00:00+02:00"))
+        );
+        assert_eq!(
+            QuotaPeriod::Week.bounds(&now),
+            (ms("2026-10-12T00:00:00+02:00"), ms("2026-10-19T00:00:00+02:00"))
+        );
+        // The same instant is already Thursday in UTC+9
+        let now = at(9, "2026-10-14T21:30:00Z");
+        assert_eq!(QuotaPeriod::Day.bounds(&now).0, ms("2026-10-15T00:00:00+09:00"));
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        let sunday = at(0, "2026-10-18T12:00:00Z");
+        assert_eq!(QuotaPeriod::Week.bounds(&sunday).0, ms("2026-10-12T00:00:00Z"));
+        let monday = at(0, "2026-10-19T00:00:00Z");
+        assert_eq!(QuotaPeriod::Week.bounds(&monday).0, ms("2026-10-19T00:00:00Z"));
+    }
+
+    #[test]
+    fn projects_exhaustion_from_burn_rate() {
+        let hour = 3_600_000;
+        // $2 left at $1/hour
+        assert_eq!(project_exhaustion(8.0, 10.0, 1.0 / hour as f64, 0, 10 * hour), Some(2 * hour));
+        // Not reached before the period ends
+        assert_eq!(project_exhaustion(8.0, 10.0, 1.0 / hour as f64, 0, hour), None);
+        assert_eq!(project_exhaustion(8.0, 10.0, 0.0, 0, hour), None);
+        assert_eq!(project_exhaustion(12.0, 10.0, 0.0, 5, hour), Some(5));
+    }
+}
@@ -0,0 +1,675 @@
+// In-place message redaction.
+//
+// Matched spans in content/tool_input/tool_output are replaced with a typed
+// placeholder such as `[REDACTED:api_key]`. The `redaction_log` table only records
+// what category was removed, where, by whom and when - never the original text.
+// Redaction is intentionally irreversible: nothing is kept that could undo it.
+//
+// The rows are rewritten with `secure_delete` on, so the old text isn't left in
+// freed pages. Copies of the message outside its row are purged right after: the
+// message and block caches drop it, `messages_fts` is optimized so the deleted
+// postings are merged away rather than just marked, and the removed text is scrubbed
+// from the cassettes and stream frames recorded on disk and from the sidecar log.
+
+use crate::db;
+use crate::jobs;
+use crate::message_blocks::BlockCache;
+use crate::messages::MessageCache;
+use crate::{cassette, message_search, sidecar_log};
+use regex::{Regex, RegexBuilder};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use tauri::{AppHandle, Manager, Window};
+
+// Compiled-regex size cap so a pathological pattern can't stall the app
+pub(crate) const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+const TASK_BATCH_SIZE: i64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactField {
+    Content,
+    ToolInput,
+    ToolOutput,
+}
+
+impl RedactField {
+    const ALL: [RedactField; 3] = [
+        RedactField::Content,
+        RedactField::ToolInput,
+        RedactField::ToolOutput,
+    ];
+
+    fn column(self) -> &'static str {
+        match self {
+            RedactField::Content => "content",
+            RedactField::ToolInput => "tool_input",
+            RedactField::ToolOutput => "tool_output",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            RedactField::Content => 0,
+            RedactField::ToolInput => 1,
+            RedactField::ToolOutput => 2,
+        }
+    }
+}
+
+/// A span to redact, in UTF-16 code units as produced by JS string indexing
+#[derive(Debug, Clone, Deserialize)]
+pub struct RedactRange {
+    pub field: RedactField,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RedactionTarget {
+    Ranges { ranges: Vec<RedactRange> },
+    Pattern { pattern: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactionOutcome {
+    pub message_id: i64,
+    pub spans_redacted: u32,
+    pub fields: Vec<RedactField>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TaskRedactionSummary {
+    pub dry_run: bool,
+    pub messages_scanned: u32,
+    pub messages_matched: u32,
+    pub spans: u32,
+}
+
+struct MessageFields {
+    task_id: String,
+    values: [Option<String>; 3],
+}
+
+fn placeholder(category: &str) -> String {
+    format!("[REDACTED:{}]", category)
+}
+
+/// The text a redaction removed and what replaced it, for scrubbing the copies kept
+/// outside the `messages` rows. Never stored or returned.
+#[derive(Debug, Default)]
+pub(crate) struct Scrubber {
+    spans: Vec<(String, String)>,
+}
+
+impl Scrubber {
+    pub(crate) fn add(&mut self, removed: &str, replacement: &str) {
+        if removed.is_empty() || self.spans.iter().any(|(r, _)| r == removed) {
+            return;
+        }
+        self.spans.push((removed.to_string(), replacement.to_string()));
+        // Longest first, so a span that contains another is replaced whole
+        self.spans.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// `text` with every removed span replaced; borrowed when nothing matched
+    pub(crate) fn scrub<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut out = Cow::Borrowed(text);
+        for (removed, replacement) in &self.spans {
+            if out.contains(removed.as_str()) {
+                out = Cow::Owned(out.replace(removed.as_str(), replacement));
+            }
+        }
+        out
+    }
+}
+
+/// Credentials that are redacted automatically from text the app hands onwards,
+/// with their replacement. `${1}` keeps the variable name of an assignment.
+const SECRET_PATTERNS: &[(&str, &str)] = &[
+    (r"\b(?:sk|pk|rk)-[A-Za-z0-9_-]{16,}", "[REDACTED:api_key]"),
+    (r"\bAKIA[0-9A-Z]{16}\b", "[REDACTED:api_key]"),
+    (r"\bgh[pousr]_[A-Za-z0-9]{20,}", "[REDACTED:token]"),
+    (r"(?i)\bbearer\s+[A-Za-z0-9._~+/=-]{16,}", "Bearer [REDACTED:token]"),
+    (
+        r#"(?i)\b([A-Z0-9_]*(?:SECRET|TOKEN|PASSWORD|API_KEY)[A-Z0-9_]*)\s*[=:]\s*["']?[^\s"']{6,}["']?"#,
+        "${1}=[REDACTED:secret]",
+    ),
+];
+
+/// Replace anything that looks like a credential with a placeholder
+pub(crate) fn redact_secrets(text: &str) -> String {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        SECRET_PATTERNS
+            .iter()
+            .map(|(pattern, replacement)| (Regex::new(pattern).expect("valid secret pattern"), *replacement))
+            .collect()
+    });
+    let mut out = text.to_string();
+    for (re, replacement) in patterns {
+        if re.is_match(&out) {
+            out = re.replace_all(&out, *replacement).into_owned();
+        }
+    }
+    out
+}
+
+fn validate_category(category: &str) -> Result<(), String> {
+    let valid = !category.is_empty()
+        && category.len() <= 32
+        && category
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid redaction category '{}': use 1-32 lowercase letters, digits, '_' or '-'",
+            category
+        ))
+    }
+}
+
+pub(crate) fn compile_pattern(pattern: &str) -> Result<Regex, String> {
+    if pattern.is_empty() {
+        return Err("Redaction pattern must not be empty".to_string());
+    }
+    RegexBuilder::new(pattern)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid redaction pattern: {}", e))
+}
+
+/// Convert a UTF-16 offset into a byte offset, rejecting offsets inside a character
+fn utf16_to_byte(text: &str, offset: usize) -> Option<usize> {
+    let mut units = 0;
+    for (byte, ch) in text.char_indices() {
+        if units == offset {
+            return Some(byte);
+        }
+        if units > offset {
+            return None;
+        }
+        units += ch.len_utf16();
+    }
+    (units == offset).then_some(text.len())
+}
+
+fn apply_ranges(
+    text: &str,
+    ranges: &[(usize, usize)],
+    replacement: &str,
+    scrubber: &mut Scrubber,
+) -> Result<String, String> {
+    let mut spans = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges {
+        if start >= end {
+            return Err(format!("Empty or inverted range {}..{}", start, end));
+        }
+        let (Some(s), Some(e)) = (utf16_to_byte(text, start), utf16_to_byte(text, end)) else {
+            return Err(format!("Range {}..{} is outside the text", start, end));
+        };
+        spans.push((s, e));
+    }
+    spans.sort();
+    if spans.windows(2).any(|w| w[0].1 > w[1].0) {
+        return Err("Redaction ranges overlap".to_string());
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (s, e) in spans {
+        out.push_str(&text[cursor..s]);
+        out.push_str(replacement);
+        scrubber.add(&text[s..e], replacement);
+        cursor = e;
+    }
+    out.push_str(&text[cursor..]);
+    Ok(out)
+}
+
+fn load_fields(conn: &Connection, message_id: i64) -> Result<MessageFields, String> {
+    conn.query_row(
+        "SELECT task_id, content, tool_input, tool_output FROM messages WHERE id = ?1",
+        [message_id],
+        |row| {
+            Ok(MessageFields {
+                task_id: row.get(0)?,
+                values: [row.get(1)?, row.get(2)?, row.get(3)?],
+            })
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Message {} not found", message_id))
+}
+
+/// Replace every pattern match in the given fields, returning the match count
+fn redact_with_pattern(
+    values: &mut [Option<String>; 3],
+    re: &Regex,
+    replacement: &str,
+    scrubber: &mut Scrubber,
+) -> (u32, Vec<RedactField>) {
+    let mut spans = 0;
+    let mut touched = Vec::new();
+    for field in RedactField::ALL {
+        let Some(text) = values[field.index()].as_ref() else {
+            continue;
+        };
+        let mut count = 0;
+        for found in re.find_iter(text) {
+            scrubber.add(found.as_str(), replacement);
+            count += 1;
+        }
+        if count > 0 {
+            let replaced = re.replace_all(text, replacement).into_owned();
+            values[field.index()] = Some(replaced);
+            spans += count;
+            touched.push(field);
+        }
+    }
+    (spans, touched)
+}
+
+fn count_pattern(values: &[Option<String>; 3], re: &Regex) -> u32 {
+    values
+        .iter()
+        .flatten()
+        .map(|text| re.find_iter(text).count() as u32)
+        .sum()
+}
+
+fn write_redaction(
+    conn: &mut Connection,
+    message_id: i64,
+    fields: &MessageFields,
+    touched: &[RedactField],
+    spans: u32,
+    category: &str,
+    actor: &str,
+) -> Result<(), String> {
+    conn.pragma_update(None, "secure_delete", true).map_err(|e| e.to_string())?;
+    let result = write_rows(conn, message_id, fields, touched, spans, category, actor);
+    conn.pragma_update(None, "secure_delete", false).map_err(|e| e.to_string())?;
+    result
+}
+
+fn write_rows(
+    conn: &mut Connection,
+    message_id: i64,
+    fields: &MessageFields,
+    touched: &[RedactField],
+    spans: u32,
+    category: &str,
+    actor: &str,
+) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE messages SET content = ?1, tool_input = ?2, tool_output = ?3, redacted = 1 WHERE id = ?4",
+        params![fields.values[0], fields.values[1], fields.values[2], message_id],
+    )
+    .map_err(|e| e.to_string())?;
+    let columns: Vec<&str> = touched.iter().map(|f| f.column()).collect();
+    tx.execute(
+        "INSERT INTO redaction_log (message_id, task_id, category, fields, span_count, actor)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![message_id, fields.task_id, category, columns.join(","), spans, actor],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+fn redact_message(
+    conn: &mut Connection,
+    message_id: i64,
+    target: &RedactionTarget,
+    category: &str,
+    actor: &str,
+    scrubber: &mut Scrubber,
+) -> Result<(RedactionOutcome, String), String> {
+    let mut fields = load_fields(conn, message_id)?;
+    let replacement = placeholder(category);
+
+    let (spans, touched) = match target {
+        RedactionTarget::Pattern { pattern } => {
+            let re = compile_pattern(pattern)?;
+            redact_with_pattern(&mut fields.values, &re, &replacement, scrubber)
+        }
+        RedactionTarget::Ranges { ranges } => {
+            let mut spans = 0;
+            let mut touched = Vec::new();
+            for field in RedactField::ALL {
+                let field_ranges: Vec<(usize, usize)> = ranges
+                    .iter()
+                    .filter(|r| r.field == field)
+                    .map(|r| (r.start, r.end))
+                    .collect();
+                if field_ranges.is_empty() {
+                    continue;
+                }
+                let Some(text) = fields.values[field.index()].as_ref() else {
+                    return Err(format!("Message {} has no {}", message_id, field.column()));
+                };
+                let replaced = apply_ranges(text, &field_ranges, &replacement, scrubber)?;
+                fields.values[field.index()] = Some(replaced);
+                spans += field_ranges.len() as u32;
+                touched.push(field);
+            }
+            (spans, touched)
+        }
+    };
+
+    if spans > 0 {
+        write_redaction(conn, message_id, &fields, &touched, spans, category, actor)?;
+    }
+    let outcome = RedactionOutcome {
+        message_id,
+        spans_redacted: spans,
+        fields: touched,
+    };
+    Ok((outcome, fields.task_id))
+}
+
+/// A pattern run over one task's messages
+struct TaskRedaction<'a> {
+    task_id: &'a str,
+    re: &'a Regex,
+    category: &'a str,
+    actor: &'a str,
+    dry_run: bool,
+}
+
+/// What a redaction rewrote, so the copies outside the rows can be purged after
+#[derive(Debug, Default)]
+struct Purge {
+    message_ids: Vec<i64>,
+    scrubber: Scrubber,
+}
+
+/// Count, or unless `dry_run` redact, every match in a task's messages, a batch of
+/// ids at a time. Rewritten messages are added to `purge` as they are written, so
+/// a cancelled run still purges what it redacted.
+fn redact_task_messages(
+    conn: &mut Connection,
+    run: &TaskRedaction,
+    purge: &mut Purge,
+    cancelled: impl Fn() -> bool,
+    progress: impl Fn(u64, u64),
+) -> Result<TaskRedactionSummary, String> {
+    let total: i64 = conn
+        .query_row("SELECT COUNT(*) FROM messages WHERE task_id = ?1", [run.task_id], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let replacement = placeholder(run.category);
+    let mut summary = TaskRedactionSummary {
+        dry_run: run.dry_run,
+        ..Default::default()
+    };
+
+    let mut last_id = 0i64;
+    loop {
+        if cancelled() {
+            return Err("Cancelled".to_string());
+        }
+        let ids: Vec<i64> = {
+            let mut stmt = conn
+                .prepare("SELECT id FROM messages WHERE task_id = ?1 AND id > ?2 ORDER BY id LIMIT ?3")
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map(params![run.task_id, last_id, TASK_BATCH_SIZE], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+        };
+        let Some(&batch_last) = ids.last() else {
+            break;
+        };
+        for id in ids {
+            let mut fields = load_fields(conn, id)?;
+            summary.messages_scanned += 1;
+            let spans = if run.dry_run {
+                count_pattern(&fields.values, run.re)
+            } else {
+                let (spans, touched) =
+                    redact_with_pattern(&mut fields.values, run.re, &replacement, &mut purge.scrubber);
+                if spans > 0 {
+                    write_redaction(conn, id, &fields, &touched, spans, run.category, run.actor)?;
+                    purge.message_ids.push(id);
+                }
+                spans
+            };
+            if spans > 0 {
+                summary.messages_matched += 1;
+                summary.spans += spans;
+            }
+        }
+        last_id = batch_last;
+        progress(summary.messages_scanned as u64, total as u64);
+    }
+    Ok(summary)
+}
+
+/// Remove what was redacted from every copy kept outside the rewritten rows.
+/// Failures are logged rather than returned: the rows are already redacted.
+fn purge_copies(app: &AppHandle, conn: &Connection, task_id: &str, purge: &Purge) {
+    if purge.message_ids.is_empty() {
+        return;
+    }
+    let messages = app.state::<MessageCache>();
+    let blocks = app.state::<BlockCache>();
+    for &id in &purge.message_ids {
+        messages.forget(task_id, id);
+        blocks.forget(id);
+    }
+    let optimized = conn
+        .pragma_update(None, "secure_delete", true)
+        .map_err(|e| e.to_string())
+        .and_then(|_| message_search::purge_deleted(conn));
+    let _ = conn.pragma_update(None, "secure_delete", false);
+    if let Err(e) = optimized {
+        eprintln!("[Redaction] Failed to optimize the search index: {}", e);
+    }
+    if purge.scrubber.is_empty() {
+        return;
+    }
+    if let Err(e) = cassette::scrub(app, conn, &purge.scrubber) {
+        eprintln!("[Redaction] Failed to scrub recorded cassettes: {}", e);
+    }
+    if let Err(e) = sidecar_log::scrub(app, &purge.scrubber) {
+        eprintln!("[Redaction] Failed to scrub the sidecar log: {}", e);
+    }
+}
+
+/// Redact spans of a single message, either explicit ranges or every match of a pattern
+#[tauri::command]
+pub async fn redact_message_content(
+    app: AppHandle,
+    window: Window,
+    message_id: i64,
+    target: RedactionTarget,
+    category: String,
+) -> Result<RedactionOutcome, String> {
+    validate_category(&category)?;
+    let actor = window.label().to_string();
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let mut purge = Purge::default();
+        let (outcome, task_id) = redact_message(conn, message_id, &target, &category, &actor, &mut purge.scrubber)?;
+        if outcome.spans_redacted > 0 {
+            purge.message_ids.push(message_id);
+            purge_copies(&handle, conn, &task_id, &purge);
+        }
+        Ok(outcome)
+    })
+    .await
+}
+
+/// Redact a pattern across every message of a task as a background job.
+/// Run with `dry_run: true` first to get the match counts without writing anything.
+#[tauri::command]
+pub fn redact_pattern_across_task(
+    app: AppHandle,
+    window: Window,
+    task_id: String,
+    pattern: String,
+    category: String,
+    dry_run: bool,
+) -> Result<u64, String> {
+    validate_category(&category)?;
+    let re = compile_pattern(&pattern)?;
+    let actor = window.label().to_string();
+    let kind = if dry_run { "redact_task_dry_run" } else { "redact_task" };
+    Ok(jobs::spawn(&app, kind, move |ctx| {
+        let mut conn = db::open(ctx.app())?;
+        let run = TaskRedaction {
+            task_id: &task_id,
+            re: &re,
+            category: &category,
+            actor: &actor,
+            dry_run,
+        };
+        let mut purge = Purge::default();
+        let summary = redact_task_messages(
+            &mut conn,
+            &run,
+            &mut purge,
+            || ctx.is_cancelled(),
+            |done, total| ctx.progress(done, total, None),
+        );
+        purge_copies(ctx.app(), &conn, &task_id, &purge);
+        Ok(json!(summary?))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "aé😀b";
+
+    fn task_db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch(
+            "INSERT INTO messages (id, task_id, type, content, tool_output) VALUES
+                 (1, 't1', 'text', 'key sk-live-1234 here', NULL),
+                 (2, 't1', 'tool_result', NULL, 'echo sk-live-1234 and sk-live-9999'),
+                 (3, 't1', 'text', 'nothing to see', NULL),
+                 (4, 't2', 'text', 'sk-live-1234 elsewhere', NULL);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn run_task(conn: &mut Connection, dry_run: bool, purge: &mut Purge) -> TaskRedactionSummary {
+        let re = compile_pattern(r"sk-live-\d+").unwrap();
+        let run = TaskRedaction {
+            task_id: "t1",
+            re: &re,
+            category: "api_key",
+            actor: "main",
+            dry_run,
+        };
+        redact_task_messages(conn, &run, purge, || false, |_, _| {}).unwrap()
+    }
+
+    fn contents(conn: &Connection) -> Vec<(Option<String>, Option<String>, i64)> {
+        let mut stmt = conn
+            .prepare("SELECT content, tool_output, redacted FROM messages ORDER BY id")
+            .unwrap();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn utf16_offsets_map_to_char_boundaries() {
+        // a = 1 unit/1 byte, é = 1 unit/2 bytes, 😀 = 2 units/4 bytes, b = 1 unit/1 byte
+        assert_eq!(utf16_to_byte(TEXT, 0), Some(0));
+        assert_eq!(utf16_to_byte(TEXT, 1), Some(1));
+        assert_eq!(utf16_to_byte(TEXT, 2), Some(3));
+        assert_eq!(utf16_to_byte(TEXT, 3), None, "inside the surrogate pair");
+        assert_eq!(utf16_to_byte(TEXT, 4), Some(7));
+        assert_eq!(utf16_to_byte(TEXT, 5), Some(TEXT.len()));
+        assert_eq!(utf16_to_byte(TEXT, 6), None);
+    }
+
+    #[test]
+    fn ranges_replace_multi_byte_spans() {
+        let mut scrubber = Scrubber::default();
+        assert_eq!(apply_ranges(TEXT, &[(1, 4)], "[X]", &mut scrubber).unwrap(), "a[X]b");
+        assert_eq!(scrubber.scrub("say é😀"), "say [X]");
+        assert!(apply_ranges(TEXT, &[(2, 3)], "[X]", &mut Scrubber::default()).is_err());
+        assert!(apply_ranges(TEXT, &[(2, 2)], "[X]", &mut Scrubber::default()).is_err());
+    }
+
+    #[test]
+    fn overlapping_ranges_are_rejected_and_adjacent_ones_are_not() {
+        let err = apply_ranges(TEXT, &[(0, 2), (1, 4)], "[X]", &mut Scrubber::default()).unwrap_err();
+        assert!(err.contains("overlap"));
+        let adjacent = apply_ranges(TEXT, &[(2, 4), (0, 2)], "[X]", &mut Scrubber::default()).unwrap();
+        assert_eq!(adjacent, "[X][X]b");
+    }
+
+    #[test]
+    fn a_dry_run_counts_without_writing() {
+        let mut conn = task_db();
+        let before = contents(&conn);
+        let mut purge = Purge::default();
+        let summary = run_task(&mut conn, true, &mut purge);
+
+        assert!(summary.dry_run);
+        assert_eq!((summary.messages_scanned, summary.messages_matched, summary.spans), (3, 2, 3));
+        assert_eq!(contents(&conn), before);
+        let logged: i64 = conn.query_row("SELECT COUNT(*) FROM redaction_log", [], |row| row.get(0)).unwrap();
+        assert_eq!(logged, 0);
+        assert!(purge.message_ids.is_empty() && purge.scrubber.is_empty());
+    }
+
+    #[test]
+    fn an_applied_run_flags_the_messages_and_logs_without_the_text() {
+        let mut conn = task_db();
+        let mut purge = Purge::default();
+        let summary = run_task(&mut conn, false, &mut purge);
+
+        assert_eq!((summary.messages_matched, summary.spans), (2, 3));
+        let rows = contents(&conn);
+        assert_eq!(rows[0], (Some("key [REDACTED:api_key] here".into()), None, 1));
+        assert_eq!(
+            rows[1],
+            (None, Some("echo [REDACTED:api_key] and [REDACTED:api_key]".into()), 1)
+        );
+        assert_eq!(rows[2].2, 0);
+        assert_eq!(rows[3], (Some("sk-live-1234 elsewhere".into()), None, 0), "other tasks are left alone");
+
+        let mut stmt = conn
+            .prepare("SELECT message_id, task_id, category, fields, span_count, actor FROM redaction_log ORDER BY id")
+            .unwrap();
+        let log: Vec<(i64, String, String, String, u32, String)> = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            log,
+            vec![
+                (1, "t1".into(), "api_key".into(), "content".into(), 1, "main".into()),
+                (2, "t1".into(), "api_key".into(), "tool_output".into(), 2, "main".into()),
+            ]
+        );
+
+        assert_eq!(purge.message_ids, vec![1, 2]);
+        assert_eq!(
+            purge.scrubber.scrub("sent sk-live-9999 and sk-live-1234"),
+            "sent [REDACTED:api_key] and [REDACTED:api_key]"
+        );
+    }
+}
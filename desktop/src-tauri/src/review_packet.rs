@@ -0,0 +1,870 @@
+// Review packets: a task or session exported for discussion, with short references
+// (M-014 for messages, F-003 for files, OP-027 for tool operations).
+//
+// References are numbered per kind in row-id order within the scope, which is the
+// order rows were recorded in: rows added after an export get new numbers and the
+// existing ones keep theirs. Deleting a row renumbers the rows after it. An export
+// writes `review-packet.html` (print it for a PDF) and `references.json`, and keeps
+// the mapping in `review_packets`, so `import_review_comments` resolves comments
+// against the packet they were made on even if the task changed since. Packets are
+// signed (see `export_manifest`); comments placed in a packet folder are checked
+// against its manifest on import.
+//
+// A file is tied to the operations that produced it when its path appears in the
+// tool call's input, and to the file it was extracted from through `source_file_id`.
+
+use crate::db;
+use crate::export_manifest::{self, VerificationReport};
+use crate::message_types::MessageType;
+use crate::task_notes::{self, TaskNote};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::path::Path;
+use tauri::AppHandle;
+
+const HTML_FILE: &str = "review-packet.html";
+const REFERENCES_FILE: &str = "references.json";
+/// Longest message body shown in the packet; the rest is elided
+const EXCERPT_CHARS: usize = 4000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Scope {
+    Task,
+    Session,
+}
+
+impl Scope {
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Task => "task",
+            Scope::Session => "session",
+        }
+    }
+
+    /// Filter on `task_id` selecting the rows in scope, bound to `?1`
+    fn filter(self) -> &'static str {
+        match self {
+            Scope::Task => "task_id = ?1",
+            Scope::Session => "task_id IN (SELECT id FROM tasks WHERE session_id = ?1)",
+        }
+    }
+}
+
+struct MessageRef {
+    reference: String,
+    id: i64,
+    task_id: String,
+    kind: MessageType,
+    content: Option<String>,
+    tool_name: Option<String>,
+    tool_input: Option<String>,
+    tool_output: Option<String>,
+    error_message: Option<String>,
+    created_at: String,
+}
+
+struct OperationRef {
+    reference: String,
+    tool_use_id: String,
+    tool_name: Option<String>,
+    call_message_id: Option<i64>,
+    result_message_id: Option<i64>,
+    is_error: bool,
+    latency_ms: Option<i64>,
+}
+
+struct FileRef {
+    reference: String,
+    id: i64,
+    name: String,
+    path: String,
+    source_file_id: Option<i64>,
+    created_at: String,
+    /// Indexes into `Packet::operations`
+    produced_by: Vec<usize>,
+}
+
+struct Packet {
+    messages: Vec<MessageRef>,
+    operations: Vec<OperationRef>,
+    files: Vec<FileRef>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewPacketReport {
+    pub packet_id: String,
+    pub html_path: String,
+    pub references_path: String,
+    pub messages: usize,
+    pub files: usize,
+    pub operations: usize,
+    /// `None` when the manifest couldn't be written
+    pub manifest_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentsFile {
+    packet_id: String,
+    comments: Vec<ReviewComment>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewComment {
+    #[serde(alias = "ref")]
+    reference: String,
+    #[serde(default)]
+    author: String,
+    body: String,
+    #[serde(default)]
+    created_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnmatchedComment {
+    pub reference: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommentImport {
+    pub packet_id: String,
+    pub imported: u32,
+    /// Comments already imported from this packet
+    pub duplicates: u32,
+    pub unmatched: Vec<UnmatchedComment>,
+    /// The packet folder's manifest check, when the comments file sits in a signed
+    /// packet
+    pub verification: Option<VerificationReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageNote {
+    pub id: i64,
+    pub message_id: i64,
+    pub packet_id: String,
+    pub reference: String,
+    pub author: String,
+    pub body: String,
+    pub commented_at: Option<String>,
+    pub imported_at: String,
+}
+
+fn reference(prefix: &str, n: usize) -> String {
+    format!("{}-{:03}", prefix, n + 1)
+}
+
+/// Load the rows in scope and number them
+fn build(conn: &Connection, scope: Scope, scope_id: &str) -> Result<Packet, String> {
+    let messages: Vec<MessageRef> = {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, task_id, type, content, tool_name, tool_input, tool_output, error_message, created_at
+                 FROM messages WHERE {} ORDER BY id",
+                scope.filter()
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([scope_id], |row| {
+                Ok(MessageRef {
+                    reference: String::new(),
+                    id: row.get(0)?,
+                    task_id: row.get(1)?,
+                    kind: row.get(2)?,
+                    content: row.get(3)?,
+                    tool_name: row.get(4)?,
+                    tool_input: row.get(5)?,
+                    tool_output: row.get(6)?,
+                    error_message: row.get(7)?,
+                    created_at: row.get(8)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    let operations: Vec<OperationRef> = {
+        // Operations without a recorded message sort last so they can't shift the others
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT tool_use_id, tool_name, call_message_id, result_message_id, is_error, latency_ms
+                 FROM tool_invocations WHERE {}
+                 ORDER BY COALESCE(call_message_id, result_message_id) IS NULL,
+                          COALESCE(call_message_id, result_message_id), tool_use_id",
+                scope.filter()
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([scope_id], |row| {
+                Ok(OperationRef {
+                    reference: String::new(),
+                    tool_use_id: row.get(0)?,
+                    tool_name: row.get(1)?,
+                    call_message_id: row.get(2)?,
+                    result_message_id: row.get(3)?,
+                    is_error: row.get(4)?,
+                    latency_ms: row.get(5)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    let files: Vec<FileRef> = {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT id, name, path, source_file_id, created_at FROM files WHERE {} ORDER BY id",
+                scope.filter()
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([scope_id], |row| {
+                Ok(FileRef {
+                    reference: String::new(),
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    path: row.get(2)?,
+                    source_file_id: row.get(3)?,
+                    created_at: row.get(4)?,
+                    produced_by: Vec::new(),
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+
+    let mut packet = Packet {
+        messages,
+        operations,
+        files,
+    };
+    for (n, message) in packet.messages.iter_mut().enumerate() {
+        message.reference = reference("M", n);
+    }
+    for (n, operation) in packet.operations.iter_mut().enumerate() {
+        operation.reference = reference("OP", n);
+    }
+    let inputs: HashMap<i64, &str> = packet
+        .messages
+        .iter()
+        .filter_map(|m| Some((m.id, m.tool_input.as_deref()?)))
+        .collect();
+    for (n, file) in packet.files.iter_mut().enumerate() {
+        file.reference = reference("F", n);
+        file.produced_by = packet
+            .operations
+            .iter()
+            .enumerate()
+            .filter(|(_, op)| {
+                op.call_message_id
+                    .and_then(|id| inputs.get(&id))
+                    .is_some_and(|input| !file.path.is_empty() && input.contains(file.path.as_str()))
+            })
+            .map(|(i, _)| i)
+            .collect();
+    }
+    Ok(packet)
+}
+
+impl Packet {
+    fn message(&self, id: Option<i64>) -> Option<&MessageRef> {
+        let id = id?;
+        self.messages.iter().find(|m| m.id == id)
+    }
+
+    fn file(&self, id: Option<i64>) -> Option<&FileRef> {
+        let id = id?;
+        self.files.iter().find(|f| f.id == id)
+    }
+
+    /// Operations a message is the call or result of
+    fn operations_of(&self, message_id: i64) -> impl Iterator<Item = (usize, &OperationRef)> {
+        self.operations
+            .iter()
+            .enumerate()
+            .filter(move |(_, op)| op.call_message_id == Some(message_id) || op.result_message_id == Some(message_id))
+    }
+
+    fn files_from(&self, operation: usize) -> impl Iterator<Item = &FileRef> {
+        self.files.iter().filter(move |f| f.produced_by.contains(&operation))
+    }
+
+    fn references(&self, packet_id: &str, scope: Scope, scope_id: &str, exported_at: &str) -> Value {
+        let mut refs = BTreeMap::new();
+        for m in &self.messages {
+            refs.insert(
+                m.reference.clone(),
+                json!({ "kind": "message", "message_id": m.id, "task_id": m.task_id }),
+            );
+        }
+        for op in &self.operations {
+            refs.insert(
+                op.reference.clone(),
+                json!({
+                    "kind": "operation",
+                    "tool_use_id": op.tool_use_id,
+                    "call_message_id": op.call_message_id,
+                    "result_message_id": op.result_message_id,
+                }),
+            );
+        }
+        for f in &self.files {
+            let produced_by: Vec<&str> = f
+                .produced_by
+                .iter()
+                .map(|&i| self.operations[i].reference.as_str())
+                .collect();
+            refs.insert(
+                f.reference.clone(),
+                json!({
+                    "kind": "file",
+                    "file_id": f.id,
+                    "produced_by": produced_by,
+                    "extracted_from": self.file(f.source_file_id).map(|s| &s.reference),
+                }),
+            );
+        }
+        json!({
+            "packet_id": packet_id,
+            "scope": scope.name(),
+            "scope_id": scope_id,
+            "exported_at": exported_at,
+            "references": refs,
+        })
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn excerpt(text: &str) -> String {
+    match text.char_indices().nth(EXCERPT_CHARS) {
+        Some((end, _)) => format!("{}\n…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+fn link(reference: &str) -> String {
+    format!("<a href=\"#{0}\">{0}</a>", reference)
+}
+
+fn links<'a>(references: impl Iterator<Item = &'a str>) -> String {
+    let links: Vec<String> = references.map(link).collect();
+    if links.is_empty() {
+        "—".to_string()
+    } else {
+        links.join(", ")
+    }
+}
+
+fn message_summary(m: &MessageRef) -> String {
+    let text = match m.kind {
+        MessageType::ToolUse => format!("Tool call: {}", m.tool_name.as_deref().unwrap_or("unknown")),
+        _ => m
+            .content
+            .as_deref()
+            .or(m.error_message.as_deref())
+            .and_then(|c| c.lines().find(|l| !l.trim().is_empty()))
+            .unwrap_or(m.kind.as_str())
+            .chars()
+            .take(100)
+            .collect(),
+    };
+    escape(&text)
+}
+
+const STYLE: &str = "body{font:14px/1.5 -apple-system,BlinkMacSystemFont,'Segoe UI',sans-serif;max-width:960px;margin:2em auto;padding:0 1em;color:#222}
+table{border-collapse:collapse;width:100%}td,th{border:1px solid #ddd;padding:4px 8px;text-align:left;vertical-align:top}
+section{border-top:1px solid #ddd;padding:.5em 0}h3{margin:.2em 0}.meta{color:#666;font-size:12px}
+pre{white-space:pre-wrap;word-break:break-word;background:#f6f6f6;padding:8px;border-radius:4px}
+@media print{section{break-inside:avoid-page}a{color:inherit;text-decoration:none}}";
+
+fn render_html(packet: &Packet, notes: &[TaskNote], title: &str, packet_id: &str, exported_at: &str) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title><style>{1}</style></head><body>\n\
+         <h1>{0}</h1>\n<p class=\"meta\">Packet {2} · exported {3} · {4} messages, {5} operations, {6} files</p>\n",
+        escape(title),
+        STYLE,
+        escape(packet_id),
+        escape(exported_at),
+        packet.messages.len(),
+        packet.operations.len(),
+        packet.files.len()
+    );
+
+    html.push_str("<h2>Index</h2>\n<table><tr><th>Ref</th><th>Summary</th><th>Related</th></tr>\n");
+    for m in &packet.messages {
+        let related: Vec<&str> = packet
+            .operations_of(m.id)
+            .flat_map(|(i, op)| std::iter::once(op.reference.as_str()).chain(packet.files_from(i).map(|f| f.reference.as_str())))
+            .collect();
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            link(&m.reference),
+            message_summary(m),
+            links(related.into_iter())
+        );
+    }
+    for (i, op) in packet.operations.iter().enumerate() {
+        let related = [op.call_message_id, op.result_message_id]
+            .into_iter()
+            .filter_map(|id| packet.message(id))
+            .map(|m| m.reference.as_str())
+            .chain(packet.files_from(i).map(|f| f.reference.as_str()));
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}{}</td><td>{}</td></tr>",
+            link(&op.reference),
+            escape(op.tool_name.as_deref().unwrap_or("unknown tool")),
+            if op.is_error { " (failed)" } else { "" },
+            links(related)
+        );
+    }
+    for f in &packet.files {
+        let related = f
+            .produced_by
+            .iter()
+            .map(|&i| packet.operations[i].reference.as_str())
+            .chain(packet.file(f.source_file_id).map(|s| s.reference.as_str()));
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            link(&f.reference),
+            escape(&f.name),
+            links(related)
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Messages</h2>\n");
+    for m in &packet.messages {
+        let _ = writeln!(
+            html,
+            "<section id=\"{}\"><h3>{} · {}</h3><p class=\"meta\">{} · message {} · task {}</p>",
+            m.reference,
+            m.reference,
+            escape(m.kind.as_str()),
+            escape(&m.created_at),
+            m.id,
+            escape(&m.task_id)
+        );
+        for body in [&m.content, &m.tool_input, &m.tool_output, &m.error_message]
+            .into_iter()
+            .flatten()
+            .filter(|b| !b.is_empty())
+        {
+            let _ = writeln!(html, "<pre>{}</pre>", escape(&excerpt(body)));
+        }
+        let operations: Vec<&str> = packet.operations_of(m.id).map(|(_, op)| op.reference.as_str()).collect();
+        if !operations.is_empty() {
+            let _ = writeln!(html, "<p>Operations: {}</p>", links(operations.into_iter()));
+        }
+        html.push_str("</section>\n");
+    }
+
+    html.push_str("<h2>Operations</h2>\n");
+    for (i, op) in packet.operations.iter().enumerate() {
+        let _ = writeln!(
+            html,
+            "<section id=\"{}\"><h3>{} · {}</h3><p class=\"meta\">{}{}{}</p>\
+             <p>Call: {} · Result: {} · Files: {}</p></section>",
+            op.reference,
+            op.reference,
+            escape(op.tool_name.as_deref().unwrap_or("unknown tool")),
+            escape(&op.tool_use_id),
+            op.latency_ms.map(|ms| format!(" · {} ms", ms)).unwrap_or_default(),
+            if op.is_error { " · failed" } else { "" },
+            links(packet.message(op.call_message_id).map(|m| m.reference.as_str()).into_iter()),
+            links(packet.message(op.result_message_id).map(|m| m.reference.as_str()).into_iter()),
+            links(packet.files_from(i).map(|f| f.reference.as_str()))
+        );
+    }
+
+    html.push_str("<h2>Files</h2>\n");
+    for f in &packet.files {
+        let _ = writeln!(
+            html,
+            "<section id=\"{}\"><h3>{} · {}</h3><p class=\"meta\">{} · {}</p>\
+             <p>Produced by: {} · Extracted from: {}</p></section>",
+            f.reference,
+            f.reference,
+            escape(&f.name),
+            escape(&f.path),
+            escape(&f.created_at),
+            links(f.produced_by.iter().map(|&i| packet.operations[i].reference.as_str())),
+            links(packet.file(f.source_file_id).map(|s| s.reference.as_str()).into_iter())
+        );
+    }
+    if !notes.is_empty() {
+        html.push_str("<h2>Reviewer notes</h2>\n<p class=\"meta\">Kept by people on these tasks; not shown to the agent.</p>\n");
+        for note in notes {
+            let _ = writeln!(
+                html,
+                "<section><h3>Task {}</h3><p class=\"meta\">Updated {}</p><pre>{}</pre></section>",
+                escape(&note.task_id),
+                escape(&note.updated_at),
+                escape(&note.content)
+            );
+        }
+    }
+    html.push_str("</body></html>\n");
+    html
+}
+
+/// Message a comment on `reference` is attached to, from a stored mapping
+fn resolve(refs: &Value, reference: &str) -> Result<i64, String> {
+    let entry = refs
+        .get(reference)
+        .ok_or_else(|| "Not a reference in this packet".to_string())?;
+    let message_id = match entry["kind"].as_str() {
+        Some("message") => entry["message_id"].as_i64(),
+        Some("operation") => entry["call_message_id"].as_i64().or(entry["result_message_id"].as_i64()),
+        // A file's comments go on the call that produced it
+        Some("file") => entry["produced_by"]
+            .as_array()
+            .and_then(|ops| ops.first())
+            .and_then(|op| op.as_str())
+            .and_then(|op| refs.get(op))
+            .and_then(|op| op["call_message_id"].as_i64()),
+        _ => None,
+    };
+    message_id.ok_or_else(|| "No message to attach the comment to".to_string())
+}
+
+fn import(conn: &Connection, comments: CommentsFile) -> Result<CommentImport, String> {
+    let refs: String = conn
+        .query_row(
+            "SELECT refs FROM review_packets WHERE id = ?1",
+            [&comments.packet_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Review packet '{}' was not exported from this database", comments.packet_id))?;
+    let refs: Value = serde_json::from_str(&refs).map_err(|e| e.to_string())?;
+    let mut report = CommentImport {
+        packet_id: comments.packet_id.clone(),
+        imported: 0,
+        duplicates: 0,
+        unmatched: Vec::new(),
+        verification: None,
+    };
+    for comment in comments.comments {
+        let reference = comment.reference.trim().to_ascii_uppercase();
+        let message_id = match resolve(&refs["references"], &reference) {
+            Ok(id) => id,
+            Err(reason) => {
+                report.unmatched.push(UnmatchedComment { reference, reason });
+                continue;
+            }
+        };
+        let exists: bool = conn
+            .query_row("SELECT EXISTS(SELECT 1 FROM messages WHERE id = ?1)", [message_id], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        if !exists {
+            report.unmatched.push(UnmatchedComment {
+                reference,
+                reason: "The message has been deleted".to_string(),
+            });
+            continue;
+        }
+        let inserted = conn
+            .execute(
+                "INSERT OR IGNORE INTO message_notes (message_id, packet_id, reference, author, body, commented_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    message_id,
+                    comments.packet_id,
+                    reference,
+                    comment.author.trim(),
+                    comment.body,
+                    comment.created_at
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        if inserted == 0 {
+            report.duplicates += 1;
+        } else {
+            report.imported += 1;
+        }
+    }
+    Ok(report)
+}
+
+/// Export a task or session as a review packet into the directory `path`. Pass
+/// exactly one of `task_id` and `session_id`.
+#[tauri::command]
+pub async fn export_review_packet(
+    app: AppHandle,
+    task_id: Option<String>,
+    session_id: Option<String>,
+    path: String,
+    include_notes: Option<bool>,
+) -> Result<ReviewPacketReport, String> {
+    let (scope, scope_id) = match (task_id, session_id) {
+        (Some(task_id), None) => (Scope::Task, task_id),
+        (None, Some(session_id)) => (Scope::Session, session_id),
+        _ => return Err("Pass either a task_id or a session_id".to_string()),
+    };
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        export_packet(&handle, conn, scope, &scope_id, Path::new(&path), include_notes.unwrap_or(false))
+    })
+    .await
+}
+
+/// Write the review packet for `scope_id` into `dir` and record its references
+pub(crate) fn export_packet(
+    app: &AppHandle,
+    conn: &Connection,
+    scope: Scope,
+    scope_id: &str,
+    dir: &Path,
+    include_notes: bool,
+) -> Result<ReviewPacketReport, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create '{}': {}", dir.display(), e))?;
+    let title: String = match scope {
+        Scope::Task => conn.query_row("SELECT prompt FROM tasks WHERE id = ?1", [scope_id], |row| row.get(0)),
+        Scope::Session => conn.query_row("SELECT prompt FROM sessions WHERE id = ?1", [scope_id], |row| row.get(0)),
+    }
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("{} '{}' not found", scope.name(), scope_id))?;
+    let title = title.lines().next().unwrap_or_default().chars().take(120).collect::<String>();
+
+    let packet = build(conn, scope, scope_id)?;
+    let now = chrono::Utc::now();
+    let exported_at = now.to_rfc3339();
+    let packet_id = format!("rp-{}", now.format("%Y%m%d-%H%M%S%3f"));
+    let references = packet.references(&packet_id, scope, scope_id, &exported_at);
+
+    let html_path = dir.join(HTML_FILE);
+    let references_path = dir.join(REFERENCES_FILE);
+    let notes = if include_notes {
+        task_notes::notes_where(conn, scope.filter(), scope_id)?
+    } else {
+        Vec::new()
+    };
+    std::fs::write(&html_path, render_html(&packet, &notes, &title, &packet_id, &exported_at))
+        .map_err(|e| e.to_string())?;
+    let encoded = serde_json::to_string_pretty(&references).map_err(|e| e.to_string())?;
+    std::fs::write(&references_path, &encoded).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO review_packets (id, scope, scope_id, refs) VALUES (?1, ?2, ?3, ?4)",
+        params![packet_id, scope.name(), scope_id, encoded],
+    )
+    .map_err(|e| e.to_string())?;
+    let manifest_path = export_manifest::write_signed(
+        app,
+        conn,
+        dir,
+        "review_packet",
+        &[html_path.clone(), references_path.clone()],
+    )
+    .map_err(|e| eprintln!("[ReviewPacket] No manifest for {}: {}", dir.display(), e))
+    .ok();
+
+    println!(
+        "[ReviewPacket] Exported {} for {} '{}' to {}",
+        packet_id,
+        scope.name(),
+        scope_id,
+        dir.display()
+    );
+    Ok(ReviewPacketReport {
+        packet_id,
+        html_path: html_path.to_string_lossy().into_owned(),
+        references_path: references_path.to_string_lossy().into_owned(),
+        messages: packet.messages.len(),
+        files: packet.files.len(),
+        operations: packet.operations.len(),
+        manifest_path: manifest_path.map(|p| p.to_string_lossy().into_owned()),
+    })
+}
+
+/// Attach comments collected on a review packet to the messages they refer to.
+/// The file is `{"packet_id": "...", "comments": [{"ref": "M-014", "author": "...",
+/// "body": "..."}]}`; importing the same file again adds nothing.
+#[tauri::command]
+pub async fn import_review_comments(app: AppHandle, path: String) -> Result<CommentImport, String> {
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("Cannot read '{}': {}", path, e))?;
+    let comments: CommentsFile = serde_json::from_str(&text).map_err(|e| format!("Invalid comments file: {}", e))?;
+    let packet_dir = Path::new(&path).parent().map(Path::to_path_buf).unwrap_or_default();
+    let handle = app.clone();
+    let report = db::with_conn(&app, move |conn| {
+        let verification = export_manifest::check_import(conn, &packet_dir)?;
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut report = import(&tx, comments)?;
+        tx.commit().map_err(|e| e.to_string())?;
+        report.verification = verification;
+        Ok(report)
+    })
+    .await?;
+    if report.imported > 0 {
+        db::emit_change(&handle, "message_notes", "insert", Vec::new());
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn list_message_notes(app: AppHandle, task_id: String) -> Result<Vec<MessageNote>, String> {
+    db::with_conn(&app, move |conn| {
+        let mut stmt = conn
+            .prepare(
+                "SELECT n.id, n.message_id, n.packet_id, n.reference, n.author, n.body, n.commented_at, n.imported_at
+                 FROM message_notes n JOIN messages m ON m.id = n.message_id
+                 WHERE m.task_id = ?1 ORDER BY n.message_id, n.id",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([&task_id], |row| {
+                Ok(MessageNote {
+                    id: row.get(0)?,
+                    message_id: row.get(1)?,
+                    packet_id: row.get(2)?,
+                    reference: row.get(3)?,
+                    author: row.get(4)?,
+                    body: row.get(5)?,
+                    commented_at: row.get(6)?,
+                    imported_at: row.get(7)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, prompt) VALUES ('s1', 'p');
+             INSERT INTO tasks (id, session_id, prompt) VALUES ('t1', 's1', 'Write a report'), ('t2', 's1', 'Review it');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn message(conn: &Connection, task_id: &str, kind: &str, input: Option<&str>) -> i64 {
+        conn.execute(
+            "INSERT INTO messages (task_id, type, content, tool_input) VALUES (?1, ?2, 'text', ?3)",
+            params![task_id, kind, input],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn operation(conn: &Connection, task_id: &str, tool_use_id: &str, call: i64) {
+        conn.execute(
+            "INSERT INTO tool_invocations (tool_use_id, task_id, tool_name, call_message_id) VALUES (?1, ?2, 'Write', ?3)",
+            params![tool_use_id, task_id, call],
+        )
+        .unwrap();
+    }
+
+    fn file(conn: &Connection, task_id: &str, path: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO files (task_id, name, type, path) VALUES (?1, 'f', 'document', ?2)",
+            params![task_id, path],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn refs(packet: &Packet) -> Vec<(String, String)> {
+        let messages = packet.messages.iter().map(|m| (m.reference.clone(), m.id.to_string()));
+        let operations = packet.operations.iter().map(|o| (o.reference.clone(), o.tool_use_id.clone()));
+        let files = packet.files.iter().map(|f| (f.reference.clone(), f.id.to_string()));
+        messages.chain(operations).chain(files).collect()
+    }
+
+    #[test]
+    fn references_are_stable_when_rows_are_appended() {
+        let conn = db();
+        message(&conn, "t1", "user", None);
+        let call = message(&conn, "t1", "tool_use", Some(r#"{"file_path":"/w/report.md"}"#));
+        operation(&conn, "t1", "toolu_b", call);
+        file(&conn, "t1", "/w/report.md");
+        message(&conn, "t2", "user", None);
+
+        let first = build(&conn, Scope::Session, "s1").unwrap();
+        assert_eq!(first.messages.iter().map(|m| m.reference.as_str()).collect::<Vec<_>>(), ["M-001", "M-002", "M-003"]);
+        assert_eq!(first.files[0].produced_by, vec![0]);
+
+        // New rows in both tasks, including an operation whose id sorts before the first
+        let call = message(&conn, "t1", "tool_use", Some(r#"{"file_path":"/w/notes.md"}"#));
+        operation(&conn, "t1", "toolu_a", call);
+        file(&conn, "t1", "/w/notes.md");
+        message(&conn, "t2", "text", None);
+
+        let second = build(&conn, Scope::Session, "s1").unwrap();
+        let (before, after) = (refs(&first), refs(&second));
+        assert!(before.iter().all(|r| after.contains(r)), "{:?} not kept in {:?}", before, after);
+        assert_eq!(second.operations[1].reference, "OP-002");
+        assert_eq!(second.operations[1].tool_use_id, "toolu_a");
+        assert_eq!(second.files[1].produced_by, vec![1]);
+        assert_eq!(build(&conn, Scope::Task, "t2").unwrap().messages.len(), 2);
+    }
+
+    #[test]
+    fn comments_attach_by_reference_once() {
+        let conn = db();
+        let user = message(&conn, "t1", "user", None);
+        let call = message(&conn, "t1", "tool_use", Some(r#"{"file_path":"/w/report.md"}"#));
+        operation(&conn, "t1", "toolu_1", call);
+        file(&conn, "t1", "/w/report.md");
+        let packet = build(&conn, Scope::Task, "t1").unwrap();
+        let refs = packet.references("rp-1", Scope::Task, "t1", "now");
+        conn.execute(
+            "INSERT INTO review_packets (id, scope, scope_id, refs) VALUES ('rp-1', 'task', 't1', ?1)",
+            [refs.to_string()],
+        )
+        .unwrap();
+
+        let comments = || -> CommentsFile {
+            serde_json::from_value(json!({
+                "packet_id": "rp-1",
+                "comments": [
+                    { "ref": "M-001", "author": "ana", "body": "Unclear ask" },
+                    { "ref": "f-001", "body": "Wrong format" },
+                    { "ref": "M-099", "body": "?" },
+                ]
+            }))
+            .unwrap()
+        };
+        let report = import(&conn, comments()).unwrap();
+        assert_eq!((report.imported, report.duplicates), (2, 0));
+        assert_eq!(report.unmatched.len(), 1);
+        assert_eq!(report.unmatched[0].reference, "M-099");
+        let on_call: i64 = conn
+            .query_row("SELECT message_id FROM message_notes WHERE reference = 'F-001'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(on_call, call);
+        let on_user: i64 = conn
+            .query_row("SELECT message_id FROM message_notes WHERE reference = 'M-001'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(on_user, user);
+
+        let again = import(&conn, comments()).unwrap();
+        assert_eq!((again.imported, again.duplicates), (0, 2));
+    }
+}
@@ -0,0 +1,779 @@
+// Rules that route new tasks: "tasks mentioning 'invoice' go into the Finance
+// session and get the billing tag".
+//
+// Rules live in `routing_rules` (migration 17) and are evaluated in priority order
+// when `create_task` inserts a task, inside the same transaction, so a task never
+// exists without its assignments. A `first_match` rule stops evaluation when it
+// matches; an `accumulate` rule applies and lets later rules add to it, with earlier
+// rules winning conflicting single-valued actions. What was applied, and which rules
+// caused it, is stored on the task in `routing`.
+
+use crate::context_plan::{self, ContextOverrides, ContextPlan, ContextTokenCache};
+use crate::error::CommandError;
+use crate::workspace_analysis::{self, WorkspaceAnalysis};
+use crate::{db, prompts, quota, redaction, session_files, tags};
+use regex::{Regex, RegexBuilder};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::Manager;
+
+const MAX_PATTERN_LEN: usize = 1000;
+const MAX_TAG_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    PromptContains {
+        value: String,
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+    PromptRegex {
+        pattern: String,
+    },
+    TemplateId {
+        id: String,
+    },
+    /// The task runs in this directory or below it
+    WorkingDir {
+        prefix: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Put the task in an existing session, or in the session whose prompt is
+    /// `session_name`, creating it if there is none
+    AssignSession {
+        session_id: Option<String>,
+        session_name: Option<String>,
+    },
+    AddTags {
+        tags: Vec<String>,
+    },
+    SetPermissionMode {
+        mode: String,
+    },
+    SetBudget {
+        max_cost_usd: f64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    #[default]
+    FirstMatch,
+    Accumulate,
+}
+
+impl MatchMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            MatchMode::FirstMatch => "first_match",
+            MatchMode::Accumulate => "accumulate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoutingRule {
+    pub id: i64,
+    pub name: String,
+    pub priority: i64,
+    pub enabled: bool,
+    pub mode: MatchMode,
+    /// All must match; an empty list matches every task
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoutingRuleInput {
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub mode: MatchMode,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// What the rules look at
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TaskFacts {
+    pub prompt: String,
+    pub working_dir: Option<String>,
+    pub template_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MatchedRule {
+    pub id: i64,
+    pub name: String,
+}
+
+/// Result of evaluating the rules for one task, stored on it as `routing`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RoutingDecision {
+    pub matched_rules: Vec<MatchedRule>,
+    pub session_id: Option<String>,
+    pub session_name: Option<String>,
+    pub tags: Vec<String>,
+    pub permission_mode: Option<String>,
+    pub max_cost_usd: Option<f64>,
+    /// Rule that chose the session, for the explanation
+    pub session_rule: Option<i64>,
+    /// Set when the task was created: the session it ended up in, and whether the
+    /// rules created that session
+    #[serde(default)]
+    pub assigned_session_id: Option<String>,
+    #[serde(default)]
+    pub created_session: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoutingDryRun {
+    pub decision: RoutingDecision,
+    /// Disabled rules that would have matched, in priority order
+    pub disabled_matches: Vec<MatchedRule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateTaskInput {
+    pub id: String,
+    pub session_id: String,
+    pub task_index: i64,
+    pub prompt: String,
+    pub working_dir: Option<String>,
+    pub template_id: Option<String>,
+    /// Sizes the context plan; the default window is used when unknown
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub context_overrides: ContextOverrides,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedTask {
+    pub task_id: String,
+    pub session_id: String,
+    pub task_index: i64,
+    pub routing: Option<RoutingDecision>,
+    /// Working-set files linked to the task, in order, without those the context
+    /// plan dropped
+    pub attachment_ids: Vec<i64>,
+    /// Summary of `working_dir`, for the task's context payload
+    pub workspace_analysis: Option<WorkspaceAnalysis>,
+    /// What the task's context was trimmed to; also stored as `sent_context`
+    pub context: ContextPlan,
+}
+
+/// Compiled with the same limits as redaction patterns
+fn compile_regex(pattern: &str) -> Result<Regex, String> {
+    if pattern.is_empty() {
+        return Err("Regex condition must not be empty".to_string());
+    }
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("Regex condition is longer than {} characters", MAX_PATTERN_LEN));
+    }
+    RegexBuilder::new(pattern)
+        .size_limit(redaction::PATTERN_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid regex condition: {}", e))
+}
+
+fn validate(conn: &Connection, input: &RoutingRuleInput) -> Result<(), String> {
+    if input.name.trim().is_empty() {
+        return Err("Rule name must not be empty".to_string());
+    }
+    if input.actions.is_empty() {
+        return Err("A rule needs at least one action".to_string());
+    }
+    for condition in &input.conditions {
+        match condition {
+            Condition::PromptContains { value, .. } if value.is_empty() => {
+                return Err("Substring condition must not be empty".to_string())
+            }
+            Condition::PromptRegex { pattern } => {
+                compile_regex(pattern)?;
+            }
+            Condition::TemplateId { id } if id.is_empty() => {
+                return Err("Template condition must name a template".to_string())
+            }
+            Condition::WorkingDir { prefix } if prefix.is_empty() => {
+                return Err("Working directory condition must not be empty".to_string())
+            }
+            _ => {}
+        }
+    }
+    for action in &input.actions {
+        match action {
+            Action::AssignSession { session_id: Some(id), session_name: None } => {
+                let exists: bool = conn
+                    .query_row("SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)", [id], |row| row.get(0))
+                    .map_err(|e| e.to_string())?;
+                if !exists {
+                    return Err(format!("Session '{}' does not exist", id));
+                }
+            }
+            Action::AssignSession { session_id: None, session_name: Some(name) } if !name.trim().is_empty() => {}
+            Action::AssignSession { .. } => {
+                return Err("Assign-session needs either a session id or a session name".to_string())
+            }
+            Action::AddTags { tags } => {
+                if tags.is_empty() {
+                    return Err("Add-tags needs at least one tag".to_string());
+                }
+                if let Some(tag) = tags.iter().find(|t| t.trim().is_empty() || t.trim().len() > MAX_TAG_LEN) {
+                    return Err(format!("Invalid tag '{}': use 1-{} characters", tag, MAX_TAG_LEN));
+                }
+            }
+            Action::SetPermissionMode { mode } => {
+                let valid = !mode.is_empty()
+                    && mode.len() <= 32
+                    && mode.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+                if !valid {
+                    return Err(format!("Invalid permission mode '{}'", mode));
+                }
+            }
+            Action::SetBudget { max_cost_usd } => {
+                if !max_cost_usd.is_finite() || *max_cost_usd <= 0.0 {
+                    return Err("Budget must be a positive amount".to_string());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn condition_matches(condition: &Condition, facts: &TaskFacts) -> bool {
+    match condition {
+        Condition::PromptContains { value, case_sensitive: true } => facts.prompt.contains(value.as_str()),
+        Condition::PromptContains { value, case_sensitive: false } => {
+            facts.prompt.to_lowercase().contains(&value.to_lowercase())
+        }
+        // Validated on save; a pattern that no longer compiles never matches
+        Condition::PromptRegex { pattern } => compile_regex(pattern).is_ok_and(|re| re.is_match(&facts.prompt)),
+        Condition::TemplateId { id } => facts.template_id.as_deref() == Some(id.as_str()),
+        Condition::WorkingDir { prefix } => facts
+            .working_dir
+            .as_deref()
+            .is_some_and(|dir| std::path::Path::new(dir).starts_with(prefix)),
+    }
+}
+
+fn rule_matches(rule: &RoutingRule, facts: &TaskFacts) -> bool {
+    rule.conditions.iter().all(|c| condition_matches(c, facts))
+}
+
+fn apply(decision: &mut RoutingDecision, rule: &RoutingRule) {
+    decision.matched_rules.push(MatchedRule {
+        id: rule.id,
+        name: rule.name.clone(),
+    });
+    for action in &rule.actions {
+        match action {
+            Action::AssignSession { session_id, session_name } => {
+                if decision.session_rule.is_none() {
+                    decision.session_id = session_id.clone();
+                    decision.session_name = session_name.clone();
+                    decision.session_rule = Some(rule.id);
+                }
+            }
+            Action::AddTags { tags } => {
+                for tag in tags {
+                    let tag = tag.trim();
+                    if !decision.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                        decision.tags.push(tag.to_string());
+                    }
+                }
+            }
+            Action::SetPermissionMode { mode } => {
+                decision.permission_mode.get_or_insert_with(|| mode.clone());
+            }
+            Action::SetBudget { max_cost_usd } => {
+                decision.max_cost_usd.get_or_insert(*max_cost_usd);
+            }
+        }
+    }
+}
+
+/// Evaluate enabled rules (already in priority order); `None` when nothing matched
+fn evaluate(rules: &[RoutingRule], facts: &TaskFacts) -> Option<RoutingDecision> {
+    let mut decision = RoutingDecision::default();
+    for rule in rules.iter().filter(|r| r.enabled) {
+        if !rule_matches(rule, facts) {
+            continue;
+        }
+        apply(&mut decision, rule);
+        if rule.mode == MatchMode::FirstMatch {
+            break;
+        }
+    }
+    (!decision.matched_rules.is_empty()).then_some(decision)
+}
+
+fn load_rules(conn: &Connection) -> Result<Vec<RoutingRule>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, name, priority, enabled, mode, conditions, actions, created_at, updated_at
+             FROM routing_rules ORDER BY priority, id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, bool>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, String>(7)?,
+                row.get::<_, String>(8)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    let mut rules = Vec::new();
+    for row in rows {
+        let (id, name, priority, enabled, mode, conditions, actions, created_at, updated_at) =
+            row.map_err(|e| e.to_string())?;
+        let parse_err = |e: serde_json::Error| format!("Routing rule {} is malformed: {}", id, e);
+        rules.push(RoutingRule {
+            id,
+            name,
+            priority,
+            enabled,
+            mode: if mode == "accumulate" { MatchMode::Accumulate } else { MatchMode::FirstMatch },
+            conditions: serde_json::from_str(&conditions).map_err(parse_err)?,
+            actions: serde_json::from_str(&actions).map_err(parse_err)?,
+            created_at,
+            updated_at,
+        });
+    }
+    Ok(rules)
+}
+
+fn load_rule(conn: &Connection, id: i64) -> Result<RoutingRule, String> {
+    load_rules(conn)?
+        .into_iter()
+        .find(|r| r.id == id)
+        .ok_or_else(|| format!("Routing rule {} not found", id))
+}
+
+/// Session for a rule's session name, created with the usual id shape if missing
+pub(crate) fn session_by_name(tx: &Transaction, name: &str) -> Result<(String, bool), String> {
+    let name = name.trim();
+    let existing: Option<String> = tx
+        .query_row(
+            "SELECT id FROM sessions WHERE prompt = ?1 COLLATE NOCASE ORDER BY created_at LIMIT 1",
+            [name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if let Some(id) = existing {
+        return Ok((id, false));
+    }
+    let slug: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    let mut id = format!("{}_{}", chrono::Local::now().format("%Y%m%d%H%M%S"), slug);
+    let mut n = 2;
+    while tx
+        .query_row("SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)", [&id], |row| row.get::<_, bool>(0))
+        .map_err(|e| e.to_string())?
+    {
+        id = format!("{}_{}-{}", chrono::Local::now().format("%Y%m%d%H%M%S"), slug, n);
+        n += 1;
+    }
+    tx.execute("INSERT INTO sessions (id, prompt, task_count) VALUES (?1, ?2, 0)", params![id, name])
+        .map_err(|e| e.to_string())?;
+    Ok((id, true))
+}
+
+fn create(
+    conn: &mut Connection,
+    input: CreateTaskInput,
+    workspace_analysis: Option<WorkspaceAnalysis>,
+    tokens: &ContextTokenCache,
+) -> Result<CreatedTask, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let facts = TaskFacts {
+        prompt: input.prompt.clone(),
+        working_dir: input.working_dir,
+        template_id: input.template_id,
+    };
+    let mut decision = evaluate(&load_rules(&tx)?, &facts);
+
+    let mut session_id = input.session_id;
+    let mut task_index = input.task_index;
+    if let Some(decision) = decision.as_mut() {
+        let target = match (&decision.session_id, &decision.session_name) {
+            (Some(id), _) => {
+                let exists: bool = tx
+                    .query_row("SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)", [id], |row| row.get(0))
+                    .map_err(|e| e.to_string())?;
+                // A session deleted after the rule was saved leaves the task where it was
+                exists.then(|| (id.clone(), false))
+            }
+            (None, Some(name)) => Some(session_by_name(&tx, name)?),
+            (None, None) => None,
+        };
+        if let Some((target, created)) = target.filter(|(target, _)| *target != session_id) {
+            task_index = tx
+                .query_row(
+                    "SELECT COALESCE(MAX(task_index), 0) + 1 FROM tasks WHERE session_id = ?1",
+                    [&target],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            session_id = target;
+            decision.created_session = created;
+        }
+        decision.assigned_session_id = Some(session_id.clone());
+    }
+
+    let routing = decision
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let analysis = workspace_analysis
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let (stored_prompt, overflow) = prompts::split(&tx, &input.prompt)?;
+    tx.execute(
+        "INSERT INTO tasks (id, session_id, task_index, prompt, routing, workspace_analysis)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![input.id, session_id, task_index, stored_prompt, routing, analysis],
+    )
+    .map_err(|e| e.to_string())?;
+    if overflow {
+        prompts::attach_overflow(&tx, &input.id, &input.prompt)?;
+    }
+    let attachment_ids = session_files::attach_to_task(&tx, &input.id, &session_id)?;
+    if let Some(decision) = &decision {
+        for tag in decision.tags.iter().filter_map(|tag| tags::normalize(tag).ok()) {
+            tx.execute("INSERT INTO tags (name) VALUES (?1) ON CONFLICT(name) DO NOTHING", [&tag])
+                .map_err(|e| e.to_string())?;
+            tx.execute(
+                "INSERT OR IGNORE INTO task_tags (task_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+                params![input.id, tag],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    tx.execute(
+        "UPDATE sessions SET task_count = MAX(task_count, ?1), updated_at = datetime('now') WHERE id = ?2",
+        params![task_index, session_id],
+    )
+    .map_err(|e| e.to_string())?;
+    let context = context_plan::apply_to_new_task(&tx, tokens, &input.id, input.context_overrides, input.model)?;
+    let dropped = |id: &i64| {
+        context
+            .items
+            .iter()
+            .any(|item| item.key == format!("session_file:{}", id) && item.action == context_plan::ContextAction::Drop)
+    };
+    let attachment_ids: Vec<i64> = attachment_ids.into_iter().filter(|id| !dropped(id)).collect();
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(CreatedTask {
+        task_id: input.id,
+        session_id,
+        task_index,
+        routing: decision,
+        attachment_ids,
+        workspace_analysis,
+        context,
+    })
+}
+
+/// Insert a new task, applying the routing rules in the same transaction. Fails with
+/// `quota_exceeded` while a spending quota blocks new tasks.
+#[tauri::command]
+pub async fn create_task(app: tauri::AppHandle, input: CreateTaskInput) -> Result<CreatedTask, CommandError> {
+    if let Some(error) = db::with_conn(&app, |conn| quota::blocking_error(conn)).await? {
+        return Err(error);
+    }
+    // A working_dir that can't be analyzed only costs the task its summary
+    let analysis = match input.working_dir.as_deref() {
+        Some(dir) => workspace_analysis::analyze_dir(&app, PathBuf::from(dir))
+            .await
+            .map_err(|e| eprintln!("[Routing] No workspace analysis for {}: {:?}", dir, e))
+            .ok(),
+        None => None,
+    };
+    let handle = app.clone();
+    let created = db::with_conn(&app, move |conn| {
+        let tokens = handle.state::<ContextTokenCache>();
+        create(conn, input, analysis, &tokens)
+    })
+    .await?;
+    db::emit_change(&app, "tasks", "insert", vec![created.task_id.clone()]);
+    Ok(created)
+}
+
+#[tauri::command]
+pub async fn list_routing_rules(app: tauri::AppHandle) -> Result<Vec<RoutingRule>, String> {
+    db::with_conn(&app, |conn| load_rules(conn)).await
+}
+
+/// Add a rule at the lowest priority
+#[tauri::command]
+pub async fn create_routing_rule(app: tauri::AppHandle, rule: RoutingRuleInput) -> Result<RoutingRule, String> {
+    db::with_conn(&app, move |conn| {
+        validate(conn, &rule)?;
+        conn.execute(
+            "INSERT INTO routing_rules (name, priority, enabled, mode, conditions, actions)
+             VALUES (?1, (SELECT COALESCE(MAX(priority), -1) + 1 FROM routing_rules), ?2, ?3, ?4, ?5)",
+            params![
+                rule.name.trim(),
+                rule.enabled,
+                rule.mode.as_str(),
+                serde_json::to_string(&rule.conditions).map_err(|e| e.to_string())?,
+                serde_json::to_string(&rule.actions).map_err(|e| e.to_string())?,
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        load_rule(conn, conn.last_insert_rowid())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn update_routing_rule(app: tauri::AppHandle, id: i64, rule: RoutingRuleInput) -> Result<RoutingRule, String> {
+    db::with_conn(&app, move |conn| {
+        validate(conn, &rule)?;
+        let changed = conn
+            .execute(
+                "UPDATE routing_rules
+                 SET name = ?1, enabled = ?2, mode = ?3, conditions = ?4, actions = ?5, updated_at = datetime('now')
+                 WHERE id = ?6",
+                params![
+                    rule.name.trim(),
+                    rule.enabled,
+                    rule.mode.as_str(),
+                    serde_json::to_string(&rule.conditions).map_err(|e| e.to_string())?,
+                    serde_json::to_string(&rule.actions).map_err(|e| e.to_string())?,
+                    id,
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        if changed == 0 {
+            return Err(format!("Routing rule {} not found", id));
+        }
+        load_rule(conn, id)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_routing_rule(app: tauri::AppHandle, id: i64) -> Result<bool, String> {
+    db::with_conn(&app, move |conn| {
+        conn.execute("DELETE FROM routing_rules WHERE id = ?1", [id])
+            .map(|n| n > 0)
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Set the evaluation order; `ids` must list every rule exactly once
+#[tauri::command]
+pub async fn reorder_routing_rules(app: tauri::AppHandle, ids: Vec<i64>) -> Result<Vec<RoutingRule>, String> {
+    db::with_conn(&app, move |conn| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut existing: Vec<i64> = load_rules(&tx)?.into_iter().map(|r| r.id).collect();
+        let mut requested = ids.clone();
+        existing.sort_unstable();
+        requested.sort_unstable();
+        if existing != requested {
+            return Err("Reorder must list every routing rule exactly once".to_string());
+        }
+        for (priority, id) in ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE routing_rules SET priority = ?1, updated_at = datetime('now') WHERE id = ?2",
+                params![priority as i64, id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        load_rules(conn)
+    })
+    .await
+}
+
+/// Dry-run the rules against a sample task without creating anything. Disabled
+/// rules are reported separately so they can be checked before being enabled.
+#[tauri::command]
+pub async fn test_routing_rules(
+    app: tauri::AppHandle,
+    sample_prompt: String,
+    working_dir: Option<String>,
+    template_id: Option<String>,
+) -> Result<RoutingDryRun, String> {
+    db::with_conn(&app, move |conn| {
+        let rules = load_rules(conn)?;
+        let facts = TaskFacts {
+            prompt: sample_prompt,
+            working_dir,
+            template_id,
+        };
+        Ok(RoutingDryRun {
+            decision: evaluate(&rules, &facts).unwrap_or_default(),
+            disabled_matches: rules
+                .iter()
+                .filter(|r| !r.enabled && rule_matches(r, &facts))
+                .map(|r| MatchedRule {
+                    id: r.id,
+                    name: r.name.clone(),
+                })
+                .collect(),
+        })
+    })
+    .await
+}
+
+/// Which rules routed a task and what they did; `None` if no rule matched it
+#[tauri::command]
+pub async fn get_task_routing_explanation(
+    app: tauri::AppHandle,
+    task_id: String,
+) -> Result<Option<RoutingDecision>, String> {
+    db::with_conn(&app, move |conn| {
+        let routing: Option<String> = conn
+            .query_row("SELECT routing FROM tasks WHERE id = ?1", [&task_id], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Task '{}' not found", task_id))?;
+        routing
+            .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .transpose()
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: i64, mode: MatchMode, conditions: Vec<Condition>, actions: Vec<Action>) -> RoutingRule {
+        RoutingRule {
+            id,
+            name: format!("rule {}", id),
+            priority: id,
+            enabled: true,
+            mode,
+            conditions,
+            actions,
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    fn facts(prompt: &str) -> TaskFacts {
+        TaskFacts {
+            prompt: prompt.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn invoice_rules(first_mode: MatchMode) -> Vec<RoutingRule> {
+        vec![
+            rule(
+                1,
+                first_mode,
+                vec![Condition::PromptContains {
+                    value: "invoice".into(),
+                    case_sensitive: false,
+                }],
+                vec![
+                    Action::AssignSession {
+                        session_id: None,
+                        session_name: Some("Finance".into()),
+                    },
+                    Action::AddTags { tags: vec!["billing".into()] },
+                ],
+            ),
+            rule(
+                2,
+                MatchMode::FirstMatch,
+                vec![Condition::PromptRegex {
+                    pattern: r"\bQ[1-4]\b".into(),
+                }],
+                vec![
+                    Action::AssignSession {
+                        session_id: None,
+                        session_name: Some("Quarterly".into()),
+                    },
+                    Action::AddTags {
+                        tags: vec!["reporting".into(), "Billing".into()],
+                    },
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn first_match_stops_evaluation() {
+        let decision = evaluate(&invoice_rules(MatchMode::FirstMatch), &facts("Send the Q3 INVOICE")).unwrap();
+        assert_eq!(decision.matched_rules.len(), 1);
+        assert_eq!(decision.session_name.as_deref(), Some("Finance"));
+        assert_eq!(decision.tags, vec!["billing"]);
+    }
+
+    #[test]
+    fn accumulate_continues_and_earlier_rules_win_conflicts() {
+        let decision = evaluate(&invoice_rules(MatchMode::Accumulate), &facts("Send the Q3 invoice")).unwrap();
+        assert_eq!(decision.matched_rules.iter().map(|r| r.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(decision.session_name.as_deref(), Some("Finance"));
+        assert_eq!(decision.session_rule, Some(1));
+        assert_eq!(decision.tags, vec!["billing", "reporting"]);
+    }
+
+    #[test]
+    fn disabled_and_unmatched_rules_are_skipped() {
+        let mut rules = invoice_rules(MatchMode::FirstMatch);
+        rules[0].enabled = false;
+        assert_eq!(evaluate(&rules, &facts("pay the invoice")), None);
+        assert_eq!(evaluate(&rules, &facts("Q2 numbers")).unwrap().matched_rules[0].id, 2);
+    }
+
+    #[test]
+    fn working_dir_matches_whole_components() {
+        let condition = Condition::WorkingDir {
+            prefix: "/home/me/work".into(),
+        };
+        let mut task = facts("x");
+        task.working_dir = Some("/home/me/work/app".into());
+        assert!(condition_matches(&condition, &task));
+        task.working_dir = Some("/home/me/workshop".into());
+        assert!(!condition_matches(&condition, &task));
+    }
+
+    #[test]
+    fn oversized_patterns_are_rejected() {
+        assert!(compile_regex(&"a".repeat(MAX_PATTERN_LEN + 1)).is_err());
+        assert!(compile_regex("(unclosed").is_err());
+        assert!(compile_regex(r"\binvoice\b").is_ok());
+    }
+}
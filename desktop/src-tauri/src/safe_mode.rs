@@ -0,0 +1,479 @@
+// Safe mode: a way back in when startup keeps failing.
+//
+// Every launch records a pending attempt in `startup.json` next to the database,
+// and the attempt is cleared when the webview sends `app://ready`. A pending attempt
+// found at launch therefore failed. After `CRASH_LOOP_THRESHOLD` consecutive failed
+// attempts the app starts in safe mode: the background starters in `FEATURES` and
+// the API sidecar are skipped (the sidecar can still be started on demand with
+// `force_restart_api`) and the main window loads with `?safe=1`. Setting the
+// `WORKANY_SAFE_MODE` environment variable forces it.
+//
+// A panic hook appends every panic, tagged with the attempt, to `startup.log`, and
+// `get_safe_mode_report` shows those from the failed attempts. The remediation
+// commands persist their change and restart into a full startup; reaching ready
+// there is what ends safe mode; a safe-mode launch reaching ready does not. Features
+// disabled this way stay off in normal launches too. Nothing here may stop the app
+// from starting: on a read-only or full disk, accounting is skipped and logged.
+
+use crate::{
+    background, backup, captures, db, db_size, deep_link, focus, focus_sessions, idle, integrity, legacy_sql,
+    media_backfill, notify, power, quota, schema_drift, splash, stall, task_metrics, trash, volumes, window,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tauri::{AppHandle, Listener, Manager, State};
+
+const RECORD_FILE: &str = "startup.json";
+const LOG_FILE: &str = "startup.log";
+pub(crate) const READY_EVENT: &str = "app://ready";
+const SAFE_MODE_ENV: &str = "WORKANY_SAFE_MODE";
+/// Consecutive failed launches before the next one starts in safe mode
+const CRASH_LOOP_THRESHOLD: usize = 3;
+/// Failed attempts remembered for the report
+const MAX_ATTEMPTS: usize = 10;
+/// The log is cut to its newer half beyond this
+const MAX_LOG_BYTES: u64 = 256 * 1024;
+
+pub(crate) const SIDECAR: &str = "sidecar";
+
+/// Background starters skipped in safe mode, by the name used to disable them
+const FEATURES: &[(&str, fn(&AppHandle))] = &[
+    ("db_size", db_size::start),
+    ("integrity", integrity::start),
+    ("backup", backup::start),
+    ("captures", captures::start),
+    ("idle", idle::start),
+    ("deep_link", deep_link::init),
+    ("stall", stall::start),
+    ("notify", notify::start),
+    ("focus", focus::start),
+    ("power", power::start),
+    ("quota", quota::start),
+    ("background", background::start),
+    ("media_backfill", media_backfill::start),
+    ("legacy_sql", legacy_sql::start),
+    ("trash", trash::start),
+    ("volumes", volumes::start),
+    ("focus_sessions", focus_sessions::start),
+    ("schema_drift", schema_drift::start),
+    ("task_metrics", task_metrics::start),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attempt {
+    pub id: String,
+    pub started_at: String,
+    pub safe_mode: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StartupRecord {
+    #[serde(default)]
+    pending: Option<Attempt>,
+    /// Consecutive failed attempts, oldest first
+    #[serde(default)]
+    failed: Vec<Attempt>,
+    #[serde(default)]
+    disabled_features: Vec<String>,
+    /// Set by remediation: the next launch starts fully even after a crash loop
+    #[serde(default)]
+    try_full_startup: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartupError {
+    pub attempt: String,
+    pub at: String,
+    pub message: String,
+    pub location: Option<String>,
+    pub thread: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedAttempt {
+    #[serde(flatten)]
+    pub attempt: Attempt,
+    /// Panics logged during the attempt; empty when it died without one
+    pub errors: Vec<StartupError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SafeModeReport {
+    pub active: bool,
+    pub reason: Option<String>,
+    pub failed_attempts: Vec<FailedAttempt>,
+    pub disabled_features: Vec<String>,
+    pub features: Vec<&'static str>,
+    pub log_path: Option<String>,
+}
+
+/// How this launch started
+pub struct SafeMode {
+    active: bool,
+    reason: Option<String>,
+    attempt_id: String,
+    dir: Option<PathBuf>,
+    disabled: Vec<String>,
+    ready: AtomicBool,
+}
+
+impl SafeMode {
+    /// Whether the optional `feature` starts in this launch
+    pub fn runs(&self, feature: &str) -> bool {
+        !self.active && !self.disabled.iter().any(|f| f == feature)
+    }
+}
+
+fn load(dir: &Path) -> StartupRecord {
+    std::fs::read(dir.join(RECORD_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save(dir: &Path, record: &StartupRecord) -> Result<(), String> {
+    let bytes = serde_json::to_vec_pretty(record).map_err(|e| e.to_string())?;
+    let tmp = dir.join(format!("{}.partial", RECORD_FILE));
+    std::fs::create_dir_all(dir)
+        .and_then(|()| std::fs::write(&tmp, bytes))
+        .and_then(|()| std::fs::rename(&tmp, dir.join(RECORD_FILE)))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            e.to_string()
+        })
+}
+
+fn append_log(dir: &Path, entry: &StartupError) {
+    let Ok(mut line) = serde_json::to_vec(entry) else { return };
+    line.push(b'\n');
+    // A panic while panicking aborts, so every failure here is swallowed
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(dir.join(LOG_FILE)) {
+        let _ = file.write_all(&line);
+    }
+}
+
+/// Keep the newer half of an oversized log
+fn trim_log(dir: &Path) {
+    let path = dir.join(LOG_FILE);
+    if std::fs::metadata(&path).map_or(true, |m| m.len() <= MAX_LOG_BYTES) {
+        return;
+    }
+    let Ok(text) = std::fs::read_to_string(&path) else { return };
+    let mut cut = text.len() / 2;
+    while !text.is_char_boundary(cut) {
+        cut += 1;
+    }
+    let kept = text[cut..].split_once('\n').map_or("", |(_, rest)| rest);
+    let tmp = dir.join(format!("{}.partial", LOG_FILE));
+    if std::fs::write(&tmp, kept).and_then(|()| std::fs::rename(&tmp, &path)).is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+}
+
+fn read_log(dir: &Path) -> Vec<StartupError> {
+    std::fs::read_to_string(dir.join(LOG_FILE))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn install_panic_hook(dir: PathBuf, attempt: String) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panic with a non-string payload".to_string());
+        splash::on_panic(&message);
+        append_log(
+            &dir,
+            &StartupError {
+                attempt: attempt.clone(),
+                at: chrono::Utc::now().to_rfc3339(),
+                message,
+                location: info.location().map(|l| format!("{}:{}", l.file(), l.line())),
+                thread: std::thread::current().name().map(str::to_string),
+            },
+        );
+        previous(info);
+    }));
+}
+
+/// Account for this launch in `dir`: an attempt still pending from the last launch
+/// counts as failed. Returns whether to start in safe mode and why.
+fn open_attempt(dir: Option<&Path>, attempt_id: &str, forced: bool) -> (bool, Option<String>, Vec<String>) {
+    let mut record = dir.map(load).unwrap_or_default();
+    if let Some(previous) = record.pending.take() {
+        record.failed.push(previous);
+        let excess = record.failed.len().saturating_sub(MAX_ATTEMPTS);
+        record.failed.drain(..excess);
+    }
+    let crash_loop = record.failed.len() >= CRASH_LOOP_THRESHOLD && !record.try_full_startup;
+    let reason = if forced {
+        Some(format!("{} is set", SAFE_MODE_ENV))
+    } else if crash_loop {
+        Some(format!("The last {} launches did not finish starting", record.failed.len()))
+    } else {
+        None
+    };
+    record.try_full_startup = false;
+    record.pending = Some(Attempt {
+        id: attempt_id.to_string(),
+        started_at: chrono::Utc::now().to_rfc3339(),
+        safe_mode: reason.is_some(),
+    });
+    if let Some(dir) = dir {
+        if let Err(e) = save(dir, &record) {
+            eprintln!("[SafeMode] Cannot record the startup attempt, crash loops won't be detected: {}", e);
+        }
+        trim_log(dir);
+    }
+    (reason.is_some(), reason, record.disabled_features)
+}
+
+/// Clear `attempt_id`; a full startup also ends the failure streak
+fn close_attempt(dir: &Path, attempt_id: &str, safe_mode: bool) -> Result<(), String> {
+    let mut record = load(dir);
+    if record.pending.as_ref().is_some_and(|p| p.id == attempt_id) {
+        record.pending = None;
+    }
+    if !safe_mode {
+        record.failed.clear();
+    }
+    save(dir, &record)
+}
+
+/// Decide how this launch starts and install the panic hook. Must run first in
+/// `run`, so failures in everything after it are counted.
+pub fn begin(identifier: &str) -> SafeMode {
+    let dir = db::db_path_for_identifier(identifier).and_then(|p| p.parent().map(Path::to_path_buf));
+    let attempt_id = format!("{}-{}", chrono::Utc::now().format("%Y%m%dT%H%M%S%3f"), std::process::id());
+    if let Some(dir) = &dir {
+        install_panic_hook(dir.clone(), attempt_id.clone());
+    }
+    let forced = std::env::var_os(SAFE_MODE_ENV).is_some();
+    let (active, reason, disabled) = open_attempt(dir.as_deref(), &attempt_id, forced);
+    if let Some(reason) = &reason {
+        eprintln!("[SafeMode] Starting in safe mode: {}", reason);
+    }
+    SafeMode {
+        active,
+        reason,
+        attempt_id,
+        dir,
+        disabled,
+        ready: AtomicBool::new(false),
+    }
+}
+
+/// Run the optional starters this launch allows, wait for `app://ready`, and point
+/// the main window at the safe-mode UI when needed
+pub fn init(app: &AppHandle) {
+    let state = app.state::<SafeMode>();
+    for (feature, start) in FEATURES {
+        if state.runs(feature) {
+            start(app);
+        } else if !state.active {
+            println!("[SafeMode] {} is disabled", feature);
+        }
+    }
+    if state.active {
+        if let Some(main) = app.get_webview_window(window::MAIN_WINDOW) {
+            if let Ok(mut url) = main.url() {
+                url.query_pairs_mut().append_pair("safe", "1");
+                if let Err(e) = main.navigate(url) {
+                    eprintln!("[SafeMode] Cannot load the safe-mode UI: {}", e);
+                }
+            }
+        }
+    }
+    let handle = app.clone();
+    app.listen_any(READY_EVENT, move |_| {
+        let state = handle.state::<SafeMode>();
+        if state.ready.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        if let Some(dir) = &state.dir {
+            match close_attempt(dir, &state.attempt_id, state.active) {
+                Ok(()) => println!("[SafeMode] Startup {} finished", state.attempt_id),
+                Err(e) => eprintln!("[SafeMode] Cannot clear the startup attempt: {}", e),
+            }
+        }
+    });
+}
+
+fn known(feature: &str) -> bool {
+    feature == SIDECAR || FEATURES.iter().any(|(name, _)| *name == feature)
+}
+
+/// Persist `change` and restart into a full startup
+fn restart_with(app: &AppHandle, change: impl FnOnce(&mut StartupRecord)) -> Result<(), String> {
+    let state = app.state::<SafeMode>();
+    let dir = state.dir.as_deref().ok_or("No config directory to record the change in")?;
+    let mut record = load(dir);
+    change(&mut record);
+    // The restart is deliberate, not a failed launch
+    record.pending = None;
+    record.try_full_startup = true;
+    save(dir, &record).map_err(|e| format!("Cannot save the startup record: {}", e))?;
+    println!("[SafeMode] Restarting into a full startup");
+    app.restart()
+}
+
+#[tauri::command]
+pub fn get_safe_mode_report(state: State<'_, SafeMode>) -> SafeModeReport {
+    let (record, log) = match &state.dir {
+        Some(dir) => (load(dir), read_log(dir)),
+        None => (StartupRecord::default(), Vec::new()),
+    };
+    let failed_attempts = record
+        .failed
+        .into_iter()
+        .map(|attempt| FailedAttempt {
+            errors: log.iter().filter(|e| e.attempt == attempt.id).cloned().collect(),
+            attempt,
+        })
+        .collect();
+    SafeModeReport {
+        active: state.active,
+        reason: state.reason.clone(),
+        failed_attempts,
+        disabled_features: record.disabled_features,
+        features: FEATURES.iter().map(|(name, _)| *name).chain([SIDECAR]).collect(),
+        log_path: state.dir.as_ref().map(|d| d.join(LOG_FILE).to_string_lossy().into_owned()),
+    }
+}
+
+/// Turn off an optional startup feature (see `features` in the report) and restart
+#[tauri::command]
+pub fn disable_feature_and_restart(app: AppHandle, feature: String) -> Result<(), String> {
+    if !known(&feature) {
+        return Err(format!("Unknown startup feature '{}'", feature));
+    }
+    restart_with(&app, |record| {
+        if !record.disabled_features.contains(&feature) {
+            record.disabled_features.push(feature);
+        }
+    })
+}
+
+/// Turn a feature disabled through safe mode back on from the next launch
+#[tauri::command]
+pub fn enable_startup_feature(state: State<'_, SafeMode>, feature: String) -> Result<(), String> {
+    let dir = state.dir.as_deref().ok_or("No config directory to record the change in")?;
+    let mut record = load(dir);
+    record.disabled_features.retain(|f| *f != feature);
+    save(dir, &record)
+}
+
+/// Delete a saved setting, so its default applies, and restart
+#[tauri::command]
+pub async fn reset_setting_and_restart(app: AppHandle, key: String) -> Result<(), String> {
+    let name = key.clone();
+    let removed = db::with_conn(&app, move |conn| {
+        conn.execute("DELETE FROM settings WHERE key = ?1", [&name]).map_err(|e| e.to_string())
+    })
+    .await?;
+    if removed == 0 {
+        return Err(format!("No saved setting named '{}'", key));
+    }
+    println!("[SafeMode] Reset setting '{}'", key);
+    restart_with(&app, |_| {})
+}
+
+/// Leave safe mode without changing anything and try a full startup again
+#[tauri::command]
+pub fn retry_full_startup(app: AppHandle) -> Result<(), String> {
+    restart_with(&app, |_| {})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workany-safe-mode-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn three_failed_launches_start_safe_mode_until_a_full_startup() {
+        let dir = temp_dir("loop");
+        for n in 0..CRASH_LOOP_THRESHOLD {
+            let (active, _, _) = open_attempt(Some(&dir), &format!("a{}", n), false);
+            assert!(!active, "launch {} started in safe mode", n);
+        }
+        let (active, reason, _) = open_attempt(Some(&dir), "safe", false);
+        assert!(active);
+        assert!(reason.unwrap().contains("3 launches"));
+
+        // Reaching ready in safe mode doesn't end it
+        close_attempt(&dir, "safe", true).unwrap();
+        assert!(open_attempt(Some(&dir), "safe2", false).0);
+
+        // A remediation restart tries a full startup, and its ready clears the streak
+        let mut record = load(&dir);
+        record.pending = None;
+        record.try_full_startup = true;
+        save(&dir, &record).unwrap();
+        assert!(!open_attempt(Some(&dir), "full", false).0);
+        close_attempt(&dir, "full", false).unwrap();
+        assert!(load(&dir).failed.is_empty());
+        assert!(!open_attempt(Some(&dir), "next", false).0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unwritable_or_corrupt_state_never_blocks_startup() {
+        let dir = temp_dir("broken");
+        std::fs::write(dir.join(RECORD_FILE), b"{not json").unwrap();
+        assert!(!open_attempt(Some(&dir), "a", false).0);
+        assert_eq!(load(&dir).pending.unwrap().id, "a");
+
+        // A "directory" that is really a file: every write fails
+        let blocked = dir.join("file");
+        std::fs::write(&blocked, b"").unwrap();
+        assert!(!open_attempt(Some(&blocked), "b", false).0);
+        let entry = StartupError {
+            attempt: "b".into(),
+            at: String::new(),
+            message: "boom".into(),
+            location: None,
+            thread: None,
+        };
+        append_log(&blocked, &entry);
+        assert!(open_attempt(None, "c", true).0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn panics_are_logged_against_their_attempt() {
+        let dir = temp_dir("log");
+        for (attempt, message) in [("a1", "bad setting"), ("a2", "watcher path")] {
+            let entry = StartupError {
+                attempt: attempt.into(),
+                at: String::new(),
+                message: message.into(),
+                location: Some("src/lib.rs:1".into()),
+                thread: Some("main".into()),
+            };
+            append_log(&dir, &entry);
+        }
+        let log = read_log(&dir);
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[1].attempt, "a2");
+
+        std::fs::write(dir.join(LOG_FILE), "x\n".repeat(MAX_LOG_BYTES as usize)).unwrap();
+        trim_log(&dir);
+        assert!(std::fs::metadata(dir.join(LOG_FILE)).unwrap().len() <= MAX_LOG_BYTES);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,726 @@
+// Saved task list filters, evaluated in SQLite.
+//
+// A filter definition is structured data, validated before it is stored or run.
+// `compile` turns it into one WHERE clause over `tasks` plus positional parameters:
+// the SQL text only ever comes from the fixed fragments below, and every value from
+// the definition (statuses, tag names, dates, the text query) is bound as a
+// parameter. The text component reuses the task list search, including its FTS
+// lookups. Date ranges go through `created_epoch` like the timeline, so they use
+// `idx_tasks_timeline`.
+//
+// `evaluate_filter` pages by `(created_epoch, id)` newest first and returns the
+// total count. It also reports the plan steps that scan a table instead of
+// searching an index, so slow filters show which index is missing.
+//
+// Saved filters are ordered by `pinned` then `position`, which is the order the
+// sidebar and the command palette list them in.
+
+use crate::{db, tasks, timeline};
+use chrono::{Duration as ChronoDuration, Local, NaiveDate, TimeZone};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, ErrorCode};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+const STATUSES: &[&str] = &["running", "completed", "error", "stopped"];
+const MAX_NAME_CHARS: usize = 100;
+const MAX_TAGS: usize = 20;
+const MAX_TEXT_CHARS: usize = 500;
+const MAX_LAST_DAYS: u32 = 3650;
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 200;
+
+const TAG_ALL_CLAUSE: &str =
+    "id IN (SELECT tt.task_id FROM task_tags tt JOIN tags t ON t.id = tt.tag_id WHERE t.name = ?)";
+const DELIVERABLES_CLAUSE: &str = "EXISTS (SELECT 1 FROM files f WHERE f.task_id = tasks.id
+                                          AND f.type IN ('document', 'presentation', 'spreadsheet', 'website')
+                                          AND f.source_file_id IS NULL)";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagMatch {
+    /// Tasks carrying every listed tag
+    #[default]
+    All,
+    /// Tasks carrying at least one
+    Any,
+}
+
+/// Local creation dates; either `last_days` or `from`/`to`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DateFilter {
+    /// `YYYY-MM-DD`, inclusive
+    pub from: Option<String>,
+    /// `YYYY-MM-DD`, inclusive
+    pub to: Option<String>,
+    /// The last N local days, today included
+    pub last_days: Option<u32>,
+}
+
+/// Inclusive bounds; tasks without a value never match a bounded range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Range<T> {
+    pub min: Option<T>,
+    pub max: Option<T>,
+}
+
+/// What a filter matches; every field that is set must match
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterDefinition {
+    /// Any of these
+    pub statuses: Vec<String>,
+    pub tags: Vec<String>,
+    pub tag_match: TagMatch,
+    pub session_id: Option<String>,
+    pub created: Option<DateFilter>,
+    /// Dollars
+    pub cost: Option<Range<f64>>,
+    /// In the units of `tasks.duration`
+    pub duration: Option<Range<i64>>,
+    pub favorite: Option<bool>,
+    pub has_deliverables: Option<bool>,
+    /// Matched like the task list search box
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedFilter {
+    pub id: i64,
+    pub name: String,
+    pub definition: FilterDefinition,
+    pub pinned: bool,
+    pub position: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedFilterInput {
+    pub name: String,
+    pub definition: FilterDefinition,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilteredTask {
+    pub id: String,
+    pub prompt: String,
+    pub status: String,
+    pub cost: Option<f64>,
+    pub duration: Option<i64>,
+    pub session_id: Option<String>,
+    pub favorite: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterPage {
+    /// Newest first
+    pub tasks: Vec<FilteredTask>,
+    /// Matching tasks across all pages
+    pub total: i64,
+    /// Pass back to get the next page; `None` on the last page
+    pub next_cursor: Option<String>,
+    /// Query plan steps that scan rather than search an index; empty when the
+    /// filter is fully indexed
+    pub full_scans: Vec<String>,
+}
+
+/// A WHERE clause over `tasks` and its positional parameters
+#[derive(Debug)]
+struct Compiled {
+    sql: String,
+    params: Vec<Value>,
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("Invalid date: {}", value))
+}
+
+fn validate_range<T: PartialOrd + Copy>(name: &str, range: &Range<T>, zero: T) -> Result<(), String> {
+    for bound in [range.min, range.max].into_iter().flatten() {
+        // NaN has no ordering, so it is rejected here too
+        if matches!(bound.partial_cmp(&zero), None | Some(Ordering::Less)) {
+            return Err(format!("The {} range must not be negative", name));
+        }
+    }
+    if let (Some(min), Some(max)) = (range.min, range.max) {
+        if min > max {
+            return Err(format!("The {} range's minimum is above its maximum", name));
+        }
+    }
+    Ok(())
+}
+
+fn validate(definition: &FilterDefinition) -> Result<(), String> {
+    if let Some(status) = definition.statuses.iter().find(|s| !STATUSES.contains(&s.as_str())) {
+        return Err(format!("Unknown status: {}", status));
+    }
+    if definition.tags.len() > MAX_TAGS {
+        return Err(format!("A filter can match at most {} tags", MAX_TAGS));
+    }
+    if definition.tags.iter().any(|tag| tag.trim().is_empty()) {
+        return Err("Tag names must not be empty".to_string());
+    }
+    if definition.text.as_deref().is_some_and(|text| text.chars().count() > MAX_TEXT_CHARS) {
+        return Err(format!("The text query is limited to {} characters", MAX_TEXT_CHARS));
+    }
+    if let Some(created) = &definition.created {
+        match created.last_days {
+            Some(_) if created.from.is_some() || created.to.is_some() => {
+                return Err("Use either last_days or from/to for the date range".to_string());
+            }
+            Some(days) if !(1..=MAX_LAST_DAYS).contains(&days) => {
+                return Err(format!("last_days must be between 1 and {}", MAX_LAST_DAYS));
+            }
+            _ => {}
+        }
+        let from = created.from.as_deref().map(parse_date).transpose()?;
+        let to = created.to.as_deref().map(parse_date).transpose()?;
+        if let (Some(from), Some(to)) = (from, to) {
+            if from > to {
+                return Err("The date range starts after it ends".to_string());
+            }
+        }
+    }
+    if let Some(cost) = &definition.cost {
+        validate_range("cost", cost, 0.0)?;
+    }
+    if let Some(duration) = &definition.duration {
+        validate_range("duration", duration, 0)?;
+    }
+    Ok(())
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+/// Compile a validated definition. `today` and `tz` resolve the date range.
+fn compile<Tz: TimeZone>(definition: &FilterDefinition, tz: &Tz, today: NaiveDate) -> Result<Compiled, String> {
+    validate(definition)?;
+    // Trashed tasks only show in the trash
+    let mut clauses = vec!["trashed_at IS NULL".to_string()];
+    let mut params = Vec::new();
+
+    if !definition.statuses.is_empty() {
+        clauses.push(format!("status IN ({})", placeholders(definition.statuses.len())));
+        params.extend(definition.statuses.iter().map(|s| Value::Text(s.clone())));
+    }
+    let tags: Vec<Value> = definition.tags.iter().map(|t| Value::Text(t.trim().to_string())).collect();
+    match definition.tag_match {
+        _ if tags.is_empty() => {}
+        TagMatch::All => {
+            for tag in tags {
+                clauses.push(TAG_ALL_CLAUSE.to_string());
+                params.push(tag);
+            }
+        }
+        TagMatch::Any => {
+            clauses.push(format!(
+                "id IN (SELECT tt.task_id FROM task_tags tt JOIN tags t ON t.id = tt.tag_id WHERE t.name IN ({}))",
+                placeholders(tags.len())
+            ));
+            params.extend(tags);
+        }
+    }
+    if let Some(session_id) = &definition.session_id {
+        clauses.push("session_id = ?".to_string());
+        params.push(Value::Text(session_id.clone()));
+    }
+    if let Some(created) = &definition.created {
+        let (from, to) = match created.last_days {
+            Some(days) => (Some(today - ChronoDuration::days(days as i64 - 1)), None),
+            None => (
+                created.from.as_deref().map(parse_date).transpose()?,
+                created.to.as_deref().map(parse_date).transpose()?,
+            ),
+        };
+        if let Some(from) = from {
+            clauses.push("created_epoch >= ?".to_string());
+            params.push(Value::Integer(timeline::day_bounds(tz, from).0));
+        }
+        if let Some(to) = to {
+            clauses.push("created_epoch < ?".to_string());
+            params.push(Value::Integer(timeline::day_bounds(tz, to).1));
+        }
+    }
+    for (column, min, max) in [
+        ("cost", definition.cost.and_then(|r| r.min).map(Value::Real), definition.cost.and_then(|r| r.max).map(Value::Real)),
+        (
+            "duration",
+            definition.duration.and_then(|r| r.min).map(Value::Integer),
+            definition.duration.and_then(|r| r.max).map(Value::Integer),
+        ),
+    ] {
+        if let Some(min) = min {
+            clauses.push(format!("{} >= ?", column));
+            params.push(min);
+        }
+        if let Some(max) = max {
+            clauses.push(format!("{} <= ?", column));
+            params.push(max);
+        }
+    }
+    if let Some(favorite) = definition.favorite {
+        clauses.push("COALESCE(favorite, 0) = ?".to_string());
+        params.push(Value::Integer(favorite as i64));
+    }
+    match definition.has_deliverables {
+        Some(true) => clauses.push(DELIVERABLES_CLAUSE.to_string()),
+        Some(false) => clauses.push(format!("NOT {}", DELIVERABLES_CLAUSE)),
+        None => {}
+    }
+    if let Some(text) = definition.text.as_deref().filter(|t| !t.trim().is_empty()) {
+        clauses.push(tasks::SEARCH_CLAUSE.to_string());
+        params.extend(tasks::search_params(text));
+    }
+    Ok(Compiled {
+        sql: clauses.join(" AND "),
+        params,
+    })
+}
+
+/// Plan steps of the page query that walk a whole table or index
+fn full_scans(conn: &Connection, compiled: &Compiled) -> Result<Vec<String>, String> {
+    let sql = format!(
+        "EXPLAIN QUERY PLAN SELECT id FROM tasks WHERE {} ORDER BY created_epoch DESC, id DESC",
+        compiled.sql
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let details = stmt
+        .query_map(params_from_iter(compiled.params.iter()), |row| row.get::<_, String>(3))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    // FTS lookups show as scans of the virtual table but use the full-text index
+    Ok(details
+        .into_iter()
+        .filter(|d| d.starts_with("SCAN ") && !d.contains("VIRTUAL TABLE") && !d.contains("CONSTANT ROW"))
+        .collect())
+}
+
+/// Position after the last task of a page: `epoch:id`
+fn parse_cursor(cursor: &str) -> Result<(i64, String), String> {
+    let (epoch, id) = cursor.split_once(':').ok_or("Invalid filter cursor")?;
+    Ok((epoch.parse().map_err(|_| "Invalid filter cursor")?, id.to_string()))
+}
+
+fn evaluate<Tz: TimeZone>(
+    conn: &Connection,
+    definition: &FilterDefinition,
+    tz: &Tz,
+    today: NaiveDate,
+    cursor: Option<&str>,
+    limit: u32,
+) -> Result<FilterPage, String> {
+    let compiled = compile(definition, tz, today)?;
+    let limit = limit.clamp(1, MAX_LIMIT);
+
+    let total: i64 = conn
+        .query_row(
+            &format!("SELECT COUNT(*) FROM tasks WHERE {}", compiled.sql),
+            params_from_iter(compiled.params.iter()),
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut params = compiled.params.clone();
+    let after = match cursor {
+        Some(cursor) => {
+            let (epoch, id) = parse_cursor(cursor)?;
+            params.push(Value::Integer(epoch));
+            params.push(Value::Text(id));
+            "AND (created_epoch, id) < (?, ?)"
+        }
+        None => "",
+    };
+    params.push(Value::Integer(limit as i64 + 1));
+    let sql = format!(
+        "SELECT id, prompt, status, cost, duration, session_id, COALESCE(favorite, 0), created_at, updated_at,
+                created_epoch
+         FROM tasks WHERE {} {}
+         ORDER BY created_epoch DESC, id DESC LIMIT ?",
+        compiled.sql, after
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params_from_iter(params), |row| {
+            Ok((
+                FilteredTask {
+                    id: row.get(0)?,
+                    prompt: row.get(1)?,
+                    status: row.get(2)?,
+                    cost: row.get(3)?,
+                    duration: row.get(4)?,
+                    session_id: row.get(5)?,
+                    favorite: row.get(6)?,
+                    created_at: row.get(7)?,
+                    updated_at: row.get(8)?,
+                },
+                row.get::<_, i64>(9)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let has_more = rows.len() > limit as usize;
+    let mut tasks = Vec::with_capacity(rows.len());
+    let mut last = None;
+    for (task, epoch) in rows.into_iter().take(limit as usize) {
+        last = Some(format!("{}:{}", epoch, task.id));
+        tasks.push(task);
+    }
+    let full_scans = full_scans(conn, &compiled)?;
+    if !full_scans.is_empty() {
+        eprintln!("[SavedFilters] Filter scans instead of using an index: {}", full_scans.join("; "));
+    }
+    Ok(FilterPage {
+        tasks,
+        total,
+        next_cursor: if has_more { last } else { None },
+        full_scans,
+    })
+}
+
+fn load_filters(conn: &Connection) -> Result<Vec<SavedFilter>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, name, definition, pinned, position, created_at, updated_at
+             FROM saved_filters ORDER BY pinned DESC, position, id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, bool>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    let mut filters = Vec::new();
+    for row in rows {
+        let (id, name, definition, pinned, position, created_at, updated_at) = row.map_err(|e| e.to_string())?;
+        filters.push(SavedFilter {
+            id,
+            name,
+            definition: serde_json::from_str(&definition)
+                .map_err(|e| format!("Saved filter {} is malformed: {}", id, e))?,
+            pinned,
+            position,
+            created_at,
+            updated_at,
+        });
+    }
+    Ok(filters)
+}
+
+fn load_filter(conn: &Connection, id: i64) -> Result<SavedFilter, String> {
+    load_filters(conn)?
+        .into_iter()
+        .find(|f| f.id == id)
+        .ok_or_else(|| format!("Saved filter {} not found", id))
+}
+
+fn validate_input(input: &SavedFilterInput) -> Result<String, String> {
+    let name = input.name.trim();
+    if name.is_empty() {
+        return Err("A saved filter needs a name".to_string());
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!("Saved filter names are limited to {} characters", MAX_NAME_CHARS));
+    }
+    validate(&input.definition)?;
+    Ok(name.to_string())
+}
+
+fn name_taken(name: &str) -> impl Fn(rusqlite::Error) -> String + '_ {
+    move |e| match e.sqlite_error_code() {
+        Some(ErrorCode::ConstraintViolation) => format!("A saved filter named \"{}\" already exists", name),
+        _ => e.to_string(),
+    }
+}
+
+#[tauri::command]
+pub async fn list_saved_filters(app: tauri::AppHandle) -> Result<Vec<SavedFilter>, String> {
+    db::with_conn(&app, |conn| load_filters(conn)).await
+}
+
+/// Add a filter at the end of its group (pinned or not)
+#[tauri::command]
+pub async fn create_saved_filter(app: tauri::AppHandle, filter: SavedFilterInput) -> Result<SavedFilter, String> {
+    db::with_conn(&app, move |conn| {
+        let name = validate_input(&filter)?;
+        conn.execute(
+            "INSERT INTO saved_filters (name, definition, pinned, position)
+             VALUES (?1, ?2, ?3, (SELECT COALESCE(MAX(position), -1) + 1 FROM saved_filters))",
+            params![
+                name,
+                serde_json::to_string(&filter.definition).map_err(|e| e.to_string())?,
+                filter.pinned,
+            ],
+        )
+        .map_err(name_taken(&name))?;
+        load_filter(conn, conn.last_insert_rowid())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn update_saved_filter(
+    app: tauri::AppHandle,
+    id: i64,
+    filter: SavedFilterInput,
+) -> Result<SavedFilter, String> {
+    db::with_conn(&app, move |conn| {
+        let name = validate_input(&filter)?;
+        let changed = conn
+            .execute(
+                "UPDATE saved_filters SET name = ?1, definition = ?2, pinned = ?3, updated_at = datetime('now')
+                 WHERE id = ?4",
+                params![
+                    name,
+                    serde_json::to_string(&filter.definition).map_err(|e| e.to_string())?,
+                    filter.pinned,
+                    id,
+                ],
+            )
+            .map_err(name_taken(&name))?;
+        if changed == 0 {
+            return Err(format!("Saved filter {} not found", id));
+        }
+        load_filter(conn, id)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_saved_filter(app: tauri::AppHandle, id: i64) -> Result<bool, String> {
+    db::with_conn(&app, move |conn| {
+        conn.execute("DELETE FROM saved_filters WHERE id = ?1", [id])
+            .map(|n| n > 0)
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Set the sidebar order; `ids` must list every saved filter exactly once. Pinned
+/// filters still come first.
+#[tauri::command]
+pub async fn reorder_saved_filters(app: tauri::AppHandle, ids: Vec<i64>) -> Result<Vec<SavedFilter>, String> {
+    db::with_conn(&app, move |conn| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut existing: Vec<i64> = load_filters(&tx)?.into_iter().map(|f| f.id).collect();
+        let mut requested = ids.clone();
+        existing.sort_unstable();
+        requested.sort_unstable();
+        if existing != requested {
+            return Err("Reorder must list every saved filter exactly once".to_string());
+        }
+        for (position, id) in ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE saved_filters SET position = ?1, updated_at = datetime('now') WHERE id = ?2",
+                params![position as i64, id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        load_filters(conn)
+    })
+    .await
+}
+
+/// One page of the tasks matching a saved filter (`filter_id`) or an unsaved
+/// `definition`, newest first, with the total count
+#[tauri::command]
+pub async fn evaluate_filter(
+    app: tauri::AppHandle,
+    filter_id: Option<i64>,
+    definition: Option<FilterDefinition>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+) -> Result<FilterPage, String> {
+    db::with_conn(&app, move |conn| {
+        let definition = match (filter_id, definition) {
+            (Some(id), None) => load_filter(conn, id)?.definition,
+            (None, Some(definition)) => definition,
+            _ => return Err("Pass either a saved filter id or a definition".to_string()),
+        };
+        evaluate(
+            conn,
+            &definition,
+            &Local,
+            Local::now().date_naive(),
+            cursor.as_deref(),
+            limit.unwrap_or(DEFAULT_LIMIT),
+        )
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 10).unwrap()
+    }
+
+    /// a: completed, $2, favorite, tagged client-x, with a report, on Mar 9
+    /// b: error, $0.50, tagged client-x and urgent, session s2, on Mar 10
+    /// c: error, $1.50, untagged, a note about an invoice, on Jan 5
+    /// d: completed, no cost, trashed
+    fn fixture() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch(
+            "INSERT INTO tasks (id, prompt, status, cost, duration, session_id, favorite, created_at) VALUES
+                ('a', 'Quarterly report for client X', 'completed', 2.0, 90000, 's1', 1, '2024-03-09 10:00:00'),
+                ('b', 'Fix the deploy script', 'error', 0.5, 4000, 's2', 0, '2024-03-10 08:00:00'),
+                ('c', 'Summarise emails', 'error', 1.5, 20000, 's1', 0, '2024-01-05 12:00:00'),
+                ('d', 'Old draft', 'completed', NULL, NULL, 's1', 0, '2024-03-08 12:00:00');
+             UPDATE tasks SET trashed_at = datetime('now') WHERE id = 'd';
+             INSERT INTO tags (id, name) VALUES (1, 'client-x'), (2, 'urgent');
+             INSERT INTO task_tags (task_id, tag_id) VALUES ('a', 1), ('b', 1), ('b', 2), ('d', 1);
+             INSERT INTO files (task_id, name, type, path) VALUES ('a', 'report.docx', 'document', '/tmp/r');
+             INSERT INTO task_notes (task_id, content) VALUES ('c', 'Waiting on the invoice from accounting');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn ids(conn: &Connection, definition: FilterDefinition) -> Vec<String> {
+        let page = evaluate(conn, &definition, &Utc, today(), None, 100).unwrap();
+        assert_eq!(page.total as usize, page.tasks.len());
+        page.tasks.into_iter().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn each_predicate_matches_on_its_own() {
+        let conn = fixture();
+        let all = FilterDefinition::default;
+        assert_eq!(ids(&conn, all()), vec!["b", "a", "c"]);
+        let statuses = vec!["error".to_string()];
+        assert_eq!(ids(&conn, FilterDefinition { statuses, ..all() }), vec!["b", "c"]);
+        let tags = vec!["Client-X".to_string(), "urgent".to_string()];
+        assert_eq!(ids(&conn, FilterDefinition { tags: tags.clone(), ..all() }), vec!["b"]);
+        let any = FilterDefinition { tags, tag_match: TagMatch::Any, ..all() };
+        assert_eq!(ids(&conn, any), vec!["b", "a"]);
+        let session_id = Some("s1".to_string());
+        assert_eq!(ids(&conn, FilterDefinition { session_id, ..all() }), vec!["a", "c"]);
+        let created = Some(DateFilter { last_days: Some(2), ..Default::default() });
+        assert_eq!(ids(&conn, FilterDefinition { created, ..all() }), vec!["b", "a"]);
+        let created = Some(DateFilter { to: Some("2024-03-09".to_string()), ..Default::default() });
+        assert_eq!(ids(&conn, FilterDefinition { created, ..all() }), vec!["a", "c"]);
+        let cost = Some(Range { min: Some(1.0), max: None });
+        assert_eq!(ids(&conn, FilterDefinition { cost, ..all() }), vec!["a", "c"]);
+        let duration = Some(Range { min: Some(5000), max: Some(30000) });
+        assert_eq!(ids(&conn, FilterDefinition { duration, ..all() }), vec!["c"]);
+        assert_eq!(ids(&conn, FilterDefinition { favorite: Some(true), ..all() }), vec!["a"]);
+        assert_eq!(ids(&conn, FilterDefinition { has_deliverables: Some(true), ..all() }), vec!["a"]);
+        assert_eq!(ids(&conn, FilterDefinition { has_deliverables: Some(false), ..all() }), vec!["b", "c"]);
+        let text = Some("REPORT".to_string());
+        assert_eq!(ids(&conn, FilterDefinition { text, ..all() }), vec!["a"]);
+        // Found through the notes' full-text index
+        let text = Some("invoice".to_string());
+        assert_eq!(ids(&conn, FilterDefinition { text, ..all() }), vec!["c"]);
+    }
+
+    #[test]
+    fn predicates_combine() {
+        let conn = fixture();
+        let failed_client_work = FilterDefinition {
+            statuses: vec!["error".to_string()],
+            tags: vec!["client-x".to_string()],
+            created: Some(DateFilter { last_days: Some(30), ..Default::default() }),
+            cost: Some(Range { min: Some(0.25), max: Some(1.0) }),
+            ..Default::default()
+        };
+        assert_eq!(ids(&conn, failed_client_work), vec!["b"]);
+        let none = FilterDefinition {
+            favorite: Some(true),
+            statuses: vec!["error".to_string()],
+            ..Default::default()
+        };
+        assert!(ids(&conn, none).is_empty());
+    }
+
+    #[test]
+    fn user_text_is_never_sql() {
+        let conn = fixture();
+        for text in ["'; DROP TABLE tasks; --", "report\" OR 1=1 --", "NEAR(a b) OR *", "%_\\"] {
+            let definition = FilterDefinition {
+                text: Some(text.to_string()),
+                tags: vec![text.to_string()],
+                session_id: Some(text.to_string()),
+                ..Default::default()
+            };
+            assert!(ids(&conn, definition).is_empty(), "{}", text);
+        }
+        assert_eq!(ids(&conn, FilterDefinition::default()).len(), 3);
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        let conn = fixture();
+        let invalid = [
+            serde_json::json!({"statuses": ["done"]}),
+            serde_json::json!({"cost": {"min": 2.0, "max": 1.0}}),
+            serde_json::json!({"duration": {"min": -1}}),
+            serde_json::json!({"created": {"last_days": 7, "from": "2024-01-01"}}),
+            serde_json::json!({"created": {"from": "2024-02-01", "to": "2024-01-01"}}),
+            serde_json::json!({"created": {"from": "yesterday"}}),
+            serde_json::json!({"tags": [" "]}),
+        ];
+        for value in invalid {
+            let definition: FilterDefinition = serde_json::from_value(value.clone()).unwrap();
+            assert!(evaluate(&conn, &definition, &Utc, today(), None, 10).is_err(), "{}", value);
+        }
+        assert!(serde_json::from_value::<FilterDefinition>(serde_json::json!({"project": "x"})).is_err());
+    }
+
+    #[test]
+    fn pages_follow_the_cursor_and_keep_the_total() {
+        let conn = fixture();
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = evaluate(&conn, &FilterDefinition::default(), &Utc, today(), cursor.as_deref(), 1).unwrap();
+            assert_eq!(page.total, 3);
+            seen.extend(page.tasks.into_iter().map(|t| t.id));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn unindexed_filters_are_reported() {
+        let conn = fixture();
+        let by_session = FilterDefinition {
+            session_id: Some("s1".to_string()),
+            ..Default::default()
+        };
+        let page = evaluate(&conn, &by_session, &Utc, today(), None, 10).unwrap();
+        assert!(page.full_scans.is_empty(), "{:?}", page.full_scans);
+        let by_status = FilterDefinition {
+            statuses: vec!["error".to_string()],
+            ..Default::default()
+        };
+        let page = evaluate(&conn, &by_status, &Utc, today(), None, 10).unwrap();
+        assert!(!page.full_scans.is_empty());
+    }
+}
@@ -0,0 +1,601 @@
+// Cron expressions for recurring tasks: parsing, zone-aware next runs and
+// descriptions.
+//
+// Expressions have the five standard fields (minute, hour, day of month, month, day
+// of week) with lists, ranges, steps and three-letter names, or one of `@hourly`,
+// `@daily`, `@weekly`, `@monthly` and `@yearly`. As in Vixie cron, when both day
+// fields are restricted a day matches if either does.
+//
+// Fire times are computed in an IANA time zone, never the process's local offset.
+// Around DST changes:
+// - a time that doesn't exist (spring forward) fires once, at the end of the gap;
+//   several times inside one gap fire together at that instant
+// - a time that happens twice (fall back) fires once, at its first occurrence, and
+//   the repeated hour is not run again
+//
+// Descriptions are a locale catalog key (`schedule.*`) with parameters, plus English
+// text for logs.
+//
+// This crate has no recurring-task scheduler and no `schedules` table yet, so only
+// `preview_schedule` uses this. There is no per-schedule timezone column to add,
+// no rows to backfill, and no stored `next_run_at` to compute. When a scheduler
+// lands, it should store an IANA zone per schedule, backfill existing rows with
+// `system_zone()`, and compute `next_run_at` with `CronSchedule::next_after` in
+// that zone.
+
+use crate::error::CommandError;
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// How far ahead to look before deciding an expression never fires (e.g. `0 0 30 2 *`)
+const MAX_SEARCH_DAYS: i64 = 366 * 8;
+const MAX_PREVIEW: u32 = 50;
+
+const MONTH_NAMES: &[&str] = &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const DAY_NAMES: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const DAY_LABELS: &[&str] = &["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+const MONTH_LABELS: &[&str] = &[
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October",
+    "November", "December",
+];
+
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    /// Names for `min..`, matched case-insensitively
+    names: &'static [&'static str],
+}
+
+const FIELDS: [Field; 5] = [
+    Field { name: "minute", min: 0, max: 59, names: &[] },
+    Field { name: "hour", min: 0, max: 23, names: &[] },
+    Field { name: "day of month", min: 1, max: 31, names: &[] },
+    Field { name: "month", min: 1, max: 12, names: MONTH_NAMES },
+    // 7 is accepted as Sunday and folded onto 0
+    Field { name: "day of week", min: 0, max: 7, names: DAY_NAMES },
+];
+
+/// A parse failure, located by byte offsets into the expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl CronError {
+    fn new(message: impl Into<String>, start: usize, end: usize) -> Self {
+        CronError {
+            message: message.into(),
+            start,
+            end,
+        }
+    }
+
+    /// As a command error, with the span converted to characters for the editor
+    fn into_command_error(self, expression: &str) -> CommandError {
+        let chars = |bytes: usize| expression.get(..bytes).map_or(bytes, |s| s.chars().count());
+        let position = chars(self.start);
+        CommandError::InvalidSchedule {
+            message: self.message,
+            position,
+            length: chars(self.end).saturating_sub(position).max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    /// Bit n set when value n matches
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+fn bits(values: impl IntoIterator<Item = u32>) -> u64 {
+    values.into_iter().fold(0, |acc, v| acc | (1 << v))
+}
+
+fn is_set(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn set_values(mask: u64) -> Vec<u32> {
+    (0..64).filter(|v| is_set(mask, *v)).collect()
+}
+
+fn parse_value(field: &Field, text: &str, start: usize) -> Result<u32, CronError> {
+    let end = start + text.len();
+    if let Some(index) = field.names.iter().position(|n| n.eq_ignore_ascii_case(text)) {
+        return Ok(field.min + index as u32);
+    }
+    let value: u32 = text.parse().map_err(|_| {
+        if text.is_empty() {
+            CronError::new(format!("Missing {} value", field.name), start, end.max(start + 1))
+        } else {
+            CronError::new(format!("'{}' is not a valid {}", text, field.name), start, end)
+        }
+    })?;
+    if value < field.min || value > field.max {
+        return Err(CronError::new(
+            format!("{} {} is out of range {}-{}", field.name, value, field.min, field.max),
+            start,
+            end,
+        ));
+    }
+    Ok(value)
+}
+
+/// One field's mask, and whether it was anything but `*`
+fn parse_field(field: &Field, text: &str, offset: usize) -> Result<(u64, bool), CronError> {
+    let mut mask = 0;
+    let mut part_start = offset;
+    for part in text.split(',') {
+        let part_end = part_start + part.len();
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step_start = part_start + range.len() + 1;
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| CronError::new("Step must be a number above 0", step_start, part_end.max(step_start + 1)))?;
+                (range, Some(step))
+            }
+            None => (part, None),
+        };
+        let (low, high) = if range == "*" {
+            (field.min, field.max)
+        } else if let Some((low, high)) = range.split_once('-') {
+            let low_value = parse_value(field, low, part_start)?;
+            let high_value = parse_value(field, high, part_start + low.len() + 1)?;
+            if low_value > high_value {
+                return Err(CronError::new(
+                    format!("Range {} runs backwards", range),
+                    part_start,
+                    part_start + range.len(),
+                ));
+            }
+            (low_value, high_value)
+        } else {
+            let value = parse_value(field, range, part_start)?;
+            // `5/15` means from 5 to the end in steps of 15
+            (value, if step.is_some() { field.max } else { value })
+        };
+        let step = step.unwrap_or(1) as usize;
+        mask |= bits((low..=high).step_by(step));
+        part_start = part_end + 1;
+    }
+    Ok((mask, text != "*"))
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, CronError> {
+        let trimmed = expression.trim();
+        let expanded = match trimmed.to_ascii_lowercase().as_str() {
+            "@hourly" => Some("0 * * * *"),
+            "@daily" | "@midnight" => Some("0 0 * * *"),
+            "@weekly" => Some("0 0 * * 0"),
+            "@monthly" => Some("0 0 1 * *"),
+            "@yearly" | "@annually" => Some("0 0 1 1 *"),
+            other if other.starts_with('@') => {
+                let start = expression.len() - expression.trim_start().len();
+                return Err(CronError::new(
+                    format!("Unknown shortcut {}", trimmed),
+                    start,
+                    start + trimmed.len(),
+                ));
+            }
+            _ => None,
+        };
+        if let Some(expanded) = expanded {
+            return Self::parse(expanded);
+        }
+
+        // Fields with their byte offsets into `expression`
+        let mut fields = Vec::new();
+        let mut start = None;
+        for (i, c) in expression.char_indices() {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(i),
+                (true, Some(s)) => {
+                    fields.push((s, &expression[s..i]));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            fields.push((s, &expression[s..]));
+        }
+        if fields.len() != FIELDS.len() {
+            let (start, end) = match fields.get(FIELDS.len()) {
+                Some((extra, _)) => (*extra, expression.len()),
+                None => (expression.len(), expression.len() + 1),
+            };
+            return Err(CronError::new(
+                format!("Expected 5 fields (minute hour day month weekday), found {}", fields.len()),
+                start,
+                end,
+            ));
+        }
+
+        let mut masks = [(0u64, false); 5];
+        for (i, (offset, text)) in fields.iter().enumerate() {
+            masks[i] = parse_field(&FIELDS[i], text, *offset)?;
+        }
+        let mut days_of_week = masks[4].0;
+        if is_set(days_of_week, 7) {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+        Ok(CronSchedule {
+            minutes: masks[0].0,
+            hours: masks[1].0,
+            days_of_month: masks[2].0,
+            months: masks[3].0,
+            days_of_week,
+            dom_restricted: masks[2].1,
+            dow_restricted: masks[4].1,
+        })
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !is_set(self.months, date.month()) {
+            return false;
+        }
+        let dom = is_set(self.days_of_month, date.day());
+        let dow = is_set(self.days_of_week, date.weekday().num_days_from_sunday());
+        match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        }
+    }
+
+    /// The first fire time strictly after `after`, in `tz`
+    pub fn next_after(&self, after: DateTime<Utc>, tz: &Tz) -> Option<ScheduledInstant> {
+        let local = after.with_timezone(tz).naive_local();
+        let start = local.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let hours = set_values(self.hours);
+        let minutes = set_values(self.minutes);
+        for offset in 0..MAX_SEARCH_DAYS {
+            let date = start.date() + ChronoDuration::days(offset);
+            if !self.matches_date(date) {
+                continue;
+            }
+            for hour in &hours {
+                for minute in &minutes {
+                    let candidate = date.and_hms_opt(*hour, *minute, 0)?;
+                    if candidate < start {
+                        continue;
+                    }
+                    let resolved = resolve(tz, candidate);
+                    if resolved.at > after {
+                        return Some(resolved);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// The next `count` fire times after `after`
+    pub fn upcoming(&self, after: DateTime<Utc>, tz: &Tz, count: usize) -> Vec<ScheduledInstant> {
+        let mut runs = Vec::with_capacity(count);
+        let mut cursor = after;
+        while runs.len() < count {
+            let Some(next) = self.next_after(cursor, tz) else { break };
+            cursor = next.at;
+            runs.push(next);
+        }
+        runs
+    }
+
+    pub fn describe(&self, expression: &str) -> ScheduleDescription {
+        let single = |mask: u64| {
+            let values = set_values(mask);
+            (values.len() == 1).then(|| values[0])
+        };
+        let all = |mask: u64, field: &Field| mask == bits(field.min..=field.max);
+        let every_day = !self.dom_restricted && !self.dow_restricted && all(self.months, &FIELDS[3]);
+        let time = match (single(self.hours), single(self.minutes)) {
+            (Some(hour), Some(minute)) => Some(format!("{:02}:{:02}", hour, minute)),
+            _ => None,
+        };
+
+        let mut params = Map::new();
+        let (key, text) = if every_day && all(self.hours, &FIELDS[1]) && all(self.minutes, &FIELDS[0]) {
+            ("everyMinute", "every minute".to_string())
+        } else if let (true, true, Some(n)) = (every_day, all(self.hours, &FIELDS[1]), step_from_zero(self.minutes)) {
+            params.insert("n".into(), json!(n));
+            ("everyNMinutes", format!("every {} minutes", n))
+        } else if let (true, true, Some(minute)) = (every_day, all(self.hours, &FIELDS[1]), single(self.minutes)) {
+            params.insert("minute".into(), json!(format!("{:02}", minute)));
+            ("hourlyAt", format!("every hour at minute {:02}", minute))
+        } else if let Some(time) = time {
+            params.insert("time".into(), json!(time));
+            let days = set_values(self.days_of_week);
+            let only_dow = !self.dom_restricted && self.dow_restricted && all(self.months, &FIELDS[3]);
+            if every_day {
+                ("dailyAt", format!("every day at {}", time))
+            } else if only_dow && days == [1, 2, 3, 4, 5] {
+                ("weekdaysAt", format!("every weekday at {}", time))
+            } else if only_dow && days == [0, 6] {
+                ("weekendsAt", format!("every Saturday and Sunday at {}", time))
+            } else if only_dow {
+                let labels: Vec<&str> = days.iter().map(|d| DAY_LABELS[*d as usize]).collect();
+                params.insert("days".into(), json!(days));
+                ("weeklyAt", format!("every {} at {}", labels.join(", "), time))
+            } else if let (true, false, Some(day)) = (self.dom_restricted, self.dow_restricted, single(self.days_of_month)) {
+                params.insert("day".into(), json!(day));
+                match single(self.months) {
+                    Some(month) if !all(self.months, &FIELDS[3]) => {
+                        params.insert("month".into(), json!(month));
+                        (
+                            "yearlyAt",
+                            format!("every year on {} {} at {}", MONTH_LABELS[month as usize - 1], day, time),
+                        )
+                    }
+                    _ if all(self.months, &FIELDS[3]) => ("monthlyAt", format!("on day {} of every month at {}", day, time)),
+                    _ => custom(expression, &mut params),
+                }
+            } else {
+                custom(expression, &mut params)
+            }
+        } else {
+            custom(expression, &mut params)
+        };
+        ScheduleDescription {
+            key: format!("schedule.{}", key),
+            params: Value::Object(params),
+            text,
+        }
+    }
+}
+
+/// `n` when `minutes` is exactly 0, n, 2n, ... below 60
+fn step_from_zero(minutes: u64) -> Option<u32> {
+    let values = set_values(minutes);
+    let n = *values.get(1)?;
+    (values[0] == 0 && minutes == bits((0..60).step_by(n as usize))).then_some(n)
+}
+
+fn custom(expression: &str, params: &mut Map<String, Value>) -> (&'static str, String) {
+    params.insert("expression".into(), json!(expression.trim()));
+    ("custom", format!("on the schedule {}", expression.trim()))
+}
+
+/// How DST changed a fire time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DstAdjustment {
+    /// The local time was skipped; fired at the end of the gap
+    Skipped,
+    /// The local time happened twice; fired at the first
+    Ambiguous,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledInstant {
+    pub at: DateTime<Utc>,
+    pub adjustment: Option<DstAdjustment>,
+}
+
+/// The instant of local time `naive` in `tz`, under the policy in the header
+fn resolve(tz: &Tz, naive: NaiveDateTime) -> ScheduledInstant {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(at) => ScheduledInstant {
+            at: at.with_timezone(&Utc),
+            adjustment: None,
+        },
+        LocalResult::Ambiguous(first, _) => ScheduledInstant {
+            at: first.with_timezone(&Utc),
+            adjustment: Some(DstAdjustment::Ambiguous),
+        },
+        LocalResult::None => {
+            let mut probe = naive;
+            // Gaps are an hour in practice; no zone has one longer than a day
+            for _ in 0..24 * 60 {
+                probe += ChronoDuration::minutes(1);
+                if let Some(at) = tz.from_local_datetime(&probe).earliest() {
+                    return ScheduledInstant {
+                        at: at.with_timezone(&Utc),
+                        adjustment: Some(DstAdjustment::Skipped),
+                    };
+                }
+            }
+            ScheduledInstant {
+                at: naive.and_utc(),
+                adjustment: Some(DstAdjustment::Skipped),
+            }
+        }
+    }
+}
+
+/// The system's IANA zone, or UTC when it can't be determined
+pub fn system_zone() -> Tz {
+    iana_time_zone::get_timezone()
+        .ok()
+        .and_then(|name| name.parse().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+pub fn parse_zone(name: &str) -> Result<Tz, String> {
+    name.trim().parse().map_err(|_| format!("Unknown time zone: {}", name))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleDescription {
+    /// Locale catalog key, e.g. `schedule.weekdaysAt`
+    pub key: String,
+    /// Values for the key's placeholders; `days` are 0-6 from Sunday, `month` 1-12
+    pub params: Value,
+    /// English rendering, for logs
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreviewRun {
+    /// RFC 3339 in the schedule's zone
+    pub local: String,
+    /// RFC 3339 in UTC
+    pub utc: String,
+    pub adjustment: Option<DstAdjustment>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchedulePreview {
+    pub timezone: String,
+    pub description: ScheduleDescription,
+    pub runs: Vec<PreviewRun>,
+}
+
+fn preview(expression: &str, count: u32, tz: Tz, now: DateTime<Utc>) -> Result<SchedulePreview, CommandError> {
+    let schedule = CronSchedule::parse(expression).map_err(|e| e.into_command_error(expression))?;
+    let runs = schedule
+        .upcoming(now, &tz, count.clamp(1, MAX_PREVIEW) as usize)
+        .into_iter()
+        .map(|run| PreviewRun {
+            local: run.at.with_timezone(&tz).to_rfc3339(),
+            utc: run.at.to_rfc3339(),
+            adjustment: run.adjustment,
+        })
+        .collect();
+    Ok(SchedulePreview {
+        timezone: tz.name().to_string(),
+        description: schedule.describe(expression),
+        runs,
+    })
+}
+
+/// The next `count` fire times of a cron expression in `timezone` (the system zone
+/// if unset), with a description
+#[tauri::command]
+pub fn preview_schedule(cron_expr: String, count: u32, timezone: Option<String>) -> Result<SchedulePreview, CommandError> {
+    let tz = match timezone.as_deref().filter(|name| !name.trim().is_empty()) {
+        Some(name) => parse_zone(name)?,
+        None => system_zone(),
+    };
+    preview(&cron_expr, count, tz, Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+    }
+
+    fn runs(expression: &str, zone: &str, after: &str, count: usize) -> Vec<(String, Option<DstAdjustment>)> {
+        let tz = parse_zone(zone).unwrap();
+        CronSchedule::parse(expression)
+            .unwrap()
+            .upcoming(utc(after), &tz, count)
+            .into_iter()
+            .map(|run| (run.at.to_rfc3339(), run.adjustment))
+            .collect()
+    }
+
+    fn error(expression: &str) -> (String, usize, usize) {
+        let e = CronSchedule::parse(expression).unwrap_err();
+        (e.message, e.start, e.end)
+    }
+
+    #[test]
+    fn errors_point_at_the_bad_part() {
+        assert_eq!(error("0 9 * * mon-fri/0").1..error("0 9 * * mon-fri/0").2, 16..17);
+        let (message, start, end) = error("0 25 * * *");
+        assert_eq!((start, end), (2, 4));
+        assert!(message.contains("out of range 0-23"), "{}", message);
+        assert_eq!(error("0 9 * foo *").1..error("0 9 * foo *").2, 6..9);
+        assert_eq!(error("0 9 * *").1, 7);
+        assert_eq!(error("0 9 * * * *").1, 10);
+        assert_eq!(error("0 9 * 1,5-3 *").1..error("0 9 * 1,5-3 *").2, 8..11);
+    }
+
+    #[test]
+    fn describes_common_schedules() {
+        let describe = |e: &str| {
+            let d = CronSchedule::parse(e).unwrap().describe(e);
+            (d.key, d.text)
+        };
+        assert_eq!(describe("0 9 * * 1-5"), ("schedule.weekdaysAt".into(), "every weekday at 09:00".into()));
+        assert_eq!(describe("*/15 * * * *").1, "every 15 minutes");
+        assert_eq!(describe("30 * * * *").1, "every hour at minute 30");
+        assert_eq!(describe("@daily").1, "every day at 00:00");
+        assert_eq!(describe("0 18 * * sat,sun").0, "schedule.weekendsAt");
+        assert_eq!(describe("0 8 * * 1,3,5").1, "every Monday, Wednesday, Friday at 08:00");
+        assert_eq!(describe("15 7 1 * *").1, "on day 1 of every month at 07:15");
+        assert_eq!(describe("0 0 25 dec *").1, "every year on December 25 at 00:00");
+        assert_eq!(describe("5 4 * * 7").1, "every Sunday at 04:05");
+        assert_eq!(describe("0 9-17 * * *").0, "schedule.custom");
+    }
+
+    #[test]
+    fn day_fields_combine_like_cron() {
+        // The 13th, and every Friday
+        let dates: Vec<String> = runs("0 0 13 * 5", "UTC", "2024-09-01T00:00:00Z", 4)
+            .into_iter()
+            .map(|(at, _)| at[..10].to_string())
+            .collect();
+        assert_eq!(dates, vec!["2024-09-06", "2024-09-13", "2024-09-20", "2024-09-27"]);
+        assert!(runs("0 0 30 2 *", "UTC", "2024-01-01T00:00:00Z", 1).is_empty());
+    }
+
+    #[test]
+    fn spring_forward_fires_at_the_end_of_the_gap() {
+        // New York skips 02:00-03:00 on 2024-03-10
+        assert_eq!(
+            runs("30 2 * * *", "America/New_York", "2024-03-09T12:00:00Z", 2),
+            vec![
+                ("2024-03-10T07:00:00+00:00".to_string(), Some(DstAdjustment::Skipped)),
+                ("2024-03-11T06:30:00+00:00".to_string(), None),
+            ]
+        );
+        // Every time inside the gap collapses onto one run
+        let quarter_hours = runs("*/15 2 * * *", "America/New_York", "2024-03-10T06:00:00Z", 2);
+        assert_eq!(quarter_hours[0].0, "2024-03-10T07:00:00+00:00");
+        assert_eq!(quarter_hours[1].0, "2024-03-11T06:00:00+00:00");
+    }
+
+    #[test]
+    fn fall_back_fires_once_at_the_first_occurrence() {
+        // New York repeats 01:00-02:00 on 2024-11-03 (EDT, then EST)
+        assert_eq!(
+            runs("30 1 * * *", "America/New_York", "2024-11-02T12:00:00Z", 2),
+            vec![
+                ("2024-11-03T05:30:00+00:00".to_string(), Some(DstAdjustment::Ambiguous)),
+                ("2024-11-04T06:30:00+00:00".to_string(), None),
+            ]
+        );
+        let hourly: Vec<String> = runs("0 * * * *", "America/New_York", "2024-11-03T03:30:00Z", 3)
+            .into_iter()
+            .map(|(at, _)| at)
+            .collect();
+        assert_eq!(
+            hourly,
+            vec!["2024-11-03T04:00:00+00:00", "2024-11-03T05:00:00+00:00", "2024-11-03T07:00:00+00:00"]
+        );
+    }
+
+    #[test]
+    fn previews_show_local_and_utc() {
+        let preview = preview("0 9 * * *", 2, parse_zone("Europe/Berlin").unwrap(), utc("2024-06-01T00:00:00Z")).unwrap();
+        assert_eq!(preview.timezone, "Europe/Berlin");
+        assert_eq!(preview.runs[0].local, "2024-06-01T09:00:00+02:00");
+        assert_eq!(preview.runs[0].utc, "2024-06-01T07:00:00+00:00");
+        assert!(matches!(
+            super::preview("0 9 * *", 2, Tz::UTC, Utc::now()),
+            Err(CommandError::InvalidSchedule { position: 7, length: 1, .. })
+        ));
+    }
+}
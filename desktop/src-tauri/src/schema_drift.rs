@@ -0,0 +1,290 @@
+// Message shapes the app doesn't know yet.
+//
+// The sidecar grows new message types, result subtypes and tools faster than the
+// renderer learns them, and an unknown shape degrades quietly (an empty bubble, a
+// tool call missing from the audit trail). `MessageType` and the registries below
+// list what the app understands. A background tick checks messages inserted since the last tick
+// against them and counts each unknown shape once per kind and value in
+// `schema_drift`, with a few sample message ids. Reports are filtered through the
+// registries when read, so teaching the app a shape also stops it being reported.
+//
+// Past `DRIFT_THRESHOLD` unknown messages, `schema://drift-detected` is emitted
+// once so the UI can suggest an update. The self-test carries the report too.
+
+use crate::message_types::MessageType;
+use crate::{background, db, events, settings};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::time::Duration;
+use tauri::AppHandle;
+
+/// Message `subtype`s with their own rendering or handling
+pub(crate) const MESSAGE_SUBTYPES: &[&str] = &[
+    "success",
+    "error",
+    "error_max_turns",
+    "error_during_execution",
+    "system",
+    "permission_request",
+    "question",
+];
+/// Tools with a renderer for their input and output
+pub(crate) const TOOL_NAMES: &[&str] = &[
+    "AskUserQuestion",
+    "Bash",
+    "BashOutput",
+    "Edit",
+    "ExitPlanMode",
+    "Glob",
+    "Grep",
+    "KillShell",
+    "LS",
+    "MultiEdit",
+    "NotebookEdit",
+    "Read",
+    "Skill",
+    "Task",
+    "TodoWrite",
+    "WebFetch",
+    "WebSearch",
+    "Write",
+];
+/// MCP tools are named by their servers and rendered generically
+const MCP_TOOL_PREFIX: &str = "mcp__";
+
+const CURSOR_KEY: &str = "schema_drift_last_message_id";
+const NOTIFIED_KEY: &str = "schema_drift_notified";
+/// Unknown messages before `schema://drift-detected` fires
+const DRIFT_THRESHOLD: i64 = 20;
+const MAX_SAMPLES: i64 = 5;
+/// Messages checked per tick, so a first run over a long history is spread out
+const BATCH: i64 = 2000;
+const ACTIVITY: background::Activity = background::Activity {
+    name: "schema_drift",
+    interval: Duration::from_secs(60),
+    coalescable: true,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DriftedShape {
+    /// `message_type`, `subtype` or `tool_name`
+    pub kind: String,
+    pub value: String,
+    pub occurrences: i64,
+    /// Up to `MAX_SAMPLES` of the first messages seen with it
+    pub sample_message_ids: Vec<i64>,
+    pub first_seen_at: String,
+    pub last_seen_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaDriftReport {
+    /// Most frequent first
+    pub shapes: Vec<DriftedShape>,
+    /// Messages up to this id have been checked
+    pub checked_through: i64,
+}
+
+fn is_known(kind: &str, value: &str) -> bool {
+    match kind {
+        // Misspellings of a canonical type count as unknown until they are normalized
+        "message_type" => MessageType::canonical(value).is_some(),
+        "subtype" => MESSAGE_SUBTYPES.contains(&value),
+        "tool_name" => TOOL_NAMES.contains(&value) || value.starts_with(MCP_TOOL_PREFIX),
+        _ => true,
+    }
+}
+
+/// Record the unknown shapes of messages after `after`, up to `BATCH` of them.
+/// Returns the last message id checked.
+fn scan(conn: &mut Connection, after: i64) -> Result<i64, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut last = after;
+    {
+        let mut rows = tx
+            .prepare_cached(
+                "SELECT id, type, subtype, tool_name, created_at FROM messages WHERE id > ?1 ORDER BY id LIMIT ?2",
+            )
+            .map_err(|e| e.to_string())?;
+        let mut record = tx
+            .prepare_cached(
+                "INSERT INTO schema_drift (kind, value, occurrences, sample_message_ids, first_seen_at, last_seen_at)
+                 VALUES (?1, ?2, 1, json_array(?3), ?4, ?4)
+                 ON CONFLICT (kind, value) DO UPDATE SET
+                     occurrences = occurrences + 1,
+                     last_seen_at = MAX(last_seen_at, excluded.last_seen_at),
+                     sample_message_ids = CASE WHEN json_array_length(sample_message_ids) < ?5
+                                               THEN json_insert(sample_message_ids, '$[#]', ?3)
+                                               ELSE sample_message_ids END",
+            )
+            .map_err(|e| e.to_string())?;
+        let mut rows = rows.query(params![after, BATCH]).map_err(|e| e.to_string())?;
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let id: i64 = row.get(0).map_err(|e| e.to_string())?;
+            let shapes: [(&str, Option<String>); 3] = [
+                ("message_type", row.get(1).map_err(|e| e.to_string())?),
+                ("subtype", row.get(2).map_err(|e| e.to_string())?),
+                ("tool_name", row.get(3).map_err(|e| e.to_string())?),
+            ];
+            let created_at: String = row.get(4).map_err(|e| e.to_string())?;
+            for (kind, value) in shapes {
+                let Some(value) = value.filter(|v| !is_known(kind, v)) else {
+                    continue;
+                };
+                record
+                    .execute(params![kind, value, id, created_at, MAX_SAMPLES])
+                    .map_err(|e| e.to_string())?;
+            }
+            last = id;
+        }
+    }
+    settings::set(&tx, CURSOR_KEY, &last)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(last)
+}
+
+/// Unknown shapes seen so far, leaving out any the registries have since learned
+pub(crate) fn report(conn: &Connection) -> Result<SchemaDriftReport, String> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT kind, value, occurrences, sample_message_ids, first_seen_at, last_seen_at
+             FROM schema_drift ORDER BY occurrences DESC, kind, value",
+        )
+        .map_err(|e| e.to_string())?;
+    let shapes = stmt
+        .query_map([], |row| {
+            let samples: String = row.get(3)?;
+            Ok(DriftedShape {
+                kind: row.get(0)?,
+                value: row.get(1)?,
+                occurrences: row.get(2)?,
+                sample_message_ids: serde_json::from_str(&samples).unwrap_or_default(),
+                first_seen_at: row.get(4)?,
+                last_seen_at: row.get(5)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .filter(|shape| shape.as_ref().map_or(true, |s| !is_known(&s.kind, &s.value)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(SchemaDriftReport {
+        shapes,
+        checked_through: settings::get_or(conn, CURSOR_KEY, 0)?,
+    })
+}
+
+fn tick(app: &AppHandle) -> Result<(), String> {
+    let mut conn = db::open(app)?;
+    let after = settings::get_or(&conn, CURSOR_KEY, 0)?;
+    if scan(&mut conn, after)? == after || settings::get_or(&conn, NOTIFIED_KEY, false)? {
+        return Ok(());
+    }
+    let report = report(&conn)?;
+    let occurrences: i64 = report.shapes.iter().map(|s| s.occurrences).sum();
+    if occurrences < DRIFT_THRESHOLD {
+        return Ok(());
+    }
+    settings::set(&conn, NOTIFIED_KEY, &true)?;
+    println!(
+        "[SchemaDrift] {} messages with {} unknown shapes",
+        occurrences,
+        report.shapes.len()
+    );
+    events::emit_typed(
+        app,
+        events::SchemaDriftDetected {
+            shapes: report.shapes.len() as u32,
+            occurrences,
+        },
+    );
+    Ok(())
+}
+
+/// Start checking new messages against the known shapes
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &ACTIVITY);
+        if let Err(e) = tick(&app) {
+            eprintln!("[SchemaDrift] Check failed: {}", e);
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn get_schema_drift_report(app: AppHandle) -> Result<SchemaDriftReport, String> {
+    db::with_conn(&app, |conn| report(conn)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute("INSERT INTO tasks (id, prompt) VALUES ('t1', 'p')", []).unwrap();
+        conn
+    }
+
+    fn message(conn: &Connection, kind: &str, subtype: Option<&str>, tool_name: Option<&str>) {
+        conn.execute(
+            "INSERT INTO messages (task_id, type, subtype, tool_name) VALUES ('t1', ?1, ?2, ?3)",
+            params![kind, subtype, tool_name],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn unknown_shapes_are_counted_once_per_value() {
+        let mut conn = db();
+        message(&conn, "text", None, None);
+        message(&conn, "tool_use", None, Some("Bash"));
+        message(&conn, "tool_use", None, Some("mcp__github__search"));
+        for _ in 0..7 {
+            message(&conn, "tool_use", None, Some("Whiteboard"));
+        }
+        message(&conn, "result", Some("error_budget"), None);
+
+        let last = scan(&mut conn, 0).unwrap();
+        assert_eq!(last, 11);
+        message(&conn, "thinking", None, Some("Whiteboard"));
+        assert_eq!(scan(&mut conn, last).unwrap(), 12);
+        // Nothing new: nothing counted twice
+        assert_eq!(scan(&mut conn, 12).unwrap(), 12);
+
+        let report = report(&conn).unwrap();
+        let shapes: Vec<(&str, &str, i64)> = report
+            .shapes
+            .iter()
+            .map(|s| (s.kind.as_str(), s.value.as_str(), s.occurrences))
+            .collect();
+        assert_eq!(
+            shapes,
+            vec![
+                ("tool_name", "Whiteboard", 8),
+                ("message_type", "thinking", 1),
+                ("subtype", "error_budget", 1),
+            ]
+        );
+        assert_eq!(report.shapes[0].sample_message_ids, vec![4, 5, 6, 7, 8]);
+        assert_eq!(report.checked_through, 12);
+        let rows: i64 = conn
+            .query_row("SELECT COUNT(*) FROM schema_drift", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 3);
+    }
+
+    #[test]
+    fn shapes_the_registries_know_are_not_reported() {
+        let conn = db();
+        conn.execute_batch(
+            "INSERT INTO schema_drift (kind, value, occurrences) VALUES
+                ('tool_name', 'Read', 3),
+                ('subtype', 'not_yet_known', 2);",
+        )
+        .unwrap();
+        let report = report(&conn).unwrap();
+        assert_eq!(report.shapes.len(), 1);
+        assert_eq!(report.shapes[0].value, "not_yet_known");
+    }
+}
@@ -0,0 +1,676 @@
+// Secret storage (API keys) behind a backend trait.
+//
+// The platform keychain is preferred. Where it can't be reached (Linux without a
+// running Secret Service, SSH sessions) secrets go to an encrypted file in the app
+// config dir instead: XChaCha20-Poly1305 under a key derived with Argon2 from either
+// a user passphrase or, if the user accepts weaker protection, the machine identity.
+// Once `secrets.enc` exists it is the active backend, so a choice made via
+// `migrate_secrets_backend` sticks across launches.
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+const SECRETS_FILE: &str = "secrets.enc";
+const KEYCHAIN_SERVICE: &str = "com.cloudwork.desktop";
+/// Keychains can't enumerate entries, so the keychain backend keeps its own list
+const KEYCHAIN_INDEX: &str = "__workany_secret_index";
+const FILE_VERSION: u32 = 1;
+const KEY_CHECK_CONTEXT: &[u8] = b"workany-secrets-v1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretsError {
+    /// The file store needs its passphrase before it can be read
+    Locked,
+    /// The file store hasn't been created yet
+    NotSetUp,
+    WrongPassphrase,
+    Corrupted(String),
+    Unavailable(String),
+    /// A migrated entry didn't read back from the target
+    Verification(String),
+    Io(String),
+}
+
+impl fmt::Display for SecretsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Locked => write!(f, "Secret store is locked"),
+            Self::NotSetUp => write!(f, "Secret store has not been set up"),
+            Self::WrongPassphrase => write!(f, "Wrong passphrase for the secret store"),
+            Self::Corrupted(e) => write!(f, "Secret store is corrupted: {}", e),
+            Self::Unavailable(e) => write!(f, "Keychain unavailable: {}", e),
+            Self::Verification(key) => write!(f, "Secret '{}' did not read back after migration", key),
+            Self::Io(e) => write!(f, "Secret store I/O error: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    Keychain,
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protection {
+    Passphrase,
+    /// Key derived from machine identity; stops casual copying of the file only
+    MachineKey,
+}
+
+pub trait SecretsBackend: Send {
+    fn kind(&self) -> BackendKind;
+    fn get(&self, key: &str) -> Result<Option<String>, SecretsError>;
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SecretsError>;
+    fn delete(&mut self, key: &str) -> Result<(), SecretsError>;
+    fn keys(&self) -> Result<Vec<String>, SecretsError>;
+
+    fn locked(&self) -> bool {
+        false
+    }
+
+    fn protection(&self) -> Option<Protection> {
+        None
+    }
+
+    fn unlock(&mut self, _passphrase: &str) -> Result<(), SecretsError> {
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Platform keychain
+// ---------------------------------------------------------------------------
+
+pub struct KeychainBackend;
+
+impl KeychainBackend {
+    fn entry(account: &str) -> Result<keyring::Entry, SecretsError> {
+        keyring::Entry::new(KEYCHAIN_SERVICE, account).map_err(|e| SecretsError::Unavailable(e.to_string()))
+    }
+
+    /// Whether the keychain answers at all; a missing entry still counts as reachable
+    pub fn probe() -> Result<(), SecretsError> {
+        match Self::entry(KEYCHAIN_INDEX)?.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(SecretsError::Unavailable(e.to_string())),
+        }
+    }
+
+    fn read(account: &str) -> Result<Option<String>, SecretsError> {
+        match Self::entry(account)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(SecretsError::Unavailable(e.to_string())),
+        }
+    }
+
+    fn write_index(keys: &[String]) -> Result<(), SecretsError> {
+        let json = serde_json::to_string(keys).map_err(|e| SecretsError::Io(e.to_string()))?;
+        Self::entry(KEYCHAIN_INDEX)?
+            .set_password(&json)
+            .map_err(|e| SecretsError::Unavailable(e.to_string()))
+    }
+}
+
+impl SecretsBackend for KeychainBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Keychain
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, SecretsError> {
+        Self::read(key)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SecretsError> {
+        Self::entry(key)?
+            .set_password(value)
+            .map_err(|e| SecretsError::Unavailable(e.to_string()))?;
+        let mut keys = self.keys()?;
+        if !keys.iter().any(|k| k == key) {
+            keys.push(key.to_string());
+            Self::write_index(&keys)?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), SecretsError> {
+        match Self::entry(key)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(SecretsError::Unavailable(e.to_string())),
+        }
+        let mut keys = self.keys()?;
+        keys.retain(|k| k != key);
+        Self::write_index(&keys)
+    }
+
+    fn keys(&self) -> Result<Vec<String>, SecretsError> {
+        match Self::read(KEYCHAIN_INDEX)? {
+            Some(json) => serde_json::from_str(&json).map_err(|e| SecretsError::Corrupted(e.to_string())),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Encrypted file
+// ---------------------------------------------------------------------------
+
+/// On-disk layout of `secrets.enc`
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    protection: Protection,
+    salt: String,
+    /// Hash of the derived key, to tell a wrong passphrase from a damaged file
+    key_check: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+struct Unlocked {
+    key: [u8; 32],
+    salt: Vec<u8>,
+}
+
+pub struct FileBackend {
+    path: PathBuf,
+    protection: Option<Protection>,
+    unlocked: Option<Unlocked>,
+}
+
+fn derive_key(secret: &str, salt: &[u8]) -> Result<[u8; 32], SecretsError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(secret.as_bytes(), salt, &mut key)
+        .map_err(|e| SecretsError::Io(e.to_string()))?;
+    Ok(key)
+}
+
+fn key_check(key: &[u8; 32]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(KEY_CHECK_CONTEXT);
+    hasher.update(key);
+    BASE64.encode(hasher.finalize())
+}
+
+/// Stable per-machine, per-user string the weaker protection mode keys from
+fn machine_secret() -> String {
+    let machine_id = ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .find_map(|p| std::fs::read_to_string(p).ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default();
+    let home = dirs::home_dir().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default();
+    format!("{}:{}:{}", KEYCHAIN_SERVICE, machine_id, home)
+}
+
+impl FileBackend {
+    /// Open the store at `path` without unlocking it
+    pub fn open(path: PathBuf) -> Result<Self, SecretsError> {
+        let protection = if path.exists() {
+            Some(Self::read_envelope(&path)?.protection)
+        } else {
+            None
+        };
+        Ok(Self {
+            path,
+            protection,
+            unlocked: None,
+        })
+    }
+
+    /// Create an empty store, replacing any existing file. `None` uses the machine key.
+    pub fn create(path: PathBuf, passphrase: Option<&str>) -> Result<Self, SecretsError> {
+        let (protection, secret) = match passphrase {
+            Some(p) => (Protection::Passphrase, p.to_string()),
+            None => (Protection::MachineKey, machine_secret()),
+        };
+        let mut salt = vec![0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(&secret, &salt)?;
+        let backend = Self {
+            path,
+            protection: Some(protection),
+            unlocked: Some(Unlocked { key, salt }),
+        };
+        backend.save(&BTreeMap::new())?;
+        Ok(backend)
+    }
+
+    pub fn exists(&self) -> bool {
+        self.protection.is_some()
+    }
+
+    /// Unlock a machine-key store without asking the user
+    pub fn unlock_with_machine_key(&mut self) -> Result<(), SecretsError> {
+        let secret = machine_secret();
+        self.unlock_with(&secret)
+    }
+
+    fn unlock_with(&mut self, secret: &str) -> Result<(), SecretsError> {
+        let envelope = Self::read_envelope(&self.path)?;
+        let salt = BASE64
+            .decode(&envelope.salt)
+            .map_err(|e| SecretsError::Corrupted(e.to_string()))?;
+        let key = derive_key(secret, &salt)?;
+        if key_check(&key) != envelope.key_check {
+            return Err(SecretsError::WrongPassphrase);
+        }
+        // Decrypt once so a damaged file is reported now rather than on first read
+        Self::decrypt(&envelope, &key)?;
+        self.unlocked = Some(Unlocked { key, salt });
+        Ok(())
+    }
+
+    fn read_envelope(path: &Path) -> Result<Envelope, SecretsError> {
+        let data = std::fs::read(path).map_err(|e| SecretsError::Io(e.to_string()))?;
+        let envelope: Envelope = serde_json::from_slice(&data).map_err(|e| SecretsError::Corrupted(e.to_string()))?;
+        if envelope.version != FILE_VERSION {
+            return Err(SecretsError::Corrupted(format!("unsupported version {}", envelope.version)));
+        }
+        Ok(envelope)
+    }
+
+    fn decrypt(envelope: &Envelope, key: &[u8; 32]) -> Result<BTreeMap<String, String>, SecretsError> {
+        let corrupted = |e: &dyn fmt::Display| SecretsError::Corrupted(e.to_string());
+        let nonce = BASE64.decode(&envelope.nonce).map_err(|e| corrupted(&e))?;
+        if nonce.len() != 24 {
+            return Err(corrupted(&"bad nonce length"));
+        }
+        let ciphertext = BASE64.decode(&envelope.ciphertext).map_err(|e| corrupted(&e))?;
+        let plaintext = XChaCha20Poly1305::new(Key::from_slice(key))
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| corrupted(&"authentication failed"))?;
+        serde_json::from_slice(&plaintext).map_err(|e| corrupted(&e))
+    }
+
+    fn unlocked(&self) -> Result<&Unlocked, SecretsError> {
+        match (&self.protection, &self.unlocked) {
+            (None, _) => Err(SecretsError::NotSetUp),
+            (Some(_), None) => Err(SecretsError::Locked),
+            (Some(_), Some(unlocked)) => Ok(unlocked),
+        }
+    }
+
+    fn load(&self) -> Result<BTreeMap<String, String>, SecretsError> {
+        let unlocked = self.unlocked()?;
+        Self::decrypt(&Self::read_envelope(&self.path)?, &unlocked.key)
+    }
+
+    fn save(&self, entries: &BTreeMap<String, String>) -> Result<(), SecretsError> {
+        let unlocked = self.unlocked()?;
+        let protection = self.protection.ok_or(SecretsError::NotSetUp)?;
+        let plaintext = serde_json::to_vec(entries).map_err(|e| SecretsError::Io(e.to_string()))?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&unlocked.key))
+            .encrypt(&nonce, plaintext.as_ref())
+            .map_err(|e| SecretsError::Io(e.to_string()))?;
+        let envelope = Envelope {
+            version: FILE_VERSION,
+            protection,
+            salt: BASE64.encode(&unlocked.salt),
+            key_check: key_check(&unlocked.key),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        let json = serde_json::to_vec_pretty(&envelope).map_err(|e| SecretsError::Io(e.to_string()))?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| SecretsError::Io(e.to_string()))?;
+        }
+        // Write-then-rename so a crash never leaves a half-written store
+        let tmp = self.path.with_extension("enc.tmp");
+        std::fs::write(&tmp, json).map_err(|e| SecretsError::Io(e.to_string()))?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| SecretsError::Io(e.to_string()))
+    }
+}
+
+impl SecretsBackend for FileBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::File
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, SecretsError> {
+        Ok(self.load()?.remove(key))
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SecretsError> {
+        let mut entries = self.load()?;
+        entries.insert(key.to_string(), value.to_string());
+        self.save(&entries)
+    }
+
+    fn delete(&mut self, key: &str) -> Result<(), SecretsError> {
+        let mut entries = self.load()?;
+        if entries.remove(key).is_some() {
+            self.save(&entries)?;
+        }
+        Ok(())
+    }
+
+    fn keys(&self) -> Result<Vec<String>, SecretsError> {
+        Ok(self.load()?.into_keys().collect())
+    }
+
+    fn locked(&self) -> bool {
+        self.protection.is_some() && self.unlocked.is_none()
+    }
+
+    fn protection(&self) -> Option<Protection> {
+        self.protection
+    }
+
+    fn unlock(&mut self, passphrase: &str) -> Result<(), SecretsError> {
+        match self.protection {
+            None => Err(SecretsError::NotSetUp),
+            Some(Protection::MachineKey) => self.unlock_with_machine_key(),
+            Some(Protection::Passphrase) => self.unlock_with(passphrase),
+        }
+    }
+}
+
+/// Copy every entry into `target`, verify each reads back, then delete from `source`
+pub fn migrate(source: &mut dyn SecretsBackend, target: &mut dyn SecretsBackend) -> Result<usize, SecretsError> {
+    let mut entries = Vec::new();
+    for key in source.keys()? {
+        if let Some(value) = source.get(&key)? {
+            entries.push((key, value));
+        }
+    }
+    for (key, value) in &entries {
+        target.set(key, value)?;
+    }
+    for (key, value) in &entries {
+        if target.get(key)?.as_deref() != Some(value.as_str()) {
+            return Err(SecretsError::Verification(key.clone()));
+        }
+    }
+    for (key, _) in &entries {
+        source.delete(key)?;
+    }
+    Ok(entries.len())
+}
+
+// ---------------------------------------------------------------------------
+// App state and commands
+// ---------------------------------------------------------------------------
+
+struct Active {
+    backend: Box<dyn SecretsBackend>,
+    keychain_error: Option<String>,
+}
+
+/// The active backend, chosen on first use because probing the keychain can block
+#[derive(Default)]
+pub struct Secrets(Mutex<Option<Active>>);
+
+fn secrets_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join(SECRETS_FILE))
+        .map_err(|e| e.to_string())
+}
+
+fn select_backend(app: &AppHandle) -> Result<Active, String> {
+    let keychain_error = KeychainBackend::probe().err().map(|e| e.to_string());
+    let mut file = FileBackend::open(secrets_path(app)?).map_err(|e| e.to_string())?;
+    if !file.exists() && keychain_error.is_none() {
+        return Ok(Active {
+            backend: Box::new(KeychainBackend),
+            keychain_error,
+        });
+    }
+    if let Some(e) = &keychain_error {
+        println!("[Secrets] Keychain unreachable ({}); using encrypted file store", e);
+    }
+    if file.protection() == Some(Protection::MachineKey) {
+        if let Err(e) = file.unlock_with_machine_key() {
+            eprintln!("[Secrets] Could not unlock file store with machine key: {}", e);
+        }
+    }
+    Ok(Active {
+        backend: Box::new(file),
+        keychain_error,
+    })
+}
+
+/// Run `f` against the active backend on the blocking thread pool
+async fn with_backend<T, F>(app: &AppHandle, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&AppHandle, &mut Active) -> Result<T, String> + Send + 'static,
+{
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<Secrets>();
+        let mut guard = state.0.lock().map_err(|e| e.to_string())?;
+        if guard.is_none() {
+            *guard = Some(select_backend(&app)?);
+        }
+        let active = guard.as_mut().expect("backend selected above");
+        f(&app, active)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Run `f` against the active backend from code already off the async runtime
+pub(crate) fn with_backend_blocking<T>(
+    app: &AppHandle,
+    f: impl FnOnce(&mut dyn SecretsBackend) -> Result<T, SecretsError>,
+) -> Result<T, String> {
+    let state = app.state::<Secrets>();
+    let mut guard = state.0.lock().map_err(|e| e.to_string())?;
+    if guard.is_none() {
+        *guard = Some(select_backend(app)?);
+    }
+    let active = guard.as_mut().expect("backend selected above");
+    f(active.backend.as_mut()).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SecretsBackendInfo {
+    pub backend: BackendKind,
+    pub keychain_available: bool,
+    /// Why the keychain can't be used, when it can't
+    pub keychain_error: Option<String>,
+    pub protection: Option<Protection>,
+    /// The file store exists and needs `unlock_secrets`
+    pub locked: bool,
+    /// The file store is active but `setup_file_secrets` hasn't been called
+    pub needs_setup: bool,
+    /// Plain-language description of the security tradeoff, for the settings UI
+    pub warning: Option<String>,
+}
+
+fn backend_info(active: &Active) -> SecretsBackendInfo {
+    let backend = active.backend.kind();
+    let protection = active.backend.protection();
+    let warning = match (backend, protection) {
+        (BackendKind::Keychain, _) => None,
+        (BackendKind::File, Some(Protection::Passphrase)) => Some(
+            "API keys are stored in an encrypted file protected by your passphrase instead of the system keychain."
+                .to_string(),
+        ),
+        (BackendKind::File, Some(Protection::MachineKey)) => Some(
+            "API keys are stored in a file encrypted with a key derived from this machine. Anyone with access to your user account can read them."
+                .to_string(),
+        ),
+        (BackendKind::File, None) => Some(
+            "The system keychain is unavailable. Choose a passphrase or machine-bound protection to store API keys."
+                .to_string(),
+        ),
+    };
+    SecretsBackendInfo {
+        backend,
+        keychain_available: active.keychain_error.is_none(),
+        keychain_error: active.keychain_error.clone(),
+        protection,
+        locked: active.backend.locked(),
+        needs_setup: backend == BackendKind::File && protection.is_none(),
+        warning,
+    }
+}
+
+#[tauri::command]
+pub async fn store_secret(app: AppHandle, key: String, value: String) -> Result<(), String> {
+    with_backend(&app, move |_, active| active.backend.set(&key, &value).map_err(|e| e.to_string())).await
+}
+
+#[tauri::command]
+pub async fn get_secret(app: AppHandle, key: String) -> Result<Option<String>, String> {
+    with_backend(&app, move |_, active| active.backend.get(&key).map_err(|e| e.to_string())).await
+}
+
+#[tauri::command]
+pub async fn delete_secret(app: AppHandle, key: String) -> Result<(), String> {
+    with_backend(&app, move |_, active| active.backend.delete(&key).map_err(|e| e.to_string())).await
+}
+
+#[tauri::command]
+pub async fn get_secrets_backend_info(app: AppHandle) -> Result<SecretsBackendInfo, String> {
+    with_backend(&app, |_, active| Ok(backend_info(active))).await
+}
+
+/// Create the file store when it is active but not set up yet. Without a passphrase
+/// the key is derived from the machine, which the caller must have confirmed.
+#[tauri::command]
+pub async fn setup_file_secrets(app: AppHandle, passphrase: Option<String>) -> Result<SecretsBackendInfo, String> {
+    with_backend(&app, move |app, active| {
+        if active.backend.kind() != BackendKind::File || active.backend.protection().is_some() {
+            return Err("File secret store is already set up or not in use".to_string());
+        }
+        let file = FileBackend::create(secrets_path(app)?, passphrase.as_deref()).map_err(|e| e.to_string())?;
+        active.backend = Box::new(file);
+        Ok(backend_info(active))
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn unlock_secrets(app: AppHandle, passphrase: String) -> Result<SecretsBackendInfo, String> {
+    with_backend(&app, move |_, active| {
+        active.backend.unlock(&passphrase).map_err(|e| e.to_string())?;
+        Ok(backend_info(active))
+    })
+    .await
+}
+
+/// Move every secret to `target`. Moving to the file store creates it with
+/// `passphrase` (or the machine key); moving back to the keychain removes the file
+/// once all entries have been verified there.
+#[tauri::command]
+pub async fn migrate_secrets_backend(
+    app: AppHandle,
+    target: BackendKind,
+    passphrase: Option<String>,
+) -> Result<usize, String> {
+    with_backend(&app, move |app, active| {
+        if active.backend.kind() == target {
+            return Err(format!("Secrets are already stored in the {:?} backend", target));
+        }
+        let path = secrets_path(app)?;
+        let moved = match target {
+            BackendKind::Keychain => {
+                KeychainBackend::probe().map_err(|e| e.to_string())?;
+                let mut keychain = KeychainBackend;
+                let moved = migrate(active.backend.as_mut(), &mut keychain).map_err(|e| e.to_string())?;
+                std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+                active.backend = Box::new(keychain);
+                moved
+            }
+            BackendKind::File => {
+                let mut file = FileBackend::create(path, passphrase.as_deref()).map_err(|e| e.to_string())?;
+                let moved = migrate(active.backend.as_mut(), &mut file).map_err(|e| e.to_string())?;
+                active.backend = Box::new(file);
+                moved
+            }
+        };
+        println!("[Secrets] Moved {} secrets to the {:?} backend", moved, target);
+        Ok(moved)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workany-secrets-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(SECRETS_FILE)
+    }
+
+    #[test]
+    fn file_backend_round_trip() {
+        let path = temp_path("round-trip");
+        let mut store = FileBackend::create(path.clone(), Some("correct horse")).unwrap();
+        store.set("anthropic", "sk-123").unwrap();
+        store.set("openai", "sk-456").unwrap();
+        store.delete("openai").unwrap();
+
+        let mut reopened = FileBackend::open(path).unwrap();
+        assert!(reopened.locked());
+        assert_eq!(reopened.get("anthropic"), Err(SecretsError::Locked));
+        reopened.unlock("correct horse").unwrap();
+        assert_eq!(reopened.get("anthropic").unwrap().as_deref(), Some("sk-123"));
+        assert_eq!(reopened.get("openai").unwrap(), None);
+        assert_eq!(reopened.keys().unwrap(), vec!["anthropic".to_string()]);
+    }
+
+    #[test]
+    fn file_backend_rejects_wrong_passphrase() {
+        let path = temp_path("wrong-pass");
+        let mut store = FileBackend::create(path.clone(), Some("right")).unwrap();
+        store.set("key", "value").unwrap();
+
+        let mut reopened = FileBackend::open(path).unwrap();
+        assert_eq!(reopened.unlock("wrong"), Err(SecretsError::WrongPassphrase));
+        assert!(reopened.locked());
+    }
+
+    #[test]
+    fn file_backend_reports_corruption() {
+        let path = temp_path("corrupt");
+        let mut store = FileBackend::create(path.clone(), Some("pass")).unwrap();
+        store.set("key", "value").unwrap();
+
+        // Flip the ciphertext but keep the envelope well-formed
+        let mut envelope: Envelope = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let mut ciphertext = BASE64.decode(&envelope.ciphertext).unwrap();
+        ciphertext[0] ^= 0xff;
+        envelope.ciphertext = BASE64.encode(ciphertext);
+        std::fs::write(&path, serde_json::to_vec(&envelope).unwrap()).unwrap();
+        let mut reopened = FileBackend::open(path.clone()).unwrap();
+        assert!(matches!(reopened.unlock("pass"), Err(SecretsError::Corrupted(_))));
+
+        std::fs::write(&path, b"{ not json").unwrap();
+        assert!(matches!(FileBackend::open(path), Err(SecretsError::Corrupted(_))));
+    }
+
+    #[test]
+    fn migrate_verifies_before_deleting_source() {
+        let mut source = FileBackend::create(temp_path("migrate-src"), Some("a")).unwrap();
+        source.set("one", "1").unwrap();
+        source.set("two", "2").unwrap();
+        let mut target = FileBackend::create(temp_path("migrate-dst"), None).unwrap();
+
+        assert_eq!(migrate(&mut source, &mut target).unwrap(), 2);
+        assert!(source.keys().unwrap().is_empty());
+        assert_eq!(target.get("two").unwrap().as_deref(), Some("2"));
+    }
+}
@@ -0,0 +1,430 @@
+// On-demand health diagnostics.
+//
+// `run_self_test` runs every check on its own thread as a background job. A failing
+// or hanging check never blocks the others: anything still running when the suite
+// deadline passes is reported as timed out. The last report is kept in settings.
+
+use crate::platform::{self, Feature, Support};
+use crate::{db, integrity, jobs, migrations, schema_drift, settings};
+use serde::{Deserialize, Serialize};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const SUITE_TIMEOUT: Duration = Duration::from_secs(10);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+const LAST_REPORT_KEY: &str = "last_self_test";
+const PROBE_KEY: &str = "__self_test_probe";
+// Endpoint used for both the connectivity and the clock check
+const REFERENCE_URL: &str = "https://api.anthropic.com";
+const LOW_DISK_WARN_BYTES: u64 = 1024 * 1024 * 1024;
+const LOW_DISK_FAIL_BYTES: u64 = 200 * 1024 * 1024;
+const CLOCK_SKEW_WARN_SECS: i64 = 60;
+const CLOCK_SKEW_FAIL_SECS: i64 = 300;
+const ANOMALY_LOOKBACK_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub id: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// Key into the frontend locale catalog for a remediation hint
+    pub hint_key: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub passed: u32,
+    pub warnings: u32,
+    pub failures: u32,
+    pub checks: Vec<CheckResult>,
+}
+
+struct Outcome {
+    status: CheckStatus,
+    detail: String,
+}
+
+impl Outcome {
+    fn pass(detail: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+        }
+    }
+
+    fn warn(detail: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(detail: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+        }
+    }
+}
+
+type CheckFn = fn(&AppHandle) -> Outcome;
+
+const CHECKS: &[(&str, CheckFn)] = &[
+    ("database_integrity", check_database_integrity),
+    ("database_round_trip", check_database_round_trip),
+    ("integrity_journal", check_integrity_journal),
+    ("api_port", check_api_port),
+    ("api_health", check_api_health),
+    ("disk_space", check_disk_space),
+    ("directory_permissions", check_directory_permissions),
+    ("network", check_network),
+    ("clock", check_clock),
+    ("desktop_session", check_desktop_session),
+    ("schema_drift", check_schema_drift),
+];
+
+fn hint_key(id: &str, status: CheckStatus) -> Option<String> {
+    match status {
+        CheckStatus::Pass => None,
+        _ => Some(format!("selfTest.hints.{}", id)),
+    }
+}
+
+fn check_database_integrity(app: &AppHandle) -> Outcome {
+    let conn = match db::open(app) {
+        Ok(conn) => conn,
+        Err(e) => return Outcome::fail(format!("Cannot open database: {}", e)),
+    };
+    let integrity: String = match conn.query_row("PRAGMA quick_check", [], |row| row.get(0)) {
+        Ok(value) => value,
+        Err(e) => return Outcome::fail(format!("quick_check failed: {}", e)),
+    };
+    if integrity != "ok" {
+        return Outcome::fail(format!("quick_check reported: {}", integrity));
+    }
+
+    let expected = migrations::latest_version();
+    let applied: Option<i64> = conn
+        .query_row("SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1", [], |row| {
+            row.get(0)
+        })
+        .unwrap_or(None);
+    match applied {
+        Some(version) if version == expected => {
+            Outcome::pass(format!("quick_check ok, schema version {}", version))
+        }
+        Some(version) => Outcome::warn(format!(
+            "quick_check ok, schema version {} (expected {})",
+            version, expected
+        )),
+        None => Outcome::warn("quick_check ok, no migrations recorded yet"),
+    }
+}
+
+fn check_database_round_trip(app: &AppHandle) -> Outcome {
+    let result = (|| -> Result<bool, String> {
+        let conn = db::open(app)?;
+        let token = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        settings::set(&conn, PROBE_KEY, &token)?;
+        let read: Option<i64> = settings::get(&conn, PROBE_KEY)?;
+        settings::remove(&conn, PROBE_KEY)?;
+        Ok(read == Some(token))
+    })();
+    match result {
+        Ok(true) => Outcome::pass("Write and read back succeeded"),
+        Ok(false) => Outcome::fail("Value read back did not match what was written"),
+        Err(e) => Outcome::fail(e),
+    }
+}
+
+fn check_integrity_journal(app: &AppHandle) -> Outcome {
+    let anomalies = match db::open(app).and_then(|conn| integrity::latest_anomalies(&conn)) {
+        Ok(anomalies) => anomalies,
+        Err(e) => return Outcome::warn(format!("Cannot read integrity journal: {}", e)),
+    };
+    let recent = (chrono::Utc::now() - chrono::Duration::days(ANOMALY_LOOKBACK_DAYS))
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    let details: Vec<String> = anomalies
+        .iter()
+        .filter(|a| a.taken_at >= recent)
+        .map(|a| format!("{} ({}): {}", a.table, a.taken_at, a.detail))
+        .collect();
+    if details.is_empty() {
+        Outcome::pass("No unexplained row count drops")
+    } else {
+        Outcome::warn(details.join("; "))
+    }
+}
+
+fn check_api_port(_app: &AppHandle) -> Outcome {
+    let port = crate::sidecar::api_port();
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    match TcpListener::bind(addr) {
+        Ok(_) => Outcome::fail(format!("Nothing is listening on port {}", port)),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            Outcome::pass(format!("Port {} is held by a listener", port))
+        }
+        Err(e) => Outcome::warn(format!("Could not probe port {}: {}", port, e)),
+    }
+}
+
+fn check_api_health(app: &AppHandle) -> Outcome {
+    let url = format!("http://127.0.0.1:{}/health", crate::sidecar::api_port());
+    let agent = ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build();
+    match agent.get(&url).call() {
+        Ok(response) => {
+            let body = response.into_string().unwrap_or_default();
+            Outcome::pass(format!("Health endpoint answered: {}", body.trim()))
+        }
+        Err(ureq::Error::Status(code, _)) => {
+            Outcome::fail(format!("Health endpoint returned HTTP {}", code))
+        }
+        Err(e) if !crate::power::grace_remaining(app).is_zero() => {
+            Outcome::warn(format!("Health endpoint unreachable just after resume: {}", e))
+        }
+        Err(e) => Outcome::fail(format!("Health endpoint unreachable: {}", e)),
+    }
+}
+
+fn check_disk_space(app: &AppHandle) -> Outcome {
+    let dir = match app.path().app_config_dir() {
+        Ok(dir) => dir,
+        Err(e) => return Outcome::fail(e.to_string()),
+    };
+    match fs2::available_space(&dir) {
+        Ok(bytes) if bytes < LOW_DISK_FAIL_BYTES => {
+            Outcome::fail(format!("Only {} MB free", bytes / 1024 / 1024))
+        }
+        Ok(bytes) if bytes < LOW_DISK_WARN_BYTES => {
+            Outcome::warn(format!("{} MB free", bytes / 1024 / 1024))
+        }
+        Ok(bytes) => Outcome::pass(format!("{} MB free", bytes / 1024 / 1024)),
+        Err(e) => Outcome::warn(format!("Could not query free space: {}", e)),
+    }
+}
+
+fn managed_dirs(app: &AppHandle) -> Vec<(&'static str, Option<PathBuf>)> {
+    let path = app.path();
+    vec![
+        ("config", path.app_config_dir().ok()),
+        ("data", path.app_data_dir().ok()),
+        ("log", path.app_log_dir().ok()),
+        ("cache", path.app_cache_dir().ok()),
+    ]
+}
+
+fn check_directory_permissions(app: &AppHandle) -> Outcome {
+    let mut problems = Vec::new();
+    for (name, dir) in managed_dirs(app) {
+        let Some(dir) = dir else {
+            problems.push(format!("{}: cannot be resolved", name));
+            continue;
+        };
+        let probe = dir.join(".self_test_probe");
+        let result = std::fs::create_dir_all(&dir)
+            .and_then(|_| std::fs::write(&probe, b"probe"))
+            .and_then(|_| std::fs::read(&probe))
+            .and_then(|_| std::fs::remove_file(&probe));
+        if let Err(e) = result {
+            problems.push(format!("{} ({}): {}", name, dir.display(), e));
+        }
+    }
+    if problems.is_empty() {
+        Outcome::pass("All managed directories are readable and writable")
+    } else {
+        Outcome::fail(problems.join("; "))
+    }
+}
+
+fn reference_request() -> Result<ureq::Response, String> {
+    let agent = ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build();
+    match agent.head(REFERENCE_URL).call() {
+        Ok(response) => Ok(response),
+        // Any HTTP answer proves connectivity; we only care about transport errors
+        Err(ureq::Error::Status(_, response)) => Ok(response),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The startup platform probe, so Wayland and tray problems show up in reports
+fn check_desktop_session(app: &AppHandle) -> Outcome {
+    let Some(caps) = platform::capabilities(app) else {
+        return Outcome::warn("The platform probe has not run");
+    };
+    let version = |v: Option<u32>| v.map_or("absent".to_string(), |v| format!("v{}", v));
+    let mut detail = format!(
+        "{} session={} desktop={} portal={} (GlobalShortcuts {}, ScreenCast {}) tray_host={}",
+        caps.os,
+        caps.session_type.as_deref().unwrap_or("-"),
+        caps.desktop.as_deref().unwrap_or("-"),
+        caps.portal_available,
+        version(caps.global_shortcuts_portal_version),
+        version(caps.screen_cast_portal_version),
+        caps.tray_host
+    );
+    if !caps.probe_errors.is_empty() {
+        detail.push_str(&format!("; probe errors: {}", caps.probe_errors.join(", ")));
+    }
+    let unsupported: Vec<String> = [Feature::GlobalShortcuts, Feature::ScreenCapture, Feature::Tray]
+        .into_iter()
+        .filter(|f| caps.support(*f) == Support::Unsupported)
+        .map(|f| format!("{:?}", f))
+        .collect();
+    if unsupported.is_empty() {
+        Outcome::pass(detail)
+    } else {
+        Outcome::warn(format!("Unsupported: {}; {}", unsupported.join(", "), detail))
+    }
+}
+
+fn check_schema_drift(app: &AppHandle) -> Outcome {
+    let report = match db::open(app).and_then(|conn| schema_drift::report(&conn)) {
+        Ok(report) => report,
+        Err(e) => return Outcome::warn(format!("Cannot read schema drift: {}", e)),
+    };
+    if report.shapes.is_empty() {
+        return Outcome::pass(format!("All message shapes known (checked through #{})", report.checked_through));
+    }
+    let shapes: Vec<String> = report
+        .shapes
+        .iter()
+        .take(10)
+        .map(|s| format!("{} {} x{} (e.g. #{:?})", s.kind, s.value, s.occurrences, s.sample_message_ids))
+        .collect();
+    Outcome::warn(format!("Unknown message shapes: {}", shapes.join("; ")))
+}
+
+fn check_network(_app: &AppHandle) -> Outcome {
+    match reference_request() {
+        Ok(response) => Outcome::pass(format!(
+            "{} reachable (HTTP {})",
+            REFERENCE_URL,
+            response.status()
+        )),
+        Err(e) => Outcome::fail(format!("{} unreachable: {}", REFERENCE_URL, e)),
+    }
+}
+
+fn check_clock(_app: &AppHandle) -> Outcome {
+    let response = match reference_request() {
+        Ok(response) => response,
+        Err(e) => return Outcome::warn(format!("Skipped, reference server unreachable: {}", e)),
+    };
+    let Some(date) = response.header("Date") else {
+        return Outcome::warn("Reference server sent no Date header");
+    };
+    let server = match chrono::DateTime::parse_from_rfc2822(date) {
+        Ok(server) => server,
+        Err(e) => return Outcome::warn(format!("Unparseable Date header '{}': {}", date, e)),
+    };
+    let skew = (chrono::Utc::now() - server.with_timezone(&chrono::Utc))
+        .num_seconds()
+        .abs();
+    if skew >= CLOCK_SKEW_FAIL_SECS {
+        Outcome::fail(format!("System clock is off by {}s", skew))
+    } else if skew >= CLOCK_SKEW_WARN_SECS {
+        Outcome::warn(format!("System clock is off by {}s", skew))
+    } else {
+        Outcome::pass(format!("System clock within {}s of server time", skew))
+    }
+}
+
+fn run_suite(ctx: &jobs::JobContext) -> SelfTestReport {
+    let started_at = chrono::Utc::now().to_rfc3339();
+    let suite_start = Instant::now();
+    let deadline = suite_start + SUITE_TIMEOUT;
+    let total = CHECKS.len() as u64;
+    let (tx, rx) = mpsc::channel();
+
+    for (index, &(_, check)) in CHECKS.iter().enumerate() {
+        let tx = tx.clone();
+        let app = ctx.app().clone();
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            let outcome = check(&app);
+            let _ = tx.send((index, outcome, start.elapsed()));
+        });
+    }
+    drop(tx);
+
+    let mut results: Vec<Option<CheckResult>> = vec![None; CHECKS.len()];
+    let mut done = 0u64;
+    while done < total {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let Ok((index, outcome, elapsed)) = rx.recv_timeout(remaining) else {
+            break;
+        };
+        let id = CHECKS[index].0;
+        results[index] = Some(CheckResult {
+            id: id.to_string(),
+            status: outcome.status,
+            detail: outcome.detail,
+            hint_key: hint_key(id, outcome.status),
+            duration_ms: elapsed.as_millis() as u64,
+        });
+        done += 1;
+        ctx.progress(done, total, Some(id.to_string()));
+    }
+
+    let checks: Vec<CheckResult> = results
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| {
+            result.unwrap_or_else(|| {
+                let id = CHECKS[index].0;
+                CheckResult {
+                    id: id.to_string(),
+                    status: CheckStatus::TimedOut,
+                    detail: format!("Did not finish within {}s", SUITE_TIMEOUT.as_secs()),
+                    hint_key: hint_key(id, CheckStatus::TimedOut),
+                    duration_ms: SUITE_TIMEOUT.as_millis() as u64,
+                }
+            })
+        })
+        .collect();
+
+    let count = |status: CheckStatus| checks.iter().filter(|c| c.status == status).count() as u32;
+    SelfTestReport {
+        started_at,
+        duration_ms: suite_start.elapsed().as_millis() as u64,
+        passed: count(CheckStatus::Pass),
+        warnings: count(CheckStatus::Warn),
+        failures: count(CheckStatus::Fail) + count(CheckStatus::TimedOut),
+        checks,
+    }
+}
+
+/// Run the full self-test suite as a background job and return its id
+#[tauri::command]
+pub fn run_self_test(app: AppHandle) -> u64 {
+    jobs::spawn(&app, "self_test", |ctx| {
+        let report = run_suite(ctx);
+        // Persisting is best effort: the database itself may be what's broken
+        if let Ok(conn) = db::open(ctx.app()) {
+            let _ = settings::set(&conn, LAST_REPORT_KEY, &report);
+        }
+        serde_json::to_value(&report).map_err(|e| e.to_string())
+    })
+}
+
+/// Most recent stored self-test report, if one has been run
+#[tauri::command]
+pub async fn get_last_self_test(app: AppHandle) -> Result<Option<SelfTestReport>, String> {
+    db::with_conn(&app, |conn| settings::get(conn, LAST_REPORT_KEY)).await
+}
@@ -0,0 +1,464 @@
+// A session's working set: reference files every new task in the session gets.
+//
+// Files are copied into the `attachments` table (kind `file`), text as UTF-8 and
+// anything else as base64, with a token estimate taken once when stored.
+// `session_files` pins attachments to a session in order; when a task is created,
+// the `always_include` ones are linked to it in `task_attachments` by reference, so
+// the content is never duplicated per task.
+//
+// Deleting an attachment that is in a working set needs `force`, and the result
+// lists the links that went with it.
+
+use crate::error::CommandError;
+use crate::tokens::{self, TokenizerKind};
+use crate::{db, files, settings};
+use base64::Engine;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use serde_json::json;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+const FILE_ATTACHMENT: &str = "file";
+/// Largest file that can be added to a working set
+const MAX_FILE_BYTES: u64 = 20 * 1024 * 1024;
+const WARN_TOKENS_KEY: &str = "working_set_warn_tokens";
+const DEFAULT_WARN_TOKENS: u64 = 50_000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentInfo {
+    pub id: i64,
+    pub name: Option<String>,
+    pub mime_type: Option<String>,
+    /// `utf8` or `base64`
+    pub encoding: String,
+    pub size: i64,
+    /// `None` where no local estimate is possible
+    pub tokens: Option<u64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionFile {
+    pub attachment: AttachmentInfo,
+    pub position: i64,
+    pub always_include: bool,
+    pub added_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkingSet {
+    pub session_id: String,
+    pub files: Vec<SessionFile>,
+    /// Estimated tokens every new task pays for the always-included files
+    pub per_task_tokens: u64,
+    /// Always-included files whose tokens can't be estimated
+    pub uncounted_files: u32,
+    pub warn_tokens: u64,
+    /// Set when `per_task_tokens` is above `warn_tokens`
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemovedLink {
+    pub session_id: String,
+    pub position: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentDeletion {
+    pub attachment_id: i64,
+    /// Working-set links removed along with the attachment
+    pub removed_links: Vec<RemovedLink>,
+    /// Tasks that had the attachment linked
+    pub unlinked_tasks: u32,
+}
+
+fn mime_type(name: &str) -> Option<&'static str> {
+    let ext = name.rsplit_once('.')?.1.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "txt" | "log" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "html" | "htm" => "text/html",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/yaml",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => return None,
+    })
+}
+
+/// Token estimate for stored content: counted for text, from the pixel size for
+/// images, `None` for other binaries
+fn estimate_tokens(bytes: &[u8], text: Option<&str>) -> Option<u64> {
+    if let Some(text) = text {
+        return Some(tokens::count_text(TokenizerKind::Claude, text));
+    }
+    let reader = image::ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?;
+    let (width, height) = reader.into_dimensions().ok()?;
+    Some(tokens::image_tokens(TokenizerKind::Claude, width, height))
+}
+
+const ATTACHMENT_COLUMNS: &str = "a.id, a.name, a.mime_type, a.encoding, a.size, a.tokens, a.created_at";
+
+fn attachment_from_row(row: &rusqlite::Row) -> rusqlite::Result<AttachmentInfo> {
+    Ok(AttachmentInfo {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        mime_type: row.get(2)?,
+        encoding: row.get(3)?,
+        size: row.get(4)?,
+        tokens: row.get::<_, Option<i64>>(5)?.map(|t| t as u64),
+        created_at: row.get(6)?,
+    })
+}
+
+fn load_attachment(conn: &Connection, attachment_id: i64) -> Result<(AttachmentInfo, String), String> {
+    conn.query_row(
+        &format!("SELECT {}, a.kind FROM attachments a WHERE a.id = ?1", ATTACHMENT_COLUMNS),
+        [attachment_id],
+        |row| Ok((attachment_from_row(row)?, row.get(7)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Attachment {} not found", attachment_id))
+}
+
+pub(crate) fn load_working_set(conn: &Connection, session_id: &str) -> Result<WorkingSet, String> {
+    let files: Vec<SessionFile> = {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {}, s.position, s.always_include, s.added_at
+                 FROM session_files s JOIN attachments a ON a.id = s.attachment_id
+                 WHERE s.session_id = ?1 ORDER BY s.position",
+                ATTACHMENT_COLUMNS
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([session_id], |row| {
+                Ok(SessionFile {
+                    attachment: attachment_from_row(row)?,
+                    position: row.get(7)?,
+                    always_include: row.get(8)?,
+                    added_at: row.get(9)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    let included = files.iter().filter(|f| f.always_include);
+    let per_task_tokens = included.clone().filter_map(|f| f.attachment.tokens).sum();
+    let uncounted_files = included.filter(|f| f.attachment.tokens.is_none()).count() as u32;
+    let warn_tokens = settings::get_or(conn, WARN_TOKENS_KEY, DEFAULT_WARN_TOKENS)?;
+    let warning = (per_task_tokens > warn_tokens).then(|| {
+        format!(
+            "The working set adds about {} tokens to every new task in this session",
+            per_task_tokens
+        )
+    });
+    Ok(WorkingSet {
+        session_id: session_id.to_string(),
+        files,
+        per_task_tokens,
+        uncounted_files,
+        warn_tokens,
+        warning,
+    })
+}
+
+/// Link the session's always-included files to a task that was just created,
+/// returning the attachment ids in working-set order
+pub(crate) fn attach_to_task(conn: &Connection, task_id: &str, session_id: &str) -> Result<Vec<i64>, String> {
+    let ids: Vec<i64> = {
+        let mut stmt = conn
+            .prepare("SELECT attachment_id FROM session_files WHERE session_id = ?1 AND always_include = 1 ORDER BY position")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([session_id], |row| row.get(0)).map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    for (position, id) in ids.iter().enumerate() {
+        conn.execute(
+            "INSERT OR IGNORE INTO task_attachments (task_id, attachment_id, position, source)
+             VALUES (?1, ?2, ?3, 'session')",
+            params![task_id, id, position as i64],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(ids)
+}
+
+/// Copy a file into the attachment store so it can be added to working sets
+#[tauri::command]
+pub async fn store_attachment_file(app: AppHandle, path: String) -> Result<AttachmentInfo, String> {
+    let path = files::ensure_allowed(&app, &PathBuf::from(&path))?;
+    let size = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+    if size > MAX_FILE_BYTES {
+        return Err(format!(
+            "'{}' is {} MB; working set files are limited to {} MB",
+            path.display(),
+            size / (1024 * 1024),
+            MAX_FILE_BYTES / (1024 * 1024)
+        ));
+    }
+    let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+    let mime = name.as_deref().and_then(mime_type);
+    let text = std::str::from_utf8(&bytes).ok();
+    let tokens = estimate_tokens(&bytes, text);
+    let (encoding, content) = match text {
+        Some(text) => ("utf8", text.to_string()),
+        None => ("base64", base64::engine::general_purpose::STANDARD.encode(&bytes)),
+    };
+    db::with_conn(&app, move |conn| {
+        conn.execute(
+            "INSERT INTO attachments (kind, name, mime_type, encoding, content, size, tokens)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                FILE_ATTACHMENT,
+                name,
+                mime,
+                encoding,
+                content,
+                bytes.len() as i64,
+                tokens.map(|t| t as i64)
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(load_attachment(conn, conn.last_insert_rowid())?.0)
+    })
+    .await
+}
+
+/// Append an attachment to the session's working set, included in new tasks by default
+#[tauri::command]
+pub async fn add_session_file(app: AppHandle, session_id: String, attachment_id: i64) -> Result<WorkingSet, String> {
+    db::with_conn(&app, move |conn| {
+        let exists: bool = conn
+            .query_row("SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)", [&session_id], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        if !exists {
+            return Err(format!("Session '{}' not found", session_id));
+        }
+        let (_, kind) = load_attachment(conn, attachment_id)?;
+        if kind != FILE_ATTACHMENT {
+            return Err(format!("Attachment {} is not a file", attachment_id));
+        }
+        conn.execute(
+            "INSERT INTO session_files (session_id, attachment_id, position)
+             VALUES (?1, ?2, (SELECT COALESCE(MAX(position), -1) + 1 FROM session_files WHERE session_id = ?1))
+             ON CONFLICT(session_id, attachment_id) DO NOTHING",
+            params![session_id, attachment_id],
+        )
+        .map_err(|e| e.to_string())?;
+        load_working_set(conn, &session_id)
+    })
+    .await
+}
+
+/// Take an attachment out of the working set; the attachment itself is kept
+#[tauri::command]
+pub async fn remove_session_file(app: AppHandle, session_id: String, attachment_id: i64) -> Result<WorkingSet, String> {
+    db::with_conn(&app, move |conn| {
+        conn.execute(
+            "DELETE FROM session_files WHERE session_id = ?1 AND attachment_id = ?2",
+            params![session_id, attachment_id],
+        )
+        .map_err(|e| e.to_string())?;
+        load_working_set(conn, &session_id)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn list_session_files(app: AppHandle, session_id: String) -> Result<WorkingSet, String> {
+    db::with_conn(&app, move |conn| load_working_set(conn, &session_id)).await
+}
+
+/// Set the order of the working set; `attachment_ids` must list every file in it once
+#[tauri::command]
+pub async fn reorder_session_files(
+    app: AppHandle,
+    session_id: String,
+    attachment_ids: Vec<i64>,
+) -> Result<WorkingSet, String> {
+    db::with_conn(&app, move |conn| {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut existing: Vec<i64> = load_working_set(&tx, &session_id)?
+            .files
+            .iter()
+            .map(|f| f.attachment.id)
+            .collect();
+        let mut requested = attachment_ids.clone();
+        existing.sort_unstable();
+        requested.sort_unstable();
+        if existing != requested {
+            return Err("Reorder must list every file in the working set exactly once".to_string());
+        }
+        for (position, id) in attachment_ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE session_files SET position = ?1 WHERE session_id = ?2 AND attachment_id = ?3",
+                params![position as i64, session_id, id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        let set = load_working_set(&tx, &session_id)?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(set)
+    })
+    .await
+}
+
+/// Keep a file in the working set without adding it to new tasks, or include it again
+#[tauri::command]
+pub async fn set_session_file_always_include(
+    app: AppHandle,
+    session_id: String,
+    attachment_id: i64,
+    always_include: bool,
+) -> Result<WorkingSet, String> {
+    db::with_conn(&app, move |conn| {
+        let changed = conn
+            .execute(
+                "UPDATE session_files SET always_include = ?1 WHERE session_id = ?2 AND attachment_id = ?3",
+                params![always_include, session_id, attachment_id],
+            )
+            .map_err(|e| e.to_string())?;
+        if changed == 0 {
+            return Err(format!("Attachment {} is not in the working set", attachment_id));
+        }
+        load_working_set(conn, &session_id)
+    })
+    .await
+}
+
+/// Delete a stored file, refusing with `conflict` while it is in a working set
+/// unless `force` is set
+pub(crate) fn delete(conn: &mut Connection, attachment_id: i64, force: bool) -> Result<AttachmentDeletion, CommandError> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let (_, kind) = load_attachment(&tx, attachment_id)?;
+    if kind != FILE_ATTACHMENT {
+        // Prompt overflow belongs to its task and goes when the task does
+        return Err(format!("Attachment {} is not a file", attachment_id).into());
+    }
+    let removed_links: Vec<RemovedLink> = {
+        let mut stmt = tx
+            .prepare("SELECT session_id, position FROM session_files WHERE attachment_id = ?1 ORDER BY session_id")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([attachment_id], |row| {
+                Ok(RemovedLink {
+                    session_id: row.get(0)?,
+                    position: row.get(1)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    if !removed_links.is_empty() && !force {
+        return Err(CommandError::Conflict {
+            message: format!(
+                "Attachment {} is in the working set of {} session(s)",
+                attachment_id,
+                removed_links.len()
+            ),
+            existing: json!({ "sessions": removed_links }),
+        });
+    }
+    let unlinked_tasks: u32 = tx
+        .query_row(
+            "SELECT COUNT(*) FROM task_attachments WHERE attachment_id = ?1",
+            [attachment_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    tx.execute("DELETE FROM attachments WHERE id = ?1", [attachment_id])
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(AttachmentDeletion {
+        attachment_id,
+        removed_links,
+        unlinked_tasks,
+    })
+}
+
+/// Delete a stored file. One that is in a working set is refused with `conflict`
+/// unless `force` is set, in which case the links go with it and are reported.
+#[tauri::command]
+pub async fn delete_attachment(app: AppHandle, attachment_id: i64, force: bool) -> Result<AttachmentDeletion, CommandError> {
+    let deletion = db::with_conn(&app, move |conn| Ok(delete(conn, attachment_id, force))).await??;
+    if !deletion.removed_links.is_empty() {
+        println!(
+            "[WorkingSet] Deleted attachment {} and its links in {} session(s)",
+            attachment_id,
+            deletion.removed_links.len()
+        );
+    }
+    Ok(deletion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch("PRAGMA foreign_keys = ON").unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, prompt) VALUES ('s1', 'p');
+             INSERT INTO attachments (id, kind, name, encoding, content, size, tokens)
+             VALUES (1, 'file', 'a.md', 'utf8', 'alpha', 5, 2000),
+                    (2, 'file', 'b.md', 'utf8', 'beta', 4, 60000);
+             INSERT INTO session_files (session_id, attachment_id, position, always_include)
+             VALUES ('s1', 2, 0, 1), ('s1', 1, 1, 0);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn new_tasks_get_always_included_files_in_order() {
+        let conn = db();
+        conn.execute("UPDATE session_files SET always_include = 1", []).unwrap();
+        conn.execute("INSERT INTO tasks (id, session_id, prompt) VALUES ('t1', 's1', 'p')", [])
+            .unwrap();
+        assert_eq!(attach_to_task(&conn, "t1", "s1").unwrap(), vec![2, 1]);
+        let set = load_working_set(&conn, "s1").unwrap();
+        assert_eq!(set.per_task_tokens, 62_000);
+        assert!(set.warning.is_some());
+    }
+
+    #[test]
+    fn excluded_files_are_not_counted_or_attached() {
+        let conn = db();
+        conn.execute("UPDATE session_files SET always_include = 0 WHERE attachment_id = 2", [])
+            .unwrap();
+        let set = load_working_set(&conn, "s1").unwrap();
+        assert_eq!(set.per_task_tokens, 0);
+        assert!(set.warning.is_none());
+        conn.execute("INSERT INTO tasks (id, session_id, prompt) VALUES ('t1', 's1', 'p')", [])
+            .unwrap();
+        assert!(attach_to_task(&conn, "t1", "s1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn deleting_a_working_set_file_needs_force() {
+        let mut conn = db();
+        let refused = delete(&mut conn, 2, false).unwrap_err();
+        assert!(matches!(refused, CommandError::Conflict { .. }));
+        assert_eq!(load_working_set(&conn, "s1").unwrap().files.len(), 2);
+
+        let deletion = delete(&mut conn, 2, true).unwrap();
+        assert_eq!(deletion.removed_links.len(), 1);
+        assert_eq!(deletion.removed_links[0].session_id, "s1");
+        let remaining = load_working_set(&conn, "s1").unwrap();
+        assert_eq!(remaining.files.len(), 1);
+        assert_eq!(remaining.files[0].attachment.id, 1);
+    }
+}
@@ -0,0 +1,44 @@
+// Typed access to the key/value `settings` table.
+//
+// Values are stored JSON-encoded, the same way the frontend settings store writes
+// them, so both sides can read each other's keys.
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Read and decode a setting; missing keys and undecodable values yield `None`
+pub fn get<T: DeserializeOwned>(conn: &Connection, key: &str) -> Result<Option<T>, String> {
+    let raw: Option<String> = conn
+        .query_row("SELECT value FROM settings WHERE key = ?1", [key], |row| {
+            row.get(0)
+        })
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(raw.and_then(|value| serde_json::from_str(&value).ok()))
+}
+
+pub fn get_or<T: DeserializeOwned>(conn: &Connection, key: &str, default: T) -> Result<T, String> {
+    Ok(get(conn, key)?.unwrap_or(default))
+}
+
+pub fn set<T: Serialize>(conn: &Connection, key: &str, value: &T) -> Result<(), String> {
+    let encoded = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value, updated_at) VALUES (?1, ?2, datetime('now'))",
+        params![key, encoded],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn remove(conn: &Connection, key: &str) -> Result<(), String> {
+    conn.execute("DELETE FROM settings WHERE key = ?1", [key])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Developer mode unlocks maintainer tooling; always on in debug builds
+pub fn developer_mode(conn: &Connection) -> bool {
+    cfg!(debug_assertions) || get_or(conn, "developer_mode", false).unwrap_or(false)
+}
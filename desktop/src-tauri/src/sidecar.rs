@@ -0,0 +1,1439 @@
+// The API sidecar: the bundled `workany-api` process this app spawns, supervises
+// and stops when `sidecar_mode` says this run manages it.
+//
+// `Manager` owns the child process, its port, the crash-restart policy and the loop
+// reading the child's output. Processes come from a `Spawner`, the shell plugin in
+// the app, and everything else the app does around them (events, the pid file, the
+// sidecar log, the readiness watch) goes through a `Host`, so tests can drive
+// spawns, crashes and stops with scripted `CommandEvent`s. A sidecar that fails on
+// its own is respawned with backoff until it has crashed `MAX_SIDECAR_RESTARTS`
+// times in a row; one that exits cleanly or is stopped on purpose is not. Stops go
+// through SIGTERM first so the API can flush SQLite. The port is chosen once, from
+// the `api_port` setting, and kept across restarts until `set_api_port` asks for
+// another. Stderr lines also go to the webview as `sidecar://stderr`; stdout lines
+// only with the `sidecar_stdout_events` debug setting on, since the API is chatty.
+
+use crate::sidecar_log::LogStream;
+use crate::{
+    api_health, circuit, db, events, pidfile, port_owner, safe_mode, settings, sidecar_log, sidecar_mode, window,
+};
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::async_runtime::Receiver;
+use tauri::{AppHandle, Manager as _};
+use tauri_plugin_shell::process::{CommandChild, CommandEvent, TerminatedPayload};
+use tauri_plugin_shell::ShellExt;
+
+// Port the sidecar tries first; taken ports are skipped upward from here
+const DEFAULT_API_PORT: u16 = 2620;
+// Ports tried above the preferred one before asking the OS for any free port
+const PORT_SEARCH_SPAN: u16 = 50;
+// Setting with the preferred sidecar port, instead of DEFAULT_API_PORT
+pub(crate) const PINNED_API_PORT_KEY: &str = "api_port";
+// Setting that opts into killing whatever holds the preferred port
+const KILL_PORT_OWNER_KEY: &str = "api_kill_port_owner";
+// Port of the API started separately with `pnpm dev:api` during development
+const DEV_API_PORT: u16 = 2026;
+// How long force_restart_api waits for the port to be released
+const PORT_FREE_TIMEOUT: Duration = Duration::from_secs(10);
+// How long a killed sidecar's port may take to be released
+const PORT_RELEASE_TIMEOUT: Duration = Duration::from_secs(2);
+// Delay before respawning a crashed sidecar, doubling per attempt up to the cap
+const RESTART_BACKOFF_START: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(30);
+// How long a stopping sidecar gets after SIGTERM to flush SQLite and stop its agent
+// processes before it is killed, in milliseconds
+const SHUTDOWN_GRACE_KEY: &str = "sidecar_shutdown_grace_ms";
+const DEFAULT_SHUTDOWN_GRACE_MS: u64 = 5_000;
+const MAX_SHUTDOWN_GRACE_MS: u64 = 60_000;
+/// Lines of stderr sent with `sidecar://terminated`
+const TERMINATED_STDERR_LINES: usize = 20;
+// Debug setting: also send stdout lines to the webview, from the next spawn
+const STDOUT_EVENTS_KEY: &str = "sidecar_stdout_events";
+// Crash restarts in a row before the sidecar is left down
+const MAX_SIDECAR_RESTARTS: u32 = 5;
+// A sidecar that ran this long before crashing starts the backoff over
+const SIDECAR_STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+/// The manager `start` set up for the app, for `api_port` callers without a handle
+static ACTIVE: OnceLock<Manager> = OnceLock::new();
+
+/// The sidecar's port, chosen once so restarts keep it until `set_api_port` asks
+/// for another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SidecarPort {
+    pub port: u16,
+    /// Opted in through `api_kill_port_owner`: whatever else holds the port is killed
+    pub kill_owner: bool,
+}
+
+/// When a sidecar that failed on its own is respawned
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RestartPolicy {
+    /// Delay before the first respawn, doubling per attempt up to `backoff_max`
+    pub backoff_start: Duration,
+    pub backoff_max: Duration,
+    /// Crash restarts in a row before the sidecar is left down
+    pub max_restarts: u32,
+    /// A sidecar that ran this long before crashing starts the backoff over
+    pub stable_uptime: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            backoff_start: RESTART_BACKOFF_START,
+            backoff_max: RESTART_BACKOFF_MAX,
+            max_restarts: MAX_SIDECAR_RESTARTS,
+            stable_uptime: SIDECAR_STABLE_UPTIME,
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// The wait before crash restart `attempt`, counted from 1; `None` once the
+    /// budget is spent
+    fn delay(&self, attempt: u32) -> Option<Duration> {
+        (attempt <= self.max_restarts).then(|| {
+            self.backoff_start
+                .saturating_mul(2u32.saturating_pow(attempt - 1))
+                .min(self.backoff_max)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+enum Phase {
+    #[default]
+    Stopped,
+    Running,
+    Restarting,
+    Failed(String),
+}
+
+/// The sidecar process's state, as `api_status` reports it. Whether the API
+/// answers is `ApiStatus`.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SidecarStatus {
+    Running { pid: u32, port: u16, uptime_secs: u64 },
+    /// Being restarted by hand or after a crash
+    Restarting,
+    /// Not started (safe mode), stopped while idle, or exited cleanly
+    Stopped,
+    Failed { reason: String },
+}
+
+/// The sidecar's state as one flat record, from `get_sidecar_status`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SidecarSummary {
+    running: bool,
+    pid: Option<u32>,
+    uptime_secs: u64,
+    /// Sidecars spawned after the first this session, after crashes or by hand
+    restarts: u32,
+}
+
+/// A spawned sidecar process
+pub(crate) trait SidecarChild: Send {
+    fn pid(&self) -> u32;
+    /// Ask the process to exit, so the API can flush SQLite and stop its agent
+    /// processes; its `Terminated` event says when it has
+    fn terminate(&self);
+    /// Kill the process and whatever it started
+    fn kill(self: Box<Self>);
+}
+
+/// Starts sidecar processes on a port: the bundled binary through the shell plugin
+/// in the app, a fake replaying scripted output in tests
+pub(crate) trait Spawner: Send + Sync {
+    fn spawn(&self, port: u16) -> Result<Spawned, String>;
+}
+
+/// A spawned process's output events and its handle
+pub(crate) type Spawned = (Receiver<CommandEvent>, Box<dyn SidecarChild>);
+
+/// What the manager needs from the app around the process itself
+pub(crate) trait Host: Send + Sync {
+    /// The port for the first spawn
+    fn choose_port(&self) -> SidecarPort;
+    /// How long a stopping sidecar gets after SIGTERM before it is killed
+    fn shutdown_grace(&self) -> Duration;
+    /// Stop a stale sidecar holding `port`; only asked for opted-in ports
+    fn clear_port(&self, port: u16);
+    /// Wait for `port` to be released before a restart spawns on it
+    fn wait_for_port(&self, port: u16) -> Result<(), String>;
+    fn spawned(&self, pid: u32, port: u16);
+    fn output(&self, stream: LogStream, chunk: &[u8]);
+    /// Whether stdout lines go to the webview too; asked once per spawn
+    fn stdout_events(&self) -> bool;
+    /// One line of output for the webview
+    fn line(&self, stream: LogStream, line: &str);
+    /// The sidecar `pid` terminated; `expected` unless it died on its own
+    fn terminated(&self, pid: u32, status: &TerminatedPayload, expected: bool, spawned_at: DateTime<Utc>);
+    /// It died on its own with a failure; `restart_in_ms` says whether it comes back
+    fn crashed(&self, crash: events::SidecarCrashed);
+    /// Restarts stopped after repeated crashes
+    fn gave_up(&self, port: u16, error: String);
+    /// A restart by hand is stopping the sidecar `pid`
+    fn restarting(&self, pid: Option<u32>);
+    /// The sidecar was stopped for good on exit
+    fn shut_down(&self, port: Option<SidecarPort>);
+}
+
+type Backend = (Arc<dyn Spawner>, Arc<dyn Host>);
+
+/// Supervises the sidecar. Clones share one process; the app manages one.
+#[derive(Clone, Default)]
+pub(crate) struct Manager(Arc<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    policy: RestartPolicy,
+    /// Set once by `start`
+    backend: OnceLock<Backend>,
+    child: Mutex<Option<Box<dyn SidecarChild>>>,
+    /// When the tracked child was spawned
+    started: Mutex<Option<Instant>>,
+    port: Mutex<Option<SidecarPort>>,
+    /// Crash restarts in a row so far
+    restarts: AtomicU32,
+    /// Sidecars spawned this session, for `get_sidecar_status`
+    spawns: AtomicU32,
+    /// Held while a process is spawned, so two starts can't both spawn one
+    spawning: AtomicBool,
+    /// Set on app exit so the kill isn't taken for a crash, and nothing respawns
+    shutting_down: AtomicBool,
+    /// Held by `exclusive_restart`, so concurrent restarts are refused
+    restarting: AtomicBool,
+    /// PID of the last sidecar seen to terminate, signalled on `exited`
+    terminated: Mutex<Option<u32>>,
+    exited: Condvar,
+    /// What the sidecar was last seen doing, for `api_status`
+    phase: Mutex<Phase>,
+}
+
+impl Manager {
+    #[cfg(test)]
+    fn new(policy: RestartPolicy) -> Self {
+        Self(Arc::new(Inner {
+            policy,
+            ..Default::default()
+        }))
+    }
+
+    /// Take over the sidecar for `app`: stop the sidecar a killed previous run left
+    /// behind, choose this run's port and, unless safe mode defers it until
+    /// `force_restart_api`, spawn the sidecar. Returns whether it spawned.
+    pub(crate) fn start(&self, app: &AppHandle) -> Result<bool, String> {
+        self.attach(Arc::new(ShellSpawner(app.clone())), Arc::new(AppHost(app.clone())));
+        let _ = ACTIVE.set(self.clone());
+        reap_orphaned_sidecar(app);
+        self.port()?;
+        if !app.state::<safe_mode::SafeMode>().runs(safe_mode::SIDECAR) {
+            return Ok(false);
+        }
+        self.launch().map(|_| true)
+    }
+
+    fn attach(&self, spawner: Arc<dyn Spawner>, host: Arc<dyn Host>) {
+        let _ = self.0.backend.set((spawner, host));
+    }
+
+    fn backend(&self) -> Result<Backend, String> {
+        self.0
+            .backend
+            .get()
+            .cloned()
+            .ok_or_else(|| "The API sidecar hasn't been set up".to_string())
+    }
+
+    /// The port chosen so far, without choosing one
+    pub(crate) fn chosen_port(&self) -> Option<SidecarPort> {
+        self.0.port.lock().ok().and_then(|port| *port)
+    }
+
+    /// The port chosen so far, or the host's choice
+    fn port(&self) -> Result<SidecarPort, String> {
+        let (_, host) = self.backend()?;
+        let mut chosen = self.0.port.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(*chosen.get_or_insert_with(|| host.choose_port()))
+    }
+
+    /// Let the next spawn choose its port again
+    pub(crate) fn forget_port(&self) {
+        if let Ok(mut chosen) = self.0.port.lock() {
+            *chosen = None;
+        }
+    }
+
+    fn set_phase(&self, phase: Phase) {
+        if let Ok(mut current) = self.0.phase.lock() {
+            *current = phase;
+        }
+    }
+
+    /// PID of the tracked sidecar
+    pub(crate) fn pid(&self) -> Option<u32> {
+        self.0.child.lock().ok().and_then(|guard| guard.as_ref().map(|child| child.pid()))
+    }
+
+    fn uptime(&self) -> Option<Duration> {
+        self.0.started.lock().ok().and_then(|started| started.map(|at| at.elapsed()))
+    }
+
+    /// Clear the port of other processes if opted in, then spawn a fresh sidecar
+    pub(crate) fn launch(&self) -> Result<u32, String> {
+        let (_, host) = self.backend()?;
+        let port = self.port()?;
+        if port.kill_owner {
+            host.clear_port(port.port);
+        }
+        self.spawn()
+    }
+
+    /// Spawn a sidecar on the chosen port and track it, returning its PID. Refused
+    /// while another is tracked or being spawned, and once the app is exiting.
+    fn spawn(&self) -> Result<u32, String> {
+        let (spawner, host) = self.backend()?;
+        if self.0.spawning.swap(true, Ordering::SeqCst) {
+            return Err("The API sidecar is already starting".to_string());
+        }
+        let result = self.spawn_tracked(spawner.as_ref(), host);
+        self.0.spawning.store(false, Ordering::SeqCst);
+        result
+    }
+
+    fn spawn_tracked(&self, spawner: &dyn Spawner, host: Arc<dyn Host>) -> Result<u32, String> {
+        if let Some(pid) = self.pid() {
+            return Err(format!("The API sidecar is already running (PID {})", pid));
+        }
+        if self.0.shutting_down.load(Ordering::SeqCst) {
+            return Err("The app is shutting down".to_string());
+        }
+        let port = self.port()?.port;
+        let (rx, child) = spawner.spawn(port).map_err(|e| {
+            let reason = format!("Failed to spawn API sidecar: {}", e);
+            self.set_phase(Phase::Failed(reason.clone()));
+            reason
+        })?;
+        let pid = child.pid();
+        {
+            let mut guard = self.0.child.lock().map_err(|e| e.to_string())?;
+            // Exit cleanup takes the child under this lock after setting the flag,
+            // so a sidecar spawned while it ran is either seen by it or killed here
+            if self.0.shutting_down.load(Ordering::SeqCst) {
+                drop(guard);
+                child.kill();
+                return Err("The app is shutting down".to_string());
+            }
+            *guard = Some(child);
+        }
+        if let Ok(mut started) = self.0.started.lock() {
+            *started = Some(Instant::now());
+        }
+        self.0.spawns.fetch_add(1, Ordering::SeqCst);
+        self.set_phase(Phase::Running);
+        host.spawned(pid, port);
+        self.watch(host, rx, pid);
+        Ok(pid)
+    }
+
+    /// Forward the sidecar's output to the host. The loop ends with the process's
+    /// `Terminated` event, so a replaced sidecar's loop doesn't outlive it.
+    fn watch(&self, host: Arc<dyn Host>, mut rx: Receiver<CommandEvent>, pid: u32) {
+        let manager = self.clone();
+        let spawned_at = Utc::now();
+        let stdout_events = host.stdout_events();
+        tauri::async_runtime::spawn(async move {
+            let forward = |stream, chunk: &[u8]| {
+                for line in sidecar_log::split_lines(&String::from_utf8_lossy(chunk)) {
+                    host.line(stream, line);
+                }
+            };
+            while let Some(event) = rx.recv().await {
+                match event {
+                    CommandEvent::Stdout(chunk) => {
+                        host.output(LogStream::Stdout, &chunk);
+                        if stdout_events {
+                            forward(LogStream::Stdout, &chunk);
+                        }
+                    }
+                    CommandEvent::Stderr(chunk) => {
+                        host.output(LogStream::Stderr, &chunk);
+                        forward(LogStream::Stderr, &chunk);
+                    }
+                    // Also into api.log, which is all a packaged app leaves behind
+                    CommandEvent::Error(error) => {
+                        let message = format!("[API Spawn Error] {}", error);
+                        eprintln!("{}", message);
+                        host.output(LogStream::Stderr, message.as_bytes());
+                    }
+                    CommandEvent::Terminated(status) => {
+                        let message = format!("[API] Process terminated with status: {:?}", status);
+                        println!("{}", message);
+                        host.output(LogStream::Stderr, message.as_bytes());
+                        manager.on_terminated(&host, pid, &status, spawned_at);
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    /// The sidecar `pid` is gone: wake `terminate`, tell the host, and respawn it if
+    /// it died on its own
+    fn on_terminated(&self, host: &Arc<dyn Host>, pid: u32, status: &TerminatedPayload, spawned_at: DateTime<Utc>) {
+        if let Ok(mut terminated) = self.0.terminated.lock() {
+            *terminated = Some(pid);
+            self.0.exited.notify_all();
+        }
+        // Stops and exit cleanup untrack the child first
+        let expected = self.0.shutting_down.load(Ordering::SeqCst) || self.pid() != Some(pid);
+        host.terminated(pid, status, expected, spawned_at);
+        self.handle_exit(host, pid, status.code, status.signal);
+    }
+
+    /// React to the sidecar `pid` exiting. Only a child that is still tracked died
+    /// on its own; after a failure exit it is respawned with backoff.
+    fn handle_exit(&self, host: &Arc<dyn Host>, pid: u32, code: Option<i32>, signal: Option<i32>) {
+        let inner = &self.0;
+        if inner.shutting_down.load(Ordering::SeqCst) {
+            return;
+        }
+        {
+            let Ok(mut guard) = inner.child.lock() else {
+                return;
+            };
+            if guard.as_ref().map(|child| child.pid()) != Some(pid) {
+                return;
+            }
+            // The process is gone; don't let exit cleanup kill a reused PID
+            *guard = None;
+        }
+        if code == Some(0) {
+            println!("[API] Sidecar exited cleanly; not restarting");
+            self.set_phase(Phase::Stopped);
+            return;
+        }
+
+        if self.uptime().is_some_and(|uptime| uptime >= inner.policy.stable_uptime) {
+            inner.restarts.store(0, Ordering::SeqCst);
+        }
+        let attempt = inner.restarts.fetch_add(1, Ordering::SeqCst) + 1;
+        let max_attempts = inner.policy.max_restarts;
+        let delay = inner.policy.delay(attempt);
+        eprintln!(
+            "[API] Sidecar crashed (code {:?}, signal {:?}); {}",
+            code,
+            signal,
+            match delay {
+                Some(delay) => format!("restart {} of {} in {}s", attempt, max_attempts, delay.as_secs()),
+                None => "giving up after repeated crashes".to_string(),
+            }
+        );
+        host.crashed(events::SidecarCrashed {
+            code,
+            signal,
+            attempt,
+            max_attempts,
+            restart_in_ms: delay.map(|delay| delay.as_millis() as u64),
+        });
+        let Some(delay) = delay else {
+            let error = format!("The API sidecar crashed {} times in a row", max_attempts);
+            self.set_phase(Phase::Failed(error.clone()));
+            host.gave_up(self.chosen_port().map_or(DEFAULT_API_PORT, |p| p.port), error);
+            return;
+        };
+        self.set_phase(Phase::Restarting);
+
+        let manager = self.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            // Shutdown or a manual restart during the wait wins
+            let inner = &manager.0;
+            if inner.shutting_down.load(Ordering::SeqCst)
+                || inner.restarting.load(Ordering::SeqCst)
+                || manager.pid().is_some()
+            {
+                return;
+            }
+            if let Err(e) = manager.launch() {
+                eprintln!("[API] Restart after crash failed: {}", e);
+                if manager.pid().is_none() {
+                    manager.set_phase(Phase::Failed(e));
+                }
+            }
+        });
+    }
+
+    /// Send the untracked `child` SIGTERM so the API can flush the SQLite WAL, finish
+    /// in-flight writes and stop its agent processes, then kill it and whatever it
+    /// started if it hasn't terminated within `grace`
+    fn terminate(&self, child: Box<dyn SidecarChild>, grace: Duration) {
+        let pid = child.pid();
+        child.terminate();
+        let exited = self.0.terminated.lock().ok().and_then(|terminated| {
+            self.0
+                .exited
+                .wait_timeout_while(terminated, grace, |terminated| *terminated != Some(pid))
+                .ok()
+                .map(|(_, timeout)| !timeout.timed_out())
+        });
+        if exited == Some(true) {
+            println!("[API] Sidecar (PID {}) exited after SIGTERM", pid);
+        } else {
+            eprintln!(
+                "[API] Sidecar (PID {}) still running {}ms after SIGTERM; killing it",
+                pid,
+                grace.as_millis()
+            );
+            child.kill();
+        }
+    }
+
+    /// Stop the tracked sidecar, returning whether one was running
+    pub(crate) fn stop(&self) -> bool {
+        let Ok((_, host)) = self.backend() else {
+            return false;
+        };
+        let child = self.0.child.lock().ok().and_then(|mut guard| guard.take());
+        // A restart stops the old sidecar on its way to the new one
+        if let Ok(mut phase) = self.0.phase.lock() {
+            if !matches!(*phase, Phase::Restarting) {
+                *phase = Phase::Stopped;
+            }
+        }
+        match child {
+            Some(child) => {
+                self.terminate(child, host.shutdown_grace());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop the sidecar, wait for its port, and spawn a fresh one on the same port,
+    /// returning the port and the new PID
+    pub(crate) fn restart(&self) -> Result<(u16, u32), String> {
+        let (_, host) = self.backend()?;
+        self.set_phase(Phase::Restarting);
+        if self.stop() {
+            println!("[API] Restart: stopped tracked sidecar process");
+        }
+        // A manual restart gets the full crash-restart budget back
+        self.0.restarts.store(0, Ordering::SeqCst);
+        // Clear the port even if the tracked child was already gone, if opted in
+        let port = self.port()?;
+        if port.kill_owner {
+            host.clear_port(port.port);
+        }
+        if let Err(e) = host.wait_for_port(port.port) {
+            self.set_phase(Phase::Failed(e.clone()));
+            return Err(e);
+        }
+        let pid = self.spawn()?;
+        Ok((port.port, pid))
+    }
+
+    /// Run `restart` unless another restart is in progress, announcing it with
+    /// `sidecar://restarting`
+    pub(crate) fn exclusive_restart<T>(&self, restart: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+        let (_, host) = self.backend()?;
+        if self.0.restarting.swap(true, Ordering::SeqCst) {
+            return Err("The API sidecar is already restarting".to_string());
+        }
+        self.set_phase(Phase::Restarting);
+        host.restarting(self.pid());
+        let result = restart();
+        self.0.restarting.store(false, Ordering::SeqCst);
+        result
+    }
+
+    /// Claim the stop on exit, for `RunEvent::ExitRequested`: whether a sidecar is
+    /// running and nothing has started stopping it for the exit yet
+    pub(crate) fn begin_shutdown(&self) -> bool {
+        self.pid().is_some() && !self.0.shutting_down.swap(true, Ordering::SeqCst)
+    }
+
+    /// Stop the sidecar for good on app exit; nothing respawns it afterwards
+    pub(crate) fn shutdown(&self) {
+        self.0.shutting_down.store(true, Ordering::SeqCst);
+        if self.stop() {
+            println!("[App] API sidecar stopped");
+        }
+        if let Ok((_, host)) = self.backend() {
+            host.shut_down(self.chosen_port());
+        }
+    }
+
+    pub(crate) fn status(&self) -> SidecarStatus {
+        let phase = self.0.phase.lock().map(|phase| phase.clone()).unwrap_or_default();
+        match phase {
+            Phase::Running => match (self.pid(), self.chosen_port()) {
+                (Some(pid), Some(port)) => SidecarStatus::Running {
+                    pid,
+                    port: port.port,
+                    uptime_secs: self.uptime().map_or(0, |uptime| uptime.as_secs()),
+                },
+                // Exited between the phase and the child being read
+                _ => SidecarStatus::Stopped,
+            },
+            Phase::Restarting => SidecarStatus::Restarting,
+            Phase::Stopped => SidecarStatus::Stopped,
+            Phase::Failed(reason) => SidecarStatus::Failed { reason },
+        }
+    }
+
+    pub(crate) fn summary(&self) -> SidecarSummary {
+        let pid = self.pid();
+        SidecarSummary {
+            running: pid.is_some(),
+            pid,
+            uptime_secs: pid.and(self.uptime()).map_or(0, |uptime| uptime.as_secs()),
+            restarts: self.0.spawns.load(Ordering::SeqCst).saturating_sub(1),
+        }
+    }
+}
+
+/// Port the API server is expected on: the sidecar's, or a separately run API's
+pub(crate) fn api_port() -> u16 {
+    match sidecar_mode::current() {
+        Some(mode) if !mode.managed => mode.external_port,
+        _ => ACTIVE
+            .get()
+            .and_then(Manager::chosen_port)
+            .map_or(DEFAULT_API_PORT, |p| p.port),
+    }
+}
+
+/// Where a separately run API listens unless `api_port` is pinned
+pub(crate) fn default_external_port() -> u16 {
+    if cfg!(debug_assertions) {
+        DEV_API_PORT
+    } else {
+        DEFAULT_API_PORT
+    }
+}
+
+/// Ask the OS for a free port. The listener is dropped before returning, so the
+/// sidecar can bind the port.
+fn free_port() -> std::io::Result<u16> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+    listener.local_addr().map(|addr| addr.port())
+}
+
+fn port_is_free(port: u16) -> bool {
+    std::net::TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// Whether another socket holds `port`. Binding answers this without parsing any
+/// tool output; errors other than `AddrInUse` don't count as a conflict.
+fn port_in_use(port: u16) -> bool {
+    matches!(
+        std::net::TcpListener::bind(("127.0.0.1", port)),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse
+    )
+}
+
+/// `preferred` if it is free or held only by a stale sidecar of ours (which is
+/// killed), otherwise the next free port above it, otherwise any free port.
+/// Unrelated processes are never touched.
+fn find_available_port(preferred: u16) -> u16 {
+    if port_is_free(preferred) {
+        return preferred;
+    }
+    if let Some(owner) = port_owner::port_owner(preferred).filter(|&pid| is_sidecar_process(pid)) {
+        println!("[API] Port {} is held by a stale sidecar (PID {}); stopping it", preferred, owner);
+        kill_pids(&[owner]);
+        if wait_for_port_free(preferred, Duration::from_secs(2)).is_ok() {
+            return preferred;
+        }
+    }
+    let above = (preferred.saturating_add(1)..=preferred.saturating_add(PORT_SEARCH_SPAN)).find(|&port| port_is_free(port));
+    above.unwrap_or_else(|| {
+        free_port().unwrap_or_else(|e| {
+            eprintln!("[API] No free port ({}), trying {} anyway", e, preferred);
+            preferred
+        })
+    })
+}
+
+/// Whether `pid` is a `workany-api` sidecar, e.g. one left behind by a crash
+fn is_sidecar_process(pid: u32) -> bool {
+    use std::process::Command;
+
+    #[cfg(unix)]
+    let output = Command::new("ps").args(["-p", &pid.to_string(), "-o", "comm="]).output();
+    #[cfg(windows)]
+    let output = Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+        .output();
+    output.is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("workany-api"))
+}
+
+/// Ask `pids` to exit: SIGTERM on Unix, `taskkill` without `/F` on Windows
+fn terminate_pids(pids: &[u32]) {
+    use std::process::Command;
+
+    for pid in pids {
+        #[cfg(unix)]
+        let _ = Command::new("kill").args(["-TERM", &pid.to_string()]).output();
+        #[cfg(windows)]
+        let _ = Command::new("taskkill").args(["/T", "/PID", &pid.to_string()]).output();
+    }
+}
+
+/// Kill the processes `pid` started, e.g. the agent processes of a sidecar that
+/// didn't stop them itself
+fn kill_child_processes(pid: u32) {
+    use std::process::Command;
+
+    #[cfg(unix)]
+    let _ = Command::new("pkill").args(["-KILL", "-P", &pid.to_string()]).output();
+    #[cfg(windows)]
+    let _ = Command::new("taskkill").args(["/F", "/T", "/PID", &pid.to_string()]).output();
+}
+
+/// Grace period for a stopping sidecar, from `sidecar_shutdown_grace_ms`
+fn shutdown_grace(app: &AppHandle) -> Duration {
+    let ms = db::open(app)
+        .and_then(|conn| settings::get_or(&conn, SHUTDOWN_GRACE_KEY, DEFAULT_SHUTDOWN_GRACE_MS))
+        .unwrap_or(DEFAULT_SHUTDOWN_GRACE_MS);
+    Duration::from_millis(ms.min(MAX_SHUTDOWN_GRACE_MS))
+}
+
+fn kill_pids(pids: &[u32]) {
+    use std::process::Command;
+
+    for pid in pids {
+        #[cfg(unix)]
+        let _ = Command::new("kill").args(["-9", &pid.to_string()]).output();
+        #[cfg(windows)]
+        let _ = Command::new("taskkill").args(["/F", "/PID", &pid.to_string()]).output();
+    }
+}
+
+/// Stop a stale sidecar listening on the API port, killing it if it is still there
+/// after `grace`. Only used when `api_kill_port_owner` opts in. A port held by
+/// anything other than `workany-api` is left alone, and the caller falls back to
+/// another port or reports the port as busy.
+fn kill_existing_api_process(port: u16, grace: Duration) {
+    if !port_in_use(port) {
+        return;
+    }
+    // Only ever our own sidecar; anything else is left for the dynamic port to avoid
+    let Some(pid) = port_owner::port_owner(port) else {
+        eprintln!("[API] Port {} is in use by a process we can't see; not stopping it", port);
+        return;
+    };
+    if !is_sidecar_process(pid) {
+        eprintln!("[API] Port {} is held by another process (PID {}); not stopping it", port, pid);
+        return;
+    }
+    println!("[API] Stopping existing process on port {}: PID {}", port, pid);
+    terminate_pids(&[pid]);
+    if wait_for_port_free(port, grace).is_ok() {
+        return;
+    }
+    if let Some(pid) = port_owner::port_owner(port).filter(|&pid| is_sidecar_process(pid)) {
+        println!("[API] Killing process on port {} after the grace period: PID {}", port, pid);
+        kill_pids(&[pid]);
+    }
+    // Wait for the OS to release the port rather than guessing how long it takes
+    if let Err(e) = wait_for_port_free(port, PORT_RELEASE_TIMEOUT) {
+        eprintln!("[API] {}", e);
+    }
+}
+
+/// Stop the sidecar a previous run left behind when it crashed or was force-quit,
+/// before this run chooses its port
+fn reap_orphaned_sidecar(app: &AppHandle) {
+    let Some(orphan) = pidfile::take_orphan(app) else {
+        return;
+    };
+    println!("[API] Stopping the sidecar left by a previous run: PID {}", orphan.pid);
+    terminate_pids(&[orphan.pid]);
+    let deadline = Instant::now() + shutdown_grace(app);
+    while pidfile::is_running(&orphan) && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    if pidfile::is_running(&orphan) {
+        println!("[API] Killing the previous run's sidecar after the grace period: PID {}", orphan.pid);
+        kill_child_processes(orphan.pid);
+        kill_pids(&[orphan.pid]);
+    }
+}
+
+/// Poll until nothing is listening on `port`, or give up after `timeout`
+fn wait_for_port_free(port: u16, timeout: Duration) -> Result<(), String> {
+    let start = Instant::now();
+    loop {
+        if std::net::TcpListener::bind(("127.0.0.1", port)).is_ok() {
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(format!("Port {} is still in use after {}s", port, timeout.as_secs()));
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// The port for this run from the `api_port` setting: the preferred port if it is
+/// free, otherwise the next free one. Startup reads the setting through its own
+/// short-lived connection, before the webview has opened the database.
+fn choose_port(app: &AppHandle) -> SidecarPort {
+    let (preferred, kill_owner) = db::open(app)
+        .and_then(|conn| {
+            Ok((
+                settings::get_or(&conn, PINNED_API_PORT_KEY, DEFAULT_API_PORT)?,
+                settings::get_or(&conn, KILL_PORT_OWNER_KEY, false)?,
+            ))
+        })
+        .unwrap_or((DEFAULT_API_PORT, false));
+    if kill_owner && !port_is_free(preferred) {
+        kill_existing_api_process(preferred, shutdown_grace(app));
+    }
+    let port = find_available_port(preferred);
+    if port == preferred {
+        println!("[API] Using port {}", port);
+    } else {
+        println!("[API] Port {} is taken; using {}", preferred, port);
+    }
+    SidecarPort {
+        port,
+        kill_owner: kill_owner && port == preferred,
+    }
+}
+
+/// The bundled `workany-api` binary, through the shell plugin
+struct ShellSpawner(AppHandle);
+
+impl Spawner for ShellSpawner {
+    fn spawn(&self, port: u16) -> Result<Spawned, String> {
+        let (rx, child) = self
+            .0
+            .shell()
+            .sidecar("workany-api")
+            .map_err(|e| e.to_string())?
+            .env("PORT", port.to_string())
+            .env("NODE_ENV", "production")
+            .spawn()
+            .map_err(|e| e.to_string())?;
+        Ok((rx, Box::new(ShellChild(child))))
+    }
+}
+
+struct ShellChild(CommandChild);
+
+impl SidecarChild for ShellChild {
+    fn pid(&self) -> u32 {
+        self.0.pid()
+    }
+
+    fn terminate(&self) {
+        terminate_pids(&[self.0.pid()]);
+    }
+
+    fn kill(self: Box<Self>) {
+        kill_child_processes(self.0.pid());
+        let _ = self.0.kill();
+    }
+}
+
+/// The app around the sidecar: settings, events, the pid file and the API readiness
+struct AppHost(AppHandle);
+
+impl Host for AppHost {
+    fn choose_port(&self) -> SidecarPort {
+        choose_port(&self.0)
+    }
+
+    fn shutdown_grace(&self) -> Duration {
+        shutdown_grace(&self.0)
+    }
+
+    fn clear_port(&self, port: u16) {
+        kill_existing_api_process(port, shutdown_grace(&self.0));
+    }
+
+    fn wait_for_port(&self, port: u16) -> Result<(), String> {
+        wait_for_port_free(port, PORT_FREE_TIMEOUT)
+    }
+
+    fn spawned(&self, pid: u32, port: u16) {
+        // On disk, for the next launch to clean up if this one is killed
+        pidfile::record(&self.0, pid);
+        // A fresh process gets a fresh chance on every endpoint
+        circuit::reset_all(&self.0);
+        events::emit_typed(&self.0, events::SidecarSpawned { pid });
+        events::emit_typed(&self.0, events::SidecarStarted { pid, port });
+        api_health::watch_api_ready(&self.0, port);
+    }
+
+    fn output(&self, stream: LogStream, chunk: &[u8]) {
+        sidecar_log::record(&self.0, stream, chunk);
+    }
+
+    fn stdout_events(&self) -> bool {
+        db::open(&self.0)
+            .and_then(|conn| settings::get_or(&conn, STDOUT_EVENTS_KEY, false))
+            .unwrap_or(false)
+    }
+
+    fn line(&self, stream: LogStream, line: &str) {
+        let line = line.to_string();
+        match stream {
+            LogStream::Stdout => events::emit_typed(&self.0, events::SidecarStdout { line }),
+            LogStream::Stderr => events::emit_typed(&self.0, events::SidecarStderr { line }),
+        }
+    }
+
+    /// Tell the webview, with the end of the sidecar's stderr for crash details
+    fn terminated(&self, pid: u32, status: &TerminatedPayload, expected: bool, spawned_at: DateTime<Utc>) {
+        events::emit_typed(
+            &self.0,
+            events::SidecarTerminated {
+                pid,
+                code: status.code,
+                signal: status.signal,
+                expected,
+                stderr_tail: sidecar_log::stderr_since(&self.0, spawned_at, TERMINATED_STDERR_LINES),
+            },
+        );
+    }
+
+    fn crashed(&self, crash: events::SidecarCrashed) {
+        // Requests wait for the respawned sidecar's health check
+        if crash.restart_in_ms.is_some() {
+            api_health::set_api_status(&self.0, api_health::ApiStatus::Pending);
+        }
+        events::emit_typed(&self.0, crash);
+    }
+
+    fn gave_up(&self, port: u16, error: String) {
+        let unreachable = events::ApiUnreachable {
+            port,
+            timeout_ms: 0,
+            error,
+        };
+        api_health::set_api_status(&self.0, api_health::ApiStatus::Unreachable(unreachable.clone()));
+        events::emit_typed_to(&self.0, window::MAIN_WINDOW, unreachable);
+    }
+
+    fn restarting(&self, pid: Option<u32>) {
+        events::emit_typed(&self.0, events::SidecarRestarting { pid });
+    }
+
+    fn shut_down(&self, port: Option<SidecarPort>) {
+        pidfile::clear(&self.0);
+        // Opted-in kill by port, as a fallback
+        if let Some(port) = port.filter(|p| p.kill_owner) {
+            kill_existing_api_process(port.port, shutdown_grace(&self.0));
+        }
+    }
+}
+
+/// Whether the sidecar process is running, restarting, stopped or failed, for a
+/// status indicator that doesn't infer it from failed requests
+#[tauri::command]
+pub fn api_status(app: AppHandle) -> Result<SidecarStatus, String> {
+    if !sidecar_mode::managed() {
+        return Err(sidecar_mode::unmanaged_error());
+    }
+    Ok(app.state::<Manager>().status())
+}
+
+/// Whether the sidecar runs, its PID and uptime, and how often it was restarted
+/// this session; `api_status` has the same process state as a tagged status
+#[tauri::command]
+pub fn get_sidecar_status(app: AppHandle) -> Result<SidecarSummary, String> {
+    if !sidecar_mode::managed() {
+        return Err(sidecar_mode::unmanaged_error());
+    }
+    Ok(app.state::<Manager>().summary())
+}
+
+/// Port the webview should send API requests to
+#[tauri::command]
+pub fn get_api_port() -> u16 {
+    api_port()
+}
+
+fn store_api_port(conn: &rusqlite::Connection, port: Option<u16>) -> Result<(), String> {
+    match port {
+        Some(port) if port < 1024 => Err(format!("Port {} is reserved; pick one from 1024 up", port)),
+        Some(port) => settings::set(conn, PINNED_API_PORT_KEY, &port),
+        None => settings::remove(conn, PINNED_API_PORT_KEY),
+    }
+}
+
+/// Prefer `port` for the sidecar from the next launch, or with `None` go back to
+/// the default. A taken port is skipped for the next free one above it.
+#[tauri::command]
+pub async fn set_pinned_api_port(app: AppHandle, port: Option<u16>) -> Result<(), String> {
+    db::with_conn(&app, move |conn| store_api_port(conn, port)).await
+}
+
+/// Manage the sidecar (`true`) or expect a separately run API (`false`) from the
+/// next launch; `None` goes back to the build's default. `WORKANY_SIDECAR` still wins.
+#[tauri::command]
+pub async fn set_sidecar_enabled(app: AppHandle, enabled: Option<bool>) -> Result<(), String> {
+    db::with_conn(&app, move |conn| match enabled {
+        Some(enabled) => settings::set(conn, sidecar_mode::SIDECAR_ENABLED_KEY, &enabled),
+        None => settings::remove(conn, sidecar_mode::SIDECAR_ENABLED_KEY),
+    })
+    .await
+}
+
+/// Like `set_pinned_api_port`, but also restart the sidecar on the new port now.
+/// Returns the port it got, which is the next free one above `port` if that is
+/// taken; the webview gets it with `api://ready`.
+#[tauri::command]
+pub async fn set_api_port(app: AppHandle, port: Option<u16>) -> Result<u16, String> {
+    if !sidecar_mode::managed() {
+        return Err(sidecar_mode::unmanaged_error());
+    }
+    db::with_conn(&app, move |conn| store_api_port(conn, port)).await?;
+    let sidecar = app.state::<Manager>().inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        sidecar.exclusive_restart(|| {
+            // The old sidecar lets go of its port before the new one is chosen
+            sidecar.stop();
+            sidecar.forget_port();
+            sidecar.restart().map(|(port, _)| port)
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Set how long a stopping sidecar gets after SIGTERM before it is killed, in
+/// milliseconds; `None` restores the default
+#[tauri::command]
+pub async fn set_sidecar_shutdown_grace(app: AppHandle, grace_ms: Option<u64>) -> Result<(), String> {
+    if grace_ms.is_some_and(|ms| ms > MAX_SHUTDOWN_GRACE_MS) {
+        return Err(format!("The grace period can be at most {}ms", MAX_SHUTDOWN_GRACE_MS));
+    }
+    db::with_conn(&app, move |conn| match grace_ms {
+        Some(ms) => settings::set(conn, SHUTDOWN_GRACE_KEY, &ms),
+        None => settings::remove(conn, SHUTDOWN_GRACE_KEY),
+    })
+    .await
+}
+
+/// Also send the sidecar's stdout to the webview as `sidecar://stdout`, for
+/// debugging; takes effect from the next spawn
+#[tauri::command]
+pub async fn set_sidecar_stdout_events(app: AppHandle, enabled: bool) -> Result<(), String> {
+    db::with_conn(&app, move |conn| settings::set(conn, STDOUT_EVENTS_KEY, &enabled)).await
+}
+
+/// Recovery for a wedged sidecar: stop the tracked child (and, if opted in,
+/// whatever else holds the port), wait for the port to be released, then spawn a fresh
+/// sidecar on the same port. Returns once spawned; readiness follows as `api://ready`.
+#[tauri::command]
+pub async fn force_restart_api(app: AppHandle) -> Result<(), String> {
+    if !sidecar_mode::managed() {
+        return Err(sidecar_mode::unmanaged_error());
+    }
+    let sidecar = app.state::<Manager>().inner().clone();
+    tauri::async_runtime::spawn_blocking(move || sidecar.restart().map(|_| ()))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Restart a wedged API like `force_restart_api`, but only return, with the new
+/// PID, once the fresh sidecar answers its health check; fails if it doesn't
+/// within the API ready timeout, or while another restart is in progress. The UI
+/// gets `sidecar://restarting` and then `sidecar://started`.
+#[tauri::command]
+pub async fn restart_api_sidecar(app: AppHandle) -> Result<u32, String> {
+    if !sidecar_mode::managed() {
+        return Err(sidecar_mode::unmanaged_error());
+    }
+    let sidecar = app.state::<Manager>().inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        sidecar.exclusive_restart(|| {
+            // stop waits for the old process's Terminated event
+            sidecar.restart().and_then(|(port, pid)| {
+                api_health::wait_for_api_health(port, api_health::api_ready_timeout(&app)).map(|_| pid)
+            })
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::mpsc;
+    use tauri::async_runtime::Sender;
+
+    const PORT: u16 = 4100;
+
+    /// What a fake sidecar does once spawned
+    #[derive(Debug, Clone, Copy)]
+    enum Script {
+        /// Print a line on each stream and exit with this code
+        Exit(i32),
+        /// Run until asked to stop
+        Run,
+        /// Run until killed
+        IgnoreTerm,
+    }
+
+    fn terminated(code: Option<i32>, signal: Option<i32>) -> CommandEvent {
+        CommandEvent::Terminated(TerminatedPayload { code, signal })
+    }
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    struct FakeChild {
+        pid: u32,
+        obeys_term: bool,
+        events: Sender<CommandEvent>,
+        log: Log,
+    }
+
+    impl SidecarChild for FakeChild {
+        fn pid(&self) -> u32 {
+            self.pid
+        }
+
+        fn terminate(&self) {
+            if self.obeys_term {
+                self.log.lock().unwrap().push(format!("exited {}", self.pid));
+                let _ = self.events.try_send(terminated(None, Some(15)));
+            }
+        }
+
+        fn kill(self: Box<Self>) {
+            self.log.lock().unwrap().push(format!("killed {}", self.pid));
+            let _ = self.events.try_send(terminated(None, Some(9)));
+        }
+    }
+
+    /// Spawns processes following `scripts` in order, then ones that run
+    #[derive(Default)]
+    struct FakeSpawner {
+        scripts: Mutex<VecDeque<Script>>,
+        spawned: AtomicU32,
+        /// Signals the fake processes got
+        log: Log,
+    }
+
+    impl FakeSpawner {
+        fn new(scripts: &[Script]) -> Arc<Self> {
+            Arc::new(Self {
+                scripts: Mutex::new(scripts.iter().copied().collect()),
+                ..Default::default()
+            })
+        }
+
+        fn log(&self) -> Vec<String> {
+            self.log.lock().unwrap().clone()
+        }
+
+        fn spawned(&self) -> u32 {
+            self.spawned.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Spawner for FakeSpawner {
+        fn spawn(&self, port: u16) -> Result<Spawned, String> {
+            assert_eq!(port, PORT);
+            let pid = 100 + self.spawned.fetch_add(1, Ordering::SeqCst);
+            let script = self.scripts.lock().unwrap().pop_front().unwrap_or(Script::Run);
+            let (tx, rx) = tauri::async_runtime::channel(16);
+            if let Script::Exit(code) = script {
+                tx.try_send(CommandEvent::Stdout(b"listening\n".to_vec())).unwrap();
+                tx.try_send(CommandEvent::Stderr(b"boom\n".to_vec())).unwrap();
+                tx.try_send(terminated(Some(code), None)).unwrap();
+            }
+            let child = FakeChild {
+                pid,
+                obeys_term: !matches!(script, Script::IgnoreTerm),
+                events: tx,
+                log: self.log.clone(),
+            };
+            Ok((rx, Box::new(child)))
+        }
+    }
+
+    /// Records what the manager reports; `wait_for_port` blocks while gated
+    #[derive(Default)]
+    struct FakeHost {
+        events: Mutex<Vec<String>>,
+        /// Output kept for the sidecar log
+        output: Mutex<Vec<String>>,
+        /// Output lines sent on to the webview
+        lines: Mutex<Vec<String>>,
+        stdout_events: AtomicBool,
+        gate: Mutex<Option<mpsc::Receiver<()>>>,
+    }
+
+    impl FakeHost {
+        fn note(&self, line: String) {
+            self.events.lock().unwrap().push(line);
+        }
+
+        fn events(&self) -> Vec<String> {
+            self.events.lock().unwrap().clone()
+        }
+
+        fn saw(&self, line: &str) -> bool {
+            self.events().iter().any(|event| event == line)
+        }
+
+        fn lines(&self) -> Vec<String> {
+            self.lines.lock().unwrap().clone()
+        }
+
+        fn output(&self) -> Vec<String> {
+            self.output.lock().unwrap().clone()
+        }
+
+        /// Hold the next `wait_for_port` until the returned sender is used
+        fn gate(&self) -> mpsc::Sender<()> {
+            let (tx, rx) = mpsc::channel();
+            *self.gate.lock().unwrap() = Some(rx);
+            tx
+        }
+    }
+
+    impl Host for FakeHost {
+        fn choose_port(&self) -> SidecarPort {
+            SidecarPort {
+                port: PORT,
+                kill_owner: false,
+            }
+        }
+
+        fn shutdown_grace(&self) -> Duration {
+            Duration::from_millis(50)
+        }
+
+        fn clear_port(&self, _port: u16) {}
+
+        fn wait_for_port(&self, port: u16) -> Result<(), String> {
+            self.note(format!("waiting {}", port));
+            let gate = self.gate.lock().unwrap().take();
+            if let Some(gate) = gate {
+                let _ = gate.recv();
+            }
+            Ok(())
+        }
+
+        fn spawned(&self, pid: u32, _port: u16) {
+            self.note(format!("spawned {}", pid));
+        }
+
+        fn output(&self, stream: LogStream, chunk: &[u8]) {
+            let chunk = String::from_utf8_lossy(chunk);
+            self.output.lock().unwrap().push(format!("{:?} {}", stream, chunk.trim_end()));
+        }
+
+        fn stdout_events(&self) -> bool {
+            self.stdout_events.load(Ordering::SeqCst)
+        }
+
+        fn line(&self, stream: LogStream, line: &str) {
+            self.lines.lock().unwrap().push(format!("{:?} {}", stream, line));
+        }
+
+        fn terminated(&self, pid: u32, _status: &TerminatedPayload, expected: bool, _spawned_at: DateTime<Utc>) {
+            self.note(format!("terminated {}{}", pid, if expected { " expected" } else { "" }));
+        }
+
+        fn crashed(&self, crash: events::SidecarCrashed) {
+            self.note(format!(
+                "crashed {}/{} restart {:?}",
+                crash.attempt, crash.max_attempts, crash.restart_in_ms
+            ));
+        }
+
+        fn gave_up(&self, port: u16, _error: String) {
+            self.note(format!("gave up {}", port));
+        }
+
+        fn restarting(&self, pid: Option<u32>) {
+            self.note(format!("restarting {:?}", pid));
+        }
+
+        fn shut_down(&self, _port: Option<SidecarPort>) {
+            self.note("shut down".to_string());
+        }
+    }
+
+    fn policy() -> RestartPolicy {
+        RestartPolicy {
+            backoff_start: Duration::from_millis(5),
+            backoff_max: Duration::from_millis(20),
+            max_restarts: 2,
+            stable_uptime: Duration::from_secs(60),
+        }
+    }
+
+    fn manager(scripts: &[Script], policy: RestartPolicy) -> (Manager, Arc<FakeSpawner>, Arc<FakeHost>) {
+        let spawner = FakeSpawner::new(scripts);
+        let host = Arc::new(FakeHost::default());
+        let manager = Manager::new(policy);
+        manager.attach(spawner.clone(), host.clone());
+        (manager, spawner, host)
+    }
+
+    fn wait_until(what: &str, done: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn the_restart_delay_doubles_up_to_the_cap_and_runs_out() {
+        let policy = RestartPolicy::default();
+        let delays: Vec<_> = (1..=6).map(|attempt| policy.delay(attempt)).collect();
+        let secs = |s| Some(Duration::from_secs(s));
+        assert_eq!(delays, [secs(1), secs(2), secs(4), secs(8), secs(16), None]);
+        let capped = RestartPolicy {
+            max_restarts: 10,
+            ..policy
+        };
+        assert_eq!(capped.delay(10), secs(30));
+    }
+
+    #[test]
+    fn a_crashed_sidecar_is_respawned_until_it_stays_up() {
+        let (manager, spawner, host) = manager(&[Script::Exit(1), Script::Run], policy());
+        assert_eq!(manager.launch(), Ok(100));
+        wait_until("the respawn", || manager.pid() == Some(101));
+        assert_eq!(
+            host.events(),
+            [
+                "spawned 100",
+                "terminated 100",
+                "crashed 1/2 restart Some(5)",
+                "spawned 101"
+            ]
+        );
+        assert!(matches!(manager.status(), SidecarStatus::Running { pid: 101, port: PORT, .. }));
+        assert_eq!(manager.summary().restarts, 1);
+        assert_eq!(spawner.spawned(), 2);
+    }
+
+    #[test]
+    fn repeated_crashes_give_up_and_clean_exits_are_not_restarted() {
+        let (manager, spawner, host) = manager(&[Script::Exit(1), Script::Exit(1), Script::Exit(1)], policy());
+        manager.launch().unwrap();
+        wait_until("giving up", || host.saw("gave up 4100"));
+        assert!(host.saw("crashed 2/2 restart Some(10)"));
+        assert!(host.saw("crashed 3/2 restart None"));
+        assert!(matches!(manager.status(), SidecarStatus::Failed { .. }));
+        assert_eq!(spawner.spawned(), 3);
+
+        let (manager, spawner, host) = manager(&[Script::Exit(0)], policy());
+        manager.launch().unwrap();
+        wait_until("the exit", || host.saw("terminated 100"));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(matches!(manager.status(), SidecarStatus::Stopped));
+        assert_eq!(spawner.spawned(), 1);
+    }
+
+    #[test]
+    fn stderr_lines_are_forwarded_and_stdout_only_when_asked() {
+        let (manager, _spawner, host) = manager(&[Script::Exit(0)], policy());
+        manager.launch().unwrap();
+        wait_until("the exit", || host.saw("terminated 100"));
+        assert_eq!(host.lines(), ["Stderr boom"]);
+
+        let (manager, _spawner, host) = manager(&[Script::Exit(0)], policy());
+        host.stdout_events.store(true, Ordering::SeqCst);
+        manager.launch().unwrap();
+        wait_until("the exit", || host.saw("terminated 100"));
+        assert_eq!(host.lines(), ["Stdout listening", "Stderr boom"]);
+    }
+
+    #[test]
+    fn spawn_errors_and_exits_are_kept_in_the_sidecar_log() {
+        let (manager, spawner, host) = manager(&[Script::Exit(3)], RestartPolicy { max_restarts: 0, ..policy() });
+        manager.launch().unwrap();
+        wait_until("the exit", || host.saw("terminated 100"));
+        assert_eq!(
+            host.output(),
+            [
+                "Stdout listening",
+                "Stderr boom",
+                "Stderr [API] Process terminated with status: TerminatedPayload { code: Some(3), signal: None }",
+            ]
+        );
+        assert_eq!(spawner.spawned(), 1);
+    }
+
+    #[test]
+    fn a_second_start_or_restart_is_refused_while_one_is_under_way() {
+        let (manager, spawner, _host) = manager(&[], policy());
+        assert_eq!(manager.launch(), Ok(100));
+        let second = manager.launch().unwrap_err();
+        assert!(second.contains("already running (PID 100)"), "{}", second);
+        assert_eq!(spawner.spawned(), 1);
+
+        let nested = manager.exclusive_restart(|| manager.exclusive_restart(|| Ok(())));
+        assert_eq!(nested, Err("The API sidecar is already restarting".to_string()));
+        // The flag is released once the outer restart is done
+        assert_eq!(manager.exclusive_restart(|| manager.restart()), Ok((PORT, 101)));
+        assert_eq!(spawner.log(), ["exited 100"]);
+    }
+
+    #[test]
+    fn a_stop_kills_a_sidecar_that_ignores_sigterm() {
+        let (manager, spawner, host) = manager(&[Script::IgnoreTerm], policy());
+        manager.launch().unwrap();
+        assert!(manager.stop());
+        assert!(!manager.stop());
+        assert_eq!(spawner.log(), ["killed 100"]);
+        wait_until("the kill", || host.saw("terminated 100 expected"));
+        assert!(!host.events().iter().any(|event| event.starts_with("crashed")));
+    }
+
+    #[test]
+    fn shutting_down_during_a_restart_leaves_nothing_running() {
+        let (manager, spawner, host) = manager(&[], policy());
+        manager.launch().unwrap();
+        let release = host.gate();
+        let restart = {
+            let manager = manager.clone();
+            std::thread::spawn(move || manager.exclusive_restart(|| manager.restart()))
+        };
+        wait_until("the restart to wait for the port", || host.saw("waiting 4100"));
+        manager.shutdown();
+        release.send(()).unwrap();
+        assert_eq!(restart.join().unwrap(), Err("The app is shutting down".to_string()));
+        assert_eq!(manager.pid(), None);
+        assert_eq!(spawner.spawned(), 1);
+        assert!(host.saw("terminated 100 expected"));
+        assert!(host.saw("shut down"));
+    }
+
+    #[test]
+    fn shutting_down_during_the_crash_backoff_cancels_the_respawn() {
+        let slow = RestartPolicy {
+            backoff_start: Duration::from_millis(50),
+            ..policy()
+        };
+        let (manager, spawner, host) = manager(&[Script::Exit(1)], slow);
+        manager.launch().unwrap();
+        wait_until("the crash", || host.saw("crashed 1/2 restart Some(50)"));
+        manager.shutdown();
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(spawner.spawned(), 1);
+        assert_eq!(manager.pid(), None);
+        assert!(!manager.begin_shutdown());
+    }
+}
@@ -0,0 +1,483 @@
+// Recent API sidecar output, kept in memory so it can be attached to diagnostics,
+// and streamed to the webview for the in-app log viewer.
+//
+// Output is no longer printed, since stdout is invisible in a packaged app; this
+// keeps the last `CAPACITY` lines with the time they arrived. Output arrives in
+// chunks of bytes that may hold several lines and needn't be UTF-8, so chunks are
+// decoded lossily and split, and every line is kept and streamed on its own. Each line is
+// an `api-log` event until more than `STREAM_RATE` arrive in a second; the rest of
+// that second's lines are queued and sent every `FLUSH_INTERVAL` as one
+// `api-log-batch`. Every line is also appended to `api.log` in the app log
+// directory with its time and level, so output survives the app closing and
+// `read_api_log_tail` can show it later; the file is rotated at
+// `MAX_FILE_BYTES`, keeping `LOG_FILES` files in all. A message redaction `scrub`s
+// the removed text from the buffer and from every log file. `record` runs on the
+// sidecar's output task, so the writes stay off the main thread. Development builds
+// don't manage the sidecar, so the buffer and file stay empty there.
+
+use crate::events::{self, ApiLogBatch, ApiLogLevel, ApiLogLine};
+use crate::redaction;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const CAPACITY: usize = 2000;
+const LOG_FILE: &str = "api.log";
+const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+/// `api.log` plus this many minus one rotated files (`api.log.1` is the newest)
+const LOG_FILES: usize = 5;
+/// Most lines `read_api_log_tail` returns
+const MAX_TAIL_LINES: usize = 5000;
+/// Bytes read at a time when reading a log file from its end
+const TAIL_BLOCK: u64 = 64 * 1024;
+/// Lines per second sent as single events before the rest are batched
+const STREAM_RATE: u32 = 60;
+const FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    pub at: DateTime<Utc>,
+    pub stream: LogStream,
+    pub line: String,
+}
+
+/// Rate limit for the `api-log` stream
+#[derive(Default)]
+struct Throttle {
+    window_start: Option<Instant>,
+    sent: u32,
+    pending: Vec<ApiLogLine>,
+    /// A flush of `pending` is scheduled
+    flushing: bool,
+}
+
+impl Throttle {
+    /// `Some` to send `line` now, `None` if it was queued for the next batch
+    fn offer(&mut self, now: Instant, line: ApiLogLine) -> Option<ApiLogLine> {
+        if self.window_start.map_or(true, |start| now.duration_since(start) >= Duration::from_secs(1)) {
+            self.window_start = Some(now);
+            self.sent = 0;
+        }
+        // Keep order: once lines are queued, later ones queue behind them
+        if self.sent < STREAM_RATE && self.pending.is_empty() {
+            self.sent += 1;
+            return Some(line);
+        }
+        self.pending.push(line);
+        None
+    }
+}
+
+/// The last `count` lines of the file at `path`, oldest first. The file is read
+/// backwards a block at a time, so a large log costs no more than its tail.
+fn tail(path: &Path, count: usize) -> io::Result<Vec<String>> {
+    let mut file = File::open(path)?;
+    let mut start = file.metadata()?.len();
+    let mut buf = Vec::new();
+    // One line break more than `count`, so the first line kept is complete
+    while start > 0 && buf.iter().filter(|&&b| b == b'\n').count() <= count {
+        let step = TAIL_BLOCK.min(start);
+        start -= step;
+        let mut block = vec![0; step as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&buf);
+        buf = block;
+    }
+    let text = String::from_utf8_lossy(&buf);
+    let lines: Vec<&str> = text.lines().collect();
+    let skip = lines.len().saturating_sub(count);
+    Ok(lines[skip..].iter().map(|line| line.to_string()).collect())
+}
+
+/// The last `count` lines across `path` and its rotated files, oldest first
+fn tail_files(path: &Path, count: usize) -> io::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    for n in 0..LOG_FILES {
+        if lines.len() >= count {
+            break;
+        }
+        let file = if n == 0 { path.to_path_buf() } else { numbered(path, n) };
+        let mut older = match tail(&file, count - lines.len()) {
+            Ok(older) => older,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e),
+        };
+        older.append(&mut lines);
+        lines = older;
+    }
+    Ok(lines)
+}
+
+/// The open `api.log` and its size so far
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    len: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { path, file, len })
+    }
+
+    /// Append `entry`, first rotating if it would take the file past the limit
+    fn write(&mut self, entry: &str) -> io::Result<()> {
+        if self.len > 0 && self.len + entry.len() as u64 > MAX_FILE_BYTES {
+            rotate(&self.path, LOG_FILES)?;
+            *self = Self::open(self.path.clone())?;
+        }
+        self.file.write_all(entry.as_bytes())?;
+        self.len += entry.len() as u64;
+        Ok(())
+    }
+}
+
+/// `path.n`, the `n`th newest rotated file
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path.display(), n))
+}
+
+/// Shift `path.1` .. `path.{keep - 2}` up by one, dropping the oldest, and move
+/// `path` to `path.1`
+fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    for n in (1..keep.saturating_sub(1)).rev() {
+        let from = numbered(path, n);
+        if from.exists() {
+            fs::rename(&from, numbered(path, n + 1))?;
+        }
+    }
+    if keep > 1 {
+        fs::rename(path, numbered(path, 1))
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[derive(Default)]
+pub struct SidecarLog {
+    lines: Mutex<VecDeque<LogLine>>,
+    throttle: Mutex<Throttle>,
+    /// Opened on the first line; stays `None` if the log directory isn't writable
+    file: Mutex<Option<LogFile>>,
+}
+
+fn log_path(app: &AppHandle) -> Result<PathBuf, String> {
+    Ok(app.path().app_log_dir().map_err(|e| e.to_string())?.join(LOG_FILE))
+}
+
+/// Append a line to `api.log`, opening it on first use. Failures are logged once
+/// per attempt to open, not per line.
+fn persist(app: &AppHandle, state: &SidecarLog, at: DateTime<Utc>, level: ApiLogLevel, line: &str) {
+    let Ok(mut file) = state.file.lock() else {
+        return;
+    };
+    if file.is_none() {
+        match log_path(app).and_then(|path| LogFile::open(path).map_err(|e| e.to_string())) {
+            Ok(opened) => *file = Some(opened),
+            Err(e) => {
+                eprintln!("[API] Cannot open the sidecar log file: {}", e);
+                return;
+            }
+        }
+    }
+    let level = match level {
+        ApiLogLevel::Info => "INFO",
+        ApiLogLevel::Error => "ERROR",
+    };
+    let entry = format!("{} {:<5} {}\n", at.to_rfc3339_opts(SecondsFormat::Millis, true), level, line);
+    if let Some(Err(e)) = file.as_mut().map(|f| f.write(&entry)) {
+        eprintln!("[API] Cannot write the sidecar log file: {}", e);
+        // Reopen on the next line, which also retries a failed rotation
+        *file = None;
+    }
+}
+
+/// The non-empty lines of an output chunk
+pub(crate) fn split_lines(chunk: &str) -> impl Iterator<Item = &str> {
+    chunk.lines().map(str::trim_end).filter(|line| !line.is_empty())
+}
+
+/// Send `line` to the webview, or queue it and make sure a flush is coming
+fn send(app: &AppHandle, state: &SidecarLog, line: ApiLogLine) {
+    let Ok(mut throttle) = state.throttle.lock() else {
+        return;
+    };
+    if let Some(line) = throttle.offer(Instant::now(), line) {
+        drop(throttle);
+        events::emit_typed(app, line);
+        return;
+    }
+    if std::mem::replace(&mut throttle.flushing, true) {
+        return;
+    }
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(FLUSH_INTERVAL);
+        let Some(state) = app.try_state::<SidecarLog>() else {
+            return;
+        };
+        let lines = match state.throttle.lock() {
+            Ok(mut throttle) => {
+                throttle.flushing = false;
+                std::mem::take(&mut throttle.pending)
+            }
+            Err(_) => return,
+        };
+        if !lines.is_empty() {
+            events::emit_typed(&app, ApiLogBatch { lines });
+        }
+    });
+}
+
+pub fn record(app: &AppHandle, stream: LogStream, chunk: &[u8]) {
+    let Some(state) = app.try_state::<SidecarLog>() else {
+        return;
+    };
+    let level = match stream {
+        LogStream::Stdout => ApiLogLevel::Info,
+        LogStream::Stderr => ApiLogLevel::Error,
+    };
+    // Invalid bytes become U+FFFD rather than losing the line
+    let chunk = String::from_utf8_lossy(chunk);
+    for line in split_lines(&chunk) {
+        let at = Utc::now();
+        if let Ok(mut lines) = state.lines.lock() {
+            if lines.len() == CAPACITY {
+                lines.pop_front();
+            }
+            lines.push_back(LogLine {
+                at,
+                stream,
+                line: line.to_string(),
+            });
+        }
+        persist(app, &state, at, level, line);
+        send(
+            app,
+            &state,
+            ApiLogLine {
+                level,
+                line: line.to_string(),
+                ts: at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            },
+        );
+    }
+}
+
+/// Rewrite the log file at `path` with redacted text replaced; false if there was
+/// nothing to replace or no file
+fn scrub_file(path: &Path, scrubber: &redaction::Scrubber) -> io::Result<bool> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let text = String::from_utf8_lossy(&bytes);
+    let Cow::Owned(scrubbed) = scrubber.scrub(&text) else {
+        return Ok(false);
+    };
+    let tmp = PathBuf::from(format!("{}.partial", path.display()));
+    fs::write(&tmp, scrubbed)?;
+    fs::rename(&tmp, path)?;
+    Ok(true)
+}
+
+/// Replace redacted text in the buffered lines and in `api.log` and its rotated
+/// files. Appends wait until the files are rewritten.
+pub(crate) fn scrub(app: &AppHandle, scrubber: &redaction::Scrubber) -> Result<(), String> {
+    let Some(state) = app.try_state::<SidecarLog>() else {
+        return Ok(());
+    };
+    if let Ok(mut lines) = state.lines.lock() {
+        for entry in lines.iter_mut() {
+            if let Cow::Owned(scrubbed) = scrubber.scrub(&entry.line) {
+                entry.line = scrubbed;
+            }
+        }
+    }
+    let path = log_path(app)?;
+    let mut file = state.file.lock().map_err(|e| e.to_string())?;
+    let mut rewrote = false;
+    for n in 0..LOG_FILES {
+        let log = if n == 0 { path.clone() } else { numbered(&path, n) };
+        rewrote |= scrub_file(&log, scrubber).map_err(|e| format!("{}: {}", log.display(), e))?;
+    }
+    if rewrote {
+        // The open handle still points at the replaced file; reopen on the next line
+        *file = None;
+    }
+    Ok(())
+}
+
+/// Lines that arrived between `from` and `to`, oldest first
+pub fn between(app: &AppHandle, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<LogLine> {
+    let Some(state) = app.try_state::<SidecarLog>() else {
+        return Vec::new();
+    };
+    let Ok(lines) = state.lines.lock() else {
+        return Vec::new();
+    };
+    lines
+        .iter()
+        .filter(|l| l.at >= from && l.at <= to)
+        .cloned()
+        .collect()
+}
+
+/// The last `max` stderr lines that arrived since `since`, oldest first
+pub fn stderr_since(app: &AppHandle, since: DateTime<Utc>, max: usize) -> Vec<String> {
+    let Some(state) = app.try_state::<SidecarLog>() else {
+        return Vec::new();
+    };
+    let Ok(lines) = state.lines.lock() else {
+        return Vec::new();
+    };
+    let mut tail: Vec<String> = lines
+        .iter()
+        .rev()
+        .take_while(|l| l.at >= since)
+        .filter(|l| l.stream == LogStream::Stderr)
+        .take(max)
+        .map(|l| l.line.clone())
+        .collect();
+    tail.reverse();
+    tail
+}
+
+/// Where sidecar output is written, for attaching to bug reports. The file may not
+/// exist yet if the sidecar hasn't printed anything.
+#[tauri::command]
+pub fn get_api_log_path(app: AppHandle) -> Result<String, String> {
+    log_path(&app).map(|path| path.to_string_lossy().into_owned())
+}
+
+/// The last `lines` lines written to `api.log` (at most `MAX_TAIL_LINES`), oldest
+/// first, continuing into the rotated files when `api.log` holds fewer. Empty if
+/// the sidecar hasn't printed anything yet.
+#[tauri::command]
+pub fn read_api_log_tail(app: AppHandle, lines: usize) -> Result<Vec<String>, String> {
+    let path = log_path(&app)?;
+    tail_files(&path, lines.min(MAX_TAIL_LINES)).map_err(|e| e.to_string())
+}
+
+/// Reveal `api.log` in the file manager, or open the log directory if there is
+/// no file yet
+#[tauri::command]
+pub fn open_api_log(app: AppHandle) -> Result<(), String> {
+    use tauri_plugin_opener::OpenerExt;
+    let path = log_path(&app)?;
+    if path.exists() {
+        app.opener().reveal_item_in_dir(&path).map_err(|e| e.to_string())
+    } else {
+        let dir = path.parent().ok_or("The log directory is unknown")?;
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        app.opener()
+            .open_path(dir.to_string_lossy(), None::<&str>)
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str) -> ApiLogLine {
+        ApiLogLine {
+            level: ApiLogLevel::Info,
+            line: text.to_string(),
+            ts: String::new(),
+        }
+    }
+
+    #[test]
+    fn chunks_split_into_lines() {
+        let lines: Vec<&str> = split_lines("first\r\nsecond\n\n  third  \n").collect();
+        assert_eq!(lines, vec!["first", "second", "  third"]);
+    }
+
+    #[test]
+    fn bursts_past_the_rate_are_queued_in_order() {
+        let mut throttle = Throttle::default();
+        let start = Instant::now();
+        let sent = (0..100)
+            .filter(|i| throttle.offer(start, line(&i.to_string())).is_some())
+            .count();
+        assert_eq!(sent, STREAM_RATE as usize);
+        assert_eq!(throttle.pending.len(), 40);
+
+        // A new second doesn't jump the queue while lines are still pending
+        let later = start + Duration::from_secs(1);
+        assert!(throttle.offer(later, line("late")).is_none());
+        assert_eq!(throttle.pending.last().unwrap().line, "late");
+
+        throttle.pending.clear();
+        assert!(throttle.offer(later, line("next")).is_some());
+    }
+
+    #[test]
+    fn the_log_rotates_keeping_the_newest_files() {
+        let dir = std::env::temp_dir().join(format!("workany-api-log-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join(LOG_FILE);
+        let mut log = LogFile::open(path.clone()).unwrap();
+        let entry = "x".repeat(MAX_FILE_BYTES as usize / 2 + 1);
+        for n in 0..7 {
+            log.write(&format!("{}{}\n", n, entry)).unwrap();
+        }
+
+        let first_char = |p: PathBuf| fs::read_to_string(p).unwrap().chars().next().unwrap();
+        let mut files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["api.log", "api.log.1", "api.log.2", "api.log.3", "api.log.4"]);
+        assert_eq!(first_char(path.clone()), '6');
+        assert_eq!(first_char(dir.join("api.log.1")), '5');
+        assert_eq!(first_char(dir.join("api.log.4")), '2');
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_tail_spans_rotated_files_and_survives_invalid_utf8() {
+        let dir = std::env::temp_dir().join(format!("workany-api-tail-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LOG_FILE);
+        fs::write(numbered(&path, 1), b"one\ntwo \xff\xfe\n").unwrap();
+        // Long enough to take several blocks
+        let middle: String = (0..20_000).map(|n| format!("line {}\n", n)).collect();
+        fs::write(&path, format!("{}three\nfour\n", middle)).unwrap();
+
+        let last = tail_files(&path, 2).unwrap();
+        assert_eq!(last, vec!["three", "four"]);
+        let all = tail_files(&path, 20_004).unwrap();
+        assert_eq!(all.len(), 20_004);
+        assert_eq!(all[0], "one");
+        assert_eq!(all[1], "two \u{fffd}\u{fffd}");
+        assert_eq!(all[2], "line 0");
+        assert_eq!(tail_files(&path, 0).unwrap(), Vec::<String>::new());
+        assert_eq!(tail_files(&dir.join("missing.log"), 5).unwrap(), Vec::<String>::new());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,135 @@
+// Whether this run spawns and supervises the API sidecar or expects an API that is
+// managed separately.
+//
+// Release builds manage the sidecar and debug builds expect `pnpm dev:api`, unless
+// the `sidecar_enabled` setting says otherwise; `WORKANY_SIDECAR=1` or `=0` overrides
+// both, e.g. to test spawning, crash restarts and shutdown in a debug build, or to
+// run a release build against an API started by hand. The choice is made once at
+// startup, after migrations, and logged with where it came from. An unmanaged API
+// is expected on the pinned `api_port`, or the build's default port.
+
+use crate::{db, settings};
+use std::sync::OnceLock;
+use tauri::AppHandle;
+
+pub(crate) const SIDECAR_ENV: &str = "WORKANY_SIDECAR";
+pub(crate) const SIDECAR_ENABLED_KEY: &str = "sidecar_enabled";
+
+static MODE: OnceLock<SidecarMode> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Source {
+    Env,
+    Setting,
+    Build,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SidecarMode {
+    pub managed: bool,
+    pub source: Source,
+    /// Where an unmanaged API listens; unused while the sidecar is managed
+    pub external_port: u16,
+}
+
+/// `WORKANY_SIDECAR` as a switch: `1`/`true`/`on` or `0`/`false`/`off`
+fn parse_env(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "on" | "yes" => Some(true),
+        "0" | "false" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn resolve(env: Option<&str>, setting: Option<bool>, release: bool) -> (bool, Source) {
+    if let Some(value) = env {
+        match parse_env(value) {
+            Some(managed) => return (managed, Source::Env),
+            None => eprintln!("[API] Ignoring {}={:?}; use 1 or 0", SIDECAR_ENV, value),
+        }
+    }
+    match setting {
+        Some(managed) => (managed, Source::Setting),
+        None => (release, Source::Build),
+    }
+}
+
+/// Choose the mode for this run and log it; later calls return the first choice
+pub(crate) fn decide(app: &AppHandle, default_port: u16) -> SidecarMode {
+    *MODE.get_or_init(|| {
+        let (setting, pinned) = db::open(app)
+            .map(|conn| {
+                (
+                    settings::get::<bool>(&conn, SIDECAR_ENABLED_KEY).ok().flatten(),
+                    settings::get::<u16>(&conn, crate::sidecar::PINNED_API_PORT_KEY).ok().flatten(),
+                )
+            })
+            .unwrap_or((None, None));
+        let env = std::env::var(SIDECAR_ENV).ok();
+        let (managed, source) = resolve(env.as_deref(), setting, !cfg!(debug_assertions));
+        let mode = SidecarMode {
+            managed,
+            source,
+            external_port: pinned.unwrap_or(default_port),
+        };
+        let from = match source {
+            Source::Env => format!("{}={}", SIDECAR_ENV, env.unwrap_or_default()),
+            Source::Setting => format!("the {} setting", SIDECAR_ENABLED_KEY),
+            Source::Build => format!("the {} build default", if cfg!(debug_assertions) { "debug" } else { "release" }),
+        };
+        if managed {
+            println!("[API] Managing the API sidecar ({})", from);
+        } else {
+            println!(
+                "[API] Not managing the API sidecar ({}); expecting a separately run API on port {}",
+                from, mode.external_port
+            );
+        }
+        mode
+    })
+}
+
+/// The mode chosen for this run, `None` before `decide`
+pub(crate) fn current() -> Option<SidecarMode> {
+    MODE.get().copied()
+}
+
+/// Whether this run spawns the sidecar; false until `decide` chose to
+pub(crate) fn managed() -> bool {
+    current().is_some_and(|mode| mode.managed)
+}
+
+/// The error sidecar commands return while the API is managed elsewhere
+pub(crate) fn unmanaged_error() -> String {
+    let port = current().map_or(0, |mode| mode.external_port);
+    format!(
+        "The API sidecar is not managed by this run; the API is expected on port {} (set {}=1 to manage it)",
+        port, SIDECAR_ENV
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_env_var_overrides_the_setting_and_the_build() {
+        assert_eq!(resolve(Some("1"), Some(false), false), (true, Source::Env));
+        assert_eq!(resolve(Some("0"), Some(true), true), (false, Source::Env));
+        assert_eq!(resolve(Some(" Off "), None, true), (false, Source::Env));
+    }
+
+    #[test]
+    fn the_setting_overrides_the_build_default() {
+        assert_eq!(resolve(None, Some(true), false), (true, Source::Setting));
+        assert_eq!(resolve(None, Some(false), true), (false, Source::Setting));
+        assert_eq!(resolve(None, None, true), (true, Source::Build));
+        assert_eq!(resolve(None, None, false), (false, Source::Build));
+    }
+
+    #[test]
+    fn an_unrecognised_env_value_is_ignored() {
+        assert_eq!(resolve(Some("maybe"), Some(true), false), (true, Source::Setting));
+        assert_eq!(resolve(Some(""), None, false), (false, Source::Build));
+    }
+}
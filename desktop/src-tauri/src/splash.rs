@@ -0,0 +1,235 @@
+// Startup splash: a small frameless window shown while the app starts, so a slow
+// startup doesn't look like a blank, hung window.
+//
+// `setup` opens it before any heavy work: the page is inline HTML with the app icon
+// and a status line, and needs neither the frontend nor the database. Backup
+// restore and migrations then run on a boot thread, and each `Phase` rewrites the
+// status line and is logged with the time since `begin`. The main window is created
+// once the database is migrated and stays hidden until the frontend sends
+// `app://ready`, the API is found unreachable (the main window shows its own error
+// screen), or `READY_FALLBACK` passes; then the splash closes. A startup step that
+// fails, including a panic, leaves the splash open with the error and a title bar
+// to close it, and closing it before startup finished quits the app.
+
+use crate::safe_mode::READY_EVENT;
+use crate::window;
+use base64::Engine;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Listener, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, WindowEvent};
+
+pub const SPLASH_WINDOW: &str = "splash";
+/// How long the main window may take to send `app://ready` before it is shown anyway
+const READY_FALLBACK: Duration = Duration::from_secs(20);
+const ICON: &[u8] = include_bytes!("../icons/128x128.png");
+
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static APP: OnceLock<AppHandle> = OnceLock::new();
+/// Set once the main window took over or the app is exiting
+static DONE: AtomicBool = AtomicBool::new(false);
+/// What the splash shows, re-applied when its page finishes loading
+static STATUS: Mutex<Status> = Mutex::new(Status {
+    message: String::new(),
+    failed: false,
+});
+
+struct Status {
+    message: String,
+    failed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Phase {
+    RestoringBackup,
+    Migrating,
+    OpeningWindow,
+    StartingServices,
+    StartingApi,
+    WaitingForApi,
+    LoadingInterface,
+}
+
+impl Phase {
+    fn message(self) -> &'static str {
+        match self {
+            Phase::RestoringBackup => "Restoring backup…",
+            Phase::Migrating => "Updating the database…",
+            Phase::OpeningWindow => "Opening the window…",
+            Phase::StartingServices => "Starting services…",
+            Phase::StartingApi => "Starting the API server…",
+            Phase::WaitingForApi => "Waiting for the API server…",
+            Phase::LoadingInterface => "Loading the interface…",
+        }
+    }
+}
+
+fn elapsed_ms() -> u128 {
+    STARTED.get().map_or(0, |started| started.elapsed().as_millis())
+}
+
+/// Start the startup clock; call first thing in `run`
+pub(crate) fn begin() {
+    STARTED.get_or_init(Instant::now);
+}
+
+/// The splash page as a data URL, with the icon inlined
+fn page_url() -> String {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let html = format!(
+        r#"<!doctype html><html><head><meta charset="utf-8"><style>
+html,body{{margin:0;height:100%;overflow:hidden;background:#1c1c1e;color:#e5e5e7;font:13px -apple-system,BlinkMacSystemFont,"Segoe UI",Roboto,sans-serif;cursor:default;user-select:none}}
+body{{display:flex;flex-direction:column;align-items:center;justify-content:center;gap:18px}}
+img{{width:72px;height:72px}}
+#status{{padding:0 24px;text-align:center;opacity:.7}}
+body.failed #status{{opacity:1;color:#ff8a80;user-select:text}}
+</style></head><body><img src="data:image/png;base64,{icon}" alt=""><div id="status">Starting…</div>
+<script>window.__splash=function(m,f){{document.getElementById('status').textContent=m;document.body.className=f?'failed':'';}};</script>
+</body></html>"#,
+        icon = engine.encode(ICON)
+    );
+    format!("data:text/html;base64,{}", engine.encode(html))
+}
+
+fn splash_window(app: &AppHandle) -> Option<WebviewWindow> {
+    app.get_webview_window(SPLASH_WINDOW)
+}
+
+/// Push the current status into the page
+fn render(window: &WebviewWindow) {
+    let (message, failed) = {
+        let status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+        (status.message.clone(), status.failed)
+    };
+    if message.is_empty() {
+        return;
+    }
+    let message = serde_json::to_string(&message).unwrap_or_default();
+    let _ = window.eval(format!("window.__splash&&window.__splash({},{})", message, failed));
+}
+
+fn set_status(app: &AppHandle, message: String, failed: bool) {
+    {
+        let mut status = STATUS.lock().unwrap_or_else(|e| e.into_inner());
+        if status.failed {
+            // The first error stays on screen
+            return;
+        }
+        *status = Status { message, failed };
+    }
+    if let Some(window) = splash_window(app) {
+        render(&window);
+    }
+}
+
+/// Show the splash; the main window is shown by `finish`
+pub(crate) fn open(app: &AppHandle) {
+    let _ = APP.set(app.clone());
+    let url = match page_url().parse() {
+        Ok(url) => WebviewUrl::External(url),
+        Err(e) => {
+            eprintln!("[Startup] Cannot build the splash page: {}", e);
+            return;
+        }
+    };
+    let built = WebviewWindowBuilder::new(app, SPLASH_WINDOW, url)
+        .title("CloudWork")
+        .inner_size(360.0, 240.0)
+        .resizable(false)
+        .decorations(false)
+        .center()
+        .focused(true)
+        .on_page_load(|window, _| render(&window))
+        .build();
+    match built {
+        Ok(splash) => {
+            let handle = app.clone();
+            splash.on_window_event(move |event| {
+                // Closed by the user before the main window took over: nothing else
+                // is visible, so don't leave the app running
+                if matches!(event, WindowEvent::Destroyed) && !DONE.load(Ordering::SeqCst) {
+                    println!("[Startup] Splash closed before startup finished; quitting");
+                    handle.exit(1);
+                }
+            });
+            println!("[Startup] Splash shown after {}ms", elapsed_ms());
+        }
+        Err(e) => eprintln!("[Startup] Cannot open the splash window: {}", e),
+    }
+    let handle = app.clone();
+    app.listen_any(READY_EVENT, move |_| finish(&handle));
+}
+
+/// Report that startup reached `phase`
+pub(crate) fn phase(app: &AppHandle, phase: Phase) {
+    if DONE.load(Ordering::SeqCst) {
+        return;
+    }
+    println!("[Startup] {:?} after {}ms", phase, elapsed_ms());
+    set_status(app, phase.message().to_string(), false);
+}
+
+/// Startup cannot continue: keep the splash open with `message` and a title bar,
+/// so the error can be read and the window closed
+pub(crate) fn fail(app: &AppHandle, message: &str) {
+    eprintln!("[Startup] Failed after {}ms: {}", elapsed_ms(), message);
+    if DONE.load(Ordering::SeqCst) {
+        return;
+    }
+    set_status(app, message.to_string(), true);
+    if let Some(window) = splash_window(app) {
+        let _ = window.set_title("CloudWork could not start");
+        let _ = window.set_decorations(true);
+        let _ = window.set_focus();
+    }
+}
+
+/// Show the main window after `READY_FALLBACK` if nothing else did
+pub(crate) fn start_fallback(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(READY_FALLBACK);
+        if !DONE.load(Ordering::SeqCst) && !failed() {
+            eprintln!("[Startup] No {} after {}s; showing the main window", READY_EVENT, READY_FALLBACK.as_secs());
+            finish(&app);
+        }
+    });
+}
+
+fn failed() -> bool {
+    STATUS.lock().map(|status| status.failed).unwrap_or(true)
+}
+
+/// Close the splash and show the main window, which starts hidden so it doesn't
+/// appear blank. Only the first call does anything, and none after `fail`: the
+/// error stays on the splash.
+pub(crate) fn finish(app: &AppHandle) {
+    if failed() || DONE.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    println!("[Startup] Finished after {}ms", elapsed_ms());
+    if let Some(main) = app.get_webview_window(window::MAIN_WINDOW) {
+        let _ = main.show();
+        let _ = main.set_focus();
+    }
+    if let Some(splash) = splash_window(app) {
+        let _ = splash.destroy();
+    }
+}
+
+/// Close the splash on exit, whatever state startup is in
+pub(crate) fn close(app: &AppHandle) {
+    DONE.store(true, Ordering::SeqCst);
+    if let Some(splash) = splash_window(app) {
+        let _ = splash.destroy();
+    }
+}
+
+/// From the panic hook: a panic before startup finished is shown on the splash
+pub(crate) fn on_panic(message: &str) {
+    if let Some(app) = APP.get() {
+        if !DONE.load(Ordering::SeqCst) {
+            fail(app, &format!("CloudWork hit an internal error while starting: {}", message));
+        }
+    }
+}
@@ -0,0 +1,192 @@
+// Watchdog for running tasks that stop producing messages.
+//
+// Activity comes from the `message_changes` feed the message triggers fill, read
+// incrementally from the last sequence number seen; only the (small) set of running
+// task ids is queried each tick. A task with no new messages for `stall_timeout_ms`
+// gets one `task-stalled` event. Nothing is failed automatically: a long tool call
+// looks the same as a hang, so the user decides whether to wait or cancel.
+
+use crate::{background, db, events, settings};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const TIMEOUT_KEY: &str = "stall_timeout_ms";
+const DEFAULT_TIMEOUT_MS: u64 = 5 * 60 * 1000;
+const TICK: Duration = Duration::from_secs(5);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "stall",
+    interval: TICK,
+    coalescable: true,
+};
+
+#[derive(Default)]
+struct Tracker {
+    last_seq: Option<i64>,
+    last_activity: HashMap<String, Instant>,
+    reported: HashSet<String>,
+    /// Not timed, e.g. while the working directory's volume is unreachable
+    paused: HashSet<String>,
+}
+
+#[derive(Default)]
+pub struct StallWatchdog(Mutex<Tracker>);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StalledTask {
+    pub task_id: String,
+    pub idle_ms: u64,
+    pub timeout_ms: u64,
+}
+
+/// Configured timeout; `None` when the watchdog is disabled (set to 0)
+fn timeout_ms(conn: &Connection) -> Result<Option<u64>, String> {
+    let ms = settings::get_or(conn, TIMEOUT_KEY, DEFAULT_TIMEOUT_MS)?;
+    Ok((ms > 0).then_some(ms))
+}
+
+fn running_tasks(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare_cached("SELECT id FROM tasks WHERE status = 'running'")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], |row| row.get(0)).map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Fold new feed entries into the tracker and return the tasks now past the timeout
+fn scan(conn: &Connection, tracker: &mut Tracker, timeout: Duration) -> Result<Vec<StalledTask>, String> {
+    let now = Instant::now();
+    match tracker.last_seq {
+        None => {
+            tracker.last_seq = Some(
+                conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM message_changes", [], |row| row.get(0))
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+        Some(after) => {
+            let mut stmt = conn
+                .prepare_cached("SELECT seq, task_id FROM message_changes WHERE seq > ?1 ORDER BY seq")
+                .map_err(|e| e.to_string())?;
+            let mut rows = stmt.query([after]).map_err(|e| e.to_string())?;
+            while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+                let seq: i64 = row.get(0).map_err(|e| e.to_string())?;
+                let task_id: String = row.get(1).map_err(|e| e.to_string())?;
+                tracker.reported.remove(&task_id);
+                tracker.last_activity.insert(task_id, now);
+                tracker.last_seq = Some(seq);
+            }
+        }
+    }
+
+    let running: HashSet<String> = running_tasks(conn)?.into_iter().collect();
+    tracker.last_activity.retain(|id, _| running.contains(id));
+    tracker.reported.retain(|id| running.contains(id));
+    tracker.paused.retain(|id| running.contains(id));
+
+    let mut stalled = Vec::new();
+    for task_id in running {
+        if tracker.paused.contains(&task_id) {
+            continue;
+        }
+        // A task first seen here gets a full timeout from now
+        let last = *tracker.last_activity.entry(task_id.clone()).or_insert(now);
+        let idle = now.duration_since(last);
+        if idle >= timeout {
+            stalled.push(StalledTask {
+                task_id,
+                idle_ms: idle.as_millis() as u64,
+                timeout_ms: timeout.as_millis() as u64,
+            });
+        }
+    }
+    Ok(stalled)
+}
+
+fn tick(app: &AppHandle) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let Some(timeout) = timeout_ms(&conn)? else {
+        return Ok(());
+    };
+    let state = app.state::<StallWatchdog>();
+    let mut tracker = state.0.lock().map_err(|e| e.to_string())?;
+    for task in scan(&conn, &mut tracker, Duration::from_millis(timeout))? {
+        if tracker.reported.insert(task.task_id.clone()) {
+            eprintln!("[Stall] Task {} has produced no messages for {}ms", task.task_id, task.idle_ms);
+            events::emit_typed(
+                app,
+                events::TaskStalled {
+                    task_id: task.task_id,
+                    idle_ms: task.idle_ms,
+                    timeout_ms: task.timeout_ms,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Give every running task a full timeout again after the machine wakes
+pub fn note_resume(app: &AppHandle) {
+    if let Ok(mut tracker) = app.state::<StallWatchdog>().0.lock() {
+        let now = Instant::now();
+        for last in tracker.last_activity.values_mut() {
+            *last = now;
+        }
+    }
+}
+
+/// Stop timing `task_id`'s silence until `resume`
+pub fn pause(app: &AppHandle, task_id: &str) {
+    if let Ok(mut tracker) = app.state::<StallWatchdog>().0.lock() {
+        tracker.paused.insert(task_id.to_string());
+    }
+}
+
+/// Time `task_id` again, with a full timeout from now
+pub fn resume(app: &AppHandle, task_id: &str) {
+    if let Ok(mut tracker) = app.state::<StallWatchdog>().0.lock() {
+        if tracker.paused.remove(task_id) {
+            tracker.last_activity.insert(task_id.to_string(), Instant::now());
+        }
+    }
+}
+
+/// Start the watchdog thread
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &ACTIVITY);
+        if let Err(e) = tick(&app) {
+            eprintln!("[Stall] Watchdog tick failed: {}", e);
+        }
+    });
+}
+
+/// Running tasks that have been silent for longer than the stall timeout
+#[tauri::command]
+pub async fn detect_stalled_tasks(app: AppHandle) -> Result<Vec<StalledTask>, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let Some(timeout) = timeout_ms(conn)? else {
+            return Ok(Vec::new());
+        };
+        let state = handle.state::<StallWatchdog>();
+        let mut tracker = state.0.lock().map_err(|e| e.to_string())?;
+        scan(conn, &mut tracker, Duration::from_millis(timeout))
+    })
+    .await
+}
+
+/// Set the stall timeout in milliseconds; 0 disables the watchdog, `None` restores
+/// the default
+#[tauri::command]
+pub async fn set_stall_timeout(app: AppHandle, timeout_ms: Option<u64>) -> Result<(), String> {
+    db::with_conn(&app, move |conn| match timeout_ms {
+        Some(ms) => settings::set(conn, TIMEOUT_KEY, &ms),
+        None => settings::remove(conn, TIMEOUT_KEY),
+    })
+    .await
+}
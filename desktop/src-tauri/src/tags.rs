@@ -0,0 +1,276 @@
+// Task tags (`tags` and `task_tags`, migration 15).
+//
+// Names are stored trimmed and lowercased, so "Work" and " work" are one tag; the
+// NOCASE unique index keeps rows written before that rule from splitting either.
+// Trashed tasks keep their tags but are left out of counts and lookups.
+
+use crate::db;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+const MAX_SUGGESTIONS: u32 = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagSuggestion {
+    pub name: String,
+    pub color: Option<String>,
+    /// Number of tasks carrying the tag
+    pub count: i64,
+    /// The name starts with the typed prefix rather than just containing it
+    pub prefix_match: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TagCount {
+    pub name: String,
+    pub color: Option<String>,
+    /// Number of tasks outside the trash carrying the tag
+    pub count: i64,
+}
+
+/// The stored form of a tag name: trimmed and lowercased
+pub(crate) fn normalize(name: &str) -> Result<String, String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err("Tag names can't be empty".to_string());
+    }
+    Ok(name)
+}
+
+/// Escape LIKE wildcards so user input matches literally (used with ESCAPE '\')
+pub(crate) fn escape_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Tags matching `prefix`: names starting with it first, then names containing it,
+/// each group ordered by how many tasks outside the trash use the tag
+pub(crate) fn suggestions(conn: &Connection, prefix: &str, limit: u32) -> Result<Vec<TagSuggestion>, String> {
+    let needle = escape_like(prefix.trim());
+    let mut stmt = conn
+        .prepare(
+            r"SELECT tg.name, tg.color, COUNT(t.id) AS usage, tg.name LIKE ?1 || '%' ESCAPE '\' AS prefix_match
+              FROM tags tg
+              LEFT JOIN task_tags tt ON tt.tag_id = tg.id
+              LEFT JOIN tasks t ON t.id = tt.task_id AND t.trashed_at IS NULL
+              WHERE tg.name LIKE '%' || ?1 || '%' ESCAPE '\'
+              GROUP BY tg.id
+              ORDER BY prefix_match DESC, usage DESC, tg.name COLLATE NOCASE
+              LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![needle, limit], |row| {
+            Ok(TagSuggestion {
+                name: row.get(0)?,
+                color: row.get(1)?,
+                count: row.get(2)?,
+                prefix_match: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Tags matching what the user has typed: names starting with `prefix` first, then
+/// names containing it, each group ordered by how many tasks use the tag. An empty
+/// prefix returns the most used tags.
+#[tauri::command]
+pub async fn tag_autocomplete(app: tauri::AppHandle, prefix: String, limit: u32) -> Result<Vec<TagSuggestion>, String> {
+    let limit = limit.clamp(1, MAX_SUGGESTIONS);
+    db::with_conn(&app, move |conn| suggestions(conn, &prefix, limit)).await
+}
+
+/// Attach `tag` to a task, creating the tag on first use. A differently cased older
+/// row is renamed to the normalized form; attaching a tag twice is a no-op.
+pub(crate) fn add(conn: &Connection, task_id: &str, tag: &str) -> Result<(), String> {
+    let name = normalize(tag)?;
+    let exists = conn
+        .query_row("SELECT 1 FROM tasks WHERE id = ?1", [task_id], |_| Ok(()))
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if exists.is_none() {
+        return Err(format!("Task '{}' not found", task_id));
+    }
+    conn.execute(
+        "INSERT INTO tags (name) VALUES (?1) ON CONFLICT(name) DO UPDATE SET name = excluded.name",
+        [&name],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR IGNORE INTO task_tags (task_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+        params![task_id, name],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Detach `tag` from a task; the tag itself stays, with its color, for reuse
+pub(crate) fn remove(conn: &Connection, task_id: &str, tag: &str) -> Result<(), String> {
+    let name = normalize(tag)?;
+    conn.execute(
+        "DELETE FROM task_tags WHERE task_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+        params![task_id, name],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub(crate) fn counts(conn: &Connection) -> Result<Vec<TagCount>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT tg.name, tg.color, COUNT(t.id) AS usage
+             FROM tags tg
+             LEFT JOIN task_tags tt ON tt.tag_id = tg.id
+             LEFT JOIN tasks t ON t.id = tt.task_id AND t.trashed_at IS NULL
+             GROUP BY tg.id
+             ORDER BY usage DESC, tg.name COLLATE NOCASE",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(TagCount {
+                name: row.get(0)?,
+                color: row.get(1)?,
+                count: row.get(2)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Ids of the tasks outside the trash carrying `tag`, newest first
+pub(crate) fn tasks_with(conn: &Connection, tag: &str) -> Result<Vec<String>, String> {
+    let name = normalize(tag)?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT t.id FROM tasks t
+             JOIN task_tags tt ON tt.task_id = t.id
+             JOIN tags tg ON tg.id = tt.tag_id
+             WHERE tg.name = ?1 AND t.trashed_at IS NULL
+             ORDER BY t.created_at DESC, t.id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([name], |row| row.get(0)).map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn add_tag(app: tauri::AppHandle, task_id: String, tag: String) -> Result<(), String> {
+    db::with_conn(&app, move |conn| add(conn, &task_id, &tag)).await
+}
+
+#[tauri::command]
+pub async fn remove_tag(app: tauri::AppHandle, task_id: String, tag: String) -> Result<(), String> {
+    db::with_conn(&app, move |conn| remove(conn, &task_id, &tag)).await
+}
+
+/// Every tag with the number of tasks using it, most used first
+#[tauri::command]
+pub async fn list_tags(app: tauri::AppHandle) -> Result<Vec<TagCount>, String> {
+    db::with_conn(&app, move |conn| counts(conn)).await
+}
+
+#[tauri::command]
+pub async fn tasks_by_tag(app: tauri::AppHandle, tag: String) -> Result<Vec<String>, String> {
+    db::with_conn(&app, move |conn| tasks_with(conn, &tag)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             INSERT INTO tasks (id, prompt, created_at) VALUES
+                 ('a', 'p', '2026-01-01'), ('b', 'p', '2026-01-02'), ('c', 'p', '2026-01-03');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn names_are_normalized_and_collapse() {
+        let conn = db();
+        add(&conn, "a", "Work").unwrap();
+        add(&conn, "b", "  work ").unwrap();
+        add(&conn, "b", "WORK").unwrap();
+        assert_eq!(
+            counts(&conn).unwrap(),
+            vec![TagCount { name: "work".into(), color: None, count: 2 }]
+        );
+        assert!(add(&conn, "a", "   ").is_err());
+        assert!(add(&conn, "missing", "work").is_err());
+    }
+
+    #[test]
+    fn older_mixed_case_rows_are_reused_and_renamed() {
+        let conn = db();
+        conn.execute("INSERT INTO tags (name, color) VALUES ('Urgent', 'red')", []).unwrap();
+        add(&conn, "a", "urgent").unwrap();
+        assert_eq!(
+            counts(&conn).unwrap(),
+            vec![TagCount { name: "urgent".into(), color: Some("red".into()), count: 1 }]
+        );
+    }
+
+    #[test]
+    fn lookups_skip_trashed_tasks_and_removed_tags() {
+        let conn = db();
+        for task in ["a", "b", "c"] {
+            add(&conn, task, "report").unwrap();
+        }
+        add(&conn, "a", "draft").unwrap();
+        assert_eq!(tasks_with(&conn, "Report").unwrap(), vec!["c", "b", "a"]);
+
+        conn.execute("UPDATE tasks SET trashed_at = datetime('now') WHERE id = 'c'", []).unwrap();
+        remove(&conn, "a", "REPORT").unwrap();
+        assert_eq!(tasks_with(&conn, "report").unwrap(), vec!["b"]);
+        remove(&conn, "a", "draft").unwrap();
+        let tags = counts(&conn).unwrap();
+        assert_eq!(tags.iter().map(|t| (t.name.as_str(), t.count)).collect::<Vec<_>>(), [("report", 1), ("draft", 0)]);
+    }
+
+    #[test]
+    fn suggestions_leave_out_trashed_tasks() {
+        let conn = db();
+        for task in ["a", "b", "c"] {
+            add(&conn, task, "report").unwrap();
+        }
+        add(&conn, "a", "reporting").unwrap();
+        add(&conn, "b", "quarterly report").unwrap();
+        let counted = |prefix: &str| -> Vec<(String, i64, bool)> {
+            suggestions(&conn, prefix, 10)
+                .unwrap()
+                .into_iter()
+                .map(|s| (s.name, s.count, s.prefix_match))
+                .collect()
+        };
+        assert_eq!(
+            counted("rep"),
+            [
+                ("report".to_string(), 3, true),
+                ("reporting".to_string(), 1, true),
+                ("quarterly report".to_string(), 1, false)
+            ]
+        );
+
+        conn.execute("UPDATE tasks SET trashed_at = datetime('now') WHERE id IN ('a', 'c')", []).unwrap();
+        assert_eq!(
+            counted("rep"),
+            [
+                ("report".to_string(), 1, true),
+                ("reporting".to_string(), 0, true),
+                ("quarterly report".to_string(), 1, false)
+            ]
+        );
+    }
+}
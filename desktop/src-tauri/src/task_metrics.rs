@@ -0,0 +1,413 @@
+// Task cost and duration as the sidecar reports and revises them.
+//
+// The final cost often arrives seconds after the result message, and the sidecar
+// may revise it after reconciling with the API, up or down. `report_task_metrics`
+// records every report in `task_metric_revisions` and applies the latest to the task
+// row; parts a report leaves out keep their current value. A report that changes
+// nothing is still recorded but leaves the row alone, so a duplicate is harmless.
+//
+// Consumers never add reports up. The cost triggers from migration 19 turn each
+// change of `tasks.cost` into one ledger delta, negative for a downward revision,
+// and the quota sums its period from the ledger again on the check that follows
+// every applied report. The timeline totals read `tasks.cost` directly.
+//
+// A task's metrics become final when a report says so, or once it has stopped
+// running and nothing was reported for `FINAL_AFTER`. Later revisions are still
+// applied. Each change emits `task://metrics-updated`.
+
+use crate::{background, db, events, quota};
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::time::Duration;
+use tauri::AppHandle;
+
+/// Quiet time after a task stops before its metrics count as final
+const FINAL_AFTER: Duration = Duration::from_secs(120);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "task_metrics",
+    interval: Duration::from_secs(30),
+    coalescable: true,
+};
+const MAX_REASON_CHARS: usize = 200;
+/// Recorded for revisions made by the finalization timeout
+const TIMEOUT_REASON: &str = "timeout";
+
+/// One report as received
+#[derive(Debug, Clone, Default)]
+struct MetricReport {
+    cost: Option<f64>,
+    duration: Option<i64>,
+    reason: Option<String>,
+    is_final: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskMetrics {
+    pub task_id: String,
+    pub cost: Option<f64>,
+    pub duration: Option<i64>,
+    pub metrics_final: bool,
+    /// The report changed the task row
+    pub changed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricRevision {
+    pub id: i64,
+    /// `None` where the report left the cost out
+    pub cost: Option<f64>,
+    pub duration: Option<i64>,
+    pub reason: Option<String>,
+    pub is_final: bool,
+    /// Unix ms
+    pub reported_at: i64,
+    /// Change from the cost in effect before this report, when both are known
+    pub cost_delta: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricHistory {
+    pub task_id: String,
+    pub cost: Option<f64>,
+    pub duration: Option<i64>,
+    pub metrics_final: bool,
+    /// Oldest first
+    pub revisions: Vec<MetricRevision>,
+}
+
+fn validate(report: &MetricReport) -> Result<(), String> {
+    if let Some(cost) = report.cost {
+        if !cost.is_finite() || cost < 0.0 {
+            return Err(format!("Invalid cost {}", cost));
+        }
+    }
+    if let Some(duration) = report.duration.filter(|d| *d < 0) {
+        return Err(format!("Invalid duration {}", duration));
+    }
+    if report.cost.is_none() && report.duration.is_none() && !report.is_final {
+        return Err("A metrics report needs a cost, a duration or the final flag".to_string());
+    }
+    if report.reason.as_deref().is_some_and(|r| r.chars().count() > MAX_REASON_CHARS) {
+        return Err(format!("Revision reasons are limited to {} characters", MAX_REASON_CHARS));
+    }
+    Ok(())
+}
+
+/// Record `report` and apply it to the task row
+fn apply(conn: &mut Connection, task_id: &str, report: &MetricReport, now_ms: i64) -> Result<TaskMetrics, String> {
+    validate(report)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let (cost, duration, metrics_final): (Option<f64>, Option<i64>, bool) = tx
+        .query_row(
+            "SELECT cost, duration, metrics_final FROM tasks WHERE id = ?1",
+            [task_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Task {} not found", task_id))?;
+    tx.execute(
+        "INSERT INTO task_metric_revisions (task_id, cost, duration, reason, is_final, reported_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![task_id, report.cost, report.duration, report.reason, report.is_final, now_ms],
+    )
+    .map_err(|e| e.to_string())?;
+
+    let metrics = TaskMetrics {
+        task_id: task_id.to_string(),
+        cost: report.cost.or(cost),
+        duration: report.duration.or(duration),
+        metrics_final: metrics_final || report.is_final,
+        changed: false,
+    };
+    let changed = metrics.cost != cost || metrics.duration != duration || metrics.metrics_final != metrics_final;
+    if changed {
+        // The ledger triggers see only the change of `cost`
+        tx.execute(
+            "UPDATE tasks SET cost = ?1, duration = ?2, metrics_final = ?3, updated_at = datetime('now')
+             WHERE id = ?4",
+            params![metrics.cost, metrics.duration, metrics.metrics_final, task_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(TaskMetrics { changed, ..metrics })
+}
+
+/// Mark final the metrics of stopped tasks that had no report for `FINAL_AFTER`
+fn finalize_stale(conn: &mut Connection, now_ms: i64) -> Result<Vec<TaskMetrics>, String> {
+    let cutoff = now_ms - FINAL_AFTER.as_millis() as i64;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let due: Vec<(String, Option<f64>, Option<i64>)> = {
+        let mut stmt = tx
+            .prepare(
+                "SELECT id, cost, duration FROM tasks
+                 WHERE metrics_final = 0 AND status != 'running'
+                   AND COALESCE((SELECT MAX(reported_at) FROM task_metric_revisions r WHERE r.task_id = tasks.id),
+                                CAST(strftime('%s', updated_at) AS INTEGER) * 1000) < ?1",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([cutoff], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+        rows
+    };
+    for (id, _, _) in &due {
+        tx.execute(
+            "INSERT INTO task_metric_revisions (task_id, reason, is_final, reported_at) VALUES (?1, ?2, 1, ?3)",
+            params![id, TIMEOUT_REASON, now_ms],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute("UPDATE tasks SET metrics_final = 1 WHERE id = ?1", [id])
+            .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(due
+        .into_iter()
+        .map(|(task_id, cost, duration)| TaskMetrics {
+            task_id,
+            cost,
+            duration,
+            metrics_final: true,
+            changed: true,
+        })
+        .collect())
+}
+
+fn history(conn: &Connection, task_id: &str) -> Result<MetricHistory, String> {
+    let (cost, duration, metrics_final) = conn
+        .query_row(
+            "SELECT cost, duration, metrics_final FROM tasks WHERE id = ?1",
+            [task_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Task {} not found", task_id))?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, cost, duration, reason, is_final, reported_at FROM task_metric_revisions
+             WHERE task_id = ?1 ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([task_id], |row| {
+            Ok(MetricRevision {
+                id: row.get(0)?,
+                cost: row.get(1)?,
+                duration: row.get(2)?,
+                reason: row.get(3)?,
+                is_final: row.get(4)?,
+                reported_at: row.get(5)?,
+                cost_delta: None,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    let mut revisions = Vec::new();
+    let mut in_effect: Option<f64> = None;
+    for row in rows {
+        let mut revision = row.map_err(|e| e.to_string())?;
+        if let Some(cost) = revision.cost {
+            revision.cost_delta = in_effect.map(|previous| cost - previous);
+            in_effect = Some(cost);
+        }
+        revisions.push(revision);
+    }
+    Ok(MetricHistory {
+        task_id: task_id.to_string(),
+        cost,
+        duration,
+        metrics_final,
+        revisions,
+    })
+}
+
+fn notify(app: &AppHandle, metrics: &TaskMetrics, reason: Option<String>) {
+    events::emit_typed(
+        app,
+        events::TaskMetricsUpdated {
+            task_id: metrics.task_id.clone(),
+            cost: metrics.cost,
+            duration: metrics.duration,
+            metrics_final: metrics.metrics_final,
+            reason,
+        },
+    );
+}
+
+/// Start finalizing the metrics of tasks that stopped receiving reports
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &ACTIVITY);
+        let result = db::open(&app).and_then(|mut conn| finalize_stale(&mut conn, Utc::now().timestamp_millis()));
+        match result {
+            Ok(finalized) => {
+                for metrics in &finalized {
+                    notify(&app, metrics, Some(TIMEOUT_REASON.to_string()));
+                }
+            }
+            Err(e) => eprintln!("[TaskMetrics] Finalizing failed: {}", e),
+        }
+    });
+}
+
+/// Record a cost and duration report for a task and apply it. Reports may arrive
+/// late, repeat, or revise earlier ones; `is_final` marks the last one expected.
+#[tauri::command]
+pub async fn report_task_metrics(
+    app: AppHandle,
+    task_id: String,
+    cost: Option<f64>,
+    duration: Option<i64>,
+    revision_reason: Option<String>,
+    is_final: Option<bool>,
+) -> Result<TaskMetrics, String> {
+    let report = MetricReport {
+        cost,
+        duration,
+        reason: revision_reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty()),
+        is_final: is_final.unwrap_or(false),
+    };
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let metrics = apply(conn, &task_id, &report, Utc::now().timestamp_millis())?;
+        if metrics.changed {
+            if let Err(e) = quota::check(&handle, conn) {
+                eprintln!("[TaskMetrics] Quota check after a revision failed: {}", e);
+            }
+            notify(&handle, &metrics, report.reason.clone());
+        }
+        Ok(metrics)
+    })
+    .await
+}
+
+/// Every metrics report for a task, so the detail view can show why a number
+/// changed
+#[tauri::command]
+pub async fn get_metric_history(app: AppHandle, task_id: String) -> Result<MetricHistory, String> {
+    db::with_conn(&app, move |conn| history(conn, &task_id)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_760_000_000_000;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch(
+            "INSERT INTO tasks (id, prompt, status) VALUES ('t', 'Write the report', 'completed');
+             INSERT INTO tasks (id, prompt, status) VALUES ('r', 'Still going', 'running');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn cost(value: f64, reason: &str) -> MetricReport {
+        MetricReport {
+            cost: Some(value),
+            reason: Some(reason.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// What the quota and the timeline would count for the task
+    fn accounted(conn: &Connection) -> (f64, f64) {
+        let ledger = conn
+            .query_row("SELECT TOTAL(delta) FROM cost_ledger WHERE task_id = 't'", [], |row| row.get(0))
+            .unwrap();
+        let row = conn
+            .query_row("SELECT TOTAL(cost) FROM tasks WHERE id = 't'", [], |row| row.get(0))
+            .unwrap();
+        (ledger, row)
+    }
+
+    fn assert_close(actual: (f64, f64), expected: f64) {
+        assert!((actual.0 - expected).abs() < 1e-9, "ledger {} != {}", actual.0, expected);
+        assert!((actual.1 - expected).abs() < 1e-9, "row {} != {}", actual.1, expected);
+    }
+
+    #[test]
+    fn late_revisions_replace_the_cost_without_double_counting() {
+        let mut conn = db();
+        let report = MetricReport {
+            duration: Some(42_000),
+            ..cost(1.0, "result")
+        };
+        assert!(apply(&mut conn, "t", &report, NOW).unwrap().changed);
+        let metrics = apply(&mut conn, "t", &cost(1.4, "reconciled"), NOW + 5_000).unwrap();
+        assert_eq!((metrics.cost, metrics.duration), (Some(1.4), Some(42_000)));
+        assert_close(accounted(&conn), 1.4);
+
+        let history = history(&conn, "t").unwrap();
+        assert_eq!(history.revisions.len(), 2);
+        assert_eq!(history.revisions[0].cost_delta, None);
+        assert!((history.revisions[1].cost_delta.unwrap() - 0.4).abs() < 1e-9);
+        assert_eq!(history.revisions[1].reason.as_deref(), Some("reconciled"));
+    }
+
+    #[test]
+    fn duplicate_reports_are_recorded_but_change_nothing() {
+        let mut conn = db();
+        apply(&mut conn, "t", &cost(1.0, "result"), NOW).unwrap();
+        assert!(!apply(&mut conn, "t", &cost(1.0, "result"), NOW + 1).unwrap().changed);
+        assert_close(accounted(&conn), 1.0);
+        let deltas: i64 = conn
+            .query_row("SELECT COUNT(*) FROM cost_ledger WHERE task_id = 't'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(deltas, 1);
+        assert_eq!(history(&conn, "t").unwrap().revisions.len(), 2);
+    }
+
+    #[test]
+    fn decreasing_revisions_credit_the_difference() {
+        let mut conn = db();
+        apply(&mut conn, "t", &cost(2.0, "result"), NOW).unwrap();
+        apply(&mut conn, "t", &cost(1.5, "refund"), NOW + 1).unwrap();
+        assert_close(accounted(&conn), 1.5);
+        let negative: f64 = conn
+            .query_row("SELECT MIN(delta) FROM cost_ledger WHERE task_id = 't'", [], |row| row.get(0))
+            .unwrap();
+        assert!((negative + 0.5).abs() < 1e-9);
+        let history = history(&conn, "t").unwrap();
+        assert!((history.revisions[1].cost_delta.unwrap() + 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn metrics_become_final_on_the_flag_or_after_quiet_time() {
+        let mut conn = db();
+        let report = MetricReport {
+            is_final: true,
+            ..cost(1.0, "final")
+        };
+        assert!(apply(&mut conn, "t", &report, NOW).unwrap().metrics_final);
+        // Final tasks and running ones are left to the timeout
+        assert!(finalize_stale(&mut conn, NOW + 3_600_000).unwrap().is_empty());
+
+        conn.execute("UPDATE tasks SET metrics_final = 0 WHERE id = 't'", []).unwrap();
+        apply(&mut conn, "t", &cost(1.2, "reconciled"), NOW).unwrap();
+        assert!(finalize_stale(&mut conn, NOW + 60_000).unwrap().is_empty());
+        let finalized = finalize_stale(&mut conn, NOW + FINAL_AFTER.as_millis() as i64 + 1).unwrap();
+        assert_eq!(finalized.len(), 1);
+        assert_eq!(finalized[0].cost, Some(1.2));
+        let history = history(&conn, "t").unwrap();
+        assert!(history.metrics_final);
+        assert_eq!(history.revisions.last().unwrap().reason.as_deref(), Some(TIMEOUT_REASON));
+    }
+
+    #[test]
+    fn invalid_reports_are_rejected() {
+        let mut conn = db();
+        assert!(apply(&mut conn, "t", &cost(-1.0, "x"), NOW).is_err());
+        assert!(apply(&mut conn, "t", &cost(f64::NAN, "x"), NOW).is_err());
+        assert!(apply(&mut conn, "t", &MetricReport::default(), NOW).is_err());
+        assert!(apply(&mut conn, "missing", &cost(1.0, "x"), NOW).is_err());
+        assert!(history(&conn, "t").unwrap().revisions.is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+// Scratch notes a person keeps on a task while it runs.
+//
+// One markdown note per task in `task_notes`, indexed by `task_notes_fts` so task
+// search finds it. Notes are for people only: nothing that builds what the agent
+// sees (`prompts`, `session_files`, `diagnostic_task`, `forks`) reads this table.
+// Exports include them only when asked, under "Reviewer notes".
+//
+// The editor saves on a debounce, and each save goes through the flag lane
+// (`FlagLane::write`) with the `row_version` the editor last saw. If another window
+// saved in between, the write is refused with `Conflict` carrying the stored note
+// so the editor can merge. Trashing a task leaves its note alone; purging the task
+// deletes it through the foreign key.
+
+use crate::db;
+use crate::error::CommandError;
+use crate::flags::FlagLane;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+const NOTE_SELECT: &str = "SELECT task_id, content, row_version, updated_at FROM task_notes";
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TaskNote {
+    pub task_id: String,
+    pub content: String,
+    pub row_version: i64,
+    pub updated_at: String,
+}
+
+impl TaskNote {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            task_id: row.get(0)?,
+            content: row.get(1)?,
+            row_version: row.get(2)?,
+            updated_at: row.get(3)?,
+        })
+    }
+}
+
+pub(crate) fn load(conn: &Connection, task_id: &str) -> rusqlite::Result<Option<TaskNote>> {
+    conn.prepare_cached(&format!("{} WHERE task_id = ?1", NOTE_SELECT))?
+        .query_row([task_id], TaskNote::from_row)
+        .optional()
+}
+
+/// Non-empty notes on the tasks matching `filter`, a WHERE clause over `task_id`
+/// with `?1` bound to `scope_id`
+pub(crate) fn notes_where(conn: &Connection, filter: &str, scope_id: &str) -> Result<Vec<TaskNote>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "{} WHERE ({}) AND trim(content) != '' ORDER BY task_id",
+            NOTE_SELECT, filter
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([scope_id], TaskNote::from_row).map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+/// Save `content` if the stored note is still at `base_version` (`None`: no note
+/// yet). Otherwise leaves it alone and returns the stored note as the error.
+fn save(
+    conn: &Connection,
+    task_id: &str,
+    content: &str,
+    base_version: Option<i64>,
+) -> rusqlite::Result<Result<TaskNote, TaskNote>> {
+    if let Some(current) = load(conn, task_id)? {
+        if base_version != Some(current.row_version) {
+            return Ok(Err(current));
+        }
+    }
+    let note = conn
+        .prepare_cached(
+            "INSERT INTO task_notes (task_id, content) VALUES (?1, ?2)
+             ON CONFLICT(task_id) DO UPDATE SET
+                 content = excluded.content,
+                 row_version = row_version + 1,
+                 updated_at = datetime('now')
+             RETURNING task_id, content, row_version, updated_at",
+        )?
+        .query_row(params![task_id, content], TaskNote::from_row)?;
+    Ok(Ok(note))
+}
+
+#[tauri::command]
+pub async fn get_task_note(app: AppHandle, task_id: String) -> Result<Option<TaskNote>, String> {
+    db::with_conn(&app, move |conn| load(conn, &task_id).map_err(|e| e.to_string())).await
+}
+
+/// Save a task's note. `base_version` is the `row_version` the editor loaded, or
+/// none for a task without a note; a stale one fails with `conflict`, with the
+/// stored note in `existing`.
+#[tauri::command]
+pub async fn set_task_note(
+    app: AppHandle,
+    task_id: String,
+    content: String,
+    base_version: Option<i64>,
+) -> Result<TaskNote, CommandError> {
+    let saved = tauri::async_runtime::spawn_blocking(move || {
+        let lane = app.state::<FlagLane>();
+        lane.write(
+            || db::open(&app),
+            |conn| {
+                let saved = save(conn, &task_id, &content, base_version)?;
+                if let Ok(note) = &saved {
+                    db::emit_versioned_change(&app, "task_notes", "update", vec![(note.task_id.clone(), note.row_version)]);
+                }
+                Ok(saved)
+            },
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    saved.map_err(|current| CommandError::Conflict {
+        message: format!(
+            "The note on task {} was changed in another window (now version {})",
+            current.task_id, current.row_version
+        ),
+        existing: serde_json::to_value(&current).unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             INSERT INTO tasks (id, prompt) VALUES ('t1', 'p'), ('t2', 'p');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn stale_versions_are_refused_with_the_stored_note() {
+        let conn = db();
+        let first = save(&conn, "t1", "check section 3", None).unwrap().unwrap();
+        assert_eq!(first.row_version, 1);
+
+        // Two windows both loaded version 1; the second save loses
+        let winner = save(&conn, "t1", "check section 3 and 4", Some(1)).unwrap().unwrap();
+        assert_eq!(winner.row_version, 2);
+        let loser = save(&conn, "t1", "numbers look off", Some(1)).unwrap().unwrap_err();
+        assert_eq!(loser, winner);
+
+        // A window that thought there was no note yet conflicts too
+        assert!(save(&conn, "t1", "new", None).unwrap().is_err());
+    }
+
+    #[test]
+    fn notes_are_searchable_and_purged_with_the_task() {
+        let conn = db();
+        save(&conn, "t1", "recheck the quarterly numbers", None).unwrap().unwrap();
+        save(&conn, "t2", "fine", None).unwrap().unwrap();
+        let matched: String = conn
+            .query_row(
+                "SELECT task_id FROM task_notes WHERE id IN
+                     (SELECT rowid FROM task_notes_fts WHERE task_notes_fts MATCH 'quarterly')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(matched, "t1");
+
+        conn.execute("UPDATE tasks SET trashed_at = datetime('now') WHERE id = 't1'", []).unwrap();
+        assert!(load(&conn, "t1").unwrap().is_some());
+        conn.execute("DELETE FROM tasks WHERE id = 't1'", []).unwrap();
+        assert!(load(&conn, "t1").unwrap().is_none());
+        let indexed: i64 = conn
+            .query_row("SELECT COUNT(*) FROM task_notes_fts WHERE task_notes_fts MATCH 'quarterly'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(indexed, 0);
+    }
+}
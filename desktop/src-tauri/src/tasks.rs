@@ -0,0 +1,132 @@
+// Task list queries shared by Rust commands.
+//
+// `TaskQuery` mirrors the filters and sort orders of the task list view so
+// commands like prev/next navigation see exactly what the list shows.
+
+use crate::db;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskSort {
+    #[default]
+    CreatedDesc,
+    CreatedAsc,
+    UpdatedDesc,
+    TaskIndexAsc,
+}
+
+impl TaskSort {
+    /// ORDER BY clause, always ending in `id` so ties are deterministic
+    pub fn order_by(self) -> &'static str {
+        match self {
+            TaskSort::CreatedDesc => "created_at DESC, id DESC",
+            TaskSort::CreatedAsc => "created_at ASC, id ASC",
+            TaskSort::UpdatedDesc => "updated_at DESC, id DESC",
+            TaskSort::TaskIndexAsc => "task_index ASC, created_at ASC, id ASC",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TaskQuery {
+    pub session_id: Option<String>,
+    pub status: Option<String>,
+    pub favorite: Option<bool>,
+    /// Case-insensitive substring match on the prompt; the overflow of an oversized
+    /// prompt is matched by words through `attachments_fts`
+    pub search: Option<String>,
+    #[serde(default)]
+    pub sort: TaskSort,
+}
+
+impl TaskQuery {
+    /// WHERE clause (without the keyword) and its positional parameters
+    pub fn filter(&self) -> (String, Vec<Value>) {
+        // Trashed tasks only show in the trash
+        let mut clauses = vec!["trashed_at IS NULL"];
+        let mut params = Vec::new();
+        if let Some(session_id) = &self.session_id {
+            clauses.push("session_id = ?");
+            params.push(Value::Text(session_id.clone()));
+        }
+        if let Some(status) = &self.status {
+            clauses.push("status = ?");
+            params.push(Value::Text(status.clone()));
+        }
+        if let Some(favorite) = self.favorite {
+            clauses.push("COALESCE(favorite, 0) = ?");
+            params.push(Value::Integer(favorite as i64));
+        }
+        if let Some(search) = self.search.as_deref().filter(|s| !s.trim().is_empty()) {
+            clauses.push(SEARCH_CLAUSE);
+            params.extend(search_params(search));
+        }
+        (clauses.join(" AND "), params)
+    }
+}
+
+/// Text search over prompts, oversized prompt overflow and task notes; takes the
+/// three parameters from `search_params`
+pub(crate) const SEARCH_CLAUSE: &str = "(instr(lower(prompt), lower(?)) > 0
+      OR prompt_overflow_attachment_id IN
+         (SELECT rowid FROM attachments_fts WHERE attachments_fts MATCH ?)
+      OR id IN
+         (SELECT task_id FROM task_notes WHERE id IN
+             (SELECT rowid FROM task_notes_fts WHERE task_notes_fts MATCH ?)))";
+
+pub(crate) fn search_params(search: &str) -> [Value; 3] {
+    // Quoted as one FTS phrase so operators in the search text are literal
+    let phrase = format!("\"{}\"", search.trim().replace('"', "\"\""));
+    [Value::Text(search.trim().to_string()), Value::Text(phrase.clone()), Value::Text(phrase)]
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Neighbors {
+    pub previous: Option<String>,
+    pub next: Option<String>,
+    /// False when the task doesn't match the query at all
+    pub in_list: bool,
+}
+
+/// Previous and next task ids around `task_id` under the list view's filter and sort
+#[tauri::command]
+pub async fn task_neighbors(
+    app: tauri::AppHandle,
+    task_id: String,
+    query: TaskQuery,
+) -> Result<Neighbors, String> {
+    db::with_conn(&app, move |conn| {
+        let (filter, mut params) = query.filter();
+        let order = query.sort.order_by();
+        let sql = format!(
+            "WITH ordered AS (
+                 SELECT id,
+                        LAG(id) OVER (ORDER BY {order}) AS previous_id,
+                        LEAD(id) OVER (ORDER BY {order}) AS next_id
+                 FROM tasks WHERE {filter}
+             )
+             SELECT previous_id, next_id FROM ordered WHERE id = ?"
+        );
+        params.push(Value::Text(task_id));
+        let row: Option<(Option<String>, Option<String>)> = conn
+            .query_row(&sql, params_from_iter(params), |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        Ok(match row {
+            Some((previous, next)) => Neighbors {
+                previous,
+                next,
+                in_list: true,
+            },
+            None => Neighbors {
+                previous: None,
+                next: None,
+                in_list: false,
+            },
+        })
+    })
+    .await
+}
@@ -0,0 +1,196 @@
+// Batch thumbnail generation for image files, and the store thumbnails live in.
+//
+// Thumbnails are files in `~/.workany/thumbnails` named by the SHA-256 of their
+// bytes; `files.thumbnail_hash` points at one and `media_format = 1` marks the row.
+// The gallery loads them through the `workany-thumb` URI scheme. Rows the media
+// backfill hasn't reached yet still carry a data URL in `files.thumbnail`.
+
+use crate::{db, events};
+use base64::Engine;
+use rusqlite::params;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::http::{Request, Response, StatusCode};
+use tauri::AppHandle;
+
+/// URI scheme serving stored thumbnails, as `workany-thumb://localhost/<hash>`
+pub(crate) const STORE_SCHEME: &str = "workany-thumb";
+
+const MAX_WORKERS: usize = 4;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const MIN_DIM: u32 = 16;
+const MAX_DIM: u32 = 2048;
+
+pub(crate) fn store_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".workany").join("thumbnails"))
+}
+
+fn is_hash(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+}
+
+/// Write `bytes` to the store unless an identical thumbnail is already there, and
+/// return its hash
+pub(crate) fn store_bytes(bytes: &[u8]) -> Result<String, String> {
+    let hash = format!("{:x}", Sha256::digest(bytes));
+    let dir = store_dir().ok_or("Cannot locate the home directory")?;
+    let path = dir.join(&hash);
+    if !path.exists() {
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let tmp = dir.join(format!("{}.partial", hash));
+        std::fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+    }
+    Ok(hash)
+}
+
+/// Bytes of a legacy `data:image/...;base64,` thumbnail
+pub(crate) fn decode_data_url(url: &str) -> Result<Vec<u8>, String> {
+    let (header, data) = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+        .ok_or("Not a data URL")?;
+    if !header.ends_with(";base64") {
+        return Err(format!("Unsupported data URL encoding '{}'", header));
+    }
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| e.to_string())?;
+    image::guess_format(&bytes).map_err(|_| "Data URL is not an image".to_string())?;
+    Ok(bytes)
+}
+
+/// Decode an image, downscale it and store it as PNG; returns the store hash
+pub(crate) fn render_thumbnail(path: &Path, max_dim: u32) -> Result<String, String> {
+    let image = image::open(path).map_err(|e| e.to_string())?;
+    let thumbnail = image.thumbnail(max_dim, max_dim);
+    let mut bytes = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    store_bytes(&bytes)
+}
+
+/// Handler for `STORE_SCHEME` requests
+pub(crate) fn serve(request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    let hash = request.uri().path().trim_start_matches('/');
+    let found = is_hash(hash)
+        .then(|| store_dir().map(|dir| dir.join(hash)))
+        .flatten()
+        .and_then(|path| std::fs::read(path).ok());
+    let response = Response::builder().header("Access-Control-Allow-Origin", "*");
+    let response = match found {
+        Some(bytes) => {
+            let mime = image::guess_format(&bytes)
+                .map(|format| format.to_mime_type())
+                .unwrap_or("application/octet-stream");
+            response
+                .header("Content-Type", mime)
+                .header("Cache-Control", "max-age=31536000, immutable")
+                .body(Cow::Owned(bytes))
+        }
+        None => response.status(StatusCode::NOT_FOUND).body(Cow::Borrowed(&[][..])),
+    };
+    response.unwrap_or_else(|_| Response::new(Cow::Borrowed(&[][..])))
+}
+
+fn pending_images(conn: &rusqlite::Connection, task_id: Option<&str>) -> Result<Vec<(i64, String)>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, path FROM files
+             WHERE type = 'image' AND thumbnail_hash IS NULL AND (thumbnail IS NULL OR thumbnail = '')
+               AND (?1 IS NULL OR task_id = ?1)
+             ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([task_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+}
+
+fn prewarm(app: &AppHandle, task_id: Option<String>, max_dim: u32) -> Result<u32, String> {
+    let conn = db::open(app)?;
+    let pending = pending_images(&conn, task_id.as_deref())?;
+    let total = pending.len() as u32;
+    if total == 0 {
+        return Ok(0);
+    }
+
+    let queue = Arc::new(Mutex::new(VecDeque::from(pending)));
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(MAX_WORKERS)
+        .min(total as usize);
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..workers {
+        let queue = queue.clone();
+        let tx = tx.clone();
+        std::thread::spawn(move || loop {
+            let next = queue.lock().ok().and_then(|mut q| q.pop_front());
+            let Some((id, path)) = next else {
+                break;
+            };
+            let result = render_thumbnail(Path::new(&path), max_dim);
+            if tx.send((id, path, result)).is_err() {
+                break;
+            }
+        });
+    }
+    drop(tx);
+
+    let emit = |done: u32, generated: u32| {
+        events::emit_typed(
+            &app,
+            events::ThumbnailProgress {
+                task_id: task_id.clone(),
+                done,
+                total,
+                generated,
+            },
+        );
+    };
+
+    let mut done = 0;
+    let mut generated = 0;
+    let mut last_emit = Instant::now();
+    for (id, path, result) in rx {
+        done += 1;
+        match result {
+            Ok(hash) => {
+                conn.execute(
+                    "UPDATE files SET thumbnail = NULL, thumbnail_hash = ?1, media_format = 1 WHERE id = ?2",
+                    params![hash, id],
+                )
+                .map_err(|e| e.to_string())?;
+                generated += 1;
+            }
+            Err(e) => eprintln!("[Thumbnails] Skipping {}: {}", path, e),
+        }
+        if last_emit.elapsed() >= PROGRESS_INTERVAL {
+            emit(done, generated);
+            last_emit = Instant::now();
+        }
+    }
+    emit(done, generated);
+    Ok(generated)
+}
+
+/// Generate missing thumbnails for image files, optionally for one task only.
+/// Returns how many thumbnails were generated.
+#[tauri::command]
+pub async fn prewarm_thumbnails(app: AppHandle, task_id: Option<String>, max_dim: u32) -> Result<u32, String> {
+    if !(MIN_DIM..=MAX_DIM).contains(&max_dim) {
+        return Err(format!("max_dim must be between {} and {}", MIN_DIM, MAX_DIM));
+    }
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || prewarm(&handle, task_id, max_dim))
+        .await
+        .map_err(|e| e.to_string())?
+}
@@ -0,0 +1,275 @@
+// Window tiling: place task windows next to the main window.
+//
+// Frames are computed by `compute_frames`, a pure function over the main window's
+// monitor work area in physical pixels, and then applied with set_position/set_size.
+// The last layout is stored in settings so `retile` can restore it after the
+// monitor setup changes.
+
+use crate::window::MAIN_WINDOW;
+use crate::{db, settings};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize};
+
+const LAST_LAYOUT_KEY: &str = "window_tiling";
+// Smallest logical size a tiled window is shrunk to
+const MIN_WIDTH: f64 = 480.0;
+const MIN_HEIGHT: f64 = 320.0;
+// Share of the work area given to the main window in MainPlusStack
+const MAIN_SHARE: f64 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TileLayout {
+    LeftRight,
+    TopBottom,
+    MainPlusStack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Frame {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedLayout {
+    layout: TileLayout,
+    window_labels: Vec<String>,
+}
+
+/// Split `length` into `count` segments of at least `min`, returning (offset, size) pairs.
+/// When there isn't room for every segment at its minimum, segments overlap evenly
+/// instead of running off the work area.
+fn split_axis(start: i32, length: u32, count: usize, min: u32) -> Vec<(i32, u32)> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let even = length / count as u32;
+    if even >= min || count == 1 {
+        let size = even.max(1);
+        return (0..count)
+            .map(|i| {
+                let offset = start + (i as u32 * size) as i32;
+                // The last segment absorbs the rounding remainder
+                let this = if i == count - 1 {
+                    length - size * (count as u32 - 1)
+                } else {
+                    size
+                };
+                (offset, this)
+            })
+            .collect();
+    }
+    let size = min.min(length);
+    let step = (length - size) / (count as u32 - 1);
+    (0..count)
+        .map(|i| {
+            // Pin the last segment to the far edge so rounding never leaves a gap
+            let offset = if i == count - 1 {
+                length - size
+            } else {
+                i as u32 * step
+            };
+            (start + offset as i32, size)
+        })
+        .collect()
+}
+
+/// Frames for the main window followed by `others` stacked windows, in that order
+pub fn compute_frames(layout: TileLayout, work_area: Frame, others: usize, min: (u32, u32)) -> Vec<Frame> {
+    let (min_w, min_h) = (min.0.min(work_area.width), min.1.min(work_area.height));
+    let count = others + 1;
+    match layout {
+        TileLayout::LeftRight => split_axis(work_area.x, work_area.width, count, min_w)
+            .into_iter()
+            .map(|(x, width)| Frame {
+                x,
+                y: work_area.y,
+                width,
+                height: work_area.height,
+            })
+            .collect(),
+        TileLayout::TopBottom => split_axis(work_area.y, work_area.height, count, min_h)
+            .into_iter()
+            .map(|(y, height)| Frame {
+                x: work_area.x,
+                y,
+                width: work_area.width,
+                height,
+            })
+            .collect(),
+        TileLayout::MainPlusStack => {
+            if others == 0 {
+                return vec![work_area];
+            }
+            let main_w = ((work_area.width as f64 * MAIN_SHARE).round() as u32)
+                .max(min_w)
+                .min(work_area.width.saturating_sub(min_w).max(min_w));
+            let stack_x = work_area.x + main_w as i32;
+            let stack_w = work_area.width.saturating_sub(main_w).max(min_w);
+            // If the stack column can't fit beside the main window it overlaps from the right
+            let stack_x = stack_x.min(work_area.x + (work_area.width - stack_w) as i32);
+            let mut frames = vec![Frame {
+                x: work_area.x,
+                y: work_area.y,
+                width: main_w,
+                height: work_area.height,
+            }];
+            frames.extend(
+                split_axis(work_area.y, work_area.height, others, min_h)
+                    .into_iter()
+                    .map(|(y, height)| Frame {
+                        x: stack_x,
+                        y,
+                        width: stack_w,
+                        height,
+                    }),
+            );
+            frames
+        }
+    }
+}
+
+fn apply_layout(app: &AppHandle, layout: TileLayout, window_labels: &[String]) -> Result<Vec<Frame>, String> {
+    let main = app
+        .get_webview_window(MAIN_WINDOW)
+        .ok_or("Main window not found")?;
+    let mut windows = Vec::with_capacity(window_labels.len());
+    for label in window_labels {
+        if label == MAIN_WINDOW {
+            continue;
+        }
+        let window = app
+            .get_webview_window(label)
+            .ok_or_else(|| format!("Window '{}' not found", label))?;
+        windows.push(window);
+    }
+
+    let monitor = main
+        .current_monitor()
+        .map_err(|e| e.to_string())?
+        .or(main.primary_monitor().map_err(|e| e.to_string())?)
+        .ok_or("No monitor available for the main window")?;
+    let area = monitor.work_area();
+    let work_area = Frame {
+        x: area.position.x,
+        y: area.position.y,
+        width: area.size.width,
+        height: area.size.height,
+    };
+    let scale = monitor.scale_factor();
+    let min = (
+        (MIN_WIDTH * scale).round() as u32,
+        (MIN_HEIGHT * scale).round() as u32,
+    );
+
+    let frames = compute_frames(layout, work_area, windows.len(), min);
+    for (window, frame) in std::iter::once(&main).chain(windows.iter()).zip(&frames) {
+        let _ = window.unminimize();
+        // Position first so windows from another monitor pick up this monitor's scale
+        window
+            .set_position(PhysicalPosition::new(frame.x, frame.y))
+            .map_err(|e| e.to_string())?;
+        window
+            .set_size(PhysicalSize::new(frame.width, frame.height))
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(frames)
+}
+
+/// Tile the main window and the given task windows on the main window's monitor
+#[tauri::command]
+pub async fn tile_windows(
+    app: AppHandle,
+    layout: TileLayout,
+    window_labels: Vec<String>,
+) -> Result<Vec<Frame>, String> {
+    let frames = apply_layout(&app, layout, &window_labels)?;
+    let saved = SavedLayout {
+        layout,
+        window_labels,
+    };
+    db::with_conn(&app, move |conn| settings::set(conn, LAST_LAYOUT_KEY, &saved)).await?;
+    Ok(frames)
+}
+
+/// Re-apply the last tiling layout, skipping windows that have since closed
+#[tauri::command]
+pub async fn retile(app: AppHandle) -> Result<Vec<Frame>, String> {
+    let saved: Option<SavedLayout> =
+        db::with_conn(&app, |conn| settings::get(conn, LAST_LAYOUT_KEY)).await?;
+    let saved = saved.ok_or("No tiling layout has been applied yet")?;
+    let labels: Vec<String> = saved
+        .window_labels
+        .into_iter()
+        .filter(|label| app.get_webview_window(label).is_some())
+        .collect();
+    apply_layout(&app, saved.layout, &labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Frame = Frame {
+        x: 0,
+        y: 25,
+        width: 1920,
+        height: 1055,
+    };
+    const MIN: (u32, u32) = (480, 320);
+
+    fn assert_inside(frames: &[Frame], area: Frame) {
+        for f in frames {
+            assert!(f.x >= area.x && f.y >= area.y, "{:?} starts outside", f);
+            assert!(f.x + f.width as i32 <= area.x + area.width as i32, "{:?} too wide", f);
+            assert!(f.y + f.height as i32 <= area.y + area.height as i32, "{:?} too tall", f);
+        }
+    }
+
+    #[test]
+    fn left_right_splits_evenly() {
+        let frames = compute_frames(TileLayout::LeftRight, AREA, 1, MIN);
+        assert_eq!(
+            frames,
+            vec![
+                Frame { x: 0, y: 25, width: 960, height: 1055 },
+                Frame { x: 960, y: 25, width: 960, height: 1055 },
+            ]
+        );
+    }
+
+    #[test]
+    fn top_bottom_gives_remainder_to_last() {
+        let frames = compute_frames(TileLayout::TopBottom, AREA, 1, MIN);
+        assert_eq!(frames[0].height + frames[1].height, AREA.height);
+        assert_eq!(frames[1].y, AREA.y + frames[0].height as i32);
+        assert_inside(&frames, AREA);
+    }
+
+    #[test]
+    fn main_plus_stack_uses_sixty_percent() {
+        let frames = compute_frames(TileLayout::MainPlusStack, AREA, 3, MIN);
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].width, 1152);
+        assert!(frames[1..].iter().all(|f| f.x == 1152 && f.width == 768));
+        assert_inside(&frames, AREA);
+    }
+
+    #[test]
+    fn respects_minimum_sizes_by_overlapping() {
+        let small = Frame { x: 100, y: 0, width: 1280, height: 720 };
+        let frames = compute_frames(TileLayout::LeftRight, small, 3, MIN);
+        assert!(frames.iter().all(|f| f.width == 480));
+        assert_eq!(frames[3].x + 480, 100 + 1280);
+        assert_inside(&frames, small);
+    }
+
+    #[test]
+    fn is_deterministic() {
+        let a = compute_frames(TileLayout::MainPlusStack, AREA, 5, MIN);
+        let b = compute_frames(TileLayout::MainPlusStack, AREA, 5, MIN);
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,466 @@
+// Data for the full-history timeline, loaded a day at a time.
+//
+// `get_timeline_buckets` returns per-day counts for a date range without touching
+// any table rows: each day is a range search on the `created_epoch` columns, and the
+// indexes added in migration 27 cover every column the aggregates read. The UI then
+// asks `get_timeline_day` for the days it scrolls into view, which interleaves the
+// day's tasks with session creations and deliverables and pages through heavy days
+// with an opaque cursor.
+//
+// Days are local calendar days, split at local midnight the same way as the quota
+// periods (`quota::midnight`). Day items carry their task's focus session, and the
+// bucket request can return the range's focus sessions for grouping.
+
+use crate::focus_sessions::{self, FocusSession};
+use crate::{db, quota};
+use chrono::{Duration as ChronoDuration, Local, NaiveDate, TimeZone};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+/// Longest range one bucket request may cover
+const MAX_RANGE_DAYS: i64 = 366;
+/// Range returned when the request leaves out `from`
+const DEFAULT_RANGE_DAYS: i64 = 90;
+const DEFAULT_DAY_LIMIT: i64 = 50;
+const MAX_DAY_LIMIT: i64 = 200;
+/// Longest prompt excerpt in a day item
+const TITLE_CHARS: i64 = 200;
+
+const TASK_STATS_SQL: &str = "SELECT COUNT(*), COALESCE(SUM(status = 'completed'), 0),
+                                     COALESCE(SUM(status = 'error'), 0), TOTAL(cost)
+                              FROM tasks WHERE created_epoch >= ?1 AND created_epoch < ?2";
+const SESSION_COUNT_SQL: &str = "SELECT COUNT(*) FROM sessions WHERE created_epoch >= ?1 AND created_epoch < ?2";
+const DELIVERABLE_COUNT_SQL: &str = "SELECT COUNT(*) FROM files
+                                     WHERE created_epoch >= ?1 AND created_epoch < ?2
+                                       AND type IN ('document', 'presentation', 'spreadsheet', 'website')
+                                       AND source_file_id IS NULL";
+
+/// Inclusive range of local dates, `YYYY-MM-DD`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TimelineRange {
+    /// Defaults to `DEFAULT_RANGE_DAYS` before `to`
+    pub from: Option<String>,
+    /// Defaults to today
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineBucket {
+    pub date: String,
+    pub tasks: i64,
+    pub completed: i64,
+    pub failed: i64,
+    pub cost: f64,
+    pub sessions_created: i64,
+    pub deliverables: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineBuckets {
+    pub from: String,
+    pub to: String,
+    /// Days in the range with any activity, oldest first
+    pub buckets: Vec<TimelineBucket>,
+    /// Local date of the oldest task, so the UI knows where history ends
+    pub first_date: Option<String>,
+    /// Focus sessions that started in the range, when asked for
+    pub focus_sessions: Option<Vec<FocusSession>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineItem {
+    /// `session_created`, `task`, `task_failed` or `deliverable`
+    pub kind: String,
+    /// Unix seconds
+    pub at: i64,
+    pub session_id: Option<String>,
+    pub task_id: Option<String>,
+    pub file_id: Option<i64>,
+    /// Prompt excerpt, or the file name for deliverables
+    pub title: String,
+    pub status: Option<String>,
+    pub cost: Option<f64>,
+    pub file_type: Option<String>,
+    /// The focus session of the item's task, if it has been assigned one
+    pub focus_session_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineDay {
+    pub date: String,
+    pub items: Vec<TimelineItem>,
+    /// Pass back to get the next page; `None` on the day's last page
+    pub next_cursor: Option<String>,
+}
+
+/// Unix seconds of the start and end of local day `date`
+pub(crate) fn day_bounds<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> (i64, i64) {
+    (
+        quota::midnight(tz, date) / 1000,
+        quota::midnight(tz, date + ChronoDuration::days(1)) / 1000,
+    )
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("Invalid date: {}", value))
+}
+
+pub(crate) fn resolve_range(range: &TimelineRange, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), String> {
+    let to = range.to.as_deref().map(parse_date).transpose()?.unwrap_or(today);
+    let from = match range.from.as_deref() {
+        Some(from) => parse_date(from)?,
+        None => to - ChronoDuration::days(DEFAULT_RANGE_DAYS - 1),
+    };
+    if from > to {
+        return Err("Timeline range starts after it ends".to_string());
+    }
+    if (to - from).num_days() >= MAX_RANGE_DAYS {
+        return Err(format!("Timeline ranges are limited to {} days", MAX_RANGE_DAYS));
+    }
+    Ok((from, to))
+}
+
+fn buckets<Tz: TimeZone>(conn: &Connection, tz: &Tz, from: NaiveDate, to: NaiveDate) -> Result<TimelineBuckets, String> {
+    let mut task_stats = conn.prepare_cached(TASK_STATS_SQL).map_err(|e| e.to_string())?;
+    let mut session_count = conn.prepare_cached(SESSION_COUNT_SQL).map_err(|e| e.to_string())?;
+    let mut deliverable_count = conn.prepare_cached(DELIVERABLE_COUNT_SQL).map_err(|e| e.to_string())?;
+
+    let mut buckets = Vec::new();
+    let mut date = from;
+    while date <= to {
+        let (start, end) = day_bounds(tz, date);
+        let (tasks, completed, failed, cost) = task_stats
+            .query_row(params![start, end], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .map_err(|e| e.to_string())?;
+        let sessions_created: i64 = session_count
+            .query_row(params![start, end], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        let deliverables: i64 = deliverable_count
+            .query_row(params![start, end], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        if tasks + sessions_created + deliverables > 0 {
+            buckets.push(TimelineBucket {
+                date: date.to_string(),
+                tasks,
+                completed,
+                failed,
+                cost,
+                sessions_created,
+                deliverables,
+            });
+        }
+        date += ChronoDuration::days(1);
+    }
+
+    let first_epoch: Option<i64> = conn
+        .query_row("SELECT MIN(created_epoch) FROM tasks", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    let first_date = first_epoch
+        .and_then(|epoch| tz.timestamp_opt(epoch, 0).single())
+        .map(|at| at.date_naive().to_string());
+
+    Ok(TimelineBuckets {
+        from: from.to_string(),
+        to: to.to_string(),
+        buckets,
+        first_date,
+        focus_sessions: None,
+    })
+}
+
+/// Position after the last item of a page: `epoch:rank:key`
+struct Cursor {
+    epoch: i64,
+    rank: i64,
+    key: String,
+}
+
+impl Cursor {
+    fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || "Invalid timeline cursor".to_string();
+        let mut parts = value.splitn(3, ':');
+        let epoch = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+        let rank = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+        let key = parts.next().ok_or_else(invalid)?.to_string();
+        Ok(Cursor { epoch, rank, key })
+    }
+
+    fn encode(&self) -> String {
+        format!("{}:{}:{}", self.epoch, self.rank, self.key)
+    }
+}
+
+/// Within a second, sessions sort before their tasks and tasks before their files
+const DAY_SQL: &str = "
+    SELECT epoch, rank, key, kind, session_id, task_id, file_id, title, status, cost, file_type,
+           (SELECT focus_session_id FROM focus_session_tasks WHERE task_id = events.task_id) FROM (
+        SELECT created_epoch AS epoch, 0 AS rank, id AS key, 'session_created' AS kind,
+               id AS session_id, NULL AS task_id, NULL AS file_id, substr(prompt, 1, ?6) AS title,
+               NULL AS status, NULL AS cost, NULL AS file_type
+        FROM sessions WHERE created_epoch >= ?1 AND created_epoch < ?2
+        UNION ALL
+        SELECT created_epoch, 1, id, CASE WHEN status = 'error' THEN 'task_failed' ELSE 'task' END,
+               session_id, id, NULL, substr(prompt, 1, ?6), status, cost, NULL
+        FROM tasks WHERE created_epoch >= ?1 AND created_epoch < ?2
+        UNION ALL
+        SELECT f.created_epoch, 2, CAST(f.id AS TEXT), 'deliverable',
+               t.session_id, f.task_id, f.id, f.name, NULL, NULL, f.type
+        FROM files f LEFT JOIN tasks t ON t.id = f.task_id
+        WHERE f.created_epoch >= ?1 AND f.created_epoch < ?2
+          AND f.type IN ('document', 'presentation', 'spreadsheet', 'website')
+          AND f.source_file_id IS NULL
+    ) AS events
+    WHERE (epoch, rank, key) > (?3, ?4, ?5)
+    ORDER BY epoch, rank, key
+    LIMIT ?7";
+
+fn day<Tz: TimeZone>(
+    conn: &Connection,
+    tz: &Tz,
+    date: NaiveDate,
+    cursor: Option<&str>,
+    limit: i64,
+) -> Result<TimelineDay, String> {
+    let (start, end) = day_bounds(tz, date);
+    let after = match cursor {
+        Some(cursor) => Cursor::parse(cursor)?,
+        None => Cursor {
+            epoch: start - 1,
+            rank: 0,
+            key: String::new(),
+        },
+    };
+    let limit = limit.clamp(1, MAX_DAY_LIMIT);
+
+    let mut stmt = conn.prepare_cached(DAY_SQL).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![start, end, after.epoch, after.rank, after.key, TITLE_CHARS, limit + 1],
+            |row| {
+                let position = Cursor {
+                    epoch: row.get(0)?,
+                    rank: row.get(1)?,
+                    key: row.get(2)?,
+                };
+                let item = TimelineItem {
+                    kind: row.get(3)?,
+                    at: position.epoch,
+                    session_id: row.get(4)?,
+                    task_id: row.get(5)?,
+                    file_id: row.get(6)?,
+                    title: row.get(7)?,
+                    status: row.get(8)?,
+                    cost: row.get(9)?,
+                    file_type: row.get(10)?,
+                    focus_session_id: row.get(11)?,
+                };
+                Ok((position, item))
+            },
+        )
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let has_more = rows.len() as i64 > limit;
+    let mut items = Vec::with_capacity(rows.len());
+    let mut last = None;
+    for (position, item) in rows.into_iter().take(limit as usize) {
+        items.push(item);
+        last = Some(position);
+    }
+    Ok(TimelineDay {
+        date: date.to_string(),
+        items,
+        next_cursor: if has_more { last.map(|c| c.encode()) } else { None },
+    })
+}
+
+/// Per-day activity counts for a range of local dates, optionally with the focus
+/// sessions that started in it
+#[tauri::command]
+pub async fn get_timeline_buckets(
+    app: tauri::AppHandle,
+    range: Option<TimelineRange>,
+    include_focus_sessions: Option<bool>,
+) -> Result<TimelineBuckets, String> {
+    let range = range.unwrap_or_default();
+    db::with_conn(&app, move |conn| {
+        let (from, to) = resolve_range(&range, Local::now().date_naive())?;
+        let mut result = buckets(conn, &Local, from, to)?;
+        if include_focus_sessions.unwrap_or(false) {
+            focus_sessions::refresh(conn)?;
+            let (start, _) = day_bounds(&Local, from);
+            let (_, end) = day_bounds(&Local, to);
+            result.focus_sessions = Some(focus_sessions::in_range(conn, start, end)?);
+        }
+        Ok(result)
+    })
+    .await
+}
+
+/// One page of a local day's tasks and events, oldest first
+#[tauri::command]
+pub async fn get_timeline_day(
+    app: tauri::AppHandle,
+    date: String,
+    cursor: Option<String>,
+    limit: Option<i64>,
+) -> Result<TimelineDay, String> {
+    let date = parse_date(&date)?;
+    db::with_conn(&app, move |conn| {
+        day(conn, &Local, date, cursor.as_deref(), limit.unwrap_or(DEFAULT_DAY_LIMIT))
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+    use std::time::Instant;
+
+    fn db() -> Connection {
+        crate::migrations::test_db()
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        parse_date(value).unwrap()
+    }
+
+    fn plan(conn: &Connection, sql: &str) -> String {
+        let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql)).unwrap();
+        let details: Vec<String> = stmt
+            .query_map(params![0, 1], |row| row.get(3))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        details.join("\n")
+    }
+
+    /// 100k tasks spread over roughly two years, a session per ten tasks and a
+    /// deliverable per four
+    fn fixture() -> Connection {
+        let mut conn = db();
+        let tx = conn.transaction().unwrap();
+        {
+            let mut session = tx
+                .prepare("INSERT INTO sessions (id, prompt, created_at) VALUES (?1, 'p', datetime(?2, 'unixepoch'))")
+                .unwrap();
+            let mut task = tx
+                .prepare(
+                    "INSERT INTO tasks (id, prompt, status, cost, session_id, created_at)
+                     VALUES (?1, 'p', ?2, 0.01, ?3, datetime(?4, 'unixepoch'))",
+                )
+                .unwrap();
+            let mut file = tx
+                .prepare(
+                    "INSERT INTO files (task_id, name, type, path, created_at)
+                     VALUES (?1, 'out.md', 'document', '/tmp/out.md', datetime(?2, 'unixepoch'))",
+                )
+                .unwrap();
+            let start = 1_700_000_000i64;
+            for i in 0..100_000i64 {
+                let at = start + i * 631;
+                let session_id = format!("s{}", i / 10);
+                if i % 10 == 0 {
+                    session.execute(params![session_id, at]).unwrap();
+                }
+                let status = if i % 7 == 0 { "error" } else { "completed" };
+                task.execute(params![format!("t{}", i), status, session_id, at]).unwrap();
+                if i % 4 == 0 {
+                    file.execute(params![format!("t{}", i), at]).unwrap();
+                }
+            }
+        }
+        tx.commit().unwrap();
+        conn.execute_batch("ANALYZE").unwrap();
+        conn
+    }
+
+    #[test]
+    fn inserts_fill_the_epoch_columns() {
+        let conn = db();
+        conn.execute(
+            "INSERT INTO tasks (id, prompt, created_at) VALUES ('t1', 'p', '2024-03-01 12:00:00')",
+            [],
+        )
+        .unwrap();
+        let epoch: i64 = conn
+            .query_row("SELECT created_epoch FROM tasks WHERE id = 't1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(epoch, 1_709_294_400);
+    }
+
+    #[test]
+    fn days_split_at_local_midnight() {
+        let conn = db();
+        // 23:30 and 00:30 in UTC+2 are 21:30 and 22:30 UTC on the same UTC day
+        conn.execute_batch(
+            "INSERT INTO tasks (id, prompt, status, created_at) VALUES
+                ('late', 'p', 'completed', '2024-03-01 21:30:00'),
+                ('early', 'p', 'error', '2024-03-01 22:30:00');",
+        )
+        .unwrap();
+        let tz = FixedOffset::east_opt(2 * 3600).unwrap();
+        let result = buckets(&conn, &tz, date("2024-03-01"), date("2024-03-02")).unwrap();
+        let days: Vec<(&str, i64, i64)> =
+            result.buckets.iter().map(|b| (b.date.as_str(), b.tasks, b.failed)).collect();
+        assert_eq!(days, vec![("2024-03-01", 1, 0), ("2024-03-02", 1, 1)]);
+        assert_eq!(result.first_date.as_deref(), Some("2024-03-01"));
+    }
+
+    #[test]
+    fn day_pages_interleave_events_without_gaps() {
+        let conn = db();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, prompt, created_at) VALUES ('s1', 'start', '2024-03-01 09:00:00');
+             INSERT INTO tasks (id, prompt, status, session_id, created_at) VALUES
+                ('a', 'first', 'completed', 's1', '2024-03-01 09:00:00'),
+                ('b', 'second', 'error', 's1', '2024-03-01 09:00:00'),
+                ('c', 'third', 'completed', 's1', '2024-03-01 10:00:00');
+             INSERT INTO files (task_id, name, type, path, created_at) VALUES
+                ('a', 'report.md', 'document', '/tmp/r', '2024-03-01 09:30:00'),
+                ('a', 'shot.png', 'image', '/tmp/s', '2024-03-01 09:30:00');",
+        )
+        .unwrap();
+        let mut kinds = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = day(&conn, &Utc, date("2024-03-01"), cursor.as_deref(), 2).unwrap();
+            kinds.extend(page.items.into_iter().map(|item| item.kind));
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(kinds, vec!["session_created", "task", "task_failed", "deliverable", "task"]);
+    }
+
+    #[test]
+    fn bucket_queries_are_index_only_on_large_histories() {
+        let conn = fixture();
+        for (sql, index) in [
+            (TASK_STATS_SQL, "USING COVERING INDEX idx_tasks_timeline"),
+            (SESSION_COUNT_SQL, "USING COVERING INDEX idx_sessions_timeline"),
+            (DELIVERABLE_COUNT_SQL, "USING COVERING INDEX idx_files_timeline"),
+        ] {
+            let plan = plan(&conn, sql);
+            assert!(plan.contains(index), "expected {} in:\n{}", index, plan);
+        }
+
+        // A month of buckets on the busiest stretch of the fixture; generous enough
+        // for debug builds on slow CI machines
+        let started = Instant::now();
+        let result = buckets(&conn, &Utc, date("2023-11-15"), date("2023-12-14")).unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(result.buckets.len(), 30);
+        assert!(result.buckets.iter().all(|b| b.tasks > 100));
+        assert!(elapsed.as_millis() < 250, "bucket query took {:?}", elapsed);
+
+        let page = day(&conn, &Utc, date("2023-11-20"), None, 50).unwrap();
+        assert_eq!(page.items.len(), 50);
+        assert!(page.next_cursor.is_some());
+    }
+}
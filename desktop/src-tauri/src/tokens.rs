@@ -0,0 +1,354 @@
+// Local token and cost estimation for prompts and their attachments.
+//
+// OpenAI-style models are counted exactly with tiktoken BPE; everything else uses a
+// language-aware characters-per-token heuristic (CJK text is close to one token per
+// character, Latin text closer to four characters per token). Prices and context
+// windows come from the embedded `model_pricing.json`, with per-model overrides
+// read from the `model_pricing_overrides` setting.
+
+use crate::{db, settings};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tauri::{AppHandle, Manager};
+use tiktoken_rs::CoreBPE;
+
+const EMBEDDED_PRICING: &str = include_str!("model_pricing.json");
+const PRICING_OVERRIDES_KEY: &str = "model_pricing_overrides";
+// Text attachments beyond this are sampled and extrapolated
+const MAX_TEXT_ATTACHMENT_BYTES: u64 = 2 * 1024 * 1024;
+const MAX_CACHE_ENTRIES: usize = 512;
+const LATIN_CHARS_PER_TOKEN: f64 = 3.8;
+const CJK_TOKENS_PER_CHAR: f64 = 1.2;
+// Relative error applied to the cost range for heuristic counts
+const HEURISTIC_ERROR: f64 = 0.2;
+const BPE_ERROR: f64 = 0.02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenizerKind {
+    Claude,
+    Cl100k,
+    O200k,
+    Heuristic,
+}
+
+impl TokenizerKind {
+    fn is_exact(self) -> bool {
+        matches!(self, TokenizerKind::Cl100k | TokenizerKind::O200k)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub tokenizer: TokenizerKind,
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+    pub context_window: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentKind {
+    Text,
+    Image,
+    Binary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentEstimate {
+    pub file_id: i64,
+    pub name: String,
+    pub kind: AttachmentKind,
+    /// `None` when the attachment can't be counted (binary or unreadable)
+    pub tokens: Option<u64>,
+    /// True when only a prefix was tokenized and the rest extrapolated
+    pub sampled: bool,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenEstimate {
+    pub model: Option<String>,
+    pub tokenizer: TokenizerKind,
+    pub exact: bool,
+    pub prompt_tokens: u64,
+    pub attachment_tokens: u64,
+    pub total_tokens: u64,
+    pub attachments: Vec<AttachmentEstimate>,
+    pub uncountable_attachments: u32,
+    pub context_window: Option<u64>,
+    /// Input-side cost range in USD; output tokens are not included
+    pub cost_low_usd: Option<f64>,
+    pub cost_high_usd: Option<f64>,
+}
+
+/// Attachment estimates keyed by path, size, mtime and tokenizer
+#[derive(Default)]
+pub struct TokenCache(Mutex<HashMap<String, AttachmentEstimate>>);
+
+fn embedded_pricing() -> &'static BTreeMap<String, ModelPricing> {
+    static TABLE: OnceLock<BTreeMap<String, ModelPricing>> = OnceLock::new();
+    TABLE.get_or_init(|| serde_json::from_str(EMBEDDED_PRICING).expect("model_pricing.json is valid"))
+}
+
+fn bpe(kind: TokenizerKind) -> Option<&'static CoreBPE> {
+    static CL100K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    static O200K: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    match kind {
+        TokenizerKind::Cl100k => CL100K.get_or_init(|| tiktoken_rs::cl100k_base().ok()).as_ref(),
+        TokenizerKind::O200k => O200K.get_or_init(|| tiktoken_rs::o200k_base().ok()).as_ref(),
+        _ => None,
+    }
+}
+
+/// Embedded prices merged with user overrides from settings
+pub(crate) fn pricing_table(conn: &rusqlite::Connection) -> BTreeMap<String, ModelPricing> {
+    let mut table = embedded_pricing().clone();
+    let overrides: Option<BTreeMap<String, ModelPricing>> =
+        settings::get(conn, PRICING_OVERRIDES_KEY).unwrap_or(None);
+    for (model, pricing) in overrides.unwrap_or_default() {
+        table.insert(normalize_model(&model), pricing);
+    }
+    table
+}
+
+/// `anthropic/claude-sonnet-4.5` -> `claude-sonnet-4-5`
+fn normalize_model(hint: &str) -> String {
+    hint.rsplit('/')
+        .next()
+        .unwrap_or(hint)
+        .trim()
+        .to_lowercase()
+        .replace(['.', '_'], "-")
+}
+
+/// Find the longest table key that prefixes the model name
+pub(crate) fn lookup_model<'a>(
+    table: &'a BTreeMap<String, ModelPricing>,
+    hint: &str,
+) -> Option<(&'a String, &'a ModelPricing)> {
+    let model = normalize_model(hint);
+    table
+        .iter()
+        .filter(|(key, _)| model.starts_with(key.as_str()))
+        .max_by_key(|(key, _)| key.len())
+}
+
+fn is_cjk(ch: char) -> bool {
+    matches!(ch as u32,
+        0x3040..=0x30FF      // Hiragana, Katakana
+        | 0x3400..=0x4DBF    // CJK Extension A
+        | 0x4E00..=0x9FFF    // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF    // Hangul syllables
+        | 0xF900..=0xFAFF    // CJK Compatibility Ideographs
+        | 0xFF00..=0xFFEF    // Full-width forms
+        | 0x20000..=0x2FA1F) // Supplementary ideographs
+}
+
+fn heuristic_tokens(text: &str) -> u64 {
+    let (mut cjk, mut other) = (0u64, 0u64);
+    for ch in text.chars() {
+        if is_cjk(ch) {
+            cjk += 1;
+        } else {
+            other += 1;
+        }
+    }
+    (cjk as f64 * CJK_TOKENS_PER_CHAR + other as f64 / LATIN_CHARS_PER_TOKEN).ceil() as u64
+}
+
+/// Count tokens in `text` with the given tokenizer
+pub(crate) fn count_text(kind: TokenizerKind, text: &str) -> u64 {
+    match bpe(kind) {
+        Some(bpe) => bpe.encode_with_special_tokens(text).len() as u64,
+        None => heuristic_tokens(text),
+    }
+}
+
+/// Image token cost from its pixel size, following each vendor's published formula
+pub(crate) fn image_tokens(kind: TokenizerKind, width: u32, height: u32) -> u64 {
+    let (w, h) = (width as f64, height as f64);
+    match kind {
+        TokenizerKind::Cl100k | TokenizerKind::O200k => {
+            // Fit within 2048x2048, shortest side to 768, then 170 tokens per 512px tile
+            let fit = (2048.0 / w.max(h)).min(1.0);
+            let (w, h) = (w * fit, h * fit);
+            let shrink = (768.0 / w.min(h)).min(1.0);
+            let tiles = ((w * shrink) / 512.0).ceil() * ((h * shrink) / 512.0).ceil();
+            85 + (tiles as u64) * 170
+        }
+        _ => {
+            // Long edge capped at 1568px, then roughly one token per 750 pixels
+            let fit = (1568.0 / w.max(h)).min(1.0);
+            ((w * fit) * (h * fit) / 750.0).ceil() as u64
+        }
+    }
+}
+
+fn estimate_text_file(kind: TokenizerKind, path: &Path, size: u64) -> Result<(u64, bool), String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    file.take(MAX_TEXT_ATTACHMENT_BYTES)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    let text = String::from_utf8_lossy(&bytes);
+    let tokens = count_text(kind, &text);
+    if size > bytes.len() as u64 && !bytes.is_empty() {
+        let scaled = tokens as f64 * size as f64 / bytes.len() as f64;
+        return Ok((scaled.ceil() as u64, true));
+    }
+    Ok((tokens, false))
+}
+
+fn estimate_attachment(kind: TokenizerKind, file_id: i64, name: String, file_type: &str, path: &Path) -> AttachmentEstimate {
+    let mut estimate = AttachmentEstimate {
+        file_id,
+        name,
+        kind: AttachmentKind::Binary,
+        tokens: None,
+        sampled: false,
+        note: None,
+    };
+    let size = match std::fs::metadata(path) {
+        Ok(meta) => meta.len(),
+        Err(e) => {
+            estimate.note = Some(format!("File unavailable: {}", e));
+            return estimate;
+        }
+    };
+    match file_type {
+        "image" => {
+            estimate.kind = AttachmentKind::Image;
+            match image::image_dimensions(path) {
+                Ok((w, h)) => estimate.tokens = Some(image_tokens(kind, w, h)),
+                Err(e) => estimate.note = Some(format!("Unreadable image: {}", e)),
+            }
+        }
+        "text" | "code" | "website" => {
+            estimate.kind = AttachmentKind::Text;
+            match estimate_text_file(kind, path, size) {
+                Ok((tokens, sampled)) => {
+                    estimate.tokens = Some(tokens);
+                    estimate.sampled = sampled;
+                }
+                Err(e) => estimate.note = Some(format!("Unreadable file: {}", e)),
+            }
+        }
+        _ => estimate.note = Some("Binary attachments can't be counted locally".to_string()),
+    }
+    estimate
+}
+
+fn cache_key(kind: TokenizerKind, path: &Path) -> Option<String> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mtime = meta
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_millis();
+    Some(format!("{:?}:{}:{}:{}", kind, path.display(), meta.len(), mtime))
+}
+
+fn estimate(
+    app: &AppHandle,
+    text: &str,
+    attachments: &[i64],
+    model_hint: Option<&str>,
+) -> Result<TokenEstimate, String> {
+    let conn = db::open(app)?;
+    let table = pricing_table(&conn);
+    let matched = model_hint.and_then(|hint| lookup_model(&table, hint));
+    let tokenizer = matched
+        .map(|(_, pricing)| pricing.tokenizer)
+        .unwrap_or(TokenizerKind::Heuristic);
+
+    let prompt_tokens = count_text(tokenizer, text);
+    let cache = app.state::<TokenCache>();
+    let mut estimates = Vec::with_capacity(attachments.len());
+    for &file_id in attachments {
+        let row: Option<(String, String, String)> = conn
+            .query_row(
+                "SELECT name, type, path FROM files WHERE id = ?1",
+                [file_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some((name, file_type, path)) = row else {
+            return Err(format!("Attachment {} not found", file_id));
+        };
+        let path = Path::new(&path);
+        let key = cache_key(tokenizer, path);
+        let cached = key
+            .as_ref()
+            .and_then(|k| cache.0.lock().ok()?.get(k).cloned());
+        let estimate = match cached {
+            Some(mut hit) => {
+                hit.file_id = file_id;
+                hit
+            }
+            None => {
+                let fresh = estimate_attachment(tokenizer, file_id, name, &file_type, path);
+                if let (Some(key), Ok(mut map)) = (key, cache.0.lock()) {
+                    if map.len() >= MAX_CACHE_ENTRIES {
+                        map.clear();
+                    }
+                    map.insert(key, fresh.clone());
+                }
+                fresh
+            }
+        };
+        estimates.push(estimate);
+    }
+
+    let attachment_tokens: u64 = estimates.iter().filter_map(|e| e.tokens).sum();
+    let uncountable = estimates.iter().filter(|e| e.tokens.is_none()).count() as u32;
+    let sampled = estimates.iter().any(|e| e.sampled);
+    let total_tokens = prompt_tokens + attachment_tokens;
+    let exact = tokenizer.is_exact() && uncountable == 0 && !sampled;
+    let error = if tokenizer.is_exact() { BPE_ERROR } else { HEURISTIC_ERROR };
+    let cost = |factor: f64| {
+        matched.map(|(_, pricing)| total_tokens as f64 * factor * pricing.input_per_mtok / 1_000_000.0)
+    };
+
+    Ok(TokenEstimate {
+        model: matched.map(|(key, _)| key.clone()),
+        tokenizer,
+        exact,
+        prompt_tokens,
+        attachment_tokens,
+        total_tokens,
+        attachments: estimates,
+        uncountable_attachments: uncountable,
+        context_window: matched.map(|(_, pricing)| pricing.context_window),
+        cost_low_usd: cost(1.0 - error),
+        cost_high_usd: cost(1.0 + error),
+    })
+}
+
+/// Estimate prompt plus attachment tokens and the input cost for a model
+#[tauri::command]
+pub async fn estimate_tokens(
+    app: AppHandle,
+    text: String,
+    attachments: Vec<i64>,
+    model_hint: Option<String>,
+) -> Result<TokenEstimate, String> {
+    let handle = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        estimate(&handle, &text, &attachments, model_hint.as_deref())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Effective pricing table (embedded defaults plus user overrides)
+#[tauri::command]
+pub async fn get_model_pricing(app: AppHandle) -> Result<BTreeMap<String, ModelPricing>, String> {
+    db::with_conn(&app, |conn| Ok(pricing_table(conn))).await
+}
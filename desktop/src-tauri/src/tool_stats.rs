@@ -0,0 +1,260 @@
+// Per-tool statistics across task history.
+//
+// Tool calls and results are paired by tool_use_id into `tool_invocations`. The
+// frontend inserts messages through the sql plugin, so pairing happens in SQLite
+// triggers (see the create_tool_invocations migration); `backfill_tool_invocations`
+// fills the table for messages recorded before the migration.
+// Message timestamps have one-second resolution, so latencies do too.
+
+use crate::message_types::MessageType;
+use crate::{db, jobs};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use tauri::AppHandle;
+
+const SQLITE_TIME: &str = "%Y-%m-%d %H:%M:%S";
+
+// Keep the failure shapes in sync with the tool_invocations_on_result trigger
+fn backfill_steps() -> [(&'static str, String); 4] {
+    let tool_use = MessageType::ToolUse.as_str();
+    let tool_result = MessageType::ToolResult.as_str();
+    [
+        (
+            "calls",
+            format!(
+                "INSERT OR IGNORE INTO tool_invocations (tool_use_id, task_id, tool_name, call_message_id, called_at)
+                 SELECT tool_use_id, task_id, tool_name, MIN(id), MIN(created_at)
+                 FROM messages WHERE type = '{tool_use}' AND tool_use_id IS NOT NULL
+                 GROUP BY tool_use_id"
+            ),
+        ),
+        (
+            "unpaired results",
+            format!(
+                "INSERT OR IGNORE INTO tool_invocations (tool_use_id, task_id)
+                 SELECT DISTINCT tool_use_id, task_id
+                 FROM messages WHERE type = '{tool_result}' AND tool_use_id IS NOT NULL"
+            ),
+        ),
+        (
+            "results",
+            format!(
+                "UPDATE tool_invocations
+                 SET (result_message_id, completed_at, is_error) = (
+                     SELECT m.id, m.created_at,
+                            (m.error_message IS NOT NULL
+                             OR m.subtype = 'error'
+                             OR m.tool_output LIKE 'Error:%'
+                             OR m.tool_output LIKE '%<tool_use_error>%')
+                     FROM messages m
+                     WHERE m.type = '{tool_result}' AND m.tool_use_id = tool_invocations.tool_use_id
+                     ORDER BY m.id LIMIT 1)
+                 WHERE completed_at IS NULL
+                   AND EXISTS (SELECT 1 FROM messages m
+                               WHERE m.type = '{tool_result}' AND m.tool_use_id = tool_invocations.tool_use_id)"
+            ),
+        ),
+        (
+            "latencies",
+            "UPDATE tool_invocations
+             SET latency_ms = CAST((julianday(completed_at) - julianday(called_at)) * 86400000 AS INTEGER)
+             WHERE latency_ms IS NULL AND called_at IS NOT NULL AND completed_at IS NOT NULL"
+                .to_string(),
+        ),
+    ]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatsRange {
+    /// RFC 3339 timestamps; `to` is exclusive
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ToolStatsGroup {
+    Tool,
+    ToolAndTask,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PeriodStats {
+    pub invocations: u32,
+    pub errors: u32,
+    pub orphaned: u32,
+    pub error_rate: f64,
+    pub p50_ms: Option<i64>,
+    pub p90_ms: Option<i64>,
+    pub p99_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolStat {
+    pub tool_name: String,
+    pub task_id: Option<String>,
+    #[serde(flatten)]
+    pub current: PeriodStats,
+    pub previous: PeriodStats,
+    pub invocations_delta: i64,
+    pub error_rate_delta: f64,
+    pub p50_delta_ms: Option<i64>,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    invocations: u32,
+    errors: u32,
+    orphaned: u32,
+    latencies: Vec<i64>,
+}
+
+impl Accumulator {
+    fn finish(mut self) -> PeriodStats {
+        self.latencies.sort_unstable();
+        let pct = |p: f64| -> Option<i64> {
+            if self.latencies.is_empty() {
+                return None;
+            }
+            // Nearest-rank percentile
+            let rank = ((p * self.latencies.len() as f64).ceil() as usize).max(1);
+            Some(self.latencies[rank - 1])
+        };
+        let completed = self.invocations - self.orphaned;
+        PeriodStats {
+            invocations: self.invocations,
+            errors: self.errors,
+            orphaned: self.orphaned,
+            error_rate: if completed > 0 {
+                self.errors as f64 / completed as f64
+            } else {
+                0.0
+            },
+            p50_ms: pct(0.50),
+            p90_ms: pct(0.90),
+            p99_ms: pct(0.99),
+        }
+    }
+}
+
+type GroupKey = (String, Option<String>);
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| format!("Invalid timestamp '{}': {}", value, e))
+}
+
+fn collect(
+    conn: &rusqlite::Connection,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    group_by: ToolStatsGroup,
+) -> Result<BTreeMap<GroupKey, Accumulator>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT tool_name, task_id, latency_ms, is_error, completed_at IS NULL
+             FROM tool_invocations
+             WHERE tool_name IS NOT NULL AND called_at >= ?1 AND called_at < ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![from.format(SQLITE_TIME).to_string(), to.format(SQLITE_TIME).to_string()],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                    row.get::<_, bool>(3)?,
+                    row.get::<_, bool>(4)?,
+                ))
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut groups: BTreeMap<GroupKey, Accumulator> = BTreeMap::new();
+    for row in rows {
+        let (tool_name, task_id, latency, is_error, orphaned) = row.map_err(|e| e.to_string())?;
+        let key = match group_by {
+            ToolStatsGroup::Tool => (tool_name, None),
+            ToolStatsGroup::ToolAndTask => (tool_name, Some(task_id)),
+        };
+        let acc = groups.entry(key).or_default();
+        acc.invocations += 1;
+        if orphaned {
+            acc.orphaned += 1;
+            continue;
+        }
+        if is_error {
+            acc.errors += 1;
+        }
+        if let Some(latency) = latency {
+            acc.latencies.push(latency.max(0));
+        }
+    }
+    Ok(groups)
+}
+
+/// Tool call counts, error rates and latency percentiles for a time range, with
+/// deltas against the previous period of the same length
+#[tauri::command]
+pub async fn get_tool_stats(
+    app: AppHandle,
+    range: StatsRange,
+    group_by: ToolStatsGroup,
+) -> Result<Vec<ToolStat>, String> {
+    let from = parse_time(&range.from)?;
+    let to = parse_time(&range.to)?;
+    if to <= from {
+        return Err("Range end must be after its start".to_string());
+    }
+    let previous_from = from - (to - from);
+
+    db::with_conn(&app, move |conn| {
+        let current = collect(conn, from, to, group_by)?;
+        let mut previous = collect(conn, previous_from, from, group_by)?;
+        let mut stats: Vec<ToolStat> = current
+            .into_iter()
+            .map(|(key, acc)| {
+                let current = acc.finish();
+                let previous = previous.remove(&key).map(Accumulator::finish).unwrap_or_default();
+                ToolStat {
+                    invocations_delta: current.invocations as i64 - previous.invocations as i64,
+                    error_rate_delta: current.error_rate - previous.error_rate,
+                    p50_delta_ms: current.p50_ms.zip(previous.p50_ms).map(|(a, b)| a - b),
+                    tool_name: key.0,
+                    task_id: key.1,
+                    current,
+                    previous,
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| b.current.invocations.cmp(&a.current.invocations));
+        Ok(stats)
+    })
+    .await
+}
+
+/// Populate `tool_invocations` from existing messages as a background job
+#[tauri::command]
+pub fn backfill_tool_invocations(app: AppHandle) -> u64 {
+    jobs::spawn(&app, "backfill_tool_invocations", |ctx| {
+        let conn = db::open(ctx.app())?;
+        let steps = backfill_steps();
+        let total = steps.len() as u64;
+        let mut affected = BTreeMap::new();
+        for (index, (step, sql)) in steps.iter().enumerate() {
+            if ctx.is_cancelled() {
+                return Err("Cancelled".to_string());
+            }
+            ctx.progress(index as u64, total, Some(step.to_string()));
+            let rows = conn.execute(sql, []).map_err(|e| e.to_string())?;
+            affected.insert(*step, rows);
+        }
+        ctx.progress(total, total, None);
+        Ok(json!(affected))
+    })
+}
@@ -0,0 +1,510 @@
+// Task trash: soft deletion, restore, and retention.
+//
+// `trash_task` sets `tasks.trashed_at` and records what restoring needs in
+// `restore_metadata`: the session the task was in and its tags. It also records
+// `trash_bytes`, the size of the task's messages and attachments at that moment,
+// so the trash total is a sum over trashed rows and never a rescan.
+//
+// Retention is a maximum age and a maximum total size. The hourly pass deletes
+// whatever is past either limit, oldest first. Permanent deletions cascade to
+// messages, files and attachments. They are recorded through
+// `integrity::record_deletion` so snapshots can attribute the drop.
+//
+// Restoring into a session that no longer exists puts the task in a session named
+// "Restored items", created if needed.
+
+use crate::{background, db, integrity, routing, settings};
+use chrono::{Duration as ChronoDuration, NaiveDateTime, Utc};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::AppHandle;
+
+const MAX_AGE_KEY: &str = "trash_max_age_days";
+const MAX_BYTES_KEY: &str = "trash_max_bytes";
+const DEFAULT_MAX_AGE_DAYS: u32 = 30;
+const DEFAULT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+const RESTORED_SESSION: &str = "Restored items";
+const SUMMARY_UPCOMING: usize = 10;
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const ACTIVITY: background::Activity = background::Activity {
+    name: "trash_retention",
+    interval: Duration::from_secs(60 * 60),
+    coalescable: true,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TagRef {
+    pub name: String,
+    pub color: Option<String>,
+}
+
+/// Where a trashed task came from
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RestoreMetadata {
+    pub session_id: Option<String>,
+    pub session_prompt: Option<String>,
+    pub task_index: Option<i64>,
+    pub tags: Vec<TagRef>,
+}
+
+/// Retention limits; 0 turns a limit off
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TrashLimits {
+    pub max_age_days: u32,
+    pub max_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PurgeReason {
+    Age,
+    Size,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledPurge {
+    pub task_id: String,
+    pub prompt: String,
+    pub trashed_at: String,
+    pub bytes: i64,
+    /// When the retention pass will delete it, `YYYY-MM-DD HH:MM:SS` UTC
+    pub purge_at: String,
+    pub reason: PurgeReason,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrashSummary {
+    pub count: i64,
+    pub bytes: i64,
+    pub limits: TrashLimits,
+    /// Soonest first
+    pub upcoming: Vec<ScheduledPurge>,
+}
+
+fn load_limits(conn: &Connection) -> Result<TrashLimits, String> {
+    Ok(TrashLimits {
+        max_age_days: settings::get_or(conn, MAX_AGE_KEY, DEFAULT_MAX_AGE_DAYS)?,
+        max_bytes: settings::get_or(conn, MAX_BYTES_KEY, DEFAULT_MAX_BYTES)?,
+    })
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+/// Message and attachment bytes held by one task
+fn task_bytes(conn: &Connection, task_id: &str) -> Result<i64, String> {
+    conn.query_row(
+        "SELECT (SELECT COALESCE(SUM(COALESCE(length(CAST(content AS BLOB)), 0)
+                                   + COALESCE(length(CAST(tool_input AS BLOB)), 0)
+                                   + COALESCE(length(CAST(tool_output AS BLOB)), 0)), 0)
+                 FROM messages WHERE task_id = ?1)
+              + (SELECT COALESCE(SUM(size), 0) FROM attachments WHERE task_id = ?1)
+              + (SELECT COALESCE(length(CAST(prompt AS BLOB)), 0) FROM tasks WHERE id = ?1)",
+        [task_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn trash(conn: &mut Connection, task_id: &str) -> Result<(), String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let found: Option<(Option<String>, Option<i64>, Option<String>)> = tx
+        .query_row(
+            "SELECT session_id, task_index, trashed_at FROM tasks WHERE id = ?1",
+            [task_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some((session_id, task_index, trashed_at)) = found else {
+        return Err(format!("Task {} not found", task_id));
+    };
+    if trashed_at.is_some() {
+        return Ok(());
+    }
+    let session_prompt: Option<String> = match &session_id {
+        Some(id) => tx
+            .query_row("SELECT prompt FROM sessions WHERE id = ?1", [id], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?,
+        None => None,
+    };
+    let tags = tx
+        .prepare(
+            "SELECT t.name, t.color FROM task_tags tt JOIN tags t ON t.id = tt.tag_id
+             WHERE tt.task_id = ?1 ORDER BY t.name",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map([task_id], |row| Ok(TagRef { name: row.get(0)?, color: row.get(1)? }))?
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(|e| e.to_string())?;
+    let metadata = RestoreMetadata {
+        session_id,
+        session_prompt,
+        task_index,
+        tags,
+    };
+    let encoded = serde_json::to_string(&metadata).map_err(|e| e.to_string())?;
+    let bytes = task_bytes(&tx, task_id)?;
+    tx.execute(
+        "UPDATE tasks SET trashed_at = ?2, restore_metadata = ?3, trash_bytes = ?4 WHERE id = ?1",
+        params![task_id, now().format(TIME_FORMAT).to_string(), encoded, bytes],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Put a trashed task back; returns the session it ended up in
+fn restore(conn: &mut Connection, task_id: &str) -> Result<Option<String>, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let found: Option<(Option<String>, Option<String>)> = tx
+        .query_row(
+            "SELECT trashed_at, restore_metadata FROM tasks WHERE id = ?1",
+            [task_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let Some((Some(_), raw)) = found else {
+        return Err(format!("Task {} is not in the trash", task_id));
+    };
+    let metadata: RestoreMetadata = raw
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default();
+
+    let mut session_id = metadata.session_id.clone();
+    let mut task_index = metadata.task_index;
+    if let Some(id) = &metadata.session_id {
+        let exists: bool = tx
+            .query_row("SELECT EXISTS(SELECT 1 FROM sessions WHERE id = ?1)", [id], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        if !exists {
+            let (restored, _) = routing::session_by_name(&tx, RESTORED_SESSION)?;
+            let next: i64 = tx
+                .query_row(
+                    "SELECT COALESCE(MAX(task_index), 0) + 1 FROM tasks WHERE session_id = ?1 AND id != ?2",
+                    params![restored, task_id],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            tx.execute(
+                "UPDATE sessions SET task_count = MAX(task_count, ?1), updated_at = datetime('now') WHERE id = ?2",
+                params![next, restored],
+            )
+            .map_err(|e| e.to_string())?;
+            session_id = Some(restored);
+            task_index = Some(next);
+        }
+    }
+    tx.execute(
+        "UPDATE tasks SET trashed_at = NULL, restore_metadata = NULL, trash_bytes = NULL,
+                          session_id = ?2, task_index = COALESCE(?3, task_index)
+         WHERE id = ?1",
+        params![task_id, session_id, task_index],
+    )
+    .map_err(|e| e.to_string())?;
+    for tag in &metadata.tags {
+        tx.execute(
+            "INSERT OR IGNORE INTO tags (name, color) VALUES (?1, ?2)",
+            params![tag.name, tag.color],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+            params![task_id, tag.name],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(session_id)
+}
+
+/// Every trashed task with the time retention will delete it, soonest first.
+/// Size is enforced oldest first: the oldest items go now until the rest fit.
+fn schedule(conn: &Connection, limits: TrashLimits, now: NaiveDateTime) -> Result<Vec<ScheduledPurge>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, substr(prompt, 1, 200), trashed_at, COALESCE(trash_bytes, 0) FROM tasks
+             WHERE trashed_at IS NOT NULL ORDER BY trashed_at, id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut over = if limits.max_bytes > 0 {
+        rows.iter().map(|r| r.3).sum::<i64>() - limits.max_bytes as i64
+    } else {
+        0
+    };
+    let mut scheduled = Vec::with_capacity(rows.len());
+    for (task_id, prompt, trashed_at, bytes) in rows {
+        let trashed = NaiveDateTime::parse_from_str(&trashed_at, TIME_FORMAT).unwrap_or(now);
+        let (purge_at, reason) = if over > 0 {
+            over -= bytes;
+            (now, PurgeReason::Size)
+        } else if limits.max_age_days > 0 {
+            let expires = trashed + ChronoDuration::days(limits.max_age_days as i64);
+            (expires.max(now), PurgeReason::Age)
+        } else {
+            continue;
+        };
+        scheduled.push(ScheduledPurge {
+            task_id,
+            prompt,
+            trashed_at,
+            bytes,
+            purge_at: purge_at.format(TIME_FORMAT).to_string(),
+            reason,
+        });
+    }
+    scheduled.sort_by(|a, b| a.purge_at.cmp(&b.purge_at).then_with(|| a.trashed_at.cmp(&b.trashed_at)));
+    Ok(scheduled)
+}
+
+/// Delete trashed tasks for good, with everything that cascades from them
+fn purge(conn: &mut Connection, task_ids: &[String]) -> Result<u32, String> {
+    if task_ids.is_empty() {
+        return Ok(0);
+    }
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let placeholders = vec!["?"; task_ids.len()].join(", ");
+    let count = |sql: &str| -> Result<u32, String> {
+        tx.query_row(&sql.replace("{ids}", &placeholders), params_from_iter(task_ids), |row| row.get(0))
+            .map_err(|e| e.to_string())
+    };
+    let messages = count(
+        "SELECT COUNT(*) FROM messages WHERE task_id IN
+             (SELECT id FROM tasks WHERE id IN ({ids}) AND trashed_at IS NOT NULL)",
+    )?;
+    let files = count(
+        "SELECT COUNT(*) FROM files WHERE task_id IN
+             (SELECT id FROM tasks WHERE id IN ({ids}) AND trashed_at IS NOT NULL)",
+    )?;
+    let tasks = tx
+        .execute(
+            &format!("DELETE FROM tasks WHERE id IN ({}) AND trashed_at IS NOT NULL", placeholders),
+            params_from_iter(task_ids),
+        )
+        .map_err(|e| e.to_string())? as u32;
+    integrity::record_deletion(&tx, "tasks", "trash", tasks)?;
+    integrity::record_deletion(&tx, "messages", "trash", messages)?;
+    integrity::record_deletion(&tx, "files", "trash", files)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(tasks)
+}
+
+/// Delete whatever retention has scheduled for now or earlier
+fn enforce(conn: &mut Connection, now: NaiveDateTime) -> Result<Vec<String>, String> {
+    let limits = load_limits(conn)?;
+    let due_before = now.format(TIME_FORMAT).to_string();
+    let due: Vec<String> = schedule(conn, limits, now)?
+        .into_iter()
+        .filter(|item| item.purge_at <= due_before)
+        .map(|item| item.task_id)
+        .collect();
+    purge(conn, &due)?;
+    Ok(due)
+}
+
+fn summary(conn: &Connection, now: NaiveDateTime) -> Result<TrashSummary, String> {
+    let limits = load_limits(conn)?;
+    let (count, bytes) = conn
+        .query_row(
+            "SELECT COUNT(*), COALESCE(SUM(trash_bytes), 0) FROM tasks WHERE trashed_at IS NOT NULL",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+    let mut upcoming = schedule(conn, limits, now)?;
+    upcoming.truncate(SUMMARY_UPCOMING);
+    Ok(TrashSummary {
+        count,
+        bytes,
+        limits,
+        upcoming,
+    })
+}
+
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &ACTIVITY);
+        match db::open(&app).and_then(|mut conn| enforce(&mut conn, now())) {
+            Ok(purged) if !purged.is_empty() => {
+                println!("[Trash] Retention deleted {} task(s)", purged.len());
+                db::emit_change(&app, "tasks", "delete", purged);
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("[Trash] Retention pass failed: {}", e),
+        }
+    });
+}
+
+/// Move a task to the trash; the size limit applies right away
+#[tauri::command]
+pub async fn trash_task(app: AppHandle, task_id: String) -> Result<TrashSummary, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        trash(conn, &task_id)?;
+        db::emit_change(&handle, "tasks", "trash", vec![task_id]);
+        let purged = enforce(conn, now())?;
+        if !purged.is_empty() {
+            db::emit_change(&handle, "tasks", "delete", purged);
+        }
+        summary(conn, now())
+    })
+    .await
+}
+
+/// Take a task out of the trash with its session and tags; returns its session id
+#[tauri::command]
+pub async fn restore_task(app: AppHandle, task_id: String) -> Result<Option<String>, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let session_id = restore(conn, &task_id)?;
+        db::emit_change(&handle, "tasks", "restore", vec![task_id]);
+        Ok(session_id)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_trash_summary(app: AppHandle) -> Result<TrashSummary, String> {
+    db::with_conn(&app, |conn| summary(conn, now())).await
+}
+
+/// Permanently delete trashed tasks: the listed ones, or all of them
+#[tauri::command]
+pub async fn empty_trash(app: AppHandle, task_ids: Option<Vec<String>>) -> Result<u32, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let ids = match task_ids {
+            Some(ids) => ids,
+            None => conn
+                .prepare("SELECT id FROM tasks WHERE trashed_at IS NOT NULL")
+                .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>, _>>())
+                .map_err(|e| e.to_string())?,
+        };
+        let purged = purge(conn, &ids)?;
+        db::emit_change(&handle, "tasks", "delete", ids);
+        Ok(purged)
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn set_trash_limits(app: AppHandle, limits: TrashLimits) -> Result<TrashSummary, String> {
+    db::with_conn(&app, move |conn| {
+        settings::set(conn, MAX_AGE_KEY, &limits.max_age_days)?;
+        settings::set(conn, MAX_BYTES_KEY, &limits.max_bytes)?;
+        summary(conn, now())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        conn.pragma_update(None, "foreign_keys", "ON").unwrap();
+        conn.execute_batch(
+            "INSERT INTO sessions (id, prompt, task_count) VALUES ('s1', 'Research', 2);
+             INSERT INTO tasks (id, prompt, session_id, task_index) VALUES
+                ('a', 'first', 's1', 1), ('b', 'second', 's1', 2);
+             INSERT INTO messages (task_id, type, content) VALUES ('a', 'text', 'hello'), ('b', 'text', 'world!!');
+             INSERT INTO tags (name, color) VALUES ('urgent', 'red');
+             INSERT INTO task_tags (task_id, tag_id) VALUES ('a', 1);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, TIME_FORMAT).unwrap()
+    }
+
+    #[test]
+    fn restore_recreates_a_deleted_session_and_tags() {
+        let mut conn = db();
+        trash(&mut conn, "a").unwrap();
+        conn.execute_batch("DELETE FROM task_tags; DELETE FROM tags; DELETE FROM sessions WHERE id = 's1';")
+            .unwrap();
+
+        let session = restore(&mut conn, "a").unwrap().unwrap();
+        let prompt: String = conn
+            .query_row("SELECT prompt FROM sessions WHERE id = ?1", [&session], |row| row.get(0))
+            .unwrap();
+        assert_eq!(prompt, RESTORED_SESSION);
+        let (task_session, trashed): (String, Option<String>) = conn
+            .query_row("SELECT session_id, trashed_at FROM tasks WHERE id = 'a'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((task_session, trashed), (session, None));
+        let tag: String = conn
+            .query_row(
+                "SELECT t.name FROM task_tags tt JOIN tags t ON t.id = tt.tag_id WHERE tt.task_id = 'a'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tag, "urgent");
+    }
+
+    #[test]
+    fn retention_deletes_oldest_first_and_accounts_for_it() {
+        let mut conn = db();
+        trash(&mut conn, "a").unwrap();
+        trash(&mut conn, "b").unwrap();
+        conn.execute_batch(
+            "UPDATE tasks SET trashed_at = '2024-01-01 00:00:00' WHERE id = 'a';
+             UPDATE tasks SET trashed_at = '2024-01-20 00:00:00' WHERE id = 'b';",
+        )
+        .unwrap();
+        let bytes_b: i64 = conn
+            .query_row("SELECT trash_bytes FROM tasks WHERE id = 'b'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(bytes_b, ("second".len() + "world!!".len()) as i64);
+
+        // Only the age limit applies: `a` goes on Jan 31, `b` on Feb 19
+        let upcoming = summary(&conn, at("2024-01-25 00:00:00")).unwrap().upcoming;
+        let dates: Vec<(&str, &str)> = upcoming.iter().map(|s| (s.task_id.as_str(), s.purge_at.as_str())).collect();
+        assert_eq!(dates, vec![("a", "2024-01-31 00:00:00"), ("b", "2024-02-19 00:00:00")]);
+        assert!(enforce(&mut conn, at("2024-01-25 00:00:00")).unwrap().is_empty());
+
+        // A size cap smaller than the trash takes the oldest right away
+        settings::set(&conn, MAX_BYTES_KEY, &(bytes_b as u64)).unwrap();
+        assert_eq!(enforce(&mut conn, at("2024-01-25 00:00:00")).unwrap(), vec!["a".to_string()]);
+        let (tasks, messages): (i64, i64) = conn
+            .query_row(
+                "SELECT (SELECT COUNT(*) FROM tasks), (SELECT COUNT(*) FROM messages WHERE task_id = 'a')",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((tasks, messages), (1, 0));
+        let accounted: Vec<(String, i64)> = conn
+            .prepare("SELECT table_name, rows_removed FROM deletion_accounting WHERE subsystem = 'trash' ORDER BY table_name")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(accounted, vec![("messages".to_string(), 1), ("tasks".to_string(), 1)]);
+    }
+}
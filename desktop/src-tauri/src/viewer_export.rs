@@ -0,0 +1,650 @@
+// A session as one self-contained HTML file: a read-only mini version of the app
+// that opens in any browser, for handing to someone without the app.
+//
+// The file is the viewer bundle (`assets/session-viewer.js`, compiled into the
+// binary) plus the session as a gzipped, base64 JSON payload in a
+// `<script type="application/json">` element. The payload shape is `ViewerPayload`,
+// versioned by `VIEWER_VERSION`; the viewer reads `version` before anything else.
+//
+// Text goes through `redaction::redact_secrets`, and fields longer than
+// `MAX_FIELD_BYTES` are cut. Images attached to messages and text or image files in
+// the session's working set are inlined up to `MAX_INLINE_BYTES` each and
+// `INLINE_BUDGET` in total; anything else is listed by name only, and paths on this
+// machine are never included. Every cut is a warning in the export report. Nothing
+// time-dependent goes into the file, so exporting an unchanged session twice gives
+// identical bytes. The signed manifest sits beside the file (see `export_manifest`).
+
+use crate::message_types::MessageType;
+use crate::review_packet::escape;
+use crate::{db, export_manifest, redaction};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use flate2::read::GzDecoder;
+use flate2::{Compression, GzBuilder};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Read, Write};
+use std::path::Path;
+use tauri::AppHandle;
+
+const VIEWER_BUNDLE: &[u8] = include_bytes!("../assets/session-viewer.js");
+const PAYLOAD_ELEMENT: &str = "workany-viewer-payload";
+const VIEWER_SCHEMA: &str = "workany-viewer";
+/// Bump when `ViewerPayload` changes shape; the viewer refuses newer versions
+pub const VIEWER_VERSION: u32 = 1;
+
+/// Longest text field kept whole; the rest is cut with a marker
+const MAX_FIELD_BYTES: usize = 256 * 1024;
+/// Largest attachment inlined
+const MAX_INLINE_BYTES: usize = 2 * 1024 * 1024;
+/// Inlined attachment bytes across the whole export
+const INLINE_BUDGET: usize = 25 * 1024 * 1024;
+/// Largest payload before compression; beyond this the export fails
+const MAX_PAYLOAD_BYTES: usize = 100 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ViewerPayload {
+    pub schema: String,
+    pub version: u32,
+    pub session: ViewerSession,
+    pub tasks: Vec<ViewerTask>,
+    /// Attachments referenced by messages, then the session's working-set files
+    pub attachments: Vec<ViewerAttachment>,
+    /// Ids in `attachments` that are working-set files, in working-set order
+    pub session_files: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ViewerSession {
+    pub id: String,
+    pub prompt: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ViewerTask {
+    pub id: String,
+    pub task_index: Option<i64>,
+    pub prompt: String,
+    pub status: String,
+    pub cost: Option<f64>,
+    pub created_at: String,
+    pub messages: Vec<ViewerMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ViewerMessage {
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub kind: MessageType,
+    pub content: Option<String>,
+    pub tool_name: Option<String>,
+    pub tool_input: Option<String>,
+    pub tool_output: Option<String>,
+    pub error_message: Option<String>,
+    pub attachment_ids: Vec<String>,
+    /// Some field was cut at `MAX_FIELD_BYTES`
+    pub truncated: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ViewerAttachment {
+    pub id: String,
+    pub name: Option<String>,
+    pub mime_type: Option<String>,
+    pub size: u64,
+    /// Inlined image
+    pub data_uri: Option<String>,
+    /// Inlined text file
+    pub text: Option<String>,
+    /// Why the content was left out
+    pub omitted: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ViewerExportReport {
+    pub path: String,
+    pub bytes: u64,
+    /// Payload JSON before and after compression
+    pub payload_bytes: u64,
+    pub compressed_bytes: u64,
+    pub tasks: usize,
+    pub messages: usize,
+    pub attachments_inlined: usize,
+    pub attachments_omitted: usize,
+    /// Every field cut and attachment left out, in export order
+    pub warnings: Vec<String>,
+    /// `None` when the manifest couldn't be written
+    pub manifest_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ViewerValidation {
+    pub path: String,
+    pub valid: bool,
+    pub version: Option<u32>,
+    pub session_id: Option<String>,
+    pub tasks: usize,
+    pub messages: usize,
+    pub attachments: usize,
+    pub problems: Vec<String>,
+}
+
+/// Attachment inlining across one export
+#[derive(Default)]
+struct Inliner {
+    attachments: Vec<ViewerAttachment>,
+    seen: HashSet<String>,
+    inlined_bytes: usize,
+    warnings: Vec<String>,
+}
+
+impl Inliner {
+    /// Whether `size` more bytes may be inlined; records why not otherwise
+    fn admit(&mut self, id: &str, size: usize) -> Result<(), String> {
+        if size > MAX_INLINE_BYTES {
+            let reason = format!("larger than {} MB", MAX_INLINE_BYTES / (1024 * 1024));
+            self.warnings.push(format!("Attachment {} left out: {}", id, reason));
+            return Err(reason);
+        }
+        if self.inlined_bytes + size > INLINE_BUDGET {
+            let reason = "export size budget reached".to_string();
+            self.warnings.push(format!("Attachment {} left out: {}", id, reason));
+            return Err(reason);
+        }
+        self.inlined_bytes += size;
+        Ok(())
+    }
+
+    /// An entry of a message's `attachments` JSON; returns its id
+    fn message_attachment(&mut self, message_id: i64, index: usize, entry: &Value) -> String {
+        let text = |key: &str| entry.get(key).and_then(Value::as_str).filter(|s| !s.is_empty());
+        let id = text("id").map_or_else(|| format!("m{}-{}", message_id, index), str::to_string);
+        if !self.seen.insert(id.clone()) {
+            return id;
+        }
+        let mime_type = text("mimeType").map(str::to_string);
+        let data = text("data").filter(|_| text("type") == Some("image"));
+        let size = data.map_or(0, |d| d.len() / 4 * 3);
+        let (data_uri, omitted) = match data {
+            Some(data) => match self.admit(&id, size) {
+                Ok(()) => {
+                    let mime = mime_type.as_deref().unwrap_or("image/png");
+                    (Some(format!("data:{};base64,{}", mime, data)), None)
+                }
+                Err(reason) => (None, Some(reason)),
+            },
+            // Files attached by path stay on this machine
+            None => (None, Some("not embedded".to_string())),
+        };
+        self.attachments.push(ViewerAttachment {
+            id: id.clone(),
+            name: text("name").map(str::to_string),
+            mime_type,
+            size: size as u64,
+            data_uri,
+            text: None,
+            omitted,
+        });
+        id
+    }
+
+    /// A working-set file from `attachments`; returns its id
+    fn session_file(&mut self, row: SessionFile) -> String {
+        let id = format!("file-{}", row.id);
+        let size = row.size.max(0) as usize;
+        let is_image = row.mime_type.as_deref().is_some_and(|m| m.starts_with("image/"));
+        let (mut data_uri, mut text, mut omitted) = (None, None, None);
+        if row.encoding == "utf8" || is_image {
+            match self.admit(&id, size) {
+                Ok(()) if row.encoding == "utf8" => text = Some(redaction::redact_secrets(&row.content)),
+                Ok(()) => {
+                    let mime = row.mime_type.as_deref().unwrap_or("application/octet-stream");
+                    data_uri = Some(format!("data:{};base64,{}", mime, row.content));
+                }
+                Err(reason) => omitted = Some(reason),
+            }
+        } else {
+            omitted = Some("binary file".to_string());
+        }
+        self.attachments.push(ViewerAttachment {
+            id: id.clone(),
+            name: row.name,
+            mime_type: row.mime_type,
+            size: size as u64,
+            data_uri,
+            text,
+            omitted,
+        });
+        id
+    }
+}
+
+struct SessionFile {
+    id: i64,
+    name: Option<String>,
+    mime_type: Option<String>,
+    encoding: String,
+    size: i64,
+    content: String,
+}
+
+/// Redact `text` and cut it at `MAX_FIELD_BYTES`, noting the cut in `truncated`
+fn clip(text: Option<String>, truncated: &mut bool) -> Option<String> {
+    let text = redaction::redact_secrets(&text?);
+    if text.len() <= MAX_FIELD_BYTES {
+        return Some(text);
+    }
+    *truncated = true;
+    let mut end = MAX_FIELD_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(format!("{}\n… [{} bytes cut]", &text[..end], text.len() - end))
+}
+
+fn build(conn: &Connection, session_id: &str) -> Result<(ViewerPayload, Vec<String>), String> {
+    let session = conn
+        .query_row(
+            "SELECT id, prompt, created_at FROM sessions WHERE id = ?1",
+            [session_id],
+            |row| {
+                Ok(ViewerSession {
+                    id: row.get(0)?,
+                    prompt: row.get(1)?,
+                    created_at: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Session '{}' not found", session_id))?;
+    let session = ViewerSession {
+        prompt: redaction::redact_secrets(&session.prompt),
+        ..session
+    };
+
+    let mut tasks = conn
+        .prepare(
+            "SELECT id, task_index,
+                    COALESCE((SELECT content FROM attachments
+                              WHERE id = tasks.prompt_overflow_attachment_id), prompt),
+                    status, cost, created_at
+             FROM tasks WHERE session_id = ?1 AND trashed_at IS NULL
+             ORDER BY task_index, created_at, id",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map([session_id], |row| {
+            Ok(ViewerTask {
+                id: row.get(0)?,
+                task_index: row.get(1)?,
+                prompt: row.get(2)?,
+                status: row.get(3)?,
+                cost: row.get(4)?,
+                created_at: row.get(5)?,
+                messages: Vec::new(),
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut inliner = Inliner::default();
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, type, content, tool_name, tool_input, tool_output, error_message, attachments, created_at
+             FROM messages WHERE task_id = ?1 ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    for task in &mut tasks {
+        let mut truncated_prompt = false;
+        task.prompt = clip(Some(std::mem::take(&mut task.prompt)), &mut truncated_prompt).unwrap_or_default();
+        if truncated_prompt {
+            inliner.warnings.push(format!("Prompt of task {} cut to {} KB", task.id, MAX_FIELD_BYTES / 1024));
+        }
+        let mut rows = stmt.query([&task.id]).map_err(|e| e.to_string())?;
+        while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+            let id: i64 = row.get(0).map_err(|e| e.to_string())?;
+            let get = |i: usize| row.get::<_, Option<String>>(i).map_err(|e| e.to_string());
+            let mut truncated = false;
+            let mut message = ViewerMessage {
+                id,
+                kind: row.get(1).map_err(|e| e.to_string())?,
+                content: clip(get(2)?, &mut truncated),
+                tool_name: get(3)?,
+                tool_input: clip(get(4)?, &mut truncated),
+                tool_output: clip(get(5)?, &mut truncated),
+                error_message: clip(get(6)?, &mut truncated),
+                attachment_ids: Vec::new(),
+                truncated,
+                created_at: row.get(8).map_err(|e| e.to_string())?,
+            };
+            if truncated {
+                inliner.warnings.push(format!("Message {} cut to {} KB per field", id, MAX_FIELD_BYTES / 1024));
+            }
+            let entries: Vec<Value> = get(7)?.and_then(|a| serde_json::from_str(&a).ok()).unwrap_or_default();
+            message.attachment_ids = entries
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| inliner.message_attachment(id, index, entry))
+                .collect();
+            task.messages.push(message);
+        }
+    }
+
+    let files = conn
+        .prepare(
+            "SELECT a.id, a.name, a.mime_type, a.encoding, a.size, a.content
+             FROM session_files s JOIN attachments a ON a.id = s.attachment_id
+             WHERE s.session_id = ?1 ORDER BY s.position",
+        )
+        .map_err(|e| e.to_string())?
+        .query_map([session_id], |row| {
+            Ok(SessionFile {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                mime_type: row.get(2)?,
+                encoding: row.get(3)?,
+                size: row.get(4)?,
+                content: row.get(5)?,
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let session_files = files.into_iter().map(|file| inliner.session_file(file)).collect();
+
+    let payload = ViewerPayload {
+        schema: VIEWER_SCHEMA.to_string(),
+        version: VIEWER_VERSION,
+        session,
+        tasks,
+        attachments: inliner.attachments,
+        session_files,
+    };
+    Ok((payload, inliner.warnings))
+}
+
+/// The HTML file for `payload`, with the payload's size before and after compression
+fn render(payload: &ViewerPayload) -> Result<(Vec<u8>, usize, usize), String> {
+    let json = serde_json::to_vec(payload).map_err(|e| e.to_string())?;
+    if json.len() > MAX_PAYLOAD_BYTES {
+        return Err(format!(
+            "The session is too large for a single-file viewer ({} MB of data, limit {} MB)",
+            json.len() / (1024 * 1024),
+            MAX_PAYLOAD_BYTES / (1024 * 1024)
+        ));
+    }
+    // A fixed header mtime keeps the output identical for identical data
+    let mut encoder = GzBuilder::new().mtime(0).write(Vec::new(), Compression::best());
+    encoder.write_all(&json).map_err(|e| e.to_string())?;
+    let compressed = encoder.finish().map_err(|e| e.to_string())?;
+
+    let title = payload.session.prompt.lines().next().unwrap_or_default().chars().take(120).collect::<String>();
+    let mut html = format!(
+        "<!doctype html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n</head>\n<body>\n<div id=\"app\"></div>\n\
+         <script type=\"application/json\" id=\"{}\" data-schema=\"{}\" data-version=\"{}\" data-encoding=\"gzip+base64\">",
+        escape(&title),
+        PAYLOAD_ELEMENT,
+        VIEWER_SCHEMA,
+        payload.version
+    )
+    .into_bytes();
+    html.extend_from_slice(BASE64.encode(&compressed).as_bytes());
+    html.extend_from_slice(b"</script>\n<script>\n");
+    html.extend_from_slice(VIEWER_BUNDLE);
+    html.extend_from_slice(b"</script>\n</body>\n</html>\n");
+    Ok((html, json.len(), compressed.len()))
+}
+
+/// Pull the payload out of a viewer file and check it against the schema
+fn parse(html: &str) -> Result<ViewerPayload, String> {
+    let marker = format!("id=\"{}\"", PAYLOAD_ELEMENT);
+    let start = html.find(&marker).ok_or("No viewer payload in the file")?;
+    let tag_end = start + html[start..].find('>').ok_or("The payload element is not closed")?;
+    let attributes = &html[start..tag_end];
+    let attribute = |name: &str| {
+        let key = format!("{}=\"", name);
+        attributes
+            .find(&key)
+            .map(|i| &attributes[i + key.len()..])
+            .and_then(|rest| rest.split('"').next())
+    };
+    if attribute("data-schema") != Some(VIEWER_SCHEMA) {
+        return Err("The payload is not a session viewer payload".to_string());
+    }
+    let version: u32 = attribute("data-version")
+        .and_then(|v| v.parse().ok())
+        .ok_or("The payload has no version")?;
+    if version > VIEWER_VERSION {
+        return Err(format!(
+            "The payload is version {}, newer than this app reads ({})",
+            version, VIEWER_VERSION
+        ));
+    }
+    let body_end = html[tag_end..].find("</script>").ok_or("The payload element is not closed")?;
+    let encoded = html[tag_end + 1..tag_end + body_end].trim();
+    let compressed = BASE64.decode(encoded).map_err(|e| format!("The payload is not valid base64: {}", e))?;
+    let mut json = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut json)
+        .map_err(|e| format!("The payload does not decompress: {}", e))?;
+    let payload: ViewerPayload =
+        serde_json::from_slice(&json).map_err(|e| format!("The payload does not match the schema: {}", e))?;
+    if payload.schema != VIEWER_SCHEMA || payload.version != version {
+        return Err("The payload header and the payload disagree".to_string());
+    }
+    Ok(payload)
+}
+
+fn validate(path: &Path, html: &str) -> ViewerValidation {
+    let mut report = ViewerValidation {
+        path: path.to_string_lossy().into_owned(),
+        valid: false,
+        version: None,
+        session_id: None,
+        tasks: 0,
+        messages: 0,
+        attachments: 0,
+        problems: Vec::new(),
+    };
+    let payload = match parse(html) {
+        Ok(payload) => payload,
+        Err(problem) => {
+            report.problems.push(problem);
+            return report;
+        }
+    };
+    let ids: BTreeMap<&str, &ViewerAttachment> = payload.attachments.iter().map(|a| (a.id.as_str(), a)).collect();
+    if ids.len() != payload.attachments.len() {
+        report.problems.push("Attachment ids are not unique".to_string());
+    }
+    for task in &payload.tasks {
+        for message in &task.messages {
+            for id in message.attachment_ids.iter().filter(|id| !ids.contains_key(id.as_str())) {
+                report.problems.push(format!("Message {} refers to missing attachment {}", message.id, id));
+            }
+        }
+    }
+    for id in payload.session_files.iter().filter(|id| !ids.contains_key(id.as_str())) {
+        report.problems.push(format!("Session file {} is missing", id));
+    }
+    for attachment in &payload.attachments {
+        if attachment.data_uri.as_deref().is_some_and(|uri| !uri.starts_with("data:")) {
+            report.problems.push(format!("Attachment {} is not a data URI", attachment.id));
+        }
+    }
+    report.valid = report.problems.is_empty();
+    report.version = Some(payload.version);
+    report.session_id = Some(payload.session.id);
+    report.tasks = payload.tasks.len();
+    report.messages = payload.tasks.iter().map(|t| t.messages.len()).sum();
+    report.attachments = payload.attachments.len();
+    report
+}
+
+/// Write a session as a single read-only HTML viewer at `path`
+#[tauri::command]
+pub async fn export_interactive_viewer(
+    app: AppHandle,
+    session_id: String,
+    path: String,
+) -> Result<ViewerExportReport, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| export_session(&handle, conn, &session_id, Path::new(&path))).await
+}
+
+/// The viewer for `session_id` written to `target`, with its manifest
+pub(crate) fn export_session(
+    app: &AppHandle,
+    conn: &Connection,
+    session_id: &str,
+    target: &Path,
+) -> Result<ViewerExportReport, String> {
+    if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Cannot create '{}': {}", parent.display(), e))?;
+    }
+    let (payload, warnings) = build(conn, session_id)?;
+    let (html, payload_bytes, compressed_bytes) = render(&payload)?;
+    let tmp = target.with_extension("html.partial");
+    std::fs::write(&tmp, &html).map_err(|e| format!("Cannot write '{}': {}", tmp.display(), e))?;
+    std::fs::rename(&tmp, target).map_err(|e| e.to_string())?;
+    let manifest_path = export_manifest::write_signed(app, conn, target, "viewer", &[target.to_path_buf()])
+        .map_err(|e| eprintln!("[Viewer] No manifest for {}: {}", target.display(), e))
+        .ok();
+
+    let omitted = payload.attachments.iter().filter(|a| a.omitted.is_some()).count();
+    println!(
+        "[Viewer] Exported session {} to {} ({} bytes, {} warnings)",
+        session_id,
+        target.display(),
+        html.len(),
+        warnings.len()
+    );
+    Ok(ViewerExportReport {
+        path: target.to_string_lossy().into_owned(),
+        bytes: html.len() as u64,
+        payload_bytes: payload_bytes as u64,
+        compressed_bytes: compressed_bytes as u64,
+        tasks: payload.tasks.len(),
+        messages: payload.tasks.iter().map(|t| t.messages.len()).sum(),
+        attachments_inlined: payload.attachments.len() - omitted,
+        attachments_omitted: omitted,
+        warnings,
+        manifest_path: manifest_path.map(|p| p.to_string_lossy().into_owned()),
+    })
+}
+
+/// Check that a viewer file's embedded payload decodes and matches the schema
+#[tauri::command]
+pub async fn validate_viewer_export(path: String) -> Result<ViewerValidation, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let html = std::fs::read_to_string(&path).map_err(|e| format!("Cannot read '{}': {}", path, e))?;
+        Ok(validate(Path::new(&path), &html))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Connection {
+        let conn = crate::migrations::test_db();
+        let image = serde_json::json!([
+            { "id": "img1", "type": "image", "name": "chart.png", "mimeType": "image/png", "data": "iVBORw0KGgo=" },
+            { "id": "doc1", "type": "file", "name": "notes.pdf", "path": "/Users/me/notes.pdf" },
+        ]);
+        conn.execute_batch(
+            "INSERT INTO sessions (id, prompt) VALUES ('s1', 'Quarterly report');
+             INSERT INTO tasks (id, session_id, task_index, prompt) VALUES
+                 ('t1', 's1', 0, 'Summarize'), ('t2', 's1', 1, 'Chart it');
+             INSERT INTO attachments (task_id, kind, name, mime_type, content, size)
+                 VALUES (NULL, 'file', 'data.csv', 'text/csv', 'a,b\n1,2', 7);
+             INSERT INTO session_files (session_id, attachment_id, position) VALUES ('s1', 1, 0);",
+        )
+        .unwrap();
+        let long = "x".repeat(MAX_FIELD_BYTES + 10);
+        conn.execute(
+            "INSERT INTO messages (task_id, type, content) VALUES ('t1', 'user', 'key sk-ant-REDACTED')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO messages (task_id, type, tool_name, tool_output) VALUES ('t1', 'tool_use', 'Bash', ?1)",
+            [&long],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO messages (task_id, type, content, attachments) VALUES ('t2', 'user', 'see chart', ?1)",
+            [image.to_string()],
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn exports_are_deterministic_redacted_and_valid() {
+        let conn = db();
+        let (payload, warnings) = build(&conn, "s1").unwrap();
+        let (first, _, _) = render(&payload).unwrap();
+        let (second, _, _) = render(&build(&conn, "s1").unwrap().0).unwrap();
+        assert_eq!(first, second);
+
+        let report = validate(Path::new("viewer.html"), std::str::from_utf8(&first).unwrap());
+        assert!(report.valid, "{:?}", report.problems);
+        assert_eq!((report.tasks, report.messages, report.attachments), (2, 3, 3));
+
+        let parsed = parse(std::str::from_utf8(&first).unwrap()).unwrap();
+        assert_eq!(parsed, payload);
+        let messages = &parsed.tasks[0].messages;
+        assert!(!messages[0].content.as_deref().unwrap().contains("sk-ant-"));
+        assert!(messages[1].truncated);
+        assert_eq!(warnings, vec![format!("Message {} cut to 256 KB per field", messages[1].id)]);
+
+        let image = &parsed.attachments[0];
+        assert_eq!(image.data_uri.as_deref(), Some("data:image/png;base64,iVBORw0KGgo="));
+        let document = &parsed.attachments[1];
+        assert_eq!(document.omitted.as_deref(), Some("not embedded"));
+        assert!(!serde_json::to_string(&parsed).unwrap().contains("/Users/me"));
+        assert_eq!(parsed.session_files, vec!["file-1"]);
+        assert_eq!(parsed.attachments[2].text.as_deref(), Some("a,b\n1,2"));
+    }
+
+    #[test]
+    fn damaged_or_newer_payloads_fail_validation() {
+        let conn = db();
+        let (html, _, _) = render(&build(&conn, "s1").unwrap().0).unwrap();
+        let html = String::from_utf8(html).unwrap();
+
+        let newer = html.replace(
+            &format!("data-version=\"{}\"", VIEWER_VERSION),
+            &format!("data-version=\"{}\"", VIEWER_VERSION + 1),
+        );
+        assert!(!validate(Path::new("v.html"), &newer).valid);
+        let garbled = html.replacen("data-encoding=\"gzip+base64\">", "data-encoding=\"gzip+base64\">!!", 1);
+        assert!(!validate(Path::new("v.html"), &garbled).valid);
+
+        let mut payload = build(&conn, "s1").unwrap().0;
+        payload.tasks[1].messages[0].attachment_ids.push("gone".to_string());
+        let (dangling, _, _) = render(&payload).unwrap();
+        let report = validate(Path::new("v.html"), std::str::from_utf8(&dangling).unwrap());
+        assert_eq!(report.problems.len(), 1, "{:?}", report.problems);
+    }
+
+    #[test]
+    fn the_bundle_cannot_close_its_script_element() {
+        let bundle = std::str::from_utf8(VIEWER_BUNDLE).unwrap();
+        assert!(!bundle.to_ascii_lowercase().contains("</script"));
+    }
+}
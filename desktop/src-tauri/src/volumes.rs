@@ -0,0 +1,468 @@
+// Working directories on network volumes (SMB, NFS, sshfs, ...). Once the server
+// goes away, a stat on such a path can block for 30 seconds or more.
+//
+// `is_network_path` reads the mount table, plus fstab for automounts that are
+// currently gone, on Linux; `mount` output on macOS; and the drive type on Windows.
+// It never touches the path itself. Filesystem work on a network path goes through
+// `guard`, which runs it on its own thread and gives up after `volume_timeout_ms`
+// (default 3s) with `CommandError::VolumeUnavailable`. The stuck thread is left to
+// finish whenever the OS gives up on the mount.
+//
+// The monitor polls the working directories of running tasks that are on network
+// volumes. A failed probe backs off, doubling from `POLL_INTERVAL` up to
+// `MAX_BACKOFF`, and emits `workspace://volume-lost`; each affected task gets a
+// system message and its stall timer is paused. The first probe that succeeds
+// emits `workspace://volume-restored` and resumes the timers.
+
+use crate::error::CommandError;
+use crate::message_types::MessageType;
+use crate::{background, db, events, settings, stall};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const TIMEOUT_KEY: &str = "volume_timeout_ms";
+const DEFAULT_TIMEOUT_MS: u64 = 3_000;
+/// Between probes of a reachable network volume
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// Longest wait between probes of a lost volume
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+const ACTIVITY: background::Activity = background::Activity {
+    name: "volumes",
+    interval: Duration::from_secs(5),
+    coalescable: true,
+};
+
+/// File system types served over the network, as the mount table names them
+const NETWORK_FS_TYPES: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb", "smb2", "smb3", "smbfs", "afpfs", "webdav", "davfs", "ncpfs", "9p", "afs",
+    "sshfs", "fuse.sshfs", "fuse.rclone", "glusterfs", "fuse.glusterfs", "ceph", "fuse.ceph", "lustre",
+];
+
+#[cfg_attr(windows, allow(dead_code))]
+fn is_network_fs(fs_type: &str) -> bool {
+    let fs_type = fs_type.to_ascii_lowercase();
+    NETWORK_FS_TYPES.contains(&fs_type.as_str())
+}
+
+/// Mount points and their types from `/proc/mounts` or fstab lines
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_mount_table(text: &str) -> Vec<(PathBuf, String)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?.replace("\\040", " ").replace("\\011", "\t");
+            Some((PathBuf::from(mount_point), fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Mount points and their types from macOS `mount` output:
+/// `//user@server/share on /Volumes/share (smbfs, nodev, nosuid, mounted by user)`
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_mount_output(text: &str) -> Vec<(PathBuf, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let (device_and_point, options) = line.rsplit_once(" (")?;
+            let (_, mount_point) = device_and_point.split_once(" on ")?;
+            let fs_type = options.split([',', ')']).next()?.trim();
+            Some((PathBuf::from(mount_point), fs_type.to_string()))
+        })
+        .collect()
+}
+
+/// Type of the deepest mount point containing `path`; on equal depth the earlier
+/// entry wins, so live mounts listed first override fstab
+#[cfg_attr(windows, allow(dead_code))]
+fn mount_type<'a>(mounts: &'a [(PathBuf, String)], path: &Path) -> Option<&'a str> {
+    let mut best: Option<(usize, &str)> = None;
+    for (mount_point, fs_type) in mounts {
+        if !path.starts_with(mount_point) {
+            continue;
+        }
+        let depth = mount_point.components().count();
+        if best.map_or(true, |(best_depth, _)| depth > best_depth) {
+            best = Some((depth, fs_type));
+        }
+    }
+    best.map(|(_, fs_type)| fs_type)
+}
+
+/// Whether `path` is on a network volume, judged from mount information only
+pub(crate) fn is_network_path(path: &Path) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let mut mounts = parse_mount_table(&std::fs::read_to_string("/proc/self/mounts").unwrap_or_default());
+        mounts.extend(parse_mount_table(&std::fs::read_to_string("/etc/fstab").unwrap_or_default()));
+        mount_type(&mounts, path).is_some_and(is_network_fs)
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let output = std::process::Command::new("mount").output();
+        let text = output.map(|o| String::from_utf8_lossy(&o.stdout).into_owned()).unwrap_or_default();
+        mount_type(&parse_mount_output(&text), path).is_some_and(is_network_fs)
+    }
+
+    #[cfg(windows)]
+    {
+        use std::path::{Component, Prefix};
+        use windows_sys::Win32::Storage::FileSystem::GetDriveTypeW;
+        const DRIVE_REMOTE: u32 = 4;
+
+        match path.components().next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::UNC(..) | Prefix::VerbatimUNC(..) => true,
+                Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                    let root: Vec<u16> = format!("{}:\\\0", letter as char).encode_utf16().collect();
+                    // SAFETY: `root` is a NUL-terminated wide string like `C:\`
+                    unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE }
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+fn timeout(conn: &Connection) -> Result<Duration, String> {
+    Ok(Duration::from_millis(settings::get_or(conn, TIMEOUT_KEY, DEFAULT_TIMEOUT_MS)?))
+}
+
+/// Run `f` on its own thread, giving up after `timeout`
+fn run_with_timeout<T: Send + 'static>(timeout: Duration, f: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    rx.recv_timeout(timeout).ok()
+}
+
+/// Run filesystem work on `path`; on a network volume it fails with
+/// `VolumeUnavailable` after the volume timeout instead of blocking the caller.
+/// Call from a blocking thread.
+pub(crate) fn guard<T: Send + 'static>(
+    app: &AppHandle,
+    path: &Path,
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, CommandError> {
+    if !is_network_path(path) {
+        return f().map_err(CommandError::from);
+    }
+    let timeout = db::open(app)
+        .and_then(|conn| timeout(&conn))
+        .unwrap_or(Duration::from_millis(DEFAULT_TIMEOUT_MS));
+    match run_with_timeout(timeout, f) {
+        Some(result) => result.map_err(CommandError::from),
+        None => {
+            eprintln!("[Volumes] {} did not answer within {}ms", path.display(), timeout.as_millis());
+            Err(CommandError::VolumeUnavailable {
+                message: format!(
+                    "{} is on a network volume that did not respond within {}s",
+                    path.display(),
+                    timeout.as_secs_f32()
+                ),
+                path: path.to_string_lossy().into_owned(),
+                timeout_ms: timeout.as_millis() as u64,
+            })
+        }
+    }
+}
+
+/// Delay before the next probe after `failures` failed probes in a row
+fn backoff(failures: u32) -> Duration {
+    if failures == 0 {
+        return POLL_INTERVAL;
+    }
+    POLL_INTERVAL
+        .saturating_mul(2u32.saturating_pow(failures.min(16)))
+        .min(MAX_BACKOFF)
+}
+
+struct Volume {
+    failures: u32,
+    next_probe: Instant,
+    lost_since: Option<Instant>,
+    /// Tasks told about the loss, whose stall timers are paused
+    paused: HashSet<String>,
+    /// Set while a probe thread runs, so a hung mount holds one thread at most
+    probing: Arc<AtomicBool>,
+}
+
+/// Network working directories being polled, by path
+#[derive(Default)]
+pub struct VolumeMonitor(Mutex<HashMap<String, Volume>>);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VolumeStatus {
+    pub path: String,
+    pub available: bool,
+    pub failures: u32,
+    pub down_ms: Option<u64>,
+    pub task_ids: Vec<String>,
+}
+
+/// Working directories of running tasks, with the tasks using each
+fn running_dirs(conn: &Connection) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT json_extract(workspace_analysis, '$.root'), id FROM tasks
+             WHERE status = 'running' AND workspace_analysis IS NOT NULL ORDER BY id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, String>(1)?)))
+        .map_err(|e| e.to_string())?;
+    let mut dirs: HashMap<String, Vec<String>> = HashMap::new();
+    for row in rows {
+        if let (Some(dir), task_id) = row.map_err(|e| e.to_string())? {
+            dirs.entry(dir).or_default().push(task_id);
+        }
+    }
+    Ok(dirs)
+}
+
+/// Record a system message on a task whose working directory is unreachable
+fn record_message(app: &AppHandle, conn: &Connection, task_id: &str, content: &str) {
+    let inserted = conn
+        .execute(
+            "INSERT INTO messages (task_id, type, subtype, content) VALUES (?1, ?2, 'system', ?3)",
+            params![task_id, MessageType::Text, content],
+        )
+        .map(|_| conn.last_insert_rowid());
+    match inserted {
+        Ok(id) => db::emit_change(app, "messages", "insert", vec![id.to_string()]),
+        Err(e) => eprintln!("[Volumes] Cannot record message on task {}: {}", task_id, e),
+    }
+}
+
+/// Probe `dir` unless its last probe is still stuck; `false` when it didn't answer
+fn probe(dir: &str, probing: &Arc<AtomicBool>, timeout: Duration) -> bool {
+    if probing.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    let flag = probing.clone();
+    let path = PathBuf::from(dir);
+    run_with_timeout(timeout, move || {
+        let ok = std::fs::metadata(&path).is_ok_and(|meta| meta.is_dir());
+        flag.store(false, Ordering::SeqCst);
+        ok
+    })
+    .unwrap_or(false)
+}
+
+fn tick(app: &AppHandle) -> Result<(), String> {
+    let conn = db::open(app)?;
+    let timeout = timeout(&conn)?;
+    let dirs: HashMap<String, Vec<String>> = running_dirs(&conn)?
+        .into_iter()
+        .filter(|(dir, _)| is_network_path(Path::new(dir)))
+        .collect();
+    let state = app.state::<VolumeMonitor>();
+    let now = Instant::now();
+
+    // Due probes, taken out of the lock so a hung one doesn't block the others' state
+    let due: Vec<(String, Arc<AtomicBool>)> = {
+        let mut volumes = state.0.lock().map_err(|e| e.to_string())?;
+        for (dir, volume) in volumes.iter() {
+            if !dirs.contains_key(dir) {
+                for task_id in &volume.paused {
+                    stall::resume(app, task_id);
+                }
+            }
+        }
+        volumes.retain(|dir, _| dirs.contains_key(dir));
+        dirs.keys()
+            .filter_map(|dir| {
+                let volume = volumes.entry(dir.clone()).or_insert_with(|| Volume {
+                    failures: 0,
+                    next_probe: now,
+                    lost_since: None,
+                    paused: HashSet::new(),
+                    probing: Arc::new(AtomicBool::new(false)),
+                });
+                (volume.next_probe <= now).then(|| (dir.clone(), volume.probing.clone()))
+            })
+            .collect()
+    };
+
+    for (dir, probing) in due {
+        let available = probe(&dir, &probing, timeout);
+        let task_ids = &dirs[&dir];
+        let mut volumes = state.0.lock().map_err(|e| e.to_string())?;
+        let Some(volume) = volumes.get_mut(&dir) else {
+            continue;
+        };
+        if available {
+            volume.failures = 0;
+            volume.next_probe = Instant::now() + POLL_INTERVAL;
+            if let Some(since) = volume.lost_since.take() {
+                let down_ms = since.elapsed().as_millis() as u64;
+                println!("[Volumes] {} is reachable again after {}ms", dir, down_ms);
+                for task_id in volume.paused.drain() {
+                    stall::resume(app, &task_id);
+                }
+                events::emit_typed(
+                    app,
+                    events::VolumeRestored {
+                        path: dir.clone(),
+                        task_ids: task_ids.clone(),
+                        down_ms,
+                    },
+                );
+            }
+            continue;
+        }
+
+        volume.failures += 1;
+        let retry_in = backoff(volume.failures);
+        volume.next_probe = Instant::now() + retry_in;
+        if volume.lost_since.is_none() {
+            volume.lost_since = Some(now);
+            eprintln!("[Volumes] {} is unreachable; retrying in {}s", dir, retry_in.as_secs());
+            events::emit_typed(
+                app,
+                events::VolumeLost {
+                    path: dir.clone(),
+                    task_ids: task_ids.clone(),
+                    retry_in_ms: retry_in.as_millis() as u64,
+                },
+            );
+        }
+        // Tasks that started on the volume after it was lost are told too
+        for task_id in task_ids {
+            if volume.paused.insert(task_id.clone()) {
+                stall::pause(app, task_id);
+                record_message(
+                    app,
+                    &conn,
+                    task_id,
+                    &format!(
+                        "The working directory {} is on a network volume that is not responding. \
+                         The task's timers are paused until it is reachable again.",
+                        dir
+                    ),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Start polling network working directories
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || loop {
+        background::sleep(&app, &ACTIVITY);
+        if let Err(e) = tick(&app) {
+            eprintln!("[Volumes] Monitor tick failed: {}", e);
+        }
+    });
+}
+
+/// Network working directories of running tasks and whether they answered their
+/// last probe
+#[tauri::command]
+pub async fn get_volume_status(app: AppHandle) -> Result<Vec<VolumeStatus>, String> {
+    let handle = app.clone();
+    db::with_conn(&app, move |conn| {
+        let dirs = running_dirs(conn)?;
+        let volumes = handle.state::<VolumeMonitor>();
+        let volumes = volumes.0.lock().map_err(|e| e.to_string())?;
+        let mut status: Vec<VolumeStatus> = volumes
+            .iter()
+            .map(|(dir, volume)| VolumeStatus {
+                path: dir.clone(),
+                available: volume.lost_since.is_none(),
+                failures: volume.failures,
+                down_ms: volume.lost_since.map(|since| since.elapsed().as_millis() as u64),
+                task_ids: dirs.get(dir).cloned().unwrap_or_default(),
+            })
+            .collect();
+        status.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(status)
+    })
+    .await
+}
+
+/// Set how long filesystem calls on a network volume may take, in milliseconds;
+/// `None` restores the default
+#[tauri::command]
+pub async fn set_volume_timeout(app: AppHandle, timeout_ms: Option<u64>) -> Result<(), String> {
+    db::with_conn(&app, move |conn| match timeout_ms {
+        Some(0) => Err("The volume timeout must be above 0".to_string()),
+        Some(ms) => settings::set(conn, TIMEOUT_KEY, &ms),
+        None => settings::remove(conn, TIMEOUT_KEY),
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linux_mount_tables_pick_the_deepest_mount() {
+        let mounts = parse_mount_table(
+            "/dev/sda1 / ext4 rw 0 0\n\
+             //nas/share /mnt/my\\040share cifs rw 0 0\n\
+             # a comment\n\
+             nas:/export /home/me/remote nfs4 rw 0 0\n\
+             tmpfs /home/me/remote/tmp tmpfs rw 0 0\n",
+        );
+        assert_eq!(mount_type(&mounts, Path::new("/mnt/my share/docs")), Some("cifs"));
+        assert_eq!(mount_type(&mounts, Path::new("/home/me/remote/src")), Some("nfs4"));
+        assert_eq!(mount_type(&mounts, Path::new("/home/me/remote/tmp/x")), Some("tmpfs"));
+        assert_eq!(mount_type(&mounts, Path::new("/home/me/local")), Some("ext4"));
+        assert!(is_network_fs("NFS4") && is_network_fs("fuse.sshfs") && !is_network_fs("ext4"));
+    }
+
+    #[test]
+    fn fstab_entries_cover_unmounted_automounts() {
+        let mut mounts = parse_mount_table("/dev/sda1 / ext4 rw 0 0\n");
+        mounts.extend(parse_mount_table("//nas/share  /mnt/nas  cifs  noauto,x-systemd.automount  0 0\n"));
+        assert_eq!(mount_type(&mounts, Path::new("/mnt/nas/projects")), Some("cifs"));
+        // A live mount at the same point wins
+        let mut mounts = parse_mount_table("/dev/sdb1 /mnt/nas ext4 rw 0 0\n");
+        mounts.extend(parse_mount_table("//nas/share /mnt/nas cifs noauto 0 0\n"));
+        assert_eq!(mount_type(&mounts, Path::new("/mnt/nas/projects")), Some("ext4"));
+    }
+
+    #[test]
+    fn macos_mount_output_is_parsed() {
+        let mounts = parse_mount_output(
+            "/dev/disk3s1s1 on / (apfs, sealed, local, read-only, journaled)\n\
+             //me@nas._smb._tcp.local/Team Drive on /Volumes/Team Drive (smbfs, nodev, nosuid, mounted by me)\n",
+        );
+        assert_eq!(mount_type(&mounts, Path::new("/Volumes/Team Drive/notes")), Some("smbfs"));
+        assert_eq!(mount_type(&mounts, Path::new("/Users/me")), Some("apfs"));
+    }
+
+    #[test]
+    fn probes_back_off_up_to_the_cap() {
+        assert_eq!(backoff(0), POLL_INTERVAL);
+        assert_eq!(backoff(1), POLL_INTERVAL * 2);
+        assert_eq!(backoff(3), POLL_INTERVAL * 8);
+        assert_eq!(backoff(40), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn slow_work_times_out() {
+        assert_eq!(run_with_timeout(Duration::from_secs(5), || 7), Some(7));
+        let slow = run_with_timeout(Duration::from_millis(20), || std::thread::sleep(Duration::from_secs(1)));
+        assert_eq!(slow, None);
+    }
+}
@@ -0,0 +1,94 @@
+// Webview startup options that must be decided before any window exists.
+//
+// Hardware acceleration is controlled through environment variables the platform
+// webview reads when it initializes, so a change only takes effect on the next launch.
+
+use crate::{db, settings};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use tauri::{AppHandle, State};
+
+const HARDWARE_ACCELERATION_KEY: &str = "hardware_acceleration";
+
+/// Whether hardware acceleration is active for this launch
+pub struct HardwareAccelerationState {
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HardwareAccelerationInfo {
+    /// Saved preference, applied at the next launch
+    pub enabled: bool,
+    /// What the running webview was started with
+    pub active: bool,
+    /// False on platforms where the webview offers no switch (macOS)
+    pub supported: bool,
+    pub restart_required: bool,
+}
+
+fn supported() -> bool {
+    cfg!(any(target_os = "linux", target_os = "windows"))
+}
+
+fn read_setting_early(identifier: &str) -> Option<bool> {
+    let path = db::db_path_for_identifier(identifier)?;
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).ok()?;
+    settings::get(&conn, HARDWARE_ACCELERATION_KEY).ok().flatten()
+}
+
+/// Apply the saved preference to the process environment. Must run before the
+/// builder creates the first window.
+pub fn apply_hardware_acceleration(identifier: &str) -> HardwareAccelerationState {
+    let enabled = read_setting_early(identifier).unwrap_or(true);
+    if !enabled {
+        println!("[Webview] Hardware acceleration disabled by user setting");
+        #[cfg(target_os = "linux")]
+        {
+            std::env::set_var("WEBKIT_DISABLE_COMPOSITING_MODE", "1");
+            std::env::set_var("WEBKIT_DISABLE_DMABUF_RENDERER", "1");
+        }
+        #[cfg(target_os = "windows")]
+        {
+            let args = match std::env::var("WEBVIEW2_ADDITIONAL_BROWSER_ARGUMENTS") {
+                Ok(existing) if !existing.is_empty() => format!("{} --disable-gpu", existing),
+                _ => "--disable-gpu".to_string(),
+            };
+            std::env::set_var("WEBVIEW2_ADDITIONAL_BROWSER_ARGUMENTS", args);
+        }
+    }
+    HardwareAccelerationState {
+        active: enabled || !supported(),
+    }
+}
+
+#[tauri::command]
+pub async fn get_hardware_acceleration(
+    app: AppHandle,
+    state: State<'_, HardwareAccelerationState>,
+) -> Result<HardwareAccelerationInfo, String> {
+    let active = state.active;
+    let enabled = db::with_conn(&app, |conn| settings::get_or(conn, HARDWARE_ACCELERATION_KEY, true)).await?;
+    Ok(HardwareAccelerationInfo {
+        enabled,
+        active,
+        supported: supported(),
+        restart_required: supported() && enabled != active,
+    })
+}
+
+/// Save the preference; the return value says whether a restart is needed
+#[tauri::command]
+pub async fn set_hardware_acceleration(
+    app: AppHandle,
+    state: State<'_, HardwareAccelerationState>,
+    enabled: bool,
+) -> Result<HardwareAccelerationInfo, String> {
+    let active = state.active;
+    db::with_conn(&app, move |conn| settings::set(conn, HARDWARE_ACCELERATION_KEY, &enabled)).await?;
+    Ok(HardwareAccelerationInfo {
+        enabled,
+        active,
+        supported: supported(),
+        restart_required: supported() && enabled != active,
+    })
+}
@@ -0,0 +1,451 @@
+// Size and cleanup of what the platform webview stores for us: HTTP cache,
+// service-worker caches and site storage (local/session storage, IndexedDB).
+//
+// Each platform keeps these in its own layout: WKWebView under ~/Library/WebKit
+// and ~/Library/Caches on macOS, the WebView2 user data folder (`EBWebView`) on
+// Windows, and WebKitGTK's data and cache directories on Linux. Only the known
+// subdirectories in `LOCATIONS` are measured or deleted, and `is_protected` refuses
+// anything that holds workany.db or the ~/.workany stores, so a cleanup can't reach
+// app data even where the webview shares a directory with it (macOS).
+//
+// Clearing everything goes through the webview's own API. A subset is deleted from
+// disk; WebView2 keeps its files locked while running, so what can't be removed
+// is recorded in `webview-cleanup.json` and deleted at the next launch, before the
+// first window exists. Windows reload afterwards so the frontend doesn't run on
+// state that is gone.
+
+use crate::error::CommandError;
+use crate::{backup, captures, db, file_repair, thumbnails};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+const PENDING_FILE: &str = "webview-cleanup.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageCategory {
+    /// HTTP, code and GPU caches; rebuilt on demand
+    HttpCache,
+    /// Service-worker registrations and their Cache Storage
+    ServiceWorkers,
+    /// Local/session storage and IndexedDB, which can hold frontend state
+    SiteStorage,
+}
+
+const ALL_CATEGORIES: [StorageCategory; 3] = [
+    StorageCategory::HttpCache,
+    StorageCategory::ServiceWorkers,
+    StorageCategory::SiteStorage,
+];
+
+#[derive(Debug, Clone, Copy)]
+enum Root {
+    /// The webview's data directory for the app
+    Data,
+    /// The webview's cache directory for the app
+    Cache,
+}
+
+#[cfg(target_os = "macos")]
+const LOCATIONS: &[(StorageCategory, Root, &str)] = &[
+    (StorageCategory::HttpCache, Root::Cache, "WebKit"),
+    (StorageCategory::ServiceWorkers, Root::Data, "WebsiteData/ServiceWorkers"),
+    (StorageCategory::ServiceWorkers, Root::Data, "WebsiteData/CacheStorage"),
+    (StorageCategory::SiteStorage, Root::Data, "WebsiteData/LocalStorage"),
+    (StorageCategory::SiteStorage, Root::Data, "WebsiteData/IndexedDB"),
+    (StorageCategory::SiteStorage, Root::Data, "WebsiteData/Default"),
+];
+#[cfg(target_os = "windows")]
+const LOCATIONS: &[(StorageCategory, Root, &str)] = &[
+    (StorageCategory::HttpCache, Root::Data, "EBWebView/Default/Cache"),
+    (StorageCategory::HttpCache, Root::Data, "EBWebView/Default/Code Cache"),
+    (StorageCategory::HttpCache, Root::Data, "EBWebView/Default/GPUCache"),
+    (StorageCategory::HttpCache, Root::Data, "EBWebView/GrShaderCache"),
+    (StorageCategory::ServiceWorkers, Root::Data, "EBWebView/Default/Service Worker"),
+    (StorageCategory::SiteStorage, Root::Data, "EBWebView/Default/Local Storage"),
+    (StorageCategory::SiteStorage, Root::Data, "EBWebView/Default/Session Storage"),
+    (StorageCategory::SiteStorage, Root::Data, "EBWebView/Default/IndexedDB"),
+];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const LOCATIONS: &[(StorageCategory, Root, &str)] = &[
+    (StorageCategory::HttpCache, Root::Cache, "WebKitCache"),
+    (StorageCategory::HttpCache, Root::Data, "WebKitCache"),
+    (StorageCategory::ServiceWorkers, Root::Cache, "CacheStorage"),
+    (StorageCategory::ServiceWorkers, Root::Data, "CacheStorage"),
+    (StorageCategory::ServiceWorkers, Root::Data, "serviceworkers"),
+    (StorageCategory::SiteStorage, Root::Data, "localstorage"),
+    (StorageCategory::SiteStorage, Root::Data, "databases"),
+    (StorageCategory::SiteStorage, Root::Data, "storage"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryUsage {
+    pub category: StorageCategory,
+    pub bytes: u64,
+    /// Directories that exist for this category
+    pub paths: Vec<String>,
+    /// Clearing it needs `force`, since the frontend may keep state there
+    pub requires_confirmation: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebviewStorageUsage {
+    pub categories: Vec<CategoryUsage>,
+    pub total_bytes: u64,
+    /// Categories waiting to be deleted at the next launch
+    pub pending_cleanup: Vec<StorageCategory>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebviewCleanup {
+    pub cleared: Vec<StorageCategory>,
+    pub freed_bytes: u64,
+    /// Categories that were in use and will be deleted at the next launch
+    pub scheduled: Vec<StorageCategory>,
+    /// Cleared through the webview instead of deleting directories
+    pub used_platform_api: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageEntry {
+    pub name: &'static str,
+    pub bytes: u64,
+    pub path: Option<String>,
+}
+
+/// Where the app's data goes, largest first
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageBreakdown {
+    pub entries: Vec<StorageEntry>,
+    pub total_bytes: u64,
+}
+
+fn root_dir(identifier: &str, root: Root) -> Option<PathBuf> {
+    #[cfg(target_os = "macos")]
+    {
+        let library = dirs::home_dir()?.join("Library");
+        match root {
+            Root::Data => Some(library.join("WebKit").join(identifier)),
+            Root::Cache => Some(library.join("Caches").join(identifier)),
+        }
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        // Tauri's app_local_data_dir / app_cache_dir, resolvable before the app is built
+        match root {
+            Root::Data => dirs::data_local_dir().map(|dir| dir.join(identifier)),
+            Root::Cache => dirs::cache_dir().map(|dir| dir.join(identifier)),
+        }
+    }
+}
+
+fn category_dirs(identifier: &str, category: StorageCategory) -> Vec<PathBuf> {
+    LOCATIONS
+        .iter()
+        .filter(|(c, _, _)| *c == category)
+        .filter_map(|(_, root, relative)| Some(root_dir(identifier, *root)?.join(relative)))
+        .collect()
+}
+
+/// Whether deleting `dir` would take the database or a managed store with it
+fn is_protected(identifier: &str, dir: &Path) -> bool {
+    let guarded = [
+        db::db_path_for_identifier(identifier),
+        thumbnails::store_dir(),
+        file_repair::sessions_dir(),
+        dirs::home_dir().map(|home| home.join(".workany")),
+    ];
+    guarded.iter().flatten().any(|path| path.starts_with(dir) || dir == path.as_path())
+}
+
+/// Total size of regular files under `path`, not following symlinks
+fn dir_size(path: &Path) -> u64 {
+    let Ok(meta) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if meta.is_file() {
+        return meta.len();
+    }
+    if !meta.is_dir() {
+        return 0;
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| dir_size(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+fn pending_path(identifier: &str) -> Option<PathBuf> {
+    Some(db::db_path_for_identifier(identifier)?.parent()?.join(PENDING_FILE))
+}
+
+fn load_pending(identifier: &str) -> Vec<StorageCategory> {
+    pending_path(identifier)
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save_pending(identifier: &str, categories: &[StorageCategory]) -> Result<(), String> {
+    let path = pending_path(identifier).ok_or("No config directory")?;
+    if categories.is_empty() {
+        let _ = std::fs::remove_file(&path);
+        return Ok(());
+    }
+    let encoded = serde_json::to_vec(categories).map_err(|e| e.to_string())?;
+    std::fs::write(&path, encoded).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+/// Delete one category's directories; returns the bytes freed and whether any
+/// directory was left behind because it is in use
+fn delete_category(identifier: &str, category: StorageCategory) -> Result<(u64, bool), String> {
+    let mut freed = 0;
+    let mut locked = false;
+    for dir in category_dirs(identifier, category) {
+        if !dir.exists() {
+            continue;
+        }
+        if is_protected(identifier, &dir) {
+            return Err(format!("Refusing to delete {}: it holds app data", dir.display()));
+        }
+        let before = dir_size(&dir);
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => freed += before,
+            Err(e) if cfg!(windows) || e.kind() == std::io::ErrorKind::PermissionDenied => {
+                eprintln!("[Webview] {} is in use: {}", dir.display(), e);
+                freed += before.saturating_sub(dir_size(&dir));
+                locked = true;
+            }
+            Err(e) => return Err(format!("Cannot delete {}: {}", dir.display(), e)),
+        }
+    }
+    Ok((freed, locked))
+}
+
+/// Delete the categories a previous run couldn't. Must run before the builder
+/// creates the first window, while nothing holds the files.
+pub fn apply_pending_cleanup(identifier: &str) {
+    let pending = load_pending(identifier);
+    if pending.is_empty() {
+        return;
+    }
+    let mut left = Vec::new();
+    for category in pending {
+        match delete_category(identifier, category) {
+            Ok((freed, false)) => println!("[Webview] Cleared {:?} at startup ({} bytes)", category, freed),
+            Ok((_, true)) => left.push(category),
+            Err(e) => eprintln!("[Webview] Cannot clear {:?} at startup: {}", category, e),
+        }
+    }
+    if let Err(e) = save_pending(identifier, &left) {
+        eprintln!("[Webview] Cannot update pending cleanup: {}", e);
+    }
+}
+
+fn usage(identifier: &str) -> WebviewStorageUsage {
+    let categories: Vec<CategoryUsage> = ALL_CATEGORIES
+        .iter()
+        .map(|&category| {
+            let present: Vec<PathBuf> = category_dirs(identifier, category)
+                .into_iter()
+                .filter(|dir| dir.exists())
+                .collect();
+            CategoryUsage {
+                category,
+                bytes: present.iter().map(|dir| dir_size(dir)).sum(),
+                paths: present.iter().map(|dir| dir.to_string_lossy().into_owned()).collect(),
+                requires_confirmation: category == StorageCategory::SiteStorage,
+            }
+        })
+        .collect();
+    WebviewStorageUsage {
+        total_bytes: categories.iter().map(|c| c.bytes).sum(),
+        categories,
+        pending_cleanup: load_pending(identifier),
+    }
+}
+
+fn reload_windows(app: &AppHandle) {
+    for (label, window) in app.webview_windows() {
+        if let Err(e) = window.eval("window.location.reload()") {
+            eprintln!("[Webview] Cannot reload {}: {}", label, e);
+        }
+    }
+}
+
+/// Sizes of the webview's caches and storage for this app, per category
+#[tauri::command]
+pub async fn get_webview_storage_usage(app: AppHandle) -> Result<WebviewStorageUsage, String> {
+    let identifier = app.config().identifier.clone();
+    tauri::async_runtime::spawn_blocking(move || usage(&identifier))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Clear the given categories and reload every window. Site storage may hold
+/// frontend state, so it is refused with `conflict` unless `force` is set.
+#[tauri::command]
+pub async fn clear_webview_storage(
+    app: AppHandle,
+    categories: Vec<StorageCategory>,
+    force: bool,
+) -> Result<WebviewCleanup, CommandError> {
+    let identifier = app.config().identifier.clone();
+    let before = {
+        let identifier = identifier.clone();
+        tauri::async_runtime::spawn_blocking(move || usage(&identifier))
+            .await
+            .map_err(|e| e.to_string())?
+    };
+    let requested: Vec<StorageCategory> = ALL_CATEGORIES.into_iter().filter(|c| categories.contains(c)).collect();
+    if requested.contains(&StorageCategory::SiteStorage) && !force {
+        let bytes = before
+            .categories
+            .iter()
+            .find(|c| c.category == StorageCategory::SiteStorage)
+            .map_or(0, |c| c.bytes);
+        return Err(CommandError::Conflict {
+            message: "Site storage can hold unsaved frontend state; clearing it needs confirmation".to_string(),
+            existing: json!({ "category": StorageCategory::SiteStorage, "bytes": bytes }),
+        });
+    }
+    let freed_by = |category: StorageCategory| {
+        before.categories.iter().find(|c| c.category == category).map_or(0, |c| c.bytes)
+    };
+
+    // Everything at once is what the webview itself can clear, locked files included
+    if requested.len() == ALL_CATEGORIES.len() {
+        let mut cleared_all = true;
+        for (label, webview) in app.webviews() {
+            if let Err(e) = webview.clear_all_browsing_data() {
+                eprintln!("[Webview] Cannot clear browsing data of {}: {}", label, e);
+                cleared_all = false;
+            }
+        }
+        if cleared_all {
+            reload_windows(&app);
+            println!("[Webview] Cleared all browsing data");
+            return Ok(WebviewCleanup {
+                freed_bytes: requested.iter().map(|&c| freed_by(c)).sum(),
+                cleared: requested,
+                scheduled: Vec::new(),
+                used_platform_api: true,
+            });
+        }
+    }
+
+    let cleanup = {
+        let identifier = identifier.clone();
+        tauri::async_runtime::spawn_blocking(move || -> Result<WebviewCleanup, String> {
+            let mut cleanup = WebviewCleanup {
+                cleared: Vec::new(),
+                freed_bytes: 0,
+                scheduled: Vec::new(),
+                used_platform_api: false,
+            };
+            for category in requested {
+                let (freed, locked) = delete_category(&identifier, category)?;
+                cleanup.freed_bytes += freed;
+                if locked {
+                    cleanup.scheduled.push(category);
+                } else {
+                    cleanup.cleared.push(category);
+                }
+            }
+            if !cleanup.scheduled.is_empty() {
+                let mut pending = load_pending(&identifier);
+                for category in &cleanup.scheduled {
+                    if !pending.contains(category) {
+                        pending.push(*category);
+                    }
+                }
+                save_pending(&identifier, &pending)?;
+            }
+            Ok(cleanup)
+        })
+        .await
+        .map_err(|e| e.to_string())??
+    };
+    println!(
+        "[Webview] Cleared {:?} ({} bytes), {:?} scheduled for next launch",
+        cleanup.cleared, cleanup.freed_bytes, cleanup.scheduled
+    );
+    reload_windows(&app);
+    Ok(cleanup)
+}
+
+/// Disk use of the database, the managed stores, backups and webview storage
+#[tauri::command]
+pub async fn get_storage_breakdown(app: AppHandle) -> Result<StorageBreakdown, String> {
+    let identifier = app.config().identifier.clone();
+    let db_path = db::db_path(&app)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let database: u64 = ["", "-wal", "-shm"]
+            .iter()
+            .map(|suffix| dir_size(&PathBuf::from(format!("{}{}", db_path.display(), suffix))))
+            .sum();
+        let backups = db_path.parent().map(|dir| dir.join(backup::BACKUP_DIR));
+        let mut entries = vec![StorageEntry {
+            name: "database",
+            bytes: database,
+            path: Some(db_path.to_string_lossy().into_owned()),
+        }];
+        for (name, dir) in [
+            ("backups", backups),
+            ("sessions", file_repair::sessions_dir()),
+            ("thumbnails", thumbnails::store_dir()),
+            ("captures", captures::screenshot_dir()),
+        ] {
+            entries.push(StorageEntry {
+                name,
+                bytes: dir.as_deref().map_or(0, dir_size),
+                path: dir.map(|dir| dir.to_string_lossy().into_owned()),
+            });
+        }
+        entries.push(StorageEntry {
+            name: "webview",
+            bytes: usage(&identifier).total_bytes,
+            path: None,
+        });
+        entries.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+        StorageBreakdown {
+            total_bytes: entries.iter().map(|e| e.bytes).sum(),
+            entries,
+        }
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_location_can_reach_the_database_or_managed_stores() {
+        let identifier = "com.workany.test";
+        for category in ALL_CATEGORIES {
+            for dir in category_dirs(identifier, category) {
+                assert!(!is_protected(identifier, &dir), "{} is protected", dir.display());
+            }
+        }
+        if let Some(db_path) = db::db_path_for_identifier(identifier) {
+            assert!(is_protected(identifier, db_path.parent().unwrap()));
+        }
+        if let Some(home) = dirs::home_dir() {
+            assert!(is_protected(identifier, &home));
+        }
+    }
+
+    #[test]
+    fn dir_size_counts_nested_files() {
+        let dir = std::env::temp_dir().join(format!("workany-webview-size-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("a"), [0u8; 10]).unwrap();
+        std::fs::write(dir.join("nested").join("b"), [0u8; 32]).unwrap();
+        assert_eq!(dir_size(&dir), 42);
+        assert_eq!(dir_size(&dir.join("missing")), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,195 @@
+// Main window behavior: what closing the window does, and the tray icon that
+// keeps the app reachable when it is minimized to the tray.
+//
+// Where the session has no tray host (see `platform`), minimize-to-tray would hide
+// the window with no way back, so closing quits instead. The saved preference is
+// kept and applies again once a host is running.
+
+use crate::error::CommandError;
+use crate::platform::{self, Feature};
+use crate::{db, events, settings};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use tauri::menu::{Menu, MenuItem};
+use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
+use tauri::{AppHandle, Manager, State, WebviewUrl, WebviewWindowBuilder, Window, WindowEvent};
+
+pub const MAIN_WINDOW: &str = "main";
+const TRAY_ID: &str = "main-tray";
+const CLOSE_BEHAVIOR_KEY: &str = "close_behavior";
+const TRAY_NOTICE_KEY: &str = "tray_unavailable_notified";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseBehavior {
+    #[default]
+    Quit,
+    MinimizeToTray,
+}
+
+impl CloseBehavior {
+    fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "quit" => Ok(CloseBehavior::Quit),
+            "minimize_to_tray" => Ok(CloseBehavior::MinimizeToTray),
+            other => Err(format!(
+                "Invalid close behavior '{}': expected 'quit' or 'minimize_to_tray'",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CloseBehaviorState(pub Mutex<CloseBehavior>);
+
+/// Create the main window, hidden until `splash::finish` shows it. It isn't in
+/// `tauri.conf.json` because it must not load before the database is migrated.
+pub fn create_main_window(app: &AppHandle) -> tauri::Result<()> {
+    WebviewWindowBuilder::new(app, MAIN_WINDOW, WebviewUrl::default())
+        .title("CloudWork")
+        .inner_size(1200.0, 800.0)
+        .visible(false)
+        .build()?;
+    Ok(())
+}
+
+/// Bring the main window back from the tray or from behind other windows
+pub fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window(MAIN_WINDOW) {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+fn ensure_tray(app: &AppHandle) -> tauri::Result<()> {
+    if app.tray_by_id(TRAY_ID).is_some() {
+        return Ok(());
+    }
+    let show = MenuItem::with_id(app, "tray-show", "Show CloudWork", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, "tray-quit", "Quit", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&show, &quit])?;
+
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("CloudWork")
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(|app, event| match event.id().as_ref() {
+            "tray-show" => show_main_window(app),
+            // Exiting goes through RunEvent::Exit, which runs the sidecar cleanup
+            "tray-quit" => app.exit(0),
+            _ => {}
+        })
+        .on_tray_icon_event(|tray, event| {
+            if let TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                ..
+            } = event
+            {
+                show_main_window(tray.app_handle());
+            }
+        });
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+    Ok(())
+}
+
+fn apply_close_behavior(app: &AppHandle, behavior: CloseBehavior) -> Result<(), String> {
+    match behavior {
+        CloseBehavior::MinimizeToTray => ensure_tray(app).map_err(|e| e.to_string()),
+        CloseBehavior::Quit => {
+            app.remove_tray_by_id(TRAY_ID);
+            Ok(())
+        }
+    }
+}
+
+/// Tell the user once why closing quits, the first time the tray fallback applies
+fn notify_tray_fallback(app: &AppHandle, conn: &rusqlite::Connection, message: String) -> Result<(), String> {
+    if settings::get_or(conn, TRAY_NOTICE_KEY, false)? {
+        return Ok(());
+    }
+    let desktop = platform::capabilities(app).and_then(|c| c.desktop);
+    events::emit_typed(app, events::TrayUnavailable { desktop, message });
+    settings::set(conn, TRAY_NOTICE_KEY, &true)
+}
+
+/// Load the persisted close behavior during setup
+pub fn init(app: &AppHandle) {
+    // The database may not exist yet on first launch; fall back to the default
+    let conn = db::open(app);
+    let saved = conn
+        .as_ref()
+        .ok()
+        .and_then(|conn| settings::get_or(conn, CLOSE_BEHAVIOR_KEY, CloseBehavior::default()).ok())
+        .unwrap_or_default();
+    let mut behavior = saved;
+    if saved == CloseBehavior::MinimizeToTray {
+        if let Err(CommandError::Unsupported { message, .. }) = platform::require(app, Feature::Tray) {
+            eprintln!("[Window] {}; closing the window will quit", message);
+            behavior = CloseBehavior::Quit;
+            if let Ok(conn) = &conn {
+                if let Err(e) = notify_tray_fallback(app, conn, message) {
+                    eprintln!("[Window] Failed to record the tray notice: {}", e);
+                }
+            }
+        }
+    }
+    if let Ok(mut current) = app.state::<CloseBehaviorState>().0.lock() {
+        *current = behavior;
+    }
+    if let Err(e) = apply_close_behavior(app, behavior) {
+        eprintln!("[Window] Failed to apply close behavior: {}", e);
+    }
+}
+
+/// Window event hook: hide instead of closing when minimizing to the tray
+pub fn on_window_event(window: &Window, event: &WindowEvent) {
+    if window.label() != MAIN_WINDOW {
+        return;
+    }
+    if let WindowEvent::CloseRequested { api, .. } = event {
+        let behavior = window
+            .state::<CloseBehaviorState>()
+            .0
+            .lock()
+            .map(|b| *b)
+            .unwrap_or_default();
+        if behavior == CloseBehavior::MinimizeToTray {
+            api.prevent_close();
+            let _ = window.hide();
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_close_behavior(state: State<'_, CloseBehaviorState>) -> CloseBehavior {
+    state.0.lock().map(|b| *b).unwrap_or_default()
+}
+
+/// Set what closing the main window does: `quit` or `minimize_to_tray`. The
+/// latter fails with `unsupported` where the session shows no tray icons.
+#[tauri::command]
+pub async fn set_close_behavior(app: AppHandle, mode: String) -> Result<CloseBehavior, CommandError> {
+    let behavior = CloseBehavior::parse(&mode)?;
+    if behavior == CloseBehavior::MinimizeToTray {
+        platform::require(&app, Feature::Tray)?;
+    }
+    db::with_conn(&app, move |conn| settings::set(conn, CLOSE_BEHAVIOR_KEY, &behavior)).await?;
+    if let Ok(mut current) = app.state::<CloseBehaviorState>().0.lock() {
+        *current = behavior;
+    }
+    // Tray icons have to be created and removed on the main thread
+    let handle = app.clone();
+    app.run_on_main_thread(move || {
+        if let Err(e) = apply_close_behavior(&handle, behavior) {
+            eprintln!("[Window] Failed to apply close behavior: {}", e);
+        }
+    })
+    .map_err(|e| e.to_string())?;
+    Ok(behavior)
+}
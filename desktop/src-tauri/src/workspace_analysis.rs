@@ -0,0 +1,465 @@
+// What kind of project a working directory is, so the agent doesn't spend turns
+// finding out.
+//
+// `analyze` walks the directory breadth-first under the workspace ignore rules and
+// matches file names against `RULES` (ecosystems, lockfiles, test frameworks,
+// tooling) and extensions against `LANGUAGES`. The walk stops at `MAX_DEPTH`,
+// `MAX_FILES` or the time budget; a cut-short walk is returned as it stands with
+// `partial` set. Results are cached per directory and reused while the
+// fingerprint of its top-level entries (names and mtimes) is unchanged.
+//
+// `create_task` analyzes a task's working_dir, stores the summary on the task as
+// `workspace_analysis` and returns it with the created task for the sidecar payload.
+// Directories on network volumes are analyzed under `volumes::guard` and flagged.
+
+use crate::error::CommandError;
+use crate::{db, volumes};
+use crate::workspace_ignore::WorkspaceRules;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+const TIME_BUDGET: Duration = Duration::from_millis(1500);
+const MAX_DEPTH: usize = 8;
+const MAX_FILES: u64 = 200_000;
+/// Paths kept as evidence per detection, and nested project folders reported
+const MAX_EVIDENCE: usize = 5;
+const MAX_PROJECTS: usize = 50;
+const CACHE_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Ecosystem,
+    PackageManager,
+    TestFramework,
+    Tool,
+}
+
+use Category::*;
+
+/// File name, what finding it means, and the name reported
+const RULES: &[(&str, Category, &str)] = &[
+    ("package.json", Ecosystem, "node"),
+    ("Cargo.toml", Ecosystem, "rust"),
+    ("pyproject.toml", Ecosystem, "python"),
+    ("setup.py", Ecosystem, "python"),
+    ("requirements.txt", Ecosystem, "python"),
+    ("go.mod", Ecosystem, "go"),
+    ("Gemfile", Ecosystem, "ruby"),
+    ("pom.xml", Ecosystem, "java"),
+    ("build.gradle", Ecosystem, "java"),
+    ("build.gradle.kts", Ecosystem, "kotlin"),
+    ("composer.json", Ecosystem, "php"),
+    ("mix.exs", Ecosystem, "elixir"),
+    ("pnpm-lock.yaml", PackageManager, "pnpm"),
+    ("yarn.lock", PackageManager, "yarn"),
+    ("package-lock.json", PackageManager, "npm"),
+    ("bun.lockb", PackageManager, "bun"),
+    ("bun.lock", PackageManager, "bun"),
+    ("Cargo.lock", PackageManager, "cargo"),
+    ("poetry.lock", PackageManager, "poetry"),
+    ("uv.lock", PackageManager, "uv"),
+    ("Pipfile.lock", PackageManager, "pipenv"),
+    ("go.sum", PackageManager, "go modules"),
+    ("Gemfile.lock", PackageManager, "bundler"),
+    ("composer.lock", PackageManager, "composer"),
+    ("jest.config.js", TestFramework, "jest"),
+    ("jest.config.ts", TestFramework, "jest"),
+    ("vitest.config.ts", TestFramework, "vitest"),
+    ("vitest.config.js", TestFramework, "vitest"),
+    ("vitest.workspace.ts", TestFramework, "vitest"),
+    ("playwright.config.ts", TestFramework, "playwright"),
+    ("cypress.config.ts", TestFramework, "cypress"),
+    ("cypress.config.js", TestFramework, "cypress"),
+    ("pytest.ini", TestFramework, "pytest"),
+    ("conftest.py", TestFramework, "pytest"),
+    ("tox.ini", TestFramework, "tox"),
+    (".rspec", TestFramework, "rspec"),
+    ("phpunit.xml", TestFramework, "phpunit"),
+    ("Dockerfile", Tool, "docker"),
+    ("docker-compose.yml", Tool, "docker compose"),
+    ("docker-compose.yaml", Tool, "docker compose"),
+    ("compose.yaml", Tool, "docker compose"),
+    ("pnpm-workspace.yaml", Tool, "pnpm workspaces"),
+    ("turbo.json", Tool, "turborepo"),
+    ("nx.json", Tool, "nx"),
+    ("lerna.json", Tool, "lerna"),
+    ("Makefile", Tool, "make"),
+    ("justfile", Tool, "just"),
+    ("tsconfig.json", Tool, "typescript"),
+    ("tauri.conf.json", Tool, "tauri"),
+];
+
+/// Lower-case extension and the language it's counted as
+const LANGUAGES: &[(&str, &str)] = &[
+    ("rs", "Rust"),
+    ("ts", "TypeScript"),
+    ("tsx", "TypeScript"),
+    ("mts", "TypeScript"),
+    ("js", "JavaScript"),
+    ("jsx", "JavaScript"),
+    ("mjs", "JavaScript"),
+    ("cjs", "JavaScript"),
+    ("py", "Python"),
+    ("go", "Go"),
+    ("rb", "Ruby"),
+    ("java", "Java"),
+    ("kt", "Kotlin"),
+    ("swift", "Swift"),
+    ("c", "C"),
+    ("h", "C"),
+    ("cc", "C++"),
+    ("cpp", "C++"),
+    ("hpp", "C++"),
+    ("cs", "C#"),
+    ("php", "PHP"),
+    ("ex", "Elixir"),
+    ("exs", "Elixir"),
+    ("vue", "Vue"),
+    ("svelte", "Svelte"),
+    ("css", "CSS"),
+    ("scss", "CSS"),
+    ("html", "HTML"),
+    ("sql", "SQL"),
+    ("sh", "Shell"),
+    ("md", "Markdown"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Detection {
+    pub category: Category,
+    pub name: String,
+    /// Where it was seen, relative to the root, shallowest first
+    pub evidence: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LanguageCount {
+    pub language: String,
+    pub files: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceAnalysis {
+    pub root: String,
+    pub fingerprint: String,
+    pub analyzed_at: String,
+    pub detections: Vec<Detection>,
+    /// Most files first
+    pub languages: Vec<LanguageCount>,
+    /// Folders below the root with their own ecosystem manifest, e.g. monorepo packages
+    pub projects: Vec<String>,
+    pub files_scanned: u64,
+    pub dirs_scanned: u64,
+    /// The walk hit a bound before seeing everything
+    pub partial: bool,
+    pub partial_reason: Option<String>,
+    pub elapsed_ms: u64,
+    /// The directory is on a network volume (SMB, NFS, ...)
+    #[serde(default)]
+    pub network_volume: bool,
+}
+
+/// Analyses by directory
+#[derive(Default)]
+pub struct AnalysisCache(Mutex<HashMap<PathBuf, WorkspaceAnalysis>>);
+
+fn mtime(meta: &std::fs::Metadata) -> u128 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos())
+}
+
+/// Names and mtimes of the root and its direct entries
+fn fingerprint(root: &Path) -> Result<String, String> {
+    let meta = std::fs::metadata(root).map_err(|e| format!("Cannot read {}: {}", root.display(), e))?;
+    let mut entries: Vec<(String, u128)> = std::fs::read_dir(root)
+        .map_err(|e| format!("Cannot read {}: {}", root.display(), e))?
+        .flatten()
+        .map(|entry| {
+            let modified = entry.metadata().map(|m| mtime(&m)).unwrap_or_default();
+            (entry.file_name().to_string_lossy().into_owned(), modified)
+        })
+        .collect();
+    entries.sort();
+    let mut hasher = Sha256::new();
+    hasher.update(mtime(&meta).to_le_bytes());
+    for (name, modified) in entries {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(modified.to_le_bytes());
+    }
+    Ok(format!("{:x}", hasher.finalize())[..16].to_string())
+}
+
+/// Walk `rules.root()` for at most `budget`
+pub fn analyze(rules: &WorkspaceRules, budget: Duration) -> Result<WorkspaceAnalysis, String> {
+    let started = Instant::now();
+    let root = rules.root();
+    let fingerprint = fingerprint(root)?;
+    let mut detections: Vec<Detection> = Vec::new();
+    let mut languages: BTreeMap<&str, u64> = BTreeMap::new();
+    let mut projects = Vec::new();
+    let (mut files_scanned, mut dirs_scanned) = (0u64, 0u64);
+    let mut partial_reason = None;
+
+    let mut queue = VecDeque::from([(root.to_path_buf(), 0usize)]);
+    'walk: while let Some((dir, depth)) = queue.pop_front() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        dirs_scanned += 1;
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            if started.elapsed() >= budget {
+                partial_reason = Some(format!("stopped after {} ms", budget.as_millis()));
+                break 'walk;
+            }
+            if files_scanned >= MAX_FILES {
+                partial_reason = Some(format!("stopped after {} files", MAX_FILES));
+                break 'walk;
+            }
+            let Ok(file_type) = entry.file_type() else { continue };
+            if file_type.is_symlink() {
+                continue;
+            }
+            let path = entry.path();
+            if file_type.is_dir() {
+                if rules.is_ignored(&path, true) {
+                    continue;
+                }
+                if depth + 1 < MAX_DEPTH {
+                    queue.push_back((path, depth + 1));
+                } else if partial_reason.is_none() {
+                    partial_reason = Some(format!("folders deeper than {} levels were skipped", MAX_DEPTH));
+                }
+                continue;
+            }
+            if rules.is_ignored(&path, false) {
+                continue;
+            }
+            files_scanned += 1;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+            for &(_, category, detected) in RULES.iter().filter(|(file, _, _)| *file == name) {
+                match detections.iter_mut().find(|d| d.category == category && d.name == detected) {
+                    Some(detection) if detection.evidence.len() < MAX_EVIDENCE => detection.evidence.push(relative.clone()),
+                    Some(_) => {}
+                    None => detections.push(Detection {
+                        category,
+                        name: detected.to_string(),
+                        evidence: vec![relative.clone()],
+                    }),
+                }
+                if category == Ecosystem && depth > 0 && projects.len() < MAX_PROJECTS {
+                    let folder = dir.strip_prefix(root).unwrap_or(&dir).to_string_lossy().replace('\\', "/");
+                    if !projects.contains(&folder) {
+                        projects.push(folder);
+                    }
+                }
+            }
+            let extension = path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+            if let Some((_, language)) = LANGUAGES.iter().find(|(ext, _)| Some(*ext) == extension.as_deref()) {
+                *languages.entry(language).or_default() += 1;
+            }
+        }
+    }
+
+    let mut languages: Vec<LanguageCount> = languages
+        .into_iter()
+        .map(|(language, files)| LanguageCount {
+            language: language.to_string(),
+            files,
+        })
+        .collect();
+    languages.sort_by(|a, b| b.files.cmp(&a.files).then_with(|| a.language.cmp(&b.language)));
+    Ok(WorkspaceAnalysis {
+        root: root.to_string_lossy().into_owned(),
+        fingerprint,
+        analyzed_at: chrono::Utc::now().to_rfc3339(),
+        detections,
+        languages,
+        projects,
+        files_scanned,
+        dirs_scanned,
+        partial: partial_reason.is_some(),
+        partial_reason,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        network_volume: false,
+    })
+}
+
+/// `analyze` unless the cache has a result for the same fingerprint
+fn analyze_cached(cache: &AnalysisCache, rules: &WorkspaceRules) -> Result<WorkspaceAnalysis, String> {
+    let root = rules.root().to_path_buf();
+    let current = fingerprint(&root)?;
+    if let Some(cached) = cache.0.lock().ok().and_then(|c| c.get(&root).cloned()) {
+        if cached.fingerprint == current {
+            return Ok(cached);
+        }
+    }
+    let analysis = analyze(rules, TIME_BUDGET)?;
+    if analysis.partial {
+        println!(
+            "[Workspace] Partial analysis of {}: {}",
+            root.display(),
+            analysis.partial_reason.as_deref().unwrap_or_default()
+        );
+    }
+    if let Ok(mut cache) = cache.0.lock() {
+        if cache.len() >= CACHE_SIZE && !cache.contains_key(&root) {
+            let oldest = cache
+                .iter()
+                .min_by(|a, b| a.1.analyzed_at.cmp(&b.1.analyzed_at))
+                .map(|(path, _)| path.clone());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+        cache.insert(root, analysis.clone());
+    }
+    Ok(analysis)
+}
+
+/// Analyze `path` with its ignore rules, off the async runtime. A network folder
+/// that doesn't answer fails with `VolumeUnavailable` instead of hanging.
+pub(crate) async fn analyze_dir(app: &AppHandle, path: PathBuf) -> Result<WorkspaceAnalysis, CommandError> {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let handle = app.clone();
+        let root = path.clone();
+        let mut analysis = volumes::guard(&app, &path, move || {
+            if !root.is_dir() {
+                return Err(format!("{} is not a folder", root.display()));
+            }
+            let rules = WorkspaceRules::load(&db::open(&handle)?, &root)?;
+            analyze_cached(&handle.state::<AnalysisCache>(), &rules)
+        })?;
+        analysis.network_volume = volumes::is_network_path(&path);
+        Ok(analysis)
+    })
+    .await
+    .map_err(|e| CommandError::from(e.to_string()))?
+}
+
+/// Summarize the ecosystems, tooling and languages in `path`
+#[tauri::command]
+pub async fn analyze_workspace(app: AppHandle, path: String) -> Result<WorkspaceAnalysis, CommandError> {
+    analyze_dir(&app, PathBuf::from(path)).await
+}
+
+/// The analysis stored on a task when it was created, if it had a working_dir
+#[tauri::command]
+pub async fn get_workspace_analysis(app: AppHandle, task_id: String) -> Result<Option<WorkspaceAnalysis>, String> {
+    db::with_conn(&app, move |conn| {
+        let stored: Option<String> = conn
+            .query_row("SELECT workspace_analysis FROM tasks WHERE id = ?1", [&task_id], |row| row.get(0))
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => format!("Task '{}' not found", task_id),
+                e => e.to_string(),
+            })?;
+        stored
+            .map(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .transpose()
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workany-analysis-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    fn names(analysis: &WorkspaceAnalysis, category: Category) -> Vec<&str> {
+        analysis
+            .detections
+            .iter()
+            .filter(|d| d.category == category)
+            .map(|d| d.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn pnpm_monorepo_with_rust_crates() {
+        let dir = fixture(
+            "mono",
+            &[
+                "package.json",
+                "pnpm-lock.yaml",
+                "pnpm-workspace.yaml",
+                "vitest.workspace.ts",
+                "packages/web/package.json",
+                "packages/web/src/app.tsx",
+                "packages/web/src/main.ts",
+                "crates/core/Cargo.toml",
+                "crates/core/src/lib.rs",
+                "Cargo.lock",
+                "node_modules/left-pad/package.json",
+                "node_modules/left-pad/index.js",
+            ],
+        );
+        let rules = WorkspaceRules::compile(&dir, &[]).unwrap();
+        let analysis = analyze(&rules, TIME_BUDGET).unwrap();
+
+        assert_eq!(names(&analysis, Ecosystem), vec!["node", "rust"]);
+        assert_eq!(names(&analysis, PackageManager), vec!["cargo", "pnpm"]);
+        assert_eq!(names(&analysis, TestFramework), vec!["vitest"]);
+        assert!(names(&analysis, Tool).contains(&"pnpm workspaces"));
+        let node = analysis.detections.iter().find(|d| d.name == "node").unwrap();
+        assert_eq!(node.evidence, vec!["package.json", "packages/web/package.json"]);
+        assert_eq!(analysis.projects, vec!["crates/core", "packages/web"]);
+        assert_eq!(
+            analysis.languages,
+            vec![
+                LanguageCount { language: "TypeScript".into(), files: 3 },
+                LanguageCount { language: "Rust".into(), files: 1 },
+            ]
+        );
+        assert!(!analysis.partial);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn running_out_of_time_returns_a_flagged_partial_result() {
+        let dir = fixture("slow", &["go.mod", "main.go", "cmd/tool/main.go"]);
+        let rules = WorkspaceRules::compile(&dir, &[]).unwrap();
+        let analysis = analyze(&rules, Duration::ZERO).unwrap();
+        assert!(analysis.partial);
+        assert!(analysis.partial_reason.is_some());
+        assert_eq!(analysis.files_scanned, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache_is_reused_until_the_top_level_changes() {
+        let dir = fixture("cache", &["Gemfile", "app.rb"]);
+        let rules = WorkspaceRules::compile(&dir, &[]).unwrap();
+        let cache = AnalysisCache::default();
+        let first = analyze_cached(&cache, &rules).unwrap();
+        let again = analyze_cached(&cache, &rules).unwrap();
+        assert_eq!(first.analyzed_at, again.analyzed_at);
+
+        std::fs::write(dir.join("Gemfile.lock"), "").unwrap();
+        let changed = analyze_cached(&cache, &rules).unwrap();
+        assert_ne!(changed.fingerprint, first.fingerprint);
+        assert_eq!(names(&changed, PackageManager), vec!["bundler"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+// Which files in a session workspace are left out of walks and the files table.
+//
+// Three sources, in gitignore syntax, are compiled into one matcher: a built-in set
+// (dependency folders, build output, OS litter), the session's override lines, and a
+// `.workanyignore` at the workspace root. Later sources win, so `!dist/` in a
+// session's overrides brings the build folder back. Workspace walks (the file scan,
+// diagnostic listings) take a `WorkspaceRules` instead of hard-coding folder names.
+//
+// Changing a session's overrides runs a reconciliation job over its files rows:
+// rows that are now ignored are deleted, except favorites, which are marked with
+// `ignored_at` instead; marked rows that are included again are unmarked.
+
+use crate::{db, file_repair, integrity, jobs, settings};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use rusqlite::Connection;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+pub const IGNORE_FILE: &str = ".workanyignore";
+const OVERRIDES_KEY: &str = "workspace_ignore_overrides";
+const MAX_OVERRIDES: usize = 200;
+const BUILT_IN: &[&str] = &[
+    "node_modules/",
+    ".git/",
+    "target/",
+    "dist/",
+    "__pycache__/",
+    ".venv/",
+    "venv/",
+    "*.pyc",
+    ".DS_Store",
+];
+/// Stand-ins for where a line came from, as reported by the matcher
+const BUILT_IN_SOURCE: &str = "<built-in>";
+const SESSION_SOURCE: &str = "<session>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleSource {
+    BuiltIn,
+    Session,
+    IgnoreFile,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleVerdict {
+    pub path: String,
+    pub ignored: bool,
+    /// The line that decided, if any did
+    pub source: Option<RuleSource>,
+    pub pattern: Option<String>,
+    /// Whether the decision came from a parent folder's rule
+    pub via_parent: bool,
+}
+
+pub struct WorkspaceRules {
+    root: PathBuf,
+    matcher: Gitignore,
+}
+
+impl WorkspaceRules {
+    /// Compile the built-in set, `overrides` and the root's `.workanyignore`
+    pub fn compile(root: &Path, overrides: &[String]) -> Result<Self, String> {
+        let mut builder = GitignoreBuilder::new(root);
+        for line in BUILT_IN {
+            builder
+                .add_line(Some(PathBuf::from(BUILT_IN_SOURCE)), line)
+                .map_err(|e| e.to_string())?;
+        }
+        for line in overrides {
+            builder
+                .add_line(Some(PathBuf::from(SESSION_SOURCE)), line)
+                .map_err(|e| format!("Invalid ignore rule '{}': {}", line, e))?;
+        }
+        let file = root.join(IGNORE_FILE);
+        if file.is_file() {
+            if let Some(e) = builder.add(&file) {
+                // A bad line leaves the rest of the file in effect
+                eprintln!("[WorkspaceIgnore] {}: {}", file.display(), e);
+            }
+        }
+        Ok(WorkspaceRules {
+            root: root.to_path_buf(),
+            matcher: builder.build().map_err(|e| e.to_string())?,
+        })
+    }
+
+    /// Rules for a workspace folder, with the overrides of the session it belongs to
+    pub fn load(conn: &Connection, root: &Path) -> Result<Self, String> {
+        let overrides = session_of(root)
+            .map(|session_id| overrides_for(conn, &session_id))
+            .transpose()?
+            .unwrap_or_default();
+        Self::compile(root, &overrides)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// `path` relative to the root; `None` for absolute paths outside it
+    fn relative<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        if path.is_relative() {
+            Some(path)
+        } else {
+            path.strip_prefix(&self.root).ok()
+        }
+    }
+
+    pub fn verdict(&self, path: &Path, is_dir: bool) -> RuleVerdict {
+        let Some(relative) = self.relative(path) else {
+            return RuleVerdict {
+                path: path.to_string_lossy().into_owned(),
+                ignored: false,
+                source: None,
+                pattern: None,
+                via_parent: false,
+            };
+        };
+        let mut via_parent = false;
+        let mut matched = self.matcher.matched(relative, is_dir);
+        if matched.is_none() {
+            matched = self.matcher.matched_path_or_any_parents(relative, is_dir);
+            via_parent = !matched.is_none();
+        }
+        let (ignored, glob) = match matched {
+            Match::None => (false, None),
+            Match::Ignore(glob) => (true, Some(glob)),
+            Match::Whitelist(glob) => (false, Some(glob)),
+        };
+        RuleVerdict {
+            path: relative.to_string_lossy().into_owned(),
+            ignored,
+            source: glob.map(|glob| match glob.from().and_then(|p| p.to_str()) {
+                Some(BUILT_IN_SOURCE) => RuleSource::BuiltIn,
+                Some(SESSION_SOURCE) => RuleSource::Session,
+                _ => RuleSource::IgnoreFile,
+            }),
+            pattern: glob.map(|glob| glob.original().to_string()),
+            via_parent,
+        }
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.relative(path)
+            .is_some_and(|relative| self.matcher.matched_path_or_any_parents(relative, is_dir).is_ignore())
+    }
+}
+
+/// The session a workspace folder belongs to, for folders under the sessions dir
+fn session_of(root: &Path) -> Option<String> {
+    let sessions = file_repair::sessions_dir()?;
+    let relative = root.strip_prefix(sessions).ok()?;
+    let mut parts = relative.components();
+    let session_id = parts.next()?.as_os_str().to_str()?.to_string();
+    parts.next().is_none().then_some(session_id)
+}
+
+fn workspace_of(session_id: &str) -> Result<PathBuf, String> {
+    file_repair::sessions_dir()
+        .map(|dir| dir.join(session_id))
+        .ok_or_else(|| "No home directory".to_string())
+}
+
+fn overrides_for(conn: &Connection, session_id: &str) -> Result<Vec<String>, String> {
+    let all: HashMap<String, Vec<String>> = settings::get_or(conn, OVERRIDES_KEY, HashMap::new())?;
+    Ok(all.get(session_id).cloned().unwrap_or_default())
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Reconciliation {
+    pub deleted: u32,
+    pub marked: u32,
+    pub unmarked: u32,
+}
+
+/// Bring a session's files rows in line with its workspace rules
+pub fn reconcile(conn: &mut Connection, rules: &WorkspaceRules, session_id: &str) -> Result<Reconciliation, String> {
+    let rows: Vec<(i64, String, bool, bool)> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT f.id, f.path, f.is_favorite, f.ignored_at IS NOT NULL
+                 FROM files f JOIN tasks t ON t.id = f.task_id
+                 WHERE t.session_id = ?1",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([session_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+
+    let mut result = Reconciliation::default();
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    for (id, path, favorite, marked) in rows {
+        let path = Path::new(&path);
+        if !path.starts_with(rules.root()) {
+            continue;
+        }
+        match (rules.is_ignored(path, false), favorite, marked) {
+            (true, false, _) => {
+                tx.execute("DELETE FROM files WHERE id = ?1", [id]).map_err(|e| e.to_string())?;
+                result.deleted += 1;
+            }
+            (true, true, false) => {
+                tx.execute("UPDATE files SET ignored_at = datetime('now') WHERE id = ?1", [id])
+                    .map_err(|e| e.to_string())?;
+                result.marked += 1;
+            }
+            (false, _, true) => {
+                tx.execute("UPDATE files SET ignored_at = NULL WHERE id = ?1", [id])
+                    .map_err(|e| e.to_string())?;
+                result.unmarked += 1;
+            }
+            _ => {}
+        }
+    }
+    integrity::record_deletion(&tx, "files", "workspace_ignore", result.deleted)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+/// How the rules of a workspace treat each sample path, relative or absolute
+#[tauri::command]
+pub async fn test_ignore_rules(
+    app: AppHandle,
+    workspace_path: String,
+    sample_paths: Vec<String>,
+) -> Result<Vec<RuleVerdict>, String> {
+    db::with_conn(&app, move |conn| {
+        let root = PathBuf::from(&workspace_path);
+        let rules = WorkspaceRules::load(conn, &root)?;
+        Ok(sample_paths
+            .iter()
+            .map(|sample| {
+                let path = root.join(sample.trim_end_matches('/'));
+                let is_dir = sample.ends_with('/') || path.is_dir();
+                rules.verdict(&path, is_dir)
+            })
+            .collect())
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_workspace_ignore_overrides(app: AppHandle, session_id: String) -> Result<Vec<String>, String> {
+    db::with_conn(&app, move |conn| overrides_for(conn, &session_id)).await
+}
+
+/// Replace a session's override lines and reconcile its files rows in a job
+#[tauri::command]
+pub async fn set_workspace_ignore_overrides(
+    app: AppHandle,
+    session_id: String,
+    patterns: Vec<String>,
+) -> Result<u64, String> {
+    let patterns: Vec<String> = patterns
+        .iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty() && !p.starts_with('#'))
+        .collect();
+    if patterns.len() > MAX_OVERRIDES {
+        return Err(format!("At most {} ignore rules per session", MAX_OVERRIDES));
+    }
+    let root = workspace_of(&session_id)?;
+    // Reject bad lines before anything is saved
+    WorkspaceRules::compile(&root, &patterns)?;
+    let id = session_id.clone();
+    db::with_conn(&app, move |conn| {
+        let mut all: HashMap<String, Vec<String>> = settings::get_or(conn, OVERRIDES_KEY, HashMap::new())?;
+        if patterns.is_empty() {
+            all.remove(&id);
+        } else {
+            all.insert(id, patterns);
+        }
+        settings::set(conn, OVERRIDES_KEY, &all)
+    })
+    .await?;
+
+    Ok(jobs::spawn(&app, "reconcile_workspace_ignore", move |ctx| {
+        let mut conn = db::open(ctx.app())?;
+        let rules = WorkspaceRules::load(&conn, &root)?;
+        let result = reconcile(&mut conn, &rules, &session_id)?;
+        if result.deleted > 0 {
+            db::emit_change(ctx.app(), "files", "delete", Vec::new());
+        }
+        println!(
+            "[WorkspaceIgnore] Session {}: {} deleted, {} marked, {} unmarked",
+            session_id, result.deleted, result.marked, result.unmarked
+        );
+        Ok(json!(result))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    fn rules(dir: &Path, overrides: &[&str], file: Option<&str>) -> WorkspaceRules {
+        std::fs::create_dir_all(dir).unwrap();
+        if let Some(contents) = file {
+            std::fs::write(dir.join(IGNORE_FILE), contents).unwrap();
+        }
+        let overrides: Vec<String> = overrides.iter().map(|s| s.to_string()).collect();
+        WorkspaceRules::compile(dir, &overrides).unwrap()
+    }
+
+    #[test]
+    fn later_sources_win() {
+        let dir = std::env::temp_dir().join(format!("workany-ignore-{}", std::process::id()));
+        let rules = rules(&dir, &["!dist/", "*.log"], Some("keep.log\n!keep.log\nsecret/\n"));
+
+        assert!(rules.is_ignored(&dir.join("node_modules/react/index.js"), false));
+        assert!(rules.is_ignored(&dir.join("pkg/__pycache__/mod.pyc"), false));
+        assert!(!rules.is_ignored(&dir.join("dist/app.js"), false));
+        assert!(rules.is_ignored(&dir.join("run.log"), false));
+        assert!(!rules.is_ignored(&dir.join("keep.log"), false));
+        assert!(!rules.is_ignored(&dir.join("src/main.rs"), false));
+
+        let verdict = rules.verdict(&dir.join("secret/key.pem"), false);
+        assert!(verdict.ignored && verdict.via_parent);
+        assert_eq!(verdict.source, Some(RuleSource::IgnoreFile));
+        assert_eq!(verdict.pattern.as_deref(), Some("secret/"));
+        let verdict = rules.verdict(&dir.join(".git"), true);
+        assert_eq!(verdict.source, Some(RuleSource::BuiltIn));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reconciliation_keeps_favorites_marked() {
+        let dir = std::env::temp_dir().join(format!("workany-ignore-rec-{}", std::process::id()));
+        let mut conn = crate::migrations::test_db();
+        conn.execute("INSERT INTO tasks (id, prompt, session_id) VALUES ('t1', 'p', 's1')", [])
+            .unwrap();
+        for (path, favorite) in [("build/out.bin", false), ("build/report.pdf", true), ("src/a.rs", false)] {
+            conn.execute(
+                "INSERT INTO files (task_id, name, type, path, is_favorite) VALUES ('t1', 'f', 'code', ?1, ?2)",
+                params![dir.join(path).to_string_lossy(), favorite],
+            )
+            .unwrap();
+        }
+
+        let first = reconcile(&mut conn, &rules(&dir, &["build/"], None), "s1").unwrap();
+        assert_eq!((first.deleted, first.marked, first.unmarked), (1, 1, 0));
+        let second = reconcile(&mut conn, &rules(&dir, &[], None), "s1").unwrap();
+        assert_eq!((second.deleted, second.marked, second.unmarked), (0, 0, 1));
+        let remaining: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0)).unwrap();
+        assert_eq!(remaining, 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,5 +1,6 @@
 import type { LibraryFile } from '@/shared/db';
 import { cn } from '@/shared/lib/utils';
+import { convertFileSrc } from '@tauri-apps/api/core';
 import {
   Code,
   File,
@@ -57,18 +58,30 @@ function getCodeColor(filename: string): string {
   }
 }
 
+// Thumbnail URL by the row's media format: stored rows load from the thumbnail
+// store, legacy rows (until the media backfill reaches them) carry a data URL
+function thumbnailSrc(file: LibraryFile): string | null {
+  if (file.media_format === 1) {
+    return file.thumbnail_hash
+      ? convertFileSrc(file.thumbnail_hash, 'workany-thumb')
+      : null;
+  }
+  return file.thumbnail;
+}
+
 export function FileCard({ file, onToggleFavorite }: FileCardProps) {
   const FileIcon = getFileIcon(file.type);
+  const thumbnail = thumbnailSrc(file);
 
   // Image/Website card with thumbnail
-  if (file.type === 'image' || (file.type === 'website' && file.thumbnail)) {
+  if (file.type === 'image' || (file.type === 'website' && thumbnail)) {
     return (
       <div className="group border-border bg-card hover:border-primary/30 relative overflow-hidden rounded-xl border transition-all duration-200 hover:shadow-md">
         {/* Thumbnail */}
         <div className="bg-muted aspect-[4/3] overflow-hidden">
-          {file.thumbnail ? (
+          {thumbnail ? (
             <img
-              src={file.thumbnail}
+              src={thumbnail}
               alt={file.name}
               className="h-full w-full object-cover"
             />
@@ -8,6 +8,7 @@
 import { useEffect, useState, type ReactNode } from 'react';
 import { SetupPage } from '@/app/pages/Setup';
 import { API_BASE_URL } from '@/config';
+import { waitForApiReady } from '@/shared/lib/api-ready';
 import { useLanguage } from '@/shared/providers/language-provider';
 import { Loader2 } from 'lucide-react';
 
@@ -26,6 +27,8 @@ async function checkClaudeCode(): Promise<boolean> {
   const maxRetries = 10;
   const retryDelay = 500;
 
+  await waitForApiReady();
+
   for (let attempt = 0; attempt < maxRetries; attempt++) {
     try {
       const response = await fetch(`${API_BASE_URL}/health/dependencies`, {
@@ -12,14 +12,32 @@
 /**
  * API port configuration
  * - Development: 2026 (run `python desktop/api/main.py`)
- * - Production: 2026 (CloudWork API service)
+ * - Production: picked by the app at startup; `resolveApiPort` reads it
+ *   before the UI loads
  */
-export const API_PORT = import.meta.env.PROD ? 2026 : 2026;
+export let API_PORT = 2026;
 
 /**
  * API base URL - connects to CloudWork Python FastAPI
  */
-export const API_BASE_URL = `http://localhost:${API_PORT}`;
+export let API_BASE_URL = `http://localhost:${API_PORT}`;
+
+/**
+ * Ask the app which port the API sidecar uses. Must run before modules that
+ * copy API_BASE_URL at import time are loaded; the port is fixed per app run.
+ */
+export async function resolveApiPort(): Promise<void> {
+  if (typeof window === 'undefined' || !('__TAURI_INTERNALS__' in window)) {
+    return;
+  }
+  try {
+    const { invoke } = await import('@tauri-apps/api/core');
+    API_PORT = await invoke<number>('get_api_port');
+    API_BASE_URL = `http://localhost:${API_PORT}`;
+  } catch (error) {
+    console.error('[API] Cannot resolve the API port:', error);
+  }
+}
 
 // =============================================================================
 // App Configuration
@@ -0,0 +1,31 @@
+export default {
+  // Schedule descriptions, keyed as returned by preview_schedule
+  everyMinute: 'Every minute',
+  everyNMinutes: 'Every {n} minutes',
+  hourlyAt: 'Every hour at minute {minute}',
+  dailyAt: 'Every day at {time}',
+  weekdaysAt: 'Every weekday at {time}',
+  weekendsAt: 'Every Saturday and Sunday at {time}',
+  weeklyAt: 'Every {days} at {time}',
+  monthlyAt: 'On day {day} of every month at {time}',
+  yearlyAt: 'Every year on {month} {day} at {time}',
+  custom: 'On the schedule {expression}',
+  days: ['Sunday', 'Monday', 'Tuesday', 'Wednesday', 'Thursday', 'Friday', 'Saturday'],
+  months: [
+    'January',
+    'February',
+    'March',
+    'April',
+    'May',
+    'June',
+    'July',
+    'August',
+    'September',
+    'October',
+    'November',
+    'December',
+  ],
+  // DST notes on previewed runs
+  skipped: 'Moved to the end of the daylight saving gap',
+  ambiguous: 'First of the two repeated times',
+};
@@ -3,6 +3,7 @@ import home from './home';
 import library from './library';
 import nav from './nav';
 import preview from './preview';
+import schedule from './schedule';
 import settings from './settings';
 import setup from './setup';
 import task from './task';
@@ -16,4 +17,5 @@ export default {
   settings,
   library,
   setup,
+  schedule,
 };
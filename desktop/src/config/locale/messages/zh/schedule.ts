@@ -0,0 +1,31 @@
+export default {
+  // Schedule descriptions, keyed as returned by preview_schedule
+  everyMinute: '每分钟',
+  everyNMinutes: '每 {n} 分钟',
+  hourlyAt: '每小时第 {minute} 分钟',
+  dailyAt: '每天 {time}',
+  weekdaysAt: '每个工作日 {time}',
+  weekendsAt: '每周六和周日 {time}',
+  weeklyAt: '每{days} {time}',
+  monthlyAt: '每月 {day} 日 {time}',
+  yearlyAt: '每年{month}{day}日 {time}',
+  custom: '按计划 {expression}',
+  days: ['周日', '周一', '周二', '周三', '周四', '周五', '周六'],
+  months: [
+    '1月',
+    '2月',
+    '3月',
+    '4月',
+    '5月',
+    '6月',
+    '7月',
+    '8月',
+    '9月',
+    '10月',
+    '11月',
+    '12月',
+  ],
+  // DST notes on previewed runs
+  skipped: '因夏令时跳过，改在时间跳变后运行',
+  ambiguous: '重复时段中的第一次',
+};
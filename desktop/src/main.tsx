@@ -2,17 +2,19 @@ import React from 'react';
 import ReactDOM from 'react-dom/client';
 import { RouterProvider } from 'react-router-dom';
 
-import { router } from './app/router';
+import { resolveApiPort } from './config';
+import { isDatabaseAvailable } from './shared/db/database';
 import { initializeSettings } from './shared/db/settings';
 import { LanguageProvider } from './shared/providers/language-provider';
 import { ThemeProvider } from './shared/providers/theme-provider';
 
 import '@/config/style/global.css';
 
-// Initialize settings from database on startup, then render app
-initializeSettings()
-  .catch(console.error)
-  .finally(() => {
+// Initialize settings from database on startup, then render app. The router is
+// loaded only once the API port is known, since its pages read API_BASE_URL.
+Promise.all([resolveApiPort(), initializeSettings().catch(console.error)])
+  .then(() => import('./app/router'))
+  .then(({ router }) => {
     ReactDOM.createRoot(document.getElementById('root') as HTMLElement).render(
       <React.StrictMode>
         <LanguageProvider>
@@ -22,4 +24,11 @@ initializeSettings()
         </LanguageProvider>
       </React.StrictMode>
     );
-  });
+    // Ends the startup attempt the backend counts towards safe mode
+    if (isDatabaseAvailable()) {
+      import('@tauri-apps/api/event')
+        .then(({ emit }) => emit('app://ready'))
+        .catch(console.error);
+    }
+  })
+  .catch(console.error);
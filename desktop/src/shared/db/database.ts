@@ -3,9 +3,15 @@ import type {
   CreateMessageInput,
   CreateSessionInput,
   CreateTaskInput,
+  Delivery,
+  ExportPreset,
+  ExportPresetInput,
   LibraryFile,
   Message,
+  MessageHit,
+  MessageTypeRepair,
   Session,
+  TagCount,
   Task,
   UpdateTaskInput,
 } from './types';
@@ -105,6 +111,28 @@ let sqliteDb: Awaited<
   ReturnType<typeof import('@tauri-apps/plugin-sql').default.load>
 > | null = null;
 
+interface LegacySqlQuery {
+  fingerprint: string;
+  statement: string;
+  table: string | null;
+  replacement: string | null;
+}
+
+// Raw SQL from the webview is deprecated; surface each statement once per run in dev
+async function watchLegacySql() {
+  const { listen } = await import('@tauri-apps/api/event');
+  await listen<LegacySqlQuery>('sql://legacy-query', ({ payload }) => {
+    if (payload.replacement) {
+      console.error(
+        `[SQLite] Rejected raw ${payload.statement} on ${payload.table}; use the ${payload.replacement} command:`,
+        payload.fingerprint
+      );
+    } else {
+      console.warn('[SQLite] Deprecated raw SQL:', payload.fingerprint);
+    }
+  });
+}
+
 async function getSQLiteDatabase() {
   if (!isTauriSync()) {
     return null;
@@ -115,6 +143,9 @@ async function getSQLiteDatabase() {
       const Database = (await import('@tauri-apps/plugin-sql')).default;
       sqliteDb = await Database.load(SQLITE_DB_NAME);
       console.log('[SQLite] Database connected successfully');
+      if (import.meta.env.DEV) {
+        void watchLegacySql();
+      }
     } catch (error) {
       console.error('[SQLite] Failed to connect:', error);
       return null;
@@ -256,7 +287,7 @@ export async function getTasksBySessionId(sessionId: string): Promise<Task[]> {
   if (database) {
     try {
       const tasks = await database.select<Task[]>(
-        'SELECT * FROM tasks WHERE session_id = $1 ORDER BY task_index ASC',
+        'SELECT * FROM tasks WHERE session_id = $1 AND trashed_at IS NULL ORDER BY task_index ASC',
         [sessionId]
       );
       // Convert favorite from 0/1 to boolean for all tasks
@@ -364,7 +395,7 @@ export async function getAllTasks(): Promise<Task[]> {
 
   if (database) {
     const tasks = await database.select<Task[]>(
-      'SELECT * FROM tasks ORDER BY created_at DESC'
+      'SELECT * FROM tasks WHERE trashed_at IS NULL ORDER BY created_at DESC'
     );
     // Convert favorite from 0/1 to boolean for all tasks
     return tasks.map((task) => ({
@@ -590,6 +621,90 @@ export async function getMessagesByTaskId(taskId: string): Promise<Message[]> {
   }
 }
 
+// Full-text search across all tasks' messages, done in SQLite rather than by
+// loading every message; unavailable in the browser fallback
+export async function searchMessages(
+  query: string,
+  limit = 50
+): Promise<MessageHit[]> {
+  if (!isTauriSync()) return [];
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<MessageHit[]>('search_messages', { query, limit });
+}
+
+// Export presets and the delivered history live in SQLite only; the browser
+// fallback has none
+export async function listExportPresets(
+  projectId: string | null = null
+): Promise<ExportPreset[]> {
+  if (!isTauriSync()) return [];
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<ExportPreset[]>('list_export_presets', { projectId });
+}
+
+export async function saveExportPreset(
+  preset: ExportPresetInput,
+  id?: number
+): Promise<ExportPreset> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return id === undefined
+    ? invoke<ExportPreset>('create_export_preset', { preset })
+    : invoke<ExportPreset>('update_export_preset', { id, preset });
+}
+
+export async function deleteExportPreset(id: number): Promise<boolean> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<boolean>('delete_export_preset', { id });
+}
+
+// Starts the preset as a job and resolves with its id; the job's result is a
+// DeliveryReport
+export async function runExportPreset(
+  presetId: number,
+  sessionId: string
+): Promise<number> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<number>('run_export_preset', { presetId, sessionId });
+}
+
+export async function listDeliveries(
+  sessionId: string | null = null,
+  limit = 100
+): Promise<Delivery[]> {
+  if (!isTauriSync()) return [];
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<Delivery[]>('list_deliveries', { sessionId, limit });
+}
+
+export async function addTag(taskId: string, tag: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('add_tag', { taskId, tag });
+}
+
+export async function removeTag(taskId: string, tag: string): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('remove_tag', { taskId, tag });
+}
+
+export async function listTags(): Promise<TagCount[]> {
+  if (!isTauriSync()) return [];
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<TagCount[]>('list_tags');
+}
+
+export async function getTaskIdsByTag(tag: string): Promise<string[]> {
+  if (!isTauriSync()) return [];
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<string[]>('tasks_by_tag', { tag });
+}
+
+export async function normalizeMessageTypes(
+  dryRun = true
+): Promise<MessageTypeRepair> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<MessageTypeRepair>('normalize_message_types', { dryRun });
+}
+
 export async function deleteMessagesByTaskId(taskId: string): Promise<number> {
   const database = await getSQLiteDatabase();
 
@@ -613,6 +728,28 @@ export async function deleteMessagesByTaskId(taskId: string): Promise<number> {
   }
 }
 
+// Cost and duration go through report_task_metrics, which keeps every report so
+// late or revised costs replace the earlier value instead of being lost
+async function reportTaskMetrics(
+  taskId: string,
+  cost?: number,
+  duration?: number,
+  reason?: string
+): Promise<void> {
+  if (cost === undefined && duration === undefined) return;
+  if (!isTauriSync()) {
+    await updateTask(taskId, { cost, duration });
+    return;
+  }
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('report_task_metrics', {
+    taskId,
+    cost: cost ?? null,
+    duration: duration ?? null,
+    revisionReason: reason ?? null,
+  });
+}
+
 // Helper function to update task status based on message type
 export async function updateTaskFromMessage(
   taskId: string,
@@ -626,17 +763,22 @@ export async function updateTaskFromMessage(
     // error_max_turns means the task was interrupted, not completed
     // Keep it in 'running' state so user knows to continue
     if (subtype === 'success') {
-      await updateTask(taskId, { status: 'completed', cost, duration });
+      await updateTask(taskId, { status: 'completed' });
     } else if (subtype === 'error_max_turns') {
-      // Task hit max turns limit - keep as running, just update cost/duration
-      await updateTask(taskId, { cost, duration });
+      // Task hit max turns limit - keep as running, only the metrics change
       console.log(
         `[Database] Task ${taskId} hit max turns limit, keeping as running`
       );
     } else {
       // Other errors
-      await updateTask(taskId, { status: 'error', cost, duration });
+      await updateTask(taskId, { status: 'error' });
     }
+    await reportTaskMetrics(
+      taskId,
+      cost,
+      duration,
+      `result:${subtype ?? 'unknown'}`
+    );
   } else if (messageType === 'error') {
     await updateTask(taskId, { status: 'error' });
   }
@@ -20,6 +20,13 @@ export interface Task {
   cost: number | null;
   duration: number | null;
   favorite?: boolean; // Whether task is favorited
+  forked_from_task?: string | null; // Task this one was forked from
+  forked_from_message?: number | null; // Message in that task the fork starts after
+  routing?: string | null; // JSON: routing rules that placed this task and what they applied
+  retry_context?: string | null; // JSON: diagnostic context from the failed task this one explains
+  prompt_overflow_attachment_id?: number | null; // Set when `prompt` is only a preview; load the rest with get_full_prompt
+  row_version?: number; // Bumped on every write; ignore changes older than the one shown
+  trashed_at?: string | null; // In the trash since; restore with restore_task
   created_at: string;
   updated_at: string;
 }
@@ -31,7 +38,8 @@ export type MessageType =
   | 'result'
   | 'error'
   | 'user'
-  | 'plan';
+  | 'plan'
+  | 'permission_request';
 
 export interface Message {
   id: number;
@@ -45,6 +53,8 @@ export interface Message {
   subtype: string | null;
   error_message: string | null;
   attachments: string | null; // JSON string of MessageAttachment[]
+  redacted?: number; // 1 once content has been redacted in place
+  include_in_context?: number; // 1 when carried into later tasks of the session
   created_at: string;
 }
 
@@ -59,6 +69,8 @@ export interface CreateTaskInput {
   session_id: string;
   task_index: number;
   prompt: string;
+  model?: string; // sizes the context plan
+  context_overrides?: ContextOverrides;
 }
 
 export interface CreateMessageInput {
@@ -99,8 +111,16 @@ export interface LibraryFile {
   type: FileType;
   path: string;
   preview: string | null;
-  thumbnail: string | null;
+  thumbnail: string | null; // Legacy data URL; only read while media_format is 0
+  thumbnail_hash?: string | null; // Thumbnail store key, set once media_format is 1
+  media_format?: 0 | 1;
   is_favorite: boolean;
+  row_version?: number; // Bumped on every write; ignore changes older than the one shown
+  sha256?: string | null;
+  source_file_id?: number | null;
+  source_entry?: string | null;
+  scan_verdict?: 'clean' | 'flagged' | 'quarantined' | 'unscanned' | null;
+  ignored_at?: string | null; // Favorite kept after its path became ignored by the workspace rules
   created_at: string;
 }
 
@@ -112,3 +132,485 @@ export interface CreateFileInput {
   preview?: string;
   thumbnail?: string;
 }
+
+// A file stored for a session's working set (attachments.kind = 'file')
+export interface Attachment {
+  id: number;
+  name: string | null;
+  mime_type: string | null;
+  encoding: 'utf8' | 'base64';
+  size: number;
+  tokens: number | null; // null when no local estimate is possible
+  created_at: string;
+}
+
+export interface SessionFile {
+  attachment: Attachment;
+  position: number;
+  always_include: boolean;
+  added_at: string;
+}
+
+export interface WorkingSet {
+  session_id: string;
+  files: SessionFile[];
+  per_task_tokens: number;
+  uncounted_files: number;
+  warn_tokens: number;
+  warning: string | null;
+}
+
+// A file from an earlier task that the draft being composed seems to be about
+export type SuggestionSource =
+  | { kind: 'file'; id: number }
+  | { kind: 'attachment'; id: number };
+
+export interface AttachmentSuggestion {
+  source: SuggestionSource;
+  name: string;
+  file_type: string | null;
+  path: string | null;
+  task_index: number | null;
+  score: number;
+  reasons: string[]; // strongest first
+}
+
+// What goes into a task's context, trimmed to the model's window
+export type ContextKind =
+  | 'prompt'
+  | 'session_file'
+  | 'workspace_analysis'
+  | 'retry_context'
+  | 'pinned_message';
+
+export type ContextAction =
+  | { action: 'include' }
+  | { action: 'truncate'; to_tokens: number }
+  | { action: 'drop' };
+
+export type TrimRule =
+  | 'user_drop'
+  | 'truncate_retry_context'
+  | 'drop_workspace_analysis'
+  | 'drop_pinned_messages'
+  | 'drop_session_files';
+
+export interface ContextItem {
+  key: string; // prompt, session_file:<id>, workspace_analysis, retry_context or message:<id>
+  kind: ContextKind;
+  label: string;
+  tokens: number;
+  uncounted: boolean;
+  forced: boolean;
+  action: ContextAction;
+  planned_tokens: number;
+}
+
+export interface ContextOverrides {
+  include?: string[]; // item keys never trimmed
+  drop?: string[];
+}
+
+export interface ContextPlan {
+  task_id: string | null;
+  model: string | null;
+  tokenizer: string;
+  context_window: number;
+  reserve_tokens: number;
+  budget_tokens: number;
+  requested_tokens: number;
+  planned_tokens: number;
+  over_budget: boolean;
+  items: ContextItem[];
+  decisions: { rule: TrimRule; key: string; saved_tokens: number }[];
+  overrides: ContextOverrides;
+}
+
+// A review comment imported onto a message from a review packet
+export interface MessageNote {
+  id: number;
+  message_id: number;
+  packet_id: string;
+  reference: string; // M-014, F-003 or OP-027 in that packet
+  author: string;
+  body: string;
+  commented_at: string | null;
+  imported_at: string;
+}
+
+// Timeline day buckets, keyed by local date (YYYY-MM-DD)
+export interface TimelineBucket {
+  date: string;
+  tasks: number;
+  completed: number;
+  failed: number;
+  cost: number;
+  sessions_created: number;
+  deliverables: number;
+}
+
+export interface TimelineBuckets {
+  from: string;
+  to: string;
+  buckets: TimelineBucket[];
+  first_date: string | null;
+  focus_sessions: FocusSession[] | null; // with include_focus_sessions
+}
+
+export interface TimelineItem {
+  kind: 'session_created' | 'task' | 'task_failed' | 'deliverable';
+  at: number; // Unix seconds
+  session_id: string | null;
+  task_id: string | null;
+  file_id: number | null;
+  title: string;
+  status: TaskStatus | null;
+  cost: number | null;
+  file_type: FileType | null;
+  focus_session_id: number | null;
+}
+
+export interface TimelineDay {
+  date: string;
+  items: TimelineItem[];
+  next_cursor: string | null;
+}
+
+// Work done in one sitting, split by idle gaps of focus_gap_minutes
+export interface FocusSession {
+  id: number;
+  started_epoch: number; // Unix seconds
+  last_activity_epoch: number;
+  ended_epoch: number | null; // null while open
+  end_reason: 'gap' | 'manual' | null;
+  task_ids: string[];
+  cost: number;
+  duration: number;
+  deliverables: { file_id: number; name: string; file_type: FileType }[];
+}
+
+// Acknowledgement of a set_flag write
+export interface FlagAck {
+  flag: 'task_favorite' | 'file_favorite';
+  id: string;
+  value: boolean;
+  row_version: number;
+}
+
+// Top-level markdown block of a message, from get_message_blocks
+export type MessageBlockKind =
+  | { kind: 'paragraph' }
+  | { kind: 'heading'; level: number }
+  | { kind: 'code'; language: string | null; html: string | null }
+  | { kind: 'table' }
+  | { kind: 'list'; ordered: boolean }
+  | { kind: 'image'; src: string; alt: string }
+  | { kind: 'quote' }
+  | { kind: 'rule' }
+  | { kind: 'html' }
+  | { kind: 'raw' };
+
+export type MessageBlock = MessageBlockKind & {
+  index: number;
+  source: string;
+  text: string;
+};
+
+export interface MessageBlocks {
+  message_id: number;
+  content_hash: string;
+  blocks: MessageBlock[];
+  degraded: string | null;
+}
+
+// One call inside batch_invoke; see list_batchable_commands
+export interface BatchCall {
+  command: string;
+  args?: Record<string, unknown>;
+}
+
+export type BatchOutcome =
+  | { status: 'ok'; value: unknown }
+  | { status: 'err'; error: { kind: string; message: string } & Record<string, unknown> };
+
+// Human-only scratch note on a task; never sent to the agent
+export interface TaskNote {
+  task_id: string;
+  content: string;
+  row_version: number;
+  updated_at: string;
+}
+
+// What analyze_workspace found in a working directory
+export interface WorkspaceDetection {
+  category: 'ecosystem' | 'package_manager' | 'test_framework' | 'tool';
+  name: string;
+  evidence: string[];
+}
+
+export interface WorkspaceAnalysis {
+  root: string;
+  fingerprint: string;
+  analyzed_at: string;
+  detections: WorkspaceDetection[];
+  languages: { language: string; files: number }[];
+  projects: string[];
+  files_scanned: number;
+  dirs_scanned: number;
+  partial: boolean;
+  partial_reason: string | null;
+  elapsed_ms: number;
+  network_volume?: boolean; // on SMB, NFS or another network mount
+}
+
+// A network working directory of running tasks, from get_volume_status
+export interface VolumeStatus {
+  path: string;
+  available: boolean;
+  failures: number;
+  down_ms: number | null;
+  task_ids: string[];
+}
+
+// Result of export_interactive_viewer
+export interface ViewerExportReport {
+  path: string;
+  bytes: number;
+  payload_bytes: number;
+  compressed_bytes: number;
+  tasks: number;
+  messages: number;
+  attachments_inlined: number;
+  attachments_omitted: number;
+  warnings: string[];
+  manifest_path: string | null;
+}
+
+export interface ViewerValidation {
+  path: string;
+  valid: boolean;
+  version: number | null;
+  session_id: string | null;
+  tasks: number;
+  messages: number;
+  attachments: number;
+  problems: string[];
+}
+
+// get_safe_mode_report; the main window loads with ?safe=1 while active
+export interface StartupError {
+  attempt: string;
+  at: string;
+  message: string;
+  location: string | null;
+  thread: string | null;
+}
+
+export interface SafeModeReport {
+  active: boolean;
+  reason: string | null;
+  failed_attempts: {
+    id: string;
+    started_at: string;
+    safe_mode: boolean;
+    errors: StartupError[];
+  }[];
+  disabled_features: string[];
+  features: string[];
+  log_path: string | null;
+}
+
+// Platform webview caches and storage, and where the app's disk space goes
+export type WebviewStorageCategory = 'http_cache' | 'service_workers' | 'site_storage';
+
+export interface WebviewStorageUsage {
+  categories: {
+    category: WebviewStorageCategory;
+    bytes: number;
+    paths: string[];
+    requires_confirmation: boolean; // clearing needs force
+  }[];
+  total_bytes: number;
+  pending_cleanup: WebviewStorageCategory[]; // deleted at the next launch
+}
+
+export interface WebviewCleanup {
+  cleared: WebviewStorageCategory[];
+  freed_bytes: number;
+  scheduled: WebviewStorageCategory[];
+  used_platform_api: boolean;
+}
+
+export interface StorageBreakdown {
+  entries: {
+    name: 'database' | 'backups' | 'sessions' | 'thumbnails' | 'captures' | 'webview';
+    bytes: number;
+    path: string | null;
+  }[];
+  total_bytes: number;
+}
+
+// Message shapes unknown to this build, from get_schema_drift_report
+export interface DriftedShape {
+  kind: 'message_type' | 'subtype' | 'tool_name';
+  value: string;
+  occurrences: number;
+  sample_message_ids: number[];
+  first_seen_at: string;
+  last_seen_at: string;
+}
+
+export interface SchemaDriftReport {
+  shapes: DriftedShape[]; // most frequent first
+  checked_through: number; // last message id checked
+}
+
+// Saved task filters, from list_saved_filters and evaluate_filter. Unset fields
+// don't filter; every set field must match.
+export interface FilterRange {
+  min?: number;
+  max?: number;
+}
+
+export interface FilterDefinition {
+  statuses?: TaskStatus[];
+  tags?: string[];
+  tag_match?: 'all' | 'any';
+  session_id?: string;
+  created?: { from?: string; to?: string } | { last_days: number }; // YYYY-MM-DD, inclusive
+  cost?: FilterRange;
+  duration?: FilterRange;
+  favorite?: boolean;
+  has_deliverables?: boolean;
+  text?: string;
+}
+
+export interface SavedFilter {
+  id: number;
+  name: string;
+  definition: FilterDefinition;
+  pinned: boolean;
+  position: number;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface FilterPage {
+  tasks: {
+    id: string;
+    prompt: string;
+    status: TaskStatus;
+    cost: number | null;
+    duration: number | null;
+    session_id: string | null;
+    favorite: boolean;
+    created_at: string;
+    updated_at: string;
+  }[];
+  total: number;
+  next_cursor: string | null; // null on the last page
+  full_scans: string[]; // query plan steps not using an index
+}
+
+// Cost and duration reports for a task, from get_metric_history
+export interface MetricRevision {
+  id: number;
+  cost: number | null; // null when the report left it out
+  duration: number | null;
+  reason: string | null;
+  is_final: boolean;
+  reported_at: number; // Unix ms
+  cost_delta: number | null;
+}
+
+export interface MetricHistory {
+  task_id: string;
+  cost: number | null;
+  duration: number | null;
+  metrics_final: boolean;
+  revisions: MetricRevision[]; // oldest first
+}
+
+// Payload of task://metrics-updated
+export interface TaskMetricsUpdated {
+  task_id: string;
+  cost: number | null;
+  duration: number | null;
+  metrics_final: boolean;
+  reason: string | null;
+}
+
+// A message matching a search_messages query
+export interface MessageHit {
+  task_id: string;
+  message_id: number;
+  snippet: string; // matched terms between \u0002 and \u0003
+}
+
+// One step of an export preset; `path` is an absolute template that may use
+// {date}, {project} and {session}
+export type ExportStep =
+  | { kind: 'viewer'; path: string } // HTML file
+  | { kind: 'review_packet'; path: string; include_notes?: boolean } // directory
+  | {
+      kind: 'transcripts';
+      path: string; // directory
+      format: 'md' | 'json';
+      include_notes?: boolean;
+    }
+  | { kind: 'deliverables'; path: string }; // zip file
+
+export interface ExportPreset {
+  id: number;
+  project_id: string | null; // null for a global preset
+  name: string;
+  steps: ExportStep[];
+  created_at: string;
+  updated_at: string;
+}
+
+export interface ExportPresetInput {
+  project_id?: string | null;
+  name: string;
+  steps: ExportStep[];
+}
+
+// Result of a run_export_preset job
+export interface DeliveryReport {
+  run_id: string;
+  preset_id: number;
+  preset_name: string;
+  session_id: string;
+  completed: boolean;
+  artifacts: { step: number; kind: ExportStep['kind']; path: string; bytes: number }[];
+  failure: { step: number; kind: ExportStep['kind']; error: string } | null;
+}
+
+// A delivered artifact, from list_deliveries
+export interface Delivery {
+  id: number;
+  run_id: string;
+  preset_id: number | null; // null once the preset was deleted
+  preset_name: string;
+  project_id: string | null;
+  session_id: string;
+  step: number;
+  kind: ExportStep['kind'];
+  path: string;
+  bytes: number;
+  delivered_at: string;
+}
+
+export interface TagCount {
+  name: string; // trimmed and lowercased
+  color: string | null;
+  count: number; // tasks outside the trash
+}
+
+export interface MessageTypeRepair {
+  dry_run: boolean;
+  changes: { from: string; to: MessageType; rows: number }[];
+  unknown: { value: string; rows: number }[]; // left as stored
+  rows_changed: number;
+  guarded: boolean; // misspelled types are rejected from now on
+}
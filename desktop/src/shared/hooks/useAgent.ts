@@ -13,6 +13,7 @@ import {
   type Task,
 } from '@/shared/db';
 import { getSettings } from '@/shared/db/settings';
+import { waitForApiReady } from '@/shared/lib/api-ready';
 import {
   loadAttachments,
   saveAttachments,
@@ -83,6 +84,7 @@ async function fetchWithRetry(
 ): Promise<Response> {
   let lastError: Error | null = null;
   const t = getErrorMessages();
+  await waitForApiReady();
 
   for (let attempt = 0; attempt < maxRetries; attempt++) {
     try {
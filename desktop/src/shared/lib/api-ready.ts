@@ -0,0 +1,239 @@
+/**
+ * Waits for the API server before the first request.
+ *
+ * The backend polls the API health endpoint after starting the sidecar and
+ * reports `api://ready` or `api://unreachable`; `get_api_status` covers the case where
+ * that happened before this window started listening. When the sidecar crashes
+ * (`sidecar://crashed`) the backend respawns it, and callers wait again.
+ */
+
+import { isDatabaseAvailable } from '@/shared/db/database';
+
+type ApiStatus =
+  | { status: 'pending' }
+  | { status: 'ready'; port: number; elapsed_ms: number }
+  | { status: 'unreachable'; port: number; timeout_ms: number; error: string };
+
+export interface ApiHealth {
+  port: number;
+  healthy: boolean;
+  method: 'health' | 'tcp' | null;
+  error: string | null;
+  elapsed_ms: number;
+}
+
+// Longer than the backend's default timeout, in case it never reports
+const FALLBACK_TIMEOUT_MS = 35_000;
+
+let ready: Promise<boolean> | null = null;
+let watchingCrashes = false;
+
+async function watchCrashes(): Promise<void> {
+  if (watchingCrashes || !isDatabaseAvailable()) return;
+  watchingCrashes = true;
+  const { listen } = await import('@tauri-apps/api/event');
+  await listen<{ attempt: number; restart_in_ms: number | null }>(
+    'sidecar://crashed',
+    ({ payload }) => {
+      console.warn(
+        payload.restart_in_ms === null
+          ? '[API] Sidecar crashed repeatedly and was not restarted'
+          : `[API] Sidecar crashed; restarting in ${payload.restart_in_ms}ms`
+      );
+      ready = null;
+    }
+  );
+  await listen<SidecarTerminated>('sidecar://terminated', ({ payload }) => {
+    if (payload.expected) return;
+    console.error(
+      `[API] Sidecar ${payload.pid} exited (code ${payload.code}, signal ${payload.signal})`,
+      payload.stderr_tail.join('\n')
+    );
+  });
+}
+
+async function waitOnce(): Promise<boolean> {
+  if (!isDatabaseAvailable()) {
+    return true;
+  }
+  const [{ listen }, { invoke }] = await Promise.all([
+    import('@tauri-apps/api/event'),
+    import('@tauri-apps/api/core'),
+  ]);
+  return new Promise<boolean>((resolve) => {
+    const unlisteners: Array<() => void> = [];
+    let settled = false;
+    const settle = (ok: boolean) => {
+      if (settled) return;
+      settled = true;
+      clearTimeout(timer);
+      unlisteners.forEach((unlisten) => unlisten());
+      resolve(ok);
+    };
+    const timer = setTimeout(() => settle(false), FALLBACK_TIMEOUT_MS);
+    Promise.all([
+      listen('api://ready', () => settle(true)),
+      listen('api://unreachable', () => settle(false)),
+    ])
+      .then((fns) => {
+        unlisteners.push(...fns);
+        if (settled) fns.forEach((unlisten) => unlisten());
+        return invoke<ApiStatus>('get_api_status');
+      })
+      .then((current) => {
+        if (current.status !== 'pending') settle(current.status === 'ready');
+      })
+      .catch((error) => {
+        console.warn('[API] Cannot check readiness:', error);
+        settle(true);
+      });
+  });
+}
+
+/** Resolves once the API answers (true) or is known to be down (false) */
+export function waitForApiReady(): Promise<boolean> {
+  void watchCrashes().catch((error) => {
+    console.warn('[API] Cannot watch for sidecar crashes:', error);
+  });
+  if (!ready) {
+    ready = waitOnce().then((ok) => {
+      // A failure isn't cached, so a later call waits for the next start
+      if (!ok) ready = null;
+      return ok;
+    });
+  }
+  return ready;
+}
+
+/** Probe the API once, e.g. after resume from sleep; a change is re-announced */
+export async function checkApiHealth(): Promise<ApiHealth> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  const health = await invoke<ApiHealth>('check_api_health');
+  if (!health.healthy) ready = null;
+  return health;
+}
+
+/**
+ * Restart a wedged API, resolving with the new sidecar's PID once it is healthy.
+ * Rejects with the reason if it doesn't come back or a restart is already running;
+ * `sidecar://restarting` and `sidecar://started` bracket the restart for spinners.
+ */
+export async function restartApiSidecar(): Promise<number> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  ready = null;
+  return invoke<number>('restart_api_sidecar');
+}
+
+/** What the sidecar process is doing; whether it answers is `ApiStatus` */
+export type SidecarStatus =
+  | { status: 'running'; pid: number; port: number; uptime_secs: number }
+  | { status: 'restarting' }
+  | { status: 'stopped' }
+  | { status: 'failed'; reason: string };
+
+/** Payload of `sidecar://terminated`, sent whenever a sidecar process exits */
+export interface SidecarTerminated {
+  pid: number;
+  code: number | null;
+  signal: number | null;
+  expected: boolean; // stopped on purpose rather than exited on its own
+  stderr_tail: string[]; // last stderr lines, oldest first
+}
+
+/** Payload of `sidecar://spawned`, sent before `sidecar://started` for each new process */
+export interface SidecarSpawned {
+  pid: number;
+}
+
+/** Payload of `sidecar://stderr`, and of `sidecar://stdout` when enabled */
+export interface SidecarOutputLine {
+  line: string;
+}
+
+/** The sidecar's state as one record, from `get_sidecar_status` */
+export interface SidecarSummary {
+  running: boolean;
+  pid: number | null;
+  uptime_secs: number;
+  restarts: number; // spawns after the first this session
+}
+
+/** The sidecar's process state, for a status dot. Rejects while the API is run separately. */
+export async function getSidecarStatus(): Promise<SidecarStatus> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<SidecarStatus>('api_status');
+}
+
+/** Running state, PID, uptime and restart count. Rejects while the API is run separately. */
+export async function getSidecarSummary(): Promise<SidecarSummary> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<SidecarSummary>('get_sidecar_status');
+}
+
+/**
+ * Store the preferred API port (`null` for the default, 2620) and restart the
+ * sidecar on it. Resolves with the port it got, which is the next free one above
+ * if the preferred port is taken; wait for readiness as after any restart.
+ */
+export async function setApiPort(port: number | null): Promise<number> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  ready = null;
+  return invoke<number>('set_api_port', { port });
+}
+
+/**
+ * Manage the sidecar (`true`) or expect a separately run API (`false`) from the
+ * next launch; `null` restores the default (managed in release, `pnpm dev:api` in
+ * development). The `WORKANY_SIDECAR` environment variable overrides this.
+ */
+export async function setSidecarEnabled(enabled: boolean | null): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('set_sidecar_enabled', { enabled });
+}
+
+/**
+ * Also send the sidecar's stdout as `sidecar://stdout` events, for debugging.
+ * Takes effect from the next spawn.
+ */
+export async function setSidecarStdoutEvents(enabled: boolean): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('set_sidecar_stdout_events', { enabled });
+}
+
+export interface ApiLogLine {
+  level: 'info' | 'error'; // stdout or stderr
+  line: string;
+  ts: string; // RFC 3339
+}
+
+/**
+ * Follow the sidecar's output one line at a time, in order. Bursts past ~60
+ * lines/s arrive as `api-log-batch`; both are delivered here line by line.
+ * Resolves with an unsubscribe function.
+ */
+export async function onApiLog(callback: (line: ApiLogLine) => void): Promise<() => void> {
+  const { listen } = await import('@tauri-apps/api/event');
+  const unlisteners = await Promise.all([
+    listen<ApiLogLine>('api-log', (event) => callback(event.payload)),
+    listen<{ lines: ApiLogLine[] }>('api-log-batch', (event) => event.payload.lines.forEach(callback)),
+  ]);
+  return () => unlisteners.forEach((unlisten) => unlisten());
+}
+
+/** Path of the sidecar's log file (`api.log` in the app log directory), for bug reports */
+export async function getApiLogPath(): Promise<string> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<string>('get_api_log_path');
+}
+
+/** The last `lines` lines of the sidecar's log, oldest first, for a "Backend logs" panel */
+export async function readApiLogTail(lines: number): Promise<string[]> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  return invoke<string[]>('read_api_log_tail', { lines });
+}
+
+/** Reveal the sidecar's log file in the file manager */
+export async function openApiLog(): Promise<void> {
+  const { invoke } = await import('@tauri-apps/api/core');
+  await invoke('open_api_log');
+}